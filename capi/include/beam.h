@@ -0,0 +1,52 @@
+/* C interface to the beam renderer - see the functions in
+ * capi/src/lib.rs for details. Strings are UTF-8, and images
+ * are in rows from the top. Internal errors are caught, and
+ * fail the call like any other error. */
+
+#ifndef BEAM_H
+#define BEAM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BEAM_ABI_VERSION 1
+
+typedef struct BeamRender BeamRender;
+
+typedef struct BeamProgress
+{
+    int complete;
+    double samples_per_pixel;
+    double seconds;
+    /* Negative until there are enough samples to estimate it */
+    double noise;
+} BeamProgress;
+
+uint32_t beam_abi_version(void);
+
+/* Valid until the next call that fails on the same thread */
+const char *beam_last_error(void);
+
+/* Returns NULL if the script fails - a max_samples of zero keeps the default */
+BeamRender *beam_render_new(const char *script, uint32_t width, uint32_t height, uint32_t max_samples);
+void beam_render_free(BeamRender *render);
+
+/* Returns 1 once complete - progress may be NULL */
+int beam_render_poll(BeamRender *render, BeamProgress *progress);
+/* Returns 0 if the render stopped without completing */
+int beam_render_wait(BeamRender *render);
+
+/* width * height * 4 bytes - returns 0 if the buffer's too small */
+int beam_render_rgba8(const BeamRender *render, uint8_t *buffer, size_t length);
+/* width * height * 3 floats - returns 0 if the buffer's too small */
+int beam_render_rgb32f(const BeamRender *render, float *buffer, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
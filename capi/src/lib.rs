@@ -0,0 +1,233 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::AssertUnwindSafe;
+
+use beam::desc::{run_script, SceneDescription};
+use beam::export::HeadlessRender;
+use beam::render::RenderOptions;
+
+/// Increased when any function or struct changes incompatibly
+pub const BEAM_ABI_VERSION: u32 = 1;
+
+/// A render, started from a script, running on background threads
+pub struct BeamRender
+{
+    render: HeadlessRender,
+    options: RenderOptions,
+}
+
+/// Progress of a render - see `include/beam.h`
+#[repr(C)]
+pub struct BeamProgress
+{
+    pub complete: c_int,
+    pub samples_per_pixel: f64,
+    pub seconds: f64,
+    /// Negative until there are enough samples to estimate it
+    pub noise: f64,
+}
+
+thread_local!
+{
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String)
+{
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Runs the function, catching any panic so it can't unwind into
+/// (or abort) the caller - it's reported as the last error, and
+/// the failed result is returned instead
+fn catch_panic<R>(failed: R, func: impl FnOnce() -> R) -> R
+{
+    match std::panic::catch_unwind(AssertUnwindSafe(func))
+    {
+        Ok(result) => result,
+        Err(panic) =>
+        {
+            let message = panic.downcast_ref::<&str>().map(|m| m.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown error".to_owned());
+
+            set_last_error(format!("Internal error: {}", message));
+            failed
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn beam_abi_version() -> u32
+{
+    BEAM_ABI_VERSION
+}
+
+/// The message of the last error on this thread, or null. It's
+/// valid until the next call that fails on the same thread.
+#[no_mangle]
+pub extern "C" fn beam_last_error() -> *const c_char
+{
+    LAST_ERROR.with(|e| e.borrow().as_ref().map(|m| m.as_ptr()).unwrap_or(std::ptr::null()))
+}
+
+/// Runs the script, and starts rendering its scene. A max samples
+/// of zero keeps the default. Returns null if the script fails.
+///
+/// # Safety
+///
+/// The script must be a valid, nul terminated, UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn beam_render_new(script: *const c_char, width: u32, height: u32, max_samples: u32) -> *mut BeamRender
+{
+    catch_panic(std::ptr::null_mut(), || render_new(script, width, height, max_samples))
+}
+
+unsafe fn render_new(script: *const c_char, width: u32, height: u32, max_samples: u32) -> *mut BeamRender
+{
+    if script.is_null() || (width == 0) || (height == 0)
+    {
+        set_last_error("A script, width and height are required".to_owned());
+        return std::ptr::null_mut();
+    }
+
+    let script = match CStr::from_ptr(script).to_str()
+    {
+        Ok(script) => script,
+        Err(_) => { set_last_error("The script isn't valid UTF-8".to_owned()); return std::ptr::null_mut(); },
+    };
+
+    let scene = match run_script(script)
+    {
+        Ok(scene) => scene,
+        Err(err) => { set_last_error(err.message()); return std::ptr::null_mut(); },
+    };
+
+    let mut options = RenderOptions::new(width, height);
+
+    if max_samples > 0
+    {
+        options.max_samples = max_samples as usize;
+    }
+
+    let render = HeadlessRender::new(SceneDescription::new_edit(&scene), &options);
+
+    Box::into_raw(Box::new(BeamRender { render, options }))
+}
+
+/// Stops the render, and frees it
+///
+/// # Safety
+///
+/// The render must be null, or from `beam_render_new`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn beam_render_free(render: *mut BeamRender)
+{
+    if !render.is_null()
+    {
+        catch_panic((), || drop(Box::from_raw(render)));
+    }
+}
+
+/// Takes the updates that are ready, without waiting, and fills in
+/// the progress, if it's not null. Returns 1 once it's complete.
+///
+/// # Safety
+///
+/// The render must be from `beam_render_new`, and the progress null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn beam_render_poll(render: *mut BeamRender, progress: *mut BeamProgress) -> c_int
+{
+    catch_panic(0, ||
+    {
+        let render = &mut *render;
+        let complete = render.render.poll();
+
+        if let Some(progress) = progress.as_mut()
+        {
+            *progress = render_progress(render);
+        }
+
+        complete as c_int
+    })
+}
+
+/// Blocks until the render is complete. Returns 0
+/// if it stopped without completing.
+///
+/// # Safety
+///
+/// The render must be from `beam_render_new`.
+#[no_mangle]
+pub unsafe extern "C" fn beam_render_wait(render: *mut BeamRender) -> c_int
+{
+    catch_panic(0, || (&mut *render).render.wait() as c_int)
+}
+
+/// Copies the image so far, as 8-bit RGBA in rows from the top, through
+/// the display transform. The buffer must hold width * height * 4 bytes.
+/// Returns 0 if it's too small.
+///
+/// # Safety
+///
+/// The render must be from `beam_render_new`, and the buffer valid for the length.
+#[no_mangle]
+pub unsafe extern "C" fn beam_render_rgba8(render: *const BeamRender, buffer: *mut u8, length: usize) -> c_int
+{
+    catch_panic(0, ||
+    {
+        let render = &*render;
+        let pixels = render.render.image().to_rgba8(&render.options).into_raw();
+
+        if buffer.is_null() || (length < pixels.len())
+        {
+            set_last_error(format!("The buffer must hold {} bytes", pixels.len()));
+            return 0;
+        }
+
+        std::ptr::copy_nonoverlapping(pixels.as_ptr(), buffer, pixels.len());
+        1
+    })
+}
+
+/// Copies the image so far, as linear (unclamped) RGB floats in rows
+/// from the top. The buffer must hold width * height * 3 floats.
+/// Returns 0 if it's too small.
+///
+/// # Safety
+///
+/// The render must be from `beam_render_new`, and the buffer valid for the length.
+#[no_mangle]
+pub unsafe extern "C" fn beam_render_rgb32f(render: *const BeamRender, buffer: *mut f32, length: usize) -> c_int
+{
+    catch_panic(0, ||
+    {
+        let render = &*render;
+        let pixels = render.render.image().to_rgb32f().into_raw();
+
+        if buffer.is_null() || (length < pixels.len())
+        {
+            set_last_error(format!("The buffer must hold {} floats", pixels.len()));
+            return 0;
+        }
+
+        std::ptr::copy_nonoverlapping(pixels.as_ptr(), buffer, pixels.len());
+        1
+    })
+}
+
+fn render_progress(render: &BeamRender) -> BeamProgress
+{
+    let progress = render.render.progress();
+
+    BeamProgress
+    {
+        complete: render.render.is_complete() as c_int,
+        samples_per_pixel: render.render.samples_per_pixel(),
+        seconds: progress.map(|p| p.total_duration.as_secs_f64()).unwrap_or(0.0),
+        noise: progress.and_then(|p| p.convergence.as_ref()).map(|c| c.noise).unwrap_or(-1.0),
+    }
+}
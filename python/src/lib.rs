@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use beam::desc::{run_script, SceneDescription};
+use beam::desc::edit::Camera;
+use beam::export::{export_render, ExportMetadata, HeadlessRender};
+use beam::render::RenderOptions;
+
+/// A render, started from a scene script, running on background threads
+#[pyclass(module = "beam")]
+struct Render
+{
+    render: HeadlessRender,
+    options: RenderOptions,
+    camera: Camera,
+}
+
+#[pymethods]
+impl Render
+{
+    #[new]
+    #[pyo3(signature = (script, width, height, max_samples=None))]
+    fn new(script: &str, width: u32, height: u32, max_samples: Option<usize>) -> PyResult<Self>
+    {
+        if (width == 0) || (height == 0)
+        {
+            return Err(PyValueError::new_err("The width and height must be non-zero"));
+        }
+
+        let scene = run_script(script).map_err(|e| PyValueError::new_err(e.message()))?;
+
+        let mut options = RenderOptions::new(width, height);
+
+        if let Some(max_samples) = max_samples
+        {
+            options.max_samples = max_samples;
+        }
+
+        let desc = SceneDescription::new_edit(&scene);
+        let camera = desc.camera.clone();
+        let render = HeadlessRender::new(desc, &options);
+
+        Ok(Render { render, options, camera })
+    }
+
+    /// Takes the updates that are ready, without
+    /// waiting, and returns if the render is complete
+    fn poll(&mut self) -> bool
+    {
+        self.render.poll()
+    }
+
+    /// Waits, without holding the GIL, until the render is complete
+    fn wait(&mut self, py: Python<'_>) -> PyResult<()>
+    {
+        let render = &mut self.render;
+
+        match py.allow_threads(|| render.wait())
+        {
+            true => Ok(()),
+            false => Err(PyRuntimeError::new_err("The render did not complete")),
+        }
+    }
+
+    #[getter]
+    fn complete(&self) -> bool
+    {
+        self.render.is_complete()
+    }
+
+    #[getter]
+    fn width(&self) -> u32
+    {
+        self.options.width
+    }
+
+    #[getter]
+    fn height(&self) -> u32
+    {
+        self.options.height
+    }
+
+    #[getter]
+    fn samples_per_pixel(&self) -> f64
+    {
+        self.render.samples_per_pixel()
+    }
+
+    #[getter]
+    fn seconds(&self) -> f64
+    {
+        self.render.progress().map(|p| p.total_duration.as_secs_f64()).unwrap_or(0.0)
+    }
+
+    /// None until there are enough samples to estimate it
+    #[getter]
+    fn noise(&self) -> Option<f64>
+    {
+        self.render.progress().and_then(|p| p.convergence.as_ref()).map(|c| c.noise)
+    }
+
+    /// The image so far, as 8-bit RGBA in rows from
+    /// the top, through the display transform
+    fn rgba8<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes>
+    {
+        PyBytes::new(py, &self.render.image().to_rgba8(&self.options).into_raw())
+    }
+
+    /// The image so far, as linear (unclamped) RGB
+    /// floats, in native byte order, in rows from the top
+    fn rgb32f<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes>
+    {
+        let pixels = self.render.image().to_rgb32f().into_raw();
+        let bytes: Vec<u8> = pixels.iter().flat_map(|f| f.to_ne_bytes()).collect();
+
+        PyBytes::new(py, &bytes)
+    }
+
+    /// Saves the image so far, in the format given by the path's extension
+    fn save(&self, path: &str) -> PyResult<()>
+    {
+        let metadata = ExportMetadata
+        {
+            scene_file: None,
+            camera: self.camera.clone(),
+            options: self.options.clone(),
+            num_samples: self.render.progress().map(|p| p.stats.num_samples).unwrap_or(0),
+            duration: self.render.progress().map(|p| p.total_duration).unwrap_or_default(),
+            burn_in: None,
+        };
+
+        export_render(Path::new(path), self.render.image(), &metadata).map_err(PyRuntimeError::new_err)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "beam")]
+fn beam_module(m: &Bound<'_, PyModule>) -> PyResult<()>
+{
+    m.add_class::<Render>()?;
+    Ok(())
+}
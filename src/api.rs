@@ -0,0 +1,176 @@
+use crate::desc::SceneDescription;
+use crate::desc::edit::{Camera, Color, Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::desc::edit::geom::Aabb;
+use crate::indexed::{GeomIndex, MaterialIndex, ObjectIndex, TextureIndex};
+use crate::math::Scalar;
+use crate::vec::{Dir3, Point3, Vec3};
+
+/// Builds an edit scene from Rust, for using beam as a library.
+/// Each method adds an item to the scene, and returns its index,
+/// to pass to the items that use it - in the same way as the
+/// script functions of the same names.
+///
+/// ```
+/// use beam::api::SceneBuilder;
+/// use beam::color::SRGB;
+/// use beam::vec::Point3;
+///
+/// let mut builder = SceneBuilder::new();
+///
+/// builder.camera(Point3::new(0.0, 1.0, 5.0), Point3::new(0.0, 1.0, 0.0), 40.0);
+///
+/// let ball = builder.sphere(Point3::new(0.0, 1.0, 0.0), 1.0);
+/// let red = builder.diffuse(SRGB::new(0.8, 0.1, 0.1, 1.0));
+/// builder.object(ball, red);
+///
+/// let description = builder.description();
+/// ```
+pub struct SceneBuilder
+{
+    scene: Scene,
+}
+
+impl Default for SceneBuilder
+{
+    fn default() -> Self
+    {
+        SceneBuilder::new()
+    }
+}
+
+impl SceneBuilder
+{
+    pub fn new() -> Self
+    {
+        SceneBuilder { scene: Scene::new() }
+    }
+
+    /// Looks at the point, with the Y axis up. The field
+    /// of view is horizontal, in degrees.
+    pub fn camera(&mut self, location: Point3, look_at: Point3, fov: Scalar) -> &mut Self
+    {
+        self.scene.camera = Camera { location, look_at, up: Point3::new(0.0, 1.0, 0.0), fov, aperture: 0.0, focus_distance: 0.0 };
+        self
+    }
+
+    /// Blurs everything not at the focus distance - zero
+    /// focuses on the camera's look-at point
+    pub fn depth_of_field(&mut self, aperture: Scalar, focus_distance: Scalar) -> &mut Self
+    {
+        self.scene.camera.aperture = aperture;
+        self.scene.camera.focus_distance = focus_distance;
+        self
+    }
+
+    pub fn solid<C: Into<Color>>(&mut self, color: C) -> TextureIndex
+    {
+        self.scene.collection.push(Texture::Solid(color.into()))
+    }
+
+    pub fn checkerboard<C: Into<Color>>(&mut self, a: C, b: C) -> TextureIndex
+    {
+        self.scene.collection.push(Texture::Checkerboard(a.into(), b.into()))
+    }
+
+    /// Adds any material, e.g. with normal maps or textures
+    pub fn material(&mut self, material: Material) -> MaterialIndex
+    {
+        self.scene.collection.push(material)
+    }
+
+    pub fn diffuse<C: Into<Color>>(&mut self, color: C) -> MaterialIndex
+    {
+        let texture = self.solid(color);
+        self.material(Material::Diffuse { texture, normal_texture: None })
+    }
+
+    /// A fuzz of zero is a perfect mirror
+    pub fn metal<C: Into<Color>>(&mut self, color: C, fuzz: Scalar) -> MaterialIndex
+    {
+        let texture = self.solid(color);
+        self.material(Material::Metal { texture, fuzz, normal_texture: None })
+    }
+
+    pub fn pbr<C: Into<Color>>(&mut self, color: C, metallic: Scalar, roughness: Scalar) -> MaterialIndex
+    {
+        let texture = self.solid(color);
+        self.material(Material::Pbr { texture, metallic, roughness, normal_texture: None })
+    }
+
+    pub fn dielectric(&mut self, ior: Scalar) -> MaterialIndex
+    {
+        self.material(Material::Dielectric { ior, tint: None })
+    }
+
+    pub fn emit<C: Into<Color>>(&mut self, color: C) -> MaterialIndex
+    {
+        let texture = self.solid(color);
+        self.material(Material::Emit { texture })
+    }
+
+    /// Adds any geometry, e.g. volumes or meshes with levels of detail
+    pub fn geom(&mut self, geom: Geom) -> GeomIndex
+    {
+        self.scene.collection.push(geom)
+    }
+
+    pub fn sphere(&mut self, center: Point3, radius: Scalar) -> GeomIndex
+    {
+        self.geom(Geom::Sphere { center, radius })
+    }
+
+    /// A corner, and the edges from it
+    pub fn rectangle(&mut self, point: Point3, u: Vec3, v: Vec3) -> GeomIndex
+    {
+        self.geom(Geom::Rectangle { point, u, v })
+    }
+
+    pub fn disc(&mut self, center: Point3, normal: Dir3, radius: Scalar) -> GeomIndex
+    {
+        self.geom(Geom::Disc { center, normal, radius })
+    }
+
+    pub fn cuboid(&mut self, min: Point3, max: Point3) -> GeomIndex
+    {
+        self.geom(Geom::Box { aabb: Aabb { min, max } })
+    }
+
+    pub fn plane(&mut self, point: Point3, normal: Dir3) -> GeomIndex
+    {
+        self.geom(Geom::Plane { point, normal })
+    }
+
+    /// Triangles are given by their corners, counter-clockwise when
+    /// seen from outside, and textured by their location
+    pub fn mesh(&mut self, triangles: Vec<[Point3; 3]>) -> GeomIndex
+    {
+        let triangles = triangles.into_iter()
+            .map(|points| Triangle { vertices: points.map(|location| TriangleVertex { location, texture_coords: location, opt_color: None, opt_normal: None }) })
+            .collect();
+
+        self.geom(Geom::Mesh { triangles, transform: Transform::new(), lod: None })
+    }
+
+    pub fn object(&mut self, geom: GeomIndex, material: MaterialIndex) -> ObjectIndex
+    {
+        self.scene.collection.push(Object { geom, material, ray_bias: None, opacity: None })
+    }
+
+    /// For anything the builder doesn't cover,
+    /// such as names, groups and transforms
+    pub fn scene_mut(&mut self) -> &mut Scene
+    {
+        &mut self.scene
+    }
+
+    pub fn build(self) -> Scene
+    {
+        self.scene
+    }
+
+    /// The scene, ready to render
+    pub fn description(self) -> SceneDescription
+    {
+        SceneDescription::new_edit(&self.scene)
+    }
+}
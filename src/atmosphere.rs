@@ -0,0 +1,472 @@
+use crate::color::LinearRGB;
+use crate::geom::Density;
+use crate::math::{Scalar, ScalarConsts};
+use crate::sample::Sampler;
+use crate::vec::{Dir3, Point3};
+
+/// Planet and atmosphere sizes, in meters
+const PLANET_RADIUS: Scalar = 6_360_000.0;
+const ATMOSPHERE_RADIUS: Scalar = 6_420_000.0;
+
+/// Scattering coefficients at sea level (per meter), and
+/// the heights over which their densities fall by 1/e
+const RAYLEIGH_SCATTERING: [Scalar; 3] = [5.802e-6, 13.558e-6, 33.1e-6];
+const RAYLEIGH_HEIGHT: Scalar = 8_000.0;
+const MIE_SCATTERING: Scalar = 3.996e-6;
+const MIE_EXTINCTION: Scalar = 4.44e-6;
+const MIE_HEIGHT: Scalar = 1_200.0;
+const MIE_G: Scalar = 0.8;
+
+/// Sky radiance is looked up in a table of view elevation
+/// by azimuth relative to the sun, as the sky is symmetric
+/// about the plane containing the sun
+const TABLE_ELEVATIONS: usize = 128;
+const TABLE_AZIMUTHS: usize = 32;
+const VIEW_STEPS: usize = 16;
+const LIGHT_STEPS: usize = 8;
+
+const CLOUD_STEPS: usize = 32;
+const CLOUD_LIGHT_STEPS: usize = 4;
+/// Clouds fade into the sky over this distance
+/// (in meters), and aren't marched beyond it
+const CLOUD_FADE_DISTANCE: Scalar = 20_000.0;
+const CLOUD_MAX_DISTANCE: Scalar = 60_000.0;
+/// Forward and back scattering lobes of the clouds' phase function
+const CLOUD_G_FORWARD: Scalar = 0.6;
+const CLOUD_G_BACK: Scalar = -0.3;
+
+/// A layer of procedural clouds - a slab of fractal noise
+#[derive(Clone, Debug)]
+pub struct CloudLayer
+{
+    /// Fraction of the sky covered, from zero to one
+    pub coverage: Scalar,
+    /// Extinction per meter in the densest parts
+    pub density: Scalar,
+    /// Height of the cloud base and its thickness, in meters
+    pub altitude: Scalar,
+    pub thickness: Scalar,
+    /// Typical size of a cloud, in meters
+    pub scale: Scalar,
+}
+
+/// The sky seen from the ground of a planet, lit by the sun -
+/// single scattering through Rayleigh (air) and Mie (haze)
+/// layers, with an optional layer of clouds. It's at an
+/// infinite distance, with y up, so the scene's own scale
+/// and the camera's location don't affect it.
+#[derive(Clone)]
+pub struct Atmosphere
+{
+    sun_dir: Dir3,
+    cos_sun_radius: Scalar,
+    /// Radiance of the sun's disk, once dimmed by the atmosphere
+    sun_radiance: LinearRGB,
+    /// The sun's irradiance once it's reached the clouds,
+    /// and the average radiance of the sky lighting them
+    sun_at_clouds: LinearRGB,
+    ambient: LinearRGB,
+    /// Sky radiance by elevation, then azimuth. Below the horizon,
+    /// it's the light scattered by the air in front of the ground.
+    table: Vec<[Scalar; 3]>,
+    clouds: Option<(CloudLayer, Density)>,
+}
+
+impl Atmosphere
+{
+    /// The sun's intensity is its irradiance above the atmosphere.
+    /// Its angular radius is in radians. Haze scales the density
+    /// of the Mie scattering layer.
+    pub fn new(sun_dir: Dir3, sun_intensity: Scalar, sun_radius: Scalar, haze: Scalar, altitude: Scalar, clouds: Option<CloudLayer>) -> Self
+    {
+        let sun_dir = sun_dir.normalized();
+        let cos_sun_radius = sun_radius.clamp(1.0e-4, 0.5 * ScalarConsts::PI).cos();
+        let air = Air { haze: haze.max(0.0), origin: Point3::new(0.0, PLANET_RADIUS + altitude.max(1.0), 0.0) };
+
+        let mut table = Vec::with_capacity(TABLE_ELEVATIONS * TABLE_AZIMUTHS);
+
+        for e in 0..TABLE_ELEVATIONS
+        {
+            let elevation = table_elevation((e as Scalar) / ((TABLE_ELEVATIONS - 1) as Scalar));
+
+            for a in 0..TABLE_AZIMUTHS
+            {
+                let azimuth = ScalarConsts::PI * (a as Scalar) / ((TABLE_AZIMUTHS - 1) as Scalar);
+                let dir = dir_relative_to_sun(sun_dir, elevation, azimuth);
+
+                table.push(air.in_scattered(dir, sun_dir).map(|c| c * sun_intensity));
+            }
+        }
+
+        // The sun's color once it's passed through the air
+
+        let sun_transmittance = air.transmittance_to_space(air.origin, sun_dir);
+        let solid_angle = 2.0 * ScalarConsts::PI * (1.0 - cos_sun_radius);
+        let sun_radiance = rgb(sun_transmittance.map(|t| t * sun_intensity / solid_angle));
+
+        let mut result = Atmosphere
+        {
+            sun_dir,
+            cos_sun_radius,
+            sun_radiance,
+            sun_at_clouds: rgb(sun_transmittance.map(|t| t * sun_intensity)),
+            ambient: LinearRGB::black(),
+            table,
+            clouds: clouds.map(|c|
+            {
+                let noise = Density::Noise{ frequency: 1.0 / c.scale.max(1.0), octaves: 4 };
+                (c, noise)
+            }),
+        };
+
+        // Clouds are lit by the sky as well as the sun - use the
+        // average radiance of the sky above the horizon
+
+        let mut sum = LinearRGB::black();
+        let samples = 8;
+
+        for e in 0..samples
+        {
+            for a in 0..samples
+            {
+                let elevation = 0.5 * ScalarConsts::PI * ((e as Scalar) + 0.5) / (samples as Scalar);
+                let azimuth = ScalarConsts::PI * ((a as Scalar) + 0.5) / (samples as Scalar);
+
+                sum = sum + result.sky(dir_relative_to_sun(sun_dir, elevation, azimuth));
+            }
+        }
+
+        result.ambient = sum.divided_by_scalar((samples * samples) as Scalar);
+        result
+    }
+
+    /// Radiance arriving from the direction - the sky,
+    /// the sun's disk, and clouds in front of them
+    pub fn radiance(&self, dir: Dir3) -> LinearRGB
+    {
+        let dir = dir.normalized();
+        let mut sky = self.sky(dir);
+
+        if dir.dot(self.sun_dir) >= self.cos_sun_radius
+        {
+            sky = sky + self.sun_radiance;
+        }
+
+        match &self.clouds
+        {
+            Some((layer, noise)) => self.add_clouds(layer, noise, dir, sky),
+            None => sky,
+        }
+    }
+
+    /// Samples a direction towards the sun's disk, uniformly in its cone
+    pub fn sample_sun(&self, sampler: &mut Sampler) -> (Dir3, Scalar)
+    {
+        let cos_theta = 1.0 - (sampler.uniform_scalar_unit() * (1.0 - self.cos_sun_radius));
+        let sin_theta = (1.0 - (cos_theta * cos_theta)).max(0.0).sqrt();
+        let phi = 2.0 * ScalarConsts::PI * sampler.uniform_scalar_unit();
+
+        let u = if self.sun_dir.x.abs() > 0.9 { Dir3::new(0.0, 1.0, 0.0) } else { Dir3::new(1.0, 0.0, 0.0) };
+        let v = self.sun_dir.cross(u).normalized();
+        let u = self.sun_dir.cross(v);
+
+        let dir = (u * (phi.cos() * sin_theta)) + (v * (phi.sin() * sin_theta)) + (self.sun_dir * cos_theta);
+
+        (dir, self.sun_cone_pdf())
+    }
+
+    pub fn sun_pdf(&self, dir: Dir3) -> Scalar
+    {
+        if dir.normalized().dot(self.sun_dir) >= self.cos_sun_radius
+        {
+            self.sun_cone_pdf()
+        }
+        else
+        {
+            0.0
+        }
+    }
+
+    fn sun_cone_pdf(&self) -> Scalar
+    {
+        1.0 / (2.0 * ScalarConsts::PI * (1.0 - self.cos_sun_radius))
+    }
+
+    /// The sky, without the sun's disk or clouds,
+    /// interpolated from the table
+    fn sky(&self, dir: Dir3) -> LinearRGB
+    {
+        let elevation = dir.y.clamp(-1.0, 1.0).asin();
+
+        let flat_dir = Dir3::new(dir.x, 0.0, dir.z);
+        let flat_sun = Dir3::new(self.sun_dir.x, 0.0, self.sun_dir.z);
+
+        let azimuth = if (flat_dir.magnitude_squared() > 0.0) && (flat_sun.magnitude_squared() > 0.0)
+        {
+            flat_dir.normalized().dot(flat_sun.normalized()).clamp(-1.0, 1.0).acos()
+        }
+        else
+        {
+            0.0
+        };
+
+        let e = table_elevation_inverse(elevation) * ((TABLE_ELEVATIONS - 1) as Scalar);
+        let a = (azimuth / ScalarConsts::PI) * ((TABLE_AZIMUTHS - 1) as Scalar);
+
+        let (e0, a0) = ((e.floor() as usize).min(TABLE_ELEVATIONS - 2), (a.floor() as usize).min(TABLE_AZIMUTHS - 2));
+        let (te, ta) = ((e - (e0 as Scalar)).clamp(0.0, 1.0), (a - (a0 as Scalar)).clamp(0.0, 1.0));
+
+        let entry = |e: usize, a: usize| self.table[(e * TABLE_AZIMUTHS) + a];
+        let lerp = |x: [Scalar; 3], y: [Scalar; 3], t: Scalar| [0, 1, 2].map(|i| x[i] + ((y[i] - x[i]) * t));
+
+        rgb(lerp(
+            lerp(entry(e0, a0), entry(e0, a0 + 1), ta),
+            lerp(entry(e0 + 1, a0), entry(e0 + 1, a0 + 1), ta),
+            te))
+    }
+
+    /// Ray marches through the cloud slab, lighting each step
+    /// with the sun (shadowed by the clouds towards it) and the sky
+    fn add_clouds(&self, layer: &CloudLayer, noise: &Density, dir: Dir3, behind: LinearRGB) -> LinearRGB
+    {
+        if dir.y <= 1.0e-3
+        {
+            return behind;
+        }
+
+        let start = layer.altitude / dir.y;
+        let end = ((layer.altitude + layer.thickness.max(1.0)) / dir.y).min(start + CLOUD_MAX_DISTANCE);
+
+        if start >= CLOUD_MAX_DISTANCE
+        {
+            return behind;
+        }
+
+        let cos_angle = dir.dot(self.sun_dir);
+        let phase = (0.7 * henyey_greenstein(cos_angle, CLOUD_G_FORWARD)) + (0.3 * henyey_greenstein(cos_angle, CLOUD_G_BACK));
+
+        let step = (end - start) / (CLOUD_STEPS as Scalar);
+        let mut transmittance = 1.0;
+        let mut scattered = LinearRGB::black();
+
+        for i in 0..CLOUD_STEPS
+        {
+            let point = Point3::zero() + (dir * (start + (((i as Scalar) + 0.5) * step)));
+            let extinction = cloud_extinction(layer, noise, point);
+
+            if extinction <= 0.0
+            {
+                continue;
+            }
+
+            // Optical depth towards the sun, through the rest of the slab
+
+            let light_length = if self.sun_dir.y > 1.0e-3 { ((layer.altitude + layer.thickness - point.y) / self.sun_dir.y).min(2.0 * layer.thickness) } else { 2.0 * layer.thickness };
+            let light_step = light_length.max(0.0) / (CLOUD_LIGHT_STEPS as Scalar);
+
+            let light_depth: Scalar = (0..CLOUD_LIGHT_STEPS)
+                .map(|j| cloud_extinction(layer, noise, point + (self.sun_dir * (((j as Scalar) + 0.5) * light_step))) * light_step)
+                .sum();
+
+            let lighting = self.sun_at_clouds.multiplied_by_scalar((-light_depth).exp() * phase)
+                + self.ambient;
+
+            // Light scattered in this step, assuming an albedo of one
+
+            let absorbed = 1.0 - (-extinction * step).exp();
+
+            scattered = scattered + lighting.multiplied_by_scalar(transmittance * absorbed);
+            transmittance *= 1.0 - absorbed;
+
+            if transmittance < 0.01
+            {
+                break;
+            }
+        }
+
+        // Distant clouds fade into the haze
+
+        let fade = (-start / CLOUD_FADE_DISTANCE).exp();
+
+        behind.multiplied_by_scalar(1.0 - (fade * (1.0 - transmittance)))
+            + scattered.multiplied_by_scalar(fade)
+    }
+}
+
+/// The air's density profile, seen from a point near the ground
+struct Air
+{
+    haze: Scalar,
+    origin: Point3,
+}
+
+impl Air
+{
+    fn densities(&self, point: Point3) -> (Scalar, Scalar)
+    {
+        let height = (point.magnitude() - PLANET_RADIUS).max(0.0);
+
+        ((-height / RAYLEIGH_HEIGHT).exp(), self.haze * (-height / MIE_HEIGHT).exp())
+    }
+
+    fn extinction(rayleigh: Scalar, mie: Scalar) -> [Scalar; 3]
+    {
+        [0, 1, 2].map(|i| (RAYLEIGH_SCATTERING[i] * rayleigh) + (MIE_EXTINCTION * mie))
+    }
+
+    /// Transmittance from the point to the top of the atmosphere,
+    /// or zero if the planet is in the way
+    fn transmittance_to_space(&self, point: Point3, dir: Dir3) -> [Scalar; 3]
+    {
+        if sphere_exit(point, dir, PLANET_RADIUS).is_some_and(|(near, _)| near > 0.0)
+        {
+            return [0.0; 3];
+        }
+
+        let length = sphere_exit(point, dir, ATMOSPHERE_RADIUS).map(|(_, far)| far).unwrap_or(0.0).max(0.0);
+        let step = length / (LIGHT_STEPS as Scalar);
+
+        let mut depth = [0.0; 3];
+
+        for i in 0..LIGHT_STEPS
+        {
+            let (rayleigh, mie) = self.densities(point + (dir * (((i as Scalar) + 0.5) * step)));
+            let extinction = Air::extinction(rayleigh, mie);
+
+            for c in 0..3
+            {
+                depth[c] += extinction[c] * step;
+            }
+        }
+
+        depth.map(|d| (-d).exp())
+    }
+
+    /// Light from the sun scattered once towards the
+    /// origin, along the direction, per unit of irradiance
+    fn in_scattered(&self, dir: Dir3, sun_dir: Dir3) -> [Scalar; 3]
+    {
+        // March to the edge of the atmosphere, or the ground
+
+        let mut length = sphere_exit(self.origin, dir, ATMOSPHERE_RADIUS).map(|(_, far)| far).unwrap_or(0.0).max(0.0);
+
+        if let Some((near, _)) = sphere_exit(self.origin, dir, PLANET_RADIUS)
+        {
+            if near > 0.0
+            {
+                length = length.min(near);
+            }
+        }
+
+        let cos_angle = dir.dot(sun_dir);
+        let rayleigh_phase = 3.0 / (16.0 * ScalarConsts::PI) * (1.0 + (cos_angle * cos_angle));
+        let mie_phase = henyey_greenstein(cos_angle, MIE_G);
+
+        let step = length / (VIEW_STEPS as Scalar);
+        let mut depth = [0.0; 3];
+        let mut result = [0.0; 3];
+
+        for i in 0..VIEW_STEPS
+        {
+            let point = self.origin + (dir * (((i as Scalar) + 0.5) * step));
+            let (rayleigh, mie) = self.densities(point);
+            let extinction = Air::extinction(rayleigh, mie);
+
+            for c in 0..3
+            {
+                depth[c] += extinction[c] * step * 0.5;
+            }
+
+            let to_sun = self.transmittance_to_space(point, sun_dir);
+
+            for c in 0..3
+            {
+                let scattering = (RAYLEIGH_SCATTERING[c] * rayleigh * rayleigh_phase) + (MIE_SCATTERING * mie * mie_phase);
+                result[c] += (-depth[c]).exp() * to_sun[c] * scattering * step;
+                depth[c] += extinction[c] * step * 0.5;
+            }
+        }
+
+        result
+    }
+}
+
+fn cloud_extinction(layer: &CloudLayer, noise: &Density, point: Point3) -> Scalar
+{
+    let height = (point.y - layer.altitude) / layer.thickness.max(1.0);
+
+    if (height <= 0.0) || (height >= 1.0)
+    {
+        return 0.0;
+    }
+
+    // Only where the noise is above the coverage threshold, and
+    // denser towards the middle of the layer. Fractal noise is
+    // mostly between a quarter and three quarters, so the
+    // threshold is moved across that range.
+
+    let threshold = 0.75 - (0.5 * layer.coverage.clamp(0.0, 1.0));
+    let shaped = ((noise.density_at(point) - threshold) / 0.25).clamp(0.0, 1.0);
+    let profile = 4.0 * height * (1.0 - height);
+
+    shaped * profile * layer.density.max(0.0)
+}
+
+fn henyey_greenstein(cos_angle: Scalar, g: Scalar) -> Scalar
+{
+    let denom = 1.0 + (g * g) - (2.0 * g * cos_angle);
+
+    (1.0 - (g * g)) / (4.0 * ScalarConsts::PI * denom * denom.sqrt())
+}
+
+/// Distances to where the ray enters and leaves a sphere
+/// of the radius around the planet's center, if it hits
+fn sphere_exit(origin: Point3, dir: Dir3, radius: Scalar) -> Option<(Scalar, Scalar)>
+{
+    let b = origin.dot(dir);
+    let c = origin.magnitude_squared() - (radius * radius);
+    let discriminant = (b * b) - c;
+
+    if discriminant < 0.0
+    {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+
+    Some((-b - root, -b + root))
+}
+
+/// Table rows are closer together near the horizon,
+/// where the sky's color changes fastest
+fn table_elevation(t: Scalar) -> Scalar
+{
+    let s = (2.0 * t) - 1.0;
+
+    s.signum() * s * s * 0.5 * ScalarConsts::PI
+}
+
+fn table_elevation_inverse(elevation: Scalar) -> Scalar
+{
+    let s = elevation / (0.5 * ScalarConsts::PI);
+
+    0.5 + (0.5 * s.signum() * s.abs().sqrt())
+}
+
+/// The direction at an elevation, and an
+/// azimuth measured from the sun's
+fn dir_relative_to_sun(sun_dir: Dir3, elevation: Scalar, azimuth: Scalar) -> Dir3
+{
+    let flat_sun = Dir3::new(sun_dir.x, 0.0, sun_dir.z);
+    let forward = if flat_sun.magnitude_squared() > 1.0e-12 { flat_sun.normalized() } else { Dir3::new(0.0, 0.0, 1.0) };
+    let side = Dir3::new(forward.z, 0.0, -forward.x);
+
+    let horizontal = (forward * azimuth.cos()) + (side * azimuth.sin());
+
+    (horizontal * elevation.cos()) + Dir3::new(0.0, elevation.sin(), 0.0)
+}
+
+fn rgb(c: [Scalar; 3]) -> LinearRGB
+{
+    LinearRGB::new(c[0], c[1], c[2], 1.0)
+}
@@ -0,0 +1,254 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// An open document, as saved for recovery
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AutosaveDocument
+{
+    pub name: String,
+    pub filename: Option<String>,
+    pub script: String,
+    /// The edit scene, as scene JSON - so edits made
+    /// in the UI, rather than the script, are kept
+    pub scene: String,
+}
+
+/// Periodically saves the open documents, so they can be recovered
+/// if the application doesn't exit cleanly. Each instance has its
+/// own file, which is removed on a clean exit, and a lock file that
+/// it holds locked while it runs. So an autosave whose lock can be
+/// taken is from a session that crashed - and taking the lock claims
+/// it, so only one instance offers to recover it.
+pub struct Autosave
+{
+    dir: PathBuf,
+    path: PathBuf,
+    lock: Option<File>,
+    /// Autosaves from crashed sessions, and their
+    /// locks, until the recovery is answered
+    claimed: Vec<(PathBuf, File)>,
+    interval: Option<Duration>,
+    last_check: Instant,
+    last_saved: Option<String>,
+}
+
+const PREFIX: &str = "beam_autosave_";
+
+impl Autosave
+{
+    pub fn new(dir: PathBuf, interval_seconds: u64) -> Self
+    {
+        // The start time is included, as a crashed
+        // session's process ID may have been re-used
+
+        let start = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or_default();
+        let path = dir.join(format!("{}{}_{}.json", PREFIX, std::process::id(), start));
+
+        let lock = std::fs::create_dir_all(&dir)
+            .and_then(|_| File::create(lock_path(&path)))
+            .and_then(|file| file.lock().map(|_| file))
+            .map_err(|e| println!("Error: Could not lock autosave {:?}: {}", path, e))
+            .ok();
+
+        let mut result = Autosave { dir, path, lock, claimed: Vec::new(), interval: None, last_check: Instant::now(), last_saved: None };
+        result.set_interval(interval_seconds);
+        result
+    }
+
+    /// In the system's temporary directory
+    pub fn default_dir() -> PathBuf
+    {
+        std::env::temp_dir().join("beam_autosave")
+    }
+
+    /// Zero disables autosaving
+    pub fn set_interval(&mut self, interval_seconds: u64)
+    {
+        self.interval = if interval_seconds == 0 { None } else { Some(Duration::from_secs(interval_seconds)) };
+    }
+
+    /// Claims the autosaves left by sessions that didn't exit cleanly,
+    /// and returns their documents - none if there aren't any. Those
+    /// that can't be read are reported, and removed.
+    pub fn load_recovery(&mut self) -> Result<Option<Vec<AutosaveDocument>>, String>
+    {
+        let entries = match std::fs::read_dir(&self.dir)
+        {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(format!("Could not read autosaves {:?}: {}", self.dir, err)),
+        };
+
+        let mut paths = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.file_name().and_then(|n| n.to_str()).map(|n| n.starts_with(PREFIX)).unwrap_or(false))
+            .collect::<Vec<_>>();
+
+        paths.sort();
+
+        let mut documents = Vec::new();
+
+        for path in paths
+        {
+            if path == self.path || path == lock_path(&self.path)
+            {
+                continue;
+            }
+
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_owned();
+
+            if extension == "lock"
+            {
+                // A session that crashed before it saved
+                // anything only leaves its lock behind
+
+                if !path.with_extension("json").exists()
+                {
+                    if let Some(lock) = try_claim(&path)
+                    {
+                        drop(lock);
+                        let _ = std::fs::remove_file(&path);
+                    }
+                }
+
+                continue;
+            }
+            else if extension != "json"
+            {
+                continue;
+            }
+
+            let lock = match try_claim(&lock_path(&path))
+            {
+                Some(lock) => lock,
+                None => continue,
+            };
+
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| serde_json::from_str::<Vec<AutosaveDocument>>(&text).map_err(|e| e.to_string()));
+
+            match loaded
+            {
+                Ok(mut loaded) =>
+                {
+                    documents.append(&mut loaded);
+                    self.claimed.push((path, lock));
+                },
+                Err(err) =>
+                {
+                    println!("Error: Could not read autosave {:?}: {}", path, err);
+                    remove_autosave(&path, lock)?;
+                },
+            }
+        }
+
+        if documents.is_empty()
+        {
+            self.clear_recovery()?;
+            return Ok(None);
+        }
+
+        Ok(Some(documents))
+    }
+
+    /// Returns true, at most once each interval, when the documents should
+    /// be gathered and passed to `save` - so they're not serialized every frame
+    pub fn is_due(&mut self) -> bool
+    {
+        match self.interval
+        {
+            Some(interval) if self.last_check.elapsed() >= interval =>
+            {
+                self.last_check = Instant::now();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Saves the documents, unless nothing has changed since the last save.
+    /// It's written to a temporary file first, so a crash while saving
+    /// doesn't lose the previous autosave.
+    pub fn save(&mut self, documents: &[AutosaveDocument]) -> Result<(), String>
+    {
+        let text = serde_json::to_string(documents)
+            .map_err(|e| format!("Could not write autosave: {}", e))?;
+
+        if self.last_saved.as_ref() == Some(&text)
+        {
+            return Ok(());
+        }
+
+        let temp_path = self.path.with_extension("json.tmp");
+
+        std::fs::write(&temp_path, &text)
+            .and_then(|_| std::fs::rename(&temp_path, &self.path))
+            .map_err(|e| format!("Could not write autosave {:?}: {}", self.path, e))?;
+
+        self.last_saved = Some(text);
+        Ok(())
+    }
+
+    /// Removes this session's autosave, and its
+    /// lock - on a clean exit
+    pub fn clear(&mut self) -> Result<(), String>
+    {
+        self.last_saved = None;
+
+        match self.lock.take()
+        {
+            Some(lock) => remove_autosave(&self.path, lock),
+            None => remove_file(&self.path),
+        }
+    }
+
+    /// Removes the claimed autosaves, once they've been recovered
+    /// or discarded. If the recovery isn't answered, they're
+    /// released when this is dropped, for the next session.
+    pub fn clear_recovery(&mut self) -> Result<(), String>
+    {
+        for (path, lock) in std::mem::take(&mut self.claimed)
+        {
+            remove_autosave(&path, lock)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf
+{
+    path.with_extension("lock")
+}
+
+/// Returns the lock, if no running session holds it
+fn try_claim(lock_path: &Path) -> Option<File>
+{
+    let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(lock_path).ok()?;
+    file.try_lock().ok()?;
+    Some(file)
+}
+
+fn remove_autosave(path: &Path, lock: File) -> Result<(), String>
+{
+    remove_file(path)?;
+
+    // Unlocked last, so no other session
+    // claims the autosave while it's removed
+
+    drop(lock);
+    remove_file(&lock_path(path))
+}
+
+fn remove_file(path: &Path) -> Result<(), String>
+{
+    match std::fs::remove_file(path)
+    {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(format!("Could not remove autosave {:?}: {}", path, err)),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,312 @@
+use crate::color::LinearRGB;
+use crate::desc::edit::Triangle;
+use crate::math::{EPSILON, Scalar, ScalarConsts};
+use crate::ray::Ray;
+use crate::sample::Sampler;
+use crate::scene::{Scene, SceneSampleStats};
+use crate::vec::{Dir3, Point3};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread::JoinHandle;
+
+use image::{ImageBuffer, Rgb};
+
+pub type Lightmap = ImageBuffer<Rgb<f32>, Vec<f32>>;
+
+#[derive(Clone)]
+pub struct BakeOptions
+{
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_texel: u32,
+    pub dilation: u32,
+}
+
+impl Default for BakeOptions
+{
+    fn default() -> Self
+    {
+        BakeOptions
+        {
+            width: 256,
+            height: 256,
+            samples_per_texel: 64,
+            dilation: 2,
+        }
+    }
+}
+
+// A texel of the lightmap that's covered by
+// the mesh's UV layout
+
+#[derive(Clone, Copy)]
+struct Texel
+{
+    x: u32,
+    y: u32,
+    location: Point3,
+    normal: Dir3,
+}
+
+/// Bakes the irradiance arriving at a mesh into a lightmap
+/// laid out by the mesh's texture coordinates. Baking runs
+/// on background threads - poll `progress` and `take_result`.
+pub struct Baker
+{
+    threads: Vec<JoinHandle<Vec<(u32, u32, LinearRGB)>>>,
+    options: BakeOptions,
+    num_texels: u32,
+    completed: Arc<AtomicU32>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Baker
+{
+    pub fn new(scene: Scene, triangles: Vec<Triangle>, options: BakeOptions) -> Self
+    {
+        let texels = rasterize_texels(&triangles, &options);
+        let num_texels = texels.len() as u32;
+        let completed = Arc::new(AtomicU32::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let num_threads = num_cpus::get().max(1);
+        let texels_per_thread = texels.len().div_ceil(num_threads).max(1);
+
+        let threads = texels
+            .chunks(texels_per_thread)
+            .map(|chunk|
+            {
+                let chunk = chunk.to_vec();
+                let scene = scene.clone();
+                let samples = options.samples_per_texel.max(1);
+                let completed = completed.clone();
+                let cancelled = cancelled.clone();
+
+                std::thread::spawn(move || bake_thread(scene, chunk, samples, completed, cancelled))
+            })
+            .collect();
+
+        Baker { threads, options, num_texels, completed, cancelled }
+    }
+
+    pub fn progress(&self) -> Scalar
+    {
+        if self.num_texels == 0
+        {
+            return 1.0;
+        }
+
+        (self.completed.load(Ordering::Relaxed) as Scalar) / (self.num_texels as Scalar)
+    }
+
+    pub fn is_finished(&self) -> bool
+    {
+        self.threads.iter().all(|t| t.is_finished())
+    }
+
+    /// Returns the lightmap, once baking has completed.
+    /// Texels not covered by the UV layout are black, other
+    /// than those filled by dilating the edges of UV islands.
+    pub fn take_result(&mut self) -> Option<Lightmap>
+    {
+        if self.threads.is_empty() || !self.is_finished()
+        {
+            return None;
+        }
+
+        let mut lightmap = Lightmap::new(self.options.width, self.options.height);
+        let mut covered = vec![false; (self.options.width as usize) * (self.options.height as usize)];
+
+        for thread in self.threads.drain(..)
+        {
+            for (x, y, color) in thread.join().unwrap()
+            {
+                lightmap.put_pixel(x, y, Rgb([color.r as f32, color.g as f32, color.b as f32]));
+                covered[(y as usize) * (self.options.width as usize) + (x as usize)] = true;
+            }
+        }
+
+        for _ in 0..self.options.dilation
+        {
+            dilate(&mut lightmap, &mut covered);
+        }
+
+        Some(lightmap)
+    }
+}
+
+impl Drop for Baker
+{
+    fn drop(&mut self)
+    {
+        self.cancelled.store(true, Ordering::Relaxed);
+
+        for thread in self.threads.drain(..)
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn rasterize_texels(triangles: &[Triangle], options: &BakeOptions) -> Vec<Texel>
+{
+    let mut result = Vec::new();
+    let mut covered = vec![false; (options.width as usize) * (options.height as usize)];
+
+    for triangle in triangles.iter()
+    {
+        let p = triangle.vertices.each_ref().map(|v| v.location);
+        let uv = triangle.vertices.each_ref().map(|v| (v.texture_coords.x * (options.width as Scalar), v.texture_coords.y * (options.height as Scalar)));
+
+        let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+        if normal.magnitude_squared() < EPSILON * EPSILON
+        {
+            continue;
+        }
+        let normal = normal.normalized();
+
+        let area = edge(uv[0], uv[1], uv[2]);
+        if area.abs() < EPSILON
+        {
+            continue;
+        }
+
+        let min_x = uv.iter().map(|t| t.0).fold(Scalar::MAX, Scalar::min).floor().max(0.0) as u32;
+        let min_y = uv.iter().map(|t| t.1).fold(Scalar::MAX, Scalar::min).floor().max(0.0) as u32;
+        let max_x = (uv.iter().map(|t| t.0).fold(Scalar::MIN, Scalar::max).ceil() as u32).min(options.width);
+        let max_y = (uv.iter().map(|t| t.1).fold(Scalar::MIN, Scalar::max).ceil() as u32).min(options.height);
+
+        for y in min_y..max_y
+        {
+            for x in min_x..max_x
+            {
+                let index = (y as usize) * (options.width as usize) + (x as usize);
+                if covered[index]
+                {
+                    continue;
+                }
+
+                // Test the texel center against the triangle in UV space
+
+                let c = ((x as Scalar) + 0.5, (y as Scalar) + 0.5);
+
+                let w0 = edge(uv[1], uv[2], c) / area;
+                let w1 = edge(uv[2], uv[0], c) / area;
+                let w2 = edge(uv[0], uv[1], c) / area;
+
+                if (w0 >= 0.0) && (w1 >= 0.0) && (w2 >= 0.0)
+                {
+                    covered[index] = true;
+
+                    result.push(Texel
+                    {
+                        x,
+                        y,
+                        location: w0 * p[0] + w1 * p[1] + w2 * p[2],
+                        normal,
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn edge(a: (Scalar, Scalar), b: (Scalar, Scalar), c: (Scalar, Scalar)) -> Scalar
+{
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn bake_thread(scene: Scene, texels: Vec<Texel>, samples: u32, completed: Arc<AtomicU32>, cancelled: Arc<AtomicBool>) -> Vec<(u32, u32, LinearRGB)>
+{
+    let mut sampler = Sampler::new();
+    let mut stats = SceneSampleStats::new();
+    let mut result = Vec::with_capacity(texels.len());
+
+    for texel in texels
+    {
+        if cancelled.load(Ordering::Relaxed)
+        {
+            break;
+        }
+
+        // Cosine-weighted sampling of the hemisphere above the
+        // surface, so the irradiance estimate is just PI
+        // times the average incoming radiance
+
+        let origin = texel.location + 1.0e-4 * texel.normal;
+        let mut sum = LinearRGB::black();
+
+        for _ in 0..samples
+        {
+            let dir = (texel.normal + sampler.uniform_dir_on_unit_sphere()).normalized();
+            let (color, probability) = scene.path_trace_global_lighting_ray(Ray::new(origin, dir), &mut sampler, &mut stats);
+
+            if probability > 0.0
+            {
+                sum = sum + color.divided_by_scalar(probability);
+            }
+        }
+
+        let irradiance = sum.multiplied_by_scalar(ScalarConsts::PI / (samples as Scalar));
+
+        result.push((texel.x, texel.y, irradiance));
+        completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    result
+}
+
+// Grows the covered region by one texel, averaging covered
+// neighbours, so that bilinear filtering at UV seams
+// doesn't bleed in black texels
+
+fn dilate(lightmap: &mut Lightmap, covered: &mut [bool])
+{
+    let (width, height) = lightmap.dimensions();
+    let mut updates = Vec::new();
+
+    for y in 0..height
+    {
+        for x in 0..width
+        {
+            if covered[(y as usize) * (width as usize) + (x as usize)]
+            {
+                continue;
+            }
+
+            let mut sum = [0.0f32; 3];
+            let mut count = 0;
+
+            for (dx, dy) in [(-1i32, 0i32), (1, 0), (0, -1), (0, 1)]
+            {
+                let nx = (x as i32) + dx;
+                let ny = (y as i32) + dy;
+
+                if (nx >= 0) && (ny >= 0) && (nx < width as i32) && (ny < height as i32)
+                    && covered[(ny as usize) * (width as usize) + (nx as usize)]
+                {
+                    let p = lightmap.get_pixel(nx as u32, ny as u32);
+                    sum[0] += p.0[0];
+                    sum[1] += p.0[1];
+                    sum[2] += p.0[2];
+                    count += 1;
+                }
+            }
+
+            if count > 0
+            {
+                let count = count as f32;
+                updates.push((x, y, Rgb([sum[0] / count, sum[1] / count, sum[2] / count])));
+            }
+        }
+    }
+
+    for (x, y, color) in updates
+    {
+        lightmap.put_pixel(x, y, color);
+        covered[(y as usize) * (width as usize) + (x as usize)] = true;
+    }
+}
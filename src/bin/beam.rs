@@ -1,416 +1,3322 @@
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
 
 use glium::Surface;
 use winit::event::{ElementState, Event, ModifiersState, VirtualKeyCode, WindowEvent};
 
-use beam::desc::{SceneDescription, StandardScene};
+use beam::autosave::{Autosave, AutosaveDocument};
+use beam::bake::{Baker, BakeOptions};
+use beam::capture::{CaptureOptions, EnvironmentCapture};
+use beam::color::{ColorGrade, CubeLut, DisplayTransform, FilmEmulation, LutInput, ResponseCurve, ToneMapping, ToneOperator};
+use beam::desc::{SceneDescription, ScriptRunner, StandardScene};
+use beam::desc::edit::{Camera, Clipboard, ClipboardItem, ContactSheet, ContactSheetMode, StudioRig, Turntable};
+use beam::exec::{Completion, ExecLimits, FunctionDoc};
+use beam::export::{BurnIn, RenderImage};
+use beam::geom::{MeshCache, OctreeQuality};
+use beam::occlusion::AoCache;
+use beam::indexed::{AnyIndex, GroupIndex, Index, MaterialIndex, ObjectIndex, TextureIndex};
 use beam::math::Scalar;
-use beam::render::{Renderer, RenderOptions, RenderIlluminationMode};
-use beam::scene::SamplingMode;
-use beam::ui::{UiDisplay, UiEdit, UiRenderer};
+use beam::probe::Sh9;
+use beam::render::{FrameHistory, InteractionTracker, PixelRect, Renderer, RenderOptions, RenderIlluminationMode};
+use beam::sample::Sampler;
+use beam::scene::{PixelInspection, SampleTechnique, SamplingMode};
+use beam::settings::{AppSettings, RenderPreset};
+use beam::ui::{CompareMode, CompareSettings, UiDisplay, UiEdit, UiRenderer};
 use beam::vec::{Mat4, Vec3, Vec4};
 
 
 fn main() -> Result<(), String>
 {
-    let filename = std::env::args().nth(1);
+    if std::env::args().nth(1).as_deref() == Some("diff")
+    {
+        return diff_files(&std::env::args().skip(2).collect::<Vec<_>>());
+    }
+
+    let mut filename = None;
+    let mut preset = None;
+    let mut status_file = None;
+    let mut output = None;
+    let mut size = (800, 600);
+    let mut tone_mapping = ToneMapping::default();
+    let mut display = None;
+    let mut lut_input = LutInput::Log2;
+    let mut grades = Vec::new();
+    let mut film: Option<FilmEmulation> = None;
+    let mut burn_in = None;
+    let mut contact_sheet = None;
+    let mut variants = Vec::new();
+    let mut octree_quality = None;
+    let mut radiance_cache = false;
+    let mut path_guiding = false;
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next()
+    {
+        if arg == "--preset"
+        {
+            preset = Some(args.next().ok_or_else(|| "--preset requires a preset name".to_owned())?);
+        }
+        else if arg == "--status-file"
+        {
+            status_file = Some(args.next().ok_or_else(|| "--status-file requires a file name".to_owned())?);
+        }
+        else if arg == "--output"
+        {
+            output = Some(args.next().ok_or_else(|| "--output requires a file name".to_owned())?);
+        }
+        else if arg == "--size"
+        {
+            let value = args.next().ok_or_else(|| "--size requires a size, e.g. 1920x1080".to_owned())?;
+            size = parse_size(&value).ok_or_else(|| format!("Invalid size {:?} - expected e.g. 1920x1080", value))?;
+        }
+        else if arg == "--exposure"
+        {
+            let value = args.next().ok_or_else(|| "--exposure requires a number of stops".to_owned())?;
+            tone_mapping.exposure = value.parse().map_err(|_| format!("Invalid exposure {:?}", value))?;
+        }
+        else if arg == "--tone-map"
+        {
+            let value = args.next().ok_or_else(|| "--tone-map requires none, reinhard or aces".to_owned())?;
+            tone_mapping.operator = ToneOperator::from_name(&value).ok_or_else(|| format!("Invalid tone map {:?} - expected none, reinhard or aces", value))?;
+        }
+        else if arg == "--gamma"
+        {
+            let value = args.next().ok_or_else(|| "--gamma requires a gamma".to_owned())?;
+            tone_mapping.gamma = value.parse().map_err(|_| format!("Invalid gamma {:?}", value))?;
+        }
+        else if arg == "--display"
+        {
+            display = Some(args.next().ok_or_else(|| "--display requires linear, srgb or a .cube file".to_owned())?);
+        }
+        else if arg == "--lut-input"
+        {
+            let value = args.next().ok_or_else(|| "--lut-input requires linear, srgb or log2".to_owned())?;
+            lut_input = LutInput::from_name(&value).ok_or_else(|| format!("Invalid LUT input {:?} - expected linear, srgb or log2", value))?;
+        }
+        else if arg == "--grade"
+        {
+            let value = args.next().ok_or_else(|| "--grade requires a .cube file".to_owned())?;
+            grades.push(ColorGrade::load(std::path::Path::new(&value))?);
+        }
+        else if arg == "--grade-strength"
+        {
+            let value = args.next().ok_or_else(|| "--grade-strength requires a strength".to_owned())?;
+            let grade = grades.last_mut().ok_or_else(|| "--grade-strength must follow a --grade".to_owned())?;
+            grade.strength = value.parse().map_err(|_| format!("Invalid grade strength {:?}", value))?;
+        }
+        else if arg == "--film-response"
+        {
+            let value = args.next().ok_or_else(|| format!("--film-response requires a curve file or one of {}", ResponseCurve::PRESET_NAMES.join(", ")))?;
+            film.get_or_insert_with(FilmEmulation::default).response = Some(std::sync::Arc::new(ResponseCurve::from_arg(&value)?));
+        }
+        else if arg == "--grain-size"
+        {
+            let value = args.next().ok_or_else(|| "--grain-size requires a size in pixels".to_owned())?;
+            film.get_or_insert_with(FilmEmulation::default).grain_size = value.parse().map_err(|_| format!("Invalid grain size {:?}", value))?;
+        }
+        else if arg == "--grain-intensity"
+        {
+            let value = args.next().ok_or_else(|| "--grain-intensity requires an intensity".to_owned())?;
+            film.get_or_insert_with(FilmEmulation::default).grain_intensity = value.parse().map_err(|_| format!("Invalid grain intensity {:?}", value))?;
+        }
+        else if arg == "--burn-in"
+        {
+            let template = args.next().ok_or_else(|| "--burn-in requires a text template, e.g. \"{scene} {spp} spp\"".to_owned())?;
+            burn_in.get_or_insert_with(BurnIn::default).template = template;
+        }
+        else if arg == "--burn-in-logo"
+        {
+            burn_in.get_or_insert_with(BurnIn::default).logo = Some(args.next().ok_or_else(|| "--burn-in-logo requires an image file".to_owned())?);
+        }
+        else if arg == "--safe-areas"
+        {
+            burn_in.get_or_insert_with(BurnIn::default).safe_areas = true;
+        }
+        else if arg == "--contact-sheet"
+        {
+            let value = args.next().ok_or_else(|| "--contact-sheet requires cameras, materials or variants".to_owned())?;
+            let mode = ContactSheetMode::from_name(&value).ok_or_else(|| format!("Invalid contact sheet mode {:?} - expected cameras, materials or variants", value))?;
+            contact_sheet.get_or_insert(ContactSheet { mode, columns: 0 }).mode = mode;
+        }
+        else if arg == "--columns"
+        {
+            let value = args.next().ok_or_else(|| "--columns requires a number of columns".to_owned())?;
+            let columns = value.parse().map_err(|_| format!("Invalid number of columns {:?}", value))?;
+            contact_sheet.get_or_insert(ContactSheet { mode: ContactSheetMode::Cameras, columns }).columns = columns;
+        }
+        else if arg == "--octree-quality"
+        {
+            let value = args.next().ok_or_else(|| "--octree-quality requires fast, balanced or high-quality".to_owned())?;
+            octree_quality = Some(OctreeQuality::from_name(&value).ok_or_else(|| format!("Invalid octree quality {:?} - expected fast, balanced or high-quality", value))?);
+        }
+        else if arg == "--radiance-cache"
+        {
+            radiance_cache = true;
+        }
+        else if arg == "--path-guiding"
+        {
+            path_guiding = true;
+        }
+        else if arg == "--variant"
+        {
+            variants.push(args.next().ok_or_else(|| "--variant requires a set and variant, e.g. Paint=Red".to_owned())?);
+        }
+        else
+        {
+            filename = Some(arg);
+        }
+    }
+
+    if let Some(output) = output
+    {
+        let filename = filename.ok_or_else(|| "--output requires a scene file".to_owned())?;
+        let mut options = RenderOptions::new(size.0, size.1);
+        options.status_file = status_file;
+        options.tone_mapping = tone_mapping;
+        options.radiance_cache = radiance_cache;
+        options.path_guiding = path_guiding;
+        options.grades = grades;
+        options.film = film;
+
+        if let Some(display) = display
+        {
+            options.display_transform = DisplayTransform::from_arg(&display, lut_input)?;
+        }
+
+        return render_headless(&filename, &output, options, preset, octree_quality, burn_in, contact_sheet, &variants);
+    }
+
     let system = beam::ui::System::init("Beam");
-    let app_state = AppState::new(&system, 128, 128, filename);
+    let app_state = AppState::new(&system, 128, 128, filename, preset, status_file);
     system.main_loop(app_state);
 }
 
-struct AppState
+/// Loads a scene from a script, or a JSON file
+fn load_scene(filename: &str) -> Result<beam::desc::edit::Scene, String>
 {
+    let text = std::fs::read_to_string(filename)
+        .map_err(|e| format!("Could not read {:?}: {}", filename, e))?;
+
+    if is_json_filename(filename)
+    {
+        beam::desc::edit::scene_from_json(&text)
+    }
+    else
+    {
+        beam::desc::run_script_with_limits(&text, Some(std::path::Path::new(filename)), ExecLimits::default(), Arc::new(AtomicBool::new(false)))
+            .map(|(scene, _)| scene)
+            .map_err(|e| format!("Could not execute script: {:?}", e))
+    }
+}
+
+/// "beam diff [--json] old new" - reports the collection
+/// items and settings that differ between the two scenes
+fn diff_files(args: &[String]) -> Result<(), String>
+{
+    let json = args.iter().any(|a| a == "--json");
+    let files = args.iter().filter(|a| *a != "--json").collect::<Vec<_>>();
+
+    if files.len() != 2
+    {
+        return Err("diff requires two scene files, e.g. beam diff old.beam new.beam".to_owned());
+    }
+
+    let diff = beam::desc::edit::diff_scenes(&load_scene(files[0])?, &load_scene(files[1])?)?;
+
+    if json
+    {
+        println!("{}", serde_json::to_string_pretty(&diff).map_err(|e| format!("Could not write diff JSON: {}", e))?);
+    }
+    else if diff.is_empty()
+    {
+        println!("No differences");
+    }
+    else
+    {
+        print!("{}", diff.report());
+    }
+
+    Ok(())
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)>
+{
+    let (width, height) = value.split_once('x')?;
+    let (width, height) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+
+    if (width == 0) || (height == 0)
+    {
+        return None;
+    }
+
+    Some((width, height))
+}
+
+/// Renders the scene without opening a window, until the render
+/// completes (e.g. reaches the preset's samples), then saves it.
+/// Contact sheets, from the arguments or the script, are rendered
+/// with each thumbnail at the given size. Variants are
+/// given as "set=variant", overriding the scene's selection.
+/// The octree quality, if given, overrides the preset's.
+fn render_headless(filename: &str, output: &str, mut options: RenderOptions, preset: Option<String>, octree_quality: Option<OctreeQuality>, burn_in: Option<BurnIn>, contact_sheet: Option<ContactSheet>, variants: &[String]) -> Result<(), String>
+{
+    let mut scene = load_scene(filename)?;
+
+    for variant in variants
+    {
+        scene.select_variant_arg(variant)?;
+    }
+
+    let mut downscale = 1;
+
+    if let Some(name) = &preset
+    {
+        let settings = AppSettings::load(AppSettings::DEFAULT_FILENAME)?;
+        let preset = settings.find_preset(name).ok_or_else(|| format!("Unknown preset {:?}", name))?;
+        preset.apply(&mut downscale, &mut options);
+
+        options.width = (options.width / downscale).max(1);
+        options.height = (options.height / downscale).max(1);
+    }
+
+    if let Some(octree_quality) = octree_quality
+    {
+        options.octree_quality = octree_quality;
+    }
+
+    if let Some(sheet) = contact_sheet.or_else(|| scene.contact_sheet.clone())
+    {
+        let image = beam::export::render_contact_sheet(&scene, &sheet, &options)?;
+
+        return image.save(output)
+            .map_err(|e| format!("Could not save contact sheet {:?}: {}", output, e));
+    }
+
+    let desc = SceneDescription::new_edit(&scene);
+    let (image, progress) = beam::export::render_to_completion(desc.clone(), &options)?;
+
+    let metadata = beam::export::ExportMetadata
+    {
+        scene_file: Some(filename.to_owned()),
+        camera: desc.camera.clone(),
+        options,
+        num_samples: progress.stats.num_samples,
+        duration: progress.total_duration,
+        burn_in,
+    };
+
+    beam::export::export_render(std::path::Path::new(output), &image, &metadata)
+}
+
+struct Document
+{
+    name: String,
     filename: Option<String>,
-    downscale: u32,
-    options: RenderOptions,
     desc: SceneDescription,
     renderer: Renderer,
     pixels: beam::ui::PixelDisplay,
+    render_image: RenderImage,
     progress: Option<beam::render::RenderProgress>,
-    keyboard_modifiers: winit::event::ModifiersState,
+    probes: Vec<(Vec3, Sh9)>,
     scene: beam::desc::edit::Scene,
+    script: String,
+    script_vars: Vec<Completion>,
+    script_error: Option<String>,
+    script_runner: Option<ScriptRunner>,
+    /// Changes that haven't been saved to the file
+    script_modified: bool,
+    scene_modified: bool,
+    mesh_cache: MeshCache,
+    ao_cache: AoCache,
+    interaction: InteractionTracker,
+    history: FrameHistory,
+}
+
+impl Document
+{
+    pub fn new(display: &glium::Display, options: &RenderOptions, name: String, desc: SceneDescription, scene: beam::desc::edit::Scene) -> Self
+    {
+        let mesh_cache = MeshCache::new();
+        let ao_cache = AoCache::new();
+        let interaction = InteractionTracker::new();
+        let history = FrameHistory::new();
+        let renderer = Renderer::new(options.clone(), desc.clone(), mesh_cache.clone(), ao_cache.clone(), interaction.clone(), history.clone());
+        let pixels = beam::ui::PixelDisplay::new(display, options.width, options.height);
+        let render_image = RenderImage::new(options.width, options.height);
+
+        Document
+        {
+            name,
+            filename: None,
+            desc,
+            renderer,
+            pixels,
+            render_image,
+            progress: None,
+            probes: Vec::new(),
+            scene,
+            script: String::new(),
+            script_vars: Vec::new(),
+            script_error: None,
+            script_runner: None,
+            script_modified: false,
+            scene_modified: false,
+            mesh_cache,
+            ao_cache,
+            interaction,
+            history,
+        }
+    }
+
+    pub fn restart_renderer(&mut self, options: &RenderOptions)
+    {
+        // Stop the current render first, so its
+        // pixels are in the history for the next
+
+        self.renderer.stop();
+        self.renderer = Renderer::new(options.clone(), self.desc.clone(), self.mesh_cache.clone(), self.ao_cache.clone(), self.interaction.clone(), self.history.clone());
+    }
+
+    pub fn load_file(&mut self, filename: &str, limits: &ExecLimits)
+    {
+        self.filename = Some(filename.to_owned());
+        self.name = filename.to_owned();
+        self.script_modified = false;
+        self.scene_modified = false;
+
+        match std::fs::read_to_string(filename)
+        {
+            Ok(text) if is_json_filename(filename) =>
+            {
+                // There's no script to run - the
+                // scene is loaded directly
+
+                match beam::desc::edit::scene_from_json(&text)
+                {
+                    Ok(scene) =>
+                    {
+                        self.desc = SceneDescription::new_edit(&scene);
+                        self.scene = scene;
+                        self.script_error = None;
+                    },
+                    Err(err) =>
+                    {
+                        println!("Error: {}", err);
+                        self.script_error = Some(err);
+                    },
+                }
+            },
+            Ok(text) =>
+            {
+                self.script = text;
+                self.run_script(limits);
+            },
+            Err(err) =>
+            {
+                println!("Error: Could not load file: {:?}", err);
+                self.desc = SceneDescription::new_standard(StandardScene::Cornell);
+            },
+        }
+    }
+
+    /// Starts running the script in the background -
+    /// the scene is updated by `poll_script`.
+    pub fn run_script(&mut self, limits: &ExecLimits)
+    {
+        self.script_runner = Some(ScriptRunner::new(self.script.clone(), self.filename.as_ref().map(std::path::PathBuf::from), *limits));
+        self.script_error = None;
+    }
+
+    /// Returns true if the script has completed
+    /// and the scene has been updated.
+    pub fn poll_script(&mut self) -> bool
+    {
+        let result = match &mut self.script_runner
+        {
+            Some(runner) => match runner.take_result()
+            {
+                Some(result) => result,
+                None => return false,
+            },
+            None => return false,
+        };
+
+        self.script_runner = None;
+
+        match result
+        {
+            Ok((scene, vars)) =>
+            {
+                // A script's scene is what it creates - but
+                // a JSON file's scene has been replaced
+
+                self.desc = SceneDescription::new_edit(&scene);
+                self.scene = scene;
+                self.scene_modified = self.filename.as_deref().map(is_json_filename).unwrap_or(false);
+                self.script_vars = vars;
+                self.script_error = None;
+                true
+            },
+            Err(err) =>
+            {
+                println!("Error: Could not execute script: {:?}", err);
+                self.script_error = Some(err.message());
+                false
+            },
+        }
+    }
+
+    /// Keeps the camera, as moved in the UI, in the edit scene. It's
+    /// only an unsaved change for JSON files - it can't be saved to
+    /// a script, and a standard scene can be opened again.
+    pub fn update_scene_camera(&mut self)
+    {
+        self.scene.camera = self.desc.camera.clone();
+        self.scene_modified |= self.filename.as_deref().map(is_json_filename).unwrap_or(false);
+    }
+
+    pub fn is_modified(&self) -> bool
+    {
+        self.script_modified || self.scene_modified
+    }
+
+    /// What hasn't been saved - scene edits made in the
+    /// UI can only be saved to a JSON file
+    pub fn unsaved_changes(&self) -> String
+    {
+        let changes = match (self.script_modified, self.scene_modified)
+        {
+            (true, true) => "script and scene edits",
+            (true, false) => "script",
+            _ => "scene edits",
+        };
+
+        match &self.filename
+        {
+            Some(_) => format!("{}: {}", self.name, changes),
+            None => format!("{}: {}, with no file", self.name, changes),
+        }
+    }
+
+    /// Saves the script - or for JSON files, the scene,
+    /// which includes the results of the script
+    pub fn save(&mut self, filename: &str) -> Result<(), String>
+    {
+        if is_json_filename(filename)
+        {
+            let json = beam::desc::edit::scene_to_json(&self.scene)?;
+
+            std::fs::write(filename, json)
+                .map_err(|e| format!("Could not save {:?}: {}", filename, e))?;
+
+            self.script_modified = false;
+            self.scene_modified = false;
+        }
+        else
+        {
+            std::fs::write(filename, &self.script)
+                .map_err(|e| format!("Could not save {:?}: {}", filename, e))?;
+
+            self.script_modified = false;
+        }
+
+        self.filename = Some(filename.to_owned());
+        self.name = filename.to_owned();
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CompareSource
+{
+    Document(usize),
+    Snapshot(usize),
+    Reference,
+}
+
+struct Compare
+{
+    enabled: bool,
+    a: CompareSource,
+    b: CompareSource,
+    settings: CompareSettings,
+    auto_flicker: bool,
+    flicker_interval: f32,
+    last_flicker: Instant,
+    dragging_wipe: bool,
+    reference_filename: String,
+    reference: Option<image::RgbaImage>,
+    snapshots: Vec<(String, image::RgbaImage)>,
+    pixels: beam::ui::PixelDisplay,
 }
 
-impl AppState
-{
-    pub fn new(system: &beam::ui::System<()>, width: u32, height: u32, default_file: Option<String>) -> Self
+impl Compare
+{
+    pub fn new(display: &glium::Display) -> Self
+    {
+        Compare
+        {
+            enabled: false,
+            a: CompareSource::Document(0),
+            b: CompareSource::Reference,
+            settings: CompareSettings::default(),
+            auto_flicker: false,
+            flicker_interval: 0.5,
+            last_flicker: Instant::now(),
+            dragging_wipe: false,
+            reference_filename: String::new(),
+            reference: None,
+            snapshots: Vec::new(),
+            pixels: beam::ui::PixelDisplay::new(display, 1, 1),
+        }
+    }
+
+    fn source_name(&self, documents: &[Document], source: CompareSource) -> String
+    {
+        match source
+        {
+            CompareSource::Document(i) => documents.get(i).map(|d| format!("Live: {}", d.name)).unwrap_or_else(|| "Live: <closed>".to_owned()),
+            CompareSource::Snapshot(i) => self.snapshots.get(i).map(|s| format!("Snapshot: {}", s.0)).unwrap_or_else(|| "Snapshot: <none>".to_owned()),
+            CompareSource::Reference => format!("Reference: {}", self.reference_filename),
+        }
+    }
+
+    fn source_image<'a>(&'a self, documents: &'a [Document], source: CompareSource) -> Option<&'a image::RgbaImage>
+    {
+        match source
+        {
+            CompareSource::Document(i) => documents.get(i).map(|d| d.pixels.image()),
+            CompareSource::Snapshot(i) => self.snapshots.get(i).map(|s| &s.1),
+            CompareSource::Reference => self.reference.as_ref(),
+        }
+    }
+
+    fn ui_source(&self, ui: &UiRenderer, label: &str, documents: &[Document], source: &mut CompareSource)
+    {
+        let mut sources = (0..documents.len()).map(CompareSource::Document).collect::<Vec<_>>();
+        sources.extend((0..self.snapshots.len()).map(CompareSource::Snapshot));
+        sources.push(CompareSource::Reference);
+
+        if let Some(_combo) = ui.imgui.begin_combo(label, self.source_name(documents, *source))
+        {
+            for (i, option) in sources.iter().enumerate()
+            {
+                let _id = ui.imgui.push_id_usize(i);
+
+                if ui.imgui.selectable_config(self.source_name(documents, *option)).selected(*option == *source).build()
+                {
+                    *source = *option;
+                }
+            }
+        }
+    }
+
+    fn load_reference(&mut self)
+    {
+        match image::open(&self.reference_filename)
+        {
+            Ok(image) =>
+            {
+                self.reference = Some(image.to_rgba8());
+            },
+            Err(err) =>
+            {
+                println!("Error: Could not load reference image: {:?}", err);
+            },
+        }
+    }
+}
+
+/// Everything that can be done from the keyboard
+/// shortcuts or the command palette
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action
+{
+    LoadBeamExample,
+    LoadCornell,
+    LoadFurnace,
+    LoadVeach,
+    ReloadFile,
+    RunScript,
+    CancelScript,
+    BuildEditScene,
+    AppendScene,
+    NewDocument,
+    DuplicateDocument,
+    GlobalBsdfAndLights,
+    GlobalLightsOnly,
+    GlobalBsdfOnly,
+    GlobalUniform,
+    LocalIllumination,
+    ToggleIllumination,
+    ToggleDenoise,
+    ApplyPreset(usize),
+    MoveLeft,
+    MoveRight,
+    MoveForward,
+    MoveBack,
+    RotateLeft,
+    RotateRight,
+    TiltUp,
+    TiltDown,
+    ZoomIn,
+    ZoomOut,
+    ExportImage,
+    ExportPbrt,
+    ExportGeometry,
+    ExportJson,
+    Turntable,
+    RenderAnimation,
+    ToggleCompare,
+    ToggleInspect,
+    ToggleFocusPick,
+    SelectPriorityRegion,
+    ClearPriorityRegion,
+    ToggleProbes,
+}
+
+struct ActionInfo
+{
+    action: Action,
+    name: &'static str,
+    shortcut: &'static str,
+}
+
+const ACTIONS: &[ActionInfo] = &[
+    ActionInfo { action: Action::LoadBeamExample, name: "Scene: Load Beam Example", shortcut: "1" },
+    ActionInfo { action: Action::LoadCornell, name: "Scene: Load Cornell Box", shortcut: "2" },
+    ActionInfo { action: Action::LoadFurnace, name: "Scene: Load Furnace", shortcut: "3" },
+    ActionInfo { action: Action::LoadVeach, name: "Scene: Load Veach", shortcut: "4" },
+    ActionInfo { action: Action::ReloadFile, name: "Scene: Reload File", shortcut: "0" },
+    ActionInfo { action: Action::RunScript, name: "Script: Run", shortcut: "" },
+    ActionInfo { action: Action::CancelScript, name: "Script: Cancel", shortcut: "" },
+    ActionInfo { action: Action::BuildEditScene, name: "Scene: Build Edited Scene", shortcut: "" },
+    ActionInfo { action: Action::AppendScene, name: "Scene: Append Scene File", shortcut: "" },
+    ActionInfo { action: Action::NewDocument, name: "Document: New", shortcut: "" },
+    ActionInfo { action: Action::DuplicateDocument, name: "Document: Duplicate", shortcut: "" },
+    ActionInfo { action: Action::GlobalBsdfAndLights, name: "Render: Global, BSDF and Lights Sampling", shortcut: "F1" },
+    ActionInfo { action: Action::GlobalLightsOnly, name: "Render: Global, Lights Only Sampling", shortcut: "F2" },
+    ActionInfo { action: Action::GlobalBsdfOnly, name: "Render: Global, BSDF Only Sampling", shortcut: "F3" },
+    ActionInfo { action: Action::GlobalUniform, name: "Render: Global, Uniform Sampling", shortcut: "F4" },
+    ActionInfo { action: Action::LocalIllumination, name: "Render: Local Illumination", shortcut: "F5" },
+    ActionInfo { action: Action::ToggleIllumination, name: "Render: Toggle Local/Global", shortcut: "L" },
+    ActionInfo { action: Action::ToggleDenoise, name: "Render: Toggle Denoise", shortcut: "" },
+    ActionInfo { action: Action::MoveLeft, name: "Camera: Move Left", shortcut: "Left" },
+    ActionInfo { action: Action::MoveRight, name: "Camera: Move Right", shortcut: "Right" },
+    ActionInfo { action: Action::MoveForward, name: "Camera: Move Forward", shortcut: "Up" },
+    ActionInfo { action: Action::MoveBack, name: "Camera: Move Back", shortcut: "Down" },
+    ActionInfo { action: Action::RotateLeft, name: "Camera: Rotate Left", shortcut: "Ctrl+Left" },
+    ActionInfo { action: Action::RotateRight, name: "Camera: Rotate Right", shortcut: "Ctrl+Right" },
+    ActionInfo { action: Action::TiltUp, name: "Camera: Tilt Up", shortcut: "Ctrl+Up" },
+    ActionInfo { action: Action::TiltDown, name: "Camera: Tilt Down", shortcut: "Ctrl+Down" },
+    ActionInfo { action: Action::ZoomIn, name: "Camera: Zoom In", shortcut: "Numpad +" },
+    ActionInfo { action: Action::ZoomOut, name: "Camera: Zoom Out", shortcut: "Numpad -" },
+    ActionInfo { action: Action::ExportImage, name: "Export: Image (PNG/EXR)", shortcut: "Ctrl+S" },
+    ActionInfo { action: Action::ExportPbrt, name: "Export: PBRT Scene", shortcut: "" },
+    ActionInfo { action: Action::ExportGeometry, name: "Export: Geometry (OBJ/glTF)", shortcut: "" },
+    ActionInfo { action: Action::ExportJson, name: "Export: Scene JSON", shortcut: "" },
+    ActionInfo { action: Action::Turntable, name: "Camera: Generate Turntable", shortcut: "" },
+    ActionInfo { action: Action::RenderAnimation, name: "Export: Render Camera Animation", shortcut: "" },
+    ActionInfo { action: Action::ToggleCompare, name: "View: Toggle Compare", shortcut: "" },
+    ActionInfo { action: Action::ToggleInspect, name: "View: Toggle Click To Inspect", shortcut: "" },
+    ActionInfo { action: Action::ToggleFocusPick, name: "Camera: Toggle Click To Pick Focus", shortcut: "" },
+    ActionInfo { action: Action::SelectPriorityRegion, name: "Render: Drag To Select Priority Region", shortcut: "" },
+    ActionInfo { action: Action::ClearPriorityRegion, name: "Render: Clear Priority Region", shortcut: "" },
+    ActionInfo { action: Action::ToggleProbes, name: "View: Toggle Probes", shortcut: "" },
+];
+
+fn action_for_key(keycode: VirtualKeyCode, ctrl: bool) -> Option<Action>
+{
+    match keycode
+    {
+        VirtualKeyCode::Key1 => Some(Action::LoadBeamExample),
+        VirtualKeyCode::Key2 => Some(Action::LoadCornell),
+        VirtualKeyCode::Key3 => Some(Action::LoadFurnace),
+        VirtualKeyCode::Key4 => Some(Action::LoadVeach),
+        VirtualKeyCode::Key0 => Some(Action::ReloadFile),
+        VirtualKeyCode::F1 => Some(Action::GlobalBsdfAndLights),
+        VirtualKeyCode::F2 => Some(Action::GlobalLightsOnly),
+        VirtualKeyCode::F3 => Some(Action::GlobalBsdfOnly),
+        VirtualKeyCode::F4 => Some(Action::GlobalUniform),
+        VirtualKeyCode::F5 => Some(Action::LocalIllumination),
+        VirtualKeyCode::L => Some(Action::ToggleIllumination),
+        VirtualKeyCode::S if ctrl => Some(Action::ExportImage),
+        VirtualKeyCode::Left => Some(if ctrl { Action::RotateLeft } else { Action::MoveLeft }),
+        VirtualKeyCode::Right => Some(if ctrl { Action::RotateRight } else { Action::MoveRight }),
+        VirtualKeyCode::Up => Some(if ctrl { Action::TiltUp } else { Action::MoveForward }),
+        VirtualKeyCode::Down => Some(if ctrl { Action::TiltDown } else { Action::MoveBack }),
+        VirtualKeyCode::NumpadAdd => Some(Action::ZoomIn),
+        VirtualKeyCode::NumpadSubtract => Some(Action::ZoomOut),
+        _ => None,
+    }
+}
+
+/// Ctrl+P - search for and run any action
+#[derive(Default)]
+struct CommandPalette
+{
+    open: bool,
+    just_opened: bool,
+    filter: String,
+}
+
+/// Adds the frame number to the filename,
+/// e.g. "frame.png" becomes "frame_0001.png"
+fn animation_frame_filename(filename: &str, frame: usize) -> String
+{
+    let path = std::path::Path::new(filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let name = match path.extension().and_then(|e| e.to_str())
+    {
+        Some(ext) => format!("{}_{:04}.{}", stem, frame, ext),
+        None => format!("{}_{:04}", stem, frame),
+    };
+
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Scenes saved as JSON are loaded directly,
+/// anything else is run as a script
+fn is_json_filename(filename: &str) -> bool
+{
+    std::path::Path::new(filename).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+/// A camera animation being rendered, one frame at a time
+struct AnimationRender
+{
+    document: usize,
+    frame: usize,
+    /// Restored once the animation is complete
+    camera: Camera,
+}
+
+/// What's waiting for unsaved changes to be
+/// saved or discarded, and the documents it affects
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UnsavedPrompt
+{
+    Exit,
+    Close(usize),
+    Reload(usize),
+}
+
+impl UnsavedPrompt
+{
+    fn verb(&self) -> &'static str
+    {
+        match self
+        {
+            UnsavedPrompt::Exit => "Exit",
+            UnsavedPrompt::Close(_) => "Close",
+            UnsavedPrompt::Reload(_) => "Reload",
+        }
+    }
+
+    fn includes(&self, document: usize) -> bool
+    {
+        match self
+        {
+            UnsavedPrompt::Exit => true,
+            UnsavedPrompt::Close(index) | UnsavedPrompt::Reload(index) => *index == document,
+        }
+    }
+}
+
+/// Objects selected in the outliner, and the
+/// settings for selecting them by material
+#[derive(Default)]
+struct Outliner
+{
+    selected: Vec<ObjectIndex>,
+    using_material: MaterialIndex,
+    using_texture: TextureIndex,
+    replacement: MaterialIndex,
+}
+
+struct AppState
+{
+    display: glium::Display,
+    downscale: u32,
+    options: RenderOptions,
+    documents: Vec<Document>,
+    active: usize,
+    select_active: bool,
+    keyboard_modifiers: winit::event::ModifiersState,
+    clipboard: Option<Clipboard>,
+    copy_object: ObjectIndex,
+    copy_material: MaterialIndex,
+    copy_group: GroupIndex,
+    compare: Compare,
+    export_filename: String,
+    display_lut_filename: String,
+    display_lut_input: LutInput,
+    grade_filename: String,
+    film_curve_filename: String,
+    burn_in_enabled: bool,
+    burn_in: BurnIn,
+    burn_in_logo: String,
+    pbrt_filename: String,
+    geometry_filename: String,
+    geometry_bake_transforms: bool,
+    json_filename: String,
+    bake_object: ObjectIndex,
+    bake_options: BakeOptions,
+    bake_filename: String,
+    baker: Option<Baker>,
+    capture_options: CaptureOptions,
+    capture_filename: String,
+    capture: Option<EnvironmentCapture>,
+    show_probes: bool,
+    probe_filename: String,
+    inspect_enabled: bool,
+    focus_pick_enabled: bool,
+    priority_select: bool,
+    priority_drag_start: Option<[f32; 2]>,
+    inspect_pixel: Option<(u32, u32)>,
+    inspection: Option<PixelInspection>,
+    show_paths: bool,
+    path_count: usize,
+    path_color_by_bounce: bool,
+    paths: Vec<PixelInspection>,
+    function_docs: Vec<std::rc::Rc<FunctionDoc>>,
+    function_filter: String,
+    script_prefix: String,
+    script_limits: ExecLimits,
+    append_filename: String,
+    studio_object: ObjectIndex,
+    studio_rig: StudioRig,
+    turntable: Turntable,
+    turntable_render: bool,
+    animation_filename: String,
+    animation: Option<AnimationRender>,
+    settings: AppSettings,
+    preset_name: String,
+    palette: CommandPalette,
+    outliner: Outliner,
+    autosave: Autosave,
+    /// Documents from a session that didn't exit cleanly,
+    /// until they're recovered or discarded
+    recovery: Option<Vec<AutosaveDocument>>,
+    save_filename: String,
+    /// Asking whether to save changes before continuing
+    unsaved_prompt: Option<UnsavedPrompt>,
+    exit_confirmed: bool,
+}
+
+impl AppState
+{
+    pub fn new(system: &beam::ui::System<()>, width: u32, height: u32, default_file: Option<String>, default_preset: Option<String>, status_file: Option<String>) -> Self
+    {
+        let display = system.display().clone();
+        let mut downscale = 1;
+        let mut options = RenderOptions::new(width, height);
+        options.status_file = status_file;
+
+        let settings = AppSettings::load(AppSettings::DEFAULT_FILENAME).unwrap_or_else(|err|
+        {
+            println!("Error: {}", err);
+            AppSettings::default()
+        });
+
+        let mut preset_name = "custom".to_owned();
+
+        if let Some(name) = &default_preset
+        {
+            match settings.find_preset(name)
+            {
+                Some(preset) =>
+                {
+                    preset.apply(&mut downscale, &mut options);
+                    preset_name = preset.name.clone();
+                },
+                None => println!("Error: Unknown preset {:?}", name),
+            }
+        }
+
+        let keyboard_modifiers = ModifiersState::empty();
+        let script_limits = ExecLimits::default();
+
+        let mut document = Document::new(
+            &display,
+            &options,
+            "Scene 1".to_owned(),
+            SceneDescription::new_standard(StandardScene::Cornell),
+            beam::desc::edit::Scene::new());
+
+        if let Some(filename) = &default_file
+        {
+            document.load_file(filename, &script_limits);
+        }
+
+        let compare = Compare::new(&display);
+
+        let mut autosave = Autosave::new(Autosave::default_dir(), settings.autosave_interval);
+        let recovery = autosave.load_recovery().unwrap_or_else(|err|
+        {
+            println!("Error: {}", err);
+            None
+        });
+
+        AppState
+        {
+            display,
+            downscale,
+            options,
+            documents: vec![document],
+            active: 0,
+            select_active: false,
+            keyboard_modifiers,
+            clipboard: None,
+            copy_object: ObjectIndex::default(),
+            copy_material: MaterialIndex::default(),
+            copy_group: GroupIndex::default(),
+            compare,
+            export_filename: "render.png".to_owned(),
+            display_lut_filename: "display.cube".to_owned(),
+            display_lut_input: LutInput::Log2,
+            grade_filename: "grade.cube".to_owned(),
+            film_curve_filename: "response.txt".to_owned(),
+            burn_in_enabled: false,
+            burn_in: BurnIn { template: BurnIn::DEFAULT_TEMPLATE.to_owned(), logo: None, safe_areas: false },
+            burn_in_logo: String::new(),
+            pbrt_filename: "scene.pbrt".to_owned(),
+            geometry_filename: "scene.gltf".to_owned(),
+            geometry_bake_transforms: false,
+            json_filename: "scene.json".to_owned(),
+            bake_object: ObjectIndex::default(),
+            bake_options: BakeOptions::default(),
+            bake_filename: "lightmap.exr".to_owned(),
+            baker: None,
+            capture_options: CaptureOptions::default(),
+            capture_filename: "environment.exr".to_owned(),
+            capture: None,
+            show_probes: false,
+            probe_filename: "probes.json".to_owned(),
+            inspect_enabled: false,
+            focus_pick_enabled: false,
+            priority_select: false,
+            priority_drag_start: None,
+            inspect_pixel: None,
+            inspection: None,
+            show_paths: false,
+            path_count: 16,
+            path_color_by_bounce: false,
+            paths: Vec::new(),
+            function_docs: beam::exec::inbuilt_function_docs(),
+            function_filter: String::new(),
+            script_prefix: String::new(),
+            script_limits,
+            append_filename: String::new(),
+            studio_object: ObjectIndex::default(),
+            studio_rig: StudioRig::default(),
+            turntable: Turntable::default(),
+            turntable_render: false,
+            animation_filename: "frame.png".to_owned(),
+            animation: None,
+            settings,
+            preset_name,
+            palette: CommandPalette::default(),
+            outliner: Outliner::default(),
+            autosave,
+            recovery,
+            save_filename: String::new(),
+            unsaved_prompt: None,
+            exit_confirmed: false,
+        }
+    }
+
+    fn doc(&self) -> &Document
+    {
+        &self.documents[self.active]
+    }
+
+    fn doc_mut(&mut self) -> &mut Document
+    {
+        &mut self.documents[self.active]
+    }
+
+    pub fn restart_renderer(&mut self)
+    {
+        let options = self.options.clone();
+        self.doc_mut().restart_renderer(&options);
+    }
+
+    /// Re-converts the existing pixels of every document after the
+    /// display options change, as the renderer only sends pixels
+    /// as they change
+    pub fn refresh_display(&mut self)
+    {
+        for doc in self.documents.iter_mut()
+        {
+            let image = doc.render_image.to_rgba8(&self.options);
+
+            for (x, y, pixel) in image.enumerate_pixels()
+            {
+                doc.pixels.set_pixel(x, y, *pixel);
+            }
+        }
+    }
+
+    fn render_display_ui(&mut self, imgui: &imgui::Ui)
+    {
+        let mut changed = false;
+        let options = &mut self.options;
+
+        changed |= imgui.slider("Exposure", -8.0, 8.0, &mut options.tone_mapping.exposure);
+
+        if let Some(_) = imgui.begin_combo("Tone Map", options.tone_mapping.operator.name())
+        {
+            for operator in ToneOperator::ALL.iter()
+            {
+                if imgui.selectable_config(operator.name()).selected(*operator == options.tone_mapping.operator).build()
+                {
+                    options.tone_mapping.operator = *operator;
+                    changed = true;
+                }
+            }
+        }
+
+        changed |= imgui.slider("Gamma", 0.5, 3.0, &mut options.tone_mapping.gamma);
+
+        if imgui.button("Reset Tone Mapping")
+        {
+            options.tone_mapping = ToneMapping::default();
+            changed = true;
+        }
+
+        imgui.separator();
+
+        if let Some(_) = imgui.begin_combo("Transform", options.display_transform.name())
+        {
+            for option in [DisplayTransform::Linear, DisplayTransform::Srgb].iter()
+            {
+                if imgui.selectable(option.name())
+                {
+                    options.display_transform = option.clone();
+                    changed = true;
+                }
+            }
+        }
+
+        imgui.input_text("LUT File", &mut self.display_lut_filename).build();
+
+        if let Some(_) = imgui.begin_combo("LUT Input", self.display_lut_input.name())
+        {
+            for input in LutInput::ALL.iter()
+            {
+                if imgui.selectable_config(input.name()).selected(*input == self.display_lut_input).build()
+                {
+                    self.display_lut_input = *input;
+
+                    if let DisplayTransform::Lut{ lut, .. } = &options.display_transform
+                    {
+                        options.display_transform = DisplayTransform::Lut{ lut: lut.clone(), input: *input };
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if imgui.button("Load LUT")
+        {
+            match CubeLut::load(std::path::Path::new(&self.display_lut_filename))
+            {
+                Ok(lut) =>
+                {
+                    options.display_transform = DisplayTransform::Lut{ lut: std::sync::Arc::new(lut), input: self.display_lut_input };
+                    changed = true;
+                },
+                Err(err) => println!("Error: {}", err),
+            }
+        }
+
+        imgui.separator();
+
+        let mut remove = None;
+
+        for (i, grade) in options.grades.iter_mut().enumerate()
+        {
+            let _id = imgui.push_id_usize(i);
+
+            changed |= imgui.slider(format!("Grade: {}", grade.lut.title), 0.0, 1.0, &mut grade.strength);
+            imgui.same_line();
+            if imgui.button("Remove")
+            {
+                remove = Some(i);
+            }
+        }
+
+        if let Some(i) = remove
+        {
+            options.grades.remove(i);
+            changed = true;
+        }
+
+        imgui.input_text("Grade File", &mut self.grade_filename).build();
+        imgui.same_line();
+        if imgui.button("Add Grade")
+        {
+            match ColorGrade::load(std::path::Path::new(&self.grade_filename))
+            {
+                Ok(grade) =>
+                {
+                    options.grades.push(grade);
+                    changed = true;
+                },
+                Err(err) => println!("Error: {}", err),
+            }
+        }
+
+        imgui.separator();
+
+        let mut film_enabled = options.film.is_some();
+
+        if imgui.checkbox("Film Emulation", &mut film_enabled)
+        {
+            options.film = if film_enabled { Some(FilmEmulation::default()) } else { None };
+            changed = true;
+        }
+
+        if let Some(film) = &mut options.film
+        {
+            if let Some(_combo) = imgui.begin_combo("Response", film.name())
+            {
+                if imgui.selectable("None")
+                {
+                    film.response = None;
+                    changed = true;
+                }
+
+                for name in ResponseCurve::PRESET_NAMES.iter()
+                {
+                    if imgui.selectable(name)
+                    {
+                        film.response = ResponseCurve::preset(name).map(std::sync::Arc::new);
+                        changed = true;
+                    }
+                }
+            }
+
+            imgui.input_text("Curve File", &mut self.film_curve_filename).build();
+            imgui.same_line();
+            if imgui.button("Load Curve")
+            {
+                match ResponseCurve::load(std::path::Path::new(&self.film_curve_filename))
+                {
+                    Ok(curve) =>
+                    {
+                        film.response = Some(std::sync::Arc::new(curve));
+                        changed = true;
+                    },
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+
+            changed |= imgui.slider("Grain Size", 1.0, 8.0, &mut film.grain_size);
+            changed |= imgui.slider("Grain Intensity", 0.0, 0.2, &mut film.grain_intensity);
+        }
+
+        if changed
+        {
+            self.refresh_display();
+        }
+    }
+
+    fn render_burn_in_ui(&mut self, imgui: &imgui::Ui)
+    {
+        imgui.checkbox("Burn-In on Export", &mut self.burn_in_enabled);
+        imgui.input_text("Text", &mut self.burn_in.template).build();
+        imgui.text_disabled("{scene} {spp} {samples} {size} {date} {time}");
+        imgui.input_text("Logo File", &mut self.burn_in_logo).build();
+        imgui.checkbox("Safe Areas", &mut self.burn_in.safe_areas);
+
+        self.burn_in.logo = Some(self.burn_in_logo.clone()).filter(|l| !l.is_empty());
+    }
+
+    /// Saves the documents that have a script or file - the
+    /// standard scenes can always be opened again
+    fn autosave(&mut self)
+    {
+        let documents = self.documents.iter()
+            .filter(|doc| !doc.script.is_empty() || doc.filename.is_some())
+            .map(|doc| Ok(AutosaveDocument
+            {
+                name: doc.name.clone(),
+                filename: doc.filename.clone(),
+                script: doc.script.clone(),
+                scene: beam::desc::edit::scene_to_json(&doc.scene)?,
+            }))
+            .collect::<Result<Vec<_>, String>>();
+
+        if let Err(err) = documents.and_then(|documents| self.autosave.save(&documents))
+        {
+            println!("Error: {}", err);
+        }
+    }
+
+    /// Opens the documents from the autosave, with the scenes as they
+    /// were - the scripts aren't run, so any edits made in the UI are kept
+    fn recover_documents(&mut self, documents: Vec<AutosaveDocument>)
+    {
+        for saved in documents
+        {
+            match beam::desc::edit::scene_from_json(&saved.scene)
+            {
+                Ok(scene) =>
+                {
+                    self.new_document(format!("{} (recovered)", saved.name), SceneDescription::new_edit(&scene), scene);
+
+                    let doc = self.doc_mut();
+                    doc.filename = saved.filename;
+                    doc.script_modified = !saved.script.is_empty();
+                    doc.script = saved.script;
+                    doc.scene_modified = true;
+                },
+                Err(err) => println!("Error: Could not recover {:?}: {}", saved.name, err),
+            }
+        }
+    }
+
+    /// Saves the active document to the save file - or if
+    /// that's empty, the file it was loaded from
+    fn save_document(&mut self)
+    {
+        let filename = match (self.save_filename.is_empty(), &self.doc().filename)
+        {
+            (false, _) => self.save_filename.clone(),
+            (true, Some(filename)) => filename.clone(),
+            (true, None) =>
+            {
+                println!("Error: Enter a file to save {:?} to", self.doc().name);
+                return;
+            },
+        };
+
+        if let Err(err) = self.doc_mut().save(&filename)
+        {
+            println!("Error: {}", err);
+        }
+    }
+
+    fn close_document(&mut self, index: usize)
+    {
+        self.documents.remove(index);
+        self.animation = None;
+        self.active = if self.active > index { self.active - 1 } else { self.active.min(self.documents.len() - 1) };
+        self.select_active = true;
+    }
+
+    fn reload_document(&mut self, index: usize)
+    {
+        let limits = self.script_limits;
+        let options = self.options.clone();
+        let doc = &mut self.documents[index];
+
+        if let Some(filename) = doc.filename.clone()
+        {
+            doc.load_file(&filename, &limits);
+            doc.restart_renderer(&options);
+        }
+    }
+
+    fn render_unsaved_ui(&mut self, ui: &UiRenderer)
+    {
+        let prompt = match self.unsaved_prompt
+        {
+            Some(prompt) => prompt,
+            None => return,
+        };
+
+        let verb = prompt.verb();
+        let mut save = false;
+        let mut discard = false;
+        let mut cancel = false;
+
+        if let Some(_unsaved_window) = ui.imgui.window("Unsaved Changes").always_auto_resize(true).begin()
+        {
+            ui.imgui.text("These documents have changes that haven't been saved:");
+
+            for (_, doc) in self.documents.iter().enumerate().filter(|(i, doc)| prompt.includes(*i) && doc.is_modified())
+            {
+                ui.imgui.bullet_text(doc.unsaved_changes());
+            }
+
+            save = ui.imgui.button(format!("Save and {}", verb));
+            ui.imgui.same_line();
+            discard = ui.imgui.button(format!("{} Without Saving", verb));
+            ui.imgui.same_line();
+            cancel = ui.imgui.button("Cancel");
+        }
+
+        let mut proceed = discard;
+
+        if save
+        {
+            // Anything that can't be saved, e.g. without a
+            // file, is still listed - so it doesn't continue
+
+            for (_, doc) in self.documents.iter_mut().enumerate().filter(|(i, doc)| prompt.includes(*i) && doc.is_modified())
+            {
+                if let Some(filename) = doc.filename.clone()
+                {
+                    if let Err(err) = doc.save(&filename)
+                    {
+                        println!("Error: {}", err);
+                    }
+                }
+            }
+
+            proceed = !self.documents.iter().enumerate().any(|(i, doc)| prompt.includes(i) && doc.is_modified());
+        }
+
+        if proceed
+        {
+            match prompt
+            {
+                UnsavedPrompt::Exit => self.exit_confirmed = true,
+                UnsavedPrompt::Close(index) => self.close_document(index),
+                UnsavedPrompt::Reload(index) => self.reload_document(index),
+            }
+        }
+
+        if proceed || cancel
+        {
+            self.unsaved_prompt = None;
+        }
+    }
+
+    fn render_recovery_ui(&mut self, ui: &UiRenderer)
+    {
+        let mut recover = false;
+        let mut discard = false;
+
+        if let Some(documents) = &self.recovery
+        {
+            if let Some(_recovery_window) = ui.imgui.window("Recover Documents").always_auto_resize(true).begin()
+            {
+                ui.imgui.text("Beam didn't exit cleanly. These documents were autosaved:");
+
+                for doc in documents.iter()
+                {
+                    ui.imgui.bullet_text(&doc.name);
+                }
+
+                recover = ui.imgui.button("Recover");
+                ui.imgui.same_line();
+                discard = ui.imgui.button("Discard");
+            }
+        }
+
+        if recover || discard
+        {
+            let documents = self.recovery.take().unwrap_or_default();
+
+            if recover
+            {
+                self.recover_documents(documents);
+            }
+
+            if let Err(err) = self.autosave.clear_recovery()
+            {
+                println!("Error: {}", err);
+            }
+        }
+    }
+
+    pub fn new_document(&mut self, name: String, desc: SceneDescription, scene: beam::desc::edit::Scene)
+    {
+        self.documents.push(Document::new(&self.display, &self.options, name, desc, scene));
+        self.active = self.documents.len() - 1;
+        self.select_active = true;
+    }
+
+    pub fn handle_keycode(&mut self, keycode: VirtualKeyCode, keymod: ModifiersState) -> bool
+    {
+        let ctrl = keymod.ctrl();
+
+        if ctrl && (keycode == VirtualKeyCode::P)
+        {
+            self.palette.open = !self.palette.open;
+            self.palette.just_opened = self.palette.open;
+            self.palette.filter.clear();
+            return false;
+        }
+
+        // Don't run shortcuts while typing into the palette
+
+        if self.palette.open
+        {
+            if keycode == VirtualKeyCode::Escape
+            {
+                self.palette.open = false;
+            }
+
+            return false;
+        }
+
+        let handled = match action_for_key(keycode, ctrl)
+        {
+            Some(action) => self.perform_action(action),
+            None => false,
+        };
+
+        if handled
+        {
+            self.options.max_blockiness = 8;
+            self.doc().interaction.notify();
+        }
+
+        handled
+    }
+
+    /// Performs the action and restarts the renderer if required
+    fn run_action(&mut self, action: Action)
+    {
+        if self.perform_action(action)
+        {
+            self.restart_renderer();
+        }
+    }
+
+    /// Returns true if the renderer needs to be restarted
+    fn perform_action(&mut self, action: Action) -> bool
+    {
+        let mut camera_changed = false;
+
+        let restart = match action
+        {
+            Action::LoadBeamExample =>
+            {
+                self.doc_mut().desc = SceneDescription::new_standard(StandardScene::BeamExample);
+                true
+            },
+            Action::LoadCornell =>
+            {
+                self.doc_mut().desc = SceneDescription::new_standard(StandardScene::Cornell);
+                true
+            },
+            Action::LoadFurnace =>
+            {
+                self.doc_mut().desc = SceneDescription::new_standard(StandardScene::Furnace);
+                true
+            },
+            Action::LoadVeach =>
+            {
+                self.doc_mut().desc = SceneDescription::new_standard(StandardScene::Veach);
+                true
+            },
+            Action::ReloadFile =>
+            {
+                if self.doc().filename.is_some() && self.unsaved_prompt.is_none()
+                {
+                    if self.doc().is_modified()
+                    {
+                        self.unsaved_prompt = Some(UnsavedPrompt::Reload(self.active));
+                    }
+                    else
+                    {
+                        self.reload_document(self.active);
+                    }
+                }
+
+                false
+            },
+            Action::RunScript =>
+            {
+                // The renderer restarts once the script completes
+
+                let limits = self.script_limits;
+                self.doc_mut().run_script(&limits);
+                false
+            },
+            Action::CancelScript =>
+            {
+                if let Some(runner) = &self.doc().script_runner
+                {
+                    runner.cancel();
+                }
+                false
+            },
+            Action::BuildEditScene =>
+            {
+                let doc = self.doc_mut();
+                doc.desc = SceneDescription::new_edit(&doc.scene);
+                true
+            },
+            Action::AppendScene =>
+            {
+                // Run with the same limits as the document's script,
+                // but wait for it, as it's being merged into the scene
+
+                let result = std::fs::read_to_string(&self.append_filename)
+                    .map_err(|e| format!("Could not read {:?}: {}", self.append_filename, e))
+                    .and_then(|text| if is_json_filename(&self.append_filename)
+                    {
+                        beam::desc::edit::scene_from_json(&text)
+                    }
+                    else
+                    {
+                        beam::desc::run_script_with_limits(&text, Some(std::path::Path::new(&self.append_filename)), self.script_limits, Arc::new(AtomicBool::new(false)))
+                            .map(|(scene, _)| scene)
+                            .map_err(|e| format!("{:?}", e))
+                    });
+
+                match result
+                {
+                    Ok(other) =>
+                    {
+                        let doc = self.doc_mut();
+                        beam::desc::edit::append_scene(&mut doc.scene, &other);
+                        doc.desc = SceneDescription::new_edit(&doc.scene);
+                        doc.scene_modified = true;
+                        true
+                    },
+                    Err(err) =>
+                    {
+                        println!("Error: Could not append scene: {}", err);
+                        false
+                    },
+                }
+            },
+            Action::NewDocument =>
+            {
+                let name = format!("Scene {}", self.documents.len() + 1);
+                let scene = beam::desc::edit::Scene::new();
+                let desc = SceneDescription::new_edit(&scene);
+                self.new_document(name, desc, scene);
+                false
+            },
+            Action::DuplicateDocument =>
+            {
+                let name = format!("{} (copy)", self.doc().name);
+                let desc = self.doc().desc.clone();
+                let scene = self.doc().scene.clone();
+                let modified = self.doc().scene_modified;
+                self.new_document(name, desc, scene);
+                self.doc_mut().scene_modified = modified;
+                false
+            },
+            Action::GlobalBsdfAndLights =>
+            {
+                self.options.illumination_mode = RenderIlluminationMode::Global;
+                self.options.sampling_mode = SamplingMode::BsdfAndLights;
+                true
+            },
+            Action::GlobalLightsOnly =>
+            {
+                self.options.illumination_mode = RenderIlluminationMode::Global;
+                self.options.sampling_mode = SamplingMode::LightsOnly;
+                true
+            },
+            Action::GlobalBsdfOnly =>
+            {
+                self.options.illumination_mode = RenderIlluminationMode::Global;
+                self.options.sampling_mode = SamplingMode::BsdfOnly;
+                true
+            },
+            Action::GlobalUniform =>
+            {
+                self.options.illumination_mode = RenderIlluminationMode::Global;
+                self.options.sampling_mode = SamplingMode::Uniform;
+                true
+            },
+            Action::LocalIllumination =>
+            {
+                self.options.illumination_mode = RenderIlluminationMode::Local;
+                true
+            },
+            Action::ToggleIllumination =>
+            {
+                self.options.illumination_mode = match self.options.illumination_mode
+                {
+                    RenderIlluminationMode::Local => RenderIlluminationMode::Global,
+                    RenderIlluminationMode::Global => RenderIlluminationMode::Local,
+                };
+                self.options.sampling_mode = SamplingMode::BsdfAndLights;
+                true
+            },
+            Action::ToggleDenoise =>
+            {
+                self.options.denoise = !self.options.denoise;
+                true
+            },
+            Action::ApplyPreset(index) =>
+            {
+                match self.settings.presets.get(index)
+                {
+                    Some(preset) =>
+                    {
+                        preset.apply(&mut self.downscale, &mut self.options);
+                        self.preset_name = preset.name.clone();
+                        true
+                    },
+                    None => false,
+                }
+            },
+            Action::MoveLeft =>
+            {
+                self.move_around(-1.0, 0.0);
+                camera_changed = true;
+                true
+            },
+            Action::MoveRight =>
+            {
+                self.move_around(1.0, 0.0);
+                camera_changed = true;
+                true
+            },
+            Action::MoveForward =>
+            {
+                self.move_around(0.0, -1.0);
+                camera_changed = true;
+                true
+            },
+            Action::MoveBack =>
+            {
+                self.move_around(0.0, 1.0);
+                camera_changed = true;
+                true
+            },
+            Action::RotateLeft =>
+            {
+                self.rotate_around(-10.0);
+                camera_changed = true;
+                true
+            },
+            Action::RotateRight =>
+            {
+                self.rotate_around(10.0);
+                camera_changed = true;
+                true
+            },
+            Action::TiltUp =>
+            {
+                self.tilt(10.0);
+                camera_changed = true;
+                true
+            },
+            Action::TiltDown =>
+            {
+                self.tilt(-10.0);
+                camera_changed = true;
+                true
+            },
+            Action::ZoomIn =>
+            {
+                let camera = &mut self.doc_mut().desc.camera;
+                camera.fov = (camera.fov - 5.0).clamp(1.0, 175.0);
+                camera_changed = true;
+                true
+            },
+            Action::ZoomOut =>
+            {
+                let camera = &mut self.doc_mut().desc.camera;
+                camera.fov = (camera.fov + 5.0).clamp(1.0, 175.0);
+                camera_changed = true;
+                true
+            },
+            Action::ExportImage =>
+            {
+                self.export();
+                false
+            },
+            Action::ExportPbrt =>
+            {
+                let doc = self.doc();
+
+                if let Err(err) = beam::export::pbrt::export_pbrt(std::path::Path::new(&self.pbrt_filename), &doc.scene, &doc.desc.camera, &self.options)
+                {
+                    println!("Error: {}", err);
+                }
+                false
+            },
+            Action::ExportGeometry =>
+            {
+                let doc = self.doc();
+                let path = std::path::Path::new(&self.geometry_filename);
+
+                // OBJ files always have the transforms applied
+
+                let result = match path.extension().and_then(|e| e.to_str())
+                {
+                    Some(ext) if ext.eq_ignore_ascii_case("obj") => beam::export::obj::export_obj(path, &doc.scene),
+                    _ => beam::export::gltf::export_gltf(path, &doc.scene, self.geometry_bake_transforms),
+                };
+
+                if let Err(err) = result
+                {
+                    println!("Error: {}", err);
+                }
+                false
+            },
+            Action::ExportJson =>
+            {
+                let result = beam::desc::edit::scene_to_json(&self.doc().scene)
+                    .and_then(|json| std::fs::write(&self.json_filename, json)
+                        .map_err(|e| format!("Could not save JSON file {:?}: {}", self.json_filename, e)));
+
+                if let Err(err) = result
+                {
+                    println!("Error: {}", err);
+                }
+                false
+            },
+            Action::Turntable =>
+            {
+                let keyframes = self.turntable.keyframes(&self.doc().scene.camera);
+                self.doc_mut().scene.camera_keyframes = keyframes;
+                self.doc_mut().scene_modified = true;
+
+                if self.turntable_render
+                {
+                    self.start_animation();
+                }
+                false
+            },
+            Action::RenderAnimation =>
+            {
+                self.start_animation();
+                false
+            },
+            Action::ToggleCompare =>
+            {
+                self.compare.enabled = !self.compare.enabled;
+                false
+            },
+            Action::ToggleInspect =>
+            {
+                self.inspect_enabled = !self.inspect_enabled;
+                false
+            },
+            Action::ToggleFocusPick =>
+            {
+                self.focus_pick_enabled = !self.focus_pick_enabled;
+                false
+            },
+            Action::SelectPriorityRegion =>
+            {
+                self.priority_select = true;
+                false
+            },
+            Action::ClearPriorityRegion =>
+            {
+                self.options.priority_region = None;
+                true
+            },
+            Action::ToggleProbes =>
+            {
+                self.show_probes = !self.show_probes;
+                false
+            },
+        };
+
+        if camera_changed
+        {
+            // Nothing else has changed, so the next
+            // render can start from this one's pixels
+
+            let doc = self.doc_mut();
+            doc.update_scene_camera();
+            doc.history.allow_reuse();
+        }
+
+        restart
+    }
+
+    fn move_around(&mut self, factor_left_right: Scalar, factor_forward_back: Scalar)
+    {
+        let camera = &mut self.doc_mut().desc.camera;
+
+        let look = camera.look_at - camera.location;
+        let right = look.cross(camera.up).normalized();
+        let back = right.cross(camera.up).normalized();
+        let up = camera.up.normalized();
+        
+        // Don't move in the up/down direction
+        let right = right - (up.dot(right) * up);
+        let back = back - (up.dot(right) * up);
+
+        // Work out how far to move
+        let dist_factor = 0.05 * look.magnitude();
+
+        let dir = dist_factor * (factor_left_right * right + factor_forward_back * back);
+
+        camera.location += dir;
+        camera.look_at += dir;
+    }
+
+    fn rotate_around(&mut self, degrees: Scalar)
+    {
+        let camera = &mut self.doc_mut().desc.camera;
+
+        let dir = camera.location - camera.look_at;
+
+        let rot = Mat4::rotation_3d(degrees.to_radians(), camera.up);
+
+        let new_dir: Vec3 = (rot * Vec4::from_direction(dir)).into();
+
+        camera.location = new_dir + camera.look_at;
+    }
+
+    fn tilt(&mut self, degrees: Scalar)
+    {
+        let camera = &mut self.doc_mut().desc.camera;
+
+        let dir = camera.location - camera.look_at;
+
+        let right = dir.cross(camera.up);
+
+        let rot = Mat4::rotation_3d(degrees.to_radians(), right);
+
+        let new_dir: Vec3 = (rot * Vec4::from_direction(dir)).into();
+
+        camera.location = new_dir + camera.look_at;
+    }
+}
+
+impl beam::ui::UiApplication<()> for AppState
+{
+    fn handle_event(&mut self, event: winit::event::Event<()>) -> Option<winit::event_loop::ControlFlow>
+    {
+        match event
+        {
+            Event::WindowEvent{ event, .. } =>
+            {
+                match event
+                {
+                    WindowEvent::ModifiersChanged(modifiers) =>
+                    {
+                        self.keyboard_modifiers = modifiers;
+                    },
+                    WindowEvent::KeyboardInput { input, .. } =>
+                    {
+                        if input.state == ElementState::Pressed
+                        {
+                            if let Some(virtual_keycode) = input.virtual_keycode
+                            {
+                                if self.handle_keycode(virtual_keycode, self.keyboard_modifiers)
+                                {
+                                    self.restart_renderer();
+                                }
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+            },
+            Event::LoopDestroyed =>
+            {
+                // A clean exit - so there's nothing to recover. An
+                // unanswered recovery is kept for the next session.
+
+                if let Err(err) = self.autosave.clear()
+                {
+                    println!("Error: {}", err);
+                }
+            },
+            _ => {},
+        }
+
+        if self.exit_confirmed
+        {
+            return Some(winit::event_loop::ControlFlow::Exit);
+        }
+
+        None
+    }
+
+    fn close_requested(&mut self) -> bool
+    {
+        if self.documents.iter().any(|doc| doc.is_modified())
+        {
+            self.unsaved_prompt = Some(UnsavedPrompt::Exit);
+            return false;
+        }
+
+        true
+    }
+
+    fn render_background(&mut self, display: &glium::Display, frame: &mut glium::Frame)
+    {
+        let frame_dimensions = frame.get_dimensions();
+        let desired_dimensions = (frame_dimensions.0 / self.downscale, frame_dimensions.1 / self.downscale);
+        if desired_dimensions != self.doc().pixels.dimensions()
+        {
+            let (width, height) = desired_dimensions;
+            self.options.width = width;
+            self.options.height = height;
+
+            for doc in self.documents.iter_mut()
+            {
+                doc.pixels.resize(width, height);
+                doc.render_image = RenderImage::new(width, height);
+                doc.restart_renderer(&self.options);
+            }
+        }
+
+        if self.compare.enabled
+        {
+            let documents = &self.documents;
+            let compare = &self.compare;
+
+            if let (Some(a), Some(b)) = (compare.source_image(documents, compare.a), compare.source_image(documents, compare.b))
+            {
+                let image = beam::ui::compare_images(a, b, &compare.settings, self.options.width, self.options.height);
+                self.compare.pixels.set_image(image);
+                self.compare.pixels.render(display, frame);
+                return;
+            }
+        }
+
+        self.doc_mut().pixels.render(display, frame);
+    }
+
+    fn render_ui(&mut self, ui: &UiRenderer)
+    {
+        // So indexes, e.g. the objects used by the
+        // tools, are picked from the active document
+
+        self.doc().scene.collection.set_ui_index_labels(ui);
+
+        if let Some(_documents_window) = ui.imgui.window("Documents").begin()
+        {
+            if let Some(_tab_bar) = ui.imgui.tab_bar("Documents")
+            {
+                let select_active = std::mem::take(&mut self.select_active);
+
+                for (i, doc) in self.documents.iter().enumerate()
+                {
+                    let mut flags = if select_active && (i == self.active) { imgui::TabItemFlags::SET_SELECTED } else { imgui::TabItemFlags::empty() };
+
+                    if doc.is_modified()
+                    {
+                        flags |= imgui::TabItemFlags::UNSAVED_DOCUMENT;
+                    }
+
+                    if let Some(_tab) = imgui::TabItem::new(format!("{}###{}", doc.name, i)).flags(flags).begin(ui.imgui)
+                    {
+                        if !select_active
+                        {
+                            self.active = i;
+                        }
+                    }
+                }
+            }
+
+            if ui.imgui.button("New")
+            {
+                self.run_action(Action::NewDocument);
+            }
+
+            ui.imgui.same_line();
+            if ui.imgui.button("Duplicate")
+            {
+                self.run_action(Action::DuplicateDocument);
+            }
+
+            ui.imgui.same_line();
+            if ui.imgui.button("Close") && (self.documents.len() > 1) && self.unsaved_prompt.is_none()
+            {
+                if self.doc().is_modified()
+                {
+                    self.unsaved_prompt = Some(UnsavedPrompt::Close(self.active));
+                }
+                else
+                {
+                    self.close_document(self.active);
+                }
+            }
+
+            ui.imgui.input_text("Save File", &mut self.save_filename).build();
+            ui.imgui.same_line();
+            if ui.imgui.button("Save")
+            {
+                self.save_document();
+            }
+
+            if ui.imgui.input_scalar("Autosave (seconds, 0 = off)", &mut self.settings.autosave_interval).enter_returns_true(true).build()
+            {
+                self.autosave.set_interval(self.settings.autosave_interval);
+
+                if let Err(err) = self.settings.save(AppSettings::DEFAULT_FILENAME)
+                {
+                    println!("Error: {}", err);
+                }
+            }
+        }
+
+        self.render_recovery_ui(ui);
+        self.render_unsaved_ui(ui);
+
+        if let Some(progress) = &self.documents[self.active].progress
+        {
+            if let Some(_progress_window) = ui.imgui.window("Progress").begin()
+            {
+                let mut changed = render_presets(ui.imgui, &mut self.settings, &mut self.preset_name, &mut self.downscale, &mut self.options);
+                changed |= render_progress(ui.imgui, &mut self.downscale, &mut self.options, progress);
+
+                if ui.imgui.button("Select Priority Region")
+                {
+                    self.priority_select = true;
+                }
+                ui.imgui.same_line();
+                if ui.imgui.button("Clear Priority Region")
+                {
+                    self.options.priority_region = None;
+                    changed = true;
+                }
+
+                if changed
+                {
+                    self.restart_renderer();
+                }
+
+                if ui.imgui.collapsing_header("Textures", imgui::TreeNodeFlags::empty())
+                {
+                    render_texture_settings(ui.imgui);
+                }
+
+                if ui.imgui.collapsing_header("Display", imgui::TreeNodeFlags::empty())
+                {
+                    self.render_display_ui(ui.imgui);
+                }
+
+                if ui.imgui.collapsing_header("Burn-In", imgui::TreeNodeFlags::empty())
+                {
+                    self.render_burn_in_ui(ui.imgui);
+                }
+
+                ui.imgui.input_text("Export File", &mut self.export_filename).build();
+                if ui.imgui.button("Export")
+                {
+                    self.export();
+                }
+            }
+        }
+        
+        if let Some(_editor_window) = ui.imgui.window("Editor Demo").begin()
+        {
+            self.doc().scene.ui_display(ui, "Display");
+
+            if self.doc_mut().scene.ui_edit(ui, "Edit")
+            {
+                self.doc_mut().scene_modified = true;
+            }
+
+            if ui.imgui.button("Build")
+            {
+                self.run_action(Action::BuildEditScene);
+            }
+
+            ui.imgui.input_text("PBRT File", &mut self.pbrt_filename).build();
+            ui.imgui.same_line();
+            if ui.imgui.button("Export PBRT")
+            {
+                self.run_action(Action::ExportPbrt);
+            }
+
+            ui.imgui.input_text("Geometry File", &mut self.geometry_filename).build();
+            ui.imgui.same_line();
+            if ui.imgui.button("Export Geometry")
+            {
+                self.run_action(Action::ExportGeometry);
+            }
+            ui.imgui.checkbox("Bake Transforms", &mut self.geometry_bake_transforms);
+
+            ui.imgui.input_text("JSON File", &mut self.json_filename).build();
+            ui.imgui.same_line();
+            if ui.imgui.button("Export JSON")
+            {
+                self.run_action(Action::ExportJson);
+            }
+
+            ui.imgui.input_text("Append File", &mut self.append_filename).build();
+            ui.imgui.same_line();
+            if ui.imgui.button("Append")
+            {
+                self.run_action(Action::AppendScene);
+            }
+
+            if ui.imgui.collapsing_header("Outliner", imgui::TreeNodeFlags::empty())
+            {
+                self.render_outliner_ui(ui);
+            }
+
+            if ui.imgui.collapsing_header("Studio Rig", imgui::TreeNodeFlags::empty())
+            {
+                self.render_studio_ui(ui);
+            }
+
+            if ui.imgui.collapsing_header("Animation", imgui::TreeNodeFlags::empty())
+            {
+                self.render_animation_ui(ui);
+            }
+
+            if ui.imgui.collapsing_header("Furnace Test", imgui::TreeNodeFlags::empty())
+            {
+                self.doc_mut().scene.furnace.ui_edit(ui, "Furnace");
+
+                if ui.imgui.button("Run Furnace Test")
+                {
+                    match SceneDescription::new_furnace(&self.doc().scene)
+                    {
+                        Ok(desc) =>
+                        {
+                            self.doc_mut().desc = desc;
+                            self.restart_renderer();
+                        },
+                        Err(err) => println!("Error: {}", err),
+                    }
+                }
+            }
+
+            if ui.imgui.collapsing_header("Light Baking", imgui::TreeNodeFlags::empty())
+            {
+                self.render_bake_ui(ui);
+            }
+
+            if ui.imgui.collapsing_header("Environment Capture", imgui::TreeNodeFlags::empty())
+            {
+                self.render_capture_ui(ui);
+            }
+
+            if ui.imgui.collapsing_header("Probes", imgui::TreeNodeFlags::empty())
+            {
+                self.render_probes_ui(ui);
+            }
+
+            if ui.imgui.collapsing_header("Clipboard", imgui::TreeNodeFlags::empty())
+            {
+                self.render_clipboard_ui(ui);
+            }
+        }
+
+        if let Some(_compare_window) = ui.imgui.window("Compare").begin()
+        {
+            self.render_compare_ui(ui);
+        }
+
+        if let Some(_script_window) = ui.imgui.window("Script").begin()
+        {
+            self.render_script_ui(ui);
+        }
+
+        if let Some(_reference_window) = ui.imgui.window("Script Reference").begin()
+        {
+            self.render_reference_ui(ui);
+        }
+
+        if let Some(_inspector_window) = ui.imgui.window("Inspector").begin()
+        {
+            self.render_inspector_ui(ui);
+        }
+
+        if self.palette.open
+        {
+            self.render_palette_ui(ui);
+        }
+
+        if self.priority_select || self.options.priority_region.is_some()
+        {
+            self.render_priority_region_ui(ui);
+        }
+
+        if (self.inspect_enabled || self.focus_pick_enabled)
+            && !self.priority_select
+            && !ui.imgui.io().want_capture_mouse
+            && ui.imgui.is_mouse_clicked(imgui::MouseButton::Left)
+        {
+            let [width, height] = ui.imgui.io().display_size;
+            let [x, y] = ui.imgui.io().mouse_pos;
+
+            if (x >= 0.0) && (y >= 0.0) && (x < width) && (y < height)
+            {
+                let px = ((x / width) * (self.options.width as f32)) as u32;
+                let py = ((y / height) * (self.options.height as f32)) as u32;
+
+                if self.focus_pick_enabled
+                {
+                    self.pick_focus(px, py);
+                }
+                else
+                {
+                    self.inspect_pixel = Some((px, py));
+                    self.inspect();
+                }
+            }
+        }
+
+        if self.show_probes
+        {
+            self.render_probe_overlay(ui);
+        }
+
+        if self.show_paths
+        {
+            self.render_path_overlay(ui);
+        }
+
+        if self.compare.enabled && (self.compare.settings.mode == CompareMode::Wipe)
+        {
+            self.render_wipe_bar(ui);
+        }
+
+        ui.imgui.show_metrics_window(&mut true);
+    }
+
+    fn idle(&mut self)
+    {
+        if let Some(baker) = &mut self.baker
+        {
+            if let Some(lightmap) = baker.take_result()
+            {
+                if let Err(err) = image::DynamicImage::ImageRgb32F(lightmap).save(&self.bake_filename)
+                {
+                    println!("Error: Could not save lightmap: {:?}", err);
+                }
+
+                self.baker = None;
+            }
+        }
+
+        if let Some(capture) = &mut self.capture
+        {
+            if let Some(map) = capture.take_result()
+            {
+                if let Err(err) = image::DynamicImage::ImageRgb32F(map).save(&self.capture_filename)
+                {
+                    println!("Error: Could not save environment map: {:?}", err);
+                }
+
+                self.capture = None;
+            }
+        }
+
+        if self.compare.enabled
+            && self.compare.auto_flicker
+            && (self.compare.last_flicker.elapsed().as_secs_f32() >= self.compare.flicker_interval)
+        {
+            self.compare.settings.show_b = !self.compare.settings.show_b;
+            self.compare.last_flicker = Instant::now();
+        }
+
+        let mut frame_complete = false;
+
+        for (index, doc) in self.documents.iter_mut().enumerate()
+        {
+            if doc.poll_script()
+            {
+                doc.restart_renderer(&self.options);
+            }
+
+            if let Some(update) = doc.renderer.get_update()
+            {
+                for pixel in update.pixels
+                {
+                    doc.render_image.update(&pixel);
+                    let (r, g, b, _) = self.options.display_color(pixel.color, pixel.rect.x, pixel.rect.y).to_u8_rgba_tuple();
+                    doc.pixels.set_pixel(pixel.rect.x, pixel.rect.y, image::Rgba([r, g, b, 255]));
+                }
+
+                doc.progress = Some(update.progress);
+
+                if update.complete && self.animation.as_ref().map(|a| a.document == index).unwrap_or(false)
+                {
+                    frame_complete = true;
+                }
+
+                if !update.probes.is_empty()
+                {
+                    doc.probes = update.probes;
+                }
+            }
+        }
+
+        if frame_complete
+        {
+            self.next_animation_frame();
+        }
+
+        if self.autosave.is_due()
+        {
+            self.autosave();
+        }
+    }
+}
+
+impl AppState
+{
+    fn render_palette_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+
+        // Actions, and the presets that can be applied
+
+        let mut entries = ACTIONS.iter()
+            .map(|info| (info.action, info.name.to_owned(), info.shortcut))
+            .chain(self.settings.presets.iter().enumerate().map(|(i, p)| (Action::ApplyPreset(i), format!("Preset: {}", p.name), "")))
+            .filter_map(|(action, name, shortcut)| beam::ui::fuzzy_match(&self.palette.filter, &name).map(|score| (score, action, name, shortcut)))
+            .collect::<Vec<_>>();
+
+        // Best matches first - the sort is stable, so
+        // equal matches stay in the registered order
+
+        entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut selected = None;
+        let mut open = self.palette.open;
+
+        if let Some(_palette_window) = imgui.window("Command Palette")
+            .opened(&mut open)
+            .collapsible(false)
+            .focus_on_appearing(true)
+            .begin()
+        {
+            if self.palette.just_opened
+            {
+                imgui.set_keyboard_focus_here();
+                self.palette.just_opened = false;
+            }
+
+            if imgui.input_text("Search", &mut self.palette.filter).enter_returns_true(true).build()
+            {
+                // Enter runs the best match
+                selected = entries.first().map(|e| e.1);
+            }
+
+            for (_, action, name, shortcut) in entries.iter()
+            {
+                if imgui.selectable(name)
+                {
+                    selected = Some(*action);
+                }
+
+                if !shortcut.is_empty()
+                {
+                    imgui.same_line();
+                    imgui.text_disabled(shortcut);
+                }
+            }
+        }
+
+        self.palette.open = open;
+
+        if let Some(action) = selected
+        {
+            self.palette.open = false;
+            self.run_action(action);
+        }
+    }
+
+    fn render_compare_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+        let compare = &mut self.compare;
+
+        imgui.checkbox("Enabled", &mut compare.enabled);
+
+        let mut a = compare.a;
+        let mut b = compare.b;
+        compare.ui_source(ui, "A", &self.documents, &mut a);
+        compare.ui_source(ui, "B", &self.documents, &mut b);
+        compare.a = a;
+        compare.b = b;
+
+        if imgui.button("Swap A/B")
+        {
+            std::mem::swap(&mut compare.a, &mut compare.b);
+        }
+
+        if let Some(_combo) = imgui.begin_combo("Mode", format!("{:?}", compare.settings.mode))
+        {
+            for mode in CompareMode::all()
+            {
+                if imgui.selectable_config(format!("{:?}", mode)).selected(*mode == compare.settings.mode).build()
+                {
+                    compare.settings.mode = *mode;
+                }
+            }
+        }
+
+        match compare.settings.mode
+        {
+            CompareMode::Wipe =>
+            {
+                imgui.slider("Wipe", 0.0, 1.0, &mut compare.settings.wipe);
+            },
+            CompareMode::Flicker =>
+            {
+                imgui.checkbox("Show B", &mut compare.settings.show_b);
+                imgui.checkbox("Auto Flicker", &mut compare.auto_flicker);
+                imgui.slider("Interval (s)", 0.1, 2.0, &mut compare.flicker_interval);
+            },
+            CompareMode::Difference | CompareMode::HeatMap =>
+            {
+                imgui.slider("Scale", 1.0, 50.0, &mut compare.settings.difference_scale);
+            },
+        }
+
+        if imgui.button("Snapshot Active Render")
+        {
+            let doc = &self.documents[self.active];
+            let name = format!("{} #{}", doc.name, compare.snapshots.len() + 1);
+            compare.snapshots.push((name, doc.pixels.image().clone()));
+        }
+
+        imgui.input_text("Reference", &mut compare.reference_filename).build();
+        imgui.same_line();
+        if imgui.button("Load")
+        {
+            compare.load_reference();
+        }
+    }
+
+    fn render_wipe_bar(&mut self, ui: &UiRenderer)
     {
-        let filename = default_file.clone();
-        let downscale = 1;
-        let options = RenderOptions::new(width, height);
-        let desc = SceneDescription::new_standard(StandardScene::Cornell);
-        let renderer = Renderer::new(options.clone(), desc.clone());
-        let pixels = beam::ui::PixelDisplay::new(system.display(), width, height);
-        let progress = None;
-        let keyboard_modifiers = ModifiersState::empty();
-        let scene = beam::desc::edit::Scene::new();
+        let imgui = ui.imgui;
+        let [width, height] = imgui.io().display_size;
+        let mouse = imgui.io().mouse_pos;
+        let x = self.compare.settings.wipe * width;
 
-        let mut result = AppState
+        if !imgui.io().want_capture_mouse
         {
-            filename,
-            downscale,
-            options,
-            desc,
-            renderer,
-            pixels,
-            progress,
-            keyboard_modifiers,
-            scene,
-        };
+            if imgui.is_mouse_clicked(imgui::MouseButton::Left) && ((mouse[0] - x).abs() < 8.0)
+            {
+                self.compare.dragging_wipe = true;
+            }
+        }
+
+        if !imgui.is_mouse_down(imgui::MouseButton::Left)
+        {
+            self.compare.dragging_wipe = false;
+        }
 
-        if let Some(filename) = &result.filename
+        if self.compare.dragging_wipe && (width > 0.0)
         {
-            let filename = filename.clone();
-            result.load_file(&filename);
-            result.renderer = result.new_renderer();
+            self.compare.settings.wipe = (mouse[0] / width).clamp(0.0, 1.0);
         }
 
-        result
+        let x = self.compare.settings.wipe * width;
+        let draw_list = imgui.get_background_draw_list();
+        draw_list.add_line([x, 0.0], [x, height], [1.0, 1.0, 1.0, 0.8]).thickness(2.0).build();
     }
 
-    pub fn new_renderer(&self) -> Renderer
+    fn export(&self)
     {
-        Renderer::new(self.options.clone(), self.desc.clone())
+        self.export_document(self.active, &self.export_filename);
     }
 
-    pub fn load_file(&mut self, filename: &str)
+    fn export_document(&self, index: usize, filename: &str)
     {
-        self.filename = Some(filename.to_owned());
+        let doc = &self.documents[index];
 
-        match std::fs::read_to_string(filename)
+        if let Some(progress) = &doc.progress
         {
-            Ok(text) =>
+            let metadata = beam::export::ExportMetadata
+            {
+                scene_file: doc.filename.clone(),
+                camera: doc.desc.camera.clone(),
+                options: self.options.clone(),
+                num_samples: progress.stats.num_samples,
+                duration: progress.total_duration,
+                burn_in: self.burn_in_enabled.then(|| self.burn_in.clone()),
+            };
+
+            if let Err(err) = beam::export::export_render(std::path::Path::new(filename), &doc.render_image, &metadata)
+            {
+                println!("Error: {}", err);
+            }
+        }
+    }
+
+    fn render_outliner_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+        let previous = self.outliner.selected.clone();
+
+        // Select by what the objects use
+
+        self.outliner.using_material.ui_edit(ui, "Material");
+        imgui.same_line();
+        if imgui.button("Select Using Material")
+        {
+            self.outliner.selected = self.doc().scene.objects_using(AnyIndex::Material(self.outliner.using_material));
+        }
+
+        self.outliner.using_texture.ui_edit(ui, "Texture");
+        imgui.same_line();
+        if imgui.button("Select Using Texture")
+        {
+            self.outliner.selected = self.doc().scene.objects_using(AnyIndex::Texture(self.outliner.using_texture));
+        }
+
+        // Bulk replace the material of the selected objects
+
+        self.outliner.replacement.ui_edit(ui, "Replacement");
+        imgui.same_line();
+        if imgui.button("Replace Material")
+        {
+            let selected = self.outliner.selected.clone();
+            let replacement = self.outliner.replacement;
+            self.doc_mut().scene.replace_material(&selected, replacement);
+            self.doc_mut().scene_modified = true;
+            self.run_action(Action::BuildEditScene);
+        }
+
+        // The objects - click to select, or ctrl+click
+        // to add or remove from the selection
+
+        imgui.text(format!("{} selected", self.outliner.selected.len()));
+
+        let objects = self.doc().scene.collection.map_all_with_index(|index: ObjectIndex, object: &beam::desc::edit::Object, _|
+            (index, format!("Object {} (geom {}, material {})", index.to_usize(), object.geom.to_usize(), object.material.to_usize())));
+
+        for (index, summary) in objects
+        {
+            let selected = self.outliner.selected.contains(&index);
+
+            if imgui.selectable_config(&summary).selected(selected).build()
             {
-                match beam::desc::run_script(&text)
+                if imgui.io().key_ctrl
                 {
-                    Ok(scene) =>
+                    if selected
                     {
-                        self.desc = SceneDescription::new_edit(&scene);
-                        self.scene = scene;
-                        return;
-                    },
-                    Err(err) =>
+                        self.outliner.selected.retain(|i| *i != index);
+                    }
+                    else
                     {
-                        println!("Error: Could not execute script: {:?}", err);
-                    },                    
+                        self.outliner.selected.push(index);
+                    }
                 }
-            },
-            Err(err) =>
-            {
-                println!("Error: Could not load file: {:?}", err);
-            },
+                else
+                {
+                    self.outliner.selected = vec![index];
+                }
+            }
         }
 
-        self.desc = SceneDescription::new_standard(StandardScene::Cornell);
+        // The furnace test defaults to the selected object
+
+        if self.outliner.selected != previous
+        {
+            if let Some(selected) = self.outliner.selected.first().copied()
+            {
+                self.doc_mut().scene.furnace.object = selected;
+            }
+        }
     }
 
-    pub fn handle_keycode(&mut self, keycode: VirtualKeyCode, keymod: ModifiersState) -> bool
+    fn render_animation_ui(&mut self, ui: &UiRenderer)
     {
-        let ctrl = keymod.ctrl();
+        let imgui = ui.imgui;
 
-        let mut camera_changed = false;
+        self.turntable.ui_edit(ui, "Turntable");
+        imgui.checkbox("Render After Generating", &mut self.turntable_render);
 
-        let handled = match keycode
+        if imgui.button("Generate Turntable")
         {
-            VirtualKeyCode::Key1 =>
-            {
-                self.desc = SceneDescription::new_standard(StandardScene::BeamExample);
-                true
-            },
-            VirtualKeyCode::Key2 =>
-            {
-                self.desc = SceneDescription::new_standard(StandardScene::Cornell);
-                true
-            },
-            VirtualKeyCode::Key3 =>
-            {
-                self.desc = SceneDescription::new_standard(StandardScene::Furnace);
-                true
-            },
-            VirtualKeyCode::Key4 =>
-            {
-                self.desc = SceneDescription::new_standard(StandardScene::Veach);
-                true
-            },
-            VirtualKeyCode::Key0 =>
+            self.run_action(Action::Turntable);
+        }
+
+        imgui.text(format!("Keyframes: {}", self.doc().scene.camera_keyframes.len()));
+        imgui.input_text("Frame File", &mut self.animation_filename).build();
+
+        match &self.animation
+        {
+            Some(animation) =>
             {
-                if let Some(filename) = self.filename.clone()
+                let frames = self.documents[animation.document].scene.camera_keyframes.len();
+
+                imgui.text(format!("Rendering frame {} of {}", animation.frame + 1, frames));
+
+                if imgui.button("Cancel")
                 {
-                    self.load_file(&filename);
-                    true
+                    self.finish_animation();
                 }
-                else
+            },
+            None =>
+            {
+                if imgui.button("Render Animation")
                 {
-                    false
+                    self.run_action(Action::RenderAnimation);
                 }
             },
-            VirtualKeyCode::F1 =>
-            {
-                self.options.illumination_mode = RenderIlluminationMode::Global;
-                self.options.sampling_mode = SamplingMode::BsdfAndLights;
-                true
-            }
-            VirtualKeyCode::F2 =>
-            {
-                self.options.illumination_mode = RenderIlluminationMode::Global;
-                self.options.sampling_mode = SamplingMode::LightsOnly;
-                true
-            }
-            VirtualKeyCode::F3 =>
+        }
+    }
+
+    /// Renders each of the active document's camera keyframes,
+    /// exporting each frame once its render is complete
+    fn start_animation(&mut self)
+    {
+        if self.doc().scene.camera_keyframes.is_empty()
+        {
+            println!("Error: The scene has no camera keyframes");
+            return;
+        }
+
+        self.finish_animation();
+
+        self.animation = Some(AnimationRender
+        {
+            document: self.active,
+            frame: 0,
+            camera: self.doc().desc.camera.clone(),
+        });
+
+        self.render_animation_frame();
+    }
+
+    fn render_animation_frame(&mut self)
+    {
+        if let Some(animation) = &self.animation
+        {
+            let doc = &mut self.documents[animation.document];
+            doc.desc.camera = doc.scene.camera_keyframes[animation.frame].clone();
+            doc.restart_renderer(&self.options);
+        }
+    }
+
+    /// Called when the current frame's render is complete
+    fn next_animation_frame(&mut self)
+    {
+        if let Some(animation) = &self.animation
+        {
+            let filename = animation_frame_filename(&self.animation_filename, animation.frame);
+            self.export_document(animation.document, &filename);
+        }
+
+        if let Some(animation) = &mut self.animation
+        {
+            animation.frame += 1;
+
+            if animation.frame < self.documents[animation.document].scene.camera_keyframes.len()
             {
-                self.options.illumination_mode = RenderIlluminationMode::Global;
-                self.options.sampling_mode = SamplingMode::BsdfOnly;
-                true
+                self.render_animation_frame();
             }
-            VirtualKeyCode::F4 =>
+            else
             {
-                self.options.illumination_mode = RenderIlluminationMode::Global;
-                self.options.sampling_mode = SamplingMode::Uniform;
-                true
+                self.finish_animation();
             }
-            VirtualKeyCode::F5 =>
+        }
+    }
+
+    fn finish_animation(&mut self)
+    {
+        if let Some(animation) = self.animation.take()
+        {
+            let doc = &mut self.documents[animation.document];
+            doc.desc.camera = animation.camera;
+            doc.restart_renderer(&self.options);
+        }
+    }
+
+    fn render_studio_ui(&mut self, ui: &UiRenderer)
+    {
+        self.studio_object.ui_edit(ui, "Target");
+        self.studio_rig.ui_edit(ui, "Rig");
+
+        if ui.imgui.button("Add Studio Rig")
+        {
+            match self.doc().scene.object_bounds(self.studio_object)
             {
-                self.options.illumination_mode = RenderIlluminationMode::Local;
-                true
+                Some(bounds) =>
+                {
+                    let rig = self.studio_rig.clone();
+                    rig.build(&mut self.doc_mut().scene, &bounds);
+                    self.doc_mut().scene_modified = true;
+                    self.run_action(Action::BuildEditScene);
+                },
+                None =>
+                {
+                    println!("Error: The target object has no bounds");
+                },
             }
-            VirtualKeyCode::L =>
+        }
+    }
+
+    fn render_bake_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+
+        self.bake_object.ui_edit(ui, "Object");
+        imgui.input_scalar("Width", &mut self.bake_options.width).build();
+        imgui.input_scalar("Height", &mut self.bake_options.height).build();
+        imgui.input_scalar("Samples/Texel", &mut self.bake_options.samples_per_texel).build();
+        imgui.input_scalar("Dilation", &mut self.bake_options.dilation).build();
+        imgui.input_text("Output File", &mut self.bake_filename).build();
+
+        match &self.baker
+        {
+            Some(baker) =>
             {
-                self.options.illumination_mode = match self.options.illumination_mode
+                imgui::ProgressBar::new(baker.progress() as f32).build(imgui);
+
+                if imgui.button("Cancel")
                 {
-                    RenderIlluminationMode::Local => RenderIlluminationMode::Global,
-                    RenderIlluminationMode::Global => RenderIlluminationMode::Local,
-                };
-                self.options.sampling_mode = SamplingMode::BsdfAndLights;
-                true
+                    self.baker = None;
+                }
             },
-            VirtualKeyCode::Left =>
+            None =>
             {
-                if ctrl
-                {
-                    self.rotate_around(-10.0);
-                }
-                else
+                if imgui.button("Bake")
                 {
-                    self.move_around(-1.0, 0.0);
+                    self.baker = Some(self.doc().scene.build_baker(&self.options, self.bake_object, &self.bake_options));
                 }
-                camera_changed = true;
-                true
             },
-            VirtualKeyCode::Right =>
+        }
+    }
+
+    fn render_capture_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+
+        ui.edit_vec3("Location", &mut self.capture_options.location);
+        imgui.input_scalar("Width", &mut self.capture_options.width).build();
+        imgui.input_scalar("Samples/Pixel", &mut self.capture_options.samples_per_pixel).build();
+        imgui.input_text("Output File", &mut self.capture_filename).build();
+
+        match &self.capture
+        {
+            Some(capture) =>
             {
-                if ctrl
-                {
-                    self.rotate_around(10.0);
-                }
-                else
+                imgui::ProgressBar::new(capture.progress() as f32).build(imgui);
+
+                if imgui.button("Cancel")
                 {
-                    self.move_around(1.0, 0.0);
+                    self.capture = None;
                 }
-                camera_changed = true;
-                true
             },
-            VirtualKeyCode::Up =>
+            None =>
             {
-                if ctrl
+                if imgui.button("Capture")
                 {
-                    self.tilt(10.0);
+                    let scene = self.doc().desc.build_scene(&self.options);
+                    self.capture = Some(EnvironmentCapture::new(scene, &self.capture_options));
                 }
-                else
-                {
-                    self.move_around(0.0, -1.0);
-                }
-                camera_changed = true;
-                true
             },
-            VirtualKeyCode::Down =>
+        }
+    }
+
+    fn render_probes_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+
+        imgui.checkbox("Show Probes", &mut self.show_probes);
+
+        for (location, sh) in self.doc().probes.iter()
+        {
+            imgui.text(format!("<{:.2}, {:.2}, {:.2}>: {} samples", location.x, location.y, location.z, sh.num_samples()));
+        }
+
+        imgui.input_text("Export File", &mut self.probe_filename).build();
+        imgui.same_line();
+        if imgui.button("Export")
+        {
+            if let Err(err) = std::fs::write(&self.probe_filename, beam::probe::probes_to_json(&self.doc().probes))
+            {
+                println!("Error: Could not export probes: {:?}", err);
+            }
+        }
+    }
+
+    // Draws each probe as a sphere, lit by the irradiance
+    // calculated from the probe's spherical harmonics
+
+    /// Draws the priority region, and lets the
+    /// user drag out a new one when selecting
+    fn render_priority_region_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+        let [width, height] = imgui.io().display_size;
+        let mouse = imgui.io().mouse_pos;
+        let draw_list = imgui.get_background_draw_list();
+
+        let (render_width, render_height) = (self.options.width as f32, self.options.height as f32);
+
+        let to_pixels = |p: [f32; 2]| -> (u32, u32)
+        {
+            let x = (p[0] / width).clamp(0.0, 1.0) * render_width;
+            let y = (p[1] / height).clamp(0.0, 1.0) * render_height;
+            (x as u32, y as u32)
+        };
+
+        if self.priority_select && !imgui.io().want_capture_mouse && imgui.is_mouse_clicked(imgui::MouseButton::Left)
+        {
+            self.priority_drag_start = Some(mouse);
+        }
+
+        match self.priority_drag_start
+        {
+            Some(start) if imgui.is_mouse_down(imgui::MouseButton::Left) =>
+            {
+                draw_list.add_rect(start, mouse, [1.0, 1.0, 0.0, 1.0]).build();
+            },
+            Some(start) =>
             {
-                if ctrl
+                let (x1, y1) = to_pixels(start);
+                let (x2, y2) = to_pixels(mouse);
+
+                self.priority_drag_start = None;
+                self.priority_select = false;
+
+                if (x1 != x2) && (y1 != y2)
                 {
-                    self.tilt(-10.0);
+                    self.options.priority_region = Some(PixelRect { x: x1.min(x2), y: y1.min(y2), width: x1.abs_diff(x2), height: y1.abs_diff(y2) });
+                    self.restart_renderer();
                 }
-                else
+            },
+            None =>
+            {
+                if let Some(region) = &self.options.priority_region
                 {
-                    self.move_around(0.0, 1.0);
+                    let scale_x = width / render_width;
+                    let scale_y = height / render_height;
+                    let min = [(region.x as f32) * scale_x, (region.y as f32) * scale_y];
+                    let max = [((region.x + region.width) as f32) * scale_x, ((region.y + region.height) as f32) * scale_y];
+
+                    draw_list.add_rect(min, max, [1.0, 1.0, 0.0, 0.5]).build();
                 }
-                camera_changed = true;
-                true
             },
-            VirtualKeyCode::NumpadAdd =>
+        }
+    }
+
+    fn render_probe_overlay(&self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+        let doc = self.doc();
+        let [width, height] = imgui.io().display_size;
+        let aspect_ratio = (self.options.width as Scalar) / (self.options.height as Scalar);
+        let camera = &doc.desc.camera;
+        let (right, up, back) = camera.basis();
+
+        let radii = doc.scene.collection.map_all(|probe: &beam::desc::edit::Probe, _| (probe.location, probe.display_radius));
+        let draw_list = imgui.get_background_draw_list();
+
+        for (location, sh) in doc.probes.iter()
+        {
+            let radius = radii.iter().find(|(l, _)| l == location).map(|(_, r)| *r).unwrap_or(16.0).max(2.0) as i32;
+
+            if let Some((u, v)) = camera.project(*location, aspect_ratio)
             {
-                self.desc.camera.fov = (self.desc.camera.fov - 5.0).clamp(1.0, 175.0);
-                camera_changed = true;
-                true
-            },
-            VirtualKeyCode::NumpadSubtract =>
+                let cx = (u as f32) * width;
+                let cy = (v as f32) * height;
+
+                for dy in -radius..=radius
+                {
+                    for dx in -radius..=radius
+                    {
+                        let nx = (dx as Scalar) / (radius as Scalar);
+                        let ny = -(dy as Scalar) / (radius as Scalar);
+                        let nz_squared = 1.0 - nx * nx - ny * ny;
+
+                        if nz_squared < 0.0
+                        {
+                            continue;
+                        }
+
+                        let normal = nx * right + ny * up + nz_squared.sqrt() * back;
+                        let color = sh.irradiance(normal).multiplied_by_scalar(std::f64::consts::FRAC_1_PI).to_srgb();
+
+                        let p = [cx + dx as f32, cy + dy as f32];
+                        draw_list.add_rect(p, [p[0] + 1.0, p[1] + 1.0], [color.r as f32, color.g as f32, color.b as f32, 1.0]).filled(true).build();
+                    }
+                }
+            }
+        }
+    }
+
+    fn inspect(&mut self)
+    {
+        if let Some((px, py)) = self.inspect_pixel
+        {
+            let doc = self.doc();
+            let scene = doc.desc.build_scene_with_monitor(&self.options, &mut (), &doc.mesh_cache).expect("Build can't be cancelled");
+            let mut sampler = Sampler::new();
+
+            let u = ((px as Scalar) + 0.5) / (self.options.width as Scalar);
+            let v = ((py as Scalar) + 0.5) / (self.options.height as Scalar);
+
+            self.inspection = Some(scene.inspect_pixel(u, v, &mut sampler));
+            self.paths = if self.show_paths { scene.inspect_paths(u, v, self.path_count, &mut sampler) } else { Vec::new() };
+        }
+    }
+
+    /// Focuses the camera on the surface seen at the pixel
+    fn pick_focus(&mut self, px: u32, py: u32)
+    {
+        let doc = self.doc();
+        let scene = doc.desc.build_scene_with_monitor(&self.options, &mut (), &doc.mesh_cache).expect("Build can't be cancelled");
+
+        let u = ((px as Scalar) + 0.5) / (self.options.width as Scalar);
+        let v = ((py as Scalar) + 0.5) / (self.options.height as Scalar);
+
+        match scene.depth_at(u, v)
+        {
+            Some(depth) =>
             {
-                self.desc.camera.fov = (self.desc.camera.fov + 5.0).clamp(1.0, 175.0);
-                camera_changed = true;
-                true
+                let doc = self.doc_mut();
+                doc.desc.camera.focus_distance = depth;
+                doc.update_scene_camera();
+                self.restart_renderer();
             },
-            _ =>
+            None =>
             {
-                false
+                println!("Error: There's no surface to focus on at pixel {}, {}", px, py);
             },
-        };
+        }
+    }
+
+    fn render_inspector_ui(&mut self, ui: &UiRenderer)
+    {
+        let imgui = ui.imgui;
+
+        imgui.checkbox("Click To Inspect", &mut self.inspect_enabled);
+        imgui.checkbox("Click To Pick Focus", &mut self.focus_pick_enabled);
+
+        let camera = &mut self.doc_mut().desc.camera;
+        let mut camera_changed = ui.edit_float("Aperture", &mut camera.aperture);
+        camera_changed |= ui.edit_float("Focus Distance", &mut camera.focus_distance);
 
         if camera_changed
         {
-            self.scene.camera = self.desc.camera.clone();
+            let doc = self.doc_mut();
+            doc.update_scene_camera();
+            self.restart_renderer();
         }
 
-        if handled
+        imgui.checkbox("Show Paths", &mut self.show_paths);
+
+        if self.show_paths
         {
-            self.options.max_blockiness = 8;
+            imgui.input_scalar("Paths", &mut self.path_count).build();
+            imgui.checkbox("Color By Bounce", &mut self.path_color_by_bounce);
+
+            if !self.path_color_by_bounce
+            {
+                for technique in [SampleTechnique::Uniform, SampleTechnique::Bsdf, SampleTechnique::Light, SampleTechnique::Specular]
+                {
+                    imgui.text_colored(technique_color(technique), technique.name());
+                    imgui.same_line();
+                }
+                imgui.new_line();
+            }
         }
 
-        handled
-    }
+        if let Some((px, py)) = self.inspect_pixel
+        {
+            imgui.label_text("Pixel", format!("{}, {}", px, py));
 
-    fn move_around(&mut self, factor_left_right: Scalar, factor_forward_back: Scalar)
-    {
-        let look = self.desc.camera.look_at - self.desc.camera.location;
-        let right = look.cross(self.desc.camera.up).normalized();
-        let back = right.cross(self.desc.camera.up).normalized();
-        let up = self.desc.camera.up.normalized();
-        
-        // Don't move in the up/down direction
-        let right = right - (up.dot(right) * up);
-        let back = back - (up.dot(right) * up);
+            if imgui.button("Retrace")
+            {
+                self.inspect();
+            }
+        }
 
-        // Work out how far to move
-        let dist_factor = 0.05 * look.magnitude();
+        if let Some(inspection) = &self.inspection
+        {
+            match inspection.path.first()
+            {
+                Some(hit) =>
+                {
+                    imgui.label_text("Object", hit.object.as_deref().unwrap_or("<unnamed>"));
+                    imgui.label_text("Material", hit.interaction);
+                    ui.display_float("Depth", &hit.distance);
+                    ui.display_vec3("Location", &hit.location);
+                    ui.display_vec3("Normal", &hit.normal);
+                    imgui.label_text("Face", if hit.front_face { "Front" } else { "Back" });
+                },
+                None =>
+                {
+                    imgui.label_text("Object", "<miss>");
+                },
+            }
 
-        let dir = dist_factor * (factor_left_right * right + factor_forward_back * back);
+            let color = inspection.color;
+            imgui.label_text("Color", format!("{:.4}, {:.4}, {:.4}", color.r, color.g, color.b));
+            ui.display_float("Probability", &inspection.probability);
+
+            if let Some(_table) = imgui.begin_table_with_flags("Path", 6, imgui::TableFlags::BORDERS | imgui::TableFlags::ROW_BG)
+            {
+                imgui.table_setup_column("Bounce");
+                imgui.table_setup_column("Object");
+                imgui.table_setup_column("Interaction");
+                imgui.table_setup_column("Location");
+                imgui.table_setup_column("Attenuation");
+                imgui.table_setup_column("Probability");
+                imgui.table_headers_row();
 
-        self.desc.camera.location += dir;
-        self.desc.camera.look_at += dir;
+                for (i, vertex) in inspection.path.iter().enumerate()
+                {
+                    imgui.table_next_row();
+                    imgui.table_next_column();
+                    imgui.text(format!("{}", i));
+                    imgui.table_next_column();
+                    imgui.text(vertex.object.as_deref().unwrap_or("<unnamed>"));
+                    imgui.table_next_column();
+                    imgui.text(format!("{} ({})", vertex.interaction, vertex.technique.name()));
+                    imgui.table_next_column();
+                    imgui.text(format!("{:.3}, {:.3}, {:.3}", vertex.location.x, vertex.location.y, vertex.location.z));
+                    imgui.table_next_column();
+                    imgui.text(format!("{:.4}, {:.4}, {:.4}", vertex.attenuation.r, vertex.attenuation.g, vertex.attenuation.b));
+                    imgui.table_next_column();
+                    imgui.text(format!("{:.4}", vertex.probability));
+                }
+            }
+        }
     }
 
-    fn rotate_around(&mut self, degrees: Scalar)
+    fn render_path_overlay(&self, ui: &UiRenderer)
     {
-        let dir = self.desc.camera.location - self.desc.camera.look_at;
+        let imgui = ui.imgui;
+        let [width, height] = imgui.io().display_size;
+        let aspect_ratio = (self.options.width as Scalar) / (self.options.height as Scalar);
+        let camera = &self.doc().desc.camera;
+        let draw_list = imgui.get_background_draw_list();
 
-        let rot = Mat4::rotation_3d(degrees.to_radians(), self.desc.camera.up);
+        let to_screen = |point: Vec3| camera.project(point, aspect_ratio).map(|(u, v)| [(u as f32) * width, (v as f32) * height]);
 
-        let new_dir: Vec3 = (rot * Vec4::from_direction(dir)).into();
+        for inspection in self.paths.iter()
+        {
+            let mut prev = inspection.origin;
+            let mut prev_color = [1.0, 1.0, 1.0, 0.5];
+
+            for (bounce, vertex) in inspection.path.iter().enumerate()
+            {
+                if let (Some(a), Some(b)) = (to_screen(prev), to_screen(vertex.location))
+                {
+                    draw_list.add_line(a, b, prev_color).build();
+                    draw_list.add_circle(b, 2.5, prev_color).filled(true).build();
+                }
+
+                prev = vertex.location;
+                prev_color = if self.path_color_by_bounce { bounce_color(bounce) } else { technique_color(vertex.technique) };
+            }
+
+            // Show the direction of paths that escaped the scene
+            // or were terminated, scaled to the first segment length
 
-        self.desc.camera.location = new_dir + self.desc.camera.look_at;
+            if let (Some(last), Some(first)) = (inspection.path.last(), inspection.path.first())
+            {
+                if let Some(dir) = last.next_dir
+                {
+                    if let (Some(a), Some(b)) = (to_screen(last.location), to_screen(last.location + first.distance * dir))
+                    {
+                        draw_list.add_line(a, b, [prev_color[0], prev_color[1], prev_color[2], 0.25]).build();
+                    }
+                }
+            }
+        }
     }
 
-    fn tilt(&mut self, degrees: Scalar)
+    fn render_script_ui(&mut self, ui: &UiRenderer)
     {
-        let dir = self.desc.camera.location - self.desc.camera.look_at;
+        let imgui = ui.imgui;
+        let doc = &mut self.documents[self.active];
 
-        let right = dir.cross(self.desc.camera.up);
+        let handler = ScriptCompletionHandler
+        {
+            docs: &self.function_docs,
+            vars: &doc.script_vars,
+            prefix: &mut self.script_prefix,
+        };
 
-        let rot = Mat4::rotation_3d(degrees.to_radians(), right);
+        if imgui.input_text_multiline("##Script", &mut doc.script, [-1.0, -60.0])
+            .callback(imgui::InputTextMultilineCallback::ALWAYS | imgui::InputTextMultilineCallback::COMPLETION, handler)
+            .build()
+        {
+            doc.script_modified = true;
+        }
 
-        let new_dir: Vec3 = (rot * Vec4::from_direction(dir)).into();
+        if imgui.is_item_active()
+        {
+            let completions = beam::exec::complete(&self.script_prefix, &self.function_docs, &doc.script_vars);
 
-        self.desc.camera.location = new_dir + self.desc.camera.look_at;
-    }
-}
+            if !completions.is_empty()
+            {
+                imgui.tooltip(||
+                {
+                    for completion in completions.iter().take(10)
+                    {
+                        imgui.text(&completion.name);
+                        imgui.same_line_with_pos(160.0);
+                        imgui.text_disabled(&completion.detail);
+                    }
 
-impl beam::ui::UiApplication<()> for AppState
-{
-    fn handle_event(&mut self, event: winit::event::Event<()>) -> Option<winit::event_loop::ControlFlow>
-    {
-        match event
+                    imgui.text_disabled("Tab to complete");
+                });
+            }
+        }
+
+        if let Some(error) = &doc.script_error
         {
-            Event::WindowEvent{ event, .. } =>
+            imgui.text_colored([1.0, 0.3, 0.3, 1.0], error);
+        }
+
+        match &doc.script_runner
+        {
+            Some(runner) =>
             {
-                match event
+                imgui.text(format!("Running... {:.1}s", runner.elapsed().as_secs_f64()));
+                imgui.same_line();
+
+                if imgui.button("Cancel")
                 {
-                    WindowEvent::ModifiersChanged(modifiers) =>
-                    {
-                        self.keyboard_modifiers = modifiers;
-                    },
-                    WindowEvent::KeyboardInput { input, .. } =>
-                    {
-                        if input.state == ElementState::Pressed
-                        {
-                            if let Some(virtual_keycode) = input.virtual_keycode
-                            {
-                                if self.handle_keycode(virtual_keycode, self.keyboard_modifiers)
-                                {
-                                    self.renderer = self.new_renderer();
-                                }
-                            }
-                        }
-                    },
-                    _ => {},
+                    runner.cancel();
+                }
+            },
+            None =>
+            {
+                if imgui.button("Run")
+                {
+                    doc.run_script(&self.script_limits);
                 }
             },
-            _ => {},
         }
 
-        None
-    }
-
-    fn render_background(&mut self, display: &glium::Display, frame: &mut glium::Frame)
-    {
-        let frame_dimensions = frame.get_dimensions();
-        let desired_dimensions = (frame_dimensions.0 / self.downscale, frame_dimensions.1 / self.downscale);
-        if desired_dimensions != self.pixels.dimensions()
+        if imgui.collapsing_header("Limits", imgui::TreeNodeFlags::empty())
         {
-            let (width, height) = desired_dimensions;
-            self.pixels.resize(width, height);
-            self.options.width = width;
-            self.options.height = height;
-            self.renderer = self.new_renderer();
+            let mut max_time = self.script_limits.max_time.as_secs_f64();
+
+            imgui.input_scalar("Max Steps", &mut self.script_limits.max_steps).build();
+            imgui.input_scalar("Max Depth", &mut self.script_limits.max_depth).build();
+            imgui.input_scalar("Max List Size", &mut self.script_limits.max_collection_size).build();
+
+            if imgui.input_scalar("Max Time (s)", &mut max_time).build()
+            {
+                self.script_limits.max_time = Duration::from_secs_f64(max_time.max(0.0));
+            }
         }
-        self.pixels.render(display, frame);
     }
 
-    fn render_ui(&mut self, ui: &UiRenderer)
+    fn render_reference_ui(&mut self, ui: &UiRenderer)
     {
-        if let Some(progress) = &self.progress
+        let imgui = ui.imgui;
+
+        imgui.input_text("Search", &mut self.function_filter).build();
+
+        for doc in self.function_docs.iter().filter(|d| d.matches(&self.function_filter))
         {
-            if let Some(_progress_window) = ui.imgui.window("Progress").begin()
+            if let Some(_node) = imgui.tree_node(doc.signature())
             {
-                if render_progress(ui.imgui, &mut self.downscale, &mut self.options, progress)
+                if doc.names.len() > 1
+                {
+                    imgui.text_disabled(format!("Also: {}", doc.names[1..].join(", ")));
+                }
+
+                imgui.text_wrapped(&doc.description);
+
+                for arg in doc.args.iter()
                 {
-                    self.renderer = self.new_renderer();
+                    imgui.bullet_text(format!("{}: {}", arg.name, arg.description));
                 }
             }
         }
-        
-        if let Some(_editor_window) = ui.imgui.window("Editor Demo").begin()
+    }
+
+    fn render_clipboard_ui(&mut self, ui: &UiRenderer)
+    {
+        self.copy_object.ui_edit(ui, "Object");
+        ui.imgui.same_line();
+        if ui.imgui.button("Copy Object")
         {
-            self.scene.ui_display(ui, "Display");
-            self.scene.ui_edit(ui, "Edit");
+            self.clipboard = Some(Clipboard::new(&self.doc().scene, ClipboardItem::Object(self.copy_object)));
+        }
 
-            if ui.imgui.button("Build")
+        self.copy_material.ui_edit(ui, "Material");
+        ui.imgui.same_line();
+        if ui.imgui.button("Copy Material")
+        {
+            self.clipboard = Some(Clipboard::new(&self.doc().scene, ClipboardItem::Material(self.copy_material)));
+        }
+
+        self.copy_group.ui_edit(ui, "Group");
+        ui.imgui.same_line();
+        if ui.imgui.button("Copy Group")
+        {
+            self.clipboard = Some(Clipboard::new(&self.doc().scene, ClipboardItem::Group(self.copy_group)));
+        }
+
+        if let Some(clipboard) = &self.clipboard
+        {
+            ui.imgui.label_text("Clipboard", clipboard.describe());
+
+            if ui.imgui.button("Paste")
             {
-                self.desc = SceneDescription::new_edit(&self.scene);
-                self.renderer = self.new_renderer();                
+                clipboard.paste_into(&mut self.documents[self.active].scene);
+                self.documents[self.active].scene_modified = true;
             }
         }
+        else
+        {
+            ui.imgui.label_text("Clipboard", "Empty");
+        }
+    }
+}
 
-        ui.imgui.show_metrics_window(&mut true);
+fn render_texture_settings(ui: &imgui::Ui)
+{
+    let mut settings = beam::import::image::texture_settings();
+    let mut budget_mb = (settings.budget_bytes / (1024 * 1024)) as u32;
+
+    let mut changed = false;
+    changed |= ui.input_scalar("Budget (MB)", &mut budget_mb).build();
+    changed |= ui.checkbox("Lazy Decode", &mut settings.lazy_decode);
+    changed |= ui.checkbox("8-bit sRGB Storage", &mut settings.srgb_8bit);
+
+    if changed
+    {
+        settings.budget_bytes = (budget_mb as u64) * 1024 * 1024;
+        beam::import::image::set_texture_settings(settings);
     }
 
-    fn idle(&mut self)
+    let stats = beam::import::image::texture_memory_stats();
+
+    ui.text(format!("Resident: {:.1} MB", (stats.resident_bytes as f64) / (1024.0 * 1024.0)));
+    ui.text(format!("Images: {} ({} loaded, {} downsampled)", stats.num_images, stats.num_resident, stats.num_downsampled));
+}
+
+/// Returns true if the render options have changed
+fn render_presets(ui: &imgui::Ui, settings: &mut AppSettings, preset_name: &mut String, downscale: &mut u32, options: &mut RenderOptions) -> bool
+{
+    let mut changed = false;
+
+    if let Some(_) = ui.begin_combo("Preset", &*preset_name)
     {
-        if let Some(update) = self.renderer.get_update()
+        for preset in settings.presets.iter()
         {
-            for pixel in update.pixels
+            if ui.selectable_config(&preset.name).selected(preset.name == *preset_name).build()
             {
-                self.pixels.set_pixel(
-                    pixel.rect.x,
-                    pixel.rect.y,
-                    image::Rgba([
-                        (pixel.color.r * 255.0) as u8,
-                        (pixel.color.g * 255.0) as u8,
-                        (pixel.color.b * 255.0) as u8,
-                        255,
-                    ]));
+                preset.apply(downscale, options);
+                *preset_name = preset.name.clone();
+                changed = true;
             }
+        }
+    }
+
+    changed |= ui.input_scalar("Max Samples", &mut options.max_samples).build();
+    changed |= ui.input_scalar("Max Bounces", &mut options.max_bounces).build();
+    changed |= ui.checkbox("Denoise", &mut options.denoise);
 
-            self.progress = Some(update.progress);
+    options.max_samples = options.max_samples.max(1);
+
+    ui.input_text("Preset Name", preset_name).build();
+    ui.same_line();
+    if ui.button("Save Preset") && !preset_name.is_empty()
+    {
+        settings.set_preset(RenderPreset::capture(preset_name.clone(), *downscale, options));
+
+        if let Err(err) = settings.save(AppSettings::DEFAULT_FILENAME)
+        {
+            println!("Error: {}", err);
         }
     }
+
+    changed
 }
 
 fn render_progress(ui: &imgui::Ui, downscale: &mut u32, options: &mut RenderOptions, progress: &beam::render::RenderProgress) -> bool
@@ -418,6 +3324,19 @@ fn render_progress(ui: &imgui::Ui, downscale: &mut u32, options: &mut RenderOpti
     let mut changed = false;
 
     changed |= ui.input_scalar("Downscale", downscale).build();
+    changed |= ui.input_scalar("Interaction Delay (ms)", &mut options.interaction_delay_ms).build();
+
+    if let Some(_) = ui.begin_combo("Tile Order", format!("{:?}", options.tile_order))
+    {
+        for order in beam::render::TileOrder::ALL
+        {
+            if ui.selectable_config(format!("{:?}", order)).selected(order == options.tile_order).build()
+            {
+                changed = true;
+                options.tile_order = order;
+            }
+        }
+    }
 
     if let Some(_) = ui.begin_combo("Illumination", format!("{:?}", options.illumination_mode))
     {
@@ -457,10 +3376,58 @@ fn render_progress(ui: &imgui::Ui, downscale: &mut u32, options: &mut RenderOpti
                 changed = true;
                 options.sampling_mode = beam::scene::SamplingMode::BsdfAndLights;
             }
+            if ui.selectable(format!("{:?}", beam::scene::SamplingMode::Reservoir))
+            {
+                changed = true;
+                options.sampling_mode = beam::scene::SamplingMode::Reservoir;
+            }
+        }
+
+        changed |= ui.input_scalar("Max Diffuse Bounces", &mut options.max_diffuse_bounces).build();
+        changed |= ui.input_scalar("Max Glossy Bounces", &mut options.max_glossy_bounces).build();
+        changed |= ui.input_scalar("Max Transmission Bounces", &mut options.max_transmission_bounces).build();
+        changed |= ui.input_scalar("Max Shadow Transparent Hits", &mut options.max_shadow_transparent_hits).build();
+        changed |= ui.input_scalar("Direct Clamp", &mut options.direct_clamp).build();
+        changed |= ui.input_scalar("Indirect Clamp", &mut options.indirect_clamp).build();
+        changed |= ui.input_scalar("Target Noise", &mut options.target_noise).build();
+        changed |= ui.input_scalar("Time Limit (s)", &mut options.time_limit).build();
+        changed |= ui.checkbox("Show Focal Plane", &mut options.show_focal_plane);
+        changed |= ui.checkbox("Radiance Cache", &mut options.radiance_cache);
+
+        if options.radiance_cache
+        {
+            changed |= ui.checkbox("Show Radiance Cache", &mut options.show_radiance_cache);
+        }
+        changed |= ui.checkbox("Path Guiding", &mut options.path_guiding);
+        changed |= ui.checkbox("Bake Transforms", &mut options.bake_transforms);
+
+        if let Some(_) = ui.begin_combo("Octree Quality", options.octree_quality.name())
+        {
+            for quality in OctreeQuality::ALL
+            {
+                if ui.selectable_config(quality.name()).selected(quality == options.octree_quality).build()
+                {
+                    changed = true;
+                    options.octree_quality = quality;
+                }
+            }
         }
     }
+    else
+    {
+        changed |= ui.checkbox("Ambient Occlusion", &mut options.ambient_occlusion);
+        changed |= ui.input_scalar("Local Samples", &mut options.local_samples).build();
+
+        options.local_samples = options.local_samples.max(1);
+    }
 
     ui.text(&progress.actions);
+
+    if let Some(build) = &progress.build
+    {
+        imgui::ProgressBar::new(build.fraction as f32).overlay_text(&build.stage).build(ui);
+    }
+
     ui.text("Total Duration:");
     ui.text(duration_to_str(&progress.total_duration));
     ui.text("Avg Sample Duration:");
@@ -471,6 +3438,22 @@ fn render_progress(ui: &imgui::Ui, downscale: &mut u32, options: &mut RenderOpti
     ui.text(progress.stats.num_rays.to_string());
     ui.text("Max Rays:");
     ui.text(progress.stats.max_rays.to_string());
+    ui.text("Rays/Second:");
+    ui.text(format!("{:.0}", progress.rays_per_second));
+    ui.text("Clamped Energy:");
+    ui.text(format!("{:.3}", progress.stats.clamped_energy));
+
+    if let Some(convergence) = &progress.convergence
+    {
+        ui.text("Estimated Noise:");
+        ui.text(format!("{:.2}%", 100.0 * convergence.noise));
+
+        if let Some(time_to_target) = &convergence.time_to_target
+        {
+            ui.text("Estimated Time to Target:");
+            ui.text(duration_to_str(time_to_target));
+        }
+    }
 
     if let Some(_) = ui.begin_table("stats", 3)
     {
@@ -505,11 +3488,133 @@ fn render_progress(ui: &imgui::Ui, downscale: &mut u32, options: &mut RenderOpti
         ui.text(progress.stats.stopped_due_to_min_prob.to_string());
         ui.table_next_column();
         ui.text(percent_to_str(progress.stats.stopped_due_to_min_prob, progress.stats.num_samples));
+
+        ui.table_next_row();
+        ui.table_next_column();
+        ui.text("Radiance Cache");
+        ui.table_next_column();
+        ui.text(progress.stats.radiance_cache_hits.to_string());
+        ui.table_next_column();
+        ui.text(percent_to_str(progress.stats.radiance_cache_hits, progress.stats.num_samples));
     }
 
+    render_timing(ui, progress);
+
     changed
 }
 
+fn render_timing(ui: &imgui::Ui, progress: &beam::render::RenderProgress)
+{
+    let stats = &progress.stats;
+
+    if stats.timed_samples > 0
+    {
+        let stages = [
+            ("Intersection", stats.intersection_time),
+            ("Shading", stats.shading_time),
+            ("Sampling", stats.sampling_time),
+        ];
+
+        let total = stages.iter().map(|(_, d)| d.as_secs_f64()).sum::<f64>();
+
+        if let Some(_) = ui.begin_table("stages", 3)
+        {
+            ui.table_next_row_with_flags(imgui::TableRowFlags::HEADERS);
+            ui.table_next_column();
+            ui.text("Stage");
+            ui.table_next_column();
+            ui.text("Per Sample");
+            ui.table_next_column();
+            ui.text("Percent");
+
+            for (name, duration) in stages.iter()
+            {
+                ui.table_next_row();
+                ui.table_next_column();
+                ui.text(name);
+                ui.table_next_column();
+                ui.text(duration_to_str(&duration.div_f64(stats.timed_samples as f64)));
+                ui.table_next_column();
+                ui.text(format!("{:.1}%", if total > 0.0 { 100.0 * duration.as_secs_f64() / total } else { 0.0 }));
+            }
+        }
+    }
+
+    if !progress.passes.is_empty()
+    {
+        if let Some(_) = ui.begin_table("passes", 2)
+        {
+            ui.table_next_row_with_flags(imgui::TableRowFlags::HEADERS);
+            ui.table_next_column();
+            ui.text("Pass");
+            ui.table_next_column();
+            ui.text("Duration");
+
+            for pass in progress.passes.iter()
+            {
+                ui.table_next_row();
+                ui.table_next_column();
+                ui.text(&pass.name);
+                ui.table_next_column();
+                ui.text(duration_to_str(&pass.duration));
+            }
+        }
+    }
+}
+
+struct ScriptCompletionHandler<'a>
+{
+    docs: &'a [std::rc::Rc<FunctionDoc>],
+    vars: &'a [Completion],
+    prefix: &'a mut String,
+}
+
+impl<'a> imgui::InputTextCallbackHandler for ScriptCompletionHandler<'a>
+{
+    fn on_always(&mut self, data: imgui::TextCallbackData)
+    {
+        *self.prefix = beam::exec::identifier_prefix(data.str(), data.cursor_pos()).to_owned();
+    }
+
+    fn on_completion(&mut self, mut data: imgui::TextCallbackData)
+    {
+        let prefix = beam::exec::identifier_prefix(data.str(), data.cursor_pos()).to_owned();
+
+        if let Some(completion) = beam::exec::complete(&prefix, self.docs, self.vars).first()
+        {
+            let pos = data.cursor_pos();
+            data.insert_chars(pos, &completion.name[prefix.len()..]);
+        }
+    }
+}
+
+fn technique_color(technique: SampleTechnique) -> [f32; 4]
+{
+    match technique
+    {
+        SampleTechnique::Uniform => [0.8, 0.8, 0.8, 1.0],
+        SampleTechnique::Bsdf => [0.2, 0.6, 1.0, 1.0],
+        SampleTechnique::Light => [1.0, 0.8, 0.1, 1.0],
+        SampleTechnique::Specular => [0.4, 1.0, 0.4, 1.0],
+        SampleTechnique::Emit => [1.0, 1.0, 1.0, 1.0],
+        SampleTechnique::Cache => [1.0, 0.4, 0.8, 1.0],
+    }
+}
+
+fn bounce_color(bounce: usize) -> [f32; 4]
+{
+    const COLORS: [[f32; 4]; 6] = [
+        [1.0, 0.3, 0.3, 1.0],
+        [1.0, 0.7, 0.2, 1.0],
+        [1.0, 1.0, 0.3, 1.0],
+        [0.3, 1.0, 0.3, 1.0],
+        [0.3, 0.7, 1.0, 1.0],
+        [0.8, 0.4, 1.0, 1.0],
+    ];
+
+    COLORS[bounce % COLORS.len()]
+}
+
 fn percent_to_str(num: u64, den: u64) -> String
 {
     let percent = 100.0 * (num as f64) / (den as f64);
@@ -0,0 +1,120 @@
+use crate::bsdf::{Bsdf, random_sample_dir_from_onb_xyz};
+use crate::intersection::ShadingIntersection;
+use crate::math::{Scalar, ScalarConsts};
+use crate::sample::Sampler;
+use crate::vec::{Dir3, bsdf_reflect};
+
+/// Below this, the GGX distribution is too sharp
+/// to sample without numerical problems
+const MIN_ALPHA: Scalar = 1.0e-3;
+
+/// The specular lobe of the Cook-Torrance microfacet BSDF, with
+/// the GGX (Trowbridge-Reitz) distribution and Smith shadowing.
+///
+/// The Fresnel term is colored, so it isn't included here - it's
+/// applied by the material as the attenuation color.
+///
+/// Equations are taken from "Microfacet Models for Refraction
+/// through Rough Surfaces" by Walter et al.
+pub struct Ggx
+{
+    normal: Dir3,
+    view: Dir3,
+    alpha: Scalar,
+}
+
+impl Ggx
+{
+    /// The roughness is perceptual, as in glTF, so
+    /// the distribution's alpha is its square
+    pub fn new(intersection: &ShadingIntersection, roughness: Scalar) -> Self
+    {
+        let alpha = (roughness * roughness).clamp(MIN_ALPHA, 1.0);
+
+        Ggx { normal: intersection.normal, view: intersection.incoming, alpha }
+    }
+
+    fn distribution(&self, cos_theta_h: Scalar) -> Scalar
+    {
+        let alpha2 = self.alpha * self.alpha;
+        let denom = (cos_theta_h * cos_theta_h * (alpha2 - 1.0)) + 1.0;
+
+        alpha2 / (ScalarConsts::PI * denom * denom)
+    }
+
+    fn smith_g1(&self, cos_theta: Scalar) -> Scalar
+    {
+        let alpha2 = self.alpha * self.alpha;
+
+        2.0 * cos_theta / (cos_theta + (alpha2 + ((1.0 - alpha2) * cos_theta * cos_theta)).sqrt())
+    }
+}
+
+impl Bsdf for Ggx
+{
+    fn generate_random_sample_dir_and_calc_pdf(&self, sampler: &mut Sampler) -> (Dir3, Scalar)
+    {
+        // Sample a microfacet normal from D(h) * cos(theta_h)
+
+        let r1 = sampler.uniform_scalar_unit();
+        let r2 = sampler.uniform_scalar_unit();
+
+        let cos_theta = ((1.0 - r1) / (1.0 + (((self.alpha * self.alpha) - 1.0) * r1))).sqrt();
+        let sin_theta = (1.0 - (cos_theta * cos_theta)).max(0.0).sqrt();
+
+        let phi = 2.0 * ScalarConsts::PI * r2;
+
+        let half = random_sample_dir_from_onb_xyz(self.normal, phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+
+        // Reflect the view direction about it
+
+        let dir = bsdf_reflect(self.view, half);
+
+        (dir, self.calculate_pdf_for_dir(dir))
+    }
+
+    fn calculate_pdf_for_dir(&self, dir: Dir3) -> Scalar
+    {
+        let dir = dir.normalized();
+        let half = self.view + dir;
+
+        if half.magnitude_squared() == 0.0
+        {
+            return 0.0;
+        }
+
+        let half = half.normalized();
+        let cos_theta_h = self.normal.dot(half);
+        let v_dot_h = self.view.dot(half);
+
+        if (cos_theta_h <= 0.0) || (v_dot_h <= 0.0)
+        {
+            return 0.0;
+        }
+
+        // Convert from the half vector's PDF
+        // to the reflected direction's PDF
+
+        self.distribution(cos_theta_h) * cos_theta_h / (4.0 * v_dot_h)
+    }
+
+    fn reflectance(&self, output_dir: Dir3) -> Scalar
+    {
+        let output_dir = output_dir.normalized();
+
+        let cos_theta_i = self.normal.dot(output_dir);
+        let cos_theta_o = self.normal.dot(self.view);
+
+        if (cos_theta_i <= 0.0) || (cos_theta_o <= 0.0)
+        {
+            return 0.0;
+        }
+
+        let half = (self.view + output_dir).normalized();
+        let cos_theta_h = self.normal.dot(half).max(0.0);
+
+        // D * G / (4 * cos_i * cos_o), multiplied by cos_i
+
+        self.distribution(cos_theta_h) * self.smith_g1(cos_theta_i) * self.smith_g1(cos_theta_o) / (4.0 * cos_theta_o)
+    }
+}
@@ -0,0 +1,26 @@
+use crate::bsdf::Bsdf;
+use crate::math::{Scalar, ScalarConsts};
+use crate::sample::Sampler;
+use crate::vec::Dir3;
+
+/// The phase function of a participating medium
+/// that scatters equally in all directions
+pub struct Isotropic;
+
+impl Bsdf for Isotropic
+{
+    fn generate_random_sample_dir_and_calc_pdf(&self, sampler: &mut Sampler) -> (Dir3, Scalar)
+    {
+        (sampler.uniform_dir_on_unit_sphere(), 0.25 * ScalarConsts::FRAC_1_PI)
+    }
+
+    fn calculate_pdf_for_dir(&self, _dir: Dir3) -> Scalar
+    {
+        0.25 * ScalarConsts::FRAC_1_PI
+    }
+
+    fn reflectance(&self, _dir: Dir3) -> Scalar
+    {
+        0.25 * ScalarConsts::FRAC_1_PI
+    }
+}
@@ -2,9 +2,13 @@ use crate::math::Scalar;
 use crate::sample::Sampler;
 use crate::vec::Dir3;
 
+pub mod ggx;
+pub mod isotropic;
 pub mod lambertian;
 pub mod phong;
 
+pub use ggx::*;
+pub use isotropic::*;
 pub use lambertian::*;
 pub use phong::*;
 
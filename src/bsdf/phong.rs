@@ -3,7 +3,6 @@ use crate::color::LinearRGB;
 use crate::intersection::ShadingIntersection;
 use crate::material::MaterialInteraction;
 use crate::math::{Scalar, ScalarConsts};
-use crate::ray::Ray;
 use crate::sample::Sampler;
 use crate::scene::{Scene, SceneSampleStats};
 use crate::vec::{Dir3, bsdf_reflect};
@@ -59,15 +58,16 @@ impl Phong
     
                     stats.num_rays += 1;
     
-                    if let Some(shadow_int) = scene.trace_intersection(&Ray::new(intersection.location, light_dir))
+                    if let (Some(shadow_int), transmittance) = scene.trace_shadow(&intersection.spawn_ray(light_dir))
                     {
-                        if let MaterialInteraction::Emit{ emitted_color } = shadow_int.material.get_surface_interaction(&shadow_int.surface.into())
+                        if let MaterialInteraction::Emit{ emitted_color } = shadow_int.material.get_surface_interaction(&mut shadow_int.surface.into())
                         {
                             // Our shadow ray has hit an emitting surface:
                             // 1) Clamp the emitted color - global illumination can need lights "brighter" than 1.0
-                            // 2) Add diffuse and specular components as required
+                            // 2) Dim and tint it by any media and glass passed through on the way
+                            // 3) Add diffuse and specular components as required
     
-                            let emitted_color = emitted_color.clamped(0.0, 1.0);
+                            let emitted_color = emitted_color.clamped(0.0, 1.0).combined_with(&transmittance);
     
                             if kd > 0.0
                             {
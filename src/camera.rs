@@ -1,14 +1,30 @@
+use crate::color::LinearRGB;
+use crate::import::image::{ColorSpace, Image};
 use crate::math::Scalar;
 use crate::ray::Ray;
+use crate::sample::Sampler;
 use crate::vec::{Point3, Dir3};
 
 #[derive(Clone)]
 pub struct Camera
 {
     location: Point3,
+    forward: Dir3,
     lower_left_corner: Point3,
     horizontal: Dir3,
     vertical: Dir3,
+    lens: Option<Lens>,
+}
+
+/// A thin lens, with the image plane
+/// at the focus distance
+#[derive(Clone)]
+struct Lens
+{
+    radius: Scalar,
+    focus_distance: Scalar,
+    u: Dir3,
+    v: Dir3,
 }
 
 impl Camera
@@ -28,13 +44,127 @@ impl Camera
         let vertical = viewport_height * -v;
         let lower_left_corner = location - (horizontal / 2.0) - (vertical / 2.0) - w;
 
-        Camera { location, lower_left_corner, horizontal, vertical }
+        Camera { location, forward: -w, lower_left_corner, horizontal, vertical, lens: None }
+    }
+
+    /// Blurs everything not at the focus distance (measured
+    /// along the view direction). An aperture of zero keeps
+    /// everything in focus.
+    pub fn with_depth_of_field(mut self, aperture: Scalar, focus_distance: Scalar) -> Self
+    {
+        if (aperture <= 0.0) || (focus_distance <= 0.0)
+        {
+            return self;
+        }
+
+        self.lower_left_corner = self.location + focus_distance * (self.lower_left_corner - self.location);
+        self.horizontal = focus_distance * self.horizontal;
+        self.vertical = focus_distance * self.vertical;
+
+        self.lens = Some(Lens
+        {
+            radius: aperture / 2.0,
+            focus_distance,
+            u: self.horizontal.normalized(),
+            v: self.vertical.normalized(),
+        });
+
+        self
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray
+    pub fn get_ray(&self, u: f64, v: f64, sampler: &mut Sampler) -> Ray
+    {
+        let source = match &self.lens
+        {
+            Some(lens) =>
+            {
+                let (x, y) = sampler.uniform_point_in_unit_disc();
+                self.location + (lens.radius * x) * lens.u + (lens.radius * y) * lens.v
+            },
+            None => self.location,
+        };
+
+        Ray::new(
+            source,
+            (self.lower_left_corner + (self.horizontal * u) + (self.vertical * v)) - source)
+    }
+
+    /// The ray through the center of the lens,
+    /// which is the same for every sample
+    pub fn get_center_ray(&self, u: f64, v: f64) -> Ray
     {
         Ray::new(
             self.location,
             (self.lower_left_corner + (self.horizontal * u) + (self.vertical * v)) - self.location)
     }
-}
\ No newline at end of file
+
+    /// The image coordinates, as for `get_center_ray`, where
+    /// the point is seen - if it's in front of the camera
+    pub fn project(&self, point: Point3) -> Option<(Scalar, Scalar)>
+    {
+        let dir = point - self.location;
+        let along = dir.dot(self.forward);
+
+        if along <= 0.0
+        {
+            return None;
+        }
+
+        let corner = self.lower_left_corner - self.location;
+        let on_image = (dir * (corner.dot(self.forward) / along)) - corner;
+
+        Some((on_image.dot(self.horizontal) / self.horizontal.magnitude_squared(), on_image.dot(self.vertical) / self.vertical.magnitude_squared()))
+    }
+
+    pub fn location(&self) -> Point3
+    {
+        self.location
+    }
+
+    /// Width of the view at unit distance from the camera
+    pub fn view_width(&self) -> Scalar
+    {
+        self.horizontal.magnitude() / self.focus_distance().unwrap_or(1.0)
+    }
+
+    /// The focus distance, if there's depth of field
+    pub fn focus_distance(&self) -> Option<Scalar>
+    {
+        self.lens.as_ref().map(|l| l.focus_distance)
+    }
+
+    /// Distance of the point from the camera, along the
+    /// view direction, as used for the focus distance
+    pub fn depth_of(&self, point: Point3) -> Scalar
+    {
+        (point - self.location).dot(self.forward)
+    }
+}
+/// An image behind the scene, stretched to fill the camera's
+/// view. It's only seen by rays from the camera that miss
+/// the scene - it doesn't light or reflect in the scene.
+#[derive(Clone)]
+pub struct Backplate
+{
+    image: Image,
+    color_space: ColorSpace,
+}
+
+impl Backplate
+{
+    pub fn new(image: Image) -> Self
+    {
+        // Capture the color space now, as the image is
+        // shared with the editor, where it can be changed
+
+        let color_space = image.color_space();
+        Backplate { image, color_space }
+    }
+
+    /// The color at the given image coordinates,
+    /// as used for `Camera::get_ray`
+    pub fn color_at(&self, u: Scalar, v: Scalar) -> LinearRGB
+    {
+        self.color_space.to_linear(self.image.sample_at_uv(u, v))
+    }
+}
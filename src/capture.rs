@@ -0,0 +1,175 @@
+use crate::color::LinearRGB;
+use crate::math::{Scalar, ScalarConsts};
+use crate::ray::Ray;
+use crate::sample::Sampler;
+use crate::scene::{Scene, SceneSampleStats};
+use crate::vec::{Dir3, Point3};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread::JoinHandle;
+
+use image::{ImageBuffer, Rgb};
+
+pub type EnvironmentMap = ImageBuffer<Rgb<f32>, Vec<f32>>;
+
+#[derive(Clone)]
+pub struct CaptureOptions
+{
+    pub location: Point3,
+    /// The height is half of the width
+    pub width: u32,
+    pub samples_per_pixel: u32,
+}
+
+impl Default for CaptureOptions
+{
+    fn default() -> Self
+    {
+        CaptureOptions
+        {
+            location: Point3::new(0.0, 0.0, 0.0),
+            width: 512,
+            samples_per_pixel: 64,
+        }
+    }
+}
+
+/// The direction of a point in an equirectangular
+/// (latitude/longitude) image. +Y is up, and the
+/// center of the image looks along -Z.
+pub fn equirectangular_dir(u: Scalar, v: Scalar) -> Dir3
+{
+    let phi = (u - 0.5) * 2.0 * ScalarConsts::PI;
+    let theta = v * ScalarConsts::PI;
+
+    Dir3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+}
+
+/// Renders the full sphere of incoming radiance at a point
+/// into an HDR environment map, e.g. to bake a skybox from
+/// a scene. Capturing runs on background threads - poll
+/// `progress` and `take_result`.
+pub struct EnvironmentCapture
+{
+    threads: Vec<JoinHandle<Vec<(u32, u32, LinearRGB)>>>,
+    width: u32,
+    height: u32,
+    completed: Arc<AtomicU32>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl EnvironmentCapture
+{
+    pub fn new(scene: Scene, options: &CaptureOptions) -> Self
+    {
+        let width = options.width.max(2);
+        let height = width / 2;
+        let completed = Arc::new(AtomicU32::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        // Interleave the rows between threads, as
+        // the cost varies a lot with the direction
+
+        let num_threads = (num_cpus::get() as u32).clamp(1, height);
+
+        let threads = (0..num_threads)
+            .map(|first_row|
+            {
+                let rows = (first_row..height).step_by(num_threads as usize).collect::<Vec<_>>();
+                let scene = scene.clone();
+                let options = options.clone();
+                let completed = completed.clone();
+                let cancelled = cancelled.clone();
+
+                std::thread::spawn(move || capture_thread(scene, options, width, height, rows, completed, cancelled))
+            })
+            .collect();
+
+        EnvironmentCapture { threads, width, height, completed, cancelled }
+    }
+
+    pub fn progress(&self) -> Scalar
+    {
+        (self.completed.load(Ordering::Relaxed) as Scalar) / (self.height as Scalar)
+    }
+
+    pub fn is_finished(&self) -> bool
+    {
+        self.threads.iter().all(|t| t.is_finished())
+    }
+
+    /// Returns the environment map, once capturing has completed
+    pub fn take_result(&mut self) -> Option<EnvironmentMap>
+    {
+        if self.threads.is_empty() || !self.is_finished()
+        {
+            return None;
+        }
+
+        let mut map = EnvironmentMap::new(self.width, self.height);
+
+        for thread in self.threads.drain(..)
+        {
+            for (x, y, color) in thread.join().unwrap()
+            {
+                map.put_pixel(x, y, Rgb([color.r as f32, color.g as f32, color.b as f32]));
+            }
+        }
+
+        Some(map)
+    }
+}
+
+impl Drop for EnvironmentCapture
+{
+    fn drop(&mut self)
+    {
+        self.cancelled.store(true, Ordering::Relaxed);
+
+        for thread in self.threads.drain(..)
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn capture_thread(scene: Scene, options: CaptureOptions, width: u32, height: u32, rows: Vec<u32>, completed: Arc<AtomicU32>, cancelled: Arc<AtomicBool>) -> Vec<(u32, u32, LinearRGB)>
+{
+    let mut sampler = Sampler::new();
+    let mut stats = SceneSampleStats::new();
+    let mut result = Vec::with_capacity(rows.len() * (width as usize));
+    let samples = options.samples_per_pixel.max(1);
+
+    for y in rows
+    {
+        if cancelled.load(Ordering::Relaxed)
+        {
+            break;
+        }
+
+        for x in 0..width
+        {
+            let mut sum = LinearRGB::black();
+
+            for _ in 0..samples
+            {
+                let u = ((x as Scalar) + sampler.uniform_scalar_unit()) / (width as Scalar);
+                let v = ((y as Scalar) + sampler.uniform_scalar_unit()) / (height as Scalar);
+
+                let (color, probability) = scene.path_trace_global_lighting_ray(Ray::new(options.location, equirectangular_dir(u, v)), &mut sampler, &mut stats);
+
+                if probability > 0.0
+                {
+                    sum = sum + color.divided_by_scalar(probability);
+                }
+            }
+
+            result.push((x, y, sum.divided_by_scalar(samples as Scalar)));
+        }
+
+        completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    result
+}
@@ -0,0 +1,81 @@
+use crate::color::LinearRGB;
+use crate::math::Scalar;
+
+/// The color of an ideal blackbody radiator at a temperature
+/// in Kelvin, scaled so the brightest component is one.
+/// Around 1000K is a dull red glow, candles and fire are
+/// around 1500K-2000K, and 6500K is close to white.
+pub fn blackbody(temperature: Scalar) -> LinearRGB
+{
+    if temperature <= 0.0
+    {
+        return LinearRGB::black();
+    }
+
+    // Integrate Planck's law against the CIE 1931 color
+    // matching functions, then convert to linear sRGB
+
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+
+    for step in 0..=80
+    {
+        let wavelength = 380.0 + 5.0 * (step as Scalar);
+        let radiance = planck(wavelength, temperature);
+
+        x += radiance * cie_x(wavelength);
+        y += radiance * cie_y(wavelength);
+        z += radiance * cie_z(wavelength);
+    }
+
+    let r = (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0);
+    let g = (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0);
+    let b = (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0);
+
+    let max = r.max(g).max(b);
+
+    if max <= 0.0
+    {
+        return LinearRGB::black();
+    }
+
+    LinearRGB::new(r / max, g / max, b / max, 1.0)
+}
+
+/// Spectral radiance, without the constant factors
+/// that are removed when the color is normalized
+fn planck(wavelength_nm: Scalar, temperature: Scalar) -> Scalar
+{
+    // Second radiation constant, hc/k, in nm K
+
+    const C2: Scalar = 1.438_776_877e7;
+
+    let wavelength_um = wavelength_nm * 1.0e-3;
+
+    1.0 / (wavelength_um.powi(5) * ((C2 / (wavelength_nm * temperature)).exp() - 1.0))
+}
+
+// Multi-lobe fits to the CIE 1931 color matching functions, from
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+// by Wyman, Sloan and Shirley, 2013
+
+fn lobe(wavelength: Scalar, mean: Scalar, below: Scalar, above: Scalar) -> Scalar
+{
+    let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+
+    (-0.5 * t * t).exp()
+}
+
+fn cie_x(wavelength: Scalar) -> Scalar
+{
+    1.056 * lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7) - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2)
+}
+
+fn cie_y(wavelength: Scalar) -> Scalar
+{
+    0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1)
+}
+
+fn cie_z(wavelength: Scalar) -> Scalar
+{
+    1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8)
+}
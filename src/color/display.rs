@@ -0,0 +1,295 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::{LinearRGB, SRGB};
+use crate::math::Scalar;
+
+/// Middle grey, and the range of stops around it, that the
+/// log2 LUT input covers - the same as AgX's log encoding
+const LOG2_MIDDLE_GREY: Scalar = 0.18;
+const LOG2_MIN_STOPS: Scalar = -12.47393;
+const LOG2_MAX_STOPS: Scalar = 4.026069;
+
+/// How the linear render is converted into display values,
+/// both in the window and when saving 8-bit images
+#[derive(Clone, Debug, Default)]
+pub enum DisplayTransform
+{
+    /// Linear values shown as-is, clipped at one
+    #[default]
+    Linear,
+    /// The standard sRGB transfer curve, clipped at one
+    Srgb,
+    /// A 3D LUT, e.g. a filmic or AgX look exported from another tool
+    Lut{ lut: Arc<CubeLut>, input: LutInput },
+}
+
+impl DisplayTransform
+{
+    /// Parses "linear", "srgb" or the path of a .cube file
+    pub fn from_arg(value: &str, input: LutInput) -> Result<Self, String>
+    {
+        if value.eq_ignore_ascii_case("linear")
+        {
+            Ok(DisplayTransform::Linear)
+        }
+        else if value.eq_ignore_ascii_case("srgb")
+        {
+            Ok(DisplayTransform::Srgb)
+        }
+        else
+        {
+            Ok(DisplayTransform::Lut{ lut: Arc::new(CubeLut::load(Path::new(value))?), input })
+        }
+    }
+
+    pub fn name(&self) -> String
+    {
+        match self
+        {
+            DisplayTransform::Linear => "Linear".to_owned(),
+            DisplayTransform::Srgb => "sRGB".to_owned(),
+            DisplayTransform::Lut{ lut, input } => format!("LUT {} ({} input)", lut.title, input.name()),
+        }
+    }
+
+    pub fn apply(&self, color: LinearRGB) -> SRGB
+    {
+        match self
+        {
+            DisplayTransform::Linear => SRGB::new(color.r, color.g, color.b, color.a),
+            DisplayTransform::Srgb => color.to_srgb(),
+            DisplayTransform::Lut{ lut, input } =>
+            {
+                let [r, g, b] = lut.sample([input.encode(color.r), input.encode(color.g), input.encode(color.b)]);
+                SRGB::new(r, g, b, color.a)
+            },
+        }
+    }
+}
+
+/// A grading LUT, applied to the display values after the display
+/// transform. The strength blends between the ungraded and
+/// fully graded colors.
+#[derive(Clone, Debug)]
+pub struct ColorGrade
+{
+    pub lut: Arc<CubeLut>,
+    pub strength: Scalar,
+}
+
+impl ColorGrade
+{
+    pub fn load(path: &Path) -> Result<Self, String>
+    {
+        Ok(ColorGrade { lut: Arc::new(CubeLut::load(path)?), strength: 1.0 })
+    }
+
+    pub fn apply(&self, color: SRGB) -> SRGB
+    {
+        let graded = self.lut.sample([color.r, color.g, color.b]);
+        let blend = |from: Scalar, to: Scalar| from + (to - from) * self.strength;
+
+        SRGB::new(blend(color.r, graded[0]), blend(color.g, graded[1]), blend(color.b, graded[2]), color.a)
+    }
+}
+
+/// The encoding that a LUT expects its input in - this isn't
+/// stored in .cube files, so it has to be chosen to match
+/// the tool that the LUT came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LutInput
+{
+    Linear,
+    Srgb,
+    Log2,
+}
+
+impl LutInput
+{
+    pub const ALL: [LutInput; 3] = [LutInput::Linear, LutInput::Srgb, LutInput::Log2];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            LutInput::Linear => "Linear",
+            LutInput::Srgb => "sRGB",
+            LutInput::Log2 => "Log2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        LutInput::ALL.iter().cloned().find(|i| i.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Encodes a linear channel into zero to one
+    fn encode(&self, value: Scalar) -> Scalar
+    {
+        match self
+        {
+            LutInput::Linear => value,
+            LutInput::Srgb => LinearRGB::new(value, 0.0, 0.0, 1.0).to_srgb().r,
+            LutInput::Log2 =>
+            {
+                let stops = (value.max(1e-10) / LOG2_MIDDLE_GREY).log2();
+                (stops - LOG2_MIN_STOPS) / (LOG2_MAX_STOPS - LOG2_MIN_STOPS)
+            },
+        }
+    }
+}
+
+/// A 3D LUT in the Resolve/Adobe .cube format
+#[derive(Debug)]
+pub struct CubeLut
+{
+    pub title: String,
+    size: usize,
+    domain_min: [Scalar; 3],
+    domain_max: [Scalar; 3],
+    /// Red changes fastest, then green, then blue
+    values: Vec<[Scalar; 3]>,
+}
+
+impl CubeLut
+{
+    pub fn load(path: &Path) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read LUT {:?}: {}", path, e))?;
+
+        let mut lut = CubeLut::parse(&text)
+            .map_err(|e| format!("Could not load LUT {:?}: {}", path, e))?;
+
+        if lut.title.is_empty()
+        {
+            lut.title = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        }
+
+        Ok(lut)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut title = String::new();
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut values = Vec::new();
+
+        for (line_num, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+
+            let error = |msg: &str| format!("Line {}: {}", line_num + 1, msg);
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            let numbers = |count: usize| -> Result<Vec<Scalar>, String>
+            {
+                let parsed = parse_numbers(rest).map_err(|e| error(&e))?;
+
+                if parsed.len() != count
+                {
+                    return Err(error(&format!("Expected {} numbers", count)));
+                }
+
+                Ok(parsed)
+            };
+
+            match keyword
+            {
+                "TITLE" => title = rest.trim_matches('"').to_owned(),
+                "LUT_3D_SIZE" =>
+                {
+                    let value = rest.parse::<usize>().map_err(|e| error(&e.to_string()))?;
+
+                    if value < 2
+                    {
+                        return Err(error("LUT_3D_SIZE must be at least 2"));
+                    }
+
+                    size = Some(value);
+                },
+                "DOMAIN_MIN" =>
+                {
+                    let min = numbers(3)?;
+                    domain_min = [min[0], min[1], min[2]];
+                },
+                "DOMAIN_MAX" =>
+                {
+                    let max = numbers(3)?;
+                    domain_max = [max[0], max[1], max[2]];
+                },
+                "LUT_3D_INPUT_RANGE" =>
+                {
+                    let range = numbers(2)?;
+                    domain_min = [range[0]; 3];
+                    domain_max = [range[1]; 3];
+                },
+                "LUT_1D_SIZE" | "LUT_1D_INPUT_RANGE" => return Err(error("1D LUTs are not supported")),
+                _ =>
+                {
+                    let parsed = parse_numbers(line)
+                        .map_err(|_| error(&format!("Unknown keyword {:?}", keyword)))?;
+
+                    if parsed.len() != 3
+                    {
+                        return Err(error("Expected 3 numbers"));
+                    }
+
+                    values.push([parsed[0], parsed[1], parsed[2]]);
+                },
+            }
+        }
+
+        let size = size.ok_or_else(|| "No LUT_3D_SIZE".to_owned())?;
+
+        if values.len() != size * size * size
+        {
+            return Err(format!("Expected {} entries for size {}, but found {}", size * size * size, size, values.len()));
+        }
+
+        Ok(CubeLut { title, size, domain_min, domain_max, values })
+    }
+
+    /// Trilinearly interpolates the LUT, clamping to its domain
+    pub fn sample(&self, input: [Scalar; 3]) -> [Scalar; 3]
+    {
+        let max = self.size - 1;
+        let mut cells = [(0, 0, 0.0); 3];
+
+        for (axis, cell) in cells.iter_mut().enumerate()
+        {
+            let unit = (input[axis] - self.domain_min[axis]) / (self.domain_max[axis] - self.domain_min[axis]);
+            let pos = (unit * (max as Scalar)).clamp(0.0, max as Scalar);
+            let lower = (pos.floor() as usize).min(max);
+
+            *cell = (lower, (lower + 1).min(max), pos - (lower as Scalar));
+        }
+
+        let value = |r: usize, g: usize, b: usize| self.values[r + self.size * (g + self.size * b)];
+        let lerp = |a: [Scalar; 3], b: [Scalar; 3], t: Scalar| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t];
+
+        let [(r0, r1, tr), (g0, g1, tg), (b0, b1, tb)] = cells;
+
+        lerp(
+            lerp(lerp(value(r0, g0, b0), value(r1, g0, b0), tr), lerp(value(r0, g1, b0), value(r1, g1, b0), tr), tg),
+            lerp(lerp(value(r0, g0, b1), value(r1, g0, b1), tr), lerp(value(r0, g1, b1), value(r1, g1, b1), tr), tg),
+            tb)
+    }
+}
+
+fn parse_numbers(text: &str) -> Result<Vec<Scalar>, String>
+{
+    text.split_whitespace()
+        .map(|n| n.parse::<Scalar>().map_err(|e| format!("Invalid number {:?}: {}", n, e)))
+        .collect()
+}
@@ -0,0 +1,237 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::SRGB;
+use crate::math::Scalar;
+
+/// Number of points sampled from the built-in curves
+const PRESET_POINTS: usize = 33;
+
+/// Stylized film emulation, applied to display values after the
+/// display transform and grades - a response curve, then grain
+#[derive(Clone, Debug)]
+pub struct FilmEmulation
+{
+    pub response: Option<Arc<ResponseCurve>>,
+    /// Grain features are roughly this many pixels across
+    pub grain_size: Scalar,
+    /// Strength of the grain, in display values - zero disables it
+    pub grain_intensity: Scalar,
+}
+
+impl Default for FilmEmulation
+{
+    fn default() -> Self
+    {
+        FilmEmulation { response: None, grain_size: 1.5, grain_intensity: 0.0 }
+    }
+}
+
+impl FilmEmulation
+{
+    /// The pixel position is needed so the grain
+    /// stays in place between updates
+    pub fn apply(&self, color: SRGB, x: u32, y: u32) -> SRGB
+    {
+        let mut rgb = [color.r, color.g, color.b];
+
+        if let Some(response) = &self.response
+        {
+            rgb = response.apply(rgb);
+        }
+
+        if self.grain_intensity > 0.0
+        {
+            // Grain is most visible in the mid-tones, and
+            // fades out in the deepest shadows and highlights
+
+            let luma = ((0.2126 * rgb[0]) + (0.7152 * rgb[1]) + (0.0722 * rgb[2])).clamp(0.0, 1.0);
+            let offset = grain_noise(x, y, self.grain_size) * self.grain_intensity * 4.0 * luma * (1.0 - luma);
+
+            rgb = [rgb[0] + offset, rgb[1] + offset, rgb[2] + offset];
+        }
+
+        SRGB::new(rgb[0], rgb[1], rgb[2], color.a)
+    }
+
+    pub fn name(&self) -> String
+    {
+        match &self.response
+        {
+            Some(response) => response.name.clone(),
+            None => "None".to_owned(),
+        }
+    }
+}
+
+/// A camera or film response curve, mapping display values from zero
+/// to one into the values the film records. It's piecewise linear
+/// between points, and can differ for each channel.
+#[derive(Clone, Debug)]
+pub struct ResponseCurve
+{
+    pub name: String,
+    /// Sorted by input
+    points: Vec<(Scalar, [Scalar; 3])>,
+}
+
+impl ResponseCurve
+{
+    pub const PRESET_NAMES: [&'static str; 3] = ["Negative", "Slide", "Cross Process"];
+
+    /// Built-in curves, each described by the contrast of an
+    /// S-curve and the black and white levels, per channel
+    pub fn preset(name: &str) -> Option<Self>
+    {
+        let channels: [(Scalar, Scalar, Scalar); 3] = match name.to_ascii_lowercase().as_str()
+        {
+            // Low contrast, with lifted blacks and soft highlights
+            "negative" => [(0.85, 0.03, 0.97); 3],
+            // High contrast and saturation, with deep blacks
+            "slide" => [(1.6, 0.0, 1.0); 3],
+            // Slide film developed as a negative - contrasty
+            // and yellow-green, with cyan shadows
+            "cross process" => [(1.4, 0.02, 1.0), (1.2, 0.06, 1.0), (0.8, 0.12, 0.85)],
+            _ => return None,
+        };
+
+        let name = Self::PRESET_NAMES.iter().find(|n| n.eq_ignore_ascii_case(name))?;
+
+        let points = (0..PRESET_POINTS)
+            .map(|i|
+            {
+                let x = (i as Scalar) / ((PRESET_POINTS - 1) as Scalar);
+                let channel = |(contrast, black, white): (Scalar, Scalar, Scalar)| black + ((white - black) * s_curve(x, contrast));
+
+                (x, [channel(channels[0]), channel(channels[1]), channel(channels[2])])
+            })
+            .collect();
+
+        Some(ResponseCurve { name: (*name).to_owned(), points })
+    }
+
+    /// Parses a preset name, or the path of a curve file
+    pub fn from_arg(value: &str) -> Result<Self, String>
+    {
+        match Self::preset(value)
+        {
+            Some(curve) => Ok(curve),
+            None => Self::load(Path::new(value)),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read response curve {:?}: {}", path, e))?;
+
+        let mut curve = ResponseCurve::parse(&text)
+            .map_err(|e| format!("Could not load response curve {:?}: {}", path, e))?;
+
+        curve.name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+
+        Ok(curve)
+    }
+
+    /// Each line is an input followed by either one output, for all
+    /// channels, or separate red, green and blue outputs. Blank lines
+    /// and lines starting with '#' are ignored.
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut points = Vec::new();
+
+        for (line_num, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+
+            let numbers = line.split(|c: char| c.is_whitespace() || (c == ','))
+                .filter(|n| !n.is_empty())
+                .map(|n| n.parse::<Scalar>().map_err(|e| format!("Line {}: Invalid number {:?}: {}", line_num + 1, n, e)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match numbers.len()
+            {
+                2 => points.push((numbers[0], [numbers[1]; 3])),
+                4 => points.push((numbers[0], [numbers[1], numbers[2], numbers[3]])),
+                _ => return Err(format!("Line {}: Expected an input and 1 or 3 outputs", line_num + 1)),
+            }
+        }
+
+        if points.len() < 2
+        {
+            return Err("At least 2 points are required".to_owned());
+        }
+
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(ResponseCurve { name: String::new(), points })
+    }
+
+    /// Inputs outside the curve are clamped to its ends
+    pub fn apply(&self, rgb: [Scalar; 3]) -> [Scalar; 3]
+    {
+        let mut result = [0.0; 3];
+
+        for (channel, (value, out)) in rgb.iter().zip(result.iter_mut()).enumerate()
+        {
+            let upper = self.points.partition_point(|p| p.0 < *value).clamp(1, self.points.len() - 1);
+            let (x0, y0) = self.points[upper - 1];
+            let (x1, y1) = self.points[upper];
+
+            let t = if x1 > x0 { ((value - x0) / (x1 - x0)).clamp(0.0, 1.0) } else { 0.0 };
+
+            *out = y0[channel] + ((y1[channel] - y0[channel]) * t);
+        }
+
+        result
+    }
+}
+
+/// An S-curve through (0, 0), (0.5, 0.5) and (1, 1), that's
+/// steeper in the middle as the contrast increases above one
+fn s_curve(x: Scalar, contrast: Scalar) -> Scalar
+{
+    let a = x.powf(contrast);
+    let b = (1.0 - x).powf(contrast);
+
+    a / (a + b)
+}
+
+/// Smoothly interpolated noise from -1 to 1, with
+/// features about the given number of pixels across
+fn grain_noise(x: u32, y: u32, size: Scalar) -> Scalar
+{
+    let size = size.max(1.0);
+    let (fx, fy) = ((x as Scalar) / size, (y as Scalar) / size);
+    let (ix, iy) = (fx.floor(), fy.floor());
+
+    let smooth = |t: Scalar| t * t * (3.0 - (2.0 * t));
+    let (tx, ty) = (smooth(fx - ix), smooth(fy - iy));
+
+    let (ix, iy) = (ix as i64, iy as i64);
+    let lerp = |a: Scalar, b: Scalar, t: Scalar| a + ((b - a) * t);
+
+    lerp(
+        lerp(hash_noise(ix, iy), hash_noise(ix + 1, iy), tx),
+        lerp(hash_noise(ix, iy + 1), hash_noise(ix + 1, iy + 1), tx),
+        ty)
+}
+
+/// A fixed random value from -1 to 1 for each lattice point
+fn hash_noise(x: i64, y: i64) -> Scalar
+{
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+    h ^= h >> 33;
+
+    (((h >> 11) as Scalar) / ((1u64 << 53) as Scalar) * 2.0) - 1.0
+}
@@ -1,5 +1,13 @@
+pub mod blackbody;
+pub mod display;
+pub mod film;
 pub mod linearrgb;
 pub mod srgb;
+pub mod tonemap;
 
+pub use blackbody::blackbody;
+pub use display::{ColorGrade, CubeLut, DisplayTransform, LutInput};
+pub use film::{FilmEmulation, ResponseCurve};
 pub use linearrgb::LinearRGB;
 pub use srgb::SRGB;
+pub use tonemap::{ToneMapping, ToneOperator};
@@ -0,0 +1,95 @@
+use crate::color::LinearRGB;
+use crate::math::Scalar;
+
+/// Compresses the unbounded range of the render into zero to one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneOperator
+{
+    /// Values are left as they are, for the display transform
+    /// to clip, or for a LUT that expects the full range
+    #[default]
+    None,
+    /// x / (1 + x) per channel - gentle, but desaturates highlights
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneOperator
+{
+    pub const ALL: [ToneOperator; 3] = [ToneOperator::None, ToneOperator::Reinhard, ToneOperator::Aces];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            ToneOperator::None => "None",
+            ToneOperator::Reinhard => "Reinhard",
+            ToneOperator::Aces => "ACES",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        ToneOperator::ALL.iter().cloned().find(|o| o.name().eq_ignore_ascii_case(name))
+    }
+
+    fn apply(&self, value: Scalar) -> Scalar
+    {
+        match self
+        {
+            ToneOperator::None => value,
+            ToneOperator::Reinhard => value.max(0.0) / (1.0 + value.max(0.0)),
+            ToneOperator::Aces =>
+            {
+                let value = value.max(0.0);
+                ((value * ((2.51 * value) + 0.03)) / ((value * ((2.43 * value) + 0.59)) + 0.14)).clamp(0.0, 1.0)
+            },
+        }
+    }
+}
+
+/// Exposure and tone mapping, applied to the linear render
+/// before the display transform
+#[derive(Clone, Debug)]
+pub struct ToneMapping
+{
+    /// In stops - each one doubles the brightness
+    pub exposure: Scalar,
+    pub operator: ToneOperator,
+    /// Values above one brighten the mid-tones
+    pub gamma: Scalar,
+}
+
+impl Default for ToneMapping
+{
+    fn default() -> Self
+    {
+        ToneMapping { exposure: 0.0, operator: ToneOperator::None, gamma: 1.0 }
+    }
+}
+
+impl ToneMapping
+{
+    pub fn apply(&self, color: LinearRGB) -> LinearRGB
+    {
+        let scale = (2.0 as Scalar).powf(self.exposure);
+        let power = 1.0 / self.gamma.max(0.01);
+
+        // Negative values, e.g. from a denoiser, are
+        // kept rather than becoming NaN
+
+        let channel = |value: Scalar|
+        {
+            let value = self.operator.apply(value * scale);
+            value.signum() * value.abs().powf(power)
+        };
+
+        LinearRGB::new(channel(color.r), channel(color.g), channel(color.b), color.a)
+    }
+
+    pub fn name(&self) -> String
+    {
+        format!("{} ({:+} EV, gamma {})", self.operator.name(), self.exposure, self.gamma)
+    }
+}
@@ -1,4 +1,3 @@
-use crate::camera::Camera;
 use crate::color::SRGB;
 use crate::desc::{SceneDescription, SceneSelection, StandardScene};
 use crate::geom::{Aabb, Plane, Sphere, Rectangle, Blob, BlobPart, BoundedSurface, csg};
@@ -22,6 +21,8 @@ pub fn generate_description() -> SceneDescription
             look_at: Point3::new(0.0, -1.0, 0.0),
             up: Point3::new(0.0, 1.0, 0.0),
             fov: 40.0,
+            aperture: 0.3,
+            focus_distance: 0.0,
         },
         selection: SceneSelection::Standard(StandardScene::BeamExample),
     }
@@ -134,7 +135,7 @@ pub fn generate_scene(desc: &SceneDescription, options: &RenderOptions) -> Scene
 
     Scene::new(
         options.sampling_mode,
-        Camera::new(desc.camera.location, desc.camera.look_at, desc.camera.up, desc.camera.fov, (options.width as f64) / (options.height as f64)),
+        desc.camera.build(options),
         // Lighting regions
         vec![
             LightingRegion::new_2(
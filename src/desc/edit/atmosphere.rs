@@ -0,0 +1,143 @@
+use crate::math::Scalar;
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::Dir3;
+use serde::{Deserialize, Serialize};
+
+/// A sky and sun that light the scene, for outdoor
+/// scenes without an environment map. Distances
+/// are in meters, and angles in degrees.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Atmosphere
+{
+    /// Angle of the sun above the horizon
+    pub sun_elevation: Scalar,
+    /// Angle of the sun around the horizon, from +z towards +x
+    pub sun_azimuth: Scalar,
+    /// Irradiance from the sun, before it enters the atmosphere
+    pub sun_intensity: Scalar,
+    /// Angular diameter of the sun's disk - larger
+    /// suns give softer shadows, and less noise
+    pub sun_size: Scalar,
+    /// Scales the amount of haze - zero gives clean air
+    pub haze: Scalar,
+    /// Height of the viewer above the ground
+    pub altitude: Scalar,
+    #[serde(default)]
+    pub clouds: Option<Clouds>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Clouds
+{
+    /// Fraction of the sky covered, from zero to one
+    pub coverage: Scalar,
+    /// Extinction per meter in the densest parts
+    pub density: Scalar,
+    /// Height of the cloud base
+    pub altitude: Scalar,
+    pub thickness: Scalar,
+    /// Typical size of a cloud
+    pub scale: Scalar,
+}
+
+impl Default for Atmosphere
+{
+    fn default() -> Self
+    {
+        Atmosphere { sun_elevation: 30.0, sun_azimuth: 0.0, sun_intensity: 20.0, sun_size: 0.53, haze: 1.0, altitude: 100.0, clouds: None }
+    }
+}
+
+impl Default for Clouds
+{
+    fn default() -> Self
+    {
+        Clouds { coverage: 0.5, density: 0.02, altitude: 1500.0, thickness: 1000.0, scale: 3000.0 }
+    }
+}
+
+impl Atmosphere
+{
+    pub fn sun_dir(&self) -> Dir3
+    {
+        let (elevation, azimuth) = (self.sun_elevation.to_radians(), self.sun_azimuth.to_radians());
+
+        Dir3::new(elevation.cos() * azimuth.sin(), elevation.sin(), elevation.cos() * azimuth.cos())
+    }
+
+    pub fn build(&self) -> crate::atmosphere::Atmosphere
+    {
+        let clouds = self.clouds.as_ref().map(|c| crate::atmosphere::CloudLayer
+        {
+            coverage: c.coverage,
+            density: c.density,
+            altitude: c.altitude,
+            thickness: c.thickness,
+            scale: c.scale,
+        });
+
+        crate::atmosphere::Atmosphere::new(self.sun_dir(), self.sun_intensity, 0.5 * self.sun_size.to_radians(), self.haze, self.altitude, clouds)
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for Atmosphere
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        ui.display_float("Sun Elevation", &self.sun_elevation);
+        ui.display_float("Sun Azimuth", &self.sun_azimuth);
+        ui.display_float("Sun Intensity", &self.sun_intensity);
+        ui.display_float("Sun Size", &self.sun_size);
+        ui.display_float("Haze", &self.haze);
+        ui.display_float("Altitude", &self.altitude);
+
+        if let Some(clouds) = &self.clouds
+        {
+            ui.display_float("Cloud Coverage", &clouds.coverage);
+            ui.display_float("Cloud Density", &clouds.density);
+            ui.display_float("Cloud Altitude", &clouds.altitude);
+            ui.display_float("Cloud Thickness", &clouds.thickness);
+            ui.display_float("Cloud Scale", &clouds.scale);
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for Atmosphere
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+        result |= ui.edit_float("Sun Elevation", &mut self.sun_elevation);
+        result |= ui.edit_float("Sun Azimuth", &mut self.sun_azimuth);
+        result |= ui.edit_float("Sun Intensity", &mut self.sun_intensity);
+        result |= ui.edit_float("Sun Size", &mut self.sun_size);
+        result |= ui.edit_float("Haze", &mut self.haze);
+        result |= ui.edit_float("Altitude", &mut self.altitude);
+
+        let mut cloudy = self.clouds.is_some();
+
+        if ui.imgui.checkbox("Clouds", &mut cloudy)
+        {
+            self.clouds = if cloudy { Some(Clouds::default()) } else { None };
+            result = true;
+        }
+
+        if let Some(clouds) = &mut self.clouds
+        {
+            result |= ui.edit_float("Cloud Coverage", &mut clouds.coverage);
+            result |= ui.edit_float("Cloud Density", &mut clouds.density);
+            result |= ui.edit_float("Cloud Altitude", &mut clouds.altitude);
+            result |= ui.edit_float("Cloud Thickness", &mut clouds.thickness);
+            result |= ui.edit_float("Cloud Scale", &mut clouds.scale);
+        }
+
+        result
+    }
+}
@@ -1,14 +1,22 @@
 use crate::render::RenderOptions;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
-use crate::vec::Point3;
+use crate::math::Scalar;
+use crate::vec::{Dir3, Point3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Camera
 {
     pub location: Point3,
     pub look_at: Point3,
     pub up: Point3,
     pub fov: f64,
+    /// Diameter of the lens - zero keeps everything in focus
+    pub aperture: Scalar,
+    /// Distance along the view direction that's in focus,
+    /// or zero to focus on the look-at point
+    pub focus_distance: Scalar,
 }
 
 impl Camera
@@ -17,12 +25,52 @@ impl Camera
     {
         let aspect_ratio = (options.width as f64) / (options.height as f64);
 
+        let focus_distance = if self.focus_distance > 0.0 { self.focus_distance } else { (self.look_at - self.location).magnitude() };
+
         crate::camera::Camera::new(
             self.location,
             self.look_at,
             self.up,
             self.fov,
             aspect_ratio)
+            .with_depth_of_field(self.aperture, focus_distance)
+    }
+}
+
+impl Camera
+{
+    /// Returns the (right, up, back) basis vectors for the camera
+    pub fn basis(&self) -> (Dir3, Dir3, Dir3)
+    {
+        let w = (self.location - self.look_at).normalized();
+        let u = self.up.cross(w).normalized();
+        let v = w.cross(u);
+
+        (u, v, w)
+    }
+
+    /// Projects a world-space point onto the image, returning the
+    /// (u, v) coordinates in the range 0..1, with v = 0 at the top,
+    /// or None if the point is behind the camera.
+    pub fn project(&self, point: Point3, aspect_ratio: Scalar) -> Option<(Scalar, Scalar)>
+    {
+        let (u, v, w) = self.basis();
+
+        let offset = point - self.location;
+        let depth = -offset.dot(w);
+
+        if depth <= 0.0
+        {
+            return None;
+        }
+
+        let viewport_width = 2.0 * (self.fov.to_radians() / 2.0).tan();
+        let viewport_height = viewport_width / aspect_ratio;
+
+        let x = offset.dot(u) / depth;
+        let y = offset.dot(v) / depth;
+
+        Some((0.5 + x / viewport_width, 0.5 - y / viewport_height))
     }
 }
 
@@ -36,10 +84,13 @@ impl Default for Camera
             look_at: Point3::new(0.0, 0.0, 0.0),
             up: Point3::new(0.0, 1.0, 0.0),
             fov: 30.0,
+            aperture: 0.0,
+            focus_distance: 0.0,
         }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Camera
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -50,9 +101,12 @@ impl UiDisplay for Camera
         ui.display_vec3("Look At", &self.look_at);
         ui.display_vec3("Up", &self.up);
         ui.display_float("FOV", &self.fov);
+        ui.display_float("Aperture", &self.aperture);
+        ui.display_float("Focus Distance", &self.focus_distance);
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Camera
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
@@ -64,6 +118,8 @@ impl UiEdit for Camera
         result |= ui.edit_vec3("Look At", &mut self.look_at);
         result |= ui.edit_vec3("Up", &mut self.up);
         result |= ui.edit_float("FOV", &mut self.fov);
+        result |= ui.edit_float("Aperture", &mut self.aperture);
+        result |= ui.edit_float("Focus Distance", &mut self.focus_distance);
         result
     }
 }
\ No newline at end of file
@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::desc::edit::Triangle;
+use crate::desc::edit::lod::triangle_bounds;
+use crate::math::Scalar;
+use crate::vec::{Mat4, Point3};
+
+/// Options for cleaning up imported meshes
+#[derive(Clone, Debug)]
+pub struct MeshCleanup
+{
+    /// Vertices closer than this fraction of the mesh's
+    /// bounding box diagonal are welded together
+    pub weld_tolerance: Scalar,
+    /// Flip triangles so neighbours are wound the same way
+    pub fix_winding: bool,
+}
+
+impl Default for MeshCleanup
+{
+    fn default() -> Self
+    {
+        MeshCleanup { weld_tolerance: 1.0e-6, fix_winding: false }
+    }
+}
+
+/// The elements removed or changed by a cleanup
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport
+{
+    pub welded_vertices: usize,
+    pub degenerate_triangles: usize,
+    pub duplicate_triangles: usize,
+    pub flipped_triangles: usize,
+}
+
+impl CleanupReport
+{
+    pub fn add(&mut self, other: &CleanupReport)
+    {
+        self.welded_vertices += other.welded_vertices;
+        self.degenerate_triangles += other.degenerate_triangles;
+        self.duplicate_triangles += other.duplicate_triangles;
+        self.flipped_triangles += other.flipped_triangles;
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        *self == CleanupReport::default()
+    }
+
+    pub fn summary(&self) -> String
+    {
+        format!("welded {} vertices, removed {} degenerate and {} duplicate triangles, flipped {} triangles",
+            self.welded_vertices, self.degenerate_triangles, self.duplicate_triangles, self.flipped_triangles)
+    }
+}
+
+impl MeshCleanup
+{
+    /// Welds nearby vertices, then removes triangles that are
+    /// degenerate (zero area, or with non-finite vertices) or
+    /// that duplicate another triangle's vertices, in either
+    /// winding. Texture coordinates and colors are kept.
+    pub fn apply(&self, triangles: Vec<Triangle>) -> (Vec<Triangle>, CleanupReport)
+    {
+        let mut report = CleanupReport::default();
+
+        // Non-finite vertices would make the bounds,
+        // and so the tolerance, meaningless
+
+        let (mut triangles, non_finite): (Vec<_>, Vec<_>) = triangles.into_iter()
+            .partition(|t| t.vertices.iter().all(|v| v.location.iter().all(|c| c.is_finite())));
+
+        report.degenerate_triangles += non_finite.len();
+
+        let bounds = triangle_bounds(&triangles, &Mat4::identity());
+        let diagonal = (bounds.max - bounds.min).magnitude();
+        let tolerance = self.weld_tolerance.max(0.0) * diagonal;
+
+        // Weld each vertex onto the first vertex
+        // within the tolerance, giving each an ID
+
+        let mut welder = Welder::new(tolerance);
+
+        let ids = triangles.iter_mut()
+            .map(|triangle|
+            {
+                let mut ids = [0; 3];
+
+                for (v, id) in triangle.vertices.iter_mut().zip(ids.iter_mut())
+                {
+                    let (welded_id, location) = welder.weld(v.location);
+
+                    if location != v.location
+                    {
+                        report.welded_vertices += 1;
+                        v.location = location;
+                    }
+
+                    *id = welded_id;
+                }
+
+                ids
+            })
+            .collect::<Vec<_>>();
+
+        // Remove collapsed and duplicated triangles
+
+        let min_area = (tolerance * tolerance).max(Scalar::MIN_POSITIVE);
+
+        let mut seen = HashSet::new();
+        let mut kept = Vec::new();
+        let mut kept_ids = Vec::new();
+
+        for (triangle, ids) in triangles.into_iter().zip(ids)
+        {
+            let [a, b, c] = triangle.vertices.clone().map(|v| v.location);
+
+            if (ids[0] == ids[1]) || (ids[1] == ids[2]) || (ids[0] == ids[2])
+                || ((b - a).cross(c - a).magnitude() <= min_area)
+            {
+                report.degenerate_triangles += 1;
+                continue;
+            }
+
+            let mut key = ids;
+            key.sort();
+
+            if !seen.insert(key)
+            {
+                report.duplicate_triangles += 1;
+                continue;
+            }
+
+            kept.push(triangle);
+            kept_ids.push(ids);
+        }
+
+        if self.fix_winding
+        {
+            report.flipped_triangles = fix_winding(&mut kept, &kept_ids);
+        }
+
+        (kept, report)
+    }
+}
+
+/// Looks up nearby vertices in a grid with
+/// cells the size of the tolerance
+struct Welder
+{
+    tolerance: Scalar,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+    locations: Vec<Point3>,
+}
+
+impl Welder
+{
+    fn new(tolerance: Scalar) -> Self
+    {
+        Welder { tolerance, cells: HashMap::new(), locations: Vec::new() }
+    }
+
+    fn cell(&self, p: Point3) -> (i64, i64, i64)
+    {
+        let size = self.tolerance.max(Scalar::MIN_POSITIVE);
+
+        ((p.x / size).floor() as i64, (p.y / size).floor() as i64, (p.z / size).floor() as i64)
+    }
+
+    fn weld(&mut self, p: Point3) -> (usize, Point3)
+    {
+        let (x, y, z) = self.cell(p);
+
+        for dx in -1..=1
+        {
+            for dy in -1..=1
+            {
+                for dz in -1..=1
+                {
+                    if let Some(ids) = self.cells.get(&(x + dx, y + dy, z + dz))
+                    {
+                        for id in ids.iter()
+                        {
+                            if (self.locations[*id] - p).magnitude() <= self.tolerance
+                            {
+                                return (*id, self.locations[*id]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let id = self.locations.len();
+        self.locations.push(p);
+        self.cells.entry((x, y, z)).or_default().push(id);
+
+        (id, p)
+    }
+}
+
+/// Flips triangles so each edge shared by two triangles is
+/// traversed in opposite directions by them. Edges shared by
+/// more than two triangles are ignored. In each connected
+/// piece, the winding of the majority of triangles is kept.
+/// Returns the number of triangles flipped.
+fn fix_winding(triangles: &mut [Triangle], ids: &[[usize; 3]]) -> usize
+{
+    let edges = |t: &[usize; 3]| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])];
+
+    let mut edge_triangles: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+
+    for (index, t) in ids.iter().enumerate()
+    {
+        for (a, b) in edges(t)
+        {
+            edge_triangles.entry((a.min(b), a.max(b))).or_default().push(index);
+        }
+    }
+
+    let mut flip: Vec<Option<bool>> = vec![None; triangles.len()];
+    let mut total = 0;
+
+    for start in 0..triangles.len()
+    {
+        if flip[start].is_some()
+        {
+            continue;
+        }
+
+        flip[start] = Some(false);
+
+        let mut piece = vec![start];
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(index) = queue.pop_front()
+        {
+            let flipped = flip[index] == Some(true);
+
+            for (a, b) in edges(&ids[index])
+            {
+                // The direction this triangle traverses the edge, once flipped
+
+                let forward = (a < b) != flipped;
+
+                let neighbours = &edge_triangles[&(a.min(b), a.max(b))];
+
+                if neighbours.len() != 2
+                {
+                    continue;
+                }
+
+                let other = if neighbours[0] == index { neighbours[1] } else { neighbours[0] };
+
+                if flip[other].is_some()
+                {
+                    continue;
+                }
+
+                // The neighbour must traverse it the other way
+
+                let other_forward = edges(&ids[other]).iter()
+                    .any(|(c, d)| (*c == a.min(b)) && (*d == a.max(b)));
+
+                flip[other] = Some(other_forward == forward);
+                piece.push(other);
+                queue.push_back(other);
+            }
+        }
+
+        let num_flipped = piece.iter().filter(|i| flip[**i] == Some(true)).count();
+        let invert = (2 * num_flipped) > piece.len();
+
+        for index in piece
+        {
+            if (flip[index] == Some(true)) != invert
+            {
+                triangles[index].vertices.swap(1, 2);
+                total += 1;
+            }
+        }
+    }
+
+    total
+}
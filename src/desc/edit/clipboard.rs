@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+
+use crate::desc::edit::{Geom, Group, Material, Object, Probe, Scene, Texture, Transform};
+use crate::import::image::Image;
+use crate::indexed::{AnyIndex, GroupIndex, Index, IndexedCollection, IndexedValue, IndexRemap, MaterialIndex, ObjectIndex};
+
+#[derive(Clone)]
+pub enum ClipboardItem
+{
+    Object(ObjectIndex),
+    Material(MaterialIndex),
+    Group(GroupIndex),
+}
+
+// Holds a copy of the source scene so that the copied
+// item (and everything it references) remains valid even
+// if the source scene is later edited or closed.
+
+#[derive(Clone)]
+pub struct Clipboard
+{
+    scene: Scene,
+    item: ClipboardItem,
+}
+
+impl Clipboard
+{
+    pub fn new(scene: &Scene, item: ClipboardItem) -> Self
+    {
+        Clipboard { scene: scene.clone(), item }
+    }
+
+    pub fn describe(&self) -> String
+    {
+        match self.item
+        {
+            ClipboardItem::Object(index) => format!("{:?}", index),
+            ClipboardItem::Material(index) => format!("{:?}", index),
+            ClipboardItem::Group(index) => format!("{:?}", index),
+        }
+    }
+
+    pub fn paste_into(&self, dest: &mut Scene)
+    {
+        let mut copier = Copier::new(&self.scene.collection, &mut dest.collection);
+
+        match self.item
+        {
+            ClipboardItem::Object(index) => copier.copy(index.to_any()),
+            ClipboardItem::Material(index) => copier.copy(index.to_any()),
+            ClipboardItem::Group(index) => copier.copy(index.to_any()),
+        }
+
+        copier.finish();
+    }
+}
+
+/// Adds everything in the other scene to the scene, re-mapping
+/// indexes. Names that are already used get a numbered suffix.
+/// The scene's camera and backplate are kept.
+pub fn append_scene(dest: &mut Scene, other: &Scene)
+{
+    Copier::new(&other.collection, &mut dest.collection).copy_all();
+}
+
+// Deep-copies items between collections. Everything the copied
+// items refer to (found with `collect_indexes`) is copied first,
+// then each copy's indexes are updated with `remap_indexes`, so
+// it follows the same references as removing and reordering.
+// Items referenced more than once are only copied once.
+
+struct Copier<'a>
+{
+    source: &'a IndexedCollection,
+    dest: &'a mut IndexedCollection,
+    copied: HashSet<AnyIndex>,
+    pending: Vec<AnyIndex>,
+    remap: IndexRemap,
+}
+
+impl<'a> Copier<'a>
+{
+    fn new(source: &'a IndexedCollection, dest: &'a mut IndexedCollection) -> Self
+    {
+        Copier
+        {
+            source,
+            dest,
+            copied: HashSet::new(),
+            pending: Vec::new(),
+            remap: IndexRemap::default(),
+        }
+    }
+
+    /// Copies every item, so that the destination
+    /// contains everything in the source
+    fn copy_all(mut self)
+    {
+        let source = self.source;
+
+        for index in source.map_all_with_index(|i, _: &Image, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Texture, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Transform, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Material, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Geom, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Object, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Group, _| i) { self.copy(index.to_any()); }
+        for index in source.map_all_with_index(|i, _: &Probe, _| i) { self.copy(index.to_any()); }
+
+        self.finish();
+    }
+
+    /// Copies the item and everything it refers to
+    fn copy(&mut self, index: AnyIndex)
+    {
+        match index
+        {
+            AnyIndex::Image(i) => self.copy_item(i),
+            AnyIndex::Texture(i) => self.copy_item(i),
+            AnyIndex::Transform(i) => self.copy_item(i),
+            AnyIndex::Material(i) => self.copy_item(i),
+            AnyIndex::Geom(i) => self.copy_item(i),
+            AnyIndex::Object(i) => self.copy_item(i),
+            AnyIndex::Group(i) => self.copy_item(i),
+            AnyIndex::Probe(i) => self.copy_item(i),
+        }
+    }
+
+    fn copy_item<I: Index>(&mut self, index: I)
+        where I::Value: IndexedValue<Index = I>
+    {
+        if !self.copied.insert(index.to_any())
+        {
+            return;
+        }
+
+        let mut referenced = HashSet::new();
+        self.source.map_item(index, |v, _| v.collect_indexes(&mut referenced));
+
+        for other in referenced.into_iter().sorted()
+        {
+            self.copy(other);
+        }
+
+        // Its value is filled in once everything it refers to
+        // has a new index - which, for groups that contain
+        // themselves, is only after it's been added
+
+        let name = self.source.name_of(index).map(|n| self.dest.unique_name::<I::Value>(&n));
+        let result = self.dest.push_opt_name(I::Value::default(), name);
+
+        self.remap.insert(index, result);
+        self.pending.push(index.to_any());
+    }
+
+    /// Fills in the copied values, now that
+    /// all of their new indexes are known
+    fn finish(mut self)
+    {
+        for index in std::mem::take(&mut self.pending)
+        {
+            match index
+            {
+                AnyIndex::Image(i) => self.finish_item(i),
+                AnyIndex::Texture(i) => self.finish_item(i),
+                AnyIndex::Transform(i) => self.finish_item(i),
+                AnyIndex::Material(i) => self.finish_item(i),
+                AnyIndex::Geom(i) => self.finish_item(i),
+                AnyIndex::Object(i) => self.finish_item(i),
+                AnyIndex::Group(i) => self.finish_item(i),
+                AnyIndex::Probe(i) => self.finish_item(i),
+            }
+        }
+    }
+
+    fn finish_item<I: Index>(&mut self, index: I)
+        where I::Value: IndexedValue<Index = I>
+    {
+        let mut value = self.source.map_item(index, |v, _| v.clone());
+        value.remap_indexes(&self.remap);
+
+        let mut result = index;
+        self.remap.apply(&mut result);
+
+        self.dest.update_value(result, value);
+    }
+}
@@ -1,7 +1,12 @@
 use crate::color::{SRGB, LinearRGB};
+use crate::math::Scalar;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+/// Saved as linear [r, g, b, a]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(from = "[Scalar; 4]", into = "[Scalar; 4]")]
 pub struct Color
 {
     linear: LinearRGB,
@@ -31,6 +36,22 @@ impl From<LinearRGB> for Color
     }
 }
 
+impl From<[Scalar; 4]> for Color
+{
+    fn from(linear: [Scalar; 4]) -> Self
+    {
+        Color { linear: LinearRGB::new(linear[0], linear[1], linear[2], linear[3]) }
+    }
+}
+
+impl From<Color> for [Scalar; 4]
+{
+    fn from(color: Color) -> Self
+    {
+        [color.linear.r, color.linear.g, color.linear.b, color.linear.a]
+    }
+}
+
 impl From<SRGB> for Color
 {
     fn from(srgb: SRGB) -> Self
@@ -47,6 +68,7 @@ impl Default for Color
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Color
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -56,6 +78,7 @@ impl UiDisplay for Color
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Color
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
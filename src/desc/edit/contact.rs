@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+use crate::desc::edit::{Object, Scene};
+use crate::indexed::{MaterialIndex, ObjectIndex};
+
+/// An alternative material for an object, to compare on a contact sheet
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialVariant
+{
+    pub name: String,
+    pub object: ObjectIndex,
+    pub material: MaterialIndex,
+}
+
+/// What each thumbnail of a contact sheet shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContactSheetMode
+{
+    /// One thumbnail for each named camera
+    Cameras,
+    /// One thumbnail for each material variant, from the scene camera
+    Materials,
+    /// One thumbnail for each variant of each variant set,
+    /// with the other sets left as they are
+    Variants,
+}
+
+impl ContactSheetMode
+{
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name
+        {
+            "cameras" => Some(ContactSheetMode::Cameras),
+            "materials" => Some(ContactSheetMode::Materials),
+            "variants" => Some(ContactSheetMode::Variants),
+            _ => None,
+        }
+    }
+}
+
+/// A grid of labeled thumbnails, for reviewing many options at once
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContactSheet
+{
+    pub mode: ContactSheetMode,
+    /// Zero chooses a roughly square grid
+    pub columns: usize,
+}
+
+impl ContactSheet
+{
+    /// A copy of the scene for each thumbnail, with its label
+    pub fn entries(&self, scene: &Scene) -> Result<Vec<(String, Scene)>, String>
+    {
+        let entries = match self.mode
+        {
+            ContactSheetMode::Cameras =>
+            {
+                scene.cameras.iter()
+                    .map(|(name, camera)|
+                    {
+                        let mut scene = scene.clone();
+                        scene.camera = camera.clone();
+                        (name.clone(), scene)
+                    })
+                    .collect::<Vec<_>>()
+            },
+            ContactSheetMode::Materials =>
+            {
+                scene.material_variants.iter()
+                    .map(|variant|
+                    {
+                        let mut scene = scene.clone();
+                        let object = scene.collection.map_item(variant.object, |object, _| object.clone());
+                        scene.collection.update_value(variant.object, Object { material: variant.material, ..object });
+                        (variant.name.clone(), scene)
+                    })
+                    .collect::<Vec<_>>()
+            },
+            ContactSheetMode::Variants =>
+            {
+                scene.variant_sets.iter().enumerate()
+                    .flat_map(|(set_index, set)| set.variants.iter().enumerate().map(move |(variant_index, variant)| (set_index, set, variant_index, variant)))
+                    .map(|(set_index, set, variant_index, variant)|
+                    {
+                        let mut scene = scene.clone();
+                        scene.variant_sets[set_index].active = Some(variant_index);
+                        (format!("{}: {}", set.name, variant.name), scene)
+                    })
+                    .collect::<Vec<_>>()
+            },
+        };
+
+        if entries.is_empty()
+        {
+            return Err(match self.mode
+            {
+                ContactSheetMode::Cameras => "The scene has no named cameras for the contact sheet".to_owned(),
+                ContactSheetMode::Materials => "The scene has no material variants for the contact sheet".to_owned(),
+                ContactSheetMode::Variants => "The scene has no variant sets for the contact sheet".to_owned(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    pub fn grid_size(&self, count: usize) -> (usize, usize)
+    {
+        let columns = if self.columns > 0 { self.columns } else { ((count as f64).sqrt().ceil() as usize).max(1) };
+        let rows = count.div_ceil(columns);
+
+        (columns, rows)
+    }
+}
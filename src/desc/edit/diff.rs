@@ -0,0 +1,257 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::desc::edit::{scene_to_json, Scene};
+
+/// The collections, as named in the scene JSON, and for reports
+const COLLECTIONS: &[(&str, &str)] = &[
+    ("images", "Images"),
+    ("textures", "Textures"),
+    ("transforms", "Transforms"),
+    ("materials", "Materials"),
+    ("geometry", "Geometry"),
+    ("objects", "Objects"),
+    ("groups", "Groups"),
+    ("probes", "Probes"),
+];
+
+/// Changed values listed for each entry - beyond this (e.g.
+/// for edited meshes) they're only counted
+const MAX_CHANGED_PATHS: usize = 8;
+
+/// The differences between two scenes
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SceneDiff
+{
+    pub entries: Vec<DiffEntry>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum DiffChange
+{
+    Added,
+    Removed,
+    /// Moved to another index, and/or with changed values
+    Changed,
+}
+
+/// A collection item (or scene setting) that was added,
+/// removed, moved or changed. Items are matched by name,
+/// or if they have no name, by index.
+#[derive(Clone, Debug, Serialize)]
+pub struct DiffEntry
+{
+    /// e.g. "Materials", or "Scene" for the camera and other settings
+    pub section: String,
+    /// The item's name, index or setting
+    pub label: String,
+    pub change: DiffChange,
+    /// Only for collection items - None if added
+    pub old_index: Option<usize>,
+    /// Only for collection items - None if removed
+    pub new_index: Option<usize>,
+    /// The values that changed, e.g. "Metal.fuzz" or "location[1]"
+    pub changed: Vec<String>,
+    /// Changed values not listed
+    pub more_changed: usize,
+}
+
+impl DiffEntry
+{
+    pub fn summary(&self) -> String
+    {
+        let mut result = format!("{}: {}", self.section, self.label);
+
+        match (self.change, self.old_index, self.new_index)
+        {
+            (DiffChange::Added, _, _) => result.push_str(" added"),
+            (DiffChange::Removed, _, _) => result.push_str(" removed"),
+            (DiffChange::Changed, Some(old), Some(new)) if old != new => result.push_str(&format!(" moved from #{} to #{}", old, new)),
+            _ => {},
+        }
+
+        if !self.changed.is_empty()
+        {
+            result.push_str(" changed: ");
+            result.push_str(&self.changed.join(", "));
+
+            if self.more_changed > 0
+            {
+                result.push_str(&format!(" and {} more", self.more_changed));
+            }
+        }
+
+        result
+    }
+}
+
+impl SceneDiff
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    /// One line per entry
+    pub fn report(&self) -> String
+    {
+        self.entries.iter().map(|e| e.summary() + "\n").collect()
+    }
+}
+
+/// Compares the scenes, through their JSON form, so everything
+/// that's saved is compared - but not e.g. image pixels, which are
+/// reloaded from the same file names
+pub fn diff_scenes(old: &Scene, new: &Scene) -> Result<SceneDiff, String>
+{
+    let old = scene_value(old)?;
+    let new = scene_value(new)?;
+
+    let mut result = SceneDiff::default();
+
+    // The camera and other settings
+
+    let mut keys = old.keys().chain(new.keys())
+        .filter(|k| (k.as_str() != "version") && !COLLECTIONS.iter().any(|(c, _)| c == k))
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    for key in keys
+    {
+        let (changed, more_changed) = changed_paths(old.get(key).unwrap_or(&Value::Null), new.get(key).unwrap_or(&Value::Null));
+
+        if !changed.is_empty()
+        {
+            result.entries.push(DiffEntry { section: "Scene".to_owned(), label: key.clone(), change: DiffChange::Changed, old_index: None, new_index: None, changed, more_changed });
+        }
+    }
+
+    for (key, section) in COLLECTIONS.iter()
+    {
+        diff_collection(section, old.get(*key), new.get(*key), &mut result.entries);
+    }
+
+    Ok(result)
+}
+
+fn scene_value(scene: &Scene) -> Result<serde_json::Map<String, Value>, String>
+{
+    match serde_json::from_str(&scene_to_json(scene)?)
+    {
+        Ok(Value::Object(map)) => Ok(map),
+        _ => Err("Could not convert the scene to JSON".to_owned()),
+    }
+}
+
+/// Each entry, with the key it's matched by - its name, or
+/// if it has no name, its index
+fn collection_entries(list: Option<&Value>) -> Vec<(String, usize, &Value)>
+{
+    let list = list.and_then(|l| l.as_array()).map(|l| l.as_slice()).unwrap_or(&[]);
+
+    list.iter().enumerate()
+        .map(|(i, entry)|
+        {
+            let key = match entry.get("name").and_then(|n| n.as_str())
+            {
+                Some(name) => format!("{:?}", name),
+                None => format!("#{}", i),
+            };
+
+            (key, i, entry.get("value").unwrap_or(&Value::Null))
+        })
+        .collect()
+}
+
+fn diff_collection(section: &str, old: Option<&Value>, new: Option<&Value>, entries: &mut Vec<DiffEntry>)
+{
+    let old = collection_entries(old);
+    let new = collection_entries(new);
+
+    let entry = |label: &str, change, old_index, new_index, (changed, more_changed)| DiffEntry
+    {
+        section: section.to_owned(),
+        label: label.to_owned(),
+        change,
+        old_index,
+        new_index,
+        changed,
+        more_changed,
+    };
+
+    for (key, old_index, old_value) in old.iter()
+    {
+        match new.iter().find(|(k, _, _)| k == key)
+        {
+            Some((_, new_index, new_value)) =>
+            {
+                let (changed, more_changed) = changed_paths(old_value, new_value);
+
+                if !changed.is_empty() || (old_index != new_index)
+                {
+                    entries.push(entry(key, DiffChange::Changed, Some(*old_index), Some(*new_index), (changed, more_changed)));
+                }
+            },
+            None => entries.push(entry(key, DiffChange::Removed, Some(*old_index), None, (Vec::new(), 0))),
+        }
+    }
+
+    for (key, new_index, _) in new.iter()
+    {
+        if !old.iter().any(|(k, _, _)| k == key)
+        {
+            entries.push(entry(key, DiffChange::Added, None, Some(*new_index), (Vec::new(), 0)));
+        }
+    }
+}
+
+/// The paths to the values that differ, and how many weren't listed
+fn changed_paths(old: &Value, new: &Value) -> (Vec<String>, usize)
+{
+    let mut changed = Vec::new();
+    let mut more_changed = 0;
+
+    compare_values("", old, new, &mut changed, &mut more_changed);
+
+    (changed, more_changed)
+}
+
+fn compare_values(path: &str, old: &Value, new: &Value, changed: &mut Vec<String>, more_changed: &mut usize)
+{
+    match (old, new)
+    {
+        (Value::Object(old), Value::Object(new)) =>
+        {
+            let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys
+            {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+
+                compare_values(&child, old.get(key).unwrap_or(&Value::Null), new.get(key).unwrap_or(&Value::Null), changed, more_changed);
+            }
+        },
+        (Value::Array(old), Value::Array(new)) if old.len() == new.len() =>
+        {
+            for (i, (old, new)) in old.iter().zip(new.iter()).enumerate()
+            {
+                compare_values(&format!("{}[{}]", path, i), old, new, changed, more_changed);
+            }
+        },
+        _ if old != new =>
+        {
+            if changed.len() < MAX_CHANGED_PATHS
+            {
+                changed.push(if path.is_empty() { "value".to_owned() } else { path.to_owned() });
+            }
+            else
+            {
+                *more_changed += 1;
+            }
+        },
+        _ => {},
+    }
+}
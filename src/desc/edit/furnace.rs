@@ -0,0 +1,54 @@
+use crate::desc::edit::Color;
+use crate::indexed::ObjectIndex;
+use crate::math::Scalar;
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+
+#[derive(Clone, Debug)]
+pub struct Furnace
+{
+    pub object: ObjectIndex,
+    pub emission: Color,
+    pub radius: Scalar,
+}
+
+impl Default for Furnace
+{
+    fn default() -> Self
+    {
+        Furnace
+        {
+            object: ObjectIndex::default(),
+            emission: Color::default(),
+            radius: 100.0,
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for Furnace
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        self.object.ui_display(ui, "Object");
+        self.emission.ui_display(ui, "Emission");
+        ui.display_float("Radius", &self.radius);
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for Furnace
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+        result |= self.object.ui_edit(ui, "Object");
+        result |= self.emission.ui_edit(ui, "Emission");
+        result |= ui.edit_float("Radius", &mut self.radius);
+        result
+    }
+}
@@ -1,22 +1,30 @@
 use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
 
-use crate::geom::Surface;
+use crate::geom::{Density, GroundFade, MeshCache, OctreeQuality, SampleableSurface, Sdf, Surface};
 use crate::desc::edit::Color;
-use crate::indexed::{IndexedValue, GeomIndex, AnyIndex, IndexedCollection};
+use crate::indexed::{IndexedValue, GeomIndex, AnyIndex, IndexedCollection, IndexRemap};
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
-use crate::vec::{Dir3, Point3, Vec3};
+use crate::vec::{Dir3, Mat4, Point3, Vec3, Vec4};
 use crate::math::Scalar;
-use crate::desc::edit::Transform;
+use crate::desc::edit::{LodView, MeshLod, Transform};
+use crate::desc::edit::lod::triangle_bounds;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TriangleVertex
 {
     pub location: Point3,
     pub texture_coords: Point3,
     pub opt_color: Option<Color>,
+    /// For smooth shading - without one, the
+    /// triangle's own (flat) normal is used
+    #[serde(default)]
+    pub opt_normal: Option<Dir3>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Triangle
 {
     pub vertices: [TriangleVertex; 3],
@@ -33,6 +41,13 @@ impl Triangle
             opt_colors = Some([c1.into_linear(), c2.into_linear(), c3.into_linear()]);
         }
 
+        let mut opt_normals = None;
+
+        if let (Some(n1), Some(n2), Some(n3)) = (self.vertices[0].opt_normal, self.vertices[1].opt_normal, self.vertices[2].opt_normal)
+        {
+            opt_normals = Some([n1, n2, n3]);
+        }
+
         crate::geom::Triangle::new(
             self.vertices[0].location,
             self.vertices[1].location,
@@ -41,9 +56,39 @@ impl Triangle
             self.vertices[1].texture_coords,
             self.vertices[2].texture_coords,
             opt_colors)
+            .with_normals(opt_normals)
     }
 }
 
+/// Moves the vertices' locations, and turns their normals, by the matrix
+pub fn transform_triangles(triangles: &[Triangle], matrix: &Mat4) -> Vec<Triangle>
+{
+    let normal_matrix = matrix.inverted().transposed();
+
+    triangles.iter()
+        .map(|t|
+        {
+            let mut t = t.clone();
+            for v in t.vertices.iter_mut()
+            {
+                v.location = matrix.mul_point(v.location);
+                v.opt_normal = v.opt_normal.map(|n| (normal_matrix * Vec4::from_direction(n)).xyz().normalized());
+            }
+            t
+        })
+        .collect()
+}
+
+/// Tessellates the SDF, which must be bounded, with its
+/// triangles textured by their location
+pub fn sdf_triangles(sdf: &Sdf, resolution: usize) -> Vec<Triangle>
+{
+    sdf.tessellate(resolution)
+        .into_iter()
+        .map(|points| Triangle { vertices: points.map(|location| TriangleVertex { location, texture_coords: location, opt_color: None, opt_normal: None }) })
+        .collect()
+}
+
 impl Default for Triangle
 {
     fn default() -> Self
@@ -56,25 +101,28 @@ impl Default for Triangle
                     location: Point3::new(1.0, 0.0, 0.0),
                     texture_coords: Point3::new(1.0, 0.0, 0.0),
                     opt_color: None,
+                    opt_normal: None,
                 },
                 TriangleVertex
                 {
                     location: Point3::new(0.0, 1.0, 0.0),
                     texture_coords: Point3::new(0.0, 1.0, 0.0),
                     opt_color: None,
+                    opt_normal: None,
                 },
                 TriangleVertex
                 {
                     location: Point3::new(0.0, 0.0, 1.0),
                     texture_coords: Point3::new(0.0, 0.0, 0.0),
                     opt_color: None,
+                    opt_normal: None,
                 },
             ]
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Aabb
 {
     pub min: Point3,
@@ -89,48 +137,182 @@ impl Default for Aabb
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Geom
 {
     Sphere{center: Point3, radius: Scalar},
+    /// A corner, and the edges from it
+    Rectangle{point: Point3, u: Vec3, v: Vec3},
+    Disc{center: Point3, normal: Dir3, radius: Scalar},
     Plane{point: Point3, normal: Dir3},
+    Ground{point: Point3, normal: Dir3, uv_scale: Scalar, fade: Option<GroundFade>},
     Box{aabb: Aabb},
-    Triangle{triangle: Triangle},
-    Mesh{triangles: Vec<Triangle>, transform: Transform},
+    Triangle{triangle: Box<Triangle>},
+    Mesh{triangles: Vec<Triangle>, transform: Transform, lod: Option<MeshLod>},
+    Volume{aabb: Aabb, density: Density, scale: Scalar},
+    /// Ray marched, rather than tessellated
+    Sdf{sdf: Sdf, transform: Transform},
+}
+
+/// Shared state while building the geometry of a scene
+pub struct GeomBuildContext<'a>
+{
+    pub cache: &'a MeshCache,
+    /// Used to select mesh levels of detail - without
+    /// a view, the most detailed meshes are always used
+    pub view: Option<LodView>,
+    /// Bake mesh transforms into the triangles. Otherwise the
+    /// triangles (and their octree) stay in object space, so
+    /// editing the transform doesn't rebuild the octree.
+    pub bake_transforms: bool,
+    pub octree_quality: OctreeQuality,
+    /// Set when the build is cancelled, to stop building octrees
+    pub cancelled: Option<&'a AtomicBool>,
 }
 
 impl Geom
 {
-    pub fn build_surface(&self, collection: &IndexedCollection) -> Box<dyn Surface>
+    /// The object matrix is the transform that will be applied
+    /// to the surface, and is only used to select a level of detail
+    pub fn build_surface(&self, collection: &IndexedCollection, build: &GeomBuildContext, object_matrix: &Mat4) -> Box<dyn Surface>
     {
         match self
         {
             Geom::Sphere{center, radius} => Box::new(crate::geom::Sphere::new(*center, *radius)),
+            Geom::Rectangle{point, u, v} => Box::new(crate::geom::Rectangle::new(*point, *u, *v)),
+            Geom::Disc{center, normal, radius} => Box::new(crate::geom::Disc::new(*center, normal.normalized(), *radius)),
             Geom::Plane{point, normal} => Box::new(crate::geom::Plane::new(*point, *normal)),
+            Geom::Ground{point, normal, uv_scale, fade} => Box::new(crate::geom::GroundPlane::new(*point, *normal, *uv_scale, *fade)),
             Geom::Box{aabb} => Box::new(crate::geom::Aabb::new(aabb.min, aabb.max)),
             Geom::Triangle{triangle} => Box::new(triangle.build()),
-            Geom::Mesh{triangles, transform} =>
+            Geom::Mesh{triangles, transform, lod} =>
+            {
+                let matrix = transform.build_matrix(collection);
+
+                let selected = match (lod, &build.view)
+                {
+                    (Some(lod), Some(view)) => lod.select(triangles, view.projected_size(&triangle_bounds(triangles, &(*object_matrix * matrix)))),
+                    _ => triangles,
+                };
+
+                if build.bake_transforms || (matrix == Mat4::identity())
+                {
+                    return Box::new(build.cache.get_or_build(
+                        selected.iter()
+                        .map(|t| t.build().transformed(&matrix)).collect(),
+                        build.octree_quality, build.cancelled));
+                }
+
+                Box::new(crate::geom::Transformed::new(
+                    Box::new(build.cache.get_or_build(selected.iter().map(|t| t.build()).collect(), build.octree_quality, build.cancelled)),
+                    matrix))
+            },
+            Geom::Volume{aabb, density, scale} => Box::new(crate::geom::Medium::new(crate::geom::Aabb::new(aabb.min, aabb.max), density.clone(), *scale)),
+            Geom::Sdf{sdf, transform} =>
             {
                 let matrix = transform.build_matrix(collection);
-                Box::new(crate::geom::Mesh::new(
-                    triangles.iter()
-                    .map(|t| t.build().transformed(&matrix)).collect()))
+
+                if matrix == Mat4::identity()
+                {
+                    return Box::new(sdf.clone());
+                }
+
+                Box::new(crate::geom::Transformed::new(Box::new(sdf.clone()), matrix))
             },
         }
     }
 
+    /// Bounds of the geometry in world space, or none
+    /// for unbounded geometry such as planes
+    pub fn world_bounds(&self, collection: &IndexedCollection) -> Option<crate::geom::Aabb>
+    {
+        match self
+        {
+            Geom::Sphere{center, radius} =>
+            {
+                let r = Vec3::new(*radius, *radius, *radius);
+                Some(crate::geom::Aabb::new(*center - r, *center + r))
+            },
+            Geom::Rectangle{point, u, v} => crate::geom::Rectangle::new(*point, *u, *v).bounding_aabb(),
+            Geom::Disc{center, normal, radius} => crate::geom::Disc::new(*center, normal.normalized(), *radius).bounding_aabb(),
+            Geom::Plane{..} | Geom::Ground{..} => None,
+            Geom::Box{aabb} | Geom::Volume{aabb, ..} => Some(crate::geom::Aabb::new(aabb.min, aabb.max)),
+            Geom::Triangle{..} | Geom::Mesh{..} => Some(triangle_bounds(&self.world_triangles(collection), &Mat4::identity())),
+            Geom::Sdf{sdf, transform} =>
+            {
+                let sdf = Sdf::transformed(sdf.clone(), transform.build_matrix(collection));
+
+                if sdf.is_bounded() { Some(sdf.bounds()) } else { None }
+            },
+        }
+    }
+
+    /// Returns the triangles (in world space) that make up
+    /// geometry with a UV layout. Analytic shapes have no
+    /// UV layout, so return no triangles.
+    pub fn world_triangles(&self, collection: &IndexedCollection) -> Vec<Triangle>
+    {
+        match self
+        {
+            Geom::Sphere{..} | Geom::Rectangle{..} | Geom::Disc{..} | Geom::Plane{..} | Geom::Ground{..} | Geom::Box{..} | Geom::Volume{..} | Geom::Sdf{..} => Vec::new(),
+            Geom::Triangle{triangle} => vec![triangle.as_ref().clone()],
+            Geom::Mesh{triangles, transform, ..} => transform_triangles(triangles, &transform.build_matrix(collection)),
+        }
+    }
+
+    /// The geometry as a light to sample, in world space once placed
+    /// by the matrix, along with its center. Only spheres, rectangles and
+    /// discs can be sampled. A non-uniform scale isn't followed exactly,
+    /// which only makes the sampling less efficient.
+    pub fn light_surface(&self, matrix: &Mat4) -> Option<(Box<dyn SampleableSurface>, Point3)>
+    {
+        let scale = matrix.determinant().abs().cbrt();
+
+        match self
+        {
+            Geom::Sphere{center, radius} =>
+            {
+                let center = matrix.mul_point(*center);
+
+                Some((Box::new(crate::geom::Sphere::new(center, *radius * scale)), center))
+            },
+            Geom::Rectangle{point, u, v} =>
+            {
+                let point = matrix.mul_point(*point);
+                let u = matrix.mul_direction(*u);
+                let v = matrix.mul_direction(*v);
+
+                Some((Box::new(crate::geom::Rectangle::new(point, u, v)), point + 0.5 * (u + v)))
+            },
+            Geom::Disc{center, normal, radius} =>
+            {
+                let center = matrix.mul_point(*center);
+                let normal = (matrix.inverted().transposed() * Vec4::from_direction(*normal)).xyz().normalized();
+
+                Some((Box::new(crate::geom::Disc::new(center, normal, *radius * scale)), center))
+            },
+            _ => None,
+        }
+    }
+
     fn ui_tag(&self) -> &'static str
     {
         match self
         {
             Geom::Sphere{..} => "Sphere",
+            Geom::Rectangle{..} => "Rectangle",
+            Geom::Disc{..} => "Disc",
             Geom::Plane{..} => "Plane",
+            Geom::Ground{..} => "Ground",
             Geom::Box{..} => "Box",
             Geom::Triangle{..} => "Triangle",
             Geom::Mesh{..} => "Mesh",
+            Geom::Volume{..} => "Volume",
+            Geom::Sdf{..} => "SDF",
         }
     }
 
+    #[cfg(feature = "ui")]
     fn ui_render_combo(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
         let mut result = false;
@@ -139,10 +321,15 @@ impl Geom
         {
             for entry in [
                 Geom::Sphere{center: Point3::new(0.0, 0.0, 0.0), radius: 0.0},
+                Geom::Rectangle{point: Point3::new(0.0, 0.0, 0.0), u: Vec3::new(1.0, 0.0, 0.0), v: Vec3::new(0.0, 0.0, 1.0)},
+                Geom::Disc{center: Point3::new(0.0, 0.0, 0.0), normal: Dir3::new(0.0, 1.0, 0.0), radius: 1.0},
                 Geom::Plane{point: Point3::new(0.0, 0.0, 0.0), normal: Dir3::new(0.0, 1.0, 0.0)},
+                Geom::Ground{point: Point3::new(0.0, 0.0, 0.0), normal: Dir3::new(0.0, 1.0, 0.0), uv_scale: 1.0, fade: None},
                 Geom::Box{aabb: Aabb::default() },
-                Geom::Triangle{triangle: Triangle::default()},
-                Geom::Mesh{triangles: vec![Triangle::default()], transform: Transform::new()},
+                Geom::Triangle{triangle: Box::default()},
+                Geom::Mesh{triangles: vec![Triangle::default()], transform: Transform::new(), lod: None},
+                Geom::Volume{aabb: Aabb::default(), density: Density::Noise{ frequency: 4.0, octaves: 4 }, scale: 1.0},
+                Geom::Sdf{sdf: Sdf::Sphere{ center: Point3::new(0.0, 0.0, 0.0), radius: 1.0 }, transform: Transform::new()},
             ]
             {
                 let entry_tag = entry.ui_tag();
@@ -176,8 +363,20 @@ impl IndexedValue for Geom
 {
     type Index = GeomIndex;
     
-    fn collect_indexes(&self, _indexes: &mut HashSet<AnyIndex>)
+    fn collect_indexes(&self, indexes: &mut HashSet<AnyIndex>)
+    {
+        if let Geom::Mesh{ transform, .. } | Geom::Sdf{ transform, .. } = self
+        {
+            transform.collect_transform_indexes(indexes);
+        }
+    }
+
+    fn remap_indexes(&mut self, remap: &IndexRemap)
     {
+        if let Geom::Mesh{ transform, .. } | Geom::Sdf{ transform, .. } = self
+        {
+            transform.remap_transform_indexes(remap);
+        }
     }
 
     fn summary(&self) -> String
@@ -186,6 +385,7 @@ impl IndexedValue for Geom
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Geom
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -198,12 +398,39 @@ impl UiDisplay for Geom
                 ui.display_vec3("Center", center);
                 ui.display_float("Radius", radius);
             },
+            Geom::Rectangle{point, u, v} =>
+            {
+                ui.imgui.label_text(label, "Rectangle");
+                ui.display_vec3("Point", point);
+                ui.display_vec3("U", u);
+                ui.display_vec3("V", v);
+            },
+            Geom::Disc{center, normal, radius} =>
+            {
+                ui.imgui.label_text(label, "Disc");
+                ui.display_vec3("Center", center);
+                ui.display_vec3("Normal", normal);
+                ui.display_float("Radius", radius);
+            },
             Geom::Plane{point, normal} =>
             {
                 ui.imgui.label_text(label, "Plane");
                 ui.display_vec3("Point", point);
                 ui.display_vec3("Normal", normal);
             },
+            Geom::Ground{point, normal, uv_scale, fade} =>
+            {
+                ui.imgui.label_text(label, "Ground");
+                ui.display_vec3("Point", point);
+                ui.display_vec3("Normal", normal);
+                ui.display_float("UV Scale", uv_scale);
+
+                if let Some(fade) = fade
+                {
+                    ui.display_float("Fade Start", &fade.start);
+                    ui.display_float("Fade End", &fade.end);
+                }
+            },
             Geom::Box{aabb} =>
             {
                 ui.imgui.label_text(label, "Box");
@@ -220,16 +447,45 @@ impl UiDisplay for Geom
                 ui.display_vec3("T2", &triangle.vertices[1].texture_coords);
                 ui.display_vec3("T3", &triangle.vertices[2].texture_coords);
             },
-            Geom::Mesh{ triangles, transform } =>
+            Geom::Mesh{ triangles, transform, lod } =>
             {
                 ui.imgui.label_text(label, "Mesh");
                 ui.imgui.label_text("Triangles", triangles.len().to_string());
+                ui.imgui.label_text("LOD Levels", lod.as_ref().map(|l| l.levels.len()).unwrap_or(0).to_string());
+                transform.ui_display(ui, "Transform");
+            },
+            Geom::Volume{ aabb, density, scale } =>
+            {
+                ui.imgui.label_text(label, "Volume");
+                ui.display_vec3("Min", &aabb.min);
+                ui.display_vec3("Max", &aabb.max);
+
+                match density
+                {
+                    Density::Grid{ resolution, .. } =>
+                    {
+                        ui.imgui.label_text("Grid", format!("{} x {} x {}", resolution[0], resolution[1], resolution[2]));
+                    },
+                    Density::Noise{ frequency, octaves } =>
+                    {
+                        ui.display_float("Noise Frequency", frequency);
+                        ui.imgui.label_text("Noise Octaves", octaves.to_string());
+                    },
+                }
+
+                ui.display_float("Scale", scale);
+            },
+            Geom::Sdf{ sdf, transform } =>
+            {
+                ui.imgui.label_text(label, "SDF");
+                display_sdf_bounds(ui, sdf);
                 transform.ui_display(ui, "Transform");
             },
         }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Geom
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
@@ -244,11 +500,43 @@ impl UiEdit for Geom
                 result |= ui.edit_vec3("Center", center);
                 result |= ui.edit_float("Radius", radius);
             },
+            Geom::Rectangle{point, u, v} =>
+            {
+                result |= ui.edit_vec3("Point", point);
+                result |= ui.edit_vec3("U", u);
+                result |= ui.edit_vec3("V", v);
+            },
+            Geom::Disc{center, normal, radius} =>
+            {
+                result |= ui.edit_vec3("Center", center);
+                result |= ui.edit_vec3("Normal", normal);
+                result |= ui.edit_float("Radius", radius);
+            },
             Geom::Plane{point, normal} =>
             {
                 result |= ui.edit_vec3("Point", point);
                 result |= ui.edit_vec3("Normal", normal);
             },
+            Geom::Ground{point, normal, uv_scale, fade} =>
+            {
+                result |= ui.edit_vec3("Point", point);
+                result |= ui.edit_vec3("Normal", normal);
+                result |= ui.edit_float("UV Scale", uv_scale);
+
+                let mut faded = fade.is_some();
+
+                if ui.imgui.checkbox("Fade", &mut faded)
+                {
+                    *fade = if faded { Some(GroundFade { start: 10.0, end: 20.0 }) } else { None };
+                    result = true;
+                }
+
+                if let Some(fade) = fade
+                {
+                    result |= ui.edit_float("Fade Start", &mut fade.start);
+                    result |= ui.edit_float("Fade End", &mut fade.end);
+                }
+            },
             Geom::Box{aabb} =>
             {
                 result |= ui.edit_vec3("Min", &mut aabb.min);
@@ -263,9 +551,43 @@ impl UiEdit for Geom
                 result |= ui.edit_vec3("T2", &mut triangle.vertices[1].texture_coords);
                 result |= ui.edit_vec3("T3", &mut triangle.vertices[2].texture_coords);
             },
-            Geom::Mesh{ triangles, transform } =>
+            Geom::Mesh{ triangles, transform, lod } =>
             {
                 ui.imgui.label_text("Triangles", triangles.len().to_string());
+
+                if let Some(lod) = lod
+                {
+                    ui.imgui.label_text("LOD Levels", lod.levels.len().to_string());
+                    result |= ui.edit_float("LOD Screen Size", &mut lod.screen_size);
+                }
+
+                result |= transform.ui_edit(ui, "Transform");
+            },
+            Geom::Volume{ aabb, density, scale } =>
+            {
+                result |= ui.edit_vec3("Min", &mut aabb.min);
+                result |= ui.edit_vec3("Max", &mut aabb.max);
+
+                match density
+                {
+                    Density::Grid{ resolution, .. } =>
+                    {
+                        ui.imgui.label_text("Grid", format!("{} x {} x {}", resolution[0], resolution[1], resolution[2]));
+                    },
+                    Density::Noise{ frequency, octaves } =>
+                    {
+                        result |= ui.edit_float("Noise Frequency", frequency);
+                        ui.imgui.label_text("Noise Octaves", octaves.to_string());
+                    },
+                }
+
+                result |= ui.edit_float("Scale", scale);
+            },
+            Geom::Sdf{ sdf, transform } =>
+            {
+                // The field itself can only be changed from a script
+
+                display_sdf_bounds(ui, sdf);
                 result |= transform.ui_edit(ui, "Transform");
             },
         }
@@ -273,4 +595,19 @@ impl UiEdit for Geom
         ui.imgui.unindent();
         result
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "ui")]
+fn display_sdf_bounds(ui: &UiRenderer, sdf: &Sdf)
+{
+    if sdf.is_bounded()
+    {
+        let bounds = sdf.bounds();
+        ui.display_vec3("Bounds Min", &bounds.min);
+        ui.display_vec3("Bounds Max", &bounds.max);
+    }
+    else
+    {
+        ui.imgui.label_text("Bounds", "Unbounded");
+    }
+}
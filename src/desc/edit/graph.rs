@@ -0,0 +1,414 @@
+use crate::color::LinearRGB;
+use crate::desc::edit::Color;
+use crate::indexed::{IndexedCollection, TextureIndex};
+use crate::math::Scalar;
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ui")]
+const NODE_WIDTH: f32 = 200.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum GraphNodeKind
+{
+    Texture{ texture: TextureIndex },
+    Solid{ color: Color },
+    Checkerboard{ color1: Color, color2: Color },
+    Blackbody{ temperature: Scalar, intensity: Scalar },
+    Dielectric{ ior: Scalar },
+    Diffuse{ texture: Option<usize> },
+    Emit{ texture: Option<usize> },
+    Metal{ texture: Option<usize>, fuzz: Scalar },
+}
+
+impl GraphNodeKind
+{
+    #[cfg(feature = "ui")]
+    fn all_defaults() -> Vec<GraphNodeKind>
+    {
+        vec![
+            GraphNodeKind::Texture{ texture: TextureIndex::default() },
+            GraphNodeKind::Solid{ color: Color::default() },
+            GraphNodeKind::Checkerboard{ color1: LinearRGB::black().into(), color2: LinearRGB::white().into() },
+            GraphNodeKind::Blackbody{ temperature: 1800.0, intensity: 1.0 },
+            GraphNodeKind::Dielectric{ ior: 1.5 },
+            GraphNodeKind::Diffuse{ texture: None },
+            GraphNodeKind::Emit{ texture: None },
+            GraphNodeKind::Metal{ texture: None, fuzz: 0.0 },
+        ]
+    }
+
+    #[cfg(feature = "ui")]
+    fn ui_tag(&self) -> &'static str
+    {
+        match self
+        {
+            GraphNodeKind::Texture{..} => "Texture",
+            GraphNodeKind::Solid{..} => "Solid",
+            GraphNodeKind::Checkerboard{..} => "Checkerboard",
+            GraphNodeKind::Blackbody{..} => "Blackbody",
+            GraphNodeKind::Dielectric{..} => "Dielectric",
+            GraphNodeKind::Diffuse{..} => "Diffuse",
+            GraphNodeKind::Emit{..} => "Emit",
+            GraphNodeKind::Metal{..} => "Metal",
+        }
+    }
+
+    pub fn is_texture(&self) -> bool
+    {
+        matches!(self, GraphNodeKind::Texture{..} | GraphNodeKind::Solid{..} | GraphNodeKind::Checkerboard{..} | GraphNodeKind::Blackbody{..})
+    }
+
+    pub fn is_material(&self) -> bool
+    {
+        !self.is_texture()
+    }
+
+    #[cfg(feature = "ui")]
+    fn num_rows(&self) -> usize
+    {
+        match self
+        {
+            GraphNodeKind::Checkerboard{..} | GraphNodeKind::Blackbody{..} | GraphNodeKind::Metal{..} => 2,
+            _ => 1,
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    fn input(&self) -> Option<usize>
+    {
+        match self
+        {
+            GraphNodeKind::Diffuse{texture}
+                | GraphNodeKind::Emit{texture}
+                | GraphNodeKind::Metal{texture, ..} => *texture,
+            _ => None,
+        }
+    }
+
+    fn input_mut(&mut self) -> Option<&mut Option<usize>>
+    {
+        match self
+        {
+            GraphNodeKind::Diffuse{texture}
+                | GraphNodeKind::Emit{texture}
+                | GraphNodeKind::Metal{texture, ..} => Some(texture),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GraphNode
+{
+    pub kind: GraphNodeKind,
+    pub position: [f32; 2],
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaterialGraph
+{
+    pub nodes: Vec<GraphNode>,
+    pub output: Option<usize>,
+}
+
+impl MaterialGraph
+{
+    pub fn new() -> Self
+    {
+        MaterialGraph
+        {
+            nodes: Vec::new(),
+            output: None,
+        }
+    }
+
+    pub fn add_node(&mut self, kind: GraphNodeKind, position: [f32; 2]) -> usize
+    {
+        self.nodes.push(GraphNode { kind, position });
+        self.nodes.len() - 1
+    }
+
+    pub fn remove_node(&mut self, index: usize)
+    {
+        self.nodes.remove(index);
+
+        let fixup = |link: &mut Option<usize>|
+        {
+            *link = match *link
+            {
+                Some(i) if i == index => None,
+                Some(i) if i > index => Some(i - 1),
+                other => other,
+            };
+        };
+
+        fixup(&mut self.output);
+
+        for node in self.nodes.iter_mut()
+        {
+            if let Some(input) = node.kind.input_mut()
+            {
+                fixup(input);
+            }
+        }
+    }
+
+    pub fn build(&self, collection: &IndexedCollection) -> crate::material::Material
+    {
+        self.output
+            .and_then(|i| self.build_material(i, collection))
+            .unwrap_or_else(|| crate::material::Material::Diffuse(crate::texture::Texture::Solid(LinearRGB::grey(0.5))))
+    }
+
+    fn build_material(&self, index: usize, collection: &IndexedCollection) -> Option<crate::material::Material>
+    {
+        let texture = |input: &Option<usize>|
+        {
+            input
+                .and_then(|i| self.build_texture(i, collection))
+                .unwrap_or_else(|| crate::texture::Texture::Solid(LinearRGB::white()))
+        };
+
+        match &self.nodes.get(index)?.kind
+        {
+            GraphNodeKind::Dielectric{ior} => Some(crate::material::Material::Dielectric(*ior, None)),
+            GraphNodeKind::Diffuse{texture: input} => Some(crate::material::Material::Diffuse(texture(input))),
+            GraphNodeKind::Emit{texture: input} => Some(crate::material::Material::Emit(texture(input))),
+            GraphNodeKind::Metal{texture: input, fuzz} => Some(crate::material::Material::Metal(texture(input), *fuzz)),
+            _ => None,
+        }
+    }
+
+    fn build_texture(&self, index: usize, collection: &IndexedCollection) -> Option<crate::texture::Texture>
+    {
+        match &self.nodes.get(index)?.kind
+        {
+            GraphNodeKind::Texture{texture} => Some(collection.map_item(*texture, |texture, collection| texture.build(collection))),
+            GraphNodeKind::Solid{color} => Some(crate::texture::Texture::Solid(color.into_linear())),
+            GraphNodeKind::Checkerboard{color1, color2} => Some(crate::texture::Texture::Checkerboard(color1.into_linear(), color2.into_linear())),
+            GraphNodeKind::Blackbody{temperature, intensity} => Some(crate::texture::Texture::Solid(crate::color::blackbody(*temperature).multiplied_by_scalar(*intensity))),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    fn ui_edit_link(ui: &UiRenderer, label: &str, link: &mut Option<usize>, choices: &[(usize, String)]) -> bool
+    {
+        let mut result = false;
+        let cur = link
+            .and_then(|l| choices.iter().find(|(i, _)| *i == l))
+            .map(|(_, s)| s.clone())
+            .unwrap_or_else(|| "<None>".to_string());
+
+        if let Some(_combo) = ui.imgui.begin_combo(label, &cur)
+        {
+            if ui.imgui.selectable_config("<None>").selected(link.is_none()).build()
+            {
+                *link = None;
+                result = true;
+            }
+
+            for (i, name) in choices.iter()
+            {
+                if ui.imgui.selectable_config(name).selected(*link == Some(*i)).build()
+                {
+                    *link = Some(*i);
+                    result = true;
+                }
+            }
+        }
+
+        result
+    }
+
+    #[cfg(feature = "ui")]
+    fn choices<F: Fn(&GraphNodeKind) -> bool>(&self, filter: F) -> Vec<(usize, String)>
+    {
+        self.nodes.iter()
+            .enumerate()
+            .filter(|(_, n)| filter(&n.kind))
+            .map(|(i, n)| (i, format!("{}: {}", i, n.kind.ui_tag())))
+            .collect()
+    }
+}
+
+impl Default for MaterialGraph
+{
+    fn default() -> Self
+    {
+        let mut graph = MaterialGraph::new();
+        let texture = graph.add_node(GraphNodeKind::Solid{ color: Color::default() }, [10.0, 10.0]);
+        let material = graph.add_node(GraphNodeKind::Diffuse{ texture: Some(texture) }, [260.0, 10.0]);
+        graph.output = Some(material);
+        graph
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for MaterialGraph
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        ui.imgui.label_text("Output", self.output.map(|o| o.to_string()).unwrap_or_else(|| "<None>".to_string()));
+
+        for (i, node) in self.nodes.iter().enumerate()
+        {
+            match node.kind.input()
+            {
+                Some(input) => ui.imgui.label_text(i.to_string(), format!("{} <- {}", node.kind.ui_tag(), input)),
+                None => ui.imgui.label_text(i.to_string(), node.kind.ui_tag()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for MaterialGraph
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+
+        let material_choices = self.choices(GraphNodeKind::is_material);
+        let texture_choices = self.choices(GraphNodeKind::is_texture);
+
+        result |= Self::ui_edit_link(ui, "Output", &mut self.output, &material_choices);
+
+        if let Some(_combo) = ui.imgui.begin_combo("Add Node", "")
+        {
+            for kind in GraphNodeKind::all_defaults()
+            {
+                if ui.imgui.selectable(kind.ui_tag())
+                {
+                    let offset = 20.0 * (self.nodes.len() % 10) as f32;
+                    self.add_node(kind, [10.0 + offset, 10.0 + offset]);
+                    result = true;
+                }
+            }
+        }
+
+        if let Some(_canvas) = ui.imgui.child_window("Canvas")
+            .size([0.0, 300.0])
+            .border(true)
+            .begin()
+        {
+            let origin = ui.imgui.cursor_screen_pos();
+            let row_height = ui.imgui.frame_height_with_spacing();
+            let draw_list = ui.imgui.get_window_draw_list();
+
+            // Links are drawn first so that they
+            // appear underneath the nodes
+
+            for node in self.nodes.iter()
+            {
+                if let Some(src) = node.kind.input().and_then(|i| self.nodes.get(i))
+                {
+                    let from = [origin[0] + src.position[0] + NODE_WIDTH, origin[1] + src.position[1] + 0.5 * row_height];
+                    let to = [origin[0] + node.position[0], origin[1] + node.position[1] + 1.5 * row_height];
+
+                    draw_list.add_bezier_curve(
+                        from,
+                        [from[0] + 50.0, from[1]],
+                        [to[0] - 50.0, to[1]],
+                        to,
+                        [0.8, 0.8, 0.3, 1.0])
+                        .thickness(2.0)
+                        .build();
+                }
+            }
+
+            let mut to_remove = None;
+
+            for (i, node) in self.nodes.iter_mut().enumerate()
+            {
+                let _id = ui.imgui.push_id_usize(i);
+
+                let pos = [origin[0] + node.position[0], origin[1] + node.position[1]];
+                let height = row_height * (1 + node.kind.num_rows()) as f32;
+                let header_color = if Some(i) == self.output { [0.6, 0.3, 0.1, 1.0] } else { [0.2, 0.3, 0.5, 1.0] };
+
+                draw_list.add_rect(pos, [pos[0] + NODE_WIDTH, pos[1] + height], [0.15, 0.15, 0.15, 1.0])
+                    .filled(true)
+                    .rounding(4.0)
+                    .build();
+                draw_list.add_rect(pos, [pos[0] + NODE_WIDTH, pos[1] + row_height], header_color)
+                    .filled(true)
+                    .rounding(4.0)
+                    .build();
+                draw_list.add_text([pos[0] + 4.0, pos[1] + 2.0], [1.0, 1.0, 1.0, 1.0], format!("{}: {}", i, node.kind.ui_tag()));
+
+                ui.imgui.set_cursor_screen_pos(pos);
+                ui.imgui.invisible_button("Drag", [NODE_WIDTH - row_height, row_height]);
+                if ui.imgui.is_item_active()
+                    && ui.imgui.is_mouse_dragging(imgui::MouseButton::Left)
+                {
+                    let delta = ui.imgui.io().mouse_delta;
+                    node.position[0] = (node.position[0] + delta[0]).max(0.0);
+                    node.position[1] = (node.position[1] + delta[1]).max(0.0);
+                }
+
+                ui.imgui.set_cursor_screen_pos([pos[0] + NODE_WIDTH - row_height, pos[1]]);
+                if ui.imgui.small_button("X")
+                {
+                    to_remove = Some(i);
+                }
+
+                ui.imgui.set_cursor_screen_pos([pos[0] + 4.0, pos[1] + row_height]);
+                let _width = ui.imgui.push_item_width(0.6 * NODE_WIDTH);
+
+                match &mut node.kind
+                {
+                    GraphNodeKind::Texture{ texture } =>
+                    {
+                        result |= texture.ui_edit(ui, "Index");
+                    },
+                    GraphNodeKind::Solid{ color } =>
+                    {
+                        result |= color.ui_edit(ui, "Color");
+                    },
+                    GraphNodeKind::Checkerboard{ color1, color2 } =>
+                    {
+                        result |= color1.ui_edit(ui, "Color 1");
+                        ui.imgui.set_cursor_screen_pos([pos[0] + 4.0, pos[1] + 2.0 * row_height]);
+                        result |= color2.ui_edit(ui, "Color 2");
+                    },
+                    GraphNodeKind::Blackbody{ temperature, intensity } =>
+                    {
+                        result |= ui.edit_float("Kelvin", temperature);
+                        ui.imgui.set_cursor_screen_pos([pos[0] + 4.0, pos[1] + 2.0 * row_height]);
+                        result |= ui.edit_float("Intensity", intensity);
+                    },
+                    GraphNodeKind::Dielectric{ ior } =>
+                    {
+                        result |= ui.edit_float("IOR", ior);
+                    },
+                    GraphNodeKind::Diffuse{ texture }
+                        | GraphNodeKind::Emit{ texture } =>
+                    {
+                        result |= Self::ui_edit_link(ui, "Texture", texture, &texture_choices);
+                    },
+                    GraphNodeKind::Metal{ texture, fuzz } =>
+                    {
+                        result |= Self::ui_edit_link(ui, "Texture", texture, &texture_choices);
+                        ui.imgui.set_cursor_screen_pos([pos[0] + 4.0, pos[1] + 2.0 * row_height]);
+                        result |= ui.edit_float("Fuzz", fuzz);
+                    },
+                }
+            }
+
+            if let Some(i) = to_remove
+            {
+                self.remove_node(i);
+                result = true;
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use crate::desc::edit::{GeomBuildContext, ObjectBuildProgress, Transform};
+use crate::indexed::{AnyIndex, GroupIndex, Index, IndexedCollection, IndexedValue, IndexRemap, ObjectIndex};
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::Mat4;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Group
+{
+    pub objects: Vec<ObjectIndex>,
+    pub groups: Vec<GroupIndex>,
+    pub transform: Transform,
+}
+
+impl Group
+{
+    /// Returns false if the build was cancelled
+    pub fn build(&self, collection: &IndexedCollection, parent_matrix: &Mat4, visiting: &mut Vec<GroupIndex>, objects: &mut Vec<crate::object::Object>, progress: &mut ObjectBuildProgress, build: &GeomBuildContext) -> bool
+    {
+        let matrix = *parent_matrix * self.transform.build_matrix(collection);
+
+        for object in self.objects.iter()
+        {
+            let label = collection.name_of(*object).unwrap_or_else(|| format!("Object {}", object.to_usize()));
+
+            objects.push(collection.map_item(*object, |object, collection| object.build_transformed(collection, &matrix, build)).with_label(label));
+
+            if !progress.object_built()
+            {
+                return false;
+            }
+        }
+
+        for group in self.groups.iter()
+        {
+            // Ignore any cycles
+
+            if !visiting.contains(group)
+            {
+                visiting.push(*group);
+
+                if !collection.map_item(*group, |group, collection| group.build(collection, &matrix, visiting, objects, progress, build))
+                {
+                    return false;
+                }
+
+                visiting.pop();
+            }
+        }
+
+        true
+    }
+}
+
+impl IndexedValue for Group
+{
+    type Index = GroupIndex;
+
+    fn collect_indexes(&self, indexes: &mut HashSet<AnyIndex>)
+    {
+        indexes.extend(self.objects.iter().map(|o| AnyIndex::Object(*o)));
+        indexes.extend(self.groups.iter().map(|g| AnyIndex::Group(*g)));
+        self.transform.collect_transform_indexes(indexes);
+    }
+
+    fn remap_indexes(&mut self, remap: &IndexRemap)
+    {
+        self.objects.iter_mut().for_each(|o| remap.apply(o));
+        self.groups.iter_mut().for_each(|g| remap.apply(g));
+        self.transform.remap_transform_indexes(remap);
+    }
+
+    fn summary(&self) -> String
+    {
+        format!("Group ({} objects, {} groups)", self.objects.len(), self.groups.len())
+    }
+}
+
+#[cfg(feature = "ui")]
+fn ui_edit_children<I: crate::indexed::Index>(ui: &UiRenderer, label: &str, children: &mut Vec<I>) -> bool
+{
+    let _id = ui.imgui.push_id(label);
+
+    let mut result = false;
+    let mut to_remove = None;
+
+    ui.imgui.text(label);
+    ui.imgui.indent();
+
+    for (i, child) in children.iter_mut().enumerate()
+    {
+        let _child_id = ui.imgui.push_id_usize(i);
+
+        result |= child.ui_edit(ui, &i.to_string());
+        ui.imgui.same_line();
+        if ui.imgui.small_button("Remove")
+        {
+            to_remove = Some(i);
+        }
+    }
+
+    if let Some(i) = to_remove
+    {
+        children.remove(i);
+        result = true;
+    }
+
+    if ui.imgui.small_button("Add")
+    {
+        children.push(I::default());
+        result = true;
+    }
+
+    ui.imgui.unindent();
+    result
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for Group
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        ui.imgui.label_text("Objects", self.objects.iter().map(|o| format!("{:?}", o)).collect::<Vec<_>>().join(", "));
+        ui.imgui.label_text("Groups", self.groups.iter().map(|g| format!("{:?}", g)).collect::<Vec<_>>().join(", "));
+        self.transform.ui_display(ui, "Transform");
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for Group
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+        result |= ui_edit_children(ui, "Objects", &mut self.objects);
+        result |= ui_edit_children(ui, "Groups", &mut self.groups);
+        result |= self.transform.ui_edit(ui, "Transform");
+        result
+    }
+}
@@ -0,0 +1,121 @@
+use serde_json::{Map, Value};
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Upgrades a file by one version - the first entry upgrades
+/// version 1 to 2, and so on. When the format changes, including
+/// when a field is added, add a step here (which bumps the current
+/// version), and a fixture saved in the new version to the tests.
+pub const MIGRATIONS: &[Migration] = &[
+    colors_as_arrays,
+    image_paths,
+];
+
+/// Upgrades a file that was saved in the given version to the current
+/// version, so only the current version needs to be deserialized.
+pub fn migrate(value: &mut Value, version: u32) -> Result<(), String>
+{
+    if version == 0
+    {
+        return Err("Scene JSON version 0 is not valid".to_owned());
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip((version - 1) as usize)
+    {
+        migration(value)
+            .map_err(|e| format!("Could not upgrade scene JSON from version {}: {}", index + 1, e))?;
+
+        set_version(value, (index as u32) + 2)?;
+    }
+
+    Ok(())
+}
+
+fn set_version(value: &mut Value, version: u32) -> Result<(), String>
+{
+    match value
+    {
+        Value::Object(file) =>
+        {
+            file.insert("version".to_owned(), Value::from(version));
+            Ok(())
+        },
+        _ => Err("Scene JSON is not an object".to_owned()),
+    }
+}
+
+/// Version 2 - colors changed from { "linear": { "r", "g", "b", "a" } }
+/// to a [r, g, b, a] array. Nothing else has a "linear" field, so
+/// they can be found anywhere in the file.
+fn colors_as_arrays(value: &mut Value) -> Result<(), String>
+{
+    match value
+    {
+        Value::Object(map) =>
+        {
+            if let Some(array) = color_as_array(map)
+            {
+                *value = array;
+                return Ok(());
+            }
+
+            for child in map.values_mut()
+            {
+                colors_as_arrays(child)?;
+            }
+        },
+        Value::Array(array) =>
+        {
+            for child in array.iter_mut()
+            {
+                colors_as_arrays(child)?;
+            }
+        },
+        _ => {},
+    }
+
+    Ok(())
+}
+
+fn color_as_array(map: &Map<String, Value>) -> Option<Value>
+{
+    if map.len() != 1
+    {
+        return None;
+    }
+
+    let linear = map.get("linear")?.as_object()?;
+
+    let components = ["r", "g", "b", "a"].iter()
+        .map(|c| linear.get(*c).filter(|v| v.is_number()).cloned())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Value::Array(components))
+}
+
+/// Version 3 - images are re-loaded from a "path", rather than
+/// their name. Earlier versions only had the name to go on. It
+/// also added the atmosphere, lighting region, object opacity and
+/// dielectric tint, which are optional so need no changes.
+fn image_paths(value: &mut Value) -> Result<(), String>
+{
+    let images = match value.get_mut("images")
+    {
+        Some(Value::Array(images)) => images,
+        Some(_) => return Err("Images are not a list".to_owned()),
+        None => return Ok(()),
+    };
+
+    for image in images.iter_mut()
+    {
+        let name = image.get("name").cloned().unwrap_or(Value::Null);
+
+        match image.get_mut("value")
+        {
+            Some(Value::Object(value)) => { value.insert("path".to_owned(), name); },
+            _ => return Err("Image has no value".to_owned()),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+mod migrate;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::desc::edit::{Atmosphere, Camera, ContactSheet, Geom, Group, LightingRegion, Material, MaterialVariant, Object, Probe, Scene, Texture, Transform, VariantSet};
+use crate::import::FileSystemContext;
+use crate::import::image::{import_image, ColorSpace, Image};
+use crate::indexed::{ImageIndex, IndexedCollection, IndexedValue};
+
+/// The version written into saved files - one more than the
+/// number of migrations. Files from a later version are
+/// rejected, as they may describe things that this
+/// version would silently drop. So adding a field, even an
+/// optional one, needs a new version - with a migration
+/// that does nothing, if older files need no changes.
+pub const SCENE_JSON_VERSION: u32 = (migrate::MIGRATIONS.len() as u32) + 1;
+
+/// A machine-friendly alternative to the script format. Collection
+/// items are listed in index order, so indexes in the file refer
+/// to the position in the matching list.
+#[derive(Serialize, Deserialize)]
+struct SceneFile
+{
+    version: u32,
+    #[serde(default)]
+    camera: Camera,
+    #[serde(default)]
+    backplate: Option<ImageIndex>,
+    #[serde(default)]
+    atmosphere: Option<Atmosphere>,
+    #[serde(default)]
+    lighting_region: Option<LightingRegion>,
+    #[serde(default)]
+    camera_keyframes: Vec<Camera>,
+    #[serde(default)]
+    cameras: Vec<(String, Camera)>,
+    #[serde(default)]
+    material_variants: Vec<MaterialVariant>,
+    #[serde(default)]
+    variant_sets: Vec<VariantSet>,
+    #[serde(default)]
+    contact_sheet: Option<ContactSheet>,
+    #[serde(default)]
+    images: Vec<Entry<ImageFile>>,
+    #[serde(default)]
+    textures: Vec<Entry<Texture>>,
+    #[serde(default)]
+    transforms: Vec<Entry<Transform>>,
+    #[serde(default)]
+    materials: Vec<Entry<Material>>,
+    #[serde(default)]
+    geometry: Vec<Entry<Geom>>,
+    #[serde(default)]
+    objects: Vec<Entry<Object>>,
+    #[serde(default)]
+    groups: Vec<Entry<Group>>,
+    #[serde(default)]
+    probes: Vec<Entry<Probe>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T>
+{
+    #[serde(default)]
+    name: Option<String>,
+    value: T,
+}
+
+/// Pixels aren't saved - images are re-loaded from the file
+/// they were originally loaded from. Those that weren't, e.g.
+/// embedded in a model, are loaded as empty placeholders.
+#[derive(Serialize, Deserialize)]
+struct ImageFile
+{
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    color_space: ColorSpace,
+}
+
+pub fn scene_to_json(scene: &Scene) -> Result<String, String>
+{
+    let collection = &scene.collection;
+
+    let file = SceneFile
+    {
+        version: SCENE_JSON_VERSION,
+        camera: scene.camera.clone(),
+        backplate: scene.backplate,
+        atmosphere: scene.atmosphere.clone(),
+        lighting_region: scene.lighting_region.clone(),
+        camera_keyframes: scene.camera_keyframes.clone(),
+        cameras: scene.cameras.clone(),
+        material_variants: scene.material_variants.clone(),
+        variant_sets: scene.variant_sets.clone(),
+        contact_sheet: scene.contact_sheet.clone(),
+        images: collection.map_all_with_index(|index, image: &Image, collection| (!collection.is_default(index)).then(|| Entry { name: collection.name_of(index), value: ImageFile { path: image.source(), color_space: image.color_space() } })).into_iter().flatten().collect(),
+        textures: save_entries(collection),
+        transforms: save_entries(collection),
+        materials: save_entries(collection),
+        geometry: save_entries(collection),
+        objects: save_entries(collection),
+        groups: save_entries(collection),
+        probes: save_entries(collection),
+    };
+
+    serde_json::to_string_pretty(&file)
+        .map_err(|e| format!("Could not write scene JSON: {}", e))
+}
+
+pub fn scene_from_json(json: &str) -> Result<Scene, String>
+{
+    // Check the version first, so a newer file reports
+    // that rather than an error about its contents
+
+    let mut value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| format!("Could not parse scene JSON: {}", e))?;
+
+    let version = value.get("version")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| "Scene JSON has no version".to_owned())?;
+
+    if version > (SCENE_JSON_VERSION as u64)
+    {
+        return Err(format!("Scene JSON is version {}, but only up to version {} is supported", version, SCENE_JSON_VERSION));
+    }
+
+    migrate::migrate(&mut value, version as u32)?;
+
+    let file: SceneFile = serde_json::from_value(value)
+        .map_err(|e| format!("Could not read scene JSON: {}", e))?;
+
+    let mut scene = Scene::new();
+    scene.camera = file.camera;
+    scene.backplate = file.backplate;
+    scene.atmosphere = file.atmosphere;
+    scene.lighting_region = file.lighting_region;
+    scene.camera_keyframes = file.camera_keyframes;
+    scene.cameras = file.cameras;
+    scene.material_variants = file.material_variants;
+    scene.variant_sets = file.variant_sets;
+    scene.contact_sheet = file.contact_sheet;
+
+    for entry in file.images
+    {
+        let image = match &entry.value.path
+        {
+            Some(path) => import_image(&path.to_string_lossy(), &mut FileSystemContext::new())
+                .map_err(|e| format!("Could not load image {:?}: {}", path, e.0))?,
+            None => Image::new_empty(1, 1),
+        };
+
+        image.set_color_space(entry.value.color_space);
+        scene.collection.push_opt_name(image, entry.name);
+    }
+
+    load_entries(&mut scene.collection, file.textures);
+    load_entries(&mut scene.collection, file.transforms);
+    load_entries(&mut scene.collection, file.materials);
+    load_entries(&mut scene.collection, file.geometry);
+    load_entries(&mut scene.collection, file.objects);
+    load_entries(&mut scene.collection, file.groups);
+    load_entries(&mut scene.collection, file.probes);
+
+    // Check the indexes now, rather than
+    // panicking when they're first used
+
+    let mut indexes = HashSet::new();
+    scene.collect_indexes(&mut indexes);
+
+    scene.collection.check_indexes(&indexes)
+        .map_err(|e| format!("Invalid scene JSON: {}", e))?;
+
+    Ok(scene)
+}
+
+fn save_entries<V: IndexedValue>(collection: &IndexedCollection) -> Vec<Entry<V>>
+{
+    // A default item is only there because the list is empty,
+    // and loading puts it back - saving it would make it real
+
+    collection.map_all_with_index(|index, value: &V, collection| (!collection.is_default(index)).then(|| Entry { name: collection.name_of(index), value: value.clone() }))
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn load_entries<V: IndexedValue>(collection: &mut IndexedCollection, entries: Vec<Entry<V>>)
+{
+    // The first push replaces the default item, so
+    // the indexes match the positions in the file
+
+    for entry in entries
+    {
+        collection.push_opt_name(entry.value, entry.name);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::desc::edit::{scene_from_json, scene_to_json, Geom, Material, Probe, Scene, Texture, SCENE_JSON_VERSION};
+use crate::indexed::{GeomIndex, MaterialIndex, TextureIndex};
+use crate::vec::Point3;
+
+// The same scene, saved in every version of the format
+
+const FIXTURES: &[(u32, &str)] = &[
+    (1, include_str!("fixtures/scene_v1.json")),
+    (2, include_str!("fixtures/scene_v2.json")),
+    (3, include_str!("fixtures/scene_v3.json")),
+];
+
+fn check_fixture_scene(scene: &Scene)
+{
+    assert_eq!(scene.camera.fov, 40.0);
+
+    assert_eq!(scene.collection.find_by_name::<GeomIndex>("Ball"), Some(GeomIndex::default()));
+    assert!(scene.collection.find_by_name::<GeomIndex>("Crate").is_some());
+    assert!(matches!(scene.collection.map_item(GeomIndex::default(), |g, _| g.clone()), Geom::Sphere{radius, ..} if radius == 1.0));
+
+    assert!(matches!(scene.collection.map_item(MaterialIndex::default(), |m, _| m.clone()), Material::Metal{fuzz, ..} if fuzz == 0.1));
+
+    match scene.collection.map_item(TextureIndex::default(), |t, _| t.clone())
+    {
+        Texture::Solid(color) => assert!((color.into_linear().g - 0.318_546_778_125_091_86).abs() < 1e-12),
+        other => panic!("Expected a solid texture, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_fixtures()
+{
+    // There must be a fixture for every version
+
+    let versions = FIXTURES.iter().map(|(v, _)| *v).collect::<Vec<_>>();
+    assert_eq!(versions, (1..=SCENE_JSON_VERSION).collect::<Vec<_>>());
+
+    let current = scene_to_json(&scene_from_json(FIXTURES.last().unwrap().1).unwrap()).unwrap();
+
+    // The latest fixture is exactly what this version writes - if
+    // a field has been added, it needs a new version and fixture
+
+    assert_eq!(current.trim_end(), FIXTURES.last().unwrap().1.trim_end(), "The format has changed without a new version");
+
+    for (version, json) in FIXTURES.iter()
+    {
+        let scene = scene_from_json(json).unwrap_or_else(|e| panic!("Could not load version {}: {}", version, e));
+
+        check_fixture_scene(&scene);
+
+        // Upgrading and saving gives the same file as the current version
+
+        assert_eq!(scene_to_json(&scene).unwrap(), current, "Version {} differs once upgraded", version);
+    }
+}
+
+#[test]
+fn test_versions()
+{
+    assert!(scene_from_json("{}").is_err());
+    assert!(scene_from_json("{ \"version\": 0 }").is_err());
+    assert!(scene_from_json(&format!("{{ \"version\": {} }}", SCENE_JSON_VERSION + 1)).is_err());
+
+    // Everything other than the version is optional
+
+    for version in 1..=SCENE_JSON_VERSION
+    {
+        assert!(scene_from_json(&format!("{{ \"version\": {} }}", version)).is_ok());
+    }
+}
+
+#[test]
+fn test_probes()
+{
+    // The first probe is a real one, but the default
+    // placeholder doesn't become one once saved
+
+    let mut scene = Scene::new();
+    assert!(scene_from_json(&scene_to_json(&scene).unwrap()).unwrap().probe_locations().is_empty());
+
+    scene.collection.push(Probe { location: Point3::new(1.0, 2.0, 3.0), ..Probe::default() });
+    assert_eq!(scene.probe_locations(), vec![Point3::new(1.0, 2.0, 3.0)]);
+    assert_eq!(scene_from_json(&scene_to_json(&scene).unwrap()).unwrap().probe_locations(), vec![Point3::new(1.0, 2.0, 3.0)]);
+}
+
+#[test]
+fn test_invalid_indexes()
+{
+    // An index past the end of its list is an error
+    // when loading, rather than a panic when it's used
+
+    let mut value: serde_json::Value = serde_json::from_str(FIXTURES.last().unwrap().1).unwrap();
+    value["objects"][0]["value"]["material"] = serde_json::Value::from(99);
+
+    let err = scene_from_json(&value.to_string()).err().unwrap();
+    assert!(err.contains("MaterialIndex(99)"), "{}", err);
+
+    // So is an image that can't be loaded
+
+    value = serde_json::from_str(FIXTURES.last().unwrap().1).unwrap();
+    value["images"][0]["value"]["path"] = serde_json::Value::from("missing.png");
+
+    let err = scene_from_json(&value.to_string()).err().unwrap();
+    assert!(err.contains("missing.png"), "{}", err);
+}
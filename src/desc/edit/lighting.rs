@@ -0,0 +1,150 @@
+use crate::desc::edit::{Geom, Material, Object, Scene};
+use crate::desc::edit::geom::Aabb;
+use crate::indexed::ObjectIndex;
+use crate::math::Scalar;
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::Point3;
+use serde::{Deserialize, Serialize};
+
+/// Which lights are sampled directly, and where. By default, every
+/// emitting sphere, rectangle and disc is sampled, everywhere.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LightingRegion
+{
+    /// Surfaces outside the bounds only sample their BSDF
+    pub bounds: Option<Aabb>,
+    /// The objects to sample - an empty list samples none
+    pub lights: Option<Vec<ObjectIndex>>,
+}
+
+impl LightingRegion
+{
+    /// Lights are placed by the groups they're in - objects
+    /// that can't be sampled, e.g. meshes, are skipped. Returns
+    /// none if there are no lights to sample.
+    pub fn build(&self, scene: &Scene) -> Option<crate::lighting::LightingRegion>
+    {
+        let mut result = match &self.bounds
+        {
+            Some(bounds) => crate::lighting::LightingRegion::new(crate::geom::Aabb::new(bounds.min, bounds.max)),
+            None => crate::lighting::LightingRegion::new(crate::geom::Aabb::new(Point3::broadcast(-Scalar::MAX), Point3::broadcast(Scalar::MAX))),
+        };
+
+        let collection = &scene.collection;
+
+        for (index, matrix) in scene.placed_objects()
+        {
+            let (geom, material) = collection.map_item(index, |object: &Object, _| (object.geom, object.material));
+
+            let sampled = match &self.lights
+            {
+                Some(lights) => lights.contains(&index),
+                None => collection.map_item(material, |material, _| matches!(material, Material::Emit{..})),
+            };
+
+            if sampled
+            {
+                if let Some((surface, center)) = collection.map_item(geom, |geom: &Geom, _| geom.light_surface(&matrix))
+                {
+                    result.global_surfaces.push(surface);
+                    result.local_points.push(center);
+                }
+            }
+        }
+
+        if result.global_surfaces.is_empty()
+        {
+            return None;
+        }
+
+        Some(result)
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for LightingRegion
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        match &self.bounds
+        {
+            Some(bounds) =>
+            {
+                ui.display_vec3("Min", &bounds.min);
+                ui.display_vec3("Max", &bounds.max);
+            },
+            None => ui.imgui.label_text("Bounds", "Everywhere"),
+        }
+
+        match &self.lights
+        {
+            Some(lights) =>
+            {
+                for (i, light) in lights.iter().enumerate()
+                {
+                    light.ui_display(ui, &format!("Light {}", i));
+                }
+            },
+            None => ui.imgui.label_text("Lights", "Every emitting sphere, rectangle and disc"),
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for LightingRegion
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+        let mut result = false;
+
+        let mut bounded = self.bounds.is_some();
+
+        if ui.imgui.checkbox("Bounded", &mut bounded)
+        {
+            self.bounds = if bounded { Some(Aabb::default()) } else { None };
+            result = true;
+        }
+
+        if let Some(bounds) = &mut self.bounds
+        {
+            result |= ui.edit_vec3("Min", &mut bounds.min);
+            result |= ui.edit_vec3("Max", &mut bounds.max);
+        }
+
+        let mut all = self.lights.is_none();
+
+        if ui.imgui.checkbox("All Emitting Objects", &mut all)
+        {
+            self.lights = if all { None } else { Some(Vec::new()) };
+            result = true;
+        }
+
+        if let Some(lights) = &mut self.lights
+        {
+            for (i, light) in lights.iter_mut().enumerate()
+            {
+                result |= light.ui_edit(ui, &format!("Light {}", i));
+            }
+
+            if ui.imgui.button("Add Light")
+            {
+                lights.push(ObjectIndex::default());
+                result = true;
+            }
+
+            ui.imgui.same_line();
+
+            if ui.imgui.button("Remove Light") && !lights.is_empty()
+            {
+                lights.pop();
+                result = true;
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::desc::edit::{Camera, Triangle};
+use crate::geom::{Aabb, AabbBuilder};
+use crate::math::Scalar;
+use crate::render::RenderOptions;
+use crate::vec::{Mat4, Point3};
+use serde::{Deserialize, Serialize};
+
+/// Lower detail versions of a mesh, used when
+/// it's small on screen.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeshLod
+{
+    /// From the most to the least detailed
+    pub levels: Vec<Vec<Triangle>>,
+    /// Projected size (in pixels) below which the first level
+    /// is used - each following level is used below half of
+    /// the previous size.
+    pub screen_size: Scalar,
+}
+
+impl MeshLod
+{
+    /// Generates levels by clustering the mesh's vertices
+    /// onto successively coarser grids.
+    pub fn decimated(triangles: &[Triangle], num_levels: usize, screen_size: Scalar) -> Self
+    {
+        let bounds = triangle_bounds(triangles, &Mat4::identity());
+        let diagonal = (bounds.max - bounds.min).magnitude();
+
+        let levels = (0..num_levels)
+            .map(|level| decimate(triangles, diagonal / ((64 >> level.min(5)) as Scalar)))
+            .collect();
+
+        MeshLod { levels, screen_size }
+    }
+
+    /// Selects the triangles to use for a mesh of the
+    /// given projected size (in pixels).
+    pub fn select<'a>(&'a self, full: &'a [Triangle], projected_size: Scalar) -> &'a [Triangle]
+    {
+        let mut result = full;
+        let mut threshold = self.screen_size;
+
+        for level in self.levels.iter()
+        {
+            if projected_size >= threshold
+            {
+                break;
+            }
+
+            result = level;
+            threshold /= 2.0;
+        }
+
+        result
+    }
+}
+
+/// The camera, as needed to select levels of detail
+#[derive(Clone, Debug)]
+pub struct LodView
+{
+    location: Point3,
+    /// Distance to the image plane, in pixels
+    focal_pixels: Scalar,
+}
+
+impl LodView
+{
+    pub fn new(camera: &Camera, options: &RenderOptions) -> Self
+    {
+        let focal_pixels = (options.width as Scalar) / (2.0 * (camera.fov.to_radians() / 2.0).tan());
+
+        LodView { location: camera.location, focal_pixels }
+    }
+
+    /// The approximate size (in pixels) of the
+    /// bounding sphere of the box, once projected.
+    pub fn projected_size(&self, bounds: &Aabb) -> Scalar
+    {
+        let center = (bounds.min + bounds.max) / 2.0;
+        let radius = (bounds.max - bounds.min).magnitude() / 2.0;
+        let distance = (center - self.location).magnitude();
+
+        if distance <= radius
+        {
+            return Scalar::INFINITY;
+        }
+
+        2.0 * radius * self.focal_pixels / distance
+    }
+}
+
+/// Bounds of the triangles, once transformed by the matrix
+pub fn triangle_bounds(triangles: &[Triangle], matrix: &Mat4) -> Aabb
+{
+    let mut builder = AabbBuilder::new();
+
+    for triangle in triangles.iter()
+    {
+        for v in triangle.vertices.iter()
+        {
+            builder.add_point(matrix.mul_point(v.location));
+        }
+    }
+
+    builder.build()
+}
+
+/// Vertex clustering - vertices are moved to the average of
+/// all vertices in their grid cell, and triangles that collapse
+/// or duplicate others are removed. Texture coordinates
+/// and colors are kept from the original triangles.
+fn decimate(triangles: &[Triangle], cell_size: Scalar) -> Vec<Triangle>
+{
+    if cell_size <= 0.0
+    {
+        return triangles.to_vec();
+    }
+
+    let cell = |p: Point3| ((p.x / cell_size).floor() as i64, (p.y / cell_size).floor() as i64, (p.z / cell_size).floor() as i64);
+
+    let mut sums: HashMap<(i64, i64, i64), (Point3, Scalar)> = HashMap::new();
+
+    for triangle in triangles.iter()
+    {
+        for v in triangle.vertices.iter()
+        {
+            let entry = sums.entry(cell(v.location)).or_insert((Point3::zero(), 0.0));
+            entry.0 += v.location;
+            entry.1 += 1.0;
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+
+    for triangle in triangles.iter()
+    {
+        let cells = [cell(triangle.vertices[0].location), cell(triangle.vertices[1].location), cell(triangle.vertices[2].location)];
+
+        if (cells[0] == cells[1]) || (cells[1] == cells[2]) || (cells[0] == cells[2])
+        {
+            continue;
+        }
+
+        let mut key = cells;
+        key.sort();
+
+        if !seen.insert(key)
+        {
+            continue;
+        }
+
+        let mut decimated = triangle.clone();
+
+        for (v, c) in decimated.vertices.iter_mut().zip(cells.iter())
+        {
+            let (sum, count) = sums[c];
+            v.location = sum / count;
+        }
+
+        result.push(decimated);
+    }
+
+    result
+}
@@ -1,16 +1,24 @@
 use std::collections::HashSet;
 
-use crate::indexed::{Index, IndexedCollection, IndexedValue, AnyIndex, MaterialIndex, TextureIndex};
+use crate::desc::edit::{GraphNodeKind, MaterialGraph};
+use crate::indexed::{Index, IndexedCollection, IndexedValue, IndexRemap, AnyIndex, MaterialIndex, TextureIndex};
 use crate::math::Scalar;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Material
 {
-    Dielectric { ior: Scalar },
-    Diffuse{ texture: TextureIndex },
+    /// The tint is the color transmitted each time light
+    /// passes through the surface, for stained glass
+    Dielectric { ior: Scalar, #[serde(default)] tint: Option<TextureIndex> },
+    Diffuse{ texture: TextureIndex, normal_texture: Option<TextureIndex> },
     Emit{ texture: TextureIndex },
-    Metal{ texture: TextureIndex, fuzz: Scalar },
+    Graph{ graph: MaterialGraph },
+    Medium{ albedo: TextureIndex, emission: TextureIndex },
+    Metal{ texture: TextureIndex, fuzz: Scalar, normal_texture: Option<TextureIndex> },
+    Pbr{ texture: TextureIndex, metallic: Scalar, roughness: Scalar, normal_texture: Option<TextureIndex> },
 }
 
 impl Material
@@ -19,10 +27,21 @@ impl Material
     {
         match self
         {
-            Material::Dielectric{ior} => crate::material::Material::Dielectric(*ior),
-            Material::Diffuse{texture} => crate::material::Material::Diffuse(collection.map_item(*texture, |texture, _| texture.build(collection))),
+            Material::Dielectric{ior, tint} => crate::material::Material::Dielectric(*ior, tint.map(|tint| collection.map_item(tint, |texture, _| texture.build(collection)))),
+            Material::Diffuse{texture, normal_texture} => build_normal_mapped(
+                crate::material::Material::Diffuse(collection.map_item(*texture, |texture, _| texture.build(collection))),
+                normal_texture, collection),
             Material::Emit{texture} => crate::material::Material::Emit(collection.map_item(*texture, |texture, _| texture.build(collection))),
-            Material::Metal{texture, fuzz} => crate::material::Material::Metal(collection.map_item(*texture, |texture, _| texture.build(collection)), *fuzz),
+            Material::Graph{graph} => graph.build(collection),
+            Material::Medium{albedo, emission} => crate::material::Material::Medium(
+                collection.map_item(*albedo, |texture, _| texture.build(collection)),
+                collection.map_item(*emission, |texture, _| texture.build(collection))),
+            Material::Metal{texture, fuzz, normal_texture} => build_normal_mapped(
+                crate::material::Material::Metal(collection.map_item(*texture, |texture, _| texture.build(collection)), *fuzz),
+                normal_texture, collection),
+            Material::Pbr{texture, metallic, roughness, normal_texture} => build_normal_mapped(
+                crate::material::Material::Pbr(collection.map_item(*texture, |texture, _| texture.build(collection)), *metallic, *roughness),
+                normal_texture, collection),
         }
     }
 
@@ -33,10 +52,14 @@ impl Material
             Material::Dielectric{..} => "Dielectric",
             Material::Diffuse{..} => "Diffuse",
             Material::Emit{..} => "Emit",
+            Material::Graph{..} => "Graph",
+            Material::Medium{..} => "Medium",
             Material::Metal{..} => "Metal",
+            Material::Pbr{..} => "PBR",
         }
     }
 
+    #[cfg(feature = "ui")]
     fn ui_render_combo(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
         let mut result = false;
@@ -44,10 +67,13 @@ impl Material
         if let Some(_) = ui.imgui.begin_combo(label, cur_tag)
         {
             for entry in [
-                Material::Dielectric{ ior: 1.5 },
-                Material::Diffuse{ texture: TextureIndex::from_usize(0) },
+                Material::Dielectric{ ior: 1.5, tint: None },
+                Material::Diffuse{ texture: TextureIndex::from_usize(0), normal_texture: None },
                 Material::Emit{ texture: TextureIndex::from_usize(0) },
-                Material::Metal{ texture: TextureIndex::from_usize(0), fuzz: 0.0 },
+                Material::Graph{ graph: MaterialGraph::default() },
+                Material::Medium{ albedo: TextureIndex::from_usize(0), emission: TextureIndex::from_usize(0) },
+                Material::Metal{ texture: TextureIndex::from_usize(0), fuzz: 0.0, normal_texture: None },
+                Material::Pbr{ texture: TextureIndex::from_usize(0), metallic: 0.0, roughness: 0.5, normal_texture: None },
             ]
             {
                 let entry_tag = entry.ui_tag();
@@ -73,7 +99,7 @@ impl Default for Material
 {
     fn default() -> Self
     {
-        Material::Diffuse{ texture: TextureIndex::from_usize(0) }
+        Material::Diffuse{ texture: TextureIndex::from_usize(0), normal_texture: None }
     }
 }
 
@@ -81,8 +107,85 @@ impl IndexedValue for Material
 {
     type Index = MaterialIndex;
     
-    fn collect_indexes(&self, _indexes: &mut HashSet<AnyIndex>)
+    fn collect_indexes(&self, indexes: &mut HashSet<AnyIndex>)
     {
+        match self
+        {
+            Material::Dielectric{ tint, .. } =>
+            {
+                if let Some(tint) = tint
+                {
+                    indexes.insert(AnyIndex::Texture(*tint));
+                }
+            },
+            Material::Diffuse{ texture, normal_texture }
+                | Material::Metal{ texture, normal_texture, .. }
+                | Material::Pbr{ texture, normal_texture, .. } =>
+            {
+                indexes.insert(AnyIndex::Texture(*texture));
+
+                if let Some(normal_texture) = normal_texture
+                {
+                    indexes.insert(AnyIndex::Texture(*normal_texture));
+                }
+            },
+            Material::Emit{ texture } =>
+            {
+                indexes.insert(AnyIndex::Texture(*texture));
+            },
+            Material::Medium{ albedo, emission } =>
+            {
+                indexes.insert(AnyIndex::Texture(*albedo));
+                indexes.insert(AnyIndex::Texture(*emission));
+            },
+            Material::Graph{ graph } =>
+            {
+                for node in graph.nodes.iter()
+                {
+                    if let GraphNodeKind::Texture{ texture } = &node.kind
+                    {
+                        indexes.insert(AnyIndex::Texture(*texture));
+                    }
+                }
+            },
+        }
+    }
+
+    fn remap_indexes(&mut self, remap: &IndexRemap)
+    {
+        match self
+        {
+            Material::Dielectric{ tint, .. } =>
+            {
+                remap.apply_opt(tint);
+            },
+            Material::Diffuse{ texture, normal_texture }
+                | Material::Metal{ texture, normal_texture, .. }
+                | Material::Pbr{ texture, normal_texture, .. } =>
+            {
+                remap.apply(texture);
+                remap.apply_opt(normal_texture);
+            },
+            Material::Emit{ texture } =>
+            {
+                remap.apply(texture);
+            },
+            Material::Medium{ albedo, emission } =>
+            {
+                remap.apply(albedo);
+                remap.apply(emission);
+            },
+            Material::Graph{ graph } =>
+            {
+                for node in graph.nodes.iter_mut()
+                {
+                    if let GraphNodeKind::Texture{ texture } = &mut node.kind
+                    {
+                        remap.apply(texture);
+                    }
+                }
+            },
+        }
     }
 
     fn summary(&self) -> String
@@ -91,37 +194,65 @@ impl IndexedValue for Material
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Material
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
     {
         match self
         {
-            Material::Dielectric{ ior } =>
+            Material::Dielectric{ ior, tint } =>
             {
                 ui.imgui.label_text(label, "Dielectric");
                 ui.display_float("IOR", ior);
+
+                if let Some(tint) = tint
+                {
+                    ui.imgui.label_text("Tint", tint.to_usize().to_string());
+                }
             },
-            Material::Diffuse{ texture } =>
+            Material::Diffuse{ texture, normal_texture } =>
             {
                 ui.imgui.label_text(label, "Diffuse");
                 ui.imgui.label_text("Texture", texture.to_usize().to_string());
+                display_normal_texture(ui, normal_texture);
             },
             Material::Emit{ texture } =>
             {
                 ui.imgui.label_text(label, "Emit");
                 ui.imgui.label_text("Texture", texture.to_usize().to_string());
             },
-            Material::Metal{ texture, fuzz } =>
+            Material::Graph{ graph } =>
+            {
+                ui.imgui.label_text(label, "Graph");
+                graph.ui_display(ui, "Graph");
+            },
+            Material::Medium{ albedo, emission } =>
+            {
+                ui.imgui.label_text(label, "Medium");
+                ui.imgui.label_text("Albedo", albedo.to_usize().to_string());
+                ui.imgui.label_text("Emission", emission.to_usize().to_string());
+            },
+            Material::Metal{ texture, fuzz, normal_texture } =>
             {
                 ui.imgui.label_text(label, "Metal");
                 ui.imgui.label_text("Texture", texture.to_usize().to_string());
                 ui.display_float("Fuzz", fuzz);
+                display_normal_texture(ui, normal_texture);
+            },
+            Material::Pbr{ texture, metallic, roughness, normal_texture } =>
+            {
+                ui.imgui.label_text(label, "PBR");
+                ui.imgui.label_text("Base Color", texture.to_usize().to_string());
+                ui.display_float("Metallic", metallic);
+                ui.display_float("Roughness", roughness);
+                display_normal_texture(ui, normal_texture);
             },
         }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Material
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
@@ -131,26 +262,94 @@ impl UiEdit for Material
 
         match self
         {
-            Material::Dielectric{ ior } =>
+            Material::Dielectric{ ior, tint } =>
             {
                 result |= ui.edit_float("IOR", ior);
+
+                let mut tinted = tint.is_some();
+
+                if ui.imgui.checkbox("Tinted", &mut tinted)
+                {
+                    *tint = if tinted { Some(TextureIndex::from_usize(0)) } else { None };
+                    result = true;
+                }
+
+                if let Some(tint) = tint
+                {
+                    result |= tint.ui_edit(ui, "Tint");
+                }
             },
-            Material::Diffuse{ texture } =>
+            Material::Diffuse{ texture, normal_texture } =>
             {
                 result |= texture.ui_edit(ui, "Texture");
+                result |= edit_normal_texture(ui, normal_texture, *texture);
             },
             Material::Emit{ texture } =>
             {
                 result |= texture.ui_edit(ui, "Texture");
             },
-            Material::Metal{ texture, fuzz } =>
+            Material::Graph{ graph } =>
+            {
+                result |= graph.ui_edit(ui, "Graph");
+            },
+            Material::Medium{ albedo, emission } =>
+            {
+                result |= albedo.ui_edit(ui, "Albedo");
+                result |= emission.ui_edit(ui, "Emission");
+            },
+            Material::Metal{ texture, fuzz, normal_texture } =>
             {
                 result |= texture.ui_edit(ui, "Texture");
                 result |= ui.edit_float("Fuzz", fuzz);
+                result |= edit_normal_texture(ui, normal_texture, *texture);
+            },
+            Material::Pbr{ texture, metallic, roughness, normal_texture } =>
+            {
+                result |= texture.ui_edit(ui, "Base Color");
+                result |= ui.edit_float("Metallic", metallic);
+                result |= ui.edit_float("Roughness", roughness);
+                result |= edit_normal_texture(ui, normal_texture, *texture);
             },
         }
 
         ui.imgui.unindent();
         result
     }
-}
\ No newline at end of file
+}
+fn build_normal_mapped(material: crate::material::Material, normal_texture: &Option<TextureIndex>, collection: &IndexedCollection) -> crate::material::Material
+{
+    match normal_texture
+    {
+        Some(normal_texture) => crate::material::Material::normal_mapped(material, collection.map_item(*normal_texture, |texture, _| texture.build(collection))),
+        None => material,
+    }
+}
+
+#[cfg(feature = "ui")]
+fn display_normal_texture(ui: &UiRenderer, normal_texture: &Option<TextureIndex>)
+{
+    if let Some(normal_texture) = normal_texture
+    {
+        ui.imgui.label_text("Normal Map", normal_texture.to_usize().to_string());
+    }
+}
+
+#[cfg(feature = "ui")]
+fn edit_normal_texture(ui: &UiRenderer, normal_texture: &mut Option<TextureIndex>, texture: TextureIndex) -> bool
+{
+    let mut result = false;
+    let mut mapped = normal_texture.is_some();
+
+    if ui.imgui.checkbox("Normal Map", &mut mapped)
+    {
+        *normal_texture = if mapped { Some(texture) } else { None };
+        result = true;
+    }
+
+    if let Some(normal_texture) = normal_texture
+    {
+        result |= normal_texture.ui_edit(ui, "Normal Texture");
+    }
+
+    result
+}
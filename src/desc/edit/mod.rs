@@ -1,17 +1,47 @@
+pub mod atmosphere;
 pub mod camera;
+pub mod cleanup;
+pub mod clipboard;
 pub mod color;
+pub mod contact;
+pub mod diff;
+pub mod furnace;
 pub mod geom;
+pub mod graph;
+pub mod group;
+pub mod json;
+pub mod lighting;
+pub mod lod;
 pub mod material;
 pub mod object;
+pub mod probe;
 pub mod scene;
+pub mod studio;
 pub mod texture;
 pub mod transform;
+pub mod turntable;
+pub mod variant;
 
+pub use atmosphere::{Atmosphere, Clouds};
 pub use camera::Camera;
+pub use cleanup::{CleanupReport, MeshCleanup};
+pub use clipboard::{Clipboard, ClipboardItem, append_scene};
 pub use color::Color;
-pub use geom::{Geom, Triangle, TriangleVertex};
+pub use contact::{ContactSheet, ContactSheetMode, MaterialVariant};
+pub use diff::{diff_scenes, DiffChange, DiffEntry, SceneDiff};
+pub use furnace::Furnace;
+pub use geom::{Geom, GeomBuildContext, Triangle, TriangleVertex, sdf_triangles, transform_triangles};
+pub use graph::{GraphNode, GraphNodeKind, MaterialGraph};
+pub use group::Group;
+pub use json::{scene_from_json, scene_to_json, SCENE_JSON_VERSION};
+pub use lighting::LightingRegion;
+pub use lod::{LodView, MeshLod};
 pub use material::Material;
-pub use object::Object;
-pub use scene::Scene;
+pub use object::{Object, Opacity};
+pub use probe::Probe;
+pub use scene::{ObjectBuildProgress, Scene};
+pub use studio::StudioRig;
 pub use texture::Texture;
 pub use transform::Transform;
+pub use turntable::Turntable;
+pub use variant::{Variant, VariantSet};
@@ -1,20 +1,90 @@
 use std::collections::HashSet;
-use crate::{indexed::{IndexedValue, GeomIndex, MaterialIndex, ObjectIndex, IndexedCollection}, ui::{UiDisplay, UiRenderer}, ui::UiEdit};
 
-#[derive(Clone, Debug, Default)]
+use crate::desc::edit::GeomBuildContext;
+use crate::geom::Transformed;
+use crate::math::Scalar;
+use crate::vec::Mat4;
+use crate::indexed::{IndexedValue, GeomIndex, IndexRemap, MaterialIndex, ObjectIndex, TextureIndex, IndexedCollection};
+#[cfg(feature = "ui")]
+use crate::{indexed::Index, ui::{UiDisplay, UiEdit, UiRenderer}};
+use serde::{Deserialize, Serialize};
+
+/// A texture that cuts holes in the object, for
+/// leaves, fences and the like on alpha cards
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Opacity
+{
+    pub texture: TextureIndex,
+    /// Use the texture's luminance, rather than its alpha
+    #[serde(default)]
+    pub luminance: bool,
+    /// Opaque at or above the cutoff, and transparent below, like glTF's
+    /// MASK mode. Without it, the opacity is the chance of a ray hitting.
+    #[serde(default)]
+    pub cutoff: Option<Scalar>,
+}
+
+impl Opacity
+{
+    fn build(&self, collection: &IndexedCollection) -> crate::object::Opacity
+    {
+        crate::object::Opacity
+        {
+            texture: collection.map_item(self.texture, |texture, _| texture.build(collection)),
+            luminance: self.luminance,
+            cutoff: self.cutoff,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Object
 {
     pub geom: GeomIndex,
     pub material: MaterialIndex,
+    /// Overrides how far rays leaving this object's surface are
+    /// offset, to fix self-intersection acne or light leaks
+    pub ray_bias: Option<Scalar>,
+    #[serde(default)]
+    pub opacity: Option<Opacity>,
 }
 
 impl Object
 {
-    pub fn build(&self, collection: &IndexedCollection) -> crate::object::Object
+    pub fn build(&self, collection: &IndexedCollection, build: &GeomBuildContext) -> crate::object::Object
     {
         crate::object::Object::new_boxed(
-            collection.map_item(self.geom, |geom, collection| geom.build_surface(collection)),
-            collection.map_item(self.material, |material, collection| material.build(collection)))
+            collection.map_item(self.geom, |geom, collection| geom.build_surface(collection, build, &Mat4::identity())),
+            self.build_material(collection))
+            .with_ray_bias(self.ray_bias)
+            .with_opacity(self.opacity.as_ref().map(|opacity| opacity.build(collection)))
+    }
+
+    pub fn build_transformed(&self, collection: &IndexedCollection, matrix: &Mat4, build: &GeomBuildContext) -> crate::object::Object
+    {
+        if *matrix == Mat4::identity()
+        {
+            return self.build(collection, build);
+        }
+
+        crate::object::Object::new(
+            Transformed::new(collection.map_item(self.geom, |geom, collection| geom.build_surface(collection, build, matrix)), *matrix),
+            self.build_material(collection))
+            .with_ray_bias(self.ray_bias)
+            .with_opacity(self.opacity.as_ref().map(|opacity| opacity.build(collection)))
+    }
+
+    /// The opacity texture decides which rays hit the
+    /// object, so the material's own alpha is ignored
+    fn build_material(&self, collection: &IndexedCollection) -> crate::material::Material
+    {
+        let material = collection.map_item(self.material, |material, collection| material.build(collection));
+
+        match self.opacity
+        {
+            Some(_) => crate::material::Material::opaque(material),
+            None => material,
+        }
     }
 }
 
@@ -22,8 +92,26 @@ impl IndexedValue for Object
 {
     type Index = ObjectIndex;
     
-    fn collect_indexes(&self, _indexes: &mut HashSet<crate::indexed::AnyIndex>)
+    fn collect_indexes(&self, indexes: &mut HashSet<crate::indexed::AnyIndex>)
     {
+        indexes.insert(crate::indexed::AnyIndex::Geom(self.geom));
+        indexes.insert(crate::indexed::AnyIndex::Material(self.material));
+
+        if let Some(opacity) = &self.opacity
+        {
+            indexes.insert(crate::indexed::AnyIndex::Texture(opacity.texture));
+        }
+    }
+
+    fn remap_indexes(&mut self, remap: &IndexRemap)
+    {
+        remap.apply(&mut self.geom);
+        remap.apply(&mut self.material);
+
+        if let Some(opacity) = &mut self.opacity
+        {
+            remap.apply(&mut opacity.texture);
+        }
     }
 
     fn summary(&self) -> String
@@ -32,6 +120,7 @@ impl IndexedValue for Object
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Object
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -40,9 +129,26 @@ impl UiDisplay for Object
 
         self.geom.ui_display(ui, "Geom");
         self.material.ui_display(ui, "Material");
+
+        if let Some(ray_bias) = &self.ray_bias
+        {
+            ui.display_float("Ray Bias", ray_bias);
+        }
+
+        if let Some(opacity) = &self.opacity
+        {
+            opacity.texture.ui_display(ui, "Opacity Texture");
+            ui.imgui.label_text("Opacity Channel", if opacity.luminance { "Luminance" } else { "Alpha" });
+
+            if let Some(cutoff) = &opacity.cutoff
+            {
+                ui.display_float("Opacity Cutoff", cutoff);
+            }
+        }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Object
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
@@ -52,6 +158,47 @@ impl UiEdit for Object
         let mut result = false;
         result |= self.geom.ui_edit(ui, "Geom");
         result |= self.material.ui_edit(ui, "Material");
+
+        let mut overridden = self.ray_bias.is_some();
+
+        if ui.imgui.checkbox("Override Ray Bias", &mut overridden)
+        {
+            self.ray_bias = if overridden { Some(1.0e-4) } else { None };
+            result = true;
+        }
+
+        if let Some(ray_bias) = &mut self.ray_bias
+        {
+            result |= ui.edit_float("Ray Bias", ray_bias);
+        }
+
+        let mut has_opacity = self.opacity.is_some();
+
+        if ui.imgui.checkbox("Opacity", &mut has_opacity)
+        {
+            self.opacity = if has_opacity { Some(Opacity { texture: TextureIndex::from_usize(0), luminance: false, cutoff: None }) } else { None };
+            result = true;
+        }
+
+        if let Some(opacity) = &mut self.opacity
+        {
+            result |= opacity.texture.ui_edit(ui, "Opacity Texture");
+            result |= ui.imgui.checkbox("Use Luminance", &mut opacity.luminance);
+
+            let mut has_cutoff = opacity.cutoff.is_some();
+
+            if ui.imgui.checkbox("Opacity Cutoff", &mut has_cutoff)
+            {
+                opacity.cutoff = if has_cutoff { Some(0.5) } else { None };
+                result = true;
+            }
+
+            if let Some(cutoff) = &mut opacity.cutoff
+            {
+                result |= ui.edit_float("Cutoff", cutoff);
+            }
+        }
+
         result
     }
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use crate::indexed::{AnyIndex, IndexedValue, IndexRemap, ProbeIndex};
+use crate::math::Scalar;
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::Point3;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Probe
+{
+    pub location: Point3,
+    pub display_radius: Scalar,
+}
+
+impl Default for Probe
+{
+    fn default() -> Self
+    {
+        Probe
+        {
+            location: Point3::new(0.0, 0.0, 0.0),
+            display_radius: 16.0,
+        }
+    }
+}
+
+impl IndexedValue for Probe
+{
+    type Index = ProbeIndex;
+
+    fn collect_indexes(&self, _indexes: &mut HashSet<AnyIndex>)
+    {
+    }
+
+    fn remap_indexes(&mut self, _remap: &IndexRemap)
+    {
+    }
+
+    fn summary(&self) -> String
+    {
+        format!("Probe <{}, {}, {}>", self.location.x, self.location.y, self.location.z)
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for Probe
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        ui.display_vec3("Location", &self.location);
+        ui.display_float("Display Radius", &self.display_radius);
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for Probe
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+        result |= ui.edit_vec3("Location", &mut self.location);
+        result |= ui.edit_float("Display Radius", &mut self.display_radius);
+        result
+    }
+}
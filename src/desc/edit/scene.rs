@@ -1,13 +1,42 @@
-use crate::indexed::{IndexedCollection, GeomIndex, ImageIndex, ObjectIndex, TextureIndex, MaterialIndex, TransformIndex};
-use crate::desc::edit::{Camera, Object};
+use std::collections::HashSet;
+
+use crate::indexed::{AnyIndex, Index, IndexedCollection, IndexRemap, GeomIndex, GroupIndex, ImageIndex, ObjectIndex, ProbeIndex, TextureIndex, MaterialIndex, TransformIndex};
+use crate::bake::{Baker, BakeOptions};
+use crate::camera::Backplate;
+use crate::desc::{BuildMonitor, BuildProgress};
+use crate::desc::edit::{Atmosphere, Camera, ContactSheet, Furnace, GeomBuildContext, Group, LightingRegion, LodView, MaterialVariant, Object, Probe, VariantSet};
+use crate::geom::{MeshCache, OctreeQuality, Sphere};
+use crate::material::Material;
+use crate::math::Scalar;
+use crate::texture::Texture;
 use crate::render::RenderOptions;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::{Mat4, Point3};
 
 #[derive(Clone)]
 pub struct Scene
 {
     pub camera: Camera,
     pub collection: IndexedCollection,
+    /// Image shown behind the scene from the camera
+    pub backplate: Option<ImageIndex>,
+    /// Sky and sun that light rays escaping the scene
+    pub atmosphere: Option<Atmosphere>,
+    /// Overrides which lights are sampled directly, and where
+    pub lighting_region: Option<LightingRegion>,
+    /// Cameras for each frame of an animation
+    pub camera_keyframes: Vec<Camera>,
+    /// Named viewpoints and alternative materials, to
+    /// compare side by side on a contact sheet
+    pub cameras: Vec<(String, Camera)>,
+    pub material_variants: Vec<MaterialVariant>,
+    /// Alternative looks, switchable per render
+    pub variant_sets: Vec<VariantSet>,
+    /// Set by scripts that are rendered as a contact sheet
+    pub contact_sheet: Option<ContactSheet>,
+    /// Settings for the furnace test - they're not saved
+    pub furnace: Furnace,
 }
 
 impl Scene
@@ -22,27 +51,418 @@ impl Scene
         collection.add_index::<MaterialIndex>("Materials");
         collection.add_index::<GeomIndex>("Geometry");
         collection.add_index::<ObjectIndex>("Objects");
+        collection.add_index::<GroupIndex>("Groups");
+        collection.add_index::<ProbeIndex>("Probes");
 
         Scene
         {
             camera,
             collection,
+            backplate: None,
+            atmosphere: None,
+            lighting_region: None,
+            camera_keyframes: Vec::new(),
+            cameras: Vec::new(),
+            material_variants: Vec::new(),
+            variant_sets: Vec::new(),
+            contact_sheet: None,
+            furnace: Furnace::default(),
         }
     }
 
     pub fn build(&self, options: &RenderOptions, camera_override: Option<&Camera>) -> crate::scene::Scene
     {
-        let objects = self.collection
-            .map_all(|obj: &Object, collection| obj.build(collection));
+        self.build_with_monitor(options, camera_override, &mut (), &MeshCache::new()).expect("Build can't be cancelled")
+    }
+
+    /// Builds the scene, returning None if the monitor cancels
+    /// it part-way through. Meshes are re-used from the cache.
+    pub fn build_with_monitor(&self, options: &RenderOptions, camera_override: Option<&Camera>, monitor: &mut dyn BuildMonitor, cache: &MeshCache) -> Option<crate::scene::Scene>
+    {
+        let camera = camera_override.unwrap_or(&self.camera);
+        let cancelled = monitor.cancelled_flag();
+        let build = GeomBuildContext { cache, view: Some(LodView::new(camera, options)), bake_transforms: options.bake_transforms, octree_quality: options.octree_quality, cancelled: cancelled.as_deref() };
+
+        let objects = self.build_objects_with_monitor(monitor, &build)?;
+        let lighting_regions = self.lighting_region.clone().unwrap_or_default().build(self).into_iter().collect();
+
+        Some(crate::scene::Scene::new(
+            options.sampling_mode,
+            camera.build(options),
+            lighting_regions,
+            objects)
+            .with_backplate(self.build_backplate())
+            .with_atmosphere(self.atmosphere.as_ref().map(|a| a.build())))
+    }
+
+    fn build_backplate(&self) -> Option<Backplate>
+    {
+        self.backplate.map(|index| Backplate::new(self.collection.map_item(index, |image, _| image.clone())))
+    }
+
+    pub fn build_objects(&self) -> Vec<crate::object::Object>
+    {
+        let build = GeomBuildContext { cache: &MeshCache::new(), view: None, bake_transforms: true, octree_quality: OctreeQuality::default(), cancelled: None };
+
+        self.build_objects_with_monitor(&mut (), &build).expect("Build can't be cancelled")
+    }
+
+    fn build_objects_with_monitor(&self, monitor: &mut dyn BuildMonitor, build: &GeomBuildContext) -> Option<Vec<crate::object::Object>>
+    {
+        // Objects and groups that aren't owned by any
+        // group are placed directly into the world
+
+        let (owned_objects, owned_groups) = self.owned_objects_and_groups();
+
+        let root_objects = self.collection
+            .map_all_with_index(|index, _: &Object, _| index)
+            .into_iter()
+            .filter(|index| !owned_objects.contains(index))
+            .collect::<Vec<_>>();
+
+        let root_groups = self.collection
+            .map_all_with_index(|index, _: &Group, _| index)
+            .into_iter()
+            .filter(|index| !owned_groups.contains(index))
+            .collect::<Vec<_>>();
+
+        // Objects in groups are counted once, even
+        // if they're instanced by more than one group
+
+        let mut progress = ObjectBuildProgress::new(monitor, self.collection.map_all(|_: &Object, _| ()).len());
+        let mut objects = Vec::new();
+
+        for index in root_objects
+        {
+            let label = self.collection.name_of(index).unwrap_or_else(|| format!("Object {}", index.to_usize()));
+
+            objects.push(self.collection.map_item(index, |obj: &Object, collection| obj.build(collection, build)).with_label(label));
+
+            if !progress.object_built()
+            {
+                return None;
+            }
+        }
+
+        for index in root_groups
+        {
+            let mut visiting = vec![index];
+
+            if !self.collection.map_item(index, |group, collection| group.build(collection, &Mat4::identity(), &mut visiting, &mut objects, &mut progress, build))
+            {
+                return None;
+            }
+        }
+
+        Some(objects)
+    }
+
+    /// Every object that's rendered, along with the matrix
+    /// from the groups it's in - objects in more than one
+    /// group are listed once for each placement.
+    pub fn placed_objects(&self) -> Vec<(ObjectIndex, Mat4)>
+    {
+        let (owned_objects, owned_groups) = self.owned_objects_and_groups();
+
+        let mut result = self.collection
+            .map_all_with_index(|index, _: &Object, _| index)
+            .into_iter()
+            .filter(|index| !owned_objects.contains(index))
+            .map(|index| (index, Mat4::identity()))
+            .collect::<Vec<_>>();
+
+        for index in self.collection.map_all_with_index(|index, _: &Group, _| index)
+        {
+            if !owned_groups.contains(&index)
+            {
+                self.place_group(index, &Mat4::identity(), &mut vec![index], &mut result);
+            }
+        }
+
+        result
+    }
+
+    fn place_group(&self, index: GroupIndex, parent_matrix: &Mat4, visiting: &mut Vec<GroupIndex>, result: &mut Vec<(ObjectIndex, Mat4)>)
+    {
+        let group = self.collection.map_item(index, |group: &Group, _| group.clone());
+        let matrix = *parent_matrix * group.transform.build_matrix(&self.collection);
+
+        result.extend(group.objects.iter().map(|o| (*o, matrix)));
+
+        for child in group.groups.iter()
+        {
+            // Ignore any cycles
+
+            if !visiting.contains(child)
+            {
+                visiting.push(*child);
+                self.place_group(*child, &matrix, visiting, result);
+                visiting.pop();
+            }
+        }
+    }
+
+    pub fn find_variant_set(&mut self, name: &str) -> Result<&mut VariantSet, String>
+    {
+        self.variant_sets.iter_mut().find(|s| s.name == name)
+            .ok_or_else(|| format!("The scene has no variant set {:?}", name))
+    }
+
+    /// Parses "set=variant", as given on the command line, and selects it
+    pub fn select_variant_arg(&mut self, arg: &str) -> Result<(), String>
+    {
+        let (set, variant) = arg.split_once('=')
+            .ok_or_else(|| format!("Invalid variant {:?} - expected set=variant", arg))?;
+
+        self.find_variant_set(set.trim())?.select(variant.trim())
+    }
+
+    /// Replaces the materials of objects with those from the
+    /// active variants. Later sets take priority where
+    /// they assign to the same object.
+    pub fn apply_active_variants(&mut self)
+    {
+        let assignments = self.variant_sets.iter()
+            .filter_map(|set| set.active_variant())
+            .flat_map(|variant| variant.assignments.iter().cloned())
+            .collect::<Vec<_>>();
+
+        for (object, material) in assignments
+        {
+            let value = self.collection.map_item(object, |object, _| object.clone());
+            self.collection.update_value(object, Object { material, ..value });
+        }
+    }
+
+    pub fn probe_locations(&self) -> Vec<Point3>
+    {
+        // Skip the default probe - it exists until
+        // the first probe is added, so isn't a real probe
+
+        self.collection
+            .map_all_with_index(|index, probe: &Probe, collection| (collection.is_default(index), probe.location))
+            .into_iter()
+            .filter(|(is_default, _)| !is_default)
+            .map(|(_, location)| location)
+            .collect()
+    }
+
+    fn owned_objects_and_groups(&self) -> (HashSet<ObjectIndex>, HashSet<GroupIndex>)
+    {
+        let groups = self.collection.map_all(|group: &Group, _| group.clone());
+
+        let owned_objects = groups.iter().flat_map(|g| g.objects.iter().copied()).collect();
+        let owned_groups = groups.iter().flat_map(|g| g.groups.iter().copied()).collect();
+
+        (owned_objects, owned_groups)
+    }
+
+    #[cfg(feature = "ui")]
+    fn ui_outliner_group(&self, ui: &UiRenderer, index: GroupIndex, visiting: &mut Vec<GroupIndex>)
+    {
+        if visiting.contains(&index)
+        {
+            return;
+        }
+
+        if let Some(_node) = ui.imgui.tree_node_config(format!("Group {}", index.to_usize())).push()
+        {
+            visiting.push(index);
+
+            let group = self.collection.map_item(index, |group: &Group, _| group.clone());
+
+            for child in group.groups.iter()
+            {
+                self.ui_outliner_group(ui, *child, visiting);
+            }
+
+            for object in group.objects.iter()
+            {
+                ui.imgui.bullet_text(format!("Object {}", object.to_usize()));
+            }
+
+            visiting.pop();
+        }
+    }
+
+    pub fn build_furnace(&self, options: &RenderOptions, camera_override: Option<&Camera>, furnace: &Furnace, cache: &MeshCache) -> crate::scene::Scene
+    {
+        let camera = camera_override.unwrap_or(&self.camera);
+        let build = GeomBuildContext { cache, view: Some(LodView::new(camera, options)), bake_transforms: options.bake_transforms, octree_quality: options.octree_quality, cancelled: None };
+
+        // Surround the selected object with a uniformly emitting
+        // sphere that's always large enough to also contain the camera
+
+        let radius = furnace.radius.max(2.0 * (camera.location - camera.look_at).magnitude());
+
+        // The object is checked when the test starts,
+        // but it's skipped, rather than panicking, if it's
+        // not there
+
+        let mut objects = Vec::new();
+
+        if self.collection.contains(furnace.object)
+        {
+            objects.push(self.collection.map_item(furnace.object, |obj: &Object, collection| obj.build(collection, &build)));
+        }
+
+        objects.push(crate::object::Object::new(
+            Sphere::new(camera.look_at, radius),
+            Material::emit(Texture::solid(furnace.emission.into_linear()))));
 
         crate::scene::Scene::new(
             options.sampling_mode,
-            camera_override.unwrap_or(&self.camera).build(options),
+            camera.build(options),
             Vec::new(),
             objects)
     }
+
+    /// The indexes kept outside the collection - so
+    /// the items they refer to can't be removed
+    pub fn collect_indexes(&self, indexes: &mut HashSet<AnyIndex>)
+    {
+        indexes.extend(self.backplate.map(AnyIndex::Image));
+
+        for variant in self.material_variants.iter()
+        {
+            indexes.insert(AnyIndex::Object(variant.object));
+            indexes.insert(AnyIndex::Material(variant.material));
+        }
+
+        for (object, material) in self.variant_sets.iter().flat_map(|s| s.variants.iter()).flat_map(|v| v.assignments.iter())
+        {
+            indexes.insert(AnyIndex::Object(*object));
+            indexes.insert(AnyIndex::Material(*material));
+        }
+
+        for light in self.lighting_region.iter().flat_map(|r| r.lights.iter().flatten())
+        {
+            indexes.insert(AnyIndex::Object(*light));
+        }
+    }
+
+    /// Updates the indexes kept outside the collection
+    fn remap_indexes(&mut self, remap: &IndexRemap)
+    {
+        remap.apply_opt(&mut self.backplate);
+
+        for variant in self.material_variants.iter_mut()
+        {
+            remap.apply(&mut variant.object);
+            remap.apply(&mut variant.material);
+        }
+
+        for (object, material) in self.variant_sets.iter_mut().flat_map(|s| s.variants.iter_mut()).flat_map(|v| v.assignments.iter_mut())
+        {
+            remap.apply(object);
+            remap.apply(material);
+        }
+
+        for light in self.lighting_region.iter_mut().flat_map(|r| r.lights.iter_mut().flatten())
+        {
+            remap.apply(light);
+        }
+
+        remap.apply(&mut self.furnace.object);
+    }
+
+    /// Removes an item, moving the later items of the same type down,
+    /// and updating every index that refers to them. Items that are
+    /// still used can't be removed.
+    pub fn remove<I: Index>(&mut self, index: I) -> Result<(), String>
+    {
+        let mut used = HashSet::new();
+        self.collect_indexes(&mut used);
+
+        if used.contains(&index.to_any())
+        {
+            return Err(format!("{:?} is used by the scene", index.to_any()));
+        }
+
+        let remap = self.collection.remove(index)?;
+        self.remap_indexes(&remap);
+        Ok(())
+    }
+
+    /// Swaps two items of the same type, updating
+    /// every index that refers to them
+    pub fn swap<I: Index>(&mut self, a: I, b: I)
+    {
+        let remap = self.collection.swap(a, b);
+        self.remap_indexes(&remap);
+    }
+
+    /// The objects that use the item (e.g. a material or
+    /// texture), either directly or through other items
+    pub fn objects_using(&self, index: AnyIndex) -> Vec<ObjectIndex>
+    {
+        self.collection.reverse_index().all_referrers(index)
+            .into_iter()
+            .filter_map(|i| match i { AnyIndex::Object(o) => Some(o), _ => None })
+            .collect()
+    }
+
+    /// World space bounds of the object's geometry.
+    /// Any transforms from groups containing the object are ignored.
+    pub fn object_bounds(&self, object: ObjectIndex) -> Option<crate::geom::Aabb>
+    {
+        let geom = self.collection.map_item(object, |o, _| o.geom);
+
+        self.collection.map_item(geom, |g, c| g.world_bounds(c))
+    }
+
+    pub fn replace_material(&mut self, objects: &[ObjectIndex], material: MaterialIndex)
+    {
+        for index in objects.iter()
+        {
+            let mut object = self.collection.map_item(*index, |o, _| o.clone());
+            object.material = material;
+            self.collection.update_value(*index, object);
+        }
+    }
+
+    /// Bakes the lighting onto the given object's UV layout.
+    /// Any transforms from groups containing the object are ignored.
+    pub fn build_baker(&self, options: &RenderOptions, object: ObjectIndex, bake_options: &BakeOptions) -> Baker
+    {
+        let triangles = self.collection.map_item(object, |obj: &Object, collection|
+            collection.map_item(obj.geom, |geom, collection| geom.world_triangles(collection)));
+
+        Baker::new(self.build(options, None), triangles, bake_options.clone())
+    }
+}
+
+/// Reports progress as each object is built
+pub struct ObjectBuildProgress<'a>
+{
+    monitor: &'a mut dyn BuildMonitor,
+    built: usize,
+    total: usize,
 }
 
+impl<'a> ObjectBuildProgress<'a>
+{
+    fn new(monitor: &'a mut dyn BuildMonitor, total: usize) -> Self
+    {
+        ObjectBuildProgress { monitor, built: 0, total }
+    }
+
+    /// Returns false if the build has been cancelled
+    pub fn object_built(&mut self) -> bool
+    {
+        self.built += 1;
+
+        self.monitor.progress(BuildProgress
+        {
+            stage: "Building objects".to_owned(),
+            fraction: ((self.built as Scalar) / (self.total.max(1) as Scalar)).min(1.0),
+        });
+
+        !self.monitor.is_cancelled()
+    }
+}
+
+#[cfg(feature = "ui")]
 impl UiDisplay for Scene
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -53,11 +473,51 @@ impl UiDisplay for Scene
             .push()
         {
             self.camera.ui_display(ui, "Camera");
+            self.backplate.ui_display(ui, "Backplate");
+
+            if let Some(atmosphere) = &self.atmosphere
+            {
+                if let Some(_atmosphere) = ui.imgui.tree_node_config("Atmosphere").push()
+                {
+                    atmosphere.ui_display(ui, "Atmosphere");
+                }
+            }
+
+            if let Some(lighting_region) = &self.lighting_region
+            {
+                if let Some(_lighting) = ui.imgui.tree_node_config("Lighting Region").push()
+                {
+                    lighting_region.ui_display(ui, "Lighting Region");
+                }
+            }
+
+            if let Some(_outliner) = ui.imgui.tree_node_config("Outliner").push()
+            {
+                let (owned_objects, owned_groups) = self.owned_objects_and_groups();
+
+                for index in self.collection.map_all_with_index(|index, _: &Group, _| index)
+                {
+                    if !owned_groups.contains(&index)
+                    {
+                        self.ui_outliner_group(ui, index, &mut Vec::new());
+                    }
+                }
+
+                for index in self.collection.map_all_with_index(|index, _: &Object, _| index)
+                {
+                    if !owned_objects.contains(&index)
+                    {
+                        ui.imgui.bullet_text(format!("Object {}", index.to_usize()));
+                    }
+                }
+            }
+
             self.collection.ui_display(ui, "Collections");
         }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Scene
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
@@ -70,7 +530,57 @@ impl UiEdit for Scene
             .push()
         {
             result |= self.camera.ui_edit(ui, "Camera");
-            result |= self.collection.ui_edit(ui, "Collections");
+            result |= self.backplate.ui_edit(ui, "Backplate");
+
+            if let Some(_atmosphere) = ui.imgui.tree_node_config("Atmosphere").push()
+            {
+                let mut enabled = self.atmosphere.is_some();
+
+                if ui.imgui.checkbox("Enabled", &mut enabled)
+                {
+                    self.atmosphere = if enabled { Some(Atmosphere::default()) } else { None };
+                    result = true;
+                }
+
+                if let Some(atmosphere) = &mut self.atmosphere
+                {
+                    result |= atmosphere.ui_edit(ui, "Atmosphere");
+                }
+            }
+
+            if let Some(_lighting) = ui.imgui.tree_node_config("Lighting Region").push()
+            {
+                let mut overridden = self.lighting_region.is_some();
+
+                if ui.imgui.checkbox("Override", &mut overridden)
+                {
+                    self.lighting_region = if overridden { Some(LightingRegion::default()) } else { None };
+                    result = true;
+                }
+
+                if let Some(lighting_region) = &mut self.lighting_region
+                {
+                    result |= lighting_region.ui_edit(ui, "Lighting Region");
+                }
+            }
+
+            if !self.variant_sets.is_empty()
+            {
+                if let Some(_variants) = ui.imgui.tree_node_config("Variants").push()
+                {
+                    for set in self.variant_sets.iter_mut()
+                    {
+                        result |= set.ui_select(ui);
+                    }
+                }
+            }
+
+            let mut used = HashSet::new();
+            self.collect_indexes(&mut used);
+
+            let (changed, remap) = self.collection.ui_edit_remapped(ui, "Collections", &used);
+            self.remap_indexes(&remap);
+            result |= changed;
         }
 
         result
@@ -0,0 +1,189 @@
+use crate::color::LinearRGB;
+use crate::desc::edit::{Geom, Group, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::geom::Aabb;
+use crate::indexed::{GeomIndex, GroupIndex, MaterialIndex, ObjectIndex};
+use crate::math::{EPSILON, Scalar, ScalarConsts};
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::{Dir3, Point3};
+
+/// A three-point lighting setup for product renders - key, fill
+/// and rim softboxes, a bounce card and a backdrop sweep, placed
+/// around a target relative to the camera.
+#[derive(Clone, Debug)]
+pub struct StudioRig
+{
+    /// Emitted radiance of the key light
+    pub key_intensity: Scalar,
+    /// Fill and rim light intensities, relative to the key light
+    pub fill: Scalar,
+    pub rim: Scalar,
+    /// Width of the softboxes - zero sizes them to the target
+    pub softbox_size: Scalar,
+}
+
+impl Default for StudioRig
+{
+    fn default() -> Self
+    {
+        StudioRig
+        {
+            key_intensity: 4.0,
+            fill: 0.4,
+            rim: 0.8,
+            softbox_size: 0.0,
+        }
+    }
+}
+
+impl StudioRig
+{
+    /// Adds the rig to the scene, returning a group containing it
+    pub fn build(&self, scene: &mut Scene, target: &Aabb) -> GroupIndex
+    {
+        let center = (target.min + target.max) / 2.0;
+        let radius = ((target.max - target.min).magnitude() / 2.0).max(EPSILON);
+        let distance = 3.0 * radius;
+        let softbox = if self.softbox_size > 0.0 { self.softbox_size } else { 1.5 * radius };
+
+        // Lights are placed relative to the camera's
+        // (horizontal) view of the target
+
+        let up = Dir3::new(0.0, 1.0, 0.0);
+        let mut forward = scene.camera.location - center;
+        forward.y = 0.0;
+        let forward = if forward.magnitude() > EPSILON { forward.normalized() } else { Dir3::new(0.0, 0.0, 1.0) };
+        let right = up.cross(forward).normalized();
+
+        let key_dir = (forward - right + 0.8 * up).normalized();
+        let fill_dir = (forward + right + 0.2 * up).normalized();
+        let rim_dir = (-forward + 0.5 * right + up).normalized();
+        let bounce_dir = (right - forward).normalized();
+
+        let objects = vec![
+            self.softbox(scene, "Studio Key", center + distance * key_dir, center, softbox, self.key_intensity),
+            self.softbox(scene, "Studio Fill", center + distance * fill_dir, center, softbox, self.key_intensity * self.fill),
+            self.softbox(scene, "Studio Rim", center + distance * rim_dir, center, 0.5 * softbox, self.key_intensity * self.rim),
+            bounce_card(scene, center + 0.7 * distance * bounce_dir, center, 1.5 * softbox),
+            backdrop(scene, target, center, forward, right, radius, distance),
+        ];
+
+        scene.collection.push_named(Group { objects, groups: Vec::new(), transform: Transform::new() }, "Studio Rig".to_owned())
+    }
+
+    fn softbox(&self, scene: &mut Scene, name: &str, location: Point3, target: Point3, size: Scalar, intensity: Scalar) -> ObjectIndex
+    {
+        let geom = quad(scene, location, (target - location).normalized(), size);
+        let material = solid_material(scene, intensity, |texture| Material::Emit{ texture });
+
+        scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, name.to_owned())
+    }
+}
+
+fn bounce_card(scene: &mut Scene, location: Point3, target: Point3, size: Scalar) -> ObjectIndex
+{
+    let geom = quad(scene, location, (target - location).normalized(), size);
+    let material = solid_material(scene, 0.9, |texture| Material::Diffuse{ texture, normal_texture: None });
+
+    scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, "Studio Bounce Card".to_owned())
+}
+
+/// A floor under the target that curves up into a wall
+/// behind it, so there's no visible horizon
+fn backdrop(scene: &mut Scene, target: &Aabb, center: Point3, forward: Dir3, right: Dir3, radius: Scalar, distance: Scalar) -> ObjectIndex
+{
+    const CURVE_SEGMENTS: usize = 12;
+
+    let up = Dir3::new(0.0, 1.0, 0.0);
+    let base = Point3::new(center.x, target.min.y, center.z);
+    let curve_radius = 1.5 * radius;
+    let half_width = 3.0 * distance;
+
+    // The profile, as (forward, up) offsets from the base
+
+    let mut profile = vec![(distance, 0.0), (-radius, 0.0)];
+
+    for i in 1..=CURVE_SEGMENTS
+    {
+        let angle = 0.5 * ScalarConsts::PI * (i as Scalar) / (CURVE_SEGMENTS as Scalar);
+
+        profile.push((-radius - curve_radius * angle.sin(), curve_radius * (1.0 - angle.cos())));
+    }
+
+    profile.push((-radius - curve_radius, 4.0 * radius + distance));
+
+    let point = |(f, u): (Scalar, Scalar), side: Scalar| base + f * forward + u * up + side * half_width * right;
+
+    let triangles = profile.windows(2)
+        .flat_map(|pair|
+        {
+            let corners = [point(pair[0], -1.0), point(pair[0], 1.0), point(pair[1], 1.0), point(pair[1], -1.0)];
+            quad_triangles(corners)
+        })
+        .collect();
+
+    let geom = scene.collection.push_named(Geom::Mesh{ triangles, transform: Transform::new(), lod: None }, "Studio Backdrop".to_owned());
+    let material = solid_material(scene, 0.8, |texture| Material::Diffuse{ texture, normal_texture: None });
+
+    scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, "Studio Backdrop".to_owned())
+}
+
+fn solid_material<F>(scene: &mut Scene, value: Scalar, material: F) -> MaterialIndex
+    where F: FnOnce(crate::indexed::TextureIndex) -> Material
+{
+    let texture = scene.collection.push(Texture::Solid(LinearRGB::grey(value).into()));
+
+    scene.collection.push(material(texture))
+}
+
+/// A square facing along the normal
+fn quad(scene: &mut Scene, center: Point3, normal: Dir3, size: Scalar) -> GeomIndex
+{
+    let helper = if normal.y.abs() > 0.9 { Dir3::new(1.0, 0.0, 0.0) } else { Dir3::new(0.0, 1.0, 0.0) };
+    let u = helper.cross(normal).normalized() * (size / 2.0);
+    let v = normal.cross(u).normalized() * (size / 2.0);
+
+    let triangles = quad_triangles([center - u - v, center + u - v, center + u + v, center - u + v]);
+
+    scene.collection.push(Geom::Mesh{ triangles, transform: Transform::new(), lod: None })
+}
+
+fn quad_triangles(corners: [Point3; 4]) -> Vec<Triangle>
+{
+    let vertex = |i: usize, u: Scalar, v: Scalar| TriangleVertex { location: corners[i], texture_coords: Point3::new(u, v, 0.0), opt_color: None, opt_normal: None };
+
+    vec![
+        Triangle { vertices: [vertex(0, 0.0, 0.0), vertex(1, 1.0, 0.0), vertex(2, 1.0, 1.0)] },
+        Triangle { vertices: [vertex(0, 0.0, 0.0), vertex(2, 1.0, 1.0), vertex(3, 0.0, 1.0)] },
+    ]
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for StudioRig
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        ui.display_float("Key Intensity", &self.key_intensity);
+        ui.display_float("Fill", &self.fill);
+        ui.display_float("Rim", &self.rim);
+        ui.display_float("Softbox Size", &self.softbox_size);
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for StudioRig
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+        result |= ui.edit_float("Key Intensity", &mut self.key_intensity);
+        result |= ui.edit_float("Fill", &mut self.fill);
+        result |= ui.edit_float("Rim", &mut self.rim);
+        result |= ui.edit_float("Softbox Size", &mut self.softbox_size);
+        result
+    }
+}
@@ -1,12 +1,15 @@
 use std::collections::HashSet;
 
+use crate::color::LinearRGB;
 use crate::desc::edit::Color;
-use crate::indexed::{IndexedValue, IndexedCollection, AnyIndex, ImageIndex, TextureIndex};
+use crate::indexed::{IndexedValue, IndexedCollection, IndexRemap, AnyIndex, ImageIndex, TextureIndex};
 use crate::math::Scalar;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
 use crate::vec::{Mat4, Point3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Texture
 {
     Solid(Color),
@@ -19,6 +22,7 @@ pub enum Texture
         rotate: Scalar,
         translate: Point3,
     },
+    Expression(String),
 }
 
 impl Texture
@@ -38,6 +42,14 @@ impl Texture
                 transform.translate_3d(*translate);
                 crate::texture::Texture::image(base_color.into_linear(), image, transform)
             },
+            Texture::Expression(script) =>
+            {
+                // Scripts are checked when they're created, but
+                // may since have been edited in the UI
+
+                crate::texture::Texture::expression(script)
+                    .unwrap_or_else(|_| crate::texture::Texture::Solid(LinearRGB::new(1.0, 0.0, 1.0, 1.0)))
+            },
         }
     }
 
@@ -48,9 +60,11 @@ impl Texture
             Texture::Solid(_) => "Solid",
             Texture::Checkerboard(_,_) => "Checkerboard",
             Texture::Image{..} => "Image",
+            Texture::Expression(_) => "Expression",
         }
     }
 
+    #[cfg(feature = "ui")]
     fn ui_render_combo(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
         let mut result = false;
@@ -65,7 +79,8 @@ impl Texture
                     image: ImageIndex::default(),
                     scale: Point3::new(1.0, 1.0, 1.0),
                     rotate: 0.0,
-                    translate: Point3::new(0.0, 0.0, 0.0)} ]
+                    translate: Point3::new(0.0, 0.0, 0.0)},
+                Texture::Expression("u".to_string()) ]
             {
                 let entry_tag = entry.ui_tag();
                 let selected = entry_tag == cur_tag;
@@ -98,8 +113,20 @@ impl IndexedValue for Texture
 {
     type Index = TextureIndex;
     
-    fn collect_indexes(&self, _indexes: &mut HashSet<AnyIndex>)
+    fn collect_indexes(&self, indexes: &mut HashSet<AnyIndex>)
+    {
+        if let Texture::Image{ image, .. } = self
+        {
+            indexes.insert(AnyIndex::Image(*image));
+        }
+    }
+
+    fn remap_indexes(&mut self, remap: &IndexRemap)
     {
+        if let Texture::Image{ image, .. } = self
+        {
+            remap.apply(image);
+        }
     }
 
     fn summary(&self) -> String
@@ -108,6 +135,7 @@ impl IndexedValue for Texture
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Texture
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -135,10 +163,16 @@ impl UiDisplay for Texture
                 ui.display_angle("Rotate", rotate);
                 ui.display_vec3("Translate", translate);
             },
+            Texture::Expression(script) =>
+            {
+                ui.imgui.label_text(label, "Expression");
+                ui.imgui.text(script);
+            },
         }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Texture
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
@@ -164,7 +198,11 @@ impl UiEdit for Texture
                 result |= ui.edit_vec3("Scale", scale);
                 result |= ui.edit_angle("Rotate", rotate);
                 result |= ui.edit_vec3("Translate", translate);
-            }
+            },
+            Texture::Expression(script) =>
+            {
+                result |= ui.imgui.input_text_multiline("Script", script, [0.0, 100.0]).build();
+            },
         }
 
         ui.imgui.unindent();
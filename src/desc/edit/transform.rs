@@ -1,8 +1,10 @@
-use crate::indexed::{IndexedValue, TransformIndex, IndexedCollection};
+use crate::indexed::{IndexedValue, IndexRemap, TransformIndex, IndexedCollection};
 use crate::math::Scalar;
 use crate::desc::edit::geom::Aabb;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiTaggedEnum};
 use crate::vec::{Vec3, Mat4, Point3, Quaternion};
+use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum TransformStageTag
@@ -15,7 +17,7 @@ pub enum TransformStageTag
     Matrix,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransformStage
 {
     Scale(Scalar),
@@ -26,7 +28,7 @@ pub enum TransformStage
     Matrix(Mat4),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transform
 {
     pub pre: Option<TransformIndex>,
@@ -46,8 +48,14 @@ impl IndexedValue for Transform
 {
     type Index = TransformIndex;
 
-    fn collect_indexes(&self, _indexes: &mut std::collections::HashSet<crate::indexed::AnyIndex>)
+    fn collect_indexes(&self, indexes: &mut std::collections::HashSet<crate::indexed::AnyIndex>)
     {
+        self.collect_transform_indexes(indexes);
+    }
+
+    fn remap_indexes(&mut self, remap: &IndexRemap)
+    {
+        self.remap_transform_indexes(remap);
     }
 
     fn summary(&self) -> String
@@ -58,6 +66,19 @@ impl IndexedValue for Transform
 
 impl Transform
 {
+    /// The pre and post transforms, which are also
+    /// referenced by the items that contain this
+    pub fn collect_transform_indexes(&self, indexes: &mut std::collections::HashSet<crate::indexed::AnyIndex>)
+    {
+        indexes.extend(self.pre.iter().chain(self.post.iter()).map(|t| crate::indexed::AnyIndex::Transform(*t)));
+    }
+
+    pub fn remap_transform_indexes(&mut self, remap: &IndexRemap)
+    {
+        remap.apply_opt(&mut self.pre);
+        remap.apply_opt(&mut self.post);
+    }
+
     pub fn new() -> Self
     {
         Transform { pre: None, stages: Vec::new(), post: None }
@@ -133,6 +154,7 @@ impl Transform
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiTaggedEnum for TransformStage
 {
     type TagEnum = TransformStageTag;
@@ -189,6 +211,7 @@ impl UiTaggedEnum for TransformStage
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for TransformStage
 {
     fn ui_display(&self, ui: &crate::ui::UiRenderer, label: &str)
@@ -223,6 +246,7 @@ impl UiDisplay for TransformStage
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for TransformStage
 {
     fn ui_edit(&mut self, ui: &crate::ui::UiRenderer, label: &str) -> bool
@@ -275,6 +299,7 @@ impl UiEdit for TransformStage
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Transform
 {
     fn ui_display(&self, ui: &crate::ui::UiRenderer, label: &str)
@@ -289,6 +314,7 @@ impl UiDisplay for Transform
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Transform
 {
     fn ui_edit(&mut self, ui: &crate::ui::UiRenderer, label: &str) -> bool
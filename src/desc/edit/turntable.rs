@@ -0,0 +1,89 @@
+use crate::desc::edit::Camera;
+use crate::math::{Scalar, ScalarConsts};
+#[cfg(feature = "ui")]
+use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+use crate::vec::Dir3;
+
+/// Camera keyframes for one full orbit around
+/// the point the camera is looking at
+#[derive(Clone, Debug)]
+pub struct Turntable
+{
+    pub frames: usize,
+    /// Distance from the look-at point - zero keeps
+    /// the camera's current distance
+    pub radius: Scalar,
+    /// Angle above the horizontal, in degrees
+    pub elevation: Scalar,
+}
+
+impl Default for Turntable
+{
+    fn default() -> Self
+    {
+        Turntable
+        {
+            frames: 36,
+            radius: 0.0,
+            elevation: 15.0,
+        }
+    }
+}
+
+impl Turntable
+{
+    /// The keyframes, starting from the camera's current
+    /// direction and orbiting around the vertical axis
+    pub fn keyframes(&self, camera: &Camera) -> Vec<Camera>
+    {
+        let offset = camera.location - camera.look_at;
+        let radius = if self.radius > 0.0 { self.radius } else { offset.magnitude() };
+        let start = offset.z.atan2(offset.x);
+        let elevation = self.elevation.to_radians();
+        let frames = self.frames.max(1);
+
+        (0..frames)
+            .map(|frame|
+            {
+                let angle = start + 2.0 * ScalarConsts::PI * (frame as Scalar) / (frames as Scalar);
+                let dir = Dir3::new(elevation.cos() * angle.cos(), elevation.sin(), elevation.cos() * angle.sin());
+
+                Camera
+                {
+                    location: camera.look_at + radius * dir,
+                    look_at: camera.look_at,
+                    up: Dir3::new(0.0, 1.0, 0.0),
+                    ..camera.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for Turntable
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        let _id = ui.imgui.push_id(label);
+
+        ui.imgui.text(format!("Frames: {}", self.frames));
+        ui.display_float("Radius", &self.radius);
+        ui.display_float("Elevation", &self.elevation);
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for Turntable
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let _id = ui.imgui.push_id(label);
+
+        let mut result = false;
+        result |= ui.imgui.input_scalar("Frames", &mut self.frames).build();
+        result |= ui.edit_float("Radius", &mut self.radius);
+        result |= ui.edit_float("Elevation", &mut self.elevation);
+        result
+    }
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use crate::indexed::{MaterialIndex, ObjectIndex};
+#[cfg(feature = "ui")]
+use crate::ui::UiRenderer;
+
+/// One look in a variant set, e.g. "Red Paint",
+/// and the materials it gives to each object
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Variant
+{
+    pub name: String,
+    pub assignments: Vec<(ObjectIndex, MaterialIndex)>,
+}
+
+/// A named group of alternative looks, e.g. "Paint". At most
+/// one variant is active - its materials replace the objects'
+/// own when the scene is rendered. With none active,
+/// the objects keep their own materials.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VariantSet
+{
+    pub name: String,
+    pub variants: Vec<Variant>,
+    #[serde(default)]
+    pub active: Option<usize>,
+}
+
+impl VariantSet
+{
+    pub fn new(name: String) -> Self
+    {
+        VariantSet { name, variants: Vec::new(), active: None }
+    }
+
+    /// Adds an assignment to the named variant,
+    /// adding the variant if it doesn't exist yet
+    pub fn assign(&mut self, variant: &str, object: ObjectIndex, material: MaterialIndex)
+    {
+        let index = match self.variants.iter().position(|v| v.name == variant)
+        {
+            Some(index) => index,
+            None =>
+            {
+                self.variants.push(Variant { name: variant.to_owned(), assignments: Vec::new() });
+                self.variants.len() - 1
+            },
+        };
+
+        self.variants[index].assignments.push((object, material));
+    }
+
+    /// Selects a variant by name - "none" selects
+    /// the objects' own materials
+    pub fn select(&mut self, variant: &str) -> Result<(), String>
+    {
+        if variant.eq_ignore_ascii_case("none")
+        {
+            self.active = None;
+            return Ok(());
+        }
+
+        let index = self.variants.iter().position(|v| v.name == variant)
+            .ok_or_else(|| format!("Variant set {:?} has no variant {:?}", self.name, variant))?;
+
+        self.active = Some(index);
+        Ok(())
+    }
+
+    pub fn active_variant(&self) -> Option<&Variant>
+    {
+        self.active.and_then(|index| self.variants.get(index))
+    }
+
+    #[cfg(feature = "ui")]
+    pub fn ui_select(&mut self, ui: &UiRenderer) -> bool
+    {
+        let mut result = false;
+        let cur_name = self.active_variant().map(|v| v.name.clone()).unwrap_or_else(|| "None".to_owned());
+
+        if let Some(_combo) = ui.imgui.begin_combo(&self.name, &cur_name)
+        {
+            let entries = std::iter::once(None).chain((0..self.variants.len()).map(Some)).collect::<Vec<_>>();
+
+            for entry in entries
+            {
+                let name = entry.map(|index| self.variants[index].name.clone()).unwrap_or_else(|| "None".to_owned());
+                let selected = entry == self.active;
+
+                if selected
+                {
+                    ui.imgui.set_item_default_focus();
+                }
+
+                if ui.imgui.selectable_config(&name).selected(selected).build()
+                {
+                    self.active = entry;
+                    result = true;
+                }
+            }
+        }
+
+        result
+    }
+}
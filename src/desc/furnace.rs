@@ -0,0 +1,23 @@
+use crate::api::SceneBuilder;
+use crate::color::SRGB;
+use crate::desc::SceneDescription;
+use crate::vec::Point3;
+
+/// A grey sphere inside a uniformly glowing one - with
+/// energy-conserving materials it should be lit evenly
+pub fn generate_description() -> SceneDescription
+{
+    let mut builder = SceneBuilder::new();
+
+    builder.camera(Point3::new(0.0, 0.0, 9.0), Point3::new(0.0, 0.0, 0.0), 40.0);
+
+    let sphere = builder.sphere(Point3::new(0.0, 0.0, 0.0), 1.0);
+    let grey = builder.diffuse(SRGB::new(0.5, 0.5, 0.5, 1.0));
+    builder.object(sphere, grey);
+
+    let surround = builder.sphere(Point3::new(0.0, 0.0, 0.0), 10.0);
+    let white = builder.emit(SRGB::new(1.0, 1.0, 1.0, 1.0));
+    builder.object(surround, white);
+
+    builder.description()
+}
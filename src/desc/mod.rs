@@ -1,9 +1,20 @@
-use crate::exec::{Context, ExecResult, parse};
+use crate::exec::{Completion, Context, ExecLimits, ExecResult, optimize, parse};
+use crate::geom::MeshCache;
+use crate::indexed::Index;
+use crate::math::Scalar;
 use crate::render::RenderOptions;
 use crate::scene::Scene;
+use crate::vec::Point3;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 mod beam;
 mod cornell;
+mod furnace;
 pub mod edit;
 mod veach;
 
@@ -21,6 +32,44 @@ pub enum SceneSelection
 {
     Standard(StandardScene),
     Edit(edit::Scene),
+    Furnace(edit::Scene, edit::Furnace),
+}
+
+/// The progress of a scene build - the current
+/// stage, and the fraction of it that's complete.
+#[derive(Clone, Debug)]
+pub struct BuildProgress
+{
+    pub stage: String,
+    pub fraction: Scalar,
+}
+
+/// Receives progress while a scene is built, and
+/// allows the build to be cancelled part-way through.
+pub trait BuildMonitor
+{
+    fn progress(&mut self, progress: BuildProgress);
+    fn is_cancelled(&self) -> bool;
+
+    /// A flag that's set when the build is cancelled, so
+    /// long running work (e.g. octree builds) can check it
+    /// without access to the monitor
+    fn cancelled_flag(&self) -> Option<Arc<AtomicBool>>
+    {
+        None
+    }
+}
+
+impl BuildMonitor for ()
+{
+    fn progress(&mut self, _progress: BuildProgress)
+    {
+    }
+
+    fn is_cancelled(&self) -> bool
+    {
+        false
+    }
 }
 
 #[derive(Clone)]
@@ -38,47 +87,145 @@ impl SceneDescription
         {
             StandardScene::BeamExample => beam::generate_description(),
             StandardScene::Cornell => cornell::generate_description(),
-            StandardScene::Furnace => Self::new_edit(&run_script(include_str!("furnace.beam")).expect("Error in in-built script")),
+            StandardScene::Furnace => furnace::generate_description(),
             StandardScene::Veach => veach::generate_description(),
         }
     }
 
+    /// The scene is rendered with its active variants
     pub fn new_edit(scene: &edit::Scene) -> Self
     {
+        let mut scene = scene.clone();
+        scene.apply_active_variants();
+
         SceneDescription
         {
             camera: scene.camera.clone(),
-            selection: SceneSelection::Edit(scene.clone()),
+            selection: SceneSelection::Edit(scene),
         }
     }
 
-    pub fn build_scene(&self, options: &RenderOptions) -> Scene
+    /// Tests the scene's furnace object - an error
+    /// if it doesn't refer to an object in the scene
+    pub fn new_furnace(scene: &edit::Scene) -> Result<Self, String>
+    {
+        let furnace = scene.furnace.clone();
+
+        if !scene.collection.contains(furnace.object)
+        {
+            return Err(format!("The furnace test's object {} doesn't exist", furnace.object.to_usize()));
+        }
+
+        let mut scene = scene.clone();
+        scene.apply_active_variants();
+
+        Ok(SceneDescription
+        {
+            camera: scene.camera.clone(),
+            selection: SceneSelection::Furnace(scene, furnace),
+        })
+    }
+
+    pub fn probe_locations(&self) -> Vec<Point3>
     {
         match &self.selection
+        {
+            SceneSelection::Standard(_) => Vec::new(),
+            SceneSelection::Edit(edit) | SceneSelection::Furnace(edit, _) => edit.probe_locations(),
+        }
+    }
+
+    pub fn build_scene(&self, options: &RenderOptions) -> Scene
+    {
+        self.build_scene_with_monitor(options, &mut (), &MeshCache::new()).expect("Build can't be cancelled")
+    }
+
+    /// Builds the scene, returning None if the monitor cancels
+    /// it part-way through. Meshes are re-used from the cache.
+    pub fn build_scene_with_monitor(&self, options: &RenderOptions, monitor: &mut dyn BuildMonitor, cache: &MeshCache) -> Option<Scene>
+    {
+        let scene = match &self.selection
         {
             SceneSelection::Standard(standard) =>
             {
-                match standard
+                Some(match standard
                 {
                     StandardScene::BeamExample => beam::generate_scene(self, options),
                     StandardScene::Cornell => cornell::generate_scene(self, options),
                     StandardScene::Furnace => panic!("Furnace is now a script"),
                     StandardScene::Veach => veach::generate_scene(self, options),
-                }
+                })
             },
             SceneSelection::Edit(edit) =>
             {
-                edit.build(options, Some(&self.camera))
-            }
-        }
+                edit.build_with_monitor(options, Some(&self.camera), monitor, cache)
+            },
+            SceneSelection::Furnace(edit, furnace) =>
+            {
+                Some(edit.build_furnace(options, Some(&self.camera), furnace, cache))
+            },
+        };
+
+        scene.map(|s| s.with_trace_options(options.trace_options()))
     }
 }
 
 pub fn run_script(script: &str) -> ExecResult<edit::Scene>
+{
+    run_script_with_vars(script).map(|(scene, _)| scene)
+}
+
+/// Runs a script, also returning the variables it defined
+/// so they can be offered as completions when editing.
+pub fn run_script_with_vars(script: &str) -> ExecResult<(edit::Scene, Vec<Completion>)>
+{
+    run_script_with_limits(script, None, ExecLimits::default(), Arc::new(AtomicBool::new(false)))
+}
+
+/// Runs a script, stopping with an error if it exceeds
+/// the limits or the cancelled flag is set. If it's from
+/// a file, the scripts it includes are relative to it.
+/// It runs on a thread with enough stack for the depth
+/// limit, and blocks until it's complete.
+pub fn run_script_with_limits(script: &str, script_file: Option<&Path>, limits: ExecLimits, cancelled: Arc<AtomicBool>) -> ScriptResult
+{
+    if cfg!(all(target_arch = "wasm32", not(target_os = "wasi")))
+    {
+        // There are no threads, so it has to fit
+        // in what's left of the browser's (small) stack
+
+        return evaluate_script(script, script_file, limits, cancelled, WASM_SCRIPT_STACK_SIZE);
+    }
+
+    std::thread::scope(|scope|
+    {
+        std::thread::Builder::new()
+            .name("script".to_owned())
+            .stack_size(SCRIPT_THREAD_STACK_SIZE)
+            .spawn_scoped(scope, || evaluate_script(script, script_file, limits, cancelled, SCRIPT_THREAD_STACK_SIZE))
+            .expect("Could not start script thread")
+            .join()
+            .unwrap()
+    })
+}
+
+/// Stops with an error before using more than the
+/// stack size, less some to spare for inbuilt functions
+fn evaluate_script(script: &str, script_file: Option<&Path>, limits: ExecLimits, cancelled: Arc<AtomicBool>, stack_size: usize) -> ScriptResult
 {
     let expressions = parse(script)?;
 
     let mut context = Context::new_with_state(edit::Scene::new());
+    context.set_limits(limits);
+    context.set_cancel_flag(cancelled);
+    context.set_stack_size(stack_size - (stack_size / 4));
+
+    if let Some(script_file) = script_file
+    {
+        context.set_script_file(script_file);
+    }
+
+    let expressions = optimize(expressions, &context);
 
     for exp in expressions
     {
@@ -87,5 +234,88 @@ pub fn run_script(script: &str) -> ExecResult<edit::Scene>
 
     let scene = context.with_app_state::<edit::Scene, _, _>(|scene| Ok(scene.clone()))?;
 
-    Ok(scene)
+    let vars = context.get_script_vars()
+        .iter()
+        .map(|(name, value)| Completion::from_var(name, value))
+        .collect();
+
+    Ok((scene, vars))
+}
+
+pub type ScriptResult = ExecResult<(edit::Scene, Vec<Completion>)>;
+
+/// Deeply recursive scripts need more stack
+/// than the default for a new thread.
+const SCRIPT_THREAD_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Where there are no threads, scripts share the caller's
+/// stack - this is half of the default WASM stack
+const WASM_SCRIPT_STACK_SIZE: usize = 512 * 1024;
+
+/// Runs a script on a background thread, so that a slow
+/// script doesn't block the UI - poll `take_result`.
+/// Dropping the runner cancels the script.
+pub struct ScriptRunner
+{
+    thread: Option<JoinHandle<ScriptResult>>,
+    cancelled: Arc<AtomicBool>,
+    start: Instant,
+}
+
+impl ScriptRunner
+{
+    pub fn new(script: String, script_file: Option<PathBuf>, limits: ExecLimits) -> Self
+    {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
+
+        let thread = std::thread::Builder::new()
+            .name("script".to_owned())
+            .stack_size(SCRIPT_THREAD_STACK_SIZE)
+            .spawn(move || evaluate_script(&script, script_file.as_deref(), limits, thread_cancelled, SCRIPT_THREAD_STACK_SIZE))
+            .expect("Could not start script thread");
+
+        ScriptRunner { thread: Some(thread), cancelled, start: Instant::now() }
+    }
+
+    pub fn elapsed(&self) -> Duration
+    {
+        self.start.elapsed()
+    }
+
+    /// Asks the script to stop - it still completes,
+    /// with a cancellation error.
+    pub fn cancel(&self)
+    {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool
+    {
+        self.thread.as_ref().map(|t| t.is_finished()).unwrap_or(true)
+    }
+
+    /// Returns the script's result, once it has completed.
+    pub fn take_result(&mut self) -> Option<ScriptResult>
+    {
+        if !self.is_finished()
+        {
+            return None;
+        }
+
+        self.thread.take().map(|t| t.join().unwrap())
+    }
+}
+
+impl Drop for ScriptRunner
+{
+    fn drop(&mut self)
+    {
+        self.cancel();
+
+        if let Some(thread) = self.thread.take()
+        {
+            let _ = thread.join();
+        }
+    }
 }
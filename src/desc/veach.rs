@@ -1,4 +1,3 @@
-use crate::camera::Camera;
 use crate::color::SRGB;
 use crate::desc::{SceneDescription, SceneSelection, StandardScene};
 use crate::geom::{Aabb, Rectangle, Sphere, bounds::BoundedSurface, csg::Merge, csg::Difference};
@@ -21,6 +20,8 @@ pub fn generate_description() -> SceneDescription
             look_at: Point3::new(-0.390985, 10.182305, 0.0),
             up: Point3::new(0.0, 0.0, 1.0),
             fov: 45.0,
+            aperture: 0.0,
+            focus_distance: 0.0,
         },
         selection: SceneSelection::Standard(StandardScene::Veach),
     }
@@ -129,7 +130,7 @@ pub fn generate_scene(desc: &SceneDescription, options: &RenderOptions) -> Scene
 
     Scene::new(
         options.sampling_mode,
-        Camera::new(desc.camera.location, desc.camera.look_at, desc.camera.up, desc.camera.fov, (options.width as f64) / (options.height as f64)),
+        desc.camera.build(options),
         vec![
             lighting_region,
         ],
@@ -0,0 +1,580 @@
+use std::cell::Cell;
+
+use crate::exec::{parse, ExecError, ExecResult, Expression, SourceLocation};
+use crate::exec::value::ValueData;
+use crate::math::Scalar;
+use crate::color::{blackbody, LinearRGB, SRGB};
+use crate::vec::Vec3;
+
+// A compiled expression is a small, type-checked subset of the
+// script language (scalars, vectors, bools, arithmetic, let,
+// if/else and a fixed set of math functions) that can be evaluated
+// from any thread without touching the script Context.
+// This makes it suitable for per-sample evaluation, such
+// as for procedural textures.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompiledType
+{
+    Bool,
+    Scalar,
+    Vec3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompiledValue
+{
+    Bool(bool),
+    Scalar(Scalar),
+    Vec3(Vec3),
+}
+
+impl CompiledValue
+{
+    fn get_type(&self) -> CompiledType
+    {
+        match self
+        {
+            CompiledValue::Bool(_) => CompiledType::Bool,
+            CompiledValue::Scalar(_) => CompiledType::Scalar,
+            CompiledValue::Vec3(_) => CompiledType::Vec3,
+        }
+    }
+
+    fn into_bool(self) -> bool
+    {
+        match self
+        {
+            CompiledValue::Bool(b) => b,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_scalar(self) -> Scalar
+    {
+        match self
+        {
+            CompiledValue::Scalar(s) => s,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_vec3(self) -> Vec3
+    {
+        match self
+        {
+            CompiledValue::Vec3(v) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    fn map(self, f: impl Fn(Scalar) -> Scalar) -> CompiledValue
+    {
+        match self
+        {
+            CompiledValue::Scalar(s) => CompiledValue::Scalar(f(s)),
+            CompiledValue::Vec3(v) => CompiledValue::Vec3(Vec3::new(f(v.x), f(v.y), f(v.z))),
+            CompiledValue::Bool(_) => unreachable!(),
+        }
+    }
+
+    fn broadcast(self) -> Vec3
+    {
+        match self
+        {
+            CompiledValue::Scalar(s) => Vec3::new(s, s, s),
+            CompiledValue::Vec3(v) => v,
+            CompiledValue::Bool(_) => unreachable!(),
+        }
+    }
+
+    fn map2(self, other: CompiledValue, f: impl Fn(Scalar, Scalar) -> Scalar) -> CompiledValue
+    {
+        match (self, other)
+        {
+            (CompiledValue::Scalar(a), CompiledValue::Scalar(b)) => CompiledValue::Scalar(f(a, b)),
+            _ =>
+            {
+                let a = self.broadcast();
+                let b = other.broadcast();
+                CompiledValue::Vec3(Vec3::new(f(a.x, b.x), f(a.y, b.y), f(a.z, b.z)))
+            },
+        }
+    }
+
+    fn map3(self, b: CompiledValue, c: CompiledValue, f: impl Fn(Scalar, Scalar, Scalar) -> Scalar) -> CompiledValue
+    {
+        match (self, b, c)
+        {
+            (CompiledValue::Scalar(a), CompiledValue::Scalar(b), CompiledValue::Scalar(c)) => CompiledValue::Scalar(f(a, b, c)),
+            _ =>
+            {
+                let a = self.broadcast();
+                let b = b.broadcast();
+                let c = c.broadcast();
+                CompiledValue::Vec3(Vec3::new(f(a.x, b.x, c.x), f(a.y, b.y, c.y), f(a.z, b.z, c.z)))
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Builtin
+{
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Sin,
+    Cos,
+    Abs,
+    Floor,
+    Fract,
+    Sqrt,
+    Pow,
+    Min,
+    Max,
+    Clamp,
+    Mix,
+    Length,
+    Dot,
+    Normalize,
+    X,
+    Y,
+    Z,
+    Rgb,
+    Blackbody,
+}
+
+impl Builtin
+{
+    fn lookup(name: &str) -> Option<Builtin>
+    {
+        match name
+        {
+            "neg" => Some(Builtin::Neg),
+            "+" | "add" => Some(Builtin::Add),
+            "-" | "sub" => Some(Builtin::Sub),
+            "*" | "mul" => Some(Builtin::Mul),
+            "/" | "div" => Some(Builtin::Div),
+            "==" | "eq" => Some(Builtin::Eq),
+            "!=" | "ne" => Some(Builtin::Ne),
+            "sin" => Some(Builtin::Sin),
+            "cos" => Some(Builtin::Cos),
+            "abs" => Some(Builtin::Abs),
+            "floor" => Some(Builtin::Floor),
+            "fract" => Some(Builtin::Fract),
+            "sqrt" => Some(Builtin::Sqrt),
+            "pow" => Some(Builtin::Pow),
+            "min" => Some(Builtin::Min),
+            "max" => Some(Builtin::Max),
+            "clamp" => Some(Builtin::Clamp),
+            "mix" => Some(Builtin::Mix),
+            "length" => Some(Builtin::Length),
+            "dot" => Some(Builtin::Dot),
+            "normalize" => Some(Builtin::Normalize),
+            "x" => Some(Builtin::X),
+            "y" => Some(Builtin::Y),
+            "z" => Some(Builtin::Z),
+            "rgb" => Some(Builtin::Rgb),
+            "blackbody" => Some(Builtin::Blackbody),
+            _ => None,
+        }
+    }
+
+    fn result_type(&self, args: &[CompiledType]) -> Option<CompiledType>
+    {
+        let numeric = args.iter().all(|a| *a != CompiledType::Bool);
+        let any_vec = args.contains(&CompiledType::Vec3);
+        let elementwise = if any_vec { CompiledType::Vec3 } else { CompiledType::Scalar };
+
+        match (self, args.len())
+        {
+            (Builtin::Neg, 1)
+                | (Builtin::Sin, 1)
+                | (Builtin::Cos, 1)
+                | (Builtin::Abs, 1)
+                | (Builtin::Floor, 1)
+                | (Builtin::Fract, 1)
+                | (Builtin::Sqrt, 1)
+                | (Builtin::Add, 2)
+                | (Builtin::Sub, 2)
+                | (Builtin::Mul, 2)
+                | (Builtin::Div, 2)
+                | (Builtin::Pow, 2)
+                | (Builtin::Min, 2)
+                | (Builtin::Max, 2)
+                | (Builtin::Clamp, 3)
+                | (Builtin::Mix, 3) if numeric => Some(elementwise),
+            (Builtin::Eq, 2)
+                | (Builtin::Ne, 2) if args.iter().all(|a| *a == CompiledType::Scalar) => Some(CompiledType::Bool),
+            (Builtin::Length, 1)
+                | (Builtin::X, 1)
+                | (Builtin::Y, 1)
+                | (Builtin::Z, 1) if args[0] == CompiledType::Vec3 => Some(CompiledType::Scalar),
+            (Builtin::Normalize, 1) if args[0] == CompiledType::Vec3 => Some(CompiledType::Vec3),
+            (Builtin::Dot, 2) if args.iter().all(|a| *a == CompiledType::Vec3) => Some(CompiledType::Scalar),
+            (Builtin::Rgb, 3) if args.iter().all(|a| *a == CompiledType::Scalar) => Some(CompiledType::Vec3),
+            (Builtin::Blackbody, 1) if args[0] == CompiledType::Scalar => Some(CompiledType::Vec3),
+            _ => None,
+        }
+    }
+
+    fn evaluate(&self, args: &[CompiledValue]) -> CompiledValue
+    {
+        match self
+        {
+            Builtin::Neg => args[0].map(|a| -a),
+            Builtin::Add => args[0].map2(args[1], |a, b| a + b),
+            Builtin::Sub => args[0].map2(args[1], |a, b| a - b),
+            Builtin::Mul => args[0].map2(args[1], |a, b| a * b),
+            Builtin::Div => args[0].map2(args[1], |a, b| a / b),
+            Builtin::Eq => CompiledValue::Bool(args[0].into_scalar() == args[1].into_scalar()),
+            Builtin::Ne => CompiledValue::Bool(args[0].into_scalar() != args[1].into_scalar()),
+            Builtin::Sin => args[0].map(Scalar::sin),
+            Builtin::Cos => args[0].map(Scalar::cos),
+            Builtin::Abs => args[0].map(Scalar::abs),
+            Builtin::Floor => args[0].map(Scalar::floor),
+            Builtin::Fract => args[0].map(|a| a - a.floor()),
+            Builtin::Sqrt => args[0].map(Scalar::sqrt),
+            Builtin::Pow => args[0].map2(args[1], Scalar::powf),
+            Builtin::Min => args[0].map2(args[1], Scalar::min),
+            Builtin::Max => args[0].map2(args[1], Scalar::max),
+            Builtin::Clamp => args[0].map3(args[1], args[2], |a, lo, hi| a.max(lo).min(hi)),
+            Builtin::Mix => args[0].map3(args[1], args[2], |a, b, t| a + (b - a) * t),
+            Builtin::Length => CompiledValue::Scalar(args[0].into_vec3().magnitude()),
+            Builtin::Dot => CompiledValue::Scalar(args[0].into_vec3().dot(args[1].into_vec3())),
+            Builtin::Normalize => CompiledValue::Vec3(args[0].into_vec3().normalized()),
+            Builtin::X => CompiledValue::Scalar(args[0].into_vec3().x),
+            Builtin::Y => CompiledValue::Scalar(args[0].into_vec3().y),
+            Builtin::Z => CompiledValue::Scalar(args[0].into_vec3().z),
+            Builtin::Rgb =>
+            {
+                // Matches the script rgb() function - the
+                // parameters are in sRGB and the result is linear
+
+                let linear: LinearRGB = SRGB::new(args[0].into_scalar(), args[1].into_scalar(), args[2].into_scalar(), 1.0).into();
+                CompiledValue::Vec3(Vec3::new(linear.r, linear.g, linear.b))
+            },
+            Builtin::Blackbody =>
+            {
+                let linear = blackbody(args[0].into_scalar());
+                CompiledValue::Vec3(Vec3::new(linear.r, linear.g, linear.b))
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node
+{
+    Constant(CompiledValue),
+    Read(usize),
+    Write(usize, Box<Node>),
+    Vector(Box<Node>, Box<Node>, Box<Node>),
+    Block(Vec<Node>),
+    If(Vec<(Node, Node)>, Box<Node>),
+    Call(Builtin, Vec<Node>),
+}
+
+impl Node
+{
+    fn evaluate(&self, slots: &mut [CompiledValue]) -> CompiledValue
+    {
+        match self
+        {
+            Node::Constant(value) => *value,
+            Node::Read(slot) => slots[*slot],
+            Node::Write(slot, node) =>
+            {
+                let value = node.evaluate(slots);
+                slots[*slot] = value;
+                value
+            },
+            Node::Vector(x, y, z) =>
+            {
+                CompiledValue::Vec3(Vec3::new(
+                    x.evaluate(slots).into_scalar(),
+                    y.evaluate(slots).into_scalar(),
+                    z.evaluate(slots).into_scalar()))
+            },
+            Node::Block(nodes) =>
+            {
+                let mut result = CompiledValue::Bool(false);
+                for node in nodes.iter()
+                {
+                    result = node.evaluate(slots);
+                }
+                result
+            },
+            Node::If(conditions, alternative) =>
+            {
+                for (cond, node) in conditions.iter()
+                {
+                    if cond.evaluate(slots).into_bool()
+                    {
+                        return node.evaluate(slots);
+                    }
+                }
+                alternative.evaluate(slots)
+            },
+            Node::Call(builtin, args) =>
+            {
+                let mut values = [CompiledValue::Bool(false); 3];
+                for (i, arg) in args.iter().enumerate()
+                {
+                    values[i] = arg.evaluate(slots);
+                }
+                builtin.evaluate(&values[0..args.len()])
+            },
+        }
+    }
+}
+
+struct Compiler
+{
+    scopes: Vec<Vec<(String, usize, CompiledType)>>,
+    num_slots: usize,
+}
+
+impl Compiler
+{
+    fn lookup(&self, name: &str) -> Option<(usize, CompiledType)>
+    {
+        self.scopes.iter().rev()
+            .flat_map(|s| s.iter().rev())
+            .find(|(n, _, _)| n == name)
+            .map(|(_, slot, t)| (*slot, *t))
+    }
+
+    fn declare(&mut self, name: &str, t: CompiledType) -> usize
+    {
+        let slot = self.num_slots;
+        self.num_slots += 1;
+        self.scopes.last_mut().unwrap().push((name.to_owned(), slot, t));
+        slot
+    }
+
+    fn compile_block(&mut self, source: SourceLocation, expressions: &[Box<Expression>]) -> ExecResult<(Node, CompiledType)>
+    {
+        if expressions.is_empty()
+        {
+            return Err(ExecError::new(source, "Compiled blocks must produce a value"));
+        }
+
+        self.scopes.push(Vec::new());
+
+        let mut nodes = Vec::new();
+        let mut result = CompiledType::Bool;
+
+        for exp in expressions.iter()
+        {
+            let (node, t) = self.compile(source, exp)?;
+            nodes.push(node);
+            result = t;
+        }
+
+        self.scopes.pop();
+
+        Ok((Node::Block(nodes), result))
+    }
+
+    fn compile(&mut self, source: SourceLocation, expression: &Expression) -> ExecResult<(Node, CompiledType)>
+    {
+        match expression
+        {
+            Expression::Constant{ value } =>
+            {
+                let value = match value.data()
+                {
+                    ValueData::Bool(b) => CompiledValue::Bool(*b),
+                    ValueData::Scalar(s) => CompiledValue::Scalar(*s),
+                    ValueData::Vec3(v) => CompiledValue::Vec3(*v),
+                    _ => return Err(ExecError::new(value.source_location(), "Only bool, scalar and vector constants can be compiled")),
+                };
+                Ok((Node::Constant(value), value.get_type()))
+            },
+            Expression::Vector{ source, expressions } =>
+            {
+                if expressions.len() != 3
+                {
+                    return Err(ExecError::new(*source, "Vector expressions only support three scalar elements"));
+                }
+
+                let mut nodes = Vec::new();
+                for exp in expressions.iter()
+                {
+                    let (node, t) = self.compile(*source, exp)?;
+                    if t != CompiledType::Scalar
+                    {
+                        return Err(ExecError::new(*source, "Vector elements must be scalars"));
+                    }
+                    nodes.push(Box::new(node));
+                }
+
+                let z = nodes.pop().unwrap();
+                let y = nodes.pop().unwrap();
+                let x = nodes.pop().unwrap();
+
+                Ok((Node::Vector(x, y, z), CompiledType::Vec3))
+            },
+            Expression::ReadNamedVar{ source, name } =>
+            {
+                match self.lookup(name)
+                {
+                    Some((slot, t)) => Ok((Node::Read(slot), t)),
+                    None => Err(ExecError::new(*source, format!("Undefined variable \"{}\"", name))),
+                }
+            },
+            Expression::WriteNamedVar{ name, expression } =>
+            {
+                let (node, t) = self.compile(source, expression)?;
+                let slot = self.declare(name, t);
+                Ok((Node::Write(slot, Box::new(node)), t))
+            },
+            Expression::Block{ expressions, .. } =>
+            {
+                self.compile_block(source, expressions)
+            },
+            Expression::If{ conditions, alternative, .. } =>
+            {
+                let alternative = match alternative
+                {
+                    Some(alternative) => alternative,
+                    None => return Err(ExecError::new(source, "Compiled if expressions require an else branch")),
+                };
+
+                let (alternative, result) = self.compile(source, alternative)?;
+
+                let mut compiled_conditions = Vec::new();
+
+                for (cond, exp) in conditions.iter()
+                {
+                    let (cond, cond_t) = self.compile(source, cond)?;
+                    if cond_t != CompiledType::Bool
+                    {
+                        return Err(ExecError::new(source, "If conditions must be bool"));
+                    }
+
+                    let (exp, exp_t) = self.compile(source, exp)?;
+                    if exp_t != result
+                    {
+                        return Err(ExecError::new(source, "All if branches must produce the same type"));
+                    }
+
+                    compiled_conditions.push((cond, exp));
+                }
+
+                Ok((Node::If(compiled_conditions, Box::new(alternative)), result))
+            },
+            Expression::Call{ call_site, function, arguments } =>
+            {
+                let builtin = match &**function
+                {
+                    Expression::ReadNamedVar{ name, .. } => Builtin::lookup(name)
+                        .ok_or_else(|| ExecError::new(*call_site, format!("Function \"{}\" can't be compiled", name)))?,
+                    _ => return Err(ExecError::new(*call_site, "Only calls to in-built functions can be compiled")),
+                };
+
+                if !arguments.named.is_empty()
+                {
+                    return Err(ExecError::new(*call_site, "Named arguments can't be compiled"));
+                }
+
+                let arguments = &arguments.positional;
+
+                let mut nodes = Vec::new();
+                let mut types = Vec::new();
+
+                for arg in arguments.iter()
+                {
+                    let (node, t) = self.compile(*call_site, arg)?;
+                    nodes.push(node);
+                    types.push(t);
+                }
+
+                match builtin.result_type(&types)
+                {
+                    Some(t) => Ok((Node::Call(builtin, nodes), t)),
+                    None => Err(ExecError::new(*call_site, format!("Invalid arguments {:?} to function {:?}", types, builtin))),
+                }
+            },
+            Expression::Function{ source, .. } =>
+            {
+                Err(ExecError::new(*source, "Functions can't be declared in compiled expressions"))
+            },
+            Expression::List{ source, .. } =>
+            {
+                Err(ExecError::new(*source, "Lists can't be used in compiled expressions"))
+            },
+            Expression::Record{ source, .. } | Expression::Field{ source, .. } =>
+            {
+                Err(ExecError::new(*source, "Records can't be used in compiled expressions"))
+            },
+        }
+    }
+}
+
+thread_local!
+{
+    // Re-used for the slots of each evaluation, as they're
+    // evaluated per-sample. It's taken while it's in use.
+    static SLOTS: Cell<Vec<CompiledValue>> = const { Cell::new(Vec::new()) };
+}
+
+#[derive(Clone, Debug)]
+pub struct CompiledExpression
+{
+    root: Node,
+    num_slots: usize,
+    result_type: CompiledType,
+}
+
+impl CompiledExpression
+{
+    pub fn compile(script: &str, variables: &[(&str, CompiledType)]) -> ExecResult<CompiledExpression>
+    {
+        let expressions = parse(script)?;
+
+        let mut compiler = Compiler
+        {
+            scopes: vec![Vec::new()],
+            num_slots: 0,
+        };
+
+        for (name, t) in variables.iter()
+        {
+            compiler.declare(name, *t);
+        }
+
+        let (root, result_type) = compiler.compile_block(SourceLocation::inbuilt(), &expressions)?;
+
+        Ok(CompiledExpression
+        {
+            root,
+            num_slots: compiler.num_slots,
+            result_type,
+        })
+    }
+
+    pub fn result_type(&self) -> CompiledType
+    {
+        self.result_type
+    }
+
+    pub fn evaluate(&self, variables: &[CompiledValue]) -> CompiledValue
+    {
+        let mut slots = SLOTS.with(|s| s.take());
+        slots.clear();
+        slots.resize(self.num_slots, CompiledValue::Bool(false));
+        slots[0..variables.len()].copy_from_slice(variables);
+
+        let result = self.root.evaluate(&mut slots);
+
+        SLOTS.with(|s| s.set(slots));
+        result
+    }
+}
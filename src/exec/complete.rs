@@ -0,0 +1,75 @@
+use std::rc::Rc;
+
+use crate::exec::{FunctionDoc, Value};
+use crate::exec::value::ValueData;
+
+#[derive(Clone, Debug)]
+pub struct Completion
+{
+    pub name: String,
+    pub detail: String,
+}
+
+impl Completion
+{
+    pub fn from_var(name: &str, value: &Value) -> Self
+    {
+        let detail = match value.data()
+        {
+            ValueData::Function(func) => func.get_doc().signature(),
+            _ => value.type_name().to_owned(),
+        };
+
+        Completion { name: name.to_owned(), detail }
+    }
+}
+
+/// Returns the partial identifier immediately
+/// before the cursor (a byte offset into the text).
+pub fn identifier_prefix(text: &str, cursor: usize) -> &str
+{
+    let before = &text[..cursor.min(text.len())];
+
+    let start = before.char_indices()
+        .rev()
+        .take_while(|(_, ch)| (*ch == '_') || ch.is_alphanumeric())
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(before.len());
+
+    let prefix = &before[start..];
+
+    // Identifiers can't start with a digit
+
+    match prefix.chars().next()
+    {
+        Some(ch) if ch.is_ascii_digit() => "",
+        _ => prefix,
+    }
+}
+
+/// Finds the functions and variables that start with the prefix,
+/// with variables from the script listed before inbuilt functions.
+pub fn complete(prefix: &str, docs: &[Rc<FunctionDoc>], vars: &[Completion]) -> Vec<Completion>
+{
+    if prefix.is_empty()
+    {
+        return Vec::new();
+    }
+
+    let mut result = vars.iter()
+        .filter(|c| c.name.starts_with(prefix) && (c.name != prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut funcs = docs.iter()
+        .flat_map(|doc| doc.names.iter().map(move |name| (name, doc)))
+        .filter(|(name, _)| name.starts_with(prefix) && (*name != prefix))
+        .filter(|(name, _)| !result.iter().any(|c| &c.name == *name))
+        .map(|(name, doc)| Completion { name: name.clone(), detail: doc.signature_as(name) })
+        .collect::<Vec<_>>();
+
+    funcs.sort_by(|a, b| a.name.cmp(&b.name));
+    result.append(&mut funcs);
+    result
+}
@@ -1,13 +1,23 @@
 use std::{rc::Rc, any::Any};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
-use crate::exec::{ActualArguments, ExecError, ExecResult, SourceLocation, Value};
+use crate::exec::{ActualArguments, ExecError, ExecLimits, ExecResult, SourceLocation, Value};
+use crate::exec::limits::ExecMonitor;
+use crate::import::FileSystemContext;
 
 #[derive(Clone)]
 pub struct Context
 {
     frame: Rc<RefCell<Frame>>,
+    monitor: Rc<ExecMonitor>,
+    /// The script files being run - the main script, if it's
+    /// from a file, then those being included, innermost last
+    script_files: Rc<RefCell<Vec<PathBuf>>>,
 }
 
 impl Context
@@ -16,7 +26,9 @@ impl Context
     {
         let mut result = Context
         {
-            frame: Rc::new(RefCell::new(Frame::new()))
+            frame: Rc::new(RefCell::new(Frame::new())),
+            monitor: Rc::new(ExecMonitor::new()),
+            script_files: Rc::new(RefCell::new(Vec::new())),
         };
 
         crate::exec::inbuilt::add_inbuilt_functions(&mut result);
@@ -30,7 +42,9 @@ impl Context
     {
         let mut result = Context
         {
-            frame: Rc::new(RefCell::new(Frame::new_with_state(app_state)))
+            frame: Rc::new(RefCell::new(Frame::new_with_state(app_state))),
+            monitor: Rc::new(ExecMonitor::new()),
+            script_files: Rc::new(RefCell::new(Vec::new())),
         };
 
         crate::exec::inbuilt::add_inbuilt_functions(&mut result);
@@ -38,6 +52,126 @@ impl Context
         result
     }
 
+    /// A separate script, with its own variables and app state,
+    /// that shares this one's limits and the files being run
+    pub fn new_script_with_state<AppState>(&self, app_state: AppState) -> Context
+    where
+        AppState: Any
+    {
+        let mut result = Context
+        {
+            frame: Rc::new(RefCell::new(Frame::new_with_state(app_state))),
+            monitor: self.monitor.clone(),
+            script_files: self.script_files.clone(),
+        };
+
+        crate::exec::inbuilt::add_inbuilt_functions(&mut result);
+
+        result
+    }
+
+    /// Sets the limits for the rest of the execution. These
+    /// are shared by all frames, including those captured by
+    /// functions that have already been defined.
+    pub fn set_limits(&self, limits: ExecLimits)
+    {
+        self.monitor.set_limits(limits);
+    }
+
+    /// Stops the execution, with an error, before it uses
+    /// more than this much stack from the current position
+    pub fn set_stack_size(&self, size: usize)
+    {
+        self.monitor.set_stack_size(size);
+    }
+
+    /// Stops the execution, with an error, once
+    /// the flag is set - e.g. from another thread.
+    pub fn set_cancel_flag(&self, cancelled: Arc<AtomicBool>)
+    {
+        self.monitor.set_cancel_flag(cancelled);
+    }
+
+    /// Sets the main script's file, so that the files it loads
+    /// are found relative to it, rather than the current directory
+    pub fn set_script_file(&self, path: &Path)
+    {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+
+        *self.script_files.borrow_mut() = vec![path];
+    }
+
+    /// For loading files relative to the script that's
+    /// running, or if it's not from a file, the current directory
+    pub fn file_system(&self) -> FileSystemContext
+    {
+        match self.script_files.borrow().last().and_then(|p| p.parent())
+        {
+            Some(dir) => FileSystemContext::new_in_dir(dir.to_owned()),
+            None => FileSystemContext::new(),
+        }
+    }
+
+    /// Returns false, without adding it, if the
+    /// file is already being run - i.e. it includes itself
+    pub fn push_script_file(&self, path: PathBuf) -> bool
+    {
+        let mut script_files = self.script_files.borrow_mut();
+
+        if script_files.contains(&path)
+        {
+            return false;
+        }
+
+        script_files.push(path);
+        true
+    }
+
+    pub fn pop_script_file(&self)
+    {
+        self.script_files.borrow_mut().pop();
+    }
+
+    /// The script's top level scope - where the
+    /// inbuilt functions and its global variables are
+    pub fn root_scope(&self) -> Context
+    {
+        let mut frame = self.frame.clone();
+
+        loop
+        {
+            let parent = match &frame.borrow().parent
+            {
+                Some(parent) => parent.clone(),
+                None => break,
+            };
+
+            frame = parent;
+        }
+
+        Context
+        {
+            frame,
+            monitor: self.monitor.clone(),
+            script_files: self.script_files.clone(),
+        }
+    }
+
+    pub fn begin_call(&self, call_site: SourceLocation) -> ExecResult<()>
+    {
+        self.monitor.begin_call(call_site)
+    }
+
+    pub fn end_call(&self)
+    {
+        self.monitor.end_call();
+    }
+
+    pub fn check_collection_size(&self, source: SourceLocation, size: usize) -> ExecResult<()>
+    {
+        self.monitor.check_collection_size(source, size)
+    }
+
     pub fn get_call_site(&self) -> SourceLocation
     {
         self.frame.borrow().call_site
@@ -57,6 +191,19 @@ impl Context
         self.frame.borrow_mut().set_var_named(name, value);
     }
 
+    /// Returns the variables defined directly in this frame,
+    /// excluding the inbuilt functions.
+    pub fn get_script_vars(&self) -> Vec<(String, Value)>
+    {
+        let mut result = self.frame.borrow().vars.iter()
+            .filter(|(_, v)| v.source_location() != SourceLocation::inbuilt())
+            .map(|(n, v)| (n.clone(), v.clone()))
+            .collect::<Vec<_>>();
+
+        result.sort_by(|a, b| a.0.cmp(&b.0));
+        result
+    }
+
     pub fn get_param(&mut self, position: usize, name: &str) -> ExecResult<Value>
     {
         match self.frame.borrow().get_opt_param(position, name)
@@ -76,11 +223,13 @@ impl Context
         self.frame.borrow().get_param_all_positional()
     }
 
-    pub fn sub_frame(&self, call_site: SourceLocation, formal_arguments: &Vec<String>, actual_arguments: ActualArguments) -> Context
+    pub fn sub_frame(&self, call_site: SourceLocation, actual_arguments: ActualArguments) -> Context
     {
         Context
         {
-            frame: Rc::new(RefCell::new(Frame::new_frame(Some(self.frame.clone()), call_site, formal_arguments, actual_arguments))),
+            frame: Rc::new(RefCell::new(Frame::new_frame(Some(self.frame.clone()), call_site, actual_arguments))),
+            monitor: self.monitor.clone(),
+            script_files: self.script_files.clone(),
         }
     }
 
@@ -89,6 +238,8 @@ impl Context
         Context
         {
             frame: Rc::new(RefCell::new(Frame::new_block(self.frame.clone()))),
+            monitor: self.monitor.clone(),
+            script_files: self.script_files.clone(),
         }
     }
 
@@ -102,12 +253,39 @@ impl Context
 
 }
 
+/// FxHash - variable names are short and come from the
+/// script itself, so there's no need for the DoS resistance
+/// (or cost) of the default SipHash.
+#[derive(Default)]
+struct NameHasher
+{
+    hash: u64,
+}
+
+impl Hasher for NameHasher
+{
+    fn write(&mut self, bytes: &[u8])
+    {
+        for b in bytes.iter()
+        {
+            self.hash = (self.hash.rotate_left(5) ^ (*b as u64)).wrapping_mul(0x517c_c1b7_2722_0a95);
+        }
+    }
+
+    fn finish(&self) -> u64
+    {
+        self.hash
+    }
+}
+
+type VarMap = HashMap<String, Value, BuildHasherDefault<NameHasher>>;
+
 struct Frame
 {
     parent: Option<Rc<RefCell<Frame>>>,
     call_site: SourceLocation,
     args: ActualArguments,
-    vars: HashMap<String, Value>,
+    vars: VarMap,
     app_state: Option<Rc<RefCell<dyn Any>>>,
 }
 
@@ -119,8 +297,8 @@ impl Frame
         {
             parent: None,
             call_site: SourceLocation::inbuilt(),
-            args: ActualArguments::Positional(Vec::new()),
-            vars: HashMap::new(),
+            args: ActualArguments::default(),
+            vars: VarMap::default(),
             app_state: None,
         }
     }
@@ -133,8 +311,8 @@ impl Frame
         {
             parent: None,
             call_site: SourceLocation::inbuilt(),
-            args: ActualArguments::Positional(Vec::new()),
-            vars: HashMap::new(),
+            args: ActualArguments::default(),
+            vars: VarMap::default(),
             app_state: Some(Rc::new(RefCell::new(app_state))),
         }
     }
@@ -147,20 +325,20 @@ impl Frame
         {
             parent: Some(parent.clone()),
             call_site,
-            args: ActualArguments::Positional(Vec::new()),
-            vars: HashMap::new(),
+            args: ActualArguments::default(),
+            vars: VarMap::default(),
             app_state: None,
         }
     }
 
-    fn new_frame(parent: Option<Rc<RefCell<Frame>>>, call_site: SourceLocation, _formal_arguments: &Vec<String>, actual_arguments: ActualArguments) -> Self
+    fn new_frame(parent: Option<Rc<RefCell<Frame>>>, call_site: SourceLocation, actual_arguments: ActualArguments) -> Self
     {
         Frame
         {
             parent,
             call_site,
             args: actual_arguments,
-            vars: HashMap::new(),
+            vars: VarMap::default(),
             app_state: None,
         }
     }
@@ -172,9 +350,12 @@ impl Frame
             return Some((*here).clone())
         }
 
-        if let ActualArguments::Named(args) = &self.args
+        // Avoid hashing the name when there
+        // are no named arguments - the usual case
+
+        if !self.args.named.is_empty()
         {
-            if let Some(val) = args.get(name)
+            if let Some(val) = self.args.named.get(name)
             {
                 return Some(val.clone())
             }
@@ -190,44 +371,29 @@ impl Frame
 
     pub fn set_var_named(&mut self, name: &str, value: Value)
     {
-        self.vars.insert(name.to_owned(), value);
+        match self.vars.get_mut(name)
+        {
+            Some(existing) => *existing = value,
+            None => { self.vars.insert(name.to_owned(), value); },
+        }
     }
 
     fn get_opt_param(&self, position: usize, name: &str) -> Option<Value>
     {
-        match &self.args
+        if !self.args.named.is_empty()
         {
-            ActualArguments::Named(hash_map) =>
-            {
-                hash_map.get(name).cloned()
-            },
-            ActualArguments::Positional(vec) =>
+            if let Some(val) = self.args.named.get(name)
             {
-                if position < vec.len()
-                {
-                    Some(vec[position].clone())
-                }
-                else
-                {
-                    None
-                }
-            },
+                return Some(val.clone());
+            }
         }
+
+        self.args.positional.get(position).cloned()
     }
 
     fn get_param_all_positional(&self) -> Vec<Value>
     {
-        match &self.args
-        {
-            ActualArguments::Named(_hash_map) =>
-            {
-                todo!()
-            },
-            ActualArguments::Positional(vec) =>
-            {
-                vec.clone()
-            },
-        }
+        self.args.positional.clone()
     }
 
     fn with_app_state<AppState, Func, Value>(&self, func: Func) -> Result<Value, ExecError>
@@ -0,0 +1,94 @@
+#[derive(Clone, Debug)]
+pub struct ArgumentDoc
+{
+    pub name: String,
+    pub description: String,
+}
+
+/// Documentation for a function, shared between
+/// all of the names it is registered under.
+#[derive(Clone, Debug)]
+pub struct FunctionDoc
+{
+    pub names: Vec<String>,
+    pub description: String,
+    pub args: Vec<ArgumentDoc>,
+    pub variadic: bool,
+    /// Calls with constant arguments can be evaluated
+    /// once, when the script is optimized.
+    pub pure: bool,
+}
+
+impl FunctionDoc
+{
+    pub fn new(names: Vec<String>, args: &[String], variadic: bool) -> Self
+    {
+        FunctionDoc
+        {
+            names,
+            description: String::new(),
+            args: args.iter().map(|name| ArgumentDoc { name: name.clone(), description: String::new() }).collect(),
+            variadic,
+            pure: false,
+        }
+    }
+
+    pub fn name(&self) -> &str
+    {
+        &self.names[0]
+    }
+
+    pub fn signature(&self) -> String
+    {
+        self.signature_as(self.name())
+    }
+
+    pub fn signature_as(&self, name: &str) -> String
+    {
+        // Only the last argument of a variadic
+        // function can be repeated
+
+        let args = self.args.iter()
+            .enumerate()
+            .map(|(i, a)| if self.variadic && ((i + 1) == self.args.len()) { format!("{}...", a.name) } else { a.name.clone() })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("{}({})", name, args)
+    }
+
+    pub fn help_text(&self) -> String
+    {
+        let mut result = self.signature();
+
+        if self.names.len() > 1
+        {
+            result.push_str(&format!("\n    Also: {}", self.names[1..].join(", ")));
+        }
+
+        if !self.description.is_empty()
+        {
+            result.push_str(&format!("\n    {}", self.description));
+        }
+
+        for arg in self.args.iter()
+        {
+            if !arg.description.is_empty()
+            {
+                result.push_str(&format!("\n    {}: {}", arg.name, arg.description));
+            }
+        }
+
+        result
+    }
+
+    /// Case-insensitive search against the names
+    /// and description.
+    pub fn matches(&self, filter: &str) -> bool
+    {
+        let filter = filter.to_lowercase();
+
+        self.names.iter().any(|n| n.to_lowercase().contains(&filter))
+            || self.description.to_lowercase().contains(&filter)
+    }
+}
@@ -31,4 +31,9 @@ impl ExecError
     {
         self.msg.clone()
     }
+
+    pub fn source_location(&self) -> SourceLocation
+    {
+        self.source
+    }
 }
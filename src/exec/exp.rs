@@ -1,5 +1,5 @@
 use crate::vec::Vec3;
-use crate::exec::{ActualArgumentExpressions, Context, ExecError, ExecResult, Function, SourceLocation, Value};
+use crate::exec::{ActualArgumentExpressions, Context, ExecError, ExecResult, FormalArgument, Function, SourceLocation, Value};
 
 //pub mod core;
 
@@ -8,12 +8,15 @@ pub enum Expression
 {
     Constant{ value: Value },
     Vector{ source: SourceLocation, expressions: Vec<Box<Expression>> },
-    Function{ source: SourceLocation, name: String, formal_arguments: Vec<String>, expression: Box<Expression> },
+    List{ source: SourceLocation, expressions: Vec<Box<Expression>> },
+    Record{ source: SourceLocation, fields: Vec<(String, Box<Expression>)> },
+    Field{ source: SourceLocation, expression: Box<Expression>, name: String },
+    Function{ source: SourceLocation, name: String, formal_arguments: Vec<FormalArgument>, expression: Box<Expression> },
     ReadNamedVar{ source: SourceLocation, name: String },
     WriteNamedVar{ name: String, expression: Box<Expression> },
     Call{ call_site: SourceLocation, function: Box<Expression>, arguments: ActualArgumentExpressions },
-    Block { expressions: Vec<Box<Expression>> },
-    If{ conditions: Vec<(Box<Expression>, Box<Expression>)>, alternative: Option<Box<Expression>> },
+    Block { expressions: Vec<Box<Expression>>, scoped: bool },
+    If{ conditions: Vec<(Box<Expression>, Box<Expression>)>, alternative: Option<Box<Expression>>, scoped: bool },
 }
 
 impl Expression
@@ -28,7 +31,22 @@ impl Expression
         Box::new(Expression::Vector{ source, expressions })
     }
 
-    pub fn new_function(source: SourceLocation, name: String, formal_arguments: Vec<String>, expression: Box<Expression>) -> Box<Expression>
+    pub fn new_list(source: SourceLocation, expressions: Vec<Box<Expression>>) -> Box<Expression>
+    {
+        Box::new(Expression::List{ source, expressions })
+    }
+
+    pub fn new_record(source: SourceLocation, fields: Vec<(String, Box<Expression>)>) -> Box<Expression>
+    {
+        Box::new(Expression::Record{ source, fields })
+    }
+
+    pub fn new_field(source: SourceLocation, expression: Box<Expression>, name: String) -> Box<Expression>
+    {
+        Box::new(Expression::Field{ source, expression, name })
+    }
+
+    pub fn new_function(source: SourceLocation, name: String, formal_arguments: Vec<FormalArgument>, expression: Box<Expression>) -> Box<Expression>
     {
         Box::new(Expression::Function{ source, name, formal_arguments, expression })
     }
@@ -50,12 +68,53 @@ impl Expression
 
     pub fn new_block(expressions: Vec<Box<Expression>>) -> Box<Expression>
     {
-        Box::new(Expression::Block{expressions})
+        // Blocks that don't declare any variables
+        // don't need their own frame
+
+        let scoped = expressions.iter().any(|e| e.declares_vars());
+
+        Box::new(Expression::Block{ expressions, scoped })
     }
 
     pub fn new_if(conditions: Vec<(Box<Expression>, Box<Expression>)>, alternative: Option<Box<Expression>>) -> Box<Expression>
     {
-        Box::new(Expression::If{ conditions, alternative })
+        let scoped = conditions.iter().any(|(c, e)| c.declares_vars() || e.declares_vars())
+            || alternative.iter().any(|a| a.declares_vars());
+
+        Box::new(Expression::If{ conditions, alternative, scoped })
+    }
+
+    /// Returns true if evaluating this expression can
+    /// set a variable in the frame it's evaluated in.
+    fn declares_vars(&self) -> bool
+    {
+        match self
+        {
+            Expression::WriteNamedVar{ .. } => true,
+            Expression::Constant{ .. }
+                | Expression::ReadNamedVar{ .. }
+                | Expression::Function{ .. }
+                | Expression::Block{ .. }
+                | Expression::If{ .. } => false,
+            Expression::Vector{ expressions, .. } | Expression::List{ expressions, .. } =>
+            {
+                expressions.iter().any(|e| e.declares_vars())
+            },
+            Expression::Record{ fields, .. } =>
+            {
+                fields.iter().any(|(_, e)| e.declares_vars())
+            },
+            Expression::Field{ expression, .. } =>
+            {
+                expression.declares_vars()
+            },
+            Expression::Call{ function, arguments, .. } =>
+            {
+                function.declares_vars()
+                    || arguments.positional.iter().any(|e| e.declares_vars())
+                    || arguments.named.iter().any(|(_, e)| e.declares_vars())
+            },
+        }
     }
 
     pub fn evaluate(&self, context: &mut Context) -> ExecResult<Value>
@@ -79,6 +138,28 @@ impl Expression
 
                 Ok(Value::new_vec3(*source, Vec3::new(x, y, z)))
             },
+            Expression::List{ source, expressions } =>
+            {
+                context.check_collection_size(*source, expressions.len())?;
+
+                let items = expressions.iter()
+                    .map(|e| e.evaluate(context))
+                    .collect::<ExecResult<Vec<_>>>()?;
+
+                Ok(Value::new_list(*source, items))
+            },
+            Expression::Record{ source, fields } =>
+            {
+                let fields = fields.iter()
+                    .map(|(n, e)| Ok((n.clone(), e.evaluate(context)?)))
+                    .collect::<ExecResult<Vec<_>>>()?;
+
+                Ok(Value::new_record(*source, fields))
+            },
+            Expression::Field{ source, expression, name } =>
+            {
+                expression.evaluate(context)?.get_field(*source, name)
+            },
             Expression::Function{ source, name, formal_arguments, expression } =>
             {
                 Ok(Value::new_function(Function::new_expression(source.clone(), name.clone(), formal_arguments.clone(), context, (*expression).clone())))
@@ -103,29 +184,31 @@ impl Expression
 
                 function.call(context, *call_site, arguments)
             },
-            Expression::Block{ expressions } =>
+            Expression::Block{ expressions, scoped } =>
             {
-                let mut block_context = context.sub_block();
+                let mut sub_block;
+                let block_context = if *scoped { sub_block = context.sub_block(); &mut sub_block } else { context };
 
                 expressions.iter()
-                    .map(|e| e.evaluate(&mut block_context))
+                    .map(|e| e.evaluate(block_context))
                     .try_fold(Value::new_void(), |_, v| v)
             },
-            Expression::If{ conditions, alternative } =>
+            Expression::If{ conditions, alternative, scoped } =>
             {
-                let mut block_context = context.sub_block();
+                let mut sub_block;
+                let block_context = if *scoped { sub_block = context.sub_block(); &mut sub_block } else { context };
 
                 for (cond, exp) in conditions.iter()
                 {
-                    if cond.evaluate(&mut block_context)?.into_bool()?
+                    if cond.evaluate(block_context)?.into_bool()?
                     {
-                        return exp.evaluate(&mut block_context);
+                        return exp.evaluate(block_context);
                     }
                 }
 
                 if let Some(alternative) = alternative
                 {
-                    return alternative.evaluate(&mut block_context);
+                    return alternative.evaluate(block_context);
                 }
 
                 // None succeeded
@@ -1,50 +1,63 @@
 use std::rc::Rc;
 use std::collections::HashMap;
-use crate::exec::{Context, ExecResult, Expression, SourceLocation, Value};
+use crate::exec::{Context, ExecError, ExecResult, Expression, FunctionDoc, SourceLocation, Value};
 
+/// The arguments at a call site - positional arguments
+/// are always listed before any named arguments.
 #[derive(Clone)]
-pub enum ActualArgumentExpressions
+pub struct ActualArgumentExpressions
 {
-    Positional(Vec<Box<Expression>>),
-    Named(Vec<(String, Box<Expression>)>),
+    pub positional: Vec<Box<Expression>>,
+    pub named: Vec<(String, Box<Expression>)>,
 }
 
 impl ActualArgumentExpressions
 {
-    pub fn evaluate(&self, context: &mut Context) -> ExecResult<ActualArguments>
+    pub fn positional(positional: Vec<Box<Expression>>) -> Self
     {
-        match self
-        {
-            ActualArgumentExpressions::Positional(expressions) =>
-            {
-                let mut values = Vec::new();
+        ActualArgumentExpressions { positional, named: Vec::new() }
+    }
 
-                for exp in expressions.iter()
-                {
-                    values.push(exp.evaluate(context)?);
-                }
+    pub fn evaluate(&self, context: &mut Context) -> ExecResult<ActualArguments>
+    {
+        let mut positional = Vec::new();
 
-                Ok(ActualArguments::Positional(values))
-            },
-            ActualArgumentExpressions::Named(expressions) =>
-            {
-                let mut values = HashMap::new();
+        for exp in self.positional.iter()
+        {
+            positional.push(exp.evaluate(context)?);
+        }
 
-                for (name, exp) in expressions.iter()
-                {
-                    values.insert(name.clone(), exp.evaluate(context)?);
-                }
+        let mut named = HashMap::new();
 
-                Ok(ActualArguments::Named(values))
-            },
+        for (name, exp) in self.named.iter()
+        {
+            named.insert(name.clone(), exp.evaluate(context)?);
         }
+
+        Ok(ActualArguments { positional, named })
     }
 }
 
-pub enum ActualArguments
+#[derive(Default)]
+pub struct ActualArguments
+{
+    pub positional: Vec<Value>,
+    pub named: HashMap<String, Value>,
+}
+
+#[derive(Clone)]
+pub struct FormalArgument
 {
-    Positional(Vec<Value>),
-    Named(HashMap<String, Value>),
+    pub name: String,
+    pub default: Option<Box<Expression>>,
+}
+
+impl FormalArgument
+{
+    pub fn new(name: String) -> Self
+    {
+        FormalArgument { name, default: None }
+    }
 }
 
 type InbuiltFunction = dyn Fn(&mut Context) -> ExecResult<Value>;
@@ -59,9 +72,11 @@ struct FunctionData
 {
     source: SourceLocation,
     name: String,
-    formal_arguments: Vec<String>,
+    formal_arguments: Vec<FormalArgument>,
+    variadic: bool,
     parent_context: Context,
     code: FunctionCode,
+    doc: Option<Rc<FunctionDoc>>,
 }
 
 #[derive(Clone)]
@@ -72,7 +87,7 @@ pub struct Function
 
 impl Function
 {
-    pub fn new_inbuilt<F>(name: String, formal_arguments: Vec<String>, context: &mut Context, function: F) -> Function
+    pub fn new_inbuilt<F>(name: String, formal_arguments: Vec<String>, variadic: bool, context: &mut Context, doc: Option<Rc<FunctionDoc>>, function: F) -> Function
         where F: Fn(&mut Context) -> ExecResult<Value> + Sized + 'static
     {
         let code = FunctionCode::Inbuilt(Box::new(function));
@@ -80,15 +95,17 @@ impl Function
         let data = Rc::new(FunctionData{
             source: SourceLocation::inbuilt(),
             name,
-            formal_arguments,
+            formal_arguments: formal_arguments.into_iter().map(FormalArgument::new).collect(),
+            variadic,
             parent_context: context.clone(),
             code,
+            doc,
         });
 
         Function{ data }
     }
 
-    pub fn new_expression(source: SourceLocation, name: String, formal_arguments: Vec<String>, context: &mut Context, expression: Box<Expression>) -> Function
+    pub fn new_expression(source: SourceLocation, name: String, formal_arguments: Vec<FormalArgument>, context: &mut Context, expression: Box<Expression>) -> Function
     {
         let code = FunctionCode::Expression(expression);
 
@@ -96,8 +113,10 @@ impl Function
             source,
             name,
             formal_arguments,
+            variadic: false,
             parent_context: context.clone(),
             code,
+            doc: None,
         });
 
         Function{ data }
@@ -108,19 +127,127 @@ impl Function
         &self.data.name
     }
 
+    /// Returns the registered documentation, or for
+    /// script functions, just the argument names.
+    pub fn get_doc(&self) -> Rc<FunctionDoc>
+    {
+        match &self.data.doc
+        {
+            Some(doc) => doc.clone(),
+            None =>
+            {
+                let names = self.data.formal_arguments.iter().map(|a| a.name.clone()).collect::<Vec<_>>();
+                Rc::new(FunctionDoc::new(vec![self.data.name.clone()], &names, false))
+            },
+        }
+    }
+
     pub fn get_source_location(&self) -> SourceLocation
     {
         self.data.source
     }
 
-    pub fn call(&self, _context: &mut Context, call_site: SourceLocation, actual_arguments: ActualArguments) -> ExecResult<Value>
+    pub fn call(&self, context: &mut Context, call_site: SourceLocation, actual_arguments: ActualArguments) -> ExecResult<Value>
     {
-        let mut context = self.data.parent_context.sub_frame(call_site, &self.data.formal_arguments, actual_arguments);
+        context.begin_call(call_site)?;
+
+        let result = self.call_within_limits(call_site, actual_arguments);
+
+        context.end_call();
+
+        result
+    }
+
+    fn call_within_limits(&self, call_site: SourceLocation, actual_arguments: ActualArguments) -> ExecResult<Value>
+    {
+        self.check_arguments(call_site, &actual_arguments)?;
 
         match &self.data.code
         {
-            FunctionCode::Inbuilt(inbuilt) => inbuilt(&mut context),
-            FunctionCode::Expression(expression) => expression.evaluate(&mut context),
+            FunctionCode::Inbuilt(inbuilt) =>
+            {
+                // Inbuilt functions read their own parameters,
+                // so that they can be optional
+
+                let mut context = self.data.parent_context.sub_frame(call_site, actual_arguments);
+
+                inbuilt(&mut context)
+            },
+            FunctionCode::Expression(expression) =>
+            {
+                let mut context = self.data.parent_context.sub_frame(call_site, ActualArguments::default());
+
+                self.bind_arguments(&mut context, call_site, actual_arguments)?;
+
+                expression.evaluate(&mut context)
+            },
+        }
+    }
+
+    fn check_arguments(&self, call_site: SourceLocation, actual_arguments: &ActualArguments) -> ExecResult<()>
+    {
+        let formal = &self.data.formal_arguments;
+
+        if !self.data.variadic && (actual_arguments.positional.len() > formal.len())
+        {
+            return Err(ExecError::new(
+                call_site,
+                format!("Function \"{}\" takes {} arguments but {} were provided", self.data.name, formal.len(), actual_arguments.positional.len())));
+        }
+
+        for (name, value) in actual_arguments.named.iter()
+        {
+            match formal.iter().position(|f| &f.name == name)
+            {
+                None =>
+                {
+                    return Err(ExecError::new(
+                        value.source_location(),
+                        format!("Function \"{}\" has no argument named \"{}\"", self.data.name, name)));
+                },
+                Some(index) if index < actual_arguments.positional.len() =>
+                {
+                    return Err(ExecError::new(
+                        value.source_location(),
+                        format!("Argument \"{}\" was provided both by position and by name", name)));
+                },
+                Some(_) => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bind_arguments(&self, context: &mut Context, call_site: SourceLocation, mut actual_arguments: ActualArguments) -> ExecResult<()>
+    {
+        let mut positional = actual_arguments.positional.drain(..);
+
+        for formal in self.data.formal_arguments.iter()
+        {
+            // Defaults are evaluated in the new frame so
+            // they can refer to earlier arguments
+
+            if let Some(value) = positional.next()
+            {
+                context.set_var_named(&formal.name, value);
+                continue;
+            }
+
+            let value = match (actual_arguments.named.remove(&formal.name), &formal.default)
+            {
+                (Some(value), _) => value,
+                (None, Some(default)) => default.evaluate(context)?,
+                (None, None) =>
+                {
+                    return Err(ExecError::new(
+                        call_site,
+                        format!("Missing argument \"{}\" for function \"{}\"", formal.name, self.data.name)));
+                },
+            };
+
+            context.set_var_named(&formal.name, value);
         }
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,17 +1,67 @@
 use crate::color::SRGB;
-use crate::desc::edit::{Camera, Geom, Material, Object, Scene, Texture, Triangle, TriangleVertex};
-use crate::exec::{Context, Value};
+use crate::desc::edit::{append_scene, sdf_triangles, Atmosphere, Camera, Clouds, Color, ContactSheet, ContactSheetMode, Geom, Group, LightingRegion, Material, MaterialVariant, MeshCleanup, MeshLod, Object, Opacity, Probe, Scene, StudioRig, Texture, Transform, Triangle, TriangleVertex, Turntable, VariantSet};
+use crate::desc::edit::transform::TransformStage;
+use crate::indexed::{GeomIndex, GroupIndex, MaterialIndex, ObjectIndex, TextureIndex};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::exec::{ActualArguments, Context, ExecResult, Function, FunctionDoc, SourceLocation, Value};
+use crate::exec::value::ValueData;
 use crate::math::Scalar;
 use crate::import;
-use crate::geom::{Sdf, Aabb};
-use crate::vec::{Dir3, Point3};
+use crate::geom::{Sdf, Aabb, Density, GroundFade};
+use crate::vec::{Dir3, Mat4, Point3, Quaternion, Vec3};
 
 use super::{ExecError, NativeFunctionBuilder};
 
 pub fn add_inbuilt_functions(root_context: &mut Context)
+{
+    for func in inbuilt_functions(root_context)
+    {
+        let name = func.get_name().to_owned();
+        root_context.set_var_named(&name, Value::new_function(func));
+    }
+}
+
+/// Documentation for each of the inbuilt functions,
+/// sorted by name, with aliases listed only once.
+pub fn inbuilt_function_docs() -> Vec<Rc<FunctionDoc>>
+{
+    let mut result: Vec<Rc<FunctionDoc>> = Vec::new();
+
+    for func in inbuilt_functions(&mut Context::new())
+    {
+        let doc = func.get_doc();
+
+        if !result.iter().any(|d| Rc::ptr_eq(d, &doc))
+        {
+            result.push(doc);
+        }
+    }
+
+    result.sort_by(|a, b| a.name().cmp(b.name()));
+    result
+}
+
+fn inbuilt_functions(root_context: &mut Context) -> Vec<Function>
 {
     let mut builder = NativeFunctionBuilder::new(root_context);
 
+    builder.add_1(
+        "help",
+        ["name"],
+        |context, name: Value|
+        {
+            let source_location = name.source_location();
+            let name = name.into_string()?;
+            let func = context.get_var_named(source_location, &name)?.into_function()?;
+
+            Ok(Value::new_string(context.get_call_site(), func.get_doc().help_text()))
+        }
+    ).describe(
+        "Returns the documentation for a function.",
+        ["Name of the function"]);
+
     builder.add_2(
         ["==", "eq"],
         ["lhs", "rhs"],
@@ -19,7 +69,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_bool(context.get_call_site(), lhs == rhs))
         }
-    );
+    ).pure().describe(
+        "Returns true if the two scalars are equal.",
+        ["Left hand side", "Right hand side"]);
 
     builder.add_2(
         ["!=", "ne"],
@@ -28,7 +80,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_bool(context.get_call_site(), lhs != rhs))
         }
-    );
+    ).pure().describe(
+        "Returns true if the two scalars are not equal.",
+        ["Left hand side", "Right hand side"]);
 
     builder.add_1(
         "neg",
@@ -37,7 +91,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_scalar(context.get_call_site(), -val))
         }
-    );
+    ).pure().describe(
+        "Negates a scalar.",
+        ["Value to negate"]);
 
     builder.add_2(
         ["+", "add"],
@@ -46,7 +102,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_scalar(context.get_call_site(), lhs + rhs))
         }
-    );
+    ).pure().describe(
+        "Adds two scalars.",
+        ["Left hand side", "Right hand side"]);
 
     builder.add_2(
         ["-", "sub"],
@@ -55,7 +113,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_scalar(context.get_call_site(), lhs - rhs))
         }
-    );
+    ).pure().describe(
+        "Subtracts two scalars.",
+        ["Left hand side", "Right hand side"]);
 
     builder.add_2(
         ["*", "mul"],
@@ -64,7 +124,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_scalar(context.get_call_site(), lhs * rhs))
         }
-    );
+    ).pure().describe(
+        "Multiplies two scalars.",
+        ["Left hand side", "Right hand side"]);
 
     builder.add_2(
         ["/", "div"],
@@ -73,7 +135,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_scalar(context.get_call_site(), lhs / rhs))
         }
-    );
+    ).pure().describe(
+        "Divides two scalars.",
+        ["Left hand side", "Right hand side"]);
 
     builder.add_3(
         "rgb",
@@ -82,7 +146,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_color(context.get_call_site(), SRGB::new(r, g, b, 1.0).into()))
         }
-    );
+    ).pure().describe(
+        "Creates an opaque color from sRGB components.",
+        ["Red, 0 to 1", "Green, 0 to 1", "Blue, 0 to 1"]);
 
     builder.add_4(
         "rgba",
@@ -91,20 +157,192 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_color(context.get_call_site(), SRGB::new(r, g, b, a).into()))
         }
-    );
+    ).pure().describe(
+        "Creates a color from sRGB components and alpha.",
+        ["Red, 0 to 1", "Green, 0 to 1", "Blue, 0 to 1", "Alpha, 0 to 1"]);
 
-    builder.add_4(
+    builder.add_2(
+        "blackbody",
+        ["temperature", "intensity"],
+        |context, temperature: Scalar, intensity: Option<Scalar>|
+        {
+            let color = crate::color::blackbody(temperature).multiplied_by_scalar(intensity.unwrap_or(1.0));
+
+            Ok(Value::new_color(context.get_call_site(), color.into()))
+        }
+    ).pure().describe(
+        "Creates the color of a blackbody radiator, such as fire or a hot filament, with the brightest component scaled to the intensity.",
+        ["Temperature in Kelvin", "Optional intensity, defaulting to 1"]);
+
+    builder.add_6(
         "camera",
-        ["location", "look_at", "up", "fov"],
-        |context, location: Point3, look_at: Point3, up: Dir3, fov: Scalar|
+        ["location", "look_at", "up", "fov", "aperture", "focus_distance"],
+        |context, location: Point3, look_at: Point3, up: Dir3, fov: Scalar, aperture: Option<Scalar>, focus_distance: Option<Scalar>|
         {
-            let camera = Camera { location, look_at, up, fov };
+            let camera = Camera { location, look_at, up, fov, aperture: aperture.unwrap_or(0.0), focus_distance: focus_distance.unwrap_or(0.0) };
 
             context.with_app_state::<Scene, _, _>(|scene| { scene.camera = camera.clone(); Ok(()) })?;
 
             Ok(Value::new_camera(context.get_call_site(), camera))
         }
-    );
+    ).describe(
+        "Sets the scene camera.",
+        ["Location of the camera", "Point the camera looks at", "Up direction", "Horizontal field of view in degrees", "Optional diameter of the lens - by default zero, keeping everything in focus", "Optional distance along the view direction that's in focus - by default the look-at point"]);
+
+    builder.add_2(
+        "depth_of_field",
+        ["aperture", "focus_distance"],
+        |context, aperture: Scalar, focus_distance: Option<Scalar>|
+        {
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    scene.camera.aperture = aperture;
+                    scene.camera.focus_distance = focus_distance.unwrap_or(0.0);
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Sets the scene camera's depth of field. Call it after setting the camera.",
+        ["Diameter of the lens - zero keeps everything in focus", "Optional distance along the view direction that's in focus - by default the look-at point"]);
+
+    builder.add_1(
+        "append_scene",
+        ["path"],
+        |context, path: Value|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let other = context.new_script_with_state(Scene::new());
+
+            run_script_file(context, other.clone(), false, source_location, &path, "append")?;
+
+            let other = other.with_app_state::<Scene, _, _>(|scene| Ok(scene.clone()))?;
+
+            context.with_app_state::<Scene, _, _>(|scene| { append_scene(scene, &other); Ok(()) })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Runs another scene script, and adds everything it creates to this scene. The camera isn't changed, and names that are already used get a numbered suffix. The path is relative to this script's file.",
+        ["Path to the scene script"]);
+
+    builder.add_1(
+        "include",
+        ["path"],
+        |context, path: Value|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            run_script_file(context, context.root_scope(), true, source_location, &path, "include")?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Runs another script as if it were part of this one, so the variables and functions it defines can be used after it, and everything it creates is added to this scene. They're defined at the top level, wherever it's included from. The path is relative to the including script's file.",
+        ["Path to the script"]);
+
+    builder.add_1(
+        "backplate",
+        ["path"],
+        |context, path: Value|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let image = import::image::import_image(&path, &mut context.file_system())
+                .map_err(|i| ExecError::new(source_location, i.0))?;
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    scene.backplate = Some(scene.collection.push_named(image, path.clone()));
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Sets an image to show behind the scene, stretched to fill the camera's view. It doesn't light the scene.",
+        ["Path to the image file"]);
+
+    builder.add_5(
+        "atmosphere",
+        ["sun_elevation", "sun_azimuth", "sun_intensity", "sun_size", "haze"],
+        |context, sun_elevation: Scalar, sun_azimuth: Scalar, sun_intensity: Option<Scalar>, sun_size: Option<Scalar>, haze: Option<Scalar>|
+        {
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    let defaults = Atmosphere::default();
+                    let clouds = scene.atmosphere.take().and_then(|a| a.clouds);
+
+                    scene.atmosphere = Some(Atmosphere
+                    {
+                        sun_elevation,
+                        sun_azimuth,
+                        sun_intensity: sun_intensity.unwrap_or(defaults.sun_intensity),
+                        sun_size: sun_size.unwrap_or(defaults.sun_size),
+                        haze: haze.unwrap_or(defaults.haze),
+                        clouds,
+                        ..defaults
+                    });
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Lights the scene with a sky and sun, seen by rays that escape the scene. The y axis is up.",
+        ["Angle of the sun above the horizon, in degrees", "Angle of the sun around the horizon, from +z towards +x, in degrees", "Optional irradiance from the sun", "Optional angular diameter of the sun, in degrees", "Optional amount of haze - zero gives clean air"]);
+
+    builder.add_5(
+        "clouds",
+        ["coverage", "density", "altitude", "thickness", "scale"],
+        |context, coverage: Scalar, density: Option<Scalar>, altitude: Option<Scalar>, thickness: Option<Scalar>, scale: Option<Scalar>|
+        {
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    let defaults = Clouds::default();
+
+                    scene.atmosphere.get_or_insert_with(Atmosphere::default).clouds = Some(Clouds
+                    {
+                        coverage,
+                        density: density.unwrap_or(defaults.density),
+                        altitude: altitude.unwrap_or(defaults.altitude),
+                        thickness: thickness.unwrap_or(defaults.thickness),
+                        scale: scale.unwrap_or(defaults.scale),
+                    });
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Adds a layer of procedural clouds to the atmosphere, adding the atmosphere if there isn't one. Distances are in meters.",
+        ["Fraction of the sky covered, from 0 to 1", "Optional extinction per meter in the densest parts", "Optional height of the cloud base", "Optional thickness of the layer", "Optional typical size of a cloud"]);
+
+    builder.add_2(
+        "lighting_region",
+        ["bounds", "lights"],
+        |context, bounds: Option<Aabb>, lights: Option<Vec<Value>>|
+        {
+            let lights = match lights
+            {
+                Some(lights) => Some(lights.into_iter().map(|l| l.into_object()).collect::<ExecResult<Vec<_>>>()?),
+                None => None,
+            };
+
+            let bounds = bounds.map(|b| crate::desc::edit::geom::Aabb { min: b.min, max: b.max });
+
+            context.with_app_state::<Scene, _, _>(|scene| { scene.lighting_region = Some(LightingRegion { bounds, lights }); Ok(()) })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Sets which lights are sampled directly, and where. Without it, every emitting sphere, rectangle and disc is sampled, everywhere.",
+        ["Optional bounds - surfaces outside them don't sample the lights", "Optional list of objects to sample - an empty list samples none"]);
 
     builder.add_2(
         "aabb",
@@ -115,7 +353,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_aabb(context.get_call_site(), aabb))
         }
-    );
+    ).pure().describe(
+        "Creates an axis-aligned bounding box.",
+        ["Minimum corner", "Maximum corner"]);
 
     builder.add_3(
         "box",
@@ -128,7 +368,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_geom(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a box geometry.",
+        ["Minimum corner", "Maximum corner", "Optional name"]);
 
     builder.add_3(
         "sphere",
@@ -140,7 +382,37 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_geom(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a sphere geometry.",
+        ["Center of the sphere", "Radius of the sphere", "Optional name"]);
+
+    builder.add_4(
+        "rectangle",
+        ["point", "u", "v", "name"],
+        |context, point: Point3, u: Vec3, v: Vec3, name: Option<String>|
+        {
+            let geom = Geom::Rectangle{ point, u, v };
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push_opt_name(geom, name)))?;
+
+            Ok(Value::new_geom(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a rectangle geometry. It faces the direction of u cross v, and is sampled directly when it's a light.",
+        ["A corner", "Edge from the corner", "Other edge from the corner", "Optional name"]);
+
+    builder.add_4(
+        "disc",
+        ["center", "normal", "radius", "name"],
+        |context, center: Point3, normal: Dir3, radius: Scalar, name: Option<String>|
+        {
+            let geom = Geom::Disc{ center, normal, radius };
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push_opt_name(geom, name)))?;
+
+            Ok(Value::new_geom(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a disc geometry, which is sampled directly when it's a light.",
+        ["Center of the disc", "Direction the disc faces", "Radius of the disc", "Optional name"]);
 
     builder.add_2(
         "sdf_sphere",
@@ -149,7 +421,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_sdf(context.get_call_site(), Sdf::Sphere{ center, radius }))
         }
-    );
+    ).pure().describe(
+        "Creates a signed distance field sphere.",
+        ["Center of the sphere", "Radius of the sphere"]);
 
     builder.add_3(
         "sdf_capsule",
@@ -158,7 +432,85 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_sdf(context.get_call_site(), Sdf::Capsule{ a, b, radius }))
         }
-    );
+    ).pure().describe(
+        "Creates a signed distance field capsule between two points.",
+        ["First end point", "Second end point", "Radius of the capsule"]);
+
+    builder.add_2(
+        "sdf_box",
+        ["min", "max"],
+        |context, min: Point3, max: Point3|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Box{ min, max }))
+        }
+    ).pure().describe(
+        "Creates a signed distance field box.",
+        ["Minimum corner", "Maximum corner"]);
+
+    builder.add_3(
+        "sdf_rounded_box",
+        ["min", "max", "radius"],
+        |context, min: Point3, max: Point3, radius: Scalar|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::RoundedBox{ min, max, radius }))
+        }
+    ).pure().describe(
+        "Creates a signed distance field box with rounded edges, that fits within the corners.",
+        ["Minimum corner", "Maximum corner", "Radius of the edges"]);
+
+    builder.add_4(
+        "sdf_torus",
+        ["center", "axis", "major_radius", "minor_radius"],
+        |context, center: Point3, axis: Dir3, major_radius: Scalar, minor_radius: Scalar|
+        {
+            if axis.magnitude_squared() <= 0.0
+            {
+                return Err(ExecError::new(context.get_call_site(), "Torus axis must not be zero"));
+            }
+
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Torus{ center, axis: axis.normalized(), major_radius, minor_radius }))
+        }
+    ).pure().describe(
+        "Creates a signed distance field torus.",
+        ["Center of the torus", "Axis it's around", "Radius of the ring", "Radius of the tube"]);
+
+    builder.add_4(
+        "sdf_cone",
+        ["a", "b", "radius_a", "radius_b"],
+        |context, a: Point3, b: Point3, radius_a: Scalar, radius_b: Scalar|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Cone{ a, b, radius_a, radius_b }))
+        }
+    ).pure().describe(
+        "Creates a signed distance field cone between two points, capped at each end.",
+        ["First end point", "Second end point", "Radius at the first end", "Radius at the second end - zero for a point"]);
+
+    builder.add_3(
+        "sdf_cylinder",
+        ["a", "b", "radius"],
+        |context, a: Point3, b: Point3, radius: Scalar|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Cylinder{ a, b, radius }))
+        }
+    ).pure().describe(
+        "Creates a signed distance field cylinder between two points, capped at each end.",
+        ["First end point", "Second end point", "Radius of the cylinder"]);
+
+    builder.add_2(
+        "sdf_plane",
+        ["point", "normal"],
+        |context, point: Point3, normal: Dir3|
+        {
+            if normal.magnitude_squared() <= 0.0
+            {
+                return Err(ExecError::new(context.get_call_site(), "Plane normal must not be zero"));
+            }
+
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Plane{ point, normal: normal.normalized() }))
+        }
+    ).pure().describe(
+        "Creates a signed distance field half-space, behind a plane. It's unbounded, so can't be made into a mesh.",
+        ["Point on the plane", "Normal, pointing out of the half-space"]);
 
     builder.add_vec(
         "sdf_union",
@@ -167,7 +519,72 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_sdf(context.get_call_site(), Sdf::Union { members }))
         }
-    );
+    ).pure().describe(
+        "Creates the union of signed distance fields.",
+        ["Signed distance fields to combine"]);
+
+    builder.add_vec(
+        "sdf_intersect",
+        "items",
+        |context, members|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Intersection { members }))
+        }
+    ).pure().describe(
+        "Creates the intersection of signed distance fields - where they all overlap.",
+        ["Signed distance fields to intersect"]);
+
+    builder.add_2(
+        "sdf_subtract",
+        ["sdf", "subtracted"],
+        |context, sdf: Sdf, subtracted: Sdf|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::Subtraction { sdf: Box::new(sdf), subtracted: Box::new(subtracted) }))
+        }
+    ).pure().describe(
+        "Cuts one signed distance field out of another.",
+        ["Signed distance field to cut from", "Signed distance field to cut out"]);
+
+    builder.add_1_vec(
+        "sdf_smooth_union",
+        ["radius", "items"],
+        |context, radius: Scalar, members|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::SmoothUnion { members, radius }))
+        }
+    ).pure().describe(
+        "Creates the union of signed distance fields, blended together where they're within the radius of each other.",
+        ["Blend radius", "Signed distance fields to combine"]);
+
+    builder.add_3(
+        "sdf_smooth_subtract",
+        ["sdf", "subtracted", "radius"],
+        |context, sdf: Sdf, subtracted: Sdf, radius: Scalar|
+        {
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::SmoothSubtraction { sdf: Box::new(sdf), subtracted: Box::new(subtracted), radius }))
+        }
+    ).pure().describe(
+        "Cuts one signed distance field out of another, with the edges of the cut rounded by the radius.",
+        ["Signed distance field to cut from", "Signed distance field to cut out", "Blend radius"]);
+
+    builder.add_3(
+        "sdf_mesh",
+        ["sdf", "resolution", "name"],
+        |context, sdf: Sdf, resolution: Scalar, name: Option<String>|
+        {
+            if !sdf.is_bounded()
+            {
+                return Err(ExecError::new(context.get_call_site(), "Signed distance field must be bounded to make a mesh"));
+            }
+
+            let geom = Geom::Mesh{ triangles: sdf_triangles(&sdf, resolution.max(1.0) as usize), transform: Transform::new(), lod: None };
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push_opt_name(geom, name)))?;
+
+            Ok(Value::new_geom(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a mesh geometry approximating a signed distance field, for exporting it or rendering it faster.",
+        ["Signed distance field", "Number of grid cells along the longest side of its bounds", "Optional name"]);
 
     builder.add_2(
         "sdf_annular",
@@ -176,7 +593,49 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
         {
             Ok(Value::new_sdf(context.get_call_site(), Sdf::Annular{ sdf: Box::new(sdf), radius }))
         }
-    );
+    ).pure().describe(
+        "Creates a shell of the given thickness around a signed distance field.",
+        ["Signed distance field", "Shell radius"]);
+
+    builder.add_5(
+        "sdf_transform",
+        ["sdf", "offset", "axis", "degrees", "scale"],
+        |context, sdf: Sdf, offset: Option<Vec3>, axis: Option<Dir3>, degrees: Option<Scalar>, scale: Option<Scalar>|
+        {
+            let mut transform = Mat4::identity();
+
+            if let Some(scale) = scale
+            {
+                if scale == 0.0
+                {
+                    return Err(ExecError::new(context.get_call_site(), "Scale must not be zero"));
+                }
+
+                transform.scale_3d(Vec3::broadcast(scale));
+            }
+
+            if let Some(degrees) = degrees
+            {
+                let axis = axis.unwrap_or_else(Dir3::unit_y);
+
+                if axis.magnitude_squared() <= 0.0
+                {
+                    return Err(ExecError::new(context.get_call_site(), "Rotation axis must not be zero"));
+                }
+
+                transform.rotate_3d(degrees.to_radians(), axis.normalized());
+            }
+
+            if let Some(offset) = offset
+            {
+                transform.translate_3d(offset);
+            }
+
+            Ok(Value::new_sdf(context.get_call_site(), Sdf::transformed(sdf, transform)))
+        }
+    ).pure().describe(
+        "Scales, then rotates, then translates a signed distance field.",
+        ["Signed distance field", "Optional offset", "Optional axis of rotation - the Y axis by default", "Optional angle of rotation in degrees", "Optional uniform scale factor"]);
 
     builder.add_2(
         "plane",
@@ -188,22 +647,126 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_geom(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds an infinite plane geometry.",
+        ["A point on the plane", "Plane normal"]);
+
+    builder.add_3(
+        "ground",
+        ["point", "normal", "uv_scale"],
+        |context, point, normal, uv_scale: Scalar|
+        {
+            if uv_scale <= 0.0
+            {
+                return Err(ExecError::new(context.get_call_site(), "Ground UV scale must be positive"));
+            }
+
+            let geom = Geom::Ground{ point, normal, uv_scale, fade: None };
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(geom)))?;
+
+            Ok(Value::new_geom(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds an infinite ground plane geometry, with texture coordinates mapped across the plane.",
+        ["The ground's origin point", "Ground normal", "Size of one unit of texture coordinates"]);
+
+    builder.add_3(
+        "ground_fade",
+        ["ground", "start", "end"],
+        |context, ground: GeomIndex, start: Scalar, end: Scalar|
+        {
+            let call_site = context.get_call_site();
+
+            if end <= start
+            {
+                return Err(ExecError::new(call_site, "Ground fade must end after it starts"));
+            }
+
+            let index = context.with_app_state::<Scene, _, _>(|scene|
+            {
+                match scene.collection.map_item(ground, |geom, _| geom.clone())
+                {
+                    Geom::Ground{ point, normal, uv_scale, .. } =>
+                    {
+                        Ok(scene.collection.push(Geom::Ground{ point, normal, uv_scale, fade: Some(GroundFade{ start, end }) }))
+                    },
+                    _ => Err(ExecError::new(call_site, "Expected a ground geometry")),
+                }
+            })?;
+
+            Ok(Value::new_geom(call_site, index))
+        }
+    ).describe(
+        "Returns a copy of a ground that fades out to the background with distance from its origin point.",
+        ["Ground geometry", "Distance at which the fade starts", "Distance beyond which the ground is invisible"]);
 
     builder.add_3(
         "triangle",
         ["v1", "v2", "v3"],
         |context, v1, v2, v3|
         {
-            let v1 = TriangleVertex{ location: v1, texture_coords: Point3::new(0.0, 0.0, 0.0), opt_color: None, };
-            let v2 = TriangleVertex{ location: v2, texture_coords: Point3::new(0.0, 0.0, 0.0), opt_color: None, };
-            let v3 = TriangleVertex{ location: v3, texture_coords: Point3::new(0.0, 0.0, 0.0), opt_color: None, };
-            let geom = Geom::Triangle{triangle: Triangle { vertices: [v1, v2, v3]}};
+            let v1 = TriangleVertex{ location: v1, texture_coords: Point3::new(0.0, 0.0, 0.0), opt_color: None, opt_normal: None };
+            let v2 = TriangleVertex{ location: v2, texture_coords: Point3::new(0.0, 0.0, 0.0), opt_color: None, opt_normal: None };
+            let v3 = TriangleVertex{ location: v3, texture_coords: Point3::new(0.0, 0.0, 0.0), opt_color: None, opt_normal: None };
+            let geom = Geom::Triangle{triangle: Box::new(Triangle { vertices: [v1, v2, v3]})};
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(geom)))?;
+
+            Ok(Value::new_geom(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a single triangle geometry.",
+        ["First vertex", "Second vertex", "Third vertex"]);
+
+    builder.add_4(
+        "volume_grid",
+        ["bounds", "resolution", "density", "scale"],
+        |context, bounds: Aabb, resolution: Vec3, density: Vec<Value>, scale: Option<Scalar>|
+        {
+            let call_site = context.get_call_site();
+
+            if resolution.iter().any(|r| (*r < 1.0) || (r.fract() != 0.0))
+            {
+                return Err(ExecError::new(call_site, "Volume grid resolution must be positive whole numbers"));
+            }
+
+            let resolution = [resolution.x as usize, resolution.y as usize, resolution.z as usize];
+            let values = density.into_iter().map(|v| v.into_scalar()).collect::<ExecResult<Vec<_>>>()?;
+
+            if values.len() != resolution.iter().product::<usize>()
+            {
+                return Err(ExecError::new(call_site, format!("Volume grid has {} densities - expected {}", values.len(), resolution.iter().product::<usize>())));
+            }
+
+            if values.iter().any(|v| *v < 0.0)
+            {
+                return Err(ExecError::new(call_site, "Volume grid densities can't be negative"));
+            }
+
+            let aabb = crate::desc::edit::geom::Aabb{ min: bounds.min, max: bounds.max };
+            let geom = Geom::Volume{ aabb, density: Density::Grid{ resolution, values: Arc::new(values) }, scale: scale.unwrap_or(1.0) };
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(geom)))?;
+
+            Ok(Value::new_geom(call_site, index))
+        }
+    ).describe(
+        "Adds a volume geometry, with densities interpolated across a grid. Use with a medium material.",
+        ["Bounding box of the volume", "Number of grid cells along each axis", "List of densities, in x, then y, then z order", "Optional extinction per unit of distance at a density of 1"]);
+
+    builder.add_4(
+        "volume_noise",
+        ["bounds", "frequency", "octaves", "scale"],
+        |context, bounds: Aabb, frequency: Scalar, octaves: Option<Scalar>, scale: Option<Scalar>|
+        {
+            let aabb = crate::desc::edit::geom::Aabb{ min: bounds.min, max: bounds.max };
+            let density = Density::Noise{ frequency, octaves: octaves.unwrap_or(4.0).max(1.0) as usize };
+            let geom = Geom::Volume{ aabb, density, scale: scale.unwrap_or(1.0) };
             let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(geom)))?;
 
             Ok(Value::new_geom(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a volume geometry, with densities from fractal noise between 0 and 1. Use with a medium material.",
+        ["Bounding box of the volume", "Noise cells across the box", "Optional number of noise octaves", "Optional extinction per unit of distance at a density of 1"]);
 
     builder.add_2(
         "load_obj",
@@ -213,9 +776,11 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
             let source_location = path.source_location();
             let path = path.into_string()?;
 
+            let file_system = context.file_system();
+
             context.with_app_state::<Scene, _, _>(|scene|
                 {
-                    import::obj::import_obj_file(&path, &destination, scene)
+                    import::obj::import_obj_file(&path, &file_system, &destination, scene)
                         .map_err(|i| ExecError::new(source_location, i.0))?;
 
                     Ok(())
@@ -223,7 +788,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_void())
         }
-    );
+    ).describe(
+        "Imports the objects from a Wavefront OBJ file, scaled to fit the destination box.",
+        ["Path to the OBJ file", "Bounding box to fit the model into"]);
 
     builder.add_1(
         "load_obj_as_mesh",
@@ -233,12 +800,89 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
             let source_location = path.source_location();
             let path = path.into_string()?;
 
-            let geom = import::obj::import_obj_file_as_triangle_mesh(&path).map_err(|i| ExecError::new(source_location, i.0))?;
+            let geom = import::obj::import_obj_file_as_triangle_mesh(&path, &context.file_system()).map_err(|i| ExecError::new(source_location, i.0))?;
             let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(geom)))?;
 
             Ok(Value::new_geom(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Imports a Wavefront OBJ file as a single triangle mesh geometry.",
+        ["Path to the OBJ file"]);
+
+    builder.add_3(
+        "mesh_lod",
+        ["mesh", "levels", "screen_size"],
+        |context, mesh: GeomIndex, levels: Vec<Value>, screen_size: Scalar|
+        {
+            let call_site = context.get_call_site();
+            let levels = levels.into_iter().map(|l| l.into_geom(context)).collect::<ExecResult<Vec<_>>>()?;
+
+            let index = context.with_app_state::<Scene, _, _>(|scene|
+            {
+                let levels = levels.iter()
+                    .map(|l| mesh_triangles(scene, *l, call_site))
+                    .collect::<ExecResult<Vec<_>>>()?;
+
+                add_mesh_lod(scene, mesh, call_site, |_| MeshLod { levels, screen_size })
+            })?;
+
+            Ok(Value::new_geom(call_site, index))
+        }
+    ).describe(
+        "Returns a copy of a mesh with lower detail meshes to use when it's small on screen. The levels' own transforms are ignored.",
+        ["Mesh geometry", "List of mesh geometries, from most to least detailed", "Size in pixels below which the first level is used - each level halves this"]);
+
+    builder.add_3(
+        "auto_lod",
+        ["mesh", "count", "screen_size"],
+        |context, mesh: GeomIndex, count: Scalar, screen_size: Scalar|
+        {
+            let call_site = context.get_call_site();
+
+            let index = context.with_app_state::<Scene, _, _>(|scene|
+                add_mesh_lod(scene, mesh, call_site, |triangles| MeshLod::decimated(triangles, count.max(0.0) as usize, screen_size)))?;
+
+            Ok(Value::new_geom(call_site, index))
+        }
+    ).describe(
+        "Returns a copy of a mesh with automatically simplified meshes to use when it's small on screen.",
+        ["Mesh geometry", "Number of levels to generate", "Size in pixels below which the first level is used - each level halves this"]);
+
+    builder.add_3(
+        "mesh_cleanup",
+        ["mesh", "weld_tolerance", "fix_winding"],
+        |context, mesh: GeomIndex, weld_tolerance: Option<Scalar>, fix_winding: Option<bool>|
+        {
+            let call_site = context.get_call_site();
+            let mut cleanup = MeshCleanup::default();
+
+            if let Some(weld_tolerance) = weld_tolerance
+            {
+                cleanup.weld_tolerance = weld_tolerance;
+            }
+
+            cleanup.fix_winding = fix_winding.unwrap_or(false);
+
+            let index = context.with_app_state::<Scene, _, _>(|scene|
+            {
+                match scene.collection.map_item(mesh, |geom, _| geom.clone())
+                {
+                    Geom::Mesh{ triangles, transform, .. } =>
+                    {
+                        let (triangles, report) = cleanup.apply(triangles);
+                        import::print_cleanup_report("Mesh", &report);
+
+                        Ok(scene.collection.push(Geom::Mesh{ triangles, transform, lod: None }))
+                    },
+                    _ => Err(ExecError::new(call_site, "Expected a mesh geometry")),
+                }
+            })?;
+
+            Ok(Value::new_geom(call_site, index))
+        }
+    ).describe(
+        "Returns a copy of a mesh with nearby vertices welded, and degenerate and duplicate triangles removed. Imported meshes are already cleaned up with the default tolerance. Levels of detail aren't copied.",
+        ["Mesh geometry", "Optional distance, as a fraction of the mesh's size, within which vertices are welded", "Optionally flip triangles so neighbours are wound the same way"]);
 
     builder.add_2(
         "load_gltf",
@@ -248,9 +892,107 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
             let source_location = path.source_location();
             let path = path.into_string()?;
 
+            let file_system = context.file_system();
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    import::gltf::import_gltf_file(&path, &file_system, &destination, scene)
+                        .map_err(|i| ExecError::new(source_location, i.0))?;
+
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Imports the objects from a glTF file, scaled to fit the destination box.",
+        ["Path to the glTF file", "Bounding box to fit the model into"]);
+
+    builder.add_2(
+        "load_fbx",
+        ["path", "destination"],
+        |context, path: Value, destination|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let file_system = context.file_system();
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    import::fbx::import_fbx_file(&path, &file_system, &destination, scene)
+                        .map_err(|i| ExecError::new(source_location, i.0))?;
+
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Imports the meshes from an FBX file, scaled to fit the destination box.",
+        ["Path to the FBX file", "Bounding box to fit the model into"]);
+
+    builder.add_1(
+        "load_pbrt",
+        ["path"],
+        |context, path: Value|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let file_system = context.file_system();
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    import::pbrt::import_pbrt_file(&path, &file_system, scene)
+                        .map_err(|i| ExecError::new(source_location, i.0))?;
+
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Imports the camera, shapes, materials and area lights from a PBRT scene file.",
+        ["Path to the PBRT file"]);
+
+    builder.add_2(
+        "load_usd",
+        ["path", "destination"],
+        |context, path: Value, destination|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let file_system = context.file_system();
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    import::usd::import_usd_file(&path, &file_system, &destination, scene)
+                        .map_err(|i| ExecError::new(source_location, i.0))?;
+
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Imports the meshes and preview surface materials from a USD text (usda) or USDZ file, scaled to fit the destination box.",
+        ["Path to the USD file", "Bounding box to fit the model into"]);
+
+    builder.add_2(
+        "load_vox",
+        ["path", "destination"],
+        |context, path: Value, destination|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let file_system = context.file_system();
+
             context.with_app_state::<Scene, _, _>(|scene|
                 {
-                    import::gltf::import_gltf_file(&path, &destination, scene)
+                    import::vox::import_vox_file(&path, &file_system, &destination, scene)
                         .map_err(|i| ExecError::new(source_location, i.0))?;
 
                     Ok(())
@@ -258,7 +1000,9 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_void())
         }
-    );
+    ).describe(
+        "Imports a MagicaVoxel model as colored voxel meshes, scaled to fit the destination box.",
+        ["Path to the .vox file", "Bounding box to fit the model into"]);
 
     builder.add_2(
         "texture_checkerboard",
@@ -270,31 +1014,96 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_texture(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a checkerboard texture alternating between two colors.",
+        ["First color", "Second color"]);
+
+    builder.add_2(
+        "texture_image",
+        ["path", "linear"],
+        |context, path: Value, linear: Option<bool>|
+        {
+            let source_location = path.source_location();
+            let path = path.into_string()?;
+
+            let image = import::image::import_image(&path, &mut context.file_system())
+                .map_err(|i| ExecError::new(source_location, i.0))?;
+
+            if linear == Some(true)
+            {
+                image.set_color_space(import::image::ColorSpace::Linear);
+            }
+
+            let index = context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    let image = scene.collection.push_named(image, path.clone());
+
+                    Ok(scene.collection.push(Texture::Image
+                    {
+                        base_color: Color::default(),
+                        image,
+                        scale: Point3::new(1.0, 1.0, 1.0),
+                        rotate: 0.0,
+                        translate: Point3::new(0.0, 0.0, 0.0),
+                    }))
+                })?;
+
+            Ok(Value::new_texture(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a texture from an image file.",
+        ["Path to the image file", "Optional - true for non-color data, such as normal maps"]);
 
     builder.add_1(
+        "texture_expression",
+        ["script"],
+        |context, script: Value|
+        {
+            let source_location = script.source_location();
+            let script = script.into_string()?;
+
+            // Check it compiles now so errors are
+            // reported against the script
+
+            crate::texture::Texture::expression(&script)
+                .map_err(|e| ExecError::new(source_location, e.message()))?;
+
+            let texture = Texture::Expression(script);
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(texture)))?;
+
+            Ok(Value::new_texture(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a texture computed from an expression of u, v, position and normal.",
+        ["Expression script"]);
+
+    builder.add_2(
         "dielectric",
-        ["ior"],
-        |context, ior|
+        ["ior", "tint"],
+        |context, ior, tint: Option<TextureIndex>|
         {
-            let material = Material::Dielectric{ ior };
+            let material = Material::Dielectric{ ior, tint };
             let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(material)))?;
 
             Ok(Value::new_material(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a dielectric material, such as glass.",
+        ["Index of refraction", "Optional color or texture transmitted each time light passes through the surface, for stained glass"]);
 
-    builder.add_1(
+    builder.add_2(
         "diffuse",
-        ["texture"],
-        |context, texture|
+        ["texture", "normal_texture"],
+        |context, texture, normal_texture: Option<TextureIndex>|
         {
-            let material = Material::Diffuse{ texture };
+            let material = Material::Diffuse{ texture, normal_texture };
             let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(material)))?;
 
             Ok(Value::new_material(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a diffuse (Lambertian) material.",
+        ["Color or texture", "Optional tangent-space normal map texture"]);
 
     builder.add_1(
         "emit",
@@ -306,35 +1115,443 @@ pub fn add_inbuilt_functions(root_context: &mut Context)
 
             Ok(Value::new_material(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds an emissive material.",
+        ["Color or texture"]);
 
-    builder.add_2(
+    builder.add_3(
         "metal",
-        ["texture", "fuzz"],
-        |context, texture, fuzz|
+        ["texture", "fuzz", "normal_texture"],
+        |context, texture, fuzz, normal_texture: Option<TextureIndex>|
+        {
+            let material = Material::Metal{ texture, fuzz, normal_texture };
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(material)))?;
+
+            Ok(Value::new_material(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a metal material.",
+        ["Color or texture", "Roughness, 0 for a perfect mirror", "Optional tangent-space normal map texture"]);
+
+    builder.add_4(
+        "pbr",
+        ["texture", "metallic", "roughness", "normal_texture"],
+        |context, texture, metallic, roughness, normal_texture: Option<TextureIndex>|
         {
-            let material = Material::Metal{ texture, fuzz };
+            let material = Material::Pbr{ texture, metallic, roughness, normal_texture };
             let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(material)))?;
 
             Ok(Value::new_material(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds a metallic-roughness material, as used by glTF.",
+        ["Base color or texture", "Metallic, from 0 for dielectric to 1 for metal", "Perceptual roughness, from 0 to 1", "Optional tangent-space normal map texture"]);
 
     builder.add_2(
+        "medium",
+        ["albedo", "emission"],
+        |context, albedo, emission: Option<TextureIndex>|
+        {
+            let index = context.with_app_state::<Scene, _, _>(|scene|
+            {
+                let emission = emission.unwrap_or_else(|| scene.collection.push(Texture::Solid(Color::from(crate::color::LinearRGB::black()))));
+
+                Ok(scene.collection.push(Material::Medium{ albedo, emission }))
+            })?;
+
+            Ok(Value::new_material(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a participating medium material, for volume geometries. Light is scattered equally in all directions, with a probability of the albedo, and otherwise absorbed - emitting the emission color.",
+        ["Color or texture", "Optional emitted color or texture, for fire"]);
+
+    builder.add_5(
         "object",
-        ["geometry", "material"],
-        |context, geom, material|
+        ["geometry", "material", "ray_bias", "opacity", "opacity_cutoff"],
+        |context, geom, material, ray_bias: Option<Scalar>, opacity: Option<TextureIndex>, opacity_cutoff: Option<Scalar>|
         {
-            let object = Object{ geom, material };
+            let opacity = opacity.map(|texture| Opacity { texture, luminance: false, cutoff: opacity_cutoff });
+            let object = Object{ geom, material, ray_bias, opacity };
             let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(object)))?;
 
             Ok(Value::new_object(context.get_call_site(), index))
         }
-    );
+    ).describe(
+        "Adds an object combining a geometry and a material.",
+        ["Geometry, or a signed distance field to ray march", "Material", "Optional distance to offset rays leaving the surface, to fix self-intersection artifacts", "Optional texture whose alpha is the chance of rays hitting the surface, for alpha cards", "Optional alpha at or above which the surface is opaque, and below which it's transparent"]);
+
+    builder.add_1(
+        "probe",
+        ["location"],
+        |context, location: Point3|
+        {
+            let probe = Probe { location, ..Probe::default() };
+            context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(probe)))?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Adds a spherical harmonic irradiance probe.",
+        ["Location of the probe"]);
+
+    builder.add_5(
+        "studio_rig",
+        ["target", "key_intensity", "fill", "rim", "softbox_size"],
+        |context, target: Aabb, key_intensity: Option<Scalar>, fill: Option<Scalar>, rim: Option<Scalar>, softbox_size: Option<Scalar>|
+        {
+            let defaults = StudioRig::default();
+
+            let rig = StudioRig
+            {
+                key_intensity: key_intensity.unwrap_or(defaults.key_intensity),
+                fill: fill.unwrap_or(defaults.fill),
+                rim: rim.unwrap_or(defaults.rim),
+                softbox_size: softbox_size.unwrap_or(defaults.softbox_size),
+            };
+
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(rig.build(scene, &target)))?;
+
+            Ok(Value::new_group(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a three-point studio lighting setup - key, fill and rim area lights, a bounce card and a backdrop sweep - around a target, placed relative to the camera. Returns a group containing the rig.",
+        ["Bounds of the subject to light", "Optional key light intensity", "Optional fill light intensity, relative to the key", "Optional rim light intensity, relative to the key", "Optional width of the softboxes - by default they're sized to the target"]);
+
+    builder.add_3(
+        "turntable",
+        ["frames", "radius", "elevation"],
+        |context, frames: Scalar, radius: Option<Scalar>, elevation: Option<Scalar>|
+        {
+            let defaults = Turntable::default();
+
+            let turntable = Turntable
+            {
+                frames: frames.max(1.0) as usize,
+                radius: radius.unwrap_or(defaults.radius),
+                elevation: elevation.unwrap_or(defaults.elevation),
+            };
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    scene.camera_keyframes = turntable.keyframes(&scene.camera);
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Sets the camera animation to one full orbit around the point the camera is looking at, replacing any existing keyframes. Call it after setting the camera.",
+        ["Number of frames", "Optional distance from the look-at point - by default the camera's current distance", "Optional angle above the horizontal, in degrees"]);
+
+    builder.add_2(
+        "add_camera",
+        ["name", "camera"],
+        |context, name: String, camera: Camera|
+        {
+            context.with_app_state::<Scene, _, _>(|scene| { scene.cameras.push((name, camera)); Ok(()) })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Adds a named camera, e.g. for a contact sheet. As camera() also sets the scene camera, set the main camera last.",
+        ["Name of the camera", "Camera"]);
+
+    builder.add_3(
+        "material_variant",
+        ["name", "object", "material"],
+        |context, name: String, object: ObjectIndex, material: MaterialIndex|
+        {
+            context.with_app_state::<Scene, _, _>(|scene| { scene.material_variants.push(MaterialVariant { name, object, material }); Ok(()) })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Adds an alternative material for an object, to compare on a contact sheet. The scene itself keeps the object's original material.",
+        ["Name of the variant", "Object", "Material to use instead"]);
+
+    builder.add_4(
+        "variant",
+        ["set", "name", "object", "material"],
+        |context, set: String, name: String, object: ObjectIndex, material: MaterialIndex|
+        {
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    let index = match scene.variant_sets.iter().position(|s| s.name == set)
+                    {
+                        Some(index) => index,
+                        None =>
+                        {
+                            scene.variant_sets.push(VariantSet::new(set));
+                            scene.variant_sets.len() - 1
+                        },
+                    };
+
+                    scene.variant_sets[index].assign(&name, object, material);
+                    Ok(())
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Assigns a material to an object in a variant of a variant set, e.g. variant(\"Paint\", \"Red\", body, red). Call it again to assign more objects in the same variant. No variant is active until one is selected.",
+        ["Name of the variant set", "Name of the variant", "Object", "Material the variant gives the object"]);
+
+    builder.add_2(
+        "select_variant",
+        ["set", "name"],
+        |context, set: String, name: String|
+        {
+            let call_site = context.get_call_site();
+
+            context.with_app_state::<Scene, _, _>(|scene|
+                {
+                    scene.find_variant_set(&set)
+                        .and_then(|set| set.select(&name))
+                        .map_err(|e| ExecError::new(call_site, e))
+                })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Selects the active variant of a variant set, or \"none\" to use the objects' own materials.",
+        ["Name of the variant set", "Name of the variant"]);
+
+    builder.add_2(
+        "contact_sheet",
+        ["mode", "columns"],
+        |context, mode: Value, columns: Option<Scalar>|
+        {
+            let source_location = mode.source_location();
+            let mode = mode.into_string()?;
+            let mode = ContactSheetMode::from_name(&mode)
+                .ok_or_else(|| ExecError::new(source_location, format!("Unknown contact sheet mode {:?} - expected \"cameras\", \"materials\" or \"variants\"", mode)))?;
+
+            let sheet = ContactSheet { mode, columns: columns.unwrap_or(0.0).max(0.0) as usize };
+
+            context.with_app_state::<Scene, _, _>(|scene| { scene.contact_sheet = Some(sheet); Ok(()) })?;
+
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Renders the scene as a contact sheet when it's exported from the command line, with a labeled thumbnail for each named camera, material variant, or variant of each variant set.",
+        ["\"cameras\", \"materials\" or \"variants\"", "Optional number of columns - by default the grid is roughly square"]);
+
+    builder.add_1(
+        "print",
+        ["value"],
+        |_context, value: Value|
+        {
+            println!("{:#}", value);
+            Ok(Value::new_void())
+        }
+    ).describe(
+        "Prints a value to the console, for debugging.",
+        ["Value to print"]);
+
+    builder.add_vec(
+        "list",
+        "items",
+        |context, items: Vec<Value>|
+        {
+            context.check_collection_size(context.get_call_site(), items.len())?;
+
+            Ok(Value::new_list(context.get_call_site(), items))
+        }
+    ).pure().describe(
+        "Creates a list - the same as [items...].",
+        ["Values in the list"]);
+
+    builder.add_1(
+        "len",
+        ["list"],
+        |context, list: Vec<Value>|
+        {
+            Ok(Value::new_scalar(context.get_call_site(), list.len() as Scalar))
+        }
+    ).pure().describe(
+        "Returns the number of items in a list.",
+        ["List"]);
+
+    builder.add_1_vec(
+        "call",
+        ["function", "args"],
+        |context, function: Function, args: Vec<Value>|
+        {
+            let call_site = context.get_call_site();
+            function.call(context, call_site, ActualArguments { positional: args, ..ActualArguments::default() })
+        }
+    ).describe(
+        "Calls a function with the given arguments.",
+        ["Function to call", "Positional arguments"]);
+
+    builder.add_2(
+        "map",
+        ["list", "function"],
+        |context, list: Vec<Value>, function: Function|
+        {
+            let call_site = context.get_call_site();
+
+            let items = list.into_iter()
+                .map(|item| function.call(context, call_site, ActualArguments { positional: vec![item], ..ActualArguments::default() }))
+                .collect::<ExecResult<Vec<_>>>()?;
+
+            Ok(Value::new_list(call_site, items))
+        }
+    ).describe(
+        "Calls a function for each item in a list, returning a list of the results.",
+        ["List", "Function taking a single item"]);
+
+    builder.add_vec(
+        "group",
+        "items",
+        |context, items: Vec<Value>|
+        {
+            let mut group = Group::default();
+
+            add_group_items(&mut group, items)?;
+
+            let index = context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(group)))?;
+
+            Ok(Value::new_group(context.get_call_site(), index))
+        }
+    ).describe(
+        "Adds a group of objects and groups.",
+        ["Objects, groups or lists of them to include"]);
+
+    builder.add_2(
+        "group_translate",
+        ["group", "offset"],
+        |context, group: GroupIndex, offset: Vec3|
+        {
+            add_group_transform_stage(context, group, TransformStage::Translate(offset))
+        }
+    ).describe(
+        "Translates a group.",
+        ["Group", "Offset"]);
+
+    builder.add_2(
+        "group_scale",
+        ["group", "scale"],
+        |context, group: GroupIndex, scale: Scalar|
+        {
+            add_group_transform_stage(context, group, TransformStage::Scale(scale))
+        }
+    ).describe(
+        "Uniformly scales a group.",
+        ["Group", "Scale factor"]);
+
+    builder.add_3(
+        "group_rotate",
+        ["group", "axis", "degrees"],
+        |context, group: GroupIndex, axis: Dir3, degrees: Scalar|
+        {
+            add_group_transform_stage(context, group, TransformStage::Quaternion(Quaternion::rotation_3d(degrees.to_radians(), axis)))
+        }
+    ).describe(
+        "Rotates a group about an axis.",
+        ["Group", "Axis of rotation", "Angle in degrees"]);
+
+    builder.build()
+}
 
-    for func in builder.build()
+fn add_group_items(group: &mut Group, items: Vec<Value>) -> ExecResult<()>
+{
+    for item in items
     {
-        let name = func.get_name().to_owned();
-        root_context.set_var_named(&name, Value::new_function(func));
+        match item.data()
+        {
+            ValueData::Object(object) => group.objects.push(*object),
+            ValueData::Group(child) => group.groups.push(*child),
+            ValueData::List(list) => add_group_items(group, list.clone())?,
+            _ => return Err(ExecError::new(item.source_location(), "Expected Object, Group or List")),
+        }
     }
+
+    Ok(())
+}
+
+fn mesh_triangles(scene: &Scene, mesh: GeomIndex, call_site: SourceLocation) -> ExecResult<Vec<Triangle>>
+{
+    match scene.collection.map_item(mesh, |geom, _| geom.clone())
+    {
+        Geom::Mesh{ triangles, .. } => Ok(triangles),
+        _ => Err(ExecError::new(call_site, "Expected a mesh geometry")),
+    }
+}
+
+fn add_mesh_lod<F>(scene: &mut Scene, mesh: GeomIndex, call_site: SourceLocation, make_lod: F) -> ExecResult<GeomIndex>
+    where F: FnOnce(&[Triangle]) -> MeshLod
+{
+    match scene.collection.map_item(mesh, |geom, _| geom.clone())
+    {
+        Geom::Mesh{ triangles, transform, .. } =>
+        {
+            let lod = make_lod(&triangles);
+            Ok(scene.collection.push(Geom::Mesh{ triangles, transform, lod: Some(lod) }))
+        },
+        _ => Err(ExecError::new(call_site, "Expected a mesh geometry")),
+    }
+}
+
+fn add_group_transform_stage(context: &mut Context, group: GroupIndex, stage: TransformStage) -> ExecResult<Value>
+{
+    context.with_app_state::<Scene, _, _>(|scene|
+    {
+        let mut value = scene.collection.map_item(group, |group, _| group.clone());
+        value.transform.stages.push(stage);
+        scene.collection.update_value(group, value);
+        Ok(())
+    })?;
+
+    Ok(Value::new_group(context.get_call_site(), group))
+}
+
+/// Runs a script file, relative to the current one, in the
+/// given scope - the including script's top level, so they
+/// share their variables and scene, or a separate script.
+/// The verb (include or append) is for errors.
+fn run_script_file(context: &Context, mut scope: Context, shares_scope: bool, source_location: SourceLocation, path: &str, verb: &str) -> ExecResult<()>
+{
+    let (script, file_system) = context.file_system().load_text_file(path)
+        .map_err(|e| ExecError::new(source_location, format!("Could not {} {:?}: {}", verb, path, e.0)))?;
+
+    let filename = file_system.cwd().join(file_system.path_to_filename(path));
+
+    if !context.push_script_file(filename)
+    {
+        return Err(ExecError::new(source_location, format!("Script {:?} {}s itself", path, verb)));
+    }
+
+    let result = crate::exec::parse(&script).and_then(|expressions|
+        {
+            let expressions = if shares_scope
+            {
+                crate::exec::optimize_included(expressions, &scope)
+            }
+            else
+            {
+                crate::exec::optimize(expressions, &scope)
+            };
+
+            for exp in expressions
+            {
+                exp.evaluate(&mut scope)?;
+            }
+
+            Ok(())
+        });
+
+    context.pop_script_file();
+
+    // Its source locations are within the included script,
+    // so report the error at the include, with the line
+
+    result.map_err(|e|
+        {
+            match e.source_location().line_number(&script)
+            {
+                Some(line) => ExecError::new(source_location, format!("In {:?} line {}: {}", path, line, e.message())),
+                None => ExecError::new(source_location, format!("In {:?}: {}", path, e.message())),
+            }
+        })
 }
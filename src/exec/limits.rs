@@ -0,0 +1,163 @@
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::exec::{ExecError, ExecResult, SourceLocation};
+
+/// Limits on a script's execution, so that a buggy script
+/// reports an error rather than hanging or exhausting memory.
+#[derive(Clone, Copy, Debug)]
+pub struct ExecLimits
+{
+    /// Maximum number of function calls
+    pub max_steps: u64,
+    pub max_time: Duration,
+    /// Maximum depth of nested function calls
+    pub max_depth: usize,
+    /// Maximum number of items in a list
+    pub max_collection_size: usize,
+}
+
+impl Default for ExecLimits
+{
+    fn default() -> Self
+    {
+        ExecLimits
+        {
+            max_steps: 100_000_000,
+            max_time: Duration::from_secs(60),
+            max_depth: 10_000,
+            max_collection_size: 1_000_000,
+        }
+    }
+}
+
+/// Checking the time and cancel flag is relatively
+/// expensive, so it's only done every this many steps
+const STEPS_PER_CHECK: u64 = 1024;
+
+/// Tracks an execution against its limits. It's shared
+/// between all of the frames of a context.
+pub struct ExecMonitor
+{
+    limits: Cell<ExecLimits>,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    /// None without a clock, as on WASM in a browser,
+    /// where only the other limits apply
+    start: Cell<Option<Instant>>,
+    cancelled: RefCell<Option<Arc<AtomicBool>>>,
+    /// Where the stack started, and how much of it can be
+    /// used - where it's known, so running out of stack is
+    /// an error before the depth limit is reached
+    stack: Cell<Option<(usize, usize)>>,
+}
+
+impl ExecMonitor
+{
+    pub fn new() -> Self
+    {
+        ExecMonitor
+        {
+            limits: Cell::new(ExecLimits::default()),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            start: Cell::new(now()),
+            cancelled: RefCell::new(None),
+            stack: Cell::new(None),
+        }
+    }
+
+    pub fn set_limits(&self, limits: ExecLimits)
+    {
+        self.limits.set(limits);
+        self.steps.set(0);
+        self.start.set(now());
+    }
+
+    pub fn set_cancel_flag(&self, cancelled: Arc<AtomicBool>)
+    {
+        *self.cancelled.borrow_mut() = Some(cancelled);
+    }
+
+    /// The stack available from the caller's
+    /// position, e.g. at the top of a thread
+    pub fn set_stack_size(&self, size: usize)
+    {
+        self.stack.set(Some((stack_position(), size)));
+    }
+
+    pub fn begin_call(&self, call_site: SourceLocation) -> ExecResult<()>
+    {
+        let limits = self.limits.get();
+        let steps = self.steps.get() + 1;
+        let depth = self.depth.get() + 1;
+
+        if steps > limits.max_steps
+        {
+            return Err(ExecError::new(call_site, format!("Script stopped after exceeding the limit of {} steps - is it stuck in a loop?", limits.max_steps)));
+        }
+
+        if depth > limits.max_depth
+        {
+            return Err(ExecError::new(call_site, format!("Script stopped after exceeding the maximum call depth of {} - is there unbounded recursion?", limits.max_depth)));
+        }
+
+        if let Some((base, size)) = self.stack.get()
+        {
+            if base.abs_diff(stack_position()) > size
+            {
+                return Err(ExecError::new(call_site, format!("Script stopped after running out of stack at a call depth of {} - is there unbounded recursion?", depth)));
+            }
+        }
+
+        if steps.is_multiple_of(STEPS_PER_CHECK)
+        {
+            if self.cancelled.borrow().as_ref().map(|c| c.load(Ordering::Relaxed)).unwrap_or(false)
+            {
+                return Err(ExecError::new(call_site, "Script cancelled"));
+            }
+
+            if self.start.get().map(|start| start.elapsed() > limits.max_time).unwrap_or(false)
+            {
+                return Err(ExecError::new(call_site, format!("Script stopped after running for longer than the limit of {:?}", limits.max_time)));
+            }
+        }
+
+        self.steps.set(steps);
+        self.depth.set(depth);
+
+        Ok(())
+    }
+
+    pub fn end_call(&self)
+    {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    pub fn check_collection_size(&self, source: SourceLocation, size: usize) -> ExecResult<()>
+    {
+        let max = self.limits.get().max_collection_size;
+
+        if size > max
+        {
+            return Err(ExecError::new(source, format!("List of {} items exceeds the limit of {} items", size, max)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The address of a local, which moves as the stack grows
+fn stack_position() -> usize
+{
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// `Instant::now` panics on WASM without WASI
+fn now() -> Option<Instant>
+{
+    if cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) { None } else { Some(Instant::now()) }
+}
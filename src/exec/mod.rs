@@ -1,20 +1,31 @@
+mod compiled;
+mod complete;
 mod context;
+mod doc;
 mod error;
 mod exp;
 mod func;
 mod inbuilt;
+mod limits;
 mod native;
+mod optimize;
 mod parser;
 mod value;
 
 #[cfg(test)]
 mod tests;
 
+pub use compiled::{CompiledExpression, CompiledType, CompiledValue};
+pub use complete::{Completion, complete, identifier_prefix};
 pub use context::Context;
+pub use doc::{ArgumentDoc, FunctionDoc};
 pub use error::ExecError;
 pub use exp::Expression;
-pub use func::{ActualArgumentExpressions, ActualArguments, Function};
+pub use func::{ActualArgumentExpressions, ActualArguments, FormalArgument, Function};
+pub use inbuilt::inbuilt_function_docs;
+pub use limits::ExecLimits;
 pub use native::NativeFunctionBuilder;
+pub use optimize::{optimize, optimize_included};
 pub use parser::{parse, SourceLocation};
 pub use value::{FromValue, Value};
 
@@ -1,9 +1,53 @@
-use crate::exec::{Context, ExecResult, FromValue, Function, Value};
+use std::rc::Rc;
+
+use crate::exec::{Context, ExecResult, FromValue, Function, FunctionDoc, Value};
+
+type NativeCode = Box<dyn Fn(&mut Context) -> ExecResult<Value>>;
+
+struct NativeFunction
+{
+    name: String,
+    formal_arguments: Vec<String>,
+    variadic: bool,
+    doc: usize,
+    code: NativeCode,
+}
 
 pub struct NativeFunctionBuilder
 {
     context: Context,
-    funcs: Vec<Function>,
+    funcs: Vec<NativeFunction>,
+    docs: Vec<FunctionDoc>,
+}
+
+/// Allows documentation to be attached to the
+/// function just added to a `NativeFunctionBuilder`.
+pub struct NativeFunctionDoc<'a>
+{
+    doc: &'a mut FunctionDoc,
+}
+
+impl<'a> NativeFunctionDoc<'a>
+{
+    /// Marks the function as having no side effects, so calls
+    /// with constant arguments can be folded.
+    pub fn pure(self) -> Self
+    {
+        self.doc.pure = true;
+        self
+    }
+
+    pub fn describe<const N: usize>(self, description: &str, arg_descriptions: [&str; N])
+    {
+        assert_eq!(N, self.doc.args.len(), "Function {} has {} arguments", self.doc.name(), self.doc.args.len());
+
+        self.doc.description = description.to_owned();
+
+        for (arg, desc) in self.doc.args.iter_mut().zip(arg_descriptions.iter())
+        {
+            arg.description = (*desc).to_owned();
+        }
+    }
 }
 
 impl NativeFunctionBuilder
@@ -14,117 +58,152 @@ impl NativeFunctionBuilder
         {
             context: context.clone(),
             funcs: Vec::new(),
+            docs: Vec::new(),
         }
     }
 
     pub fn build(self) -> Vec<Function>
     {
-        self.funcs
+        let docs = self.docs.into_iter().map(Rc::new).collect::<Vec<_>>();
+        let mut context = self.context;
+
+        self.funcs.into_iter()
+            .map(|f| Function::new_inbuilt(f.name, f.formal_arguments, f.variadic, &mut context, Some(docs[f.doc].clone()), f.code))
+            .collect()
     }
 
-    pub fn add_vec<N, F, T1>(&mut self, names: N, arg: &str, func: F)
+    fn add<N: IntoFunctionNameSet>(&mut self, names: N, formal_arguments: Vec<String>, variadic: bool, code: impl Fn(&mut Context) -> ExecResult<Value> + Copy + 'static) -> NativeFunctionDoc<'_>
+    {
+        let names = names.into_names();
+        let doc = self.docs.len();
+
+        for name in names.iter()
+        {
+            self.funcs.push(NativeFunction
+            {
+                name: name.clone(),
+                formal_arguments: formal_arguments.clone(),
+                variadic,
+                doc,
+                code: Box::new(code),
+            });
+        }
+
+        self.docs.push(FunctionDoc::new(names, &formal_arguments, variadic));
+
+        NativeFunctionDoc { doc: &mut self.docs[doc] }
+    }
+
+    pub fn add_vec<N, F, T1>(&mut self, names: N, arg: &str, func: F) -> NativeFunctionDoc<'_>
         where N: IntoFunctionNameSet,
             F: Fn(&mut Context, Vec<T1>) -> ExecResult<Value> + Copy + 'static,
             T1: FromValue
     {
-        for name in names.into_names()
-        {
-            self.funcs.push(Function::new_inbuilt(
-                name,
-                vec![arg.to_string()],
-                &mut self.context.clone(),
-                move |context|
-                {
-                    let vec = context.get_param_all_positional()
-                        .into_iter()
-                        .map(|v| T1::from_value(v, context))
-                        .collect::<ExecResult<Vec<T1>>>()?;
-                    func(context, vec)
-                }));
-        }
+        self.add(
+            names,
+            vec![arg.to_string()],
+            true,
+            move |context|
+            {
+                let vec = context.get_param_all_positional()
+                    .into_iter()
+                    .map(|v| T1::from_value(v, context))
+                    .collect::<ExecResult<Vec<T1>>>()?;
+                func(context, vec)
+            })
     }
 
-    pub fn add_0<N, F>(&mut self, names: N, func: F)
+    pub fn add_1_vec<N, F, T1, T2>(&mut self, names: N, args: [&'static str;2], func: F) -> NativeFunctionDoc<'_>
+        where N: IntoFunctionNameSet,
+            F: Fn(&mut Context, T1, Vec<T2>) -> ExecResult<Value> + Copy + 'static,
+            T1: FromValue,
+            T2: FromValue
+    {
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            true,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                let vec = context.get_param_all_positional()
+                    .into_iter()
+                    .skip(1)
+                    .map(|v| T2::from_value(v, context))
+                    .collect::<ExecResult<Vec<T2>>>()?;
+                func(context, v1, vec)
+            })
+    }
+
+    pub fn add_0<N, F>(&mut self, names: N, func: F) -> NativeFunctionDoc<'_>
         where N: IntoFunctionNameSet,
             F: Fn(&mut Context) -> ExecResult<Value> + Copy + 'static
     {
-        for name in names.into_names()
-        {
-            self.funcs.push(Function::new_inbuilt(
-                name,
-                Vec::new(),
-                &mut self.context.clone(),
-                move |context|
-                {
-                    func(context)
-                }));
-        }
+        self.add(
+            names,
+            Vec::new(),
+            false,
+            move |context|
+            {
+                func(context)
+            })
     }
 
-    pub fn add_1<N, F, T1>(&mut self, names: N, args: [&'static str;1], func: F)
+    pub fn add_1<N, F, T1>(&mut self, names: N, args: [&'static str;1], func: F) -> NativeFunctionDoc<'_>
         where N: IntoFunctionNameSet,
             F: Fn(&mut Context, T1) -> ExecResult<Value> + Copy + 'static,
             T1: FromValue
     {
-        for name in names.into_names()
-        {
-            self.funcs.push(Function::new_inbuilt(
-                name.to_string(),
-                args.iter().map(|a| a.to_string()).collect(),
-                &mut self.context.clone(),
-                move |context|
-                {
-                    let v1 = T1::from_param(context, 0, args[0])?;
-                    func(context, v1)
-                }));
-        }
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            false,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                func(context, v1)
+            })
     }
 
-    pub fn add_2<N, F, T1, T2>(&mut self, names: N, args: [&'static str;2], func: F)
+    pub fn add_2<N, F, T1, T2>(&mut self, names: N, args: [&'static str;2], func: F) -> NativeFunctionDoc<'_>
         where N: IntoFunctionNameSet,
             F: Fn(&mut Context, T1, T2) -> ExecResult<Value> + Copy + 'static,
             T1: FromValue,
             T2: FromValue,
     {
-        for name in names.into_names()
-        {
-            self.funcs.push(Function::new_inbuilt(
-                name.to_string(),
-                args.iter().map(|a| a.to_string()).collect(),
-                &mut self.context.clone(),
-                move |context|
-                {
-                    let v1 = T1::from_param(context, 0, args[0])?;
-                    let v2 = T2::from_param(context, 1, args[1])?;
-                    func(context, v1, v2)
-                }));
-        }
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            false,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                let v2 = T2::from_param(context, 1, args[1])?;
+                func(context, v1, v2)
+            })
     }
 
-    pub fn add_3<N, F, T1, T2, T3>(&mut self, names: N, args: [&'static str;3], func: F)
+    pub fn add_3<N, F, T1, T2, T3>(&mut self, names: N, args: [&'static str;3], func: F) -> NativeFunctionDoc<'_>
         where N: IntoFunctionNameSet,
             F: Fn(&mut Context, T1, T2, T3) -> ExecResult<Value> + Copy + 'static,
             T1: FromValue,
             T2: FromValue,
             T3: FromValue,
     {
-        for name in names.into_names()
-        {
-            self.funcs.push(Function::new_inbuilt(
-                name.to_string(),
-                args.iter().map(|a| a.to_string()).collect(),
-                &mut self.context.clone(),
-                move |context|
-                {
-                    let v1 = T1::from_param(context, 0, args[0])?;
-                    let v2 = T2::from_param(context, 1, args[1])?;
-                    let v3 = T3::from_param(context, 2, args[2])?;
-                    func(context, v1, v2, v3)
-                }));
-        }
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            false,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                let v2 = T2::from_param(context, 1, args[1])?;
+                let v3 = T3::from_param(context, 2, args[2])?;
+                func(context, v1, v2, v3)
+            })
     }
 
-    pub fn add_4<N, F, T1, T2, T3, T4>(&mut self, names: N, args: [&'static str;4], func: F)
+    pub fn add_4<N, F, T1, T2, T3, T4>(&mut self, names: N, args: [&'static str;4], func: F) -> NativeFunctionDoc<'_>
         where N: IntoFunctionNameSet,
             F: Fn(&mut Context, T1, T2, T3, T4) -> ExecResult<Value> + Copy + 'static,
             T1: FromValue,
@@ -132,21 +211,68 @@ impl NativeFunctionBuilder
             T3: FromValue,
             T4: FromValue,
     {
-        for name in names.into_names()
-        {
-            self.funcs.push(Function::new_inbuilt(
-                name.to_string(),
-                args.iter().map(|a| a.to_string()).collect(),
-                &mut self.context.clone(),
-                move |context|
-                {
-                    let v1 = T1::from_param(context, 0, args[0])?;
-                    let v2 = T2::from_param(context, 1, args[1])?;
-                    let v3 = T3::from_param(context, 2, args[2])?;
-                    let v4 = T4::from_param(context, 3, args[3])?;
-                    func(context, v1, v2, v3, v4)
-                }));
-        }
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            false,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                let v2 = T2::from_param(context, 1, args[1])?;
+                let v3 = T3::from_param(context, 2, args[2])?;
+                let v4 = T4::from_param(context, 3, args[3])?;
+                func(context, v1, v2, v3, v4)
+            })
+    }
+
+    pub fn add_5<N, F, T1, T2, T3, T4, T5>(&mut self, names: N, args: [&'static str;5], func: F) -> NativeFunctionDoc<'_>
+        where N: IntoFunctionNameSet,
+            F: Fn(&mut Context, T1, T2, T3, T4, T5) -> ExecResult<Value> + Copy + 'static,
+            T1: FromValue,
+            T2: FromValue,
+            T3: FromValue,
+            T4: FromValue,
+            T5: FromValue,
+    {
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            false,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                let v2 = T2::from_param(context, 1, args[1])?;
+                let v3 = T3::from_param(context, 2, args[2])?;
+                let v4 = T4::from_param(context, 3, args[3])?;
+                let v5 = T5::from_param(context, 4, args[4])?;
+                func(context, v1, v2, v3, v4, v5)
+            })
+    }
+
+    pub fn add_6<N, F, T1, T2, T3, T4, T5, T6>(&mut self, names: N, args: [&'static str;6], func: F) -> NativeFunctionDoc<'_>
+        where N: IntoFunctionNameSet,
+            F: Fn(&mut Context, T1, T2, T3, T4, T5, T6) -> ExecResult<Value> + Copy + 'static,
+            T1: FromValue,
+            T2: FromValue,
+            T3: FromValue,
+            T4: FromValue,
+            T5: FromValue,
+            T6: FromValue,
+    {
+        self.add(
+            names,
+            args.iter().map(|a| a.to_string()).collect(),
+            false,
+            move |context|
+            {
+                let v1 = T1::from_param(context, 0, args[0])?;
+                let v2 = T2::from_param(context, 1, args[1])?;
+                let v3 = T3::from_param(context, 2, args[2])?;
+                let v4 = T4::from_param(context, 3, args[3])?;
+                let v5 = T5::from_param(context, 4, args[4])?;
+                let v6 = T6::from_param(context, 5, args[5])?;
+                func(context, v1, v2, v3, v4, v5, v6)
+            })
     }
 }
 
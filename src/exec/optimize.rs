@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+
+use crate::exec::{ActualArgumentExpressions, ActualArguments, Context, Expression, FormalArgument, SourceLocation, Value};
+use crate::exec::value::ValueData;
+use crate::vec::Vec3;
+
+/// Optimizes parsed expressions before they are evaluated in the
+/// given context:
+/// - Variables that the script never binds are resolved once,
+///   here, rather than searching every frame on each use - unless
+///   it includes other scripts, which could define them
+/// - Calls to pure functions with constant arguments are folded
+/// - Vectors of constants, and if statements with constant
+///   conditions, are folded
+pub fn optimize(expressions: Vec<Box<Expression>>, context: &Context) -> Vec<Box<Expression>>
+{
+    let mut optimizer = Optimizer::new(&expressions, context, true);
+
+    expressions.into_iter().map(|e| optimizer.optimize(*e)).collect()
+}
+
+/// Optimizes a script that's run in another script's scope - i.e.
+/// one that's included. Its functions can use variables that are
+/// defined after it's run, which could replace the inbuilt ones,
+/// so variables it never binds are left to be found as it runs.
+pub fn optimize_included(expressions: Vec<Box<Expression>>, context: &Context) -> Vec<Box<Expression>>
+{
+    let mut optimizer = Optimizer::new(&expressions, context, false);
+
+    expressions.into_iter().map(|e| optimizer.optimize(*e)).collect()
+}
+
+fn collect_names(expression: &Expression, bound: &mut HashSet<String>, read: &mut HashSet<String>)
+{
+    match expression
+    {
+        Expression::Constant{ .. } => {},
+        Expression::ReadNamedVar{ name, .. } =>
+        {
+            read.insert(name.clone());
+        },
+        Expression::Vector{ expressions, .. } | Expression::List{ expressions, .. } | Expression::Block{ expressions, .. } =>
+        {
+            expressions.iter().for_each(|e| collect_names(e, bound, read));
+        },
+        Expression::Record{ fields, .. } =>
+        {
+            fields.iter().for_each(|(_, e)| collect_names(e, bound, read));
+        },
+        Expression::Field{ expression, .. } =>
+        {
+            collect_names(expression, bound, read);
+        },
+        Expression::Function{ formal_arguments, expression, .. } =>
+        {
+            for formal in formal_arguments.iter()
+            {
+                bound.insert(formal.name.clone());
+
+                if let Some(default) = &formal.default
+                {
+                    collect_names(default, bound, read);
+                }
+            }
+
+            collect_names(expression, bound, read);
+        },
+        Expression::WriteNamedVar{ name, expression } =>
+        {
+            bound.insert(name.clone());
+            collect_names(expression, bound, read);
+        },
+        Expression::Call{ function, arguments, .. } =>
+        {
+            collect_names(function, bound, read);
+            arguments.positional.iter().for_each(|e| collect_names(e, bound, read));
+            arguments.named.iter().for_each(|(_, e)| collect_names(e, bound, read));
+        },
+        Expression::If{ conditions, alternative, .. } =>
+        {
+            for (cond, exp) in conditions.iter()
+            {
+                collect_names(cond, bound, read);
+                collect_names(exp, bound, read);
+            }
+
+            if let Some(alternative) = alternative
+            {
+                collect_names(alternative, bound, read);
+            }
+        },
+    }
+}
+
+struct Optimizer
+{
+    context: Context,
+    bound: HashSet<String>,
+    resolve_unbound: bool,
+}
+
+impl Optimizer
+{
+    fn new(expressions: &[Box<Expression>], context: &Context, resolve_unbound: bool) -> Self
+    {
+        let mut bound = HashSet::new();
+        let mut read = HashSet::new();
+
+        for exp in expressions.iter()
+        {
+            collect_names(exp, &mut bound, &mut read);
+        }
+
+        let resolve_unbound = resolve_unbound && !read.contains("include");
+
+        Optimizer { context: context.clone(), bound, resolve_unbound }
+    }
+
+    fn optimize(&mut self, expression: Expression) -> Box<Expression>
+    {
+        match expression
+        {
+            Expression::Constant{ value } =>
+            {
+                Expression::new_constant(value)
+            },
+            Expression::Vector{ source, expressions } =>
+            {
+                let expressions = expressions.into_iter().map(|e| self.optimize(*e)).collect::<Vec<_>>();
+
+                let scalars = expressions.iter()
+                    .map(|e| match &**e { Expression::Constant{ value } => match value.data() { ValueData::Scalar(s) => Some(*s), _ => None }, _ => None })
+                    .collect::<Option<Vec<_>>>();
+
+                match scalars
+                {
+                    Some(s) if s.len() == 3 => Expression::new_constant(Value::new_vec3(source, Vec3::new(s[0], s[1], s[2]))),
+                    _ => Expression::new_vector(source, expressions),
+                }
+            },
+            Expression::List{ source, expressions } =>
+            {
+                Expression::new_list(source, expressions.into_iter().map(|e| self.optimize(*e)).collect())
+            },
+            Expression::Record{ source, fields } =>
+            {
+                let fields = fields.into_iter().map(|(n, e)| (n, self.optimize(*e))).collect();
+
+                Expression::new_record(source, fields)
+            },
+            Expression::Field{ source, expression, name } =>
+            {
+                Expression::new_field(source, self.optimize(*expression), name)
+            },
+            Expression::Function{ source, name, formal_arguments, expression } =>
+            {
+                let formal_arguments = formal_arguments.into_iter()
+                    .map(|f| FormalArgument { name: f.name, default: f.default.map(|d| self.optimize(*d)) })
+                    .collect();
+
+                Expression::new_function(source, name, formal_arguments, self.optimize(*expression))
+            },
+            Expression::ReadNamedVar{ source, name } =>
+            {
+                // Names the script never binds can only
+                // refer to the context's variables - if they're
+                // inbuilt, they won't change. Variables from an
+                // including script might, so they're left alone -
+                // as are all of them if this script includes
+                // another, or is included, as those could redefine
+                // them. If it's undefined, leave it to report
+                // the error if it's ever evaluated.
+
+                if self.resolve_unbound && !self.bound.contains(&name)
+                {
+                    if let Ok(value) = self.context.get_var_named(source, &name)
+                    {
+                        if value.source_location() == SourceLocation::inbuilt()
+                        {
+                            return Expression::new_constant(value);
+                        }
+                    }
+                }
+
+                Expression::new_read_named_var(source, name)
+            },
+            Expression::WriteNamedVar{ name, expression } =>
+            {
+                Expression::new_write_named_var(name, self.optimize(*expression))
+            },
+            Expression::Call{ call_site, function, arguments } =>
+            {
+                let function = self.optimize(*function);
+
+                let arguments = ActualArgumentExpressions
+                {
+                    positional: arguments.positional.into_iter().map(|e| self.optimize(*e)).collect(),
+                    named: arguments.named.into_iter().map(|(n, e)| (n, self.optimize(*e))).collect(),
+                };
+
+                if let Some(value) = self.try_fold_call(call_site, &function, &arguments)
+                {
+                    return Expression::new_constant(value);
+                }
+
+                Expression::new_call(call_site, function, arguments)
+            },
+            Expression::Block{ expressions, .. } =>
+            {
+                Expression::new_block(expressions.into_iter().map(|e| self.optimize(*e)).collect())
+            },
+            Expression::If{ conditions, alternative, .. } =>
+            {
+                let mut remaining = Vec::new();
+
+                for (cond, exp) in conditions.into_iter()
+                {
+                    let cond = self.optimize(*cond);
+                    let exp = self.optimize(*exp);
+
+                    match constant_bool(&cond)
+                    {
+                        // Never taken - skip it
+                        Some(false) => {},
+                        // Always taken - if it's the first condition
+                        // then it's the result, otherwise it's
+                        // the new alternative
+                        Some(true) =>
+                        {
+                            if remaining.is_empty()
+                            {
+                                return exp;
+                            }
+
+                            return Expression::new_if(remaining, Some(exp));
+                        },
+                        None => remaining.push((cond, exp)),
+                    }
+                }
+
+                let alternative = alternative.map(|a| self.optimize(*a));
+
+                if remaining.is_empty()
+                {
+                    return match alternative
+                    {
+                        Some(alternative) => alternative,
+                        None => Expression::new_constant(Value::new_void()),
+                    };
+                }
+
+                Expression::new_if(remaining, alternative)
+            },
+        }
+    }
+
+    fn try_fold_call(&mut self, call_site: crate::exec::SourceLocation, function: &Expression, arguments: &ActualArgumentExpressions) -> Option<Value>
+    {
+        let function = match function
+        {
+            Expression::Constant{ value } => match value.data()
+            {
+                ValueData::Function(function) if function.get_doc().pure => function.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let mut actual = ActualArguments::default();
+
+        for exp in arguments.positional.iter()
+        {
+            match exp.as_ref()
+            {
+                Expression::Constant{ value } => actual.positional.push(value.clone()),
+                _ => return None,
+            }
+        }
+
+        for (name, exp) in arguments.named.iter()
+        {
+            match exp.as_ref()
+            {
+                Expression::Constant{ value } => { actual.named.insert(name.clone(), value.clone()); },
+                _ => return None,
+            }
+        }
+
+        // Any errors are reported when it's evaluated
+
+        function.call(&mut self.context, call_site, actual).ok()
+    }
+}
+
+fn constant_bool(expression: &Expression) -> Option<bool>
+{
+    match expression
+    {
+        Expression::Constant{ value } => match value.data()
+        {
+            ValueData::Bool(b) => Some(*b),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -1,5 +1,5 @@
 use crate::math::Scalar;
-use crate::exec::{ActualArgumentExpressions, ExecResult, ExecError, Expression, Value};
+use crate::exec::{ActualArgumentExpressions, ExecResult, ExecError, Expression, FormalArgument, Value};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SourceLocation
@@ -18,6 +18,17 @@ impl SourceLocation
     {
         SourceLocation{ offset }
     }
+
+    /// The line, from one, in the script it was parsed from
+    pub fn line_number(&self, script: &str) -> Option<usize>
+    {
+        if self.offset > script.len()
+        {
+            return None;
+        }
+
+        Some(script.as_bytes()[..self.offset].iter().filter(|b| **b == b'\n').count() + 1)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -113,7 +124,26 @@ fn parse_expression<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
         {
             if parser.peek_kind(TokenKind::Identifier)
             {
-                formal_arguments.push(parser.next().text.to_owned());
+                let name = parser.next();
+
+                if formal_arguments.iter().any(|f: &FormalArgument| f.name == name.text)
+                {
+                    return Err(ExecError::new(name.source, format!("Duplicate argument \"{}\"", name.text)));
+                }
+
+                let mut formal = FormalArgument::new(name.text.to_owned());
+
+                if parser.peek_ch(':')
+                {
+                    parser.next();
+                    formal.default = Some(parse_expression(parser)?);
+                }
+                else if formal_arguments.iter().any(|f| f.default.is_some())
+                {
+                    return Err(ExecError::new(name.source, format!("Argument \"{}\" must have a default value as it follows an argument with a default", name.text)));
+                }
+
+                formal_arguments.push(formal);
 
                 if parser.peek_ch(',')
                 {
@@ -194,7 +224,7 @@ fn parse_expression<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
 
             let sub = parse_sum(parser)?;
 
-            let actual_args = ActualArgumentExpressions::Positional(vec![result, sub]);
+            let actual_args = ActualArgumentExpressions::positional(vec![result, sub]);
 
             let function = Expression::new_read_named_var(op.source, op.text.to_owned());
 
@@ -215,7 +245,7 @@ fn parse_sum<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
 
         let sub = parse_term(parser)?;
 
-        let actual_args = ActualArgumentExpressions::Positional(vec![result, sub]);
+        let actual_args = ActualArgumentExpressions::positional(vec![result, sub]);
 
         let function = Expression::new_read_named_var(op.source, op.text.to_owned());
 
@@ -235,7 +265,7 @@ fn parse_term<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
 
         let sub = parse_call(parser)?;
 
-        let actual_args = ActualArgumentExpressions::Positional(vec![result, sub]);
+        let actual_args = ActualArgumentExpressions::positional(vec![result, sub]);
 
         let function = Expression::new_read_named_var(op.source, op.text.to_owned());
 
@@ -249,76 +279,68 @@ fn parse_call<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
 {
     let mut result = parse_factor(parser)?;
 
-    while parser.peek_ch('(') || parser.peek_ch('{')
+    while parser.peek_ch('(') || parser.peek_ch('{') || parser.peek_ch('.')
     {
         let open = parser.next();
 
-        if open.text == "("
+        if open.text == "."
         {
-            let mut args = Vec::new();
+            let name = parser.expect_kind(TokenKind::Identifier)?;
 
-            loop
-            {
-                if parser.peek_ch(')')
-                {
-                    let _ = parser.next();
-                    break;
-                }
-
-                args.push(parse_expression(parser)?);
-
-                if parser.peek_ch(')')
-                {
-                    continue;
-                }
-                else if parser.peek_ch(',')
-                {
-                    let _ = parser.next();
-                    continue;
-                }
+            result = Expression::new_field(name.source, result, name.text.to_owned());
+            continue;
+        }
 
-                return Err(parser.err_expected("',' or ')'"));
-            }
+        let close = if open.text == "(" { ')' } else { '}' };
+        let mut args = ActualArgumentExpressions::positional(Vec::new());
 
-            result = Expression::new_call(open.source, result, ActualArgumentExpressions::Positional(args));
-        }
-        else // '{'
+        loop
         {
-            let mut args = Vec::new();
-
-            loop
+            if parser.peek_ch(close)
             {
-                if parser.peek_ch('}')
-                {
-                    let _ = parser.next();
-                    break;
-                }
+                let _ = parser.next();
+                break;
+            }
 
-                if !parser.peek_kind(TokenKind::Identifier)
-                {
-                    return Err(parser.err_expected("Identitfier"));
-                }
+            if parser.peek_named_arg()
+            {
                 let name = parser.next();
-
                 parser.expect_ch(':')?;
 
-                args.push((name.text.to_string(), parse_expression(parser)?));
-
-                if parser.peek_ch('}')
+                if args.named.iter().any(|(n, _)| n == name.text)
                 {
-                    continue;
-                }
-                else if parser.peek_ch(',')
-                {
-                    let _ = parser.next();
-                    continue;
+                    return Err(ExecError::new(name.source, format!("Argument \"{}\" specified more than once", name.text)));
                 }
 
-                return Err(parser.err_expected("',' or '}'"));
+                args.named.push((name.text.to_string(), parse_expression(parser)?));
+            }
+            else if close == '}'
+            {
+                return Err(parser.err_expected("Identitfier"));
+            }
+            else if !args.named.is_empty()
+            {
+                return Err(parser.err_expected("named argument after other named arguments"));
+            }
+            else
+            {
+                args.positional.push(parse_expression(parser)?);
             }
 
-            result = Expression::new_call(open.source, result, ActualArgumentExpressions::Named(args));
+            if parser.peek_ch(close)
+            {
+                continue;
+            }
+            else if parser.peek_ch(',')
+            {
+                let _ = parser.next();
+                continue;
+            }
+
+            return Err(parser.err_expected(format!("',' or '{}'", close)));
         }
+
+        result = Expression::new_call(open.source, result, args);
     }
 
     Ok(result)
@@ -338,8 +360,9 @@ fn parse_factor<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
     else if parser.peek_kind(TokenKind::String)
     {
         let token = parser.next();
+        let text = unescape_string(token.text).map_err(|msg| ExecError::new(token.source, msg))?;
 
-        return Ok(Expression::new_constant(Value::new_string(token.source, token.text.to_owned())));
+        return Ok(Expression::new_constant(Value::new_string(token.source, text)));
     }
     else if parser.peek_kind(TokenKind::Identifier)
     {
@@ -387,13 +410,81 @@ fn parse_factor<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
             }
         }
     }
+    else if parser.peek_ch('{')
+    {
+        // Record
+
+        let start = parser.next();
+
+        let mut fields: Vec<(String, Box<Expression>)> = Vec::new();
+
+        loop
+        {
+            if parser.peek_ch('}')
+            {
+                let _ = parser.next();
+
+                return Ok(Expression::new_record(start.source, fields));
+            }
+
+            let name = parser.expect_kind(TokenKind::Identifier)?;
+            parser.expect_ch(':')?;
+
+            if fields.iter().any(|(n, _)| n == name.text)
+            {
+                return Err(ExecError::new(name.source, format!("Field \"{}\" specified more than once", name.text)));
+            }
+
+            fields.push((name.text.to_owned(), parse_expression(parser)?));
+
+            if parser.peek_ch(',')
+            {
+                let _ = parser.next();
+                continue;
+            }
+            else if !parser.peek_ch('}')
+            {
+                return Err(parser.err_expected("\",\" or \"}\" to end record"));
+            }
+        }
+    }
+    else if parser.peek_ch('[')
+    {
+        // List
+
+        let start = parser.next();
+
+        let mut exprs = Vec::new();
+
+        loop
+        {
+            if parser.peek_ch(']')
+            {
+                let _ = parser.next();
+
+                return Ok(Expression::new_list(start.source, exprs));
+            }
+
+            exprs.push(parse_expression(parser)?);
+
+            if parser.peek_ch(',')
+            {
+                let _ = parser.next();
+                continue;
+            }
+            else if !parser.peek_ch(']')
+            {
+                return Err(parser.err_expected("\",\" or \"]\" to end list"));
+            }
+        }
+    }
     else if parser.peek_ch('-')
     {
         let op = parser.next();
 
         let arg = parse_factor(parser)?;
 
-        let actual_args = ActualArgumentExpressions::Positional(vec![arg]);
+        let actual_args = ActualArgumentExpressions::positional(vec![arg]);
 
         let function = Expression::new_read_named_var(op.source, "neg".to_owned());
 
@@ -411,10 +502,40 @@ fn parse_factor<'a>(parser: &mut Parser<'a>) -> ExecResult<Box<Expression>>
     }
     else
     {
-        return Err(parser.err_expected("Unsigned int, string, identifier or open parenthesis/vector".to_owned()));
+        return Err(parser.err_expected("Unsigned int, string, identifier or open parenthesis/vector/list/record".to_owned()));
     }
 }
 
+/// Replaces the escapes in the text of a string token - quotes,
+/// backslashes, newlines, tabs and carriage returns
+fn unescape_string(text: &str) -> Result<String, String>
+{
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(ch) = chars.next()
+    {
+        if ch != '\\'
+        {
+            result.push(ch);
+            continue;
+        }
+
+        match chars.next()
+        {
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(other) => return Err(format!("Invalid escape \"\\{}\" in string - use \"\\\\\" for a backslash", other)),
+            None => return Err("Unterminated escape at the end of string".to_owned()),
+        }
+    }
+
+    Ok(result)
+}
+
 struct Parser<'a>
 {
     tokens: Vec<Token<'a>>,
@@ -457,6 +578,13 @@ impl<'a> Parser<'a>
         self.tokens[self.index].kind == kind
     }
 
+    fn peek_named_arg(&self) -> bool
+    {
+        (self.tokens[self.index].kind == TokenKind::Identifier)
+            && (self.tokens[self.index + 1].kind == TokenKind::Operator)
+            && (self.tokens[self.index + 1].text == ":")
+    }
+
     fn next(&mut self) -> Token<'a>
     {
         let result = self.tokens[self.index].clone();
@@ -562,6 +690,8 @@ impl<'a> Lexer<'a>
             None => self.token_start_offset = self.input.len(),
         }
 
+        self.token_len = 0;
+
         result
     }
 
@@ -669,6 +799,7 @@ fn parse_tokens<'a>(input: &'a str) -> ExecResult<Vec<Token<'a>>>
             || ch == '*'
             || ch == '(' || ch == ')'
             || ch == '{' || ch == '}'
+            || ch == '[' || ch == ']'
             || ch == '<' || ch == '>'
             || ch == '.'
             || ch == ':'
@@ -696,7 +827,19 @@ fn parse_tokens<'a>(input: &'a str) -> ExecResult<Vec<Token<'a>>>
                     lexer.ignore_token();
                     break;
                 }
-                else if (ch >= ' ') && (ch <= '~')
+                else if ch == '\\'
+                {
+                    // Escapes are checked by the parser - this
+                    // just stops an escaped quote ending the string
+
+                    lexer.accept_char();
+
+                    if !lexer.peek().is_control()
+                    {
+                        lexer.accept_char();
+                    }
+                }
+                else if !ch.is_control()
                 {
                     lexer.accept_char();
                 }
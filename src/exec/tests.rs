@@ -1,5 +1,8 @@
 use crate::math::Scalar;
-use crate::exec::{parse, Context, ExecResult, Value};
+use crate::exec::{complete, identifier_prefix, inbuilt_function_docs, optimize, parse, CompiledExpression, CompiledType, CompiledValue, Completion, Context, ExecLimits, ExecResult, Expression, Value};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use crate::vec::Vec3;
 
 fn eval_exp(input: &str) -> ExecResult<Value>
 {
@@ -7,9 +10,22 @@ fn eval_exp(input: &str) -> ExecResult<Value>
 
     let mut context = Context::new();
 
-    expressions.iter()
+    let result = expressions.iter()
         .map(|e| e.evaluate(&mut context))
-        .try_fold(Value::new_void(), |_, v| v)
+        .try_fold(Value::new_void(), |_, v| v);
+
+    // The optimized expressions must produce the same result
+
+    let mut context = Context::new();
+    let optimized = optimize(expressions, &context);
+
+    let optimized_result = optimized.iter()
+        .map(|e| e.evaluate(&mut context))
+        .try_fold(Value::new_void(), |_, v| v);
+
+    assert_eq!(format!("{:?}", result), format!("{:?}", optimized_result), "Optimized result differs for {:?}", input);
+
+    result
 }
 
 fn check_parse_error(input: &str)
@@ -31,6 +47,22 @@ fn test_parse()
     check_parse_error("\"asdf\"+");
 }
 
+#[test]
+fn test_strings()
+{
+    let check_string = |input: &str, expected: &str| assert_eq!(eval_exp(input).and_then(|val| val.into_string()), Ok(expected.to_owned()));
+
+    check_string("\"models/file.obj\"", "models/file.obj");
+    check_string("\"say \\\"hi\\\"\"", "say \"hi\"");
+    check_string("\"C:\\\\models\\\\file.obj\"", "C:\\models\\file.obj");
+    check_string("\"a\\tb\\nc\"", "a\tb\nc");
+    check_string("\"caf\u{e9}\"", "caf\u{e9}");
+
+    check_parse_error("\"C:\\models\"");
+    check_parse_error("\"asdf\\\"");
+    check_parse_error("\"new\nline\"");
+}
+
 #[test]
 fn test_uint()
 {
@@ -59,3 +91,282 @@ fn test_uint()
     check_scalar("function fib(n) { if (n == 1) { 1 } else { n * fib(n - 1) } } fib(3)", 6.0);
     check_scalar("function fib(n) { if (n == 1) { 1 } else { n * fib(n - 1) } } fib(4)", 24.0);
 }
+
+
+#[test]
+fn test_arguments()
+{
+    check_scalar("function f(a, b) { a - b } f(5, 2)", 3.0);
+    check_scalar("function f(a, b) { a - b } f{ b: 2, a: 5 }", 3.0);
+    check_scalar("function f(a, b) { a - b } f(5, b: 2)", 3.0);
+    check_scalar("function f(a, b: 2.0) { a - b } f(5)", 3.0);
+    check_scalar("function f(a, b: 2.0) { a - b } f(5, 1)", 4.0);
+    check_scalar("function f(a, b: a * 2) { b } f(3)", 6.0);
+    check_scalar("sub(5, rhs: 2)", 3.0);
+
+    assert!(eval_exp("function f(a, b) { a - b } f(5)").is_err());
+    assert!(eval_exp("function f(a, b) { a - b } f(5, 2, 1)").is_err());
+    assert!(eval_exp("function f(a, b) { a - b } f{ a: 5, c: 2 }").is_err());
+    assert!(eval_exp("function f(a, b) { a - b } f(5, a: 2)").is_err());
+    assert!(eval_exp("sub(5, 2, 1)").is_err());
+    assert!(eval_exp("sub{ lhs: 5, rsh: 2 }").is_err());
+
+    check_parse_error("function f(a: 1, b) { a }");
+    check_parse_error("function f(a, a) { a }");
+    check_parse_error("f(a: 1, 2)");
+    check_parse_error("f(a: 1, a: 2)");
+}
+
+#[test]
+fn test_closures()
+{
+    check_scalar("function adder(n) { function(x) { x + n } } let add2 = adder(2); add2(5)", 7.0);
+    check_scalar("function adder(n) { function(x) { x + n } } adder(3)(4)", 7.0);
+    check_scalar("let n = 10; function f(x) { x + n } call(f, 1)", 11.0);
+    check_scalar("len([1, 2, 3])", 3.0);
+    check_scalar("len([])", 0.0);
+    check_scalar("let fs = [function(x) { x * 2 }, function(x) { x * 3 }]; len(map(fs, function(f) { f(1) }))", 2.0);
+    check_scalar("let twice = function(f, x) { f(f(x)) }; twice(function(x) { x * 3 }, 2)", 18.0);
+
+    check_parse_error("[1, 2");
+    assert!(eval_exp("map(1, neg)").is_err());
+}
+
+#[test]
+fn test_records()
+{
+    check_scalar("let r = { pos: <1, 2, 3>, radius: 2 }; r.radius", 2.0);
+    check_scalar("let r = { pos: <1, 2, 3>, radius: 2 }; r.pos.y", 2.0);
+    check_scalar("function area(r) { r.w * r.h } area({ w: 3, h: 4 })", 12.0);
+    check_scalar("{ inner: { a: 5 } }.inner.a", 5.0);
+
+    assert!(eval_exp("let r = { a: 1 }; r.b").is_err());
+    assert!(eval_exp("let s = 1; s.a").is_err());
+    check_parse_error("{ a: 1, a: 2 }");
+    check_parse_error("{ a: 1");
+
+    let r = eval_exp("{ pos: <1, 2, 3>, radius: 2, tags: [\"a\"], empty: {} }").unwrap();
+    assert_eq!(format!("{}", r), "{ pos: <1, 2, 3>, radius: 2, tags: [\"a\"], empty: {} }");
+    assert_eq!(format!("{:?}", r), "{\n    pos: <1, 2, 3>,\n    radius: 2,\n    tags: [\"a\"],\n    empty: {}\n}");
+}
+
+#[test]
+fn test_compiled()
+{
+    let vars = [("u", CompiledType::Scalar), ("p", CompiledType::Vec3)];
+
+    let compiled = CompiledExpression::compile("let a = u * 2; if (a == 4) { <a, 1, 0> } else { p * a }", &vars).unwrap();
+    assert_eq!(compiled.result_type(), CompiledType::Vec3);
+    assert_eq!(compiled.evaluate(&[CompiledValue::Scalar(2.0), CompiledValue::Vec3(Vec3::zero())]), CompiledValue::Vec3(Vec3::new(4.0, 1.0, 0.0)));
+    assert_eq!(compiled.evaluate(&[CompiledValue::Scalar(1.0), CompiledValue::Vec3(Vec3::one())]), CompiledValue::Vec3(Vec3::new(2.0, 2.0, 2.0)));
+
+    assert!(CompiledExpression::compile("q", &vars).is_err());
+    assert!(CompiledExpression::compile("u == p", &vars).is_err());
+    assert!(CompiledExpression::compile("if (true) { 1 }", &vars).is_err());
+}
+
+#[test]
+fn test_optimize()
+{
+    let context = Context::new();
+
+    let optimized = optimize(parse("1 + 2 * 3").unwrap(), &context);
+    assert!(matches!(*optimized[0], Expression::Constant{ .. }));
+
+    let optimized = optimize(parse("function f(a) { a * (2 + 3) } f(1)").unwrap(), &context);
+    assert!(matches!(*optimized[1], Expression::Call{ .. }));
+
+    // Names bound by the script shadow the inbuilt functions
+
+    check_scalar("let add = 5; add", 5.0);
+    check_scalar("function f(sub) { sub } f(2)", 2.0);
+    check_scalar("function f() { mul } let mul = 3; f()", 3.0);
+
+    // Blocks still scope their own variables
+
+    check_scalar("let x = 1; if (true) { let x = 2; } x", 1.0);
+    check_scalar("let x = 1; if (true) { x + 1 }", 2.0);
+    check_scalar("let x = 1; if (x == 1) { let x = 3; x } else { 0 }", 3.0);
+    assert!(eval_exp("if (true) { let y = 2; } y").is_err());
+}
+
+fn eval_with_limits(input: &str, limits: ExecLimits, cancelled: bool) -> ExecResult<Value>
+{
+    let mut context = Context::new();
+    context.set_limits(limits);
+    context.set_cancel_flag(Arc::new(AtomicBool::new(cancelled)));
+
+    parse(input)?.iter()
+        .map(|e| e.evaluate(&mut context))
+        .try_fold(Value::new_void(), |_, v| v)
+}
+
+#[test]
+fn test_limits()
+{
+    let recurse = "function f(n) { f(n + 1) } f(0)";
+    let count = "function f(n) { if (n == 0) { 0 } else { f(n - 1) } } f(10)";
+
+    let limits = ExecLimits { max_depth: 20, ..ExecLimits::default() };
+    let err = eval_with_limits(recurse, limits, false).unwrap_err();
+    assert!(err.message().contains("depth of 20"));
+
+    let limits = ExecLimits { max_steps: 10, ..ExecLimits::default() };
+    let err = eval_with_limits(count, limits, false).unwrap_err();
+    assert!(err.message().contains("limit of 10 steps"));
+    assert!(eval_with_limits(count, ExecLimits::default(), false).is_ok());
+
+    // Cancellation is only checked periodically
+
+    let many = format!("map([{}], neg)", vec!["1"; 2000].join(", "));
+    assert!(eval_with_limits(&many, ExecLimits::default(), false).is_ok());
+    let err = eval_with_limits(&many, ExecLimits::default(), true).unwrap_err();
+    assert_eq!(err.message(), "Script cancelled");
+
+    let limits = ExecLimits { max_collection_size: 2, ..ExecLimits::default() };
+    assert!(eval_with_limits("[1, 2]", limits, false).is_ok());
+    assert!(eval_with_limits("[1, 2, 3]", limits, false).is_err());
+    assert!(eval_with_limits("list(1, 2, 3)", limits, false).is_err());
+}
+
+#[test]
+fn test_help()
+{
+    let help = eval_exp("help(\"sphere\")").and_then(|val| val.into_string()).unwrap();
+    assert!(help.starts_with("sphere(center, radius, name)"));
+
+    let help = eval_exp("help(\"add\")").and_then(|val| val.into_string()).unwrap();
+    assert!(help.contains("Also: add"));
+
+    assert!(eval_exp("help(\"not_a_function\")").is_err());
+}
+
+#[test]
+fn test_complete()
+{
+    assert_eq!(identifier_prefix("let x = sph", 11), "sph");
+    assert_eq!(identifier_prefix("add(1, 2", 8), "");
+    assert_eq!(identifier_prefix("sdf_s", 5), "sdf_s");
+
+    let docs = inbuilt_function_docs();
+    let vars = vec![Completion { name: "sphere_radius".to_owned(), detail: "Scalar".to_owned() }];
+
+    let names = complete("sph", &docs, &vars).into_iter().map(|c| c.name).collect::<Vec<_>>();
+    assert_eq!(names, vec!["sphere_radius".to_owned(), "sphere".to_owned()]);
+
+    assert!(complete("", &docs, &vars).is_empty());
+}
+
+#[test]
+fn test_include()
+{
+    let dir = std::env::temp_dir().join(format!("beam_test_include_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+
+    // Included relative to the including script, and
+    // sharing variables and functions in both directions
+
+    std::fs::write(dir.join("lib").join("base.beam"), "let base = 2;").unwrap();
+    std::fs::write(dir.join("lib").join("double.beam"), "include(\"base.beam\")\nfunction double(x) { x * base }\nlet answer = double(top);").unwrap();
+    std::fs::write(dir.join("lib").join("self.beam"), "let x = 1;\ninclude(\"self.beam\")").unwrap();
+    std::fs::write(dir.join("lib").join("redefine.beam"), "function eq(a, b) { 42 }").unwrap();
+    std::fs::write(dir.join("lib").join("later.beam"), "let later = function() { eq(1, 2) };").unwrap();
+    std::fs::write(dir.join("main.beam"), "").unwrap();
+
+    let eval = |input: &str|
+    {
+        let mut context = Context::new();
+        context.set_script_file(&dir.join("main.beam"));
+
+        let expressions = optimize(parse(input)?, &context);
+
+        expressions.iter()
+            .map(|e| e.evaluate(&mut context))
+            .try_fold(Value::new_void(), |_, v| v)
+    };
+
+    assert_eq!(eval("let top = 21; include(\"lib/double.beam\") answer + double(1)").and_then(|v| v.into_scalar()), Ok(44.0));
+    assert_eq!(eval("function f() { include(\"lib/base.beam\") } f() base").and_then(|v| v.into_scalar()), Ok(2.0));
+
+    // Included scripts can redefine inbuilt functions
+
+    assert_eq!(eval("include(\"lib/redefine.beam\") eq(1, 2)").and_then(|v| v.into_scalar()), Ok(42.0));
+
+    // And their functions use those defined after they're included
+
+    assert_eq!(eval("include(\"lib/later.beam\") function eq(a, b) { 42 } later()").and_then(|v| v.into_scalar()), Ok(42.0));
+
+    let err = eval("include(\"lib/double.beam\")").unwrap_err();
+    assert!(err.message().contains("line 3"), "{}", err.message());
+
+    let err = eval("include(\"lib/self.beam\")").unwrap_err();
+    assert!(err.message().contains("includes itself"), "{}", err.message());
+
+    assert!(eval("include(\"missing.beam\")").is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_stack()
+{
+    // Recursion is stopped before it overflows the stack,
+    // even where each call uses more stack than usual
+
+    let err = crate::desc::run_script("function f(n) { if (n == 0 - 1) { 0 } else { f(n + 1) } } f(0)").err().unwrap();
+    assert!(err.message().contains("recursion"), "{}", err.message());
+}
+
+#[test]
+fn test_append_scene()
+{
+    let dir = std::env::temp_dir().join(format!("beam_test_append_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+
+    std::fs::write(dir.join("lib").join("ball.beam"), "object(sphere(<0, 0, 0>, 1), diffuse(rgb(1, 1, 1)))").unwrap();
+    std::fs::write(dir.join("lib").join("self.beam"), "append_scene(\"self.beam\")").unwrap();
+    std::fs::write(dir.join("lib").join("recurse.beam"), "function f(n) { f(n + 1) } f(0)").unwrap();
+
+    let run = |script: &str, limits: ExecLimits|
+    {
+        crate::desc::run_script_with_limits(script, Some(&dir.join("main.beam")), limits, Arc::new(AtomicBool::new(false)))
+            .map(|(scene, _)| scene)
+    };
+
+    // Relative to the appending script, and the appended
+    // script's objects are added to this scene
+
+    let scene = run("append_scene(\"lib/ball.beam\") append_scene(\"lib/ball.beam\")", ExecLimits::default()).ok().unwrap();
+    assert_eq!(scene.collection.map_all(|_: &crate::desc::edit::Object, _| ()).len(), 2);
+
+    let err = run("append_scene(\"lib/self.beam\")", ExecLimits::default()).err().unwrap();
+    assert!(err.message().contains("appends itself"), "{}", err.message());
+
+    // The appended script shares this script's limits
+
+    let err = run("append_scene(\"lib/recurse.beam\")", ExecLimits { max_depth: 20, ..ExecLimits::default() }).err().unwrap();
+    assert!(err.message().contains("depth of 20"), "{}", err.message());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_load_relative()
+{
+    let dir = std::env::temp_dir().join(format!("beam_test_load_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+
+    std::fs::write(dir.join("lib").join("triangle.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    std::fs::write(dir.join("lib").join("geom.beam"), "load_obj(\"triangle.obj\", aabb(<0 - 1, 0 - 1, 0 - 1>, <1, 1, 1>))").unwrap();
+
+    // Files are loaded relative to the script that loads
+    // them, even when it's included by another
+
+    let scene = crate::desc::run_script_with_limits("include(\"lib/geom.beam\")", Some(&dir.join("main.beam")), ExecLimits::default(), Arc::new(AtomicBool::new(false)))
+        .map(|(scene, _)| scene)
+        .ok().unwrap();
+
+    assert_eq!(scene.collection.map_all(|_: &crate::desc::edit::Object, _| ()).len(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -1,6 +1,6 @@
-use crate::desc::edit::{Camera, Color, Scene, Texture};
+use crate::desc::edit::{Camera, Color, Geom, Scene, Texture, Transform};
 use crate::geom::Aabb;
-use crate::indexed::{MaterialIndex, GeomIndex, ObjectIndex, TextureIndex};
+use crate::indexed::{Index, MaterialIndex, GeomIndex, GroupIndex, ObjectIndex, TextureIndex};
 use crate::exec::{Context, ExecError, ExecResult, Function, SourceLocation};
 use crate::geom::sdf::Sdf;
 use crate::math::Scalar;
@@ -15,12 +15,15 @@ pub enum ValueData
     Scalar(Scalar),
     Vec3(Vec3),
     Function(Function),
+    List(Vec<Value>),
+    Record(Vec<(String, Value)>),
     Camera(Camera),
     Geom(GeomIndex),
     Aabb(Aabb),
     Material(MaterialIndex),
     Color(Color),
     Object(ObjectIndex),
+    Group(GroupIndex),
     Texture(TextureIndex),
     Sdf(Sdf),
 }
@@ -64,6 +67,16 @@ impl Value
         Value { source: function.get_source_location(), data: ValueData::Function(function), }
     }
 
+    pub fn new_list(source: SourceLocation, items: Vec<Value>) -> Value
+    {
+        Value { source, data: ValueData::List(items) }
+    }
+
+    pub fn new_record(source: SourceLocation, fields: Vec<(String, Value)>) -> Value
+    {
+        Value { source, data: ValueData::Record(fields) }
+    }
+
     pub fn new_camera(source: SourceLocation, camera: Camera) -> Value
     {
         Value { source, data: ValueData::Camera(camera) }
@@ -104,10 +117,44 @@ impl Value
         Value { source, data: ValueData::Object(object) }
     }
 
+    pub fn new_group(source: SourceLocation, group: GroupIndex) -> Value
+    {
+        Value { source, data: ValueData::Group(group) }
+    }
+
     pub fn source_location(&self) -> SourceLocation
     {
         self.source
     }
+
+    pub fn data(&self) -> &ValueData
+    {
+        &self.data
+    }
+
+    pub fn type_name(&self) -> &'static str
+    {
+        match &self.data
+        {
+            ValueData::Void => "Void",
+            ValueData::Bool(_) => "Bool",
+            ValueData::String(_) => "String",
+            ValueData::Scalar(_) => "Scalar",
+            ValueData::Vec3(_) => "Vec3",
+            ValueData::Function(_) => "Function",
+            ValueData::List(_) => "List",
+            ValueData::Record(_) => "Record",
+            ValueData::Camera(_) => "Camera",
+            ValueData::Geom(_) => "Geom",
+            ValueData::Aabb(_) => "Aabb",
+            ValueData::Material(_) => "Material",
+            ValueData::Color(_) => "Color",
+            ValueData::Object(_) => "Object",
+            ValueData::Group(_) => "Group",
+            ValueData::Texture(_) => "Texture",
+            ValueData::Sdf(_) => "Sdf",
+        }
+    }
     
     pub fn into_bool(self) -> ExecResult<bool>
     {
@@ -154,11 +201,48 @@ impl Value
         }
     }
 
-    pub fn into_geom(self) -> ExecResult<GeomIndex>
+    pub fn into_list(self) -> ExecResult<Vec<Value>>
+    {
+        match self.data
+        {
+            ValueData::List(val) => Ok(val),
+            _ => Err(self.type_error("List")),
+        }
+    }
+
+    /// Reads a field of a record, or a component of a vector
+    pub fn get_field(&self, source: SourceLocation, name: &str) -> ExecResult<Value>
+    {
+        match &self.data
+        {
+            ValueData::Record(fields) =>
+            {
+                fields.iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, v)| v.clone())
+                    .ok_or_else(|| ExecError::new(source, format!("Record has no field \"{}\"", name)))
+            },
+            ValueData::Vec3(vec) =>
+            {
+                match name
+                {
+                    "x" => Ok(Value::new_scalar(source, vec.x)),
+                    "y" => Ok(Value::new_scalar(source, vec.y)),
+                    "z" => Ok(Value::new_scalar(source, vec.z)),
+                    _ => Err(ExecError::new(source, format!("Vec3 has no field \"{}\"", name))),
+                }
+            },
+            _ => Err(ExecError::new(source, format!("Expected Record or Vec3 but found {}", self.type_name()))),
+        }
+    }
+
+    /// Signed distance fields are added to the scene as geometry
+    pub fn into_geom(self, context: &mut Context) -> ExecResult<GeomIndex>
     {
         match self.data
         {
             ValueData::Geom(val) => Ok(val),
+            ValueData::Sdf(sdf) => Ok(context.with_app_state::<Scene, _, _>(|scene| Ok(scene.collection.push(Geom::Sdf{ sdf, transform: Transform::new() })))?),
             _ => Err(self.type_error("Geom")),
         }
     }
@@ -227,12 +311,116 @@ impl Value
         }
     }
 
+    pub fn into_group(self) -> ExecResult<GroupIndex>
+    {
+        match self.data
+        {
+            ValueData::Group(val) => Ok(val),
+            _ => Err(self.type_error("Group")),
+        }
+    }
+
     fn type_error(&self, expected: &str) -> ExecError
     {
         ExecError::new(self.source, format!("Expected {}", expected))
     }
 }
 
+impl Value
+{
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result
+    {
+        let pretty = f.alternate();
+
+        match &self.data
+        {
+            ValueData::Void => write!(f, "void"),
+            ValueData::Bool(val) => write!(f, "{}", val),
+            ValueData::String(val) => write!(f, "{:?}", val),
+            ValueData::Scalar(val) => write!(f, "{}", val),
+            ValueData::Vec3(val) => write!(f, "<{}, {}, {}>", val.x, val.y, val.z),
+            ValueData::Function(func) => write!(f, "function {}", func.get_doc().signature()),
+            ValueData::List(items) =>
+            {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate()
+                {
+                    if i > 0
+                    {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_indented(f, indent)?;
+                }
+                write!(f, "]")
+            },
+            ValueData::Record(fields) =>
+            {
+                if fields.is_empty()
+                {
+                    return write!(f, "{{}}");
+                }
+
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate()
+                {
+                    if pretty
+                    {
+                        write!(f, "\n{:width$}{}: ", "", name, width = (indent + 1) * 4)?;
+                    }
+                    else
+                    {
+                        write!(f, "{}{}: ", if i > 0 { ", " } else { " " }, name)?;
+                    }
+
+                    value.fmt_indented(f, indent + 1)?;
+
+                    if pretty && ((i + 1) < fields.len())
+                    {
+                        write!(f, ",")?;
+                    }
+                }
+
+                if pretty
+                {
+                    write!(f, "\n{:width$}}}", "", width = indent * 4)
+                }
+                else
+                {
+                    write!(f, " }}")
+                }
+            },
+            ValueData::Camera(_) => write!(f, "Camera"),
+            ValueData::Geom(index) => write!(f, "Geom #{}", index.to_usize()),
+            ValueData::Aabb(aabb) => write!(f, "Aabb <{}, {}, {}> - <{}, {}, {}>", aabb.min.x, aabb.min.y, aabb.min.z, aabb.max.x, aabb.max.y, aabb.max.z),
+            ValueData::Material(index) => write!(f, "Material #{}", index.to_usize()),
+            ValueData::Color(_) => write!(f, "Color"),
+            ValueData::Object(index) => write!(f, "Object #{}", index.to_usize()),
+            ValueData::Group(index) => write!(f, "Group #{}", index.to_usize()),
+            ValueData::Texture(index) => write!(f, "Texture #{}", index.to_usize()),
+            ValueData::Sdf(_) => write!(f, "Sdf"),
+        }
+    }
+}
+
+/// Formats the value as script source where possible.
+/// The alternate form (`{:#}`) spreads records over
+/// multiple lines.
+impl std::fmt::Display for Value
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl std::fmt::Debug for Value
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{:#}", self)
+    }
+}
+
 pub trait FromValue
     where Self: Sized
 {
@@ -292,6 +480,22 @@ impl FromValue for Scalar
     }
 }
 
+impl FromValue for Function
+{
+    fn from_value(value: Value, _: &mut Context) -> ExecResult<Function>
+    {
+        value.into_function()
+    }
+}
+
+impl FromValue for Vec<Value>
+{
+    fn from_value(value: Value, _: &mut Context) -> ExecResult<Vec<Value>>
+    {
+        value.into_list()
+    }
+}
+
 impl FromValue for Vec3
 {
     fn from_value(value: Value, _: &mut Context) -> ExecResult<Vec3>
@@ -342,8 +546,32 @@ impl FromValue for MaterialIndex
 
 impl FromValue for GeomIndex
 {
-    fn from_value(value: Value, _: &mut Context) -> ExecResult<GeomIndex>
+    fn from_value(value: Value, context: &mut Context) -> ExecResult<GeomIndex>
+    {
+        value.into_geom(context)
+    }
+}
+
+impl FromValue for GroupIndex
+{
+    fn from_value(value: Value, _: &mut Context) -> ExecResult<GroupIndex>
     {
-        value.into_geom()
+        value.into_group()
     }
-}
\ No newline at end of file
+}
+
+impl FromValue for ObjectIndex
+{
+    fn from_value(value: Value, _: &mut Context) -> ExecResult<ObjectIndex>
+    {
+        value.into_object()
+    }
+}
+
+impl FromValue for Camera
+{
+    fn from_value(value: Value, _: &mut Context) -> ExecResult<Camera>
+    {
+        value.into_camera()
+    }
+}
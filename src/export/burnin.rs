@@ -0,0 +1,308 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{Rgba, RgbaImage};
+
+use crate::export::ExportMetadata;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Text, a logo and guides drawn over 8-bit exports, e.g. to
+/// label work-in-progress renders that are shared with others.
+/// They're never drawn in the window, or into OpenEXR files.
+#[derive(Clone, Debug, Default)]
+pub struct BurnIn
+{
+    /// Text for the bottom-left corner - see `expand_template`
+    pub template: String,
+    /// Path of an image for the bottom-right corner
+    pub logo: Option<String>,
+    /// Outlines of the action (90%) and title (80%) safe areas
+    pub safe_areas: bool,
+}
+
+impl BurnIn
+{
+    pub const DEFAULT_TEMPLATE: &'static str = "{scene} | {spp} spp | {date} {time}";
+
+    pub fn apply(&self, image: &mut RgbaImage, metadata: &ExportMetadata) -> Result<(), String>
+    {
+        // Sized for the image, so previews and
+        // final renders look the same when shared
+
+        let scale = (image.height() / 360).max(1);
+        let margin = 6 * scale;
+
+        if self.safe_areas
+        {
+            draw_safe_area(image, 0.9);
+            draw_safe_area(image, 0.8);
+        }
+
+        let text = expand_template(&self.template, metadata);
+
+        if !text.trim().is_empty()
+        {
+            draw_text_box(image, &text, margin, scale);
+        }
+
+        if let Some(logo) = &self.logo
+        {
+            let logo = image::open(logo)
+                .map_err(|e| format!("Could not load burn-in logo {:?}: {}", logo, e))?
+                .to_rgba8();
+
+            draw_logo(image, &logo, margin);
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces {scene}, {spp}, {samples}, {size}, {date} and {time} with
+/// details of the render - the date and time are when it was exported,
+/// in UTC
+pub fn expand_template(template: &str, metadata: &ExportMetadata) -> String
+{
+    let options = &metadata.options;
+
+    let scene = metadata.scene_file.as_ref()
+        .and_then(|f| std::path::Path::new(f).file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "untitled".to_owned());
+
+    let pixels = ((options.width as u64) * (options.height as u64)).max(1);
+    let spp = metadata.num_samples / pixels;
+
+    let (date, time) = utc_date_time(SystemTime::now());
+
+    template
+        .replace("{scene}", &scene)
+        .replace("{spp}", &spp.to_string())
+        .replace("{samples}", &metadata.num_samples.to_string())
+        .replace("{size}", &format!("{}x{}", options.width, options.height))
+        .replace("{date}", &date)
+        .replace("{time}", &time)
+}
+
+fn utc_date_time(now: SystemTime) -> (String, String)
+{
+    let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // Days since 1970 to a civil date - see
+    // http://howardhinnant.github.io/date_algorithms.html
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!("{:02}:{:02} UTC", secs_of_day / 3600, (secs_of_day / 60) % 60),
+    )
+}
+
+fn blend(image: &mut RgbaImage, x: u32, y: u32, color: [u8; 3], alpha: f32)
+{
+    if (x < image.width()) && (y < image.height())
+    {
+        let pixel = image.get_pixel_mut(x, y);
+
+        for (channel, c) in pixel.0.iter_mut().zip(color.iter())
+        {
+            *channel = ((*channel as f32) * (1.0 - alpha) + (*c as f32) * alpha).round() as u8;
+        }
+    }
+}
+
+fn fill_rect(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: [u8; 3], alpha: f32)
+{
+    for py in y..(y + height)
+    {
+        for px in x..(x + width)
+        {
+            blend(image, px, py, color, alpha);
+        }
+    }
+}
+
+/// Inverted, so they show up over both light and dark areas
+fn draw_safe_area(image: &mut RgbaImage, fraction: f32)
+{
+    let (width, height) = image.dimensions();
+    let inset_x = (((width as f32) * (1.0 - fraction)) / 2.0) as u32;
+    let inset_y = (((height as f32) * (1.0 - fraction)) / 2.0) as u32;
+    let (right, bottom) = (width - inset_x - 1, height - inset_y - 1);
+
+    let mut invert = |x: u32, y: u32|
+    {
+        let pixel = image.get_pixel_mut(x, y);
+
+        for channel in pixel.0.iter_mut().take(3)
+        {
+            *channel = 255 - *channel;
+        }
+    };
+
+    for x in inset_x..=right
+    {
+        invert(x, inset_y);
+        invert(x, bottom);
+    }
+
+    for y in (inset_y + 1)..bottom
+    {
+        invert(inset_x, y);
+        invert(right, y);
+    }
+}
+
+/// White text on a translucent box, in the bottom-left corner
+fn draw_text_box(image: &mut RgbaImage, text: &str, margin: u32, scale: u32)
+{
+    let padding = 2 * scale;
+    let (width, height) = text_size(text, scale);
+    let box_width = width + 2 * padding;
+    let box_height = height + 2 * padding;
+    let box_x = margin;
+    let box_y = image.height().saturating_sub(margin + box_height);
+
+    fill_rect(image, box_x, box_y, box_width, box_height, [0, 0, 0], 0.6);
+    draw_text(image, text, box_x + padding, box_y + padding, scale);
+}
+
+/// The size of text drawn by `draw_text`, including
+/// a pixel of spacing around each character
+pub fn text_size(text: &str, scale: u32) -> (u32, u32)
+{
+    let lines = text.lines().count() as u32;
+    let longest = text.lines().map(|l| l.chars().count() as u32).max().unwrap_or(0);
+
+    (longest * (GLYPH_WIDTH + 1) * scale, lines * (GLYPH_HEIGHT + 2) * scale)
+}
+
+/// White text, with the top-left corner at the given position.
+/// Each pixel of the font is drawn as a square of the scale.
+pub fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32, scale: u32)
+{
+    let (advance, line_height) = ((GLYPH_WIDTH + 1) * scale, (GLYPH_HEIGHT + 2) * scale);
+
+    for (row, line) in text.lines().enumerate()
+    {
+        let y = y + (row as u32) * line_height + scale;
+
+        for (col, ch) in line.chars().enumerate()
+        {
+            let x = x + (col as u32) * advance;
+
+            for (gy, bits) in glyph(ch).iter().enumerate()
+            {
+                for gx in 0..GLYPH_WIDTH
+                {
+                    if (bits >> (GLYPH_WIDTH - 1 - gx)) & 1 != 0
+                    {
+                        fill_rect(image, x + gx * scale, y + (gy as u32) * scale, scale, scale, [255, 255, 255], 1.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// In the bottom-right corner, shrunk to at
+/// most an eighth of the image's height
+fn draw_logo(image: &mut RgbaImage, logo: &RgbaImage, margin: u32)
+{
+    let max_height = (image.height() / 8).max(1);
+
+    let logo = if logo.height() > max_height
+    {
+        let width = (((logo.width() as u64) * (max_height as u64)) / (logo.height() as u64)).max(1) as u32;
+        image::imageops::resize(logo, width, max_height, image::imageops::FilterType::Triangle)
+    }
+    else
+    {
+        logo.clone()
+    };
+
+    let x = image.width().saturating_sub(margin + logo.width());
+    let y = image.height().saturating_sub(margin + logo.height());
+
+    for (lx, ly, Rgba([r, g, b, a])) in logo.enumerate_pixels()
+    {
+        blend(image, x + lx, y + ly, [*r, *g, *b], (*a as f32) / 255.0);
+    }
+}
+
+/// Rows of a 5x7 bitmap font, with the leftmost pixel in the
+/// highest bit. Lower case is drawn as upper case, and anything
+/// else that's missing as a question mark.
+fn glyph(ch: char) -> [u8; 7]
+{
+    let ch = ch.to_ascii_uppercase();
+
+    GLYPHS.iter()
+        .find(|(c, _)| *c == ch)
+        .map(|(_, rows)| *rows)
+        .unwrap_or([0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04])
+}
+
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('\'', [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+];
@@ -0,0 +1,52 @@
+use image::{Rgba, RgbaImage};
+
+use crate::desc::SceneDescription;
+use crate::desc::edit::{ContactSheet, Scene};
+use crate::export::burnin::{draw_text, text_size};
+use crate::export::render_to_completion;
+use crate::render::RenderOptions;
+
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+
+/// Renders each entry of the contact sheet at the options'
+/// size, and arranges them in a grid, labeled underneath
+pub fn render_contact_sheet(scene: &Scene, sheet: &ContactSheet, options: &RenderOptions) -> Result<RgbaImage, String>
+{
+    let entries = sheet.entries(scene)?;
+    let (columns, rows) = sheet.grid_size(entries.len());
+
+    let (width, height) = (options.width, options.height);
+    let scale = (height / 240).max(1);
+    let gap = 4 * scale;
+    let label_height = text_size("X", scale).1 + 2 * scale;
+
+    let cell_width = width + gap;
+    let cell_height = height + label_height + gap;
+
+    let mut result = RgbaImage::from_pixel(
+        (columns as u32) * cell_width + gap,
+        (rows as u32) * cell_height + gap,
+        BACKGROUND);
+
+    for (i, (label, scene)) in entries.iter().enumerate()
+    {
+        println!("Contact sheet: rendering {} ({} of {})", label, i + 1, entries.len());
+
+        let (image, _) = render_to_completion(SceneDescription::new_edit(scene), options)?;
+        let thumbnail = image.to_rgba8(options);
+
+        let x = gap + ((i % columns) as u32) * cell_width;
+        let y = gap + ((i / columns) as u32) * cell_height;
+
+        image::imageops::replace(&mut result, &thumbnail, x as i64, y as i64);
+
+        // Long labels are cut off at the edge of the thumbnail
+
+        let max_chars = (width / text_size("X", scale).0.max(1)) as usize;
+        let label = label.chars().take(max_chars).collect::<String>();
+
+        draw_text(&mut result, &label, x, y + height + scale, scale);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::desc::edit::Scene;
+use crate::export::mesh::{export_material, export_meshes, ExportMaterial};
+use crate::export::{base64, json_string};
+use crate::indexed::MaterialIndex;
+use crate::math::Scalar;
+
+/// Writes the scene's meshes as a glTF 2.0 file, with the
+/// buffers embedded. Each object becomes a node - either with
+/// its transform kept as the node's matrix, or baked into the
+/// vertices. Materials are reduced to the metallic-roughness
+/// parameters, as textures aren't exported.
+pub fn export_gltf(path: &Path, scene: &Scene, bake_transforms: bool) -> Result<(), String>
+{
+    let gltf = to_gltf(scene, bake_transforms)?;
+
+    std::fs::write(path, gltf)
+        .map_err(|e| format!("Could not save glTF file {:?}: {}", path, e))
+}
+
+/// Returns the contents of the glTF file
+pub fn to_gltf(scene: &Scene, bake_transforms: bool) -> Result<String, String>
+{
+    let (meshes, _skipped) = export_meshes(scene);
+
+    if meshes.is_empty()
+    {
+        return Err("The scene has no meshes to export".to_owned());
+    }
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut gltf_meshes = Vec::new();
+    let mut nodes = Vec::new();
+    let mut materials = Vec::new();
+    let mut material_indexes: HashMap<MaterialIndex, usize> = HashMap::new();
+
+    // Each attribute is a separate buffer view
+
+    let mut push_attribute = |buffer: &mut Vec<u8>, values: Vec<[f32; 4]>, components: usize, with_bounds: bool| -> usize
+    {
+        let offset = buffer.len();
+
+        for value in values.iter()
+        {
+            for component in value.iter().take(components)
+            {
+                buffer.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+
+        buffer_views.push(format!("{{ \"buffer\": 0, \"byteOffset\": {}, \"byteLength\": {}, \"target\": 34962 }}", offset, buffer.len() - offset));
+
+        let gltf_type = match components
+        {
+            2 => "VEC2",
+            3 => "VEC3",
+            _ => "VEC4",
+        };
+
+        let bounds = if with_bounds
+        {
+            let min = (0..components).map(|c| values.iter().map(|v| v[c]).fold(f32::MAX, f32::min).to_string()).collect::<Vec<_>>();
+            let max = (0..components).map(|c| values.iter().map(|v| v[c]).fold(f32::MIN, f32::max).to_string()).collect::<Vec<_>>();
+
+            format!(", \"min\": [{}], \"max\": [{}]", min.join(", "), max.join(", "))
+        }
+        else
+        {
+            String::new()
+        };
+
+        accessors.push(format!("{{ \"bufferView\": {}, \"componentType\": 5126, \"count\": {}, \"type\": \"{}\"{} }}", buffer_views.len() - 1, values.len(), gltf_type, bounds));
+        accessors.len() - 1
+    };
+
+    for mesh in meshes.iter()
+    {
+        let material = *material_indexes.entry(mesh.material).or_insert_with(||
+        {
+            materials.push(material_json(&export_material(&scene.collection, mesh.material)));
+            materials.len() - 1
+        });
+
+        let triangles = if bake_transforms { mesh.world_triangles() } else { mesh.triangles.clone() };
+        let vertices = triangles.iter().flat_map(|t| t.vertices.iter()).collect::<Vec<_>>();
+
+        let positions = vertices.iter().map(|v| [v.location.x as f32, v.location.y as f32, v.location.z as f32, 0.0]).collect();
+        let texture_coords = vertices.iter().map(|v| [v.texture_coords.x as f32, v.texture_coords.y as f32, 0.0, 0.0]).collect();
+
+        let mut attributes = vec![
+            format!("\"POSITION\": {}", push_attribute(&mut buffer, positions, 3, true)),
+            format!("\"TEXCOORD_0\": {}", push_attribute(&mut buffer, texture_coords, 2, false)),
+        ];
+
+        if vertices.iter().all(|v| v.opt_color.is_some())
+        {
+            let colors = vertices.iter()
+                .map(|v|
+                {
+                    let c = v.opt_color.unwrap().into_linear();
+                    [c.r as f32, c.g as f32, c.b as f32, c.a as f32]
+                })
+                .collect();
+
+            attributes.push(format!("\"COLOR_0\": {}", push_attribute(&mut buffer, colors, 4, false)));
+        }
+
+        if vertices.iter().all(|v| v.opt_normal.is_some())
+        {
+            let normals = vertices.iter()
+                .map(|v|
+                {
+                    let n = v.opt_normal.unwrap();
+                    [n.x as f32, n.y as f32, n.z as f32, 0.0]
+                })
+                .collect();
+
+            attributes.push(format!("\"NORMAL\": {}", push_attribute(&mut buffer, normals, 3, false)));
+        }
+
+        gltf_meshes.push(format!("{{ \"name\": {}, \"primitives\": [ {{ \"attributes\": {{ {} }}, \"material\": {} }} ] }}",
+            json_string(&mesh.name), attributes.join(", "), material));
+
+        // glTF matrices are column-major
+
+        let matrix = if bake_transforms
+        {
+            String::new()
+        }
+        else
+        {
+            let elements = mesh.matrix.into_col_array().iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            format!(", \"matrix\": [{}]", elements.join(", "))
+        };
+
+        nodes.push(format!("{{ \"name\": {}, \"mesh\": {}{} }}", json_string(&mesh.name), gltf_meshes.len() - 1, matrix));
+    }
+
+    let mut result = String::new();
+    result.push_str("{\n");
+    result.push_str("    \"asset\": { \"version\": \"2.0\", \"generator\": \"beam\" },\n");
+    result.push_str("    \"extensionsUsed\": [ \"KHR_materials_emissive_strength\", \"KHR_materials_ior\", \"KHR_materials_transmission\" ],\n");
+    result.push_str("    \"scene\": 0,\n");
+    result.push_str(&format!("    \"scenes\": [ {{ \"nodes\": [{}] }} ],\n", (0..nodes.len()).map(|n| n.to_string()).collect::<Vec<_>>().join(", ")));
+    result.push_str(&format!("    \"nodes\": [\n        {}\n    ],\n", nodes.join(",\n        ")));
+    result.push_str(&format!("    \"meshes\": [\n        {}\n    ],\n", gltf_meshes.join(",\n        ")));
+    result.push_str(&format!("    \"materials\": [\n        {}\n    ],\n", materials.join(",\n        ")));
+    result.push_str(&format!("    \"accessors\": [\n        {}\n    ],\n", accessors.join(",\n        ")));
+    result.push_str(&format!("    \"bufferViews\": [\n        {}\n    ],\n", buffer_views.join(",\n        ")));
+    result.push_str(&format!("    \"buffers\": [ {{ \"byteLength\": {}, \"uri\": \"data:application/octet-stream;base64,{}\" }} ]\n", buffer.len(), base64(&buffer)));
+    result.push_str("}\n");
+
+    Ok(result)
+}
+
+fn material_json(material: &ExportMaterial) -> String
+{
+    let color = &material.color;
+    let mut result = format!("{{ \"name\": {}, \"pbrMetallicRoughness\": {{ \"baseColorFactor\": [{}, {}, {}, 1.0], \"metallicFactor\": {}, \"roughnessFactor\": {} }}",
+        json_string(&material.name), color.r, color.g, color.b, material.metallic, material.roughness);
+
+    let mut extensions = Vec::new();
+
+    // The emissive factor is limited to one, with
+    // an extension to scale it beyond that
+
+    let emission = &material.emission;
+    let strength = emission.r.max(emission.g).max(emission.b);
+
+    if strength > 0.0
+    {
+        let factor = |c: Scalar| if strength > 1.0 { c / strength } else { c };
+
+        result.push_str(&format!(", \"emissiveFactor\": [{}, {}, {}]", factor(emission.r), factor(emission.g), factor(emission.b)));
+
+        if strength > 1.0
+        {
+            extensions.push(format!("\"KHR_materials_emissive_strength\": {{ \"emissiveStrength\": {} }}", strength));
+        }
+    }
+
+    if let Some(ior) = material.ior
+    {
+        extensions.push("\"KHR_materials_transmission\": { \"transmissionFactor\": 1.0 }".to_owned());
+        extensions.push(format!("\"KHR_materials_ior\": {{ \"ior\": {} }}", ior));
+    }
+
+    if !extensions.is_empty()
+    {
+        result.push_str(&format!(", \"extensions\": {{ {} }}", extensions.join(", ")));
+    }
+
+    result.push_str(" }");
+    result
+}
@@ -0,0 +1,248 @@
+use crate::color::LinearRGB;
+use crate::desc::edit::{Geom, Material, Scene, Texture, Triangle, TriangleVertex, sdf_triangles, transform_triangles};
+use crate::indexed::{Index, IndexedCollection, MaterialIndex, TextureIndex};
+use crate::math::{Scalar, ScalarConsts};
+use crate::vec::{Dir3, Mat4, Point3, Vec3};
+
+const SPHERE_SEGMENTS: usize = 32;
+const SPHERE_RINGS: usize = 16;
+const DISC_SEGMENTS: usize = 32;
+
+/// The triangles of an object, in the space of its geometry,
+/// along with the matrix that places them in the world
+pub struct ExportMesh
+{
+    pub name: String,
+    pub material: MaterialIndex,
+    pub triangles: Vec<Triangle>,
+    pub matrix: Mat4,
+}
+
+impl ExportMesh
+{
+    pub fn world_triangles(&self) -> Vec<Triangle>
+    {
+        transform_triangles(&self.triangles, &self.matrix)
+    }
+}
+
+/// A material approximated by the parameters that
+/// the common interchange formats support
+pub struct ExportMaterial
+{
+    pub name: String,
+    pub color: LinearRGB,
+    pub metallic: Scalar,
+    pub roughness: Scalar,
+    pub emission: LinearRGB,
+    pub ior: Option<Scalar>,
+}
+
+/// Grid cells along the longest side of a signed
+/// distance field's bounds, when it's tessellated
+pub const SDF_RESOLUTION: usize = 128;
+
+/// Every placed object that has a finite surface. Planes and
+/// unbounded signed distance fields have no triangles to export,
+/// and volumes aren't surfaces, so these are skipped, and their
+/// names are returned.
+pub fn export_meshes(scene: &Scene) -> (Vec<ExportMesh>, Vec<String>)
+{
+    let collection = &scene.collection;
+    let mut meshes = Vec::new();
+    let mut skipped = Vec::new();
+
+    for (object_index, placement) in scene.placed_objects()
+    {
+        let object = collection.map_item(object_index, |object, _| object.clone());
+        let geom = collection.map_item(object.geom, |geom, _| geom.clone());
+        let name = collection.name_of(object_index)
+            .or_else(|| collection.name_of(object.geom))
+            .unwrap_or_else(|| format!("Object {}", object_index.to_usize()));
+
+        let (triangles, matrix) = match &geom
+        {
+            Geom::Sphere{center, radius} => (sphere(*center, *radius), placement),
+            Geom::Rectangle{point, u, v} => (rectangle(*point, *u, *v), placement),
+            Geom::Disc{center, normal, radius} => (disc(*center, *normal, *radius), placement),
+            Geom::Box{aabb} => (cuboid(aabb.min, aabb.max), placement),
+            Geom::Triangle{triangle} => (vec![triangle.as_ref().clone()], placement),
+            Geom::Mesh{triangles, transform, ..} => (triangles.clone(), placement * transform.build_matrix(collection)),
+            Geom::Sdf{sdf, transform} if sdf.is_bounded() => (sdf_triangles(sdf, SDF_RESOLUTION), placement * transform.build_matrix(collection)),
+            Geom::Plane{..} | Geom::Ground{..} | Geom::Volume{..} | Geom::Sdf{..} =>
+            {
+                skipped.push(name);
+                continue;
+            },
+        };
+
+        meshes.push(ExportMesh { name, material: object.material, triangles, matrix });
+    }
+
+    (meshes, skipped)
+}
+
+pub fn export_material(collection: &IndexedCollection, index: MaterialIndex) -> ExportMaterial
+{
+    let name = collection.name_of(index).unwrap_or_else(|| format!("material{}", index.to_usize()));
+
+    let mut result = ExportMaterial
+    {
+        name,
+        color: LinearRGB::grey(0.5),
+        metallic: 0.0,
+        roughness: 1.0,
+        emission: LinearRGB::black(),
+        ior: None,
+    };
+
+    match collection.map_item(index, |material, _| material.clone())
+    {
+        Material::Diffuse{texture, ..} =>
+        {
+            result.color = texture_color(collection, texture);
+        },
+        Material::Metal{texture, fuzz, ..} =>
+        {
+            // The importers use fuzz = roughness squared
+
+            result.color = texture_color(collection, texture);
+            result.metallic = 1.0;
+            result.roughness = fuzz.max(0.0).sqrt().min(1.0);
+        },
+        Material::Pbr{texture, metallic, roughness, ..} =>
+        {
+            result.color = texture_color(collection, texture);
+            result.metallic = metallic;
+            result.roughness = roughness;
+        },
+        Material::Dielectric{ior, tint} =>
+        {
+            result.color = tint.map(|tint| texture_color(collection, tint)).unwrap_or_else(LinearRGB::white);
+            result.roughness = 0.0;
+            result.ior = Some(ior);
+        },
+        Material::Emit{texture} =>
+        {
+            result.color = LinearRGB::black();
+            result.emission = texture_color(collection, texture);
+        },
+        Material::Graph{..} | Material::Medium{..} => {},
+    }
+
+    result
+}
+
+/// A single color for a texture - image
+/// and expression textures aren't exported
+fn texture_color(collection: &IndexedCollection, texture: TextureIndex) -> LinearRGB
+{
+    match collection.map_item(texture, |texture, _| texture.clone())
+    {
+        Texture::Solid(color) => color.into_linear(),
+        Texture::Checkerboard(c1, c2) => (c1.into_linear() + c2.into_linear()).multiplied_by_scalar(0.5),
+        Texture::Image{base_color, ..} => base_color.into_linear(),
+        Texture::Expression(_) => LinearRGB::grey(0.5),
+    }
+}
+
+fn vertex(location: Point3, u: Scalar, v: Scalar) -> TriangleVertex
+{
+    TriangleVertex { location, texture_coords: Point3::new(u, v, 0.0), opt_color: None, opt_normal: None }
+}
+
+fn sphere(center: Point3, radius: Scalar) -> Vec<Triangle>
+{
+    let point = |segment: usize, ring: usize|
+    {
+        let u = (segment as Scalar) / (SPHERE_SEGMENTS as Scalar);
+        let v = (ring as Scalar) / (SPHERE_RINGS as Scalar);
+        let phi = 2.0 * ScalarConsts::PI * u;
+        let theta = ScalarConsts::PI * v;
+
+        let location = center + radius * Point3::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin());
+
+        vertex(location, u, v)
+    };
+
+    let mut result = Vec::new();
+
+    for ring in 0..SPHERE_RINGS
+    {
+        for segment in 0..SPHERE_SEGMENTS
+        {
+            let (a, b, c, d) = (point(segment, ring), point(segment + 1, ring), point(segment + 1, ring + 1), point(segment, ring + 1));
+
+            if ring != 0
+            {
+                result.push(Triangle { vertices: [a.clone(), d.clone(), b.clone()] });
+            }
+
+            if ring != (SPHERE_RINGS - 1)
+            {
+                result.push(Triangle { vertices: [b, d, c] });
+            }
+        }
+    }
+
+    result
+}
+
+pub fn rectangle(point: Point3, u: Vec3, v: Vec3) -> Vec<Triangle>
+{
+    let v = [vertex(point, 0.0, 0.0), vertex(point + u, 1.0, 0.0), vertex(point + u + v, 1.0, 1.0), vertex(point + v, 0.0, 1.0)];
+
+    vec![
+        Triangle { vertices: [v[0].clone(), v[1].clone(), v[2].clone()] },
+        Triangle { vertices: [v[0].clone(), v[2].clone(), v[3].clone()] },
+    ]
+}
+
+/// A fan of triangles, counter-clockwise when viewed from
+/// the side the normal faces, textured by angle and radius
+pub fn disc(center: Point3, normal: Dir3, radius: Scalar) -> Vec<Triangle>
+{
+    let normal = normal.normalized();
+    let helper = if normal.x.abs() > 0.9 { Dir3::new(0.0, 1.0, 0.0) } else { Dir3::new(1.0, 0.0, 0.0) };
+    let u = helper.cross(normal).normalized();
+    let v = normal.cross(u);
+
+    let point = |segment: usize|
+    {
+        let angle = 2.0 * ScalarConsts::PI * (segment as Scalar) / (DISC_SEGMENTS as Scalar);
+
+        vertex(center + radius * (angle.cos() * u + angle.sin() * v), (segment as Scalar) / (DISC_SEGMENTS as Scalar), 1.0)
+    };
+
+    (0..DISC_SEGMENTS)
+        .map(|segment| Triangle { vertices: [vertex(center, 0.0, 0.0), point(segment), point(segment + 1)] })
+        .collect()
+}
+
+fn cuboid(min: Point3, max: Point3) -> Vec<Triangle>
+{
+    let corner = |x: bool, y: bool, z: bool| Point3::new(if x { max.x } else { min.x }, if y { max.y } else { min.y }, if z { max.z } else { min.z });
+
+    // Counter-clockwise when viewed from outside
+
+    let faces = [
+        [corner(false, false, false), corner(false, false, true), corner(false, true, true), corner(false, true, false)],
+        [corner(true, false, false), corner(true, true, false), corner(true, true, true), corner(true, false, true)],
+        [corner(false, false, false), corner(true, false, false), corner(true, false, true), corner(false, false, true)],
+        [corner(false, true, false), corner(false, true, true), corner(true, true, true), corner(true, true, false)],
+        [corner(false, false, false), corner(false, true, false), corner(true, true, false), corner(true, false, false)],
+        [corner(false, false, true), corner(true, false, true), corner(true, true, true), corner(false, true, true)],
+    ];
+
+    faces.iter()
+        .flat_map(|f|
+        {
+            let v = [vertex(f[0], 0.0, 0.0), vertex(f[1], 1.0, 0.0), vertex(f[2], 1.0, 1.0), vertex(f[3], 0.0, 1.0)];
+
+            [
+                Triangle { vertices: [v[0].clone(), v[1].clone(), v[2].clone()] },
+                Triangle { vertices: [v[0].clone(), v[2].clone(), v[3].clone()] },
+            ]
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+mod burnin;
+mod contact;
+pub mod gltf;
+mod mesh;
+pub mod obj;
+mod output;
+pub mod pbrt;
+
+use std::path::Path;
+use std::time::Duration;
+
+use image::RgbaImage;
+
+use crate::desc::edit::Camera;
+use crate::render::RenderOptions;
+
+pub use burnin::{expand_template, BurnIn};
+pub use contact::render_contact_sheet;
+pub use output::{export_render, render_to_completion, HeadlessRender, RenderImage};
+
+const THUMBNAIL_SIZE: u32 = 128;
+
+pub struct ExportMetadata
+{
+    pub scene_file: Option<String>,
+    pub camera: Camera,
+    pub options: RenderOptions,
+    pub num_samples: u64,
+    pub duration: Duration,
+    /// Only drawn over 8-bit exports
+    pub burn_in: Option<BurnIn>,
+}
+
+/// Writes the image to the given path, along with a JSON sidecar
+/// (same name, ".meta.json" extension) describing how the render was
+/// produced, so that renders remain reproducible and traceable. The
+/// extension is distinct so the sidecar can't replace a scene file.
+pub fn export_image(path: &Path, image: &RgbaImage, metadata: &ExportMetadata) -> Result<(), String>
+{
+    image.save(path)
+        .map_err(|e| format!("Could not save image {:?}: {}", path, e))?;
+
+    write_sidecar(path, image, metadata)
+}
+
+fn write_sidecar(path: &Path, image: &RgbaImage, metadata: &ExportMetadata) -> Result<(), String>
+{
+    let sidecar_path = path.with_extension("meta.json");
+    let sidecar = build_sidecar(image, metadata)?;
+
+    std::fs::write(&sidecar_path, sidecar)
+        .map_err(|e| format!("Could not save sidecar {:?}: {}", sidecar_path, e))
+}
+
+/// Progress of a render, for external monitoring
+pub struct RenderStatus
+{
+    pub actions: String,
+    pub complete: bool,
+    pub percent: f64,
+    pub samples_per_pixel: f64,
+    pub num_samples: u64,
+    pub num_rays: u64,
+    pub elapsed: Duration,
+    pub eta: Option<Duration>,
+}
+
+/// Writes the status as JSON. It's written to a temporary file
+/// and then renamed, so readers never see a partial file.
+pub fn write_status_file(path: &Path, status: &RenderStatus) -> Result<(), String>
+{
+    let elapsed = status.elapsed.as_secs_f64();
+
+    let rays_per_sec = if elapsed > 0.0 { (status.num_rays as f64) / elapsed } else { 0.0 };
+
+    let eta = match &status.eta
+    {
+        Some(eta) => format!("{:.1}", eta.as_secs_f64()),
+        None => "null".to_owned(),
+    };
+
+    let mut result = String::new();
+    result.push_str("{\n");
+    result.push_str(&format!("    \"actions\": {},\n", json_string(&status.actions)));
+    result.push_str(&format!("    \"complete\": {},\n", status.complete));
+    result.push_str(&format!("    \"percent\": {:.2},\n", status.percent));
+    result.push_str(&format!("    \"samples_per_pixel\": {:.2},\n", status.samples_per_pixel));
+    result.push_str(&format!("    \"num_samples\": {},\n", status.num_samples));
+    result.push_str(&format!("    \"num_rays\": {},\n", status.num_rays));
+    result.push_str(&format!("    \"rays_per_sec\": {:.0},\n", rays_per_sec));
+    result.push_str(&format!("    \"elapsed_secs\": {:.1},\n", elapsed));
+    result.push_str(&format!("    \"eta_secs\": {}\n", eta));
+    result.push_str("}\n");
+
+    let temp_path = path.with_extension("tmp");
+
+    std::fs::write(&temp_path, result)
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .map_err(|e| format!("Could not write status {:?}: {}", path, e))
+}
+
+fn build_sidecar(image: &RgbaImage, metadata: &ExportMetadata) -> Result<String, String>
+{
+    let camera = &metadata.camera;
+    let options = &metadata.options;
+
+    let scene_file = match &metadata.scene_file
+    {
+        Some(file) => json_string(file),
+        None => "null".to_owned(),
+    };
+
+    let vec3 = |v: crate::vec::Point3| format!("[{}, {}, {}]", v.x, v.y, v.z);
+
+    let mut result = String::new();
+    result.push_str("{\n");
+    result.push_str(&format!("    \"scene_file\": {},\n", scene_file));
+    result.push_str("    \"camera\": {\n");
+    result.push_str(&format!("        \"location\": {},\n", vec3(camera.location)));
+    result.push_str(&format!("        \"look_at\": {},\n", vec3(camera.look_at)));
+    result.push_str(&format!("        \"up\": {},\n", vec3(camera.up)));
+    result.push_str(&format!("        \"fov\": {},\n", camera.fov));
+    result.push_str(&format!("        \"aperture\": {},\n", camera.aperture));
+    result.push_str(&format!("        \"focus_distance\": {}\n", camera.focus_distance));
+    result.push_str("    },\n");
+    result.push_str(&format!("    \"width\": {},\n", options.width));
+    result.push_str(&format!("    \"height\": {},\n", options.height));
+    result.push_str(&format!("    \"illumination_mode\": {},\n", json_string(&format!("{:?}", options.illumination_mode))));
+    result.push_str(&format!("    \"sampling_mode\": {},\n", json_string(&format!("{:?}", options.sampling_mode))));
+    result.push_str(&format!("    \"tile_order\": {},\n", json_string(&format!("{:?}", options.tile_order))));
+    result.push_str(&format!("    \"max_samples\": {},\n", options.max_samples));
+    result.push_str(&format!("    \"local_samples\": {},\n", options.local_samples));
+    result.push_str(&format!("    \"max_bounces\": {},\n", options.max_bounces));
+    result.push_str(&format!("    \"max_diffuse_bounces\": {},\n", options.max_diffuse_bounces));
+    result.push_str(&format!("    \"max_glossy_bounces\": {},\n", options.max_glossy_bounces));
+    result.push_str(&format!("    \"max_transmission_bounces\": {},\n", options.max_transmission_bounces));
+    result.push_str(&format!("    \"max_shadow_transparent_hits\": {},\n", options.max_shadow_transparent_hits));
+    result.push_str(&format!("    \"octree_quality\": {},\n", json_string(options.octree_quality.name())));
+    result.push_str(&format!("    \"denoise\": {},\n", options.denoise));
+    result.push_str(&format!("    \"radiance_cache\": {},\n", options.radiance_cache));
+    result.push_str(&format!("    \"path_guiding\": {},\n", options.path_guiding));
+    result.push_str(&format!("    \"direct_clamp\": {},\n", options.direct_clamp));
+    result.push_str(&format!("    \"indirect_clamp\": {},\n", options.indirect_clamp));
+    result.push_str(&format!("    \"target_noise\": {},\n", options.target_noise));
+    result.push_str(&format!("    \"time_limit\": {},\n", options.time_limit));
+    result.push_str(&format!("    \"num_samples\": {},\n", metadata.num_samples));
+    result.push_str(&format!("    \"duration_secs\": {},\n", metadata.duration.as_secs_f64()));
+    result.push_str(&format!("    \"tone_mapping\": {{ \"exposure\": {}, \"operator\": {}, \"gamma\": {} }},\n", options.tone_mapping.exposure, json_string(options.tone_mapping.operator.name()), options.tone_mapping.gamma));
+    result.push_str(&format!("    \"display_transform\": {},\n", json_string(&options.display_transform.name())));
+    result.push_str(&format!("    \"burn_in\": {},\n", metadata.burn_in.is_some()));
+    result.push_str(&format!("    \"grades\": [{}],\n", options.grades.iter().map(|g| format!("{{ \"lut\": {}, \"strength\": {} }}", json_string(&g.lut.title), g.strength)).collect::<Vec<_>>().join(", ")));
+    result.push_str(&format!("    \"film\": {},\n", options.film.as_ref().map(|f| format!("{{ \"response\": {}, \"grain_size\": {}, \"grain_intensity\": {} }}", json_string(&f.name()), f.grain_size, f.grain_intensity)).unwrap_or_else(|| "null".to_owned())));
+    result.push_str(&format!("    \"version\": {},\n", json_string(env!("BEAM_GIT_DESCRIBE"))));
+    result.push_str(&format!("    \"thumbnail_png_base64\": {}\n", json_string(&build_thumbnail(image)?)));
+    result.push_str("}\n");
+
+    Ok(result)
+}
+
+fn build_thumbnail(image: &RgbaImage) -> Result<String, String>
+{
+    let (width, height) = image.dimensions();
+    let scale = (THUMBNAIL_SIZE as f64) / (width.max(height).max(1) as f64);
+
+    let thumbnail = if scale < 1.0
+    {
+        let thumb_width = ((width as f64) * scale).round().max(1.0) as u32;
+        let thumb_height = ((height as f64) * scale).round().max(1.0) as u32;
+        image::imageops::thumbnail(image, thumb_width, thumb_height)
+    }
+    else
+    {
+        image.clone()
+    };
+
+    let mut png = Vec::new();
+    thumbnail.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .map_err(|e| format!("Could not encode thumbnail: {}", e))?;
+
+    Ok(base64(&png))
+}
+
+pub fn json_string(s: &str) -> String
+{
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+
+    for c in s.chars()
+    {
+        match c
+        {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+fn base64(data: &[u8]) -> String
+{
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3)
+    {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        result.push(ALPHABET[((triple >> 18) & 0x3F) as usize] as char);
+        result.push(ALPHABET[((triple >> 12) & 0x3F) as usize] as char);
+        result.push(if chunk.len() > 1 { ALPHABET[((triple >> 6) & 0x3F) as usize] as char } else { '=' });
+        result.push(if chunk.len() > 2 { ALPHABET[(triple & 0x3F) as usize] as char } else { '=' });
+    }
+
+    result
+}
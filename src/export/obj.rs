@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::desc::edit::Scene;
+use crate::export::mesh::{export_material, export_meshes};
+use crate::indexed::MaterialIndex;
+
+/// Writes the scene's meshes as a Wavefront OBJ file, with
+/// the transforms applied, and a material library alongside
+/// it (same name, ".mtl" extension). Materials are reduced
+/// to a single color, as textures aren't exported.
+pub fn export_obj(path: &Path, scene: &Scene) -> Result<(), String>
+{
+    let mtl_path = path.with_extension("mtl");
+    let mtl_filename = mtl_path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "scene.mtl".to_owned());
+
+    let (obj, mtl) = to_obj(scene, &mtl_filename)?;
+
+    std::fs::write(path, obj)
+        .map_err(|e| format!("Could not save OBJ file {:?}: {}", path, e))?;
+
+    std::fs::write(&mtl_path, mtl)
+        .map_err(|e| format!("Could not save MTL file {:?}: {}", mtl_path, e))
+}
+
+/// Returns the contents of the OBJ and MTL files
+pub fn to_obj(scene: &Scene, mtl_filename: &str) -> Result<(String, String), String>
+{
+    let (meshes, skipped) = export_meshes(scene);
+
+    if meshes.is_empty()
+    {
+        return Err("The scene has no meshes to export".to_owned());
+    }
+
+    let mut obj = String::new();
+    let mut mtl = String::new();
+    let mut material_names: HashMap<MaterialIndex, String> = HashMap::new();
+    let mut num_vertices = 0;
+    let mut num_normals = 0;
+
+    obj.push_str("# Exported from beam\n");
+
+    for name in skipped
+    {
+        obj.push_str(&format!("# Skipped {} - it has no finite surface\n", name));
+    }
+
+    obj.push_str(&format!("mtllib {}\n", mtl_filename));
+
+    for mesh in meshes.iter()
+    {
+        if !material_names.contains_key(&mesh.material)
+        {
+            let material = export_material(&scene.collection, mesh.material);
+            let name = unique_name(&material.name, material_names.values());
+
+            mtl.push_str(&format!("newmtl {}\n", name));
+            mtl.push_str(&format!("Kd {} {} {}\n", material.color.r, material.color.g, material.color.b));
+            mtl.push_str(&format!("Ke {} {} {}\n", material.emission.r, material.emission.g, material.emission.b));
+
+            if material.metallic > 0.0
+            {
+                // Metals have colored reflections
+
+                mtl.push_str(&format!("Ks {} {} {}\n", material.color.r, material.color.g, material.color.b));
+                mtl.push_str(&format!("Ns {}\n", 1000.0 * (1.0 - material.roughness).powi(2)));
+                mtl.push_str("illum 3\n");
+            }
+            else if let Some(ior) = material.ior
+            {
+                mtl.push_str(&format!("Ni {}\nd 0\nillum 7\n", ior));
+            }
+            else
+            {
+                mtl.push_str("illum 1\n");
+            }
+
+            mtl.push('\n');
+            material_names.insert(mesh.material, name);
+        }
+
+        let triangles = mesh.world_triangles();
+        let with_colors = triangles.iter().all(|t| t.vertices.iter().all(|v| v.opt_color.is_some()));
+
+        obj.push_str(&format!("o {}\n", mesh.name.replace(char::is_whitespace, "_")));
+        obj.push_str(&format!("usemtl {}\n", material_names[&mesh.material]));
+
+        for vertex in triangles.iter().flat_map(|t| t.vertices.iter())
+        {
+            let l = vertex.location;
+
+            // Vertex colors are a widely supported extension
+
+            match vertex.opt_color.filter(|_| with_colors)
+            {
+                Some(color) =>
+                {
+                    let c = color.into_linear();
+                    obj.push_str(&format!("v {} {} {} {} {} {}\n", l.x, l.y, l.z, c.r, c.g, c.b));
+                },
+                None => obj.push_str(&format!("v {} {} {}\n", l.x, l.y, l.z)),
+            }
+        }
+
+        // The importer flips the v coordinate, so flip it back
+
+        for vertex in triangles.iter().flat_map(|t| t.vertices.iter())
+        {
+            obj.push_str(&format!("vt {} {}\n", vertex.texture_coords.x, 1.0 - vertex.texture_coords.y));
+        }
+
+        let with_normals = triangles.iter().all(|t| t.vertices.iter().all(|v| v.opt_normal.is_some()));
+
+        if with_normals
+        {
+            for normal in triangles.iter().flat_map(|t| t.vertices.iter()).filter_map(|v| v.opt_normal)
+            {
+                obj.push_str(&format!("vn {} {} {}\n", normal.x, normal.y, normal.z));
+            }
+        }
+
+        for i in 0..triangles.len()
+        {
+            let a = num_vertices + 3 * i + 1;
+
+            if with_normals
+            {
+                let n = num_normals + 3 * i + 1;
+                obj.push_str(&format!("f {}/{}/{} {}/{}/{} {}/{}/{}\n", a, a, n, a + 1, a + 1, n + 1, a + 2, a + 2, n + 2));
+            }
+            else
+            {
+                obj.push_str(&format!("f {}/{} {}/{} {}/{}\n", a, a, a + 1, a + 1, a + 2, a + 2));
+            }
+        }
+
+        num_vertices += 3 * triangles.len();
+
+        if with_normals
+        {
+            num_normals += 3 * triangles.len();
+        }
+    }
+
+    Ok((obj, mtl))
+}
+
+/// Material names are used as identifiers, so
+/// can't contain spaces, and must be unique
+fn unique_name<'a>(name: &str, existing: impl Iterator<Item = &'a String> + Clone) -> String
+{
+    let base = name.replace(char::is_whitespace, "_");
+    let mut result = base.clone();
+    let mut suffix = 1;
+
+    while existing.clone().any(|e| *e == result)
+    {
+        suffix += 1;
+        result = format!("{}_{}", base, suffix);
+    }
+
+    result
+}
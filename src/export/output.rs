@@ -0,0 +1,208 @@
+use std::path::Path;
+
+use image::{Rgb, Rgb32FImage, RgbaImage, Rgba};
+
+use crate::color::LinearRGB;
+use crate::desc::SceneDescription;
+use crate::export::{export_image, write_sidecar, ExportMetadata};
+use crate::geom::MeshCache;
+use crate::occlusion::AoCache;
+use crate::render::{FrameHistory, InteractionTracker, PixelUpdate, Renderer, RenderOptions, RenderProgress, RenderUpdate};
+
+/// The full precision pixels of a render, kept up to date from
+/// the renderer's updates, so it can be saved without the
+/// clamping and quantization of the displayed image.
+#[derive(Clone)]
+pub struct RenderImage
+{
+    width: u32,
+    height: u32,
+    pixels: Vec<LinearRGB>,
+}
+
+impl RenderImage
+{
+    pub fn new(width: u32, height: u32) -> Self
+    {
+        RenderImage
+        {
+            width,
+            height,
+            pixels: vec![LinearRGB::black(); (width as usize) * (height as usize)],
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32)
+    {
+        (self.width, self.height)
+    }
+
+    /// Preview passes update blocks of pixels at a time
+    pub fn update(&mut self, pixel: &PixelUpdate)
+    {
+        let rect = &pixel.rect;
+
+        for y in rect.y..(rect.y + rect.height.max(1)).min(self.height)
+        {
+            for x in rect.x..(rect.x + rect.width.max(1)).min(self.width)
+            {
+                self.pixels[(y * self.width + x) as usize] = pixel.color;
+            }
+        }
+    }
+
+    /// Converts through the options' display transform, grades and film
+    pub fn to_rgba8(&self, options: &RenderOptions) -> RgbaImage
+    {
+        RgbaImage::from_fn(self.width, self.height, |x, y|
+        {
+            let (r, g, b, _) = options.display_color(self.pixels[(y * self.width + x) as usize], x, y).to_u8_rgba_tuple();
+            Rgba([r, g, b, 255])
+        })
+    }
+
+    pub fn to_rgb32f(&self) -> Rgb32FImage
+    {
+        Rgb32FImage::from_fn(self.width, self.height, |x, y|
+        {
+            let c = self.pixels[(y * self.width + x) as usize];
+            Rgb([c.r as f32, c.g as f32, c.b as f32])
+        })
+    }
+}
+
+/// Saves the render, choosing the format from the extension. OpenEXR
+/// files keep the full (linear, unclamped) values, and anything else is
+/// saved as 8 bits per channel, through the display transform and
+/// grades of the metadata's options, with any burn-in drawn over the top.
+/// Either way a JSON sidecar is written.
+pub fn export_render(path: &Path, image: &RenderImage, metadata: &ExportMetadata) -> Result<(), String>
+{
+    let is_exr = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("exr"))
+        .unwrap_or(false);
+
+    if !is_exr
+    {
+        let mut rgba = image.to_rgba8(&metadata.options);
+
+        if let Some(burn_in) = &metadata.burn_in
+        {
+            burn_in.apply(&mut rgba, metadata)?;
+        }
+
+        return export_image(path, &rgba, metadata);
+    }
+
+    image.to_rgb32f().save(path)
+        .map_err(|e| format!("Could not save image {:?}: {}", path, e))?;
+
+    write_sidecar(path, &image.to_rgba8(&metadata.options), metadata)
+}
+
+/// Renders without a window, until the render completes
+/// (e.g. reaches the options' maximum samples)
+pub fn render_to_completion(desc: SceneDescription, options: &RenderOptions) -> Result<(RenderImage, RenderProgress), String>
+{
+    let mut render = HeadlessRender::new(desc, options);
+
+    match (render.wait(), render.progress)
+    {
+        (true, Some(progress)) => Ok((render.image, progress)),
+        _ => Err("The render did not complete".to_owned()),
+    }
+}
+
+/// A render without a window, running in the background, whose
+/// image and progress are kept up to date as it's polled - for
+/// driving renders from other programs and languages
+pub struct HeadlessRender
+{
+    renderer: Renderer,
+    image: RenderImage,
+    progress: Option<RenderProgress>,
+    complete: bool,
+}
+
+impl HeadlessRender
+{
+    pub fn new(desc: SceneDescription, options: &RenderOptions) -> Self
+    {
+        HeadlessRender
+        {
+            renderer: Renderer::new(options.clone(), desc, MeshCache::new(), AoCache::new(), InteractionTracker::new(), FrameHistory::new()),
+            image: RenderImage::new(options.width, options.height),
+            progress: None,
+            complete: false,
+        }
+    }
+
+    /// Takes the updates that are ready, without
+    /// waiting, and returns if the render is complete
+    pub fn poll(&mut self) -> bool
+    {
+        while !self.complete
+        {
+            match self.renderer.get_update()
+            {
+                Some(update) => self.apply(update),
+                None => break,
+            }
+        }
+
+        self.complete
+    }
+
+    /// Waits until the render is complete - returning
+    /// false if it stopped before completing
+    pub fn wait(&mut self) -> bool
+    {
+        while !self.complete
+        {
+            match self.renderer.wait_for_update()
+            {
+                Some(update) => self.apply(update),
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    pub fn is_complete(&self) -> bool
+    {
+        self.complete
+    }
+
+    pub fn image(&self) -> &RenderImage
+    {
+        &self.image
+    }
+
+    /// None until the scene has been built
+    pub fn progress(&self) -> Option<&RenderProgress>
+    {
+        self.progress.as_ref()
+    }
+
+    pub fn samples_per_pixel(&self) -> f64
+    {
+        let (width, height) = self.image.dimensions();
+
+        self.progress.as_ref()
+            .map(|p| (p.stats.num_samples as f64) / (((width as u64) * (height as u64)).max(1) as f64))
+            .unwrap_or(0.0)
+    }
+
+    fn apply(&mut self, update: RenderUpdate)
+    {
+        for pixel in update.pixels.iter()
+        {
+            self.image.update(pixel);
+        }
+
+        self.progress = Some(update.progress);
+        self.complete = update.complete;
+    }
+}
@@ -0,0 +1,295 @@
+use std::path::Path;
+
+use crate::color::LinearRGB;
+use crate::desc::edit::{Camera, Geom, Material, Scene, Texture, Triangle, TriangleVertex, sdf_triangles, transform_triangles};
+use crate::export::mesh::{disc, rectangle, SDF_RESOLUTION};
+use crate::indexed::{Index, IndexedCollection, MaterialIndex, TextureIndex};
+use crate::math::Scalar;
+use crate::render::RenderOptions;
+use crate::vec::{Dir3, Mat4, Point3};
+
+/// Half the size of the quad that planes are exported as,
+/// as PBRT has no infinite plane shape
+const PLANE_EXTENT: Scalar = 1.0e4;
+
+/// Writes the scene as a PBRT-v4 scene description, so it can
+/// be rendered in PBRT to validate beam's output. Materials and
+/// textures that PBRT has no equivalent for are exported as a
+/// plain color, with a comment noting what was lost.
+pub fn export_pbrt(path: &Path, scene: &Scene, camera: &Camera, options: &RenderOptions) -> Result<(), String>
+{
+    let image_filename = path.with_extension("exr")
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "render.exr".to_owned());
+
+    std::fs::write(path, to_pbrt(scene, camera, options, &image_filename))
+        .map_err(|e| format!("Could not save PBRT scene {:?}: {}", path, e))
+}
+
+pub fn to_pbrt(scene: &Scene, camera: &Camera, options: &RenderOptions, image_filename: &str) -> String
+{
+    let collection = &scene.collection;
+    let mut result = String::new();
+
+    result.push_str("# Exported from beam\n\n");
+
+    // PBRT is left-handed, so flip the image horizontally
+
+    result.push_str("Scale -1 1 1\n");
+    result.push_str(&format!("LookAt {}\n       {}\n       {}\n", point(camera.location), point(camera.look_at), point(camera.up)));
+
+    // PBRT's field of view is for the shorter image axis,
+    // while beam's is always horizontal
+
+    let aspect_ratio = (options.width as Scalar) / (options.height as Scalar);
+    let fov = if aspect_ratio > 1.0
+    {
+        2.0 * ((camera.fov.to_radians() / 2.0).tan() / aspect_ratio).atan().to_degrees()
+    }
+    else
+    {
+        camera.fov
+    };
+
+    result.push_str(&format!("Camera \"perspective\" \"float fov\" [ {} ]", fov));
+
+    if camera.aperture > 0.0
+    {
+        let focus_distance = if camera.focus_distance > 0.0 { camera.focus_distance } else { (camera.look_at - camera.location).magnitude() };
+
+        result.push_str(&format!(" \"float lensradius\" [ {} ] \"float focaldistance\" [ {} ]", camera.aperture / 2.0, focus_distance));
+    }
+
+    result.push('\n');
+    result.push_str(&format!("Film \"rgb\" \"integer xresolution\" [ {} ] \"integer yresolution\" [ {} ] \"string filename\" [ \"{}\" ]\n", options.width, options.height, image_filename));
+    result.push_str(&format!("Sampler \"zsobol\" \"integer pixelsamples\" [ {} ]\n", options.max_samples));
+    result.push_str(&format!("Integrator \"volpath\" \"integer maxdepth\" [ {} ]\n", options.max_bounces.min(1000)));
+    result.push_str("\nWorldBegin\n\n");
+
+    // Textures and materials are declared once, and referenced by name
+
+    for (index, texture) in collection.map_all_with_index(|index: TextureIndex, texture: &Texture, _| (index, texture.clone()))
+    {
+        if let Texture::Checkerboard(c1, c2) = texture
+        {
+            // Beam's checks are centered on whole texture coordinates
+
+            result.push_str(&format!("Texture \"texture{}\" \"spectrum\" \"checkerboard\" \"rgb tex1\" [ {} ] \"rgb tex2\" [ {} ] \"float udelta\" [ 0.5 ] \"float vdelta\" [ 0.5 ]\n",
+                index.to_usize(), rgb(c2.into_linear()), rgb(c1.into_linear())));
+        }
+    }
+
+    result.push('\n');
+
+    for (index, material) in collection.map_all_with_index(|index: MaterialIndex, material: &Material, _| (index, material.clone()))
+    {
+        let (params, comment) = material_params(collection, &material);
+
+        if let Some(comment) = comment
+        {
+            result.push_str(&format!("# {}\n", comment));
+        }
+
+        result.push_str(&format!("MakeNamedMaterial \"material{}\" {}\n", index.to_usize(), params));
+    }
+
+    result.push('\n');
+
+    for (object_index, matrix) in scene.placed_objects()
+    {
+        let object = collection.map_item(object_index, |object, _| object.clone());
+        let geom = collection.map_item(object.geom, |geom, _| geom.clone());
+        let material = collection.map_item(object.material, |material, _| material.clone());
+
+        result.push_str(&format!("# {}\n", collection.name_of(object_index).unwrap_or_else(|| format!("Object {}", object_index.to_usize()))));
+        result.push_str("AttributeBegin\n");
+
+        if matrix != Mat4::identity()
+        {
+            let values = matrix.into_col_array().iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+            result.push_str(&format!("    ConcatTransform [ {} ]\n", values));
+        }
+
+        if let Material::Emit{texture} = material
+        {
+            result.push_str(&format!("    AreaLightSource \"diffuse\" \"rgb L\" [ {} ]\n", rgb(solid_color(collection, texture))));
+        }
+
+        result.push_str(&format!("    NamedMaterial \"material{}\"\n", object.material.to_usize()));
+        result.push_str(&shape(collection, &geom));
+        result.push_str("AttributeEnd\n\n");
+    }
+
+    result
+}
+
+/// The parameters, and a comment if the
+/// material couldn't be exported exactly
+fn material_params(collection: &IndexedCollection, material: &Material) -> (String, Option<&'static str>)
+{
+    match material
+    {
+        Material::Dielectric{ior, ..} =>
+        {
+            (format!("\"string type\" \"dielectric\" \"float eta\" [ {} ]", ior), None)
+        },
+        Material::Diffuse{texture, ..} =>
+        {
+            let (param, comment) = texture_param(collection, "reflectance", *texture);
+
+            (format!("\"string type\" \"diffuse\" {}", param), comment)
+        },
+        Material::Emit{..} =>
+        {
+            // The emission is added as an area light on each object
+
+            ("\"string type\" \"diffuse\" \"rgb reflectance\" [ 0 0 0 ]".to_owned(), None)
+        },
+        Material::Graph{..} =>
+        {
+            ("\"string type\" \"diffuse\" \"rgb reflectance\" [ 0.5 0.5 0.5 ]".to_owned(), Some("Material graphs aren't exported - using grey diffuse"))
+        },
+        Material::Medium{..} =>
+        {
+            ("\"string type\" \"diffuse\" \"rgb reflectance\" [ 0.5 0.5 0.5 ]".to_owned(), Some("Participating media aren't exported - using grey diffuse"))
+        },
+        Material::Metal{texture, fuzz, ..} =>
+        {
+            let (param, comment) = texture_param(collection, "reflectance", *texture);
+
+            (format!("\"string type\" \"conductor\" {} \"float roughness\" [ {} ]", param, fuzz), comment)
+        },
+        Material::Pbr{texture, metallic, roughness, ..} =>
+        {
+            // PBRT has no blend of the two, so the
+            // closest of conductor or coated diffuse is used
+
+            let (param, comment) = texture_param(collection, "reflectance", *texture);
+            let roughness = roughness * roughness;
+
+            if *metallic >= 0.5
+            {
+                (format!("\"string type\" \"conductor\" {} \"float roughness\" [ {} ]", param, roughness), comment)
+            }
+            else
+            {
+                (format!("\"string type\" \"coateddiffuse\" {} \"float roughness\" [ {} ]", param, roughness), comment)
+            }
+        },
+    }
+}
+
+fn texture_param(collection: &IndexedCollection, name: &str, texture: TextureIndex) -> (String, Option<&'static str>)
+{
+    match collection.map_item(texture, |texture, _| texture.clone())
+    {
+        Texture::Solid(color) => (format!("\"rgb {}\" [ {} ]", name, rgb(color.into_linear())), None),
+        Texture::Checkerboard(..) => (format!("\"texture {}\" \"texture{}\"", name, texture.to_usize()), None),
+        Texture::Image{base_color, ..} => (format!("\"rgb {}\" [ {} ]", name, rgb(base_color.into_linear())), Some("Image textures aren't exported - using the base color")),
+        Texture::Expression(_) => (format!("\"rgb {}\" [ 0.5 0.5 0.5 ]", name), Some("Texture expressions aren't exported - using grey")),
+    }
+}
+
+/// The color of an emissive texture - area
+/// lights can only have a single color
+fn solid_color(collection: &IndexedCollection, texture: TextureIndex) -> LinearRGB
+{
+    match collection.map_item(texture, |texture, _| texture.clone())
+    {
+        Texture::Solid(color) => color.into_linear(),
+        Texture::Checkerboard(c1, c2) => (c1.into_linear() + c2.into_linear()).multiplied_by_scalar(0.5),
+        Texture::Image{base_color, ..} => base_color.into_linear(),
+        Texture::Expression(_) => LinearRGB::grey(1.0),
+    }
+}
+
+fn shape(collection: &IndexedCollection, geom: &Geom) -> String
+{
+    match geom
+    {
+        Geom::Sphere{center, radius} =>
+        {
+            format!("    Translate {}\n    Shape \"sphere\" \"float radius\" [ {} ]\n", point(*center), radius)
+        },
+        Geom::Rectangle{point, u, v} =>
+        {
+            triangle_mesh(&rectangle(*point, *u, *v))
+        },
+        Geom::Disc{center, normal, radius} =>
+        {
+            triangle_mesh(&disc(*center, *normal, *radius))
+        },
+        Geom::Plane{point, normal} | Geom::Ground{point, normal, ..} =>
+        {
+            let normal = normal.normalized();
+            let helper = if normal.x.abs() > 0.9 { Dir3::new(0.0, 1.0, 0.0) } else { Dir3::new(1.0, 0.0, 0.0) };
+            let u = normal.cross(helper).normalized() * PLANE_EXTENT;
+            let v = normal.cross(u).normalized() * PLANE_EXTENT;
+
+            triangle_mesh(&quad([*point - u - v, *point + u - v, *point + u + v, *point - u + v]))
+        },
+        Geom::Box{aabb} | Geom::Volume{aabb, ..} =>
+        {
+            let (min, max) = (aabb.min, aabb.max);
+            let corner = |x: bool, y: bool, z: bool| Point3::new(if x { max.x } else { min.x }, if y { max.y } else { min.y }, if z { max.z } else { min.z });
+
+            let faces = [
+                [corner(false, false, false), corner(false, false, true), corner(false, true, true), corner(false, true, false)],
+                [corner(true, false, false), corner(true, true, false), corner(true, true, true), corner(true, false, true)],
+                [corner(false, false, false), corner(true, false, false), corner(true, false, true), corner(false, false, true)],
+                [corner(false, true, false), corner(false, true, true), corner(true, true, true), corner(true, true, false)],
+                [corner(false, false, false), corner(false, true, false), corner(true, true, false), corner(true, false, false)],
+                [corner(false, false, true), corner(true, false, true), corner(true, true, true), corner(false, true, true)],
+            ];
+
+            triangle_mesh(&faces.iter().flat_map(|f| quad(*f)).collect::<Vec<_>>())
+        },
+        Geom::Triangle{..} | Geom::Mesh{..} =>
+        {
+            triangle_mesh(&geom.world_triangles(collection))
+        },
+        Geom::Sdf{sdf, transform} if sdf.is_bounded() =>
+        {
+            triangle_mesh(&transform_triangles(&sdf_triangles(sdf, SDF_RESOLUTION), &transform.build_matrix(collection)))
+        },
+        Geom::Sdf{..} =>
+        {
+            "    # Unbounded signed distance fields can't be exported\n".into()
+        },
+    }
+}
+
+/// The corners are (u, v) = (0, 0), (1, 0), (1, 1) and (0, 1)
+fn quad(corners: [Point3; 4]) -> Vec<Triangle>
+{
+    let uvs = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+
+    let vertex = |i: usize| TriangleVertex { location: corners[i], texture_coords: Point3::new(uvs[i].0, uvs[i].1, 0.0), opt_color: None, opt_normal: None };
+
+    vec![
+        Triangle { vertices: [vertex(0), vertex(1), vertex(2)] },
+        Triangle { vertices: [vertex(0), vertex(2), vertex(3)] },
+    ]
+}
+
+fn triangle_mesh(triangles: &[Triangle]) -> String
+{
+    let vertices = triangles.iter().flat_map(|t| t.vertices.iter());
+
+    let positions = vertices.clone().map(|v| point(v.location)).collect::<Vec<_>>().join("  ");
+    let uvs = vertices.map(|v| format!("{} {}", v.texture_coords.x, v.texture_coords.y)).collect::<Vec<_>>().join("  ");
+    let indices = (0..(3 * triangles.len())).map(|i| i.to_string()).collect::<Vec<_>>().join(" ");
+
+    format!("    Shape \"trianglemesh\"\n        \"point3 P\" [ {} ]\n        \"point2 uv\" [ {} ]\n        \"integer indices\" [ {} ]\n", positions, uvs, indices)
+}
+
+fn point(p: Point3) -> String
+{
+    format!("{} {} {}", p.x, p.y, p.z)
+}
+
+fn rgb(c: LinearRGB) -> String
+{
+    format!("{} {} {}", c.r, c.g, c.b)
+}
@@ -32,6 +32,32 @@ impl Aabb
         && (self.max.y >= other.min.y) && (other.max.y >= self.min.y)
         && (self.max.z >= other.min.z) && (other.max.z >= self.min.z)
     }
+
+    /// The part of the range where the ray is inside the box
+    pub fn clip_range(&self, ray: &Ray, range: &RayRange) -> Option<RayRange>
+    {
+        let mut tmin = range.min();
+        let mut tmax = range.max();
+
+        for axis in 0..3
+        {
+            let r_d_inv = 1.0 / ray.dir[axis];
+            let t1 = (self.min[axis] - ray.source[axis]) * r_d_inv;
+            let t2 = (self.max[axis] - ray.source[axis]) * r_d_inv;
+
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+
+        if tmin < tmax
+        {
+            Some(RayRange::new(tmin, tmax))
+        }
+        else
+        {
+            None
+        }
+    }
 }
 
 impl Surface for Aabb
@@ -99,6 +125,11 @@ impl Surface for Aabb
 
         None
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        Some(self.clone())
+    }
 }
 
 impl BoundingSurface for Aabb
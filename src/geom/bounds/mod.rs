@@ -1,4 +1,4 @@
-use crate::geom::{BoundingSurface, Surface, SurfaceIntersection};
+use crate::geom::{Aabb, BoundingSurface, Surface, SurfaceIntersection};
 use crate::ray::{Ray, RayRange};
 
 #[derive(Clone)]
@@ -29,4 +29,9 @@ impl<B: BoundingSurface + Clone + 'static, S: Surface + Clone + 'static> Surface
             None
         }
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        self.bounds.bounding_aabb().or_else(|| self.surface.bounding_aabb())
+    }
 }
@@ -0,0 +1,186 @@
+use crate::geom::Aabb;
+use crate::math::Scalar;
+use crate::ray::{Ray, RayRange};
+use crate::vec::Point3;
+
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Deep enough for any tree that fits in memory,
+/// as the tree is split at the median
+const MAX_STACK_DEPTH: usize = 64;
+
+/// A bounding volume hierarchy over a list of boxes, e.g.
+/// the objects in a scene. It only stores the indexes of
+/// the items - the caller intersects them, so it works
+/// with any type of item.
+#[derive(Clone)]
+pub struct Bvh
+{
+    nodes: Vec<BvhNode>,
+    indexes: Vec<usize>,
+}
+
+#[derive(Clone)]
+struct BvhNode
+{
+    bounds: Aabb,
+    contents: BvhContents,
+}
+
+#[derive(Clone)]
+enum BvhContents
+{
+    /// A range of the indexes
+    Leaf{ start: usize, end: usize },
+    /// Positions of the two child nodes
+    Split{ left: usize, right: usize },
+}
+
+impl Bvh
+{
+    /// Items are given as their index and bounds
+    pub fn new(mut items: Vec<(usize, Aabb)>) -> Self
+    {
+        let mut result = Bvh { nodes: Vec::new(), indexes: Vec::with_capacity(items.len()) };
+
+        if !items.is_empty()
+        {
+            result.build(&mut items);
+        }
+
+        result
+    }
+
+    fn build(&mut self, items: &mut [(usize, Aabb)]) -> usize
+    {
+        let bounds = items.iter().skip(1).fold(items[0].1.clone(), |bounds, (_, b)| bounds.union(b));
+
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { bounds, contents: BvhContents::Leaf{ start: 0, end: 0 } });
+
+        if items.len() <= MAX_LEAF_SIZE
+        {
+            let start = self.indexes.len();
+            self.indexes.extend(items.iter().map(|(i, _)| *i));
+            self.nodes[node].contents = BvhContents::Leaf{ start, end: self.indexes.len() };
+            return node;
+        }
+
+        // Split at the median center, along the
+        // axis where the centers are most spread out
+
+        let (min, max) = items.iter()
+            .map(|(_, b)| center(b))
+            .fold((Point3::broadcast(Scalar::MAX), Point3::broadcast(Scalar::MIN)), |(min, max), c| (Point3::partial_min(min, c), Point3::partial_max(max, c)));
+
+        let extent = max - min;
+        let axis = if (extent.x >= extent.y) && (extent.x >= extent.z) { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |(_, a), (_, b)| center(a)[axis].total_cmp(&center(b)[axis]));
+
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let left = self.build(left_items);
+        let right = self.build(right_items);
+
+        self.nodes[node].contents = BvhContents::Split{ left, right };
+        node
+    }
+
+    /// Visits the items whose boxes the ray passes through, nearest
+    /// boxes first. The visitor returns the distance of any hit,
+    /// which shortens the range, so boxes beyond it are skipped.
+    pub fn closest_in_range<F>(&self, ray: &Ray, range: &RayRange, mut visit: F)
+        where F: FnMut(usize, &RayRange) -> Option<Scalar>
+    {
+        let mut range = range.clone();
+
+        let entry = match self.nodes.first().and_then(|root| entry_distance(&root.bounds, ray, &range))
+        {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let mut stack = [(0, 0.0); MAX_STACK_DEPTH];
+        stack[0] = (0, entry);
+        let mut depth = 1;
+
+        while depth > 0
+        {
+            depth -= 1;
+            let (node, entry) = stack[depth];
+
+            if entry > range.max()
+            {
+                continue;
+            }
+
+            match &self.nodes[node].contents
+            {
+                BvhContents::Leaf{ start, end } =>
+                {
+                    for index in self.indexes[*start..*end].iter()
+                    {
+                        if let Some(distance) = visit(*index, &range)
+                        {
+                            range.set_max(distance);
+                        }
+                    }
+                },
+                BvhContents::Split{ left, right } =>
+                {
+                    let left = entry_distance(&self.nodes[*left].bounds, ray, &range).map(|e| (*left, e));
+                    let right = entry_distance(&self.nodes[*right].bounds, ray, &range).map(|e| (*right, e));
+
+                    // Push the further child first, so
+                    // the nearer one is visited first
+
+                    let (near, far) = match (left, right)
+                    {
+                        (Some(l), Some(r)) if r.1 < l.1 => (Some(r), Some(l)),
+                        (l, r) => (l, r),
+                    };
+
+                    for child in [far, near].iter().flatten()
+                    {
+                        stack[depth] = *child;
+                        depth += 1;
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn center(bounds: &Aabb) -> Point3
+{
+    0.5 * (bounds.min + bounds.max)
+}
+
+/// Where the ray enters the box, within the range. Touching
+/// counts as a hit, so flat boxes (e.g. around a single
+/// rectangle) aren't missed.
+fn entry_distance(bounds: &Aabb, ray: &Ray, range: &RayRange) -> Option<Scalar>
+{
+    let mut tmin = range.min();
+    let mut tmax = range.max();
+
+    for axis in 0..3
+    {
+        let r_d_inv = 1.0 / ray.dir[axis];
+        let t1 = (bounds.min[axis] - ray.source[axis]) * r_d_inv;
+        let t2 = (bounds.max[axis] - ray.source[axis]) * r_d_inv;
+
+        tmin = tmin.max(t1.min(t2));
+        tmax = tmax.min(t1.max(t2));
+    }
+
+    if tmax >= tmin
+    {
+        Some(tmin)
+    }
+    else
+    {
+        None
+    }
+}
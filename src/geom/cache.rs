@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::geom::{Mesh, OctreeQuality, Triangle};
+
+/// Acceleration structures for meshes, keyed by a hash of their
+/// (world space) triangles and the octree quality. It's owned by the application rather
+/// than the scene, so that re-rendering after changing only the
+/// materials or camera can reuse them rather than rebuilding.
+#[derive(Clone, Default)]
+pub struct MeshCache
+{
+    inner: Arc<Mutex<MeshCacheInner>>,
+}
+
+#[derive(Default)]
+struct MeshCacheInner
+{
+    meshes: HashMap<u64, Mesh>,
+    used: HashSet<u64>,
+}
+
+impl MeshCache
+{
+    pub fn new() -> Self
+    {
+        MeshCache::default()
+    }
+
+    /// Returns a mesh for the triangles, only building
+    /// a new octree if they've not been seen before.
+    /// Octrees whose build was cancelled aren't kept.
+    pub fn get_or_build(&self, triangles: Vec<Triangle>, quality: OctreeQuality, cancelled: Option<&AtomicBool>) -> Mesh
+    {
+        let key = hash_triangles(&triangles, quality);
+
+        {
+            let mut inner = self.inner.lock().unwrap();
+
+            if let Some(mesh) = inner.meshes.get(&key).cloned()
+            {
+                inner.used.insert(key);
+                return mesh;
+            }
+        }
+
+        // Build without holding the lock, so that
+        // other threads can continue to use the cache
+
+        let mesh = Mesh::new(triangles, quality, cancelled);
+
+        if cancelled.map(|c| c.load(Ordering::Relaxed)).unwrap_or(false)
+        {
+            return mesh;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.meshes.insert(key, mesh.clone());
+        inner.used.insert(key);
+
+        mesh
+    }
+
+    /// Discards any meshes that haven't been used since the
+    /// last call - call once a complete scene has been built.
+    pub fn retain_used(&self)
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let used = std::mem::take(&mut inner.used);
+
+        inner.meshes.retain(|key, _| used.contains(key));
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.inner.lock().unwrap().meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+}
+
+fn hash_triangles(triangles: &[Triangle], quality: OctreeQuality) -> u64
+{
+    let mut hasher = DefaultHasher::new();
+
+    quality.hash(&mut hasher);
+
+    hasher.write_usize(triangles.len());
+
+    for triangle in triangles.iter()
+    {
+        for p in [triangle.p0, triangle.p1, triangle.p2, triangle.t0, triangle.t1, triangle.t2].iter()
+        {
+            hasher.write_u64(p.x.to_bits());
+            hasher.write_u64(p.y.to_bits());
+            hasher.write_u64(p.z.to_bits());
+        }
+
+        if let Some(colors) = &triangle.opt_colors
+        {
+            for c in colors.iter()
+            {
+                hasher.write_u64(c.r.to_bits());
+                hasher.write_u64(c.g.to_bits());
+                hasher.write_u64(c.b.to_bits());
+                hasher.write_u64(c.a.to_bits());
+            }
+        }
+
+        if let Some(normals) = &triangle.opt_normals
+        {
+            for n in normals.iter()
+            {
+                hasher.write_u64(n.x.to_bits());
+                hasher.write_u64(n.y.to_bits());
+                hasher.write_u64(n.z.to_bits());
+            }
+        }
+    }
+
+    hasher.finish()
+}
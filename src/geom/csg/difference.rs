@@ -1,4 +1,4 @@
-use crate::geom::{Volume, Surface, SurfaceIntersection};
+use crate::geom::{Aabb, Volume, Surface, SurfaceIntersection};
 use crate::math::EPSILON;
 use crate::ray::{Ray, RayRange};
 use crate::vec::Point3;
@@ -94,6 +94,13 @@ impl<A: Volume + Clone + 'static, B: Volume + Clone + 'static> Surface for Diffe
 
         None
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        // Removing B can only make A smaller
+
+        self.a.bounding_aabb()
+    }
 }
 
 impl<A: Volume + Clone + 'static, B: Volume + Clone + 'static> Volume for Difference<A, B>
@@ -1,4 +1,4 @@
-use crate::geom::{Surface, SurfaceIntersection};
+use crate::geom::{Aabb, Surface, SurfaceIntersection};
 use crate::ray::{Ray, RayRange};
 
 #[derive(Clone)]
@@ -38,4 +38,12 @@ impl Surface for Merge
 
         closest
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        let mut bounds = self.surfaces.iter().map(|s| s.bounding_aabb());
+        let first = bounds.next()??;
+
+        bounds.try_fold(first, |result, b| Some(result.union(&b?)))
+    }
 }
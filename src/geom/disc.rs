@@ -1,4 +1,4 @@
-use crate::geom::{SampleableSurface, Surface};
+use crate::geom::{Aabb, SampleableSurface, Surface};
 use crate::intersection::SurfaceIntersection;
 use crate::math::{EPSILON, Scalar, ScalarConsts};
 use crate::ray::{Ray, RayRange};
@@ -59,6 +59,15 @@ impl Surface for Disc
 
         None
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        // The disc's sphere, as that's simpler
+
+        let radius = Point3::broadcast(self.radius.abs());
+
+        Some(Aabb::new(self.point - radius, self.point + radius))
+    }
 }
 
 impl SampleableSurface for Disc
@@ -0,0 +1,124 @@
+use crate::color::LinearRGB;
+use crate::math::{EPSILON, Scalar};
+use crate::vec::{Dir3, Point3};
+use crate::geom::{Surface, Volume};
+use crate::intersection::SurfaceIntersection;
+use crate::ray::{Ray, RayRange};
+use serde::{Deserialize, Serialize};
+
+/// Fades the ground out to the background between
+/// two distances from the ground's origin point
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GroundFade
+{
+    pub start: Scalar,
+    pub end: Scalar,
+}
+
+impl GroundFade
+{
+    /// Opacity at the given distance from the origin point
+    fn alpha(&self, distance: Scalar) -> Scalar
+    {
+        if distance <= self.start
+        {
+            1.0
+        }
+        else if distance >= self.end
+        {
+            0.0
+        }
+        else
+        {
+            // Smooth-step, so there's no visible
+            // edge at either end of the fade
+
+            let t = (distance - self.start) / (self.end - self.start);
+
+            1.0 - (t * t * (3.0 - 2.0 * t))
+        }
+    }
+}
+
+/// An infinite plane with planar texture coordinates around
+/// its origin point. With a fade, the ground is partially
+/// transparent (for diffuse materials) while fading, and
+/// isn't hit at all beyond the fade, so the horizon shows
+/// whatever is behind it rather than an aliased line.
+#[derive(Clone)]
+pub struct GroundPlane
+{
+    point: Point3,
+    normal: Dir3,
+    u: Dir3,
+    v: Dir3,
+    uv_scale: Scalar,
+    fade: Option<GroundFade>,
+}
+
+impl GroundPlane
+{
+    pub fn new(point: Point3, normal: Dir3, uv_scale: Scalar, fade: Option<GroundFade>) -> Self
+    {
+        let normal = normal.normalized();
+
+        let u = if normal.x.abs() > 0.9 { Dir3::new(0.0, 1.0, 0.0) } else { Dir3::new(1.0, 0.0, 0.0) };
+        let v = normal.cross(u).normalized();
+        let u = v.cross(normal);
+
+        GroundPlane { point, normal, u, v, uv_scale, fade }
+    }
+}
+
+impl Surface for GroundPlane
+{
+    fn closest_intersection_in_range<'r>(&self, ray: &'r Ray, range: &RayRange) -> Option<SurfaceIntersection<'r>>
+    {
+        let denom = ray.dir.dot(self.normal);
+
+        if denom.abs() > EPSILON
+        {
+            let distance = (self.point - ray.source).dot(self.normal) / denom;
+
+            if range.contains(distance)
+            {
+                let offset = ray.point_at(distance) - self.point;
+
+                let opt_color = match &self.fade
+                {
+                    Some(fade) =>
+                    {
+                        let alpha = fade.alpha(offset.magnitude());
+
+                        if alpha <= 0.0
+                        {
+                            return None;
+                        }
+
+                        Some(LinearRGB::new(1.0, 1.0, 1.0, alpha))
+                    },
+                    None => None,
+                };
+
+                let texture_coords = Point3::new(
+                    offset.dot(self.u) / self.uv_scale,
+                    offset.dot(self.v) / self.uv_scale,
+                    0.0);
+
+                return Some(ray.new_intersection_with_texture_coords(distance, self.normal, texture_coords, opt_color));
+            }
+        }
+
+        None
+    }
+}
+
+impl Volume for GroundPlane
+{
+    fn is_point_inside(&self, point: Point3) -> bool
+    {
+        let dot = (point - self.point).dot(self.normal);
+
+        dot <= 0.0
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::geom::{Aabb, Surface};
+use crate::intersection::{Face, SurfaceIntersection};
+use crate::math::Scalar;
+use crate::ray::{Ray, RayRange};
+use crate::vec::Point3;
+
+/// How the density of a medium varies across its bounding box
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Density
+{
+    /// Densities at the centers of a grid of cells, in x, then
+    /// y, then z order, interpolated between cells
+    Grid{ resolution: [usize; 3], values: Arc<Vec<Scalar>> },
+    /// Fractal value noise, between zero and one
+    Noise{ frequency: Scalar, octaves: usize },
+}
+
+impl Density
+{
+    /// The density at a point, in coordinates
+    /// from zero to one across the bounding box
+    pub fn density_at(&self, point: Point3) -> Scalar
+    {
+        match self
+        {
+            Density::Grid{ resolution, values } =>
+            {
+                let mut cells = [(0, 0, 0.0); 3];
+
+                for (axis, cell) in cells.iter_mut().enumerate()
+                {
+                    let max = resolution[axis] - 1;
+                    let pos = (point[axis] * (resolution[axis] as Scalar) - 0.5).clamp(0.0, max as Scalar);
+                    let lower = (pos.floor() as usize).min(max);
+
+                    *cell = (lower, (lower + 1).min(max), pos - (lower as Scalar));
+                }
+
+                let value = |x: usize, y: usize, z: usize| values[x + resolution[0] * (y + resolution[1] * z)];
+                let lerp = |a: Scalar, b: Scalar, t: Scalar| a + (b - a) * t;
+
+                let [(x0, x1, tx), (y0, y1, ty), (z0, z1, tz)] = cells;
+
+                lerp(
+                    lerp(lerp(value(x0, y0, z0), value(x1, y0, z0), tx), lerp(value(x0, y1, z0), value(x1, y1, z0), tx), ty),
+                    lerp(lerp(value(x0, y0, z1), value(x1, y0, z1), tx), lerp(value(x0, y1, z1), value(x1, y1, z1), tx), ty),
+                    tz)
+            },
+            Density::Noise{ frequency, octaves } =>
+            {
+                let mut total = 0.0;
+                let mut amplitude = 1.0;
+                let mut sum_amplitudes = 0.0;
+                let mut point = point * *frequency;
+
+                for _ in 0..(*octaves).max(1)
+                {
+                    total += amplitude * value_noise(point);
+                    sum_amplitudes += amplitude;
+                    amplitude *= 0.5;
+                    point *= 2.0;
+                }
+
+                total / sum_amplitudes
+            },
+        }
+    }
+
+    pub fn max_density(&self) -> Scalar
+    {
+        match self
+        {
+            Density::Grid{ values, .. } => values.iter().cloned().fold(0.0, Scalar::max),
+            Density::Noise{ .. } => 1.0,
+        }
+    }
+}
+
+/// A participating medium filling a box, with a density
+/// that can vary. Rays passing through are stochastically
+/// scattered using delta tracking - so the intersection
+/// returned is a point inside the medium, facing the ray.
+#[derive(Clone)]
+pub struct Medium
+{
+    aabb: Aabb,
+    density: Density,
+    scale: Scalar,
+    majorant: Scalar,
+}
+
+impl Medium
+{
+    /// The scale converts densities into
+    /// extinction per unit of distance
+    pub fn new(aabb: Aabb, density: Density, scale: Scalar) -> Self
+    {
+        let majorant = density.max_density() * scale;
+
+        Medium { aabb, density, scale, majorant }
+    }
+
+    fn unit_coords(&self, point: Point3) -> Point3
+    {
+        (point - self.aabb.min) / (self.aabb.max - self.aabb.min)
+    }
+
+    fn extinction_at(&self, point: Point3) -> Scalar
+    {
+        self.density.density_at(self.unit_coords(point)) * self.scale
+    }
+
+    /// Steps along the ray by exponentially distributed
+    /// distances, as if the whole medium had the maximum density.
+    /// Returns false to stop early.
+    fn track<R: Rng, F: FnMut(&mut R, Scalar) -> bool>(&self, ray: &Ray, range: &RayRange, rng: &mut R, mut step: F)
+    {
+        let range = match self.aabb.clip_range(ray, range)
+        {
+            Some(range) if self.majorant > 0.0 => range,
+            _ => return,
+        };
+
+        let rate = self.majorant * ray.dir.magnitude();
+        let mut t = range.min();
+
+        loop
+        {
+            t -= (1.0 - rng.gen::<Scalar>()).ln() / rate;
+
+            if (t >= range.max()) || !step(rng, t)
+            {
+                return;
+            }
+        }
+    }
+}
+
+impl Surface for Medium
+{
+    fn closest_intersection_in_range<'r>(&self, ray: &'r Ray, range: &RayRange) -> Option<SurfaceIntersection<'r>>
+    {
+        // Delta tracking - each step is a real collision with
+        // a probability of the actual density over the maximum
+
+        let mut rng = ray_rng(ray, range, 0);
+        let mut collision = None;
+
+        self.track(ray, range, &mut rng, |rng, t|
+        {
+            if (rng.gen::<Scalar>() * self.majorant) < self.extinction_at(ray.point_at(t))
+            {
+                collision = Some(t);
+                return false;
+            }
+
+            true
+        });
+
+        let distance = collision?;
+        let location = ray.point_at(distance);
+
+        Some(SurfaceIntersection
+        {
+            ray,
+            distance,
+            location: Some(location),
+            face: Face::Front,
+            normal: -ray.dir.normalized(),
+            texture_coords: Some(self.unit_coords(location)),
+            opt_color: None,
+            tangents: None,
+        })
+    }
+
+    fn is_medium(&self) -> bool
+    {
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, range: &RayRange) -> Scalar
+    {
+        // Ratio tracking - each step attenuates by the chance
+        // of it being a real collision
+
+        let mut rng = ray_rng(ray, range, 1);
+        let mut transmittance = 1.0;
+
+        self.track(ray, range, &mut rng, |_, t|
+        {
+            transmittance *= 1.0 - (self.extinction_at(ray.point_at(t)) / self.majorant);
+
+            transmittance > 0.0
+        });
+
+        transmittance
+    }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        Some(self.aabb.clone())
+    }
+}
+
+/// Surfaces don't have access to the sampler, so the random
+/// numbers are seeded from the ray - which itself was
+/// randomly generated by the sampler
+fn ray_rng(ray: &Ray, range: &RayRange, salt: u64) -> rand::rngs::SmallRng
+{
+    let seed = [ray.source.x, ray.source.y, ray.source.z, ray.dir.x, ray.dir.y, ray.dir.z, range.min()]
+        .iter()
+        .fold(salt, |hash, value| mix(hash ^ value.to_bits()));
+
+    rand::rngs::SmallRng::seed_from_u64(seed)
+}
+
+fn mix(value: u64) -> u64
+{
+    let mut value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Smoothly interpolated random values at integer
+/// lattice points, between zero and one
+fn value_noise(point: Point3) -> Scalar
+{
+    let lattice = |x: i64, y: i64, z: i64| ((mix(mix(mix(x as u64) ^ (y as u64)) ^ (z as u64)) >> 11) as Scalar) / ((1u64 << 53) as Scalar);
+    let smooth = |t: Scalar| t * t * (3.0 - 2.0 * t);
+    let lerp = |a: Scalar, b: Scalar, t: Scalar| a + (b - a) * t;
+
+    let floor = point.map(|c| c.floor());
+    let [x, y, z] = [floor.x as i64, floor.y as i64, floor.z as i64];
+    let [tx, ty, tz] = [smooth(point.x - floor.x), smooth(point.y - floor.y), smooth(point.z - floor.z)];
+
+    lerp(
+        lerp(lerp(lattice(x, y, z), lattice(x + 1, y, z), tx), lerp(lattice(x, y + 1, z), lattice(x + 1, y + 1, z), tx), ty),
+        lerp(lerp(lattice(x, y, z + 1), lattice(x + 1, y, z + 1), tx), lerp(lattice(x, y + 1, z + 1), lattice(x + 1, y + 1, z + 1), tx), ty),
+        tz)
+}
@@ -1,18 +1,22 @@
-use crate::geom::{Octree, Surface, Triangle};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use crate::geom::{Aabb, Octree, OctreeQuality, Surface, Triangle};
 use crate::intersection::SurfaceIntersection;
 use crate::ray::{Ray, RayRange};
 
+/// Clones share the same octree
 #[derive(Clone)]
 pub struct Mesh
 {
-    octree: Octree<Triangle>,
+    octree: Arc<Octree<Triangle>>,
 }
 
 impl Mesh
 {
-    pub fn new(triangles: Vec<Triangle>) -> Self
+    pub fn new(triangles: Vec<Triangle>, quality: OctreeQuality, cancelled: Option<&AtomicBool>) -> Self
     {
-        Mesh { octree: Octree::new(triangles, 10) }
+        Mesh { octree: Arc::new(Octree::new(triangles, 10, quality, cancelled)) }
     }
 }
 
@@ -22,4 +26,9 @@ impl Surface for Mesh
     {
         self.octree.closest_intersection_in_range(ray, range)
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        self.octree.bounding_aabb()
+    }
 }
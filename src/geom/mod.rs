@@ -7,31 +7,62 @@ use crate::sample::Sampler;
 pub mod aabb;
 pub mod blob;
 pub mod bounds;
+pub mod bvh;
+pub mod cache;
 pub mod csg;
 pub mod disc;
+pub mod ground;
+pub mod medium;
 pub mod mesh;
 pub mod octree;
 pub mod plane;
 pub mod rectangle;
 pub mod sdf;
 pub mod sphere;
+pub mod transformed;
 pub mod triangle;
 
 pub use aabb::{Aabb, AabbBuilder};
 pub use blob::{Blob, BlobPart};
 pub use bounds::BoundedSurface;
+pub use bvh::Bvh;
+pub use cache::MeshCache;
 pub use disc::Disc;
+pub use ground::{GroundFade, GroundPlane};
+pub use medium::{Density, Medium};
 pub use mesh::Mesh;
-pub use octree::Octree;
+pub use octree::{Octree, OctreeQuality};
 pub use plane::Plane;
 pub use rectangle::{OneWayRectangle, Rectangle};
 pub use sdf::Sdf;
 pub use sphere::Sphere;
+pub use transformed::Transformed;
 pub use triangle::Triangle;
 
 pub trait Surface: CloneableSurface + Send
 {
     fn closest_intersection_in_range<'r>(&self, ray: &'r Ray, range: &RayRange) -> Option<SurfaceIntersection<'r>>;
+
+    /// Participating media only block some of the light passing
+    /// through them, so are skipped when tracing shadow rays
+    fn is_medium(&self) -> bool
+    {
+        false
+    }
+
+    /// The fraction of light passing through a medium
+    /// along the ray - solid surfaces block everything
+    fn transmittance(&self, _ray: &Ray, _range: &RayRange) -> Scalar
+    {
+        0.0
+    }
+
+    /// A box containing the whole surface, used to accelerate
+    /// tracing through the scene - None if it's unbounded
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        None
+    }
 }
 
 pub trait BoundingSurface: Surface
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use float_ord::FloatOrd;
 use itertools::Itertools;
@@ -9,6 +10,71 @@ use crate::math::Scalar;
 use crate::ray::{Ray, RayRange};
 use crate::vec::Point3;
 
+/// Nodes with fewer items than this are always built on the
+/// current thread, as starting a thread would cost more
+const MIN_PARALLEL_ITEMS: usize = 4096;
+
+/// Items sampled to find the median, for fast builds
+const FAST_SAMPLE_SIZE: usize = 64;
+
+/// The cost of traversing a node, relative to
+/// testing an item, for the surface area heuristic
+const SAH_TRAVERSAL_COST: Scalar = 1.0;
+
+/// How much effort goes into choosing where to split nodes -
+/// better splits make a faster octree, but take longer to find
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OctreeQuality
+{
+    /// Splits the longest side at the median
+    /// of a sample of the items
+    Fast,
+    /// Splits to balance the number of items on each
+    /// side, while duplicating as few as possible
+    #[default]
+    Balanced,
+    /// Sweeps every possible split, minimizing the
+    /// surface area heuristic
+    HighQuality,
+}
+
+impl OctreeQuality
+{
+    pub const ALL: [OctreeQuality; 3] = [OctreeQuality::Fast, OctreeQuality::Balanced, OctreeQuality::HighQuality];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            OctreeQuality::Fast => "Fast",
+            OctreeQuality::Balanced => "Balanced",
+            OctreeQuality::HighQuality => "High Quality",
+        }
+    }
+
+    /// The name used on the command line and in settings files
+    pub fn arg_name(&self) -> &'static str
+    {
+        match self
+        {
+            OctreeQuality::Fast => "fast",
+            OctreeQuality::Balanced => "balanced",
+            OctreeQuality::HighQuality => "high-quality",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        match name
+        {
+            "fast" => Some(OctreeQuality::Fast),
+            "balanced" => Some(OctreeQuality::Balanced),
+            "high-quality" => Some(OctreeQuality::HighQuality),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Octree<S: AabbBoundedSurface + Clone + 'static>
 {
@@ -17,11 +83,14 @@ pub struct Octree<S: AabbBoundedSurface + Clone + 'static>
     tree: OctreeNode,
 }
 
-impl<S: AabbBoundedSurface + Clone + 'static> Octree<S>
+impl<S: AabbBoundedSurface + Clone + Sync + 'static> Octree<S>
 {
-    pub fn new(items: Vec<S>, target_leaf_size: usize) -> Self
+    /// Large nodes are built in parallel. Once the cancelled flag
+    /// is set, the remaining nodes are left as leaves, so the build
+    /// returns quickly - but the octree is then slow to trace.
+    pub fn new(items: Vec<S>, target_leaf_size: usize, quality: OctreeQuality, cancelled: Option<&AtomicBool>) -> Self
     {
-        let Split{ bounds, sub_tree } = build_complete_octree(&items, target_leaf_size);
+        let Split{ bounds, sub_tree } = build_complete_octree(&items, target_leaf_size, quality, cancelled);
 
         let result = Octree
         {
@@ -63,6 +132,11 @@ impl<S: AabbBoundedSurface + Clone + 'static> Surface for Octree<S>
             None
         }
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        Some(self.bounds.clone())
+    }
 }
 
 impl<S: AabbBoundedSurface + Clone + 'static> AabbBoundedSurface for Octree<S>
@@ -179,15 +253,26 @@ struct BuildInfo<'a, S: AabbBoundedSurface + Clone + 'static>
     items: &'a Vec<S>,
     item_bounds: Vec<Aabb>,
     target_leaf_size: usize,
+    quality: OctreeQuality,
+    cancelled: Option<&'a AtomicBool>,
+    /// Nodes above this depth build their children in
+    /// parallel - enough for a thread on each CPU
+    max_parallel_depth: usize,
 }
 
 impl<'a, S: AabbBoundedSurface + Clone + 'static> BuildInfo<'a, S>
 {
-    fn new(items: &'a Vec<S>, target_leaf_size: usize) -> Self
+    fn new(items: &'a Vec<S>, target_leaf_size: usize, quality: OctreeQuality, cancelled: Option<&'a AtomicBool>) -> Self
     {
         let item_bounds = items.iter().map(|i| i.get_bounding_aabb()).collect();
+        let max_parallel_depth = num_cpus::get().max(1).next_power_of_two().trailing_zeros() as usize;
+
+        BuildInfo { items, item_bounds, target_leaf_size, quality, cancelled, max_parallel_depth }
+    }
 
-        BuildInfo { items, item_bounds, target_leaf_size }
+    fn is_cancelled(&self) -> bool
+    {
+        self.cancelled.map(|c| c.load(Ordering::Relaxed)).unwrap_or(false)
     }
 }
 
@@ -196,6 +281,7 @@ struct CurSplitState
     bounds: Aabb,
     included_indexes: Vec<usize>,
     name: String,
+    depth: usize,
 }
 
 impl CurSplitState
@@ -215,19 +301,19 @@ impl CurSplitState
 
         let name = "root".to_owned();
 
-        CurSplitState { bounds, included_indexes, name }
+        CurSplitState { bounds, included_indexes, name, depth: 0 }
     }
 
     fn new_sub(&self, dim_name: &'static str, split_index: usize, bounds: Aabb, included_indexes: Vec<usize>) -> Self
     {
         let name = format!("{}.{}{}", self.name, dim_name, split_index);
-        CurSplitState { bounds, included_indexes, name }
+        CurSplitState { bounds, included_indexes, name, depth: self.depth + 1 }
     }
 }
 
-fn build_complete_octree<S: AabbBoundedSurface + Clone + 'static>(items: &Vec<S>, target_leaf_size: usize) -> Split
+fn build_complete_octree<S: AabbBoundedSurface + Clone + Sync + 'static>(items: &Vec<S>, target_leaf_size: usize, quality: OctreeQuality, cancelled: Option<&AtomicBool>) -> Split
 {
-    let info = BuildInfo::new(items, target_leaf_size);
+    let info = BuildInfo::new(items, target_leaf_size, quality, cancelled);
     let root_split = CurSplitState::new_root(&info);
 
     let root_bounds = root_split.bounds.clone();
@@ -241,31 +327,15 @@ fn build_complete_octree<S: AabbBoundedSurface + Clone + 'static>(items: &Vec<S>
 }
 
 fn create_node<'a, S>(info: &BuildInfo<'a, S>, cur_split: CurSplitState) -> OctreeNode
-    where S: AabbBoundedSurface + Clone + 'static
+    where S: AabbBoundedSurface + Clone + Sync + 'static
 {
-    if cur_split.included_indexes.len() > info.target_leaf_size
+    if (cur_split.included_indexes.len() > info.target_leaf_size) && !info.is_cancelled()
     {
-        let split_x = create_split_dim(info, &cur_split, "x", |v| v.x, |v, s| Point3::new(s, v.y, v.z));
-        let split_y = create_split_dim(info, &cur_split, "y", |v| v.y, |v, s| Point3::new(v.x, s, v.z));
-        let split_z = create_split_dim(info, &cur_split, "z", |v| v.z, |v, s| Point3::new(v.x, v.y, s));
-
-        //println!("CreateSplit: {} Summary x {:5}/{:5}/{:5} y {:5}/{:5}/{:5} z {:5}/{:5}/{:5}",
-        //    cur_split.name,
-        //    split_x.items_0.len(), split_x.items_1.len(), split_x.best_sum,
-        //    split_y.items_0.len(), split_y.items_1.len(), split_y.best_sum,
-        //    split_z.items_0.len(), split_z.items_1.len(), split_z.best_sum);
-
-        let best_split = if (split_x.best_sum <= split_y.best_sum) && (split_x.best_sum <= split_z.best_sum)
-        {
-            split_x
-        }
-        else if (split_y.best_sum <= split_x.best_sum) && (split_y.best_sum <= split_z.best_sum)
+        let best_split = match info.quality
         {
-            split_y
-        }
-        else
-        {
-            split_z
+            OctreeQuality::Fast => create_median_split(info, &cur_split),
+            OctreeQuality::Balanced => create_balanced_split(info, &cur_split),
+            OctreeQuality::HighQuality => create_sah_split(info, &cur_split),
         };
 
         if best_split.made_progress
@@ -278,8 +348,26 @@ fn create_node<'a, S>(info: &BuildInfo<'a, S>, cur_split: CurSplitState) -> Octr
             let bounds_0 = sub_split_0.bounds.clone();
             let bounds_1 = sub_split_1.bounds.clone();
 
-            let node_0 = create_node(info, sub_split_0);
-            let node_1 = create_node(info, sub_split_1);
+            // Build large nodes' children in parallel
+
+            let parallel = (cur_split.depth < info.max_parallel_depth)
+                && (sub_split_0.included_indexes.len() >= MIN_PARALLEL_ITEMS)
+                && (sub_split_1.included_indexes.len() >= MIN_PARALLEL_ITEMS);
+
+            let (node_0, node_1) = if parallel
+            {
+                std::thread::scope(|scope|
+                {
+                    let thread_0 = scope.spawn(|| create_node(info, sub_split_0));
+                    let node_1 = create_node(info, sub_split_1);
+
+                    (thread_0.join().expect("Octree build thread panicked"), node_1)
+                })
+            }
+            else
+            {
+                (create_node(info, sub_split_0), create_node(info, sub_split_1))
+            };
 
             return OctreeNode::Split(vec![
                 Split { bounds: bounds_0, sub_tree: node_0 },
@@ -290,7 +378,8 @@ fn create_node<'a, S>(info: &BuildInfo<'a, S>, cur_split: CurSplitState) -> Octr
 
     // Either:
     // 1. The number of items is already below the target leaf size,
-    // 2. No good progress could be made
+    // 2. No good progress could be made, or
+    // 3. The build has been cancelled
     // Just return a leaf containing everything
 
     //println!("CreateSplit: {} Result: Leaf", cur_split.name);
@@ -298,6 +387,189 @@ fn create_node<'a, S>(info: &BuildInfo<'a, S>, cur_split: CurSplitState) -> Octr
     OctreeNode::Leaf(cur_split.included_indexes)
 }
 
+fn create_balanced_split<'a, S>(info: &BuildInfo<'a, S>, cur_split: &CurSplitState) -> SplitOption
+    where S: AabbBoundedSurface + Clone + 'static
+{
+    let split_x = create_split_dim(info, cur_split, "x", |v| v.x, |v, s| Point3::new(s, v.y, v.z));
+    let split_y = create_split_dim(info, cur_split, "y", |v| v.y, |v, s| Point3::new(v.x, s, v.z));
+    let split_z = create_split_dim(info, cur_split, "z", |v| v.z, |v, s| Point3::new(v.x, v.y, s));
+
+    //println!("CreateSplit: {} Summary x {:5}/{:5}/{:5} y {:5}/{:5}/{:5} z {:5}/{:5}/{:5}",
+    //    cur_split.name,
+    //    split_x.items_0.len(), split_x.items_1.len(), split_x.best_sum,
+    //    split_y.items_0.len(), split_y.items_1.len(), split_y.best_sum,
+    //    split_z.items_0.len(), split_z.items_1.len(), split_z.best_sum);
+
+    if (split_x.best_sum <= split_y.best_sum) && (split_x.best_sum <= split_z.best_sum)
+    {
+        split_x
+    }
+    else if (split_y.best_sum <= split_x.best_sum) && (split_y.best_sum <= split_z.best_sum)
+    {
+        split_y
+    }
+    else
+    {
+        split_z
+    }
+}
+
+const DIM_NAMES: [&str; 3] = ["x", "y", "z"];
+
+/// Splits the longest side of the bounds at the median of the
+/// centers of a sample of the items - quick to find, but it
+/// takes no account of how large the items are
+fn create_median_split<'a, S>(info: &BuildInfo<'a, S>, cur_split: &CurSplitState) -> SplitOption
+    where S: AabbBoundedSurface + Clone + 'static
+{
+    let size = cur_split.bounds.max - cur_split.bounds.min;
+    let axis = if (size.x >= size.y) && (size.x >= size.z) { 0 } else if size.y >= size.z { 1 } else { 2 };
+
+    let step = cur_split.included_indexes.len().div_ceil(FAST_SAMPLE_SIZE).max(1);
+
+    let mut centers = cur_split.included_indexes.iter()
+        .step_by(step)
+        .map(|i| 0.5 * (info.item_bounds[*i].min[axis] + info.item_bounds[*i].max[axis]))
+        .collect_vec();
+
+    float_ord::sort(centers.as_mut_slice());
+
+    let split = centers[centers.len() / 2];
+    let option = split_at(info, cur_split, axis, split);
+
+    // Give up if the split duplicates too many items
+
+    let num = cur_split.included_indexes.len();
+
+    if option.made_progress && ((option.items_0.len() + option.items_1.len()) <= (num + (num / 2)))
+    {
+        option
+    }
+    else
+    {
+        no_split(cur_split, DIM_NAMES[axis])
+    }
+}
+
+/// Tries a split at the minimum and maximum of every item's bounds,
+/// along each axis, choosing the one with the lowest surface area
+/// heuristic cost - the expected number of items a ray passing
+/// through the node has to test
+fn create_sah_split<'a, S>(info: &BuildInfo<'a, S>, cur_split: &CurSplitState) -> SplitOption
+    where S: AabbBoundedSurface + Clone + 'static
+{
+    let num = cur_split.included_indexes.len();
+    let area = surface_area(&cur_split.bounds.min, &cur_split.bounds.max).max(Scalar::MIN_POSITIVE);
+
+    // Splitting is only worthwhile if it's cheaper than testing every item
+
+    let mut best: Option<(usize, Scalar)> = None;
+    let mut best_cost = num as Scalar;
+
+    for axis in 0..3
+    {
+        let lower = cur_split.bounds.min[axis];
+        let upper = cur_split.bounds.max[axis];
+
+        let mut mins = cur_split.included_indexes.iter().map(|i| info.item_bounds[*i].min[axis]).collect_vec();
+        let mut maxs = cur_split.included_indexes.iter().map(|i| info.item_bounds[*i].max[axis]).collect_vec();
+
+        float_ord::sort(mins.as_mut_slice());
+        float_ord::sort(maxs.as_mut_slice());
+
+        for split in mins.iter().chain(maxs.iter()).copied().filter(|p| (*p > lower) && (*p < upper))
+        {
+            // Items touching the split are in both halves
+
+            let num_0 = mins.partition_point(|m| *m <= split);
+            let num_1 = num - maxs.partition_point(|m| *m < split);
+
+            let mut max_0 = cur_split.bounds.max;
+            max_0[axis] = split;
+            let mut min_1 = cur_split.bounds.min;
+            min_1[axis] = split;
+
+            let cost = SAH_TRAVERSAL_COST
+                + (((surface_area(&cur_split.bounds.min, &max_0) * (num_0 as Scalar))
+                    + (surface_area(&min_1, &cur_split.bounds.max) * (num_1 as Scalar))) / area);
+
+            if (cost < best_cost) && (num_0 < num) && (num_1 < num)
+            {
+                best_cost = cost;
+                best = Some((axis, split));
+            }
+        }
+    }
+
+    match best
+    {
+        Some((axis, split)) => split_at(info, cur_split, axis, split),
+        None => no_split(cur_split, "x"),
+    }
+}
+
+fn surface_area(min: &Point3, max: &Point3) -> Scalar
+{
+    let size = max - min;
+
+    2.0 * ((size.x * size.y) + (size.y * size.z) + (size.z * size.x))
+}
+
+fn split_at<'a, S>(info: &BuildInfo<'a, S>, cur_split: &CurSplitState, axis: usize, split: Scalar) -> SplitOption
+    where S: AabbBoundedSurface + Clone + 'static
+{
+    if (split <= cur_split.bounds.min[axis]) || (split >= cur_split.bounds.max[axis])
+    {
+        return no_split(cur_split, DIM_NAMES[axis]);
+    }
+
+    let mut max_0 = cur_split.bounds.max;
+    max_0[axis] = split;
+    let mut min_1 = cur_split.bounds.min;
+    min_1[axis] = split;
+
+    let bounds_0 = Aabb::new(cur_split.bounds.min, max_0);
+    let bounds_1 = Aabb::new(min_1, cur_split.bounds.max);
+
+    let items_0 = cur_split.included_indexes.iter()
+        .cloned()
+        .filter(|i| bounds_0.intersects(&info.item_bounds[*i]))
+        .collect_vec();
+
+    let items_1 = cur_split.included_indexes.iter()
+        .cloned()
+        .filter(|i| bounds_1.intersects(&info.item_bounds[*i]))
+        .collect_vec();
+
+    let num = cur_split.included_indexes.len();
+    let made_progress = (items_0.len() < num) && (items_1.len() < num);
+
+    SplitOption
+    {
+        made_progress,
+        best_sum: 0,
+        dim_name: DIM_NAMES[axis],
+        bounds_0,
+        items_0,
+        bounds_1,
+        items_1,
+    }
+}
+
+fn no_split(cur_split: &CurSplitState, dim_name: &'static str) -> SplitOption
+{
+    SplitOption
+    {
+        made_progress: false,
+        best_sum: usize::MAX,
+        dim_name,
+        bounds_0: cur_split.bounds.clone(),
+        items_0: cur_split.included_indexes.clone(),
+        bounds_1: cur_split.bounds.clone(),
+        items_1: Vec::new(),
+    }
+}
+
 struct SplitOption
 {
     made_progress: bool,
@@ -450,14 +722,5 @@ fn create_split_dim<'a, S, Extract, Combine>(info: &BuildInfo<'a, S>, cur_split:
 
     // The split doens't make good progress
 
-    return SplitOption
-    {
-        made_progress: false,
-        best_sum: usize::MAX,
-        dim_name,
-        bounds_0: cur_split.bounds.clone(),
-        items_0: cur_split.included_indexes.clone(),
-        bounds_1: cur_split.bounds.clone(),
-        items_1: Vec::new(),
-    };
+    no_split(cur_split, dim_name)
 }
@@ -1,4 +1,4 @@
-use crate::geom::{SampleableSurface, Surface};
+use crate::geom::{Aabb, AabbBuilder, SampleableSurface, Surface};
 use crate::intersection::{Face, SurfaceIntersection};
 use crate::math::{EPSILON, Scalar};
 use crate::ray::{Ray, RayRange};
@@ -67,6 +67,18 @@ impl Surface for Rectangle
 
         None
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        let u = self.len_u * self.dir_u;
+        let v = self.len_v * self.dir_v;
+
+        let mut builder = AabbBuilder::new();
+        builder.add_triangle(self.point, self.point + u, self.point + v);
+        builder.add_point(self.point + u + v);
+
+        Some(builder.build())
+    }
 }
 
 impl SampleableSurface for Rectangle
@@ -141,4 +153,9 @@ impl Surface for OneWayRectangle
         self.rect.closest_intersection_in_range(ray, range)
             .filter(|i| i.face == Face::Front)
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        self.rect.bounding_aabb()
+    }
 }
\ No newline at end of file
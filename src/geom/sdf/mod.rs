@@ -2,19 +2,51 @@ use crate::intersection::SurfaceIntersection;
 use crate::math::Scalar;
 use crate::geom::{Surface, Volume};
 use crate::ray::{Ray, RayRange};
-use crate::vec::{Dir3, Point3};
+use crate::vec::{Dir3, Mat4, Point3, Vec4};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+mod tessellate;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Sdf
 {
     Sphere{ center: Point3, radius: Scalar },
     Capsule{ a: Point3, b: Point3, radius: Scalar },
     Union{ members: Vec<Sdf> },
     Annular{ sdf: Box<Sdf>, radius: Scalar },
+    Box{ min: Point3, max: Point3 },
+    /// Fits within the corners, with edges rounded by the radius
+    RoundedBox{ min: Point3, max: Point3, radius: Scalar },
+    /// Around the axis, which is normalized
+    Torus{ center: Point3, axis: Dir3, major_radius: Scalar, minor_radius: Scalar },
+    /// Capped, with the given radius at each end
+    Cone{ a: Point3, b: Point3, radius_a: Scalar, radius_b: Scalar },
+    /// The inside is the half-space behind the normalized normal
+    Plane{ point: Point3, normal: Dir3 },
+    Cylinder{ a: Point3, b: Point3, radius: Scalar },
+    Intersection{ members: Vec<Sdf> },
+    Subtraction{ sdf: Box<Sdf>, subtracted: Box<Sdf> },
+    /// Blended where the members are within the radius of each other
+    SmoothUnion{ members: Vec<Sdf>, radius: Scalar },
+    SmoothSubtraction{ sdf: Box<Sdf>, subtracted: Box<Sdf>, radius: Scalar },
+    /// Created with `Sdf::transformed`, which also finds the inverse,
+    /// and the smallest scale along any axis, which distances are
+    /// multiplied by - so they're exact for uniform scales, and
+    /// never too far otherwise
+    Transformed{ sdf: Box<Sdf>, transform: Box<Mat4>, inverse: Box<Mat4>, scale: Scalar },
 }
 
 impl Sdf
 {
+    pub fn transformed(sdf: Sdf, transform: Mat4) -> Sdf
+    {
+        let scale = [Dir3::unit_x(), Dir3::unit_y(), Dir3::unit_z()].iter()
+            .map(|axis| transform.mul_direction(*axis).magnitude())
+            .fold(Scalar::INFINITY, Scalar::min);
+
+        Sdf::Transformed{ sdf: Box::new(sdf), transform: Box::new(transform), inverse: Box::new(transform.inverted()), scale }
+    }
+
     pub fn distance(&self, pos: Point3) -> Scalar
     {
         match self
@@ -48,6 +80,40 @@ impl Sdf
             {
                 sdf.distance(pos).abs() - radius
             },
+            Sdf::Box{ min, max } => rounded_box(pos, *min, *max, 0.0).0,
+            Sdf::RoundedBox{ min, max, radius } => rounded_box(pos, *min, *max, *radius).0,
+            Sdf::Torus{ center, axis, major_radius, minor_radius } => torus(pos, *center, *axis, *major_radius, *minor_radius).0,
+            Sdf::Cone{ a, b, radius_a, radius_b } => cone(pos, *a, *b, *radius_a, *radius_b).0,
+            Sdf::Plane{ point, normal } => (pos - point).dot(*normal),
+            Sdf::Cylinder{ a, b, radius } => cone(pos, *a, *b, *radius, *radius).0,
+            Sdf::Intersection{ members } =>
+            {
+                members.iter()
+                    .map(|m| m.distance(pos))
+                    .fold(Scalar::NEG_INFINITY, Scalar::max)
+            },
+            Sdf::Subtraction{ sdf, subtracted } =>
+            {
+                sdf.distance(pos).max(-subtracted.distance(pos))
+            },
+            Sdf::SmoothUnion{ members, radius } =>
+            {
+                members.iter()
+                    .map(|m| m.distance(pos))
+                    .reduce(|a, b| smooth_min(a, b, *radius).0)
+                    .unwrap_or(Scalar::INFINITY)
+            },
+            Sdf::SmoothSubtraction{ sdf, subtracted, radius } =>
+            {
+                // A smooth maximum of the inside of one, and
+                // the outside of the other
+
+                -smooth_min(-sdf.distance(pos), subtracted.distance(pos), *radius).0
+            },
+            Sdf::Transformed{ sdf, inverse, scale, .. } =>
+            {
+                sdf.distance(inverse.mul_point(pos)) * scale
+            },
         }
     }
 
@@ -99,10 +165,243 @@ impl Sdf
 
                 distance.signum() * normal
             },
+            Sdf::Box{ min, max } => rounded_box(pos, *min, *max, 0.0).1,
+            Sdf::RoundedBox{ min, max, radius } => rounded_box(pos, *min, *max, *radius).1,
+            Sdf::Torus{ center, axis, major_radius, minor_radius } => torus(pos, *center, *axis, *major_radius, *minor_radius).1,
+            Sdf::Cone{ a, b, radius_a, radius_b } => cone(pos, *a, *b, *radius_a, *radius_b).1,
+            Sdf::Plane{ normal, .. } => *normal,
+            Sdf::Cylinder{ a, b, radius } => cone(pos, *a, *b, *radius, *radius).1,
+            Sdf::Intersection{ members } =>
+            {
+                let mut distance = Scalar::MIN;
+                let mut normal = Dir3::new(1.0, 0.0, 0.0);
+
+                for m in members.iter()
+                {
+                    let m_dist = m.distance(pos);
+
+                    if m_dist > distance
+                    {
+                        distance = m_dist;
+                        normal = m.normal(pos);
+                    }
+                }
+
+                normal
+            },
+            Sdf::Subtraction{ sdf, subtracted } =>
+            {
+                if sdf.distance(pos) >= -subtracted.distance(pos)
+                {
+                    sdf.normal(pos)
+                }
+                else
+                {
+                    -subtracted.normal(pos)
+                }
+            },
+            Sdf::SmoothUnion{ members, radius } =>
+            {
+                // The gradient is blended with the same weights
+
+                let mut result: Option<(Scalar, Dir3)> = None;
+
+                for m in members.iter()
+                {
+                    let (m_dist, m_normal) = (m.distance(pos), m.normal(pos));
+
+                    result = Some(match result
+                    {
+                        Some((distance, normal)) =>
+                        {
+                            let (blended, h) = smooth_min(distance, m_dist, *radius);
+                            (blended, (h * normal) + ((1.0 - h) * m_normal))
+                        },
+                        None => (m_dist, m_normal),
+                    });
+                }
+
+                unit_or_x(result.map(|(_, normal)| normal).unwrap_or_else(Dir3::zero))
+            },
+            Sdf::SmoothSubtraction{ sdf, subtracted, radius } =>
+            {
+                let (_, h) = smooth_min(-sdf.distance(pos), subtracted.distance(pos), *radius);
+
+                unit_or_x((h * sdf.normal(pos)) - ((1.0 - h) * subtracted.normal(pos)))
+            },
+            Sdf::Transformed{ sdf, inverse, .. } =>
+            {
+                // Normals are transformed by the inverse transpose
+
+                let normal = sdf.normal(inverse.mul_point(pos));
+
+                unit_or_x((inverse.transposed() * Vec4::from_direction(normal)).xyz())
+            },
         }
     }
 }
 
+/// Polynomial smooth minimum, blending within the radius. Also
+/// returns the first distance's weight in the blend, which is
+/// also its weight in the blended gradient.
+fn smooth_min(a: Scalar, b: Scalar, radius: Scalar) -> (Scalar, Scalar)
+{
+    if radius <= 0.0
+    {
+        return if a <= b { (a, 1.0) } else { (b, 0.0) };
+    }
+
+    let h = (0.5 + (0.5 * (b - a) / radius)).clamp(0.0, 1.0);
+
+    ((h * a) + ((1.0 - h) * b) - (radius * h * (1.0 - h)), h)
+}
+
+/// Blended gradients aren't unit length, and
+/// can cancel out where surfaces meet
+fn unit_or_x(dir: Dir3) -> Dir3
+{
+    let length = dir.magnitude();
+
+    if length > 0.0 { dir / length } else { Dir3::unit_x() }
+}
+
+// The primitives below return their distance and its gradient
+// together, as they share most of the work.
+// See https://iquilezles.org/articles/distfunctions/
+
+fn rounded_box(pos: Point3, min: Point3, max: Point3, radius: Scalar) -> (Scalar, Dir3)
+{
+    let center = 0.5 * (min + max);
+    let half = (0.5 * (max - min)).map(|c| c.abs());
+    let radius = radius.clamp(0.0, half.reduce_partial_min());
+
+    let o = pos - center;
+    let q = o.map(|c| c.abs()) - (half - Point3::broadcast(radius));
+    let outside = Point3::partial_max(q, Point3::zero());
+    let outside_distance = outside.magnitude();
+
+    if outside_distance > 0.0
+    {
+        (outside_distance - radius, (outside / outside_distance) * o.map(|c| if c < 0.0 { -1.0 } else { 1.0 }))
+    }
+    else
+    {
+        // Inside, the closest face is the one with the largest q
+
+        let axis = if (q.x >= q.y) && (q.x >= q.z) { 0 } else if q.y >= q.z { 1 } else { 2 };
+        let mut normal = Dir3::zero();
+        normal[axis] = if o[axis] < 0.0 { -1.0 } else { 1.0 };
+
+        (q[axis] - radius, normal)
+    }
+}
+
+fn torus(pos: Point3, center: Point3, axis: Dir3, major_radius: Scalar, minor_radius: Scalar) -> (Scalar, Dir3)
+{
+    revolved(pos, center, axis, |across, along|
+    {
+        let (x, y) = (across - major_radius, along);
+        let length = ((x * x) + (y * y)).sqrt();
+
+        if length > 0.0
+        {
+            (length - minor_radius, x / length, y / length)
+        }
+        else
+        {
+            (-minor_radius, 1.0, 0.0)
+        }
+    })
+}
+
+fn cone(pos: Point3, a: Point3, b: Point3, radius_a: Scalar, radius_b: Scalar) -> (Scalar, Dir3)
+{
+    let offset = b - a;
+    let length = offset.magnitude();
+    let axis = if length > 0.0 { offset / length } else { Dir3::unit_z() };
+    let (radius_a, radius_b) = (radius_a.abs(), radius_b.abs());
+
+    revolved(pos, a, axis, |across, along|
+    {
+        convex_polygon((across, along), &[(-radius_a, 0.0), (radius_a, 0.0), (radius_b, length), (-radius_b, length)])
+    })
+}
+
+/// A solid of revolution, from its profile's distance and gradient,
+/// as a function of the distance from the axis and along it
+fn revolved<F>(pos: Point3, origin: Point3, axis: Dir3, profile: F) -> (Scalar, Dir3)
+    where F: Fn(Scalar, Scalar) -> (Scalar, Scalar, Scalar)
+{
+    let o = pos - origin;
+    let along = o.dot(axis);
+    let radial = o - (along * axis);
+    let across = radial.magnitude();
+
+    let radial_dir = if across > 0.0
+    {
+        radial / across
+    }
+    else
+    {
+        let other = if axis.x.abs() < 0.9 { Dir3::unit_x() } else { Dir3::unit_y() };
+        axis.cross(other).normalized()
+    };
+
+    let (distance, d_across, d_along) = profile(across, along);
+
+    (distance, (d_across * radial_dir) + (d_along * axis))
+}
+
+/// Signed distance to a convex polygon, with its
+/// vertices counter-clockwise, and its gradient
+fn convex_polygon(point: (Scalar, Scalar), vertices: &[(Scalar, Scalar)]) -> (Scalar, Scalar, Scalar)
+{
+    let mut closest = (Scalar::MAX, 0.0, 0.0);
+    let mut outward = (1.0, 0.0);
+    let mut inside = true;
+
+    for (&(ax, ay), &(bx, by)) in vertices.iter().zip(vertices.iter().cycle().skip(1))
+    {
+        let (ex, ey) = (bx - ax, by - ay);
+        let (px, py) = (point.0 - ax, point.1 - ay);
+        let edge_squared = (ex * ex) + (ey * ey);
+
+        // Edges can be empty, e.g. at a cone's point
+
+        if edge_squared <= 0.0
+        {
+            continue;
+        }
+
+        let h = (((px * ex) + (py * ey)) / edge_squared).clamp(0.0, 1.0);
+        let (dx, dy) = (px - (h * ex), py - (h * ey));
+        let distance_squared = (dx * dx) + (dy * dy);
+
+        if distance_squared < closest.0
+        {
+            closest = (distance_squared, dx, dy);
+            outward = (ey / edge_squared.sqrt(), -ex / edge_squared.sqrt());
+        }
+
+        if ((ex * py) - (ey * px)) < 0.0
+        {
+            inside = false;
+        }
+    }
+
+    let distance = closest.0.sqrt();
+    let sign = if inside { -1.0 } else { 1.0 };
+
+    if distance > 0.0
+    {
+        (sign * distance, sign * closest.1 / distance, sign * closest.2 / distance)
+    }
+    else
+    {
+        (0.0, outward.0, outward.1)
+    }
+}
+
 impl Surface for Sdf
 {
     fn closest_intersection_in_range<'r>(&self, ray: &'r Ray, range: &RayRange) -> Option<SurfaceIntersection<'r>>
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+
+use crate::geom::Aabb;
+use crate::geom::sdf::Sdf;
+use crate::math::Scalar;
+use crate::vec::{Dir3, Point3};
+
+/// Limits the grid to about a billion cells
+const MAX_RESOLUTION: usize = 1024;
+
+type Cell = (i64, i64, i64);
+
+impl Sdf
+{
+    /// A box that contains the whole surface
+    pub fn bounds(&self) -> Aabb
+    {
+        match self
+        {
+            Sdf::Sphere{ center, radius } =>
+            {
+                let r = Point3::broadcast(radius.abs());
+                Aabb::new(center - r, center + r)
+            },
+            Sdf::Capsule{ a, b, radius } =>
+            {
+                let r = Point3::broadcast(radius.abs());
+                Aabb::new(Point3::partial_min(*a, *b) - r, Point3::partial_max(*a, *b) + r)
+            },
+            Sdf::Union{ members } =>
+            {
+                members.iter()
+                    .map(|m| m.bounds())
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap_or_else(|| Aabb::new(Point3::zero(), Point3::zero()))
+            },
+            Sdf::Annular{ sdf, radius } =>
+            {
+                let bounds = sdf.bounds();
+                let r = Point3::broadcast(radius.abs());
+                Aabb::new(bounds.min - r, bounds.max + r)
+            },
+            Sdf::Box{ min, max } | Sdf::RoundedBox{ min, max, .. } =>
+            {
+                Aabb::new(Point3::partial_min(*min, *max), Point3::partial_max(*min, *max))
+            },
+            Sdf::Torus{ center, axis, major_radius, minor_radius } =>
+            {
+                let bounds = disc_bounds(*center, *axis, major_radius.abs());
+                let r = Point3::broadcast(minor_radius.abs());
+                Aabb::new(bounds.min - r, bounds.max + r)
+            },
+            Sdf::Cone{ a, b, radius_a, radius_b } =>
+            {
+                disc_bounds(*a, b - a, radius_a.abs()).union(&disc_bounds(*b, b - a, radius_b.abs()))
+            },
+            Sdf::Plane{ .. } =>
+            {
+                Aabb::new(Point3::broadcast(-Scalar::INFINITY), Point3::broadcast(Scalar::INFINITY))
+            },
+            Sdf::Cylinder{ a, b, radius } =>
+            {
+                disc_bounds(*a, b - a, radius.abs()).union(&disc_bounds(*b, b - a, radius.abs()))
+            },
+            Sdf::Intersection{ members } =>
+            {
+                members.iter()
+                    .map(|m| m.bounds())
+                    .reduce(|a, b|
+                    {
+                        // Empty intersections are kept valid, but tiny
+
+                        let min = Point3::partial_max(a.min, b.min);
+                        Aabb::new(min, Point3::partial_max(min, Point3::partial_min(a.max, b.max)))
+                    })
+                    .unwrap_or_else(|| Aabb::new(Point3::broadcast(-Scalar::INFINITY), Point3::broadcast(Scalar::INFINITY)))
+            },
+            Sdf::Subtraction{ sdf, .. } | Sdf::SmoothSubtraction{ sdf, .. } =>
+            {
+                sdf.bounds()
+            },
+            Sdf::SmoothUnion{ members, radius } =>
+            {
+                // Blending pulls the surface out by
+                // up to a quarter of the radius
+
+                let bounds = members.iter()
+                    .map(|m| m.bounds())
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap_or_else(|| Aabb::new(Point3::zero(), Point3::zero()));
+
+                let r = Point3::broadcast(0.25 * radius.max(0.0));
+                Aabb::new(bounds.min - r, bounds.max + r)
+            },
+            Sdf::Transformed{ sdf, transform, .. } =>
+            {
+                // Around the transformed corners - unless
+                // it's unbounded, which it stays
+
+                if !sdf.is_bounded()
+                {
+                    return Aabb::new(Point3::broadcast(-Scalar::INFINITY), Point3::broadcast(Scalar::INFINITY));
+                }
+
+                let bounds = sdf.bounds();
+
+                (0..8)
+                    .map(|corner|
+                    {
+                        let point = Point3::new(
+                            if (corner & 1) != 0 { bounds.max.x } else { bounds.min.x },
+                            if (corner & 2) != 0 { bounds.max.y } else { bounds.min.y },
+                            if (corner & 4) != 0 { bounds.max.z } else { bounds.min.z });
+
+                        let point = transform.mul_point(point);
+                        Aabb::new(point, point)
+                    })
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap()
+            },
+        }
+    }
+
+    /// If the bounds are finite - so the surface can be tessellated
+    pub fn is_bounded(&self) -> bool
+    {
+        let bounds = self.bounds();
+
+        (bounds.max - bounds.min).reduce_partial_max().is_finite()
+    }
+
+    /// Polygonizes the surface with surface nets - a form of dual
+    /// contouring. The resolution is the number of grid cells
+    /// along the longest side of the bounds.
+    ///
+    /// Only cells near the surface are visited - blocks of cells
+    /// further from the surface than their size are skipped - so
+    /// the cost grows with the surface area, not the volume.
+    ///
+    /// Triangles are wound counter-clockwise when seen from outside.
+    pub fn tessellate(&self, resolution: usize) -> Vec<[Point3; 3]>
+    {
+        let resolution = resolution.clamp(1, MAX_RESOLUTION);
+        let bounds = self.bounds();
+        let extent = bounds.max - bounds.min;
+        let cell_size = extent.reduce_partial_max().max(Scalar::MIN_POSITIVE) / (resolution as Scalar);
+
+        // Leave a cell of margin on every side, so the
+        // surface never touches the edge of the grid
+
+        let origin = bounds.min - Point3::broadcast(cell_size);
+        let dims = [0, 1, 2].map(|axis| ((extent[axis] / cell_size).ceil() as i64) + 2);
+
+        let mut grid = Grid { sdf: self, origin, cell_size, distances: HashMap::new() };
+
+        // Find the cells the surface passes through
+
+        let block_size = (dims.iter().copied().max().unwrap_or(1).max(1) as u64).next_power_of_two() as i64;
+        let mut cells = Vec::new();
+
+        grid.find_surface_cells((0, 0, 0), block_size, &dims, &mut cells);
+
+        // Place one vertex in each of them
+
+        let vertices = cells.iter()
+            .map(|cell| (*cell, grid.cell_vertex(*cell)))
+            .collect::<HashMap<_, _>>();
+
+        // Each grid edge the surface crosses is shared by four
+        // surface cells - join their vertices into a quad. Every
+        // edge is visited once, from the cell at its minimum corner.
+
+        let mut result = Vec::new();
+
+        for &(x, y, z) in cells.iter()
+        {
+            let start = grid.distance((x, y, z));
+
+            for axis in 0..3
+            {
+                let end_corner = offset((x, y, z), axis, 1);
+
+                if (start < 0.0) == (grid.distance(end_corner) < 0.0)
+                {
+                    continue;
+                }
+
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+                let quad = [
+                    (x, y, z),
+                    offset((x, y, z), u, -1),
+                    offset(offset((x, y, z), u, -1), v, -1),
+                    offset((x, y, z), v, -1),
+                ];
+
+                let quad = match (vertices.get(&quad[0]), vertices.get(&quad[1]), vertices.get(&quad[2]), vertices.get(&quad[3]))
+                {
+                    (Some(a), Some(b), Some(c), Some(d)) => [*a, *b, *c, *d],
+                    _ => continue,
+                };
+
+                // The quad's winding depends on which way
+                // the surface crosses the edge
+
+                let quad = if start < 0.0 { quad } else { [quad[0], quad[3], quad[2], quad[1]] };
+
+                result.push([quad[0], quad[1], quad[2]]);
+                result.push([quad[0], quad[2], quad[3]]);
+            }
+        }
+
+        result
+    }
+}
+
+struct Grid<'a>
+{
+    sdf: &'a Sdf,
+    origin: Point3,
+    cell_size: Scalar,
+    /// Distances are evaluated at grid points on demand,
+    /// as each is shared by up to eight cells
+    distances: HashMap<Cell, Scalar>,
+}
+
+impl Grid<'_>
+{
+    fn point(&self, (x, y, z): Cell) -> Point3
+    {
+        self.origin + Point3::new(x as Scalar, y as Scalar, z as Scalar) * self.cell_size
+    }
+
+    fn distance(&mut self, corner: Cell) -> Scalar
+    {
+        if let Some(distance) = self.distances.get(&corner)
+        {
+            return *distance;
+        }
+
+        let distance = self.sdf.distance(self.point(corner));
+        self.distances.insert(corner, distance);
+        distance
+    }
+
+    fn find_surface_cells(&mut self, (x, y, z): Cell, size: i64, dims: &[i64; 3], cells: &mut Vec<Cell>)
+    {
+        if (x >= dims[0]) || (y >= dims[1]) || (z >= dims[2])
+        {
+            return;
+        }
+
+        if size == 1
+        {
+            let corners = (0..8).map(|i| self.distance((x + (i & 1), y + ((i >> 1) & 1), z + ((i >> 2) & 1)))).collect::<Vec<_>>();
+
+            if corners.iter().any(|d| *d < 0.0) && corners.iter().any(|d| *d >= 0.0)
+            {
+                cells.push((x, y, z));
+            }
+            return;
+        }
+
+        // The distance bounds how close the surface can be,
+        // so blocks where it's further than the distance from
+        // the center to the corners can't contain any surface
+
+        let half = (size as Scalar) * self.cell_size * 0.5;
+        let center = self.point((x, y, z)) + Point3::broadcast(half);
+
+        if self.sdf.distance(center).abs() > half * (3.0 as Scalar).sqrt()
+        {
+            return;
+        }
+
+        let size = size / 2;
+
+        for i in 0..8
+        {
+            self.find_surface_cells((x + (i & 1) * size, y + ((i >> 1) & 1) * size, z + ((i >> 2) & 1) * size), size, dims, cells);
+        }
+    }
+
+    /// The average of where the surface crosses the cell's
+    /// edges, moved onto the surface along the gradient
+    fn cell_vertex(&mut self, (x, y, z): Cell) -> Point3
+    {
+        let mut sum = Point3::zero();
+        let mut count = 0;
+
+        for axis in 0..3
+        {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+            for i in 0..4
+            {
+                let start = offset(offset((x, y, z), u, i & 1), v, (i >> 1) & 1);
+                let end = offset(start, axis, 1);
+
+                let (d0, d1) = (self.distance(start), self.distance(end));
+
+                if (d0 < 0.0) != (d1 < 0.0)
+                {
+                    let t = d0 / (d0 - d1);
+                    sum += self.point(start) + (self.point(end) - self.point(start)) * t;
+                    count += 1;
+                }
+            }
+        }
+
+        let min = self.point((x, y, z));
+        let max = self.point((x + 1, y + 1, z + 1));
+
+        let mut vertex = if count > 0 { sum / (count as Scalar) } else { (min + max) * 0.5 };
+
+        for _ in 0..2
+        {
+            let projected = vertex - self.sdf.normal(vertex) * self.sdf.distance(vertex);
+
+            if projected.iter().all(|c| c.is_finite())
+            {
+                vertex = projected;
+            }
+        }
+
+        // Keep the vertex in its cell, so sharp
+        // features can't fold the mesh over
+
+        Point3::partial_max(min, Point3::partial_min(max, vertex))
+    }
+}
+
+fn offset(cell: Cell, axis: usize, amount: i64) -> Cell
+{
+    match axis
+    {
+        0 => (cell.0 + amount, cell.1, cell.2),
+        1 => (cell.0, cell.1 + amount, cell.2),
+        _ => (cell.0, cell.1, cell.2 + amount),
+    }
+}
+
+/// Bounds of a disc around the axis, which needn't be normalized
+fn disc_bounds(center: Point3, axis: Dir3, radius: Scalar) -> Aabb
+{
+    let length = axis.magnitude();
+
+    let extent = if length > 0.0
+    {
+        (axis / length).map(|c| (1.0 - (c * c)).max(0.0).sqrt() * radius)
+    }
+    else
+    {
+        Point3::broadcast(radius)
+    };
+
+    Aabb::new(center - extent, center + extent)
+}
@@ -1,4 +1,4 @@
-use crate::geom::{BoundingSurface, Disc, SampleableSurface, Surface, Volume};
+use crate::geom::{Aabb, BoundingSurface, Disc, SampleableSurface, Surface, Volume};
 use crate::intersection::SurfaceIntersection;
 use crate::math::{Scalar, ScalarConsts};
 use crate::ray::{Ray, RayRange};
@@ -127,6 +127,13 @@ impl Surface for Sphere
 
         None
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        let radius = Point3::broadcast(self.radius.abs());
+
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
 }
 
 impl BoundingSurface for Sphere
@@ -0,0 +1,104 @@
+use crate::geom::{Aabb, AabbBuilder, Surface};
+use crate::intersection::SurfaceIntersection;
+use crate::math::Scalar;
+use crate::ray::{Ray, RayRange};
+use crate::vec::{Mat4, Point3, Vec4};
+
+#[derive(Clone)]
+pub struct Transformed
+{
+    surface: Box<dyn Surface>,
+    matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+}
+
+impl Transformed
+{
+    pub fn new(surface: Box<dyn Surface>, matrix: Mat4) -> Self
+    {
+        let inverse = matrix.inverted();
+        let normal_matrix = inverse.transposed();
+
+        Transformed { surface, matrix, inverse, normal_matrix }
+    }
+}
+
+impl Surface for Transformed
+{
+    fn closest_intersection_in_range<'r>(&self, ray: &'r Ray, range: &RayRange) -> Option<SurfaceIntersection<'r>>
+    {
+        // Transform the ray into the surface's local space.
+        // The local direction is normalized, so distances
+        // need to be scaled between the two spaces.
+
+        let local_source = self.inverse.mul_point(ray.source);
+        let local_dir = self.inverse.mul_direction(ray.dir);
+        let scale = local_dir.magnitude();
+
+        if scale == 0.0
+        {
+            return None;
+        }
+
+        let local_ray = Ray::new(local_source, local_dir / scale);
+        let local_range = RayRange::new(range.min() * scale, range.max() * scale);
+
+        let local = self.surface.closest_intersection_in_range(&local_ray, &local_range)?;
+
+        let location = self.matrix.mul_point(local.location());
+        let normal: crate::vec::Dir3 = (self.normal_matrix * Vec4::from_direction(local.normal)).xyz().normalized();
+
+        Some(SurfaceIntersection
+        {
+            ray,
+            distance: local.distance / scale,
+            location: Some(location),
+            face: local.face,
+            normal,
+            texture_coords: Some(local.texture_coords()),
+            opt_color: local.opt_color,
+            tangents: local.tangents.map(|t| t.map(|t| self.matrix.mul_direction(t))),
+        })
+    }
+
+    fn is_medium(&self) -> bool
+    {
+        self.surface.is_medium()
+    }
+
+    fn transmittance(&self, ray: &Ray, range: &RayRange) -> Scalar
+    {
+        let local_dir = self.inverse.mul_direction(ray.dir);
+        let scale = local_dir.magnitude();
+
+        if scale == 0.0
+        {
+            return 1.0;
+        }
+
+        let local_ray = Ray::new(self.inverse.mul_point(ray.source), local_dir / scale);
+        let local_range = RayRange::new(range.min() * scale, range.max() * scale);
+
+        self.surface.transmittance(&local_ray, &local_range)
+    }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        // Bounds of the transformed corners
+
+        let local = self.surface.bounding_aabb()?;
+        let mut builder = AabbBuilder::new();
+
+        for corner in 0..8
+        {
+            let x = if (corner & 1) == 0 { local.min.x } else { local.max.x };
+            let y = if (corner & 2) == 0 { local.min.y } else { local.max.y };
+            let z = if (corner & 4) == 0 { local.min.z } else { local.max.z };
+
+            builder.add_point(self.matrix.mul_point(Point3::new(x, y, z)));
+        }
+
+        Some(builder.build())
+    }
+}
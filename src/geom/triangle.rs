@@ -1,6 +1,6 @@
 use crate::color::LinearRGB;
 use crate::math::EPSILON;
-use crate::vec::{Point3, Mat4};
+use crate::vec::{Dir3, Point3, Mat4, Vec4};
 use crate::geom::{Aabb, AabbBoundedSurface, Surface};
 use crate::intersection::SurfaceIntersection;
 use crate::ray::{Ray, RayRange};
@@ -15,13 +15,21 @@ pub struct Triangle
     pub t1: Point3,
     pub t2: Point3,
     pub opt_colors: Option<[LinearRGB;3]>,
+    /// Interpolated for shading, so meshes look smooth
+    pub opt_normals: Option<[Dir3;3]>,
 }
 
 impl Triangle
 {
     pub fn new(p0: Point3, p1: Point3, p2: Point3, t0: Point3, t1: Point3, t2: Point3, opt_colors: Option<[LinearRGB;3]>) -> Self
     {
-        Triangle { p0, p1, p2, t0, t1, t2, opt_colors }
+        Triangle { p0, p1, p2, t0, t1, t2, opt_colors, opt_normals: None }
+    }
+
+    pub fn with_normals(mut self, opt_normals: Option<[Dir3;3]>) -> Self
+    {
+        self.opt_normals = opt_normals;
+        self
     }
 
     pub fn transformed(&self, matrix: &Mat4) -> Self
@@ -35,6 +43,12 @@ impl Triangle
             t1: self.t1,
             t2: self.t2,
             opt_colors: self.opt_colors,
+            opt_normals: self.opt_normals.map(|normals|
+            {
+                let normal_matrix = matrix.inverted().transposed();
+
+                normals.map(|n| (normal_matrix * Vec4::from_direction(n)).xyz().normalized())
+            }),
         }
     }
 }
@@ -104,14 +118,41 @@ impl Surface for Triangle
                     + vertex_colors[2].multiplied_by_scalar_inc_alpha(v)
             });
 
-            return Some(ray.new_intersection_with_texture_coords(
+            let mut intersection = ray.new_intersection_with_texture_coords(
                 t,
                 edge1.cross(edge2).normalized(),
                 texture_coords,
                 opt_color
-            ));
+            );
+
+            if let Some(normals) = &self.opt_normals
+            {
+                intersection = intersection.with_shading_normal((normals[0] * w) + (normals[1] * u) + (normals[2] * v));
+            }
+
+            // Tangents follow the texture coordinates across
+            // the triangle - there are none if they're degenerate
+
+            let duv1 = self.t1 - self.t0;
+            let duv2 = self.t2 - self.t0;
+            let det = duv1.x * duv2.y - duv2.x * duv1.y;
+
+            if det.abs() < EPSILON
+            {
+                return Some(intersection);
+            }
+
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) / det;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / det;
+
+            return Some(intersection.with_tangents(tangent, bitangent));
         }
 
         return None;
     }
+
+    fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        Some(self.get_bounding_aabb())
+    }
 }
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::bsdf::Bsdf;
+use crate::camera::Camera;
+use crate::math::{Scalar, ScalarConsts};
+use crate::radiance::{CellKey, ViewGrid};
+use crate::sample::Sampler;
+use crate::vec::{Dir3, Point3};
+
+/// Cells are coarser than the radiance cache's, as
+/// each needs many samples to learn its distribution
+const CELLS_ACROSS_VIEW: Scalar = 16.0;
+
+/// Samples a cell collects before its distribution is
+/// first used - each later refinement waits for twice
+/// as many, up to the maximum
+const FIRST_REFINEMENT: u32 = 256;
+const MAX_REFINEMENT: u32 = 65536;
+
+/// Quadtree nodes holding more than this fraction of the
+/// total energy are split - if they've had enough samples
+/// that it's not just from one or two lucky paths
+const SPLIT_FRACTION: Scalar = 0.01;
+const SPLIT_SAMPLES: Scalar = 16.0;
+
+const MAX_DEPTH: u32 = 12;
+
+/// Fraction of directions sampled from the guide,
+/// rather than the BSDF
+const GUIDE_FRACTION: Scalar = 0.5;
+
+const NUM_SHARDS: usize = 64;
+
+#[derive(Clone, Copy)]
+struct Node
+{
+    energy: Scalar,
+    samples: Scalar,
+    /// Index of the first of four children
+    children: Option<usize>,
+}
+
+/// A distribution over directions, as a quadtree over the
+/// square that directions map to with equal area - z is
+/// the first coordinate, and the angle around it the second
+#[derive(Clone)]
+pub struct DirectionTree
+{
+    nodes: Vec<Node>,
+}
+
+impl DirectionTree
+{
+    /// Starts with a few levels, so learning isn't too coarse
+    fn new() -> Self
+    {
+        let mut nodes = vec![Node { energy: 0.0, samples: 0.0, children: None }];
+
+        for _ in 0..2
+        {
+            let leaves = nodes.iter().enumerate().filter(|(_, n)| n.children.is_none()).map(|(i, _)| i).collect::<Vec<_>>();
+
+            for leaf in leaves
+            {
+                nodes[leaf].children = Some(nodes.len());
+                nodes.extend([Node { energy: 0.0, samples: 0.0, children: None }; 4]);
+            }
+        }
+
+        DirectionTree { nodes }
+    }
+
+    fn add(&mut self, dir: Dir3, value: Scalar)
+    {
+        let mut point = to_square(dir);
+        let mut index = 0;
+
+        loop
+        {
+            self.nodes[index].energy += value;
+            self.nodes[index].samples += 1.0;
+
+            match self.nodes[index].children
+            {
+                Some(first) =>
+                {
+                    let (quadrant, inner) = quadrant(point);
+                    index = first + quadrant;
+                    point = inner;
+                },
+                None => return,
+            }
+        }
+    }
+
+    /// Descends the tree, choosing children in proportion to their
+    /// energy, and returns the direction and its density by solid angle
+    fn sample(&self, sampler: &mut Sampler) -> (Dir3, Scalar)
+    {
+        let mut origin = (0.0, 0.0);
+        let mut size = 1.0;
+        let mut density = 1.0;
+        let mut node = self.nodes[0];
+
+        while let Some(first) = node.children
+        {
+            let children = &self.nodes[first..first + 4];
+            let total = children.iter().map(|c| c.energy).sum::<Scalar>();
+
+            if total <= 0.0
+            {
+                break;
+            }
+
+            // Rounding can leave the choice past the end,
+            // so it defaults to the last child with energy
+
+            let mut choice = sampler.uniform_scalar_unit() * total;
+            let mut chosen = children.iter().rposition(|c| c.energy > 0.0).unwrap_or(3);
+
+            for (i, child) in children.iter().enumerate()
+            {
+                if (choice < child.energy) && (child.energy > 0.0)
+                {
+                    chosen = i;
+                    break;
+                }
+
+                choice -= child.energy;
+            }
+
+            density *= 4.0 * children[chosen].energy / total;
+            size *= 0.5;
+            origin.0 += if (chosen & 1) != 0 { size } else { 0.0 };
+            origin.1 += if (chosen & 2) != 0 { size } else { 0.0 };
+            node = children[chosen];
+        }
+
+        let point = (origin.0 + (size * sampler.uniform_scalar_unit()), origin.1 + (size * sampler.uniform_scalar_unit()));
+
+        (from_square(point), density * 0.25 * ScalarConsts::FRAC_1_PI)
+    }
+
+    fn pdf(&self, dir: Dir3) -> Scalar
+    {
+        let mut point = to_square(dir);
+        let mut density = 1.0;
+        let mut node = self.nodes[0];
+
+        while let Some(first) = node.children
+        {
+            let children = &self.nodes[first..first + 4];
+            let total = children.iter().map(|c| c.energy).sum::<Scalar>();
+
+            if total <= 0.0
+            {
+                break;
+            }
+
+            let (quadrant, inner) = quadrant(point);
+
+            density *= 4.0 * children[quadrant].energy / total;
+            point = inner;
+            node = children[quadrant];
+        }
+
+        density * 0.25 * ScalarConsts::FRAC_1_PI
+    }
+
+    /// A new, empty, tree whose leaves are split
+    /// where this one has collected the most energy
+    fn refined(&self) -> DirectionTree
+    {
+        let total = self.nodes[0].energy;
+        let mut result = DirectionTree { nodes: vec![Node { energy: 0.0, samples: 0.0, children: None }] };
+
+        // Nodes to consider splitting - in the new tree, then
+        // in this one (if it has one there), with its energy
+
+        let mut stack = vec![(0, Some(0), total, self.nodes[0].samples, 0)];
+
+        while let Some((new_index, old_index, energy, samples, depth)) = stack.pop()
+        {
+            if (depth >= MAX_DEPTH) || (energy <= total * SPLIT_FRACTION) || (samples < SPLIT_SAMPLES)
+            {
+                continue;
+            }
+
+            let first = result.nodes.len();
+            result.nodes[new_index].children = Some(first);
+            result.nodes.extend([Node { energy: 0.0, samples: 0.0, children: None }; 4]);
+
+            let old_children = old_index.and_then(|i: usize| self.nodes[i].children);
+
+            for child in 0..4
+            {
+                // Energy is spread evenly below the old tree's leaves
+
+                let (old_child, child_energy, child_samples) = match old_children
+                {
+                    Some(old_first) => (Some(old_first + child), self.nodes[old_first + child].energy, self.nodes[old_first + child].samples),
+                    None => (None, 0.25 * energy, 0.25 * samples),
+                };
+
+                stack.push((first + child, old_child, child_energy, child_samples, depth + 1));
+            }
+        }
+
+        result
+    }
+}
+
+/// Equal-area mapping from directions to the unit square
+fn to_square(dir: Dir3) -> (Scalar, Scalar)
+{
+    let u = (0.5 * (dir.z + 1.0)).clamp(0.0, 1.0);
+    let angle = dir.y.atan2(dir.x) * 0.5 * ScalarConsts::FRAC_1_PI;
+    let v = if angle < 0.0 { angle + 1.0 } else { angle };
+
+    (u, v.clamp(0.0, 1.0))
+}
+
+fn from_square(point: (Scalar, Scalar)) -> Dir3
+{
+    let z = (2.0 * point.0) - 1.0;
+    let r = (1.0 - (z * z)).max(0.0).sqrt();
+    let angle = 2.0 * ScalarConsts::PI * point.1;
+
+    Dir3::new(r * angle.cos(), r * angle.sin(), z)
+}
+
+/// The quadrant containing the point, and the point within it
+fn quadrant(point: (Scalar, Scalar)) -> (usize, (Scalar, Scalar))
+{
+    let (x, y) = (2.0 * point.0, 2.0 * point.1);
+    let (qx, qy) = (if x >= 1.0 { 1 } else { 0 }, if y >= 1.0 { 1 } else { 0 });
+
+    (qx + (2 * qy), ((x - qx as Scalar).min(1.0), (y - qy as Scalar).min(1.0)))
+}
+
+struct Cell
+{
+    /// Collecting samples
+    learning: DirectionTree,
+    /// Learnt from earlier samples
+    guide: Option<Arc<DirectionTree>>,
+    samples: u32,
+    next_refinement: u32,
+}
+
+/// Path guiding - learns the distribution of light arriving in
+/// each cell of a world-space grid, from the paths traced through
+/// it, as quadtrees over directions (as in "Practical Path Guiding").
+/// Once a cell has enough samples, diffuse bounces there sample
+/// some directions from it, rather than from the BSDF alone. Each
+/// time a cell's samples double, its tree is refined, and what's
+/// been learnt replaces the guide. It's built for each render.
+#[derive(Clone)]
+pub struct PathGuide
+{
+    grid: ViewGrid,
+    shards: Arc<Vec<RwLock<HashMap<CellKey, GuideCell>>>>,
+}
+
+/// A cell of the guide, which can be kept while a path is
+/// traced, so it only needs to be found once for each vertex
+#[derive(Clone)]
+pub struct GuideCell
+{
+    cell: Arc<Mutex<Cell>>,
+}
+
+impl PathGuide
+{
+    pub fn new(camera: &Camera) -> Self
+    {
+        let shards = Arc::new((0..NUM_SHARDS).map(|_| RwLock::new(HashMap::new())).collect());
+
+        PathGuide { grid: ViewGrid::new(camera, CELLS_ACROSS_VIEW), shards }
+    }
+
+    pub fn cell(&self, location: Point3, normal: Dir3) -> GuideCell
+    {
+        let (key, _) = self.grid.cell(location, normal);
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = &self.shards[(hasher.finish() as usize) % NUM_SHARDS];
+
+        if let Some(cell) = shard.read().unwrap().get(&key)
+        {
+            return cell.clone();
+        }
+
+        shard.write().unwrap().entry(key)
+            .or_insert_with(|| GuideCell { cell: Arc::new(Mutex::new(Cell { learning: DirectionTree::new(), guide: None, samples: 0, next_refinement: FIRST_REFINEMENT })) })
+            .clone()
+    }
+}
+
+impl GuideCell
+{
+    /// Mixes the cell's guide, once it has one, with the BSDF
+    pub fn guided_bsdf(&self, bsdf: Box<dyn Bsdf>) -> Box<dyn Bsdf>
+    {
+        let guide = self.cell.lock().unwrap().guide.clone();
+
+        match guide
+        {
+            Some(guide) => Box::new(GuidedBsdf { bsdf, guide }),
+            None => bsdf,
+        }
+    }
+
+    /// Adds the light found arriving from the direction,
+    /// divided by the probability of sampling it
+    pub fn add_sample(&self, dir: Dir3, value: Scalar)
+    {
+        let value = if value.is_finite() { value.max(0.0) } else { 0.0 };
+
+        let mut cell = self.cell.lock().unwrap();
+
+        cell.learning.add(dir, value);
+        cell.samples += 1;
+
+        if cell.samples >= cell.next_refinement
+        {
+            if cell.learning.nodes[0].energy > 0.0
+            {
+                let refined = cell.learning.refined();
+                cell.guide = Some(Arc::new(std::mem::replace(&mut cell.learning, refined)));
+            }
+
+            cell.samples = 0;
+            cell.next_refinement = (2 * cell.next_refinement).min(MAX_REFINEMENT);
+        }
+    }
+}
+
+/// One-sample mixture of a BSDF and a learnt guide
+struct GuidedBsdf
+{
+    bsdf: Box<dyn Bsdf>,
+    guide: Arc<DirectionTree>,
+}
+
+impl Bsdf for GuidedBsdf
+{
+    fn generate_random_sample_dir_and_calc_pdf(&self, sampler: &mut Sampler) -> (Dir3, Scalar)
+    {
+        let dir = if sampler.uniform_scalar_unit() < GUIDE_FRACTION
+        {
+            self.guide.sample(sampler).0
+        }
+        else
+        {
+            self.bsdf.generate_random_sample_dir_and_calc_pdf(sampler).0
+        };
+
+        (dir, self.calculate_pdf_for_dir(dir))
+    }
+
+    fn calculate_pdf_for_dir(&self, dir: Dir3) -> Scalar
+    {
+        (GUIDE_FRACTION * self.guide.pdf(dir)) + ((1.0 - GUIDE_FRACTION) * self.bsdf.calculate_pdf_for_dir(dir))
+    }
+
+    fn reflectance(&self, output_dir: Dir3) -> Scalar
+    {
+        self.bsdf.reflectance(output_dir)
+    }
+}
+
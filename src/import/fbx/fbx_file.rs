@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::color::SRGB;
+use crate::import::ImportError;
+use crate::import::fbx::parser::{self, Node, Property};
+use crate::math::Scalar;
+use crate::vec::{Mat4, Point3, Vec3};
+
+pub type ObjectId = i64;
+
+#[derive(Debug, Clone)]
+pub struct PolygonVertex
+{
+    pub position_index: usize,
+    pub texture_coords: Option<Point3>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Polygon
+{
+    pub material_slot: usize,
+    pub vertices: Vec<PolygonVertex>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Geometry
+{
+    pub positions: Vec<Point3>,
+    pub polygons: Vec<Polygon>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Model
+{
+    pub id: ObjectId,
+    pub name: String,
+    pub parent: Option<ObjectId>,
+    pub local_transform: Mat4,
+    /// Applied to the model's geometry, but not its children
+    pub geometric_transform: Mat4,
+    pub geometry: Option<ObjectId>,
+    /// The material for each slot, in connection order
+    pub materials: Vec<ObjectId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Texture
+{
+    pub filename: String,
+    /// Binary files can embed the image
+    pub content: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Material
+{
+    pub name: String,
+    pub diffuse: SRGB,
+    pub diffuse_map: Option<Texture>,
+    pub emissive: SRGB,
+    pub opacity: Scalar,
+}
+
+#[derive(Debug, Clone)]
+pub struct FbxFile
+{
+    /// Converts from the file's axis system to Y-up
+    pub axis_transform: Mat4,
+    /// Parents are listed before their children
+    pub models: Vec<Model>,
+    pub geometries: HashMap<ObjectId, Geometry>,
+    pub materials: HashMap<ObjectId, Material>,
+}
+
+pub fn parse(contents: &[u8], filename: &str) -> Result<FbxFile, ImportError>
+{
+    let error = |msg: &str| ImportError(format!("FBX Error: {}: {}", filename, msg));
+
+    let nodes = parser::parse(contents, filename)?;
+    let top_level = |name: &str| nodes.iter().find(|n| n.name == name);
+
+    let objects = top_level("Objects").ok_or_else(|| error("No Objects section"))?;
+
+    // Connections are (child, parent, property)
+
+    let connections = top_level("Connections")
+        .map(|c| c.children_named("C")
+            .filter_map(|c| Some((c.property(1)?.as_i64()?, c.property(2)?.as_i64()?, c.property(3).and_then(|p| p.as_str()).map(|s| s.to_owned()))))
+            .collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let by_id = objects.children.iter()
+        .filter_map(|o| Some((o.property(0)?.as_i64()?, o)))
+        .collect::<HashMap<_, _>>();
+
+    let kind_of = |id: ObjectId| by_id.get(&id).map(|o| o.name.as_str());
+
+    let children_of = |parent: ObjectId, kind: &'static str| connections.iter()
+        .filter(move |(_, p, _)| *p == parent)
+        .filter(move |(c, _, _)| kind_of(*c) == Some(kind))
+        .map(|(c, _, property)| (*c, property.clone()));
+
+    let mut geometries = HashMap::new();
+
+    for node in objects.children_named("Geometry")
+    {
+        if let (Some(id), Some("Mesh")) = (node.property(0).and_then(|p| p.as_i64()), node.property(2).and_then(|p| p.as_str()))
+        {
+            geometries.insert(id, parse_geometry(node).map_err(|e| error(&format!("{}: {}", object_name(node), e)))?);
+        }
+    }
+
+    let mut materials = HashMap::new();
+
+    for node in objects.children_named("Material")
+    {
+        if let Some(id) = node.property(0).and_then(|p| p.as_i64())
+        {
+            let diffuse_map = children_of(id, "Texture")
+                .find(|(_, property)| matches!(property.as_deref(), Some("DiffuseColor") | Some("Diffuse")))
+                .and_then(|(texture, _)|
+                {
+                    // Embedded images are stored in a connected video
+
+                    let content = children_of(texture, "Video").find_map(|(video, _)| match by_id[&video].child("Content")?.property(0)?
+                    {
+                        Property::Raw(data) if !data.is_empty() => Some(data.clone()),
+                        _ => None,
+                    });
+
+                    parse_texture(by_id[&texture], content)
+                });
+
+            materials.insert(id, parse_material(node, diffuse_map));
+        }
+    }
+
+    // Models form a tree under the root (id 0)
+
+    let mut models = Vec::new();
+    let mut pending = vec![(0, None)];
+
+    while let Some((parent_id, parent)) = pending.pop()
+    {
+        for (id, _) in children_of(parent_id, "Model").collect::<Vec<_>>()
+        {
+            let node = by_id[&id];
+
+            models.push(Model
+            {
+                id,
+                name: object_name(node),
+                parent,
+                local_transform: local_transform(node),
+                geometric_transform: geometric_transform(node),
+                geometry: children_of(id, "Geometry").map(|(g, _)| g).find(|g| geometries.contains_key(g)),
+                materials: children_of(id, "Material").map(|(m, _)| m).collect(),
+            });
+
+            pending.push((id, Some(id)));
+        }
+    }
+
+    let axis_transform = top_level("GlobalSettings")
+        .map(axis_transform)
+        .unwrap_or_else(Mat4::identity);
+
+    Ok(FbxFile { axis_transform, models, geometries, materials })
+}
+
+/// The name from e.g. `Model::Cube`
+fn object_name(node: &Node) -> String
+{
+    let name = node.property(1).and_then(|p| p.as_str()).unwrap_or_default();
+
+    name.split_once("::").map(|(_, n)| n).unwrap_or(name).to_owned()
+}
+
+fn parse_geometry(node: &Node) -> Result<Geometry, String>
+{
+    let positions = node.child_f64_vec("Vertices")
+        .chunks_exact(3)
+        .map(|p| Point3::new(p[0], p[1], p[2]))
+        .collect::<Vec<_>>();
+
+    // The last index of each polygon is stored as -(index + 1),
+    // which is its bitwise not - that can't overflow
+
+    let mut polygons = Vec::new();
+    let mut current = Vec::new();
+
+    for index in node.child_i64_vec("PolygonVertexIndex")
+    {
+        let position_index = if index < 0 { !index } else { index };
+
+        let position_index = match usize::try_from(position_index)
+        {
+            Ok(i) if i < positions.len() => i,
+            _ => return Err(format!("Vertex index {} is larger than the vertex count {}", position_index, positions.len())),
+        };
+
+        current.push(PolygonVertex { position_index, texture_coords: None });
+
+        if index < 0
+        {
+            polygons.push(Polygon { material_slot: 0, vertices: std::mem::take(&mut current) });
+        }
+    }
+
+    if let Some(uvs) = node.child("LayerElementUV")
+    {
+        let values = uvs.child_f64_vec("UV");
+        let indices = uvs.child_i64_vec("UVIndex");
+        let by_control_point = matches!(uvs.child_str("MappingInformationType"), Some("ByVertice") | Some("ByVertex") | Some("ByControlPoint"));
+        let indexed = uvs.child_str("ReferenceInformationType") != Some("Direct");
+
+        let mut polygon_vertex = 0;
+
+        for polygon in polygons.iter_mut()
+        {
+            for vertex in polygon.vertices.iter_mut()
+            {
+                let i = if by_control_point { vertex.position_index } else { polygon_vertex };
+                let i = if indexed { indices.get(i).and_then(|i| usize::try_from(*i).ok()) } else { Some(i) };
+
+                // FBX texture coordinates start from the bottom left.
+                // Invalid indexes are skipped.
+
+                if let Some(i) = i.and_then(|i| i.checked_mul(2)).filter(|i| i.checked_add(1).is_some_and(|v| v < values.len()))
+                {
+                    vertex.texture_coords = Some(Point3::new(values[i], 1.0 - values[i + 1], 0.0));
+                }
+
+                polygon_vertex += 1;
+            }
+        }
+    }
+
+    if let Some(materials) = node.child("LayerElementMaterial")
+    {
+        let slots = materials.child_i64_vec("Materials");
+
+        if materials.child_str("MappingInformationType") == Some("AllSame")
+        {
+            let slot = slots.first().copied().unwrap_or(0).max(0) as usize;
+            polygons.iter_mut().for_each(|p| p.material_slot = slot);
+        }
+        else
+        {
+            polygons.iter_mut().zip(slots.iter()).for_each(|(p, slot)| p.material_slot = (*slot).max(0) as usize);
+        }
+    }
+
+    Ok(Geometry { positions, polygons })
+}
+
+fn parse_texture(node: &Node, content: Option<Vec<u8>>) -> Option<Texture>
+{
+    let filename = node.child_str("RelativeFilename")
+        .filter(|f| !f.is_empty())
+        .or_else(|| node.child_str("FileName"))?
+        .to_owned();
+
+    Some(Texture { filename, content })
+}
+
+fn parse_material(node: &Node, diffuse_map: Option<Texture>) -> Material
+{
+    let color = |name: &str, factor: &str, default: Scalar|
+    {
+        let factor = node.property70_f64_vec(factor).and_then(|f| f.first().copied()).unwrap_or(1.0);
+
+        match node.property70_f64_vec(name)
+        {
+            Some(c) if c.len() >= 3 => SRGB::new(c[0] * factor, c[1] * factor, c[2] * factor, 1.0),
+            _ => SRGB::new(default, default, default, 1.0),
+        }
+    };
+
+    let opacity = node.property70_f64_vec("Opacity")
+        .or_else(|| node.property70_f64_vec("TransparencyFactor").map(|t| t.iter().map(|t| 1.0 - t).collect()))
+        .and_then(|o| o.first().copied())
+        .unwrap_or(1.0);
+
+    Material
+    {
+        name: object_name(node),
+        diffuse: color("DiffuseColor", "DiffuseFactor", 0.8),
+        diffuse_map,
+        emissive: color("EmissiveColor", "EmissiveFactor", 0.0),
+        opacity,
+    }
+}
+
+fn vector(node: &Node, name: &str, default: Scalar) -> Vec3
+{
+    match node.property70_f64_vec(name)
+    {
+        Some(v) if v.len() >= 3 => Vec3::new(v[0], v[1], v[2]),
+        _ => Vec3::new(default, default, default),
+    }
+}
+
+/// Euler rotation, in degrees, for an FBX rotation order -
+/// e.g. for XYZ, the rotation around X is applied first
+fn rotation(angles: Vec3, order: i64) -> Mat4
+{
+    let axes = match order
+    {
+        1 => "XZY",
+        2 => "YZX",
+        3 => "YXZ",
+        4 => "ZXY",
+        5 => "ZYX",
+        _ => "XYZ",
+    };
+
+    axes.chars().fold(Mat4::identity(), |m, axis|
+    {
+        let (angle, axis) = match axis
+        {
+            'X' => (angles.x, Vec3::new(1.0, 0.0, 0.0)),
+            'Y' => (angles.y, Vec3::new(0.0, 1.0, 0.0)),
+            _ => (angles.z, Vec3::new(0.0, 0.0, 1.0)),
+        };
+
+        Mat4::rotation_3d(angle.to_radians(), axis) * m
+    })
+}
+
+/// The full FBX transform, including the pivots
+/// and pre/post rotations that DCC tools export
+fn local_transform(node: &Node) -> Mat4
+{
+    let order = node.property70("RotationOrder").and_then(|p| p.first()).and_then(|p| p.as_i64()).unwrap_or(0);
+
+    let translation = Mat4::translation_3d(vector(node, "Lcl Translation", 0.0));
+    let rotation_offset = Mat4::translation_3d(vector(node, "RotationOffset", 0.0));
+    let rotation_pivot = Mat4::translation_3d(vector(node, "RotationPivot", 0.0));
+    let pre_rotation = rotation(vector(node, "PreRotation", 0.0), 0);
+    let rotation = self::rotation(vector(node, "Lcl Rotation", 0.0), order);
+    let post_rotation = self::rotation(vector(node, "PostRotation", 0.0), 0);
+    let scaling_offset = Mat4::translation_3d(vector(node, "ScalingOffset", 0.0));
+    let scaling_pivot = Mat4::translation_3d(vector(node, "ScalingPivot", 0.0));
+    let scaling = Mat4::scaling_3d(vector(node, "Lcl Scaling", 1.0));
+
+    translation * rotation_offset * rotation_pivot * pre_rotation * rotation * post_rotation.inverted() * rotation_pivot.inverted()
+        * scaling_offset * scaling_pivot * scaling * scaling_pivot.inverted()
+}
+
+fn geometric_transform(node: &Node) -> Mat4
+{
+    Mat4::translation_3d(vector(node, "GeometricTranslation", 0.0))
+        * rotation(vector(node, "GeometricRotation", 0.0), 0)
+        * Mat4::scaling_3d(vector(node, "GeometricScaling", 1.0))
+}
+
+/// Maps the file's up, front and coordinate
+/// axes onto beam's Y, Z and X axes
+fn axis_transform(settings: &Node) -> Mat4
+{
+    let setting = |name: &str, default: i64| settings.property70(name).and_then(|p| p.first()).and_then(Property::as_i64).unwrap_or(default);
+
+    let axis = |axis: i64, sign: i64|
+    {
+        let mut row = [0.0; 4];
+        row[axis.clamp(0, 2) as usize] = if sign < 0 { -1.0 } else { 1.0 };
+        row
+    };
+
+    Mat4::from_row_arrays([
+        axis(setting("CoordAxis", 0), setting("CoordAxisSign", 1)),
+        axis(setting("UpAxis", 1), setting("UpAxisSign", 1)),
+        axis(setting("FrontAxis", 2), setting("FrontAxisSign", 1)),
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
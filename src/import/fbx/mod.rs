@@ -0,0 +1,214 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::color::SRGB;
+use crate::desc::edit::transform::TransformStage;
+use crate::desc::edit::{CleanupReport, Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::geom::{Aabb, AabbBuilder};
+use crate::import::{FileSystemContext, ImportError, cleanup_mesh, print_cleanup_report};
+use crate::import::image::Image;
+use crate::indexed::{MaterialIndex, TransformIndex};
+use crate::vec::{Mat4, Point3};
+
+pub mod fbx_file;
+mod parser;
+
+/// Imports the static meshes from an FBX file (binary or
+/// ASCII, version 7), scaled to fit the destination box.
+/// Each model's material slots become separate objects.
+pub fn import_fbx_file(path: &str, context: &FileSystemContext, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
+{
+    let filename = context.path_to_filename(path);
+    let (contents, sub_context) = context.load_binary_file(path)?;
+    let fbx_file = fbx_file::parse(&contents, path)?;
+
+    let mut scene_transform = Transform::new();
+    scene_transform.stages.push(TransformStage::Matrix(fbx_file.axis_transform));
+    let scene_transform_index = scene.collection.push_named(scene_transform.clone(), filename);
+
+    let mut resources = ResourceLoader::new(sub_context);
+    let mut transforms: HashMap<fbx_file::ObjectId, (TransformIndex, Mat4)> = HashMap::new();
+    let mut aabb_builder = AabbBuilder::new();
+    let mut num_meshes = 0;
+    let mut cleanup = CleanupReport::default();
+
+    for model in fbx_file.models.iter()
+    {
+        let (parent_index, parent_matrix) = model.parent
+            .and_then(|p| transforms.get(&p).copied())
+            .unwrap_or((scene_transform_index, fbx_file.axis_transform));
+
+        let transform_index = scene.collection.push_named(
+            Transform { pre: None, stages: vec![TransformStage::Matrix(model.local_transform)], post: Some(parent_index) },
+            model.name.clone());
+        let matrix = parent_matrix * model.local_transform;
+
+        transforms.insert(model.id, (transform_index, matrix));
+
+        if let Some(geometry) = model.geometry.and_then(|g| fbx_file.geometries.get(&g))
+        {
+            let geom_matrix = matrix * model.geometric_transform;
+            // Only the slots that are used - the
+            // file's slot numbers may be arbitrarily large
+
+            let slots = geometry.polygons.iter().map(|p| p.material_slot).collect::<BTreeSet<_>>();
+
+            for slot in slots.iter().copied()
+            {
+                let triangles = cleanup_mesh(slot_triangles(geometry, slot), &mut cleanup);
+
+                if triangles.is_empty()
+                {
+                    continue;
+                }
+
+                for triangle in triangles.iter()
+                {
+                    let [a, b, c] = &triangle.vertices;
+                    aabb_builder.add_triangle(geom_matrix.mul_point(a.location), geom_matrix.mul_point(b.location), geom_matrix.mul_point(c.location));
+                }
+
+                let material = resources.load_material(model.materials.get(slot).and_then(|m| fbx_file.materials.get_key_value(m)), scene)?;
+
+                let mut geom_transform = Transform::new();
+                geom_transform.stages.push(TransformStage::Matrix(model.geometric_transform));
+                geom_transform.post = Some(transform_index);
+
+                let name = if slots.len() == 1 { model.name.clone() } else { format!("{}.{}", model.name, slot + 1) };
+
+                let geom = scene.collection.push_named(Geom::Mesh { triangles, transform: geom_transform, lod: None }, name.clone());
+                scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, name);
+                num_meshes += 1;
+            }
+        }
+    }
+
+    if num_meshes == 0
+    {
+        return Err(ImportError(format!("FBX Error: {}: No meshes found", path)));
+    }
+
+    print_cleanup_report("FBX", &cleanup);
+
+    let aabb = aabb_builder.build();
+
+    scene_transform.stages.push(TransformStage::ShiftAndScale
+        {
+            from: crate::desc::edit::geom::Aabb{ min: aabb.min, max: aabb.max },
+            to: crate::desc::edit::geom::Aabb{ min: destination.min, max: destination.max },
+            maintain_aspect: true,
+        });
+    scene.collection.update_value(scene_transform_index, scene_transform);
+
+    Ok(())
+}
+
+/// The polygons using a material slot, as triangle fans
+fn slot_triangles(geometry: &fbx_file::Geometry, slot: usize) -> Vec<Triangle>
+{
+    let convert_vertex = |vertex: &fbx_file::PolygonVertex|
+    {
+        let location = geometry.positions[vertex.position_index];
+
+        TriangleVertex { location, texture_coords: vertex.texture_coords.unwrap_or(location), opt_color: None, opt_normal: None }
+    };
+
+    geometry.polygons.iter()
+        .filter(|p| p.material_slot == slot)
+        .flat_map(|p| (1..p.vertices.len().saturating_sub(1)).map(move |i| [&p.vertices[0], &p.vertices[i], &p.vertices[i + 1]]))
+        .map(|[a, b, c]| Triangle { vertices: [convert_vertex(a), convert_vertex(b), convert_vertex(c)] })
+        .collect()
+}
+
+struct ResourceLoader
+{
+    fs_context: FileSystemContext,
+    imported_materials: HashMap<Option<fbx_file::ObjectId>, MaterialIndex>,
+    imported_images: HashMap<String, Image>,
+}
+
+impl ResourceLoader
+{
+    fn new(fs_context: FileSystemContext) -> Self
+    {
+        ResourceLoader
+        {
+            fs_context,
+            imported_materials: HashMap::new(),
+            imported_images: HashMap::new(),
+        }
+    }
+
+    fn load_material(&mut self, material: Option<(&fbx_file::ObjectId, &fbx_file::Material)>, scene: &mut Scene) -> Result<MaterialIndex, ImportError>
+    {
+        let key = material.map(|(id, _)| *id);
+
+        if let Some(result) = self.imported_materials.get(&key)
+        {
+            return Ok(*result);
+        }
+
+        let result = match material.map(|(_, mtl)| mtl)
+        {
+            Some(mtl) if (mtl.emissive.r + mtl.emissive.g + mtl.emissive.b) > 0.0 =>
+            {
+                let texture = scene.collection.push_named(Texture::Solid(mtl.emissive.into()), mtl.name.clone());
+                scene.collection.push_named(Material::Emit { texture }, mtl.name.clone())
+            },
+            Some(mtl) if mtl.opacity < 1.0 =>
+            {
+                scene.collection.push_named(Material::Dielectric { ior: 1.5, tint: None }, mtl.name.clone())
+            },
+            Some(mtl) =>
+            {
+                let texture = match &mtl.diffuse_map
+                {
+                    Some(map) =>
+                    {
+                        let image = self.load_image(map)?;
+                        let image = scene.collection.push_named(image, map.filename.clone());
+                        let scale = Point3::new(1.0, 1.0, 1.0);
+                        let rotate = 0.0;
+                        let translate = Point3::new(0.0, 0.0, 0.0);
+
+                        // Textured diffuse colors are multiplied by the
+                        // texture, and are usually left at their default
+
+                        scene.collection.push_named(Texture::Image { base_color: SRGB::new(1.0, 1.0, 1.0, 1.0).into(), image, scale, rotate, translate }, mtl.name.clone())
+                    },
+                    None => scene.collection.push_named(Texture::Solid(mtl.diffuse.into()), mtl.name.clone()),
+                };
+
+                scene.collection.push_named(Material::Diffuse { texture, normal_texture: None }, mtl.name.clone())
+            },
+            None =>
+            {
+                let texture = scene.collection.push(Texture::Solid(SRGB::new(1.0, 1.0, 1.0, 1.0).into()));
+                scene.collection.push(Material::Diffuse { texture, normal_texture: None })
+            },
+        };
+
+        self.imported_materials.insert(key, result);
+        Ok(result)
+    }
+
+    fn load_image(&mut self, texture: &fbx_file::Texture) -> Result<Image, ImportError>
+    {
+        if let Some(existing) = self.imported_images.get(&texture.filename)
+        {
+            return Ok(existing.clone());
+        }
+
+        // Prefer an embedded image, then the path relative to the
+        // FBX file, then just the filename next to the FBX file
+
+        let image = match &texture.content
+        {
+            Some(content) => crate::import::image::import_image_from_memory(content.clone())?,
+            None => crate::import::image::import_image(&texture.filename, &mut self.fs_context)
+                .or_else(|_| crate::import::image::import_image(&self.fs_context.path_to_filename(&texture.filename.replace('\\', "/")), &mut self.fs_context))?,
+        };
+
+        self.imported_images.insert(texture.filename.clone(), image.clone());
+        Ok(image)
+    }
+}
@@ -0,0 +1,532 @@
+use std::convert::TryInto;
+use std::io::Read;
+
+use crate::import::ImportError;
+
+/// A node in an FBX document - both the binary
+/// and ASCII formats are trees of these
+#[derive(Debug, Clone)]
+pub struct Node
+{
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Property
+{
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Raw(Vec<u8>),
+    IntArray(Vec<i64>),
+    FloatArray(Vec<f64>),
+}
+
+impl Property
+{
+    pub fn as_i64(&self) -> Option<i64>
+    {
+        match self
+        {
+            Property::Int(i) => Some(*i),
+            Property::Float(f) => Some(*f as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        match self
+        {
+            Property::Int(i) => Some(*i as f64),
+            Property::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str>
+    {
+        match self
+        {
+            Property::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64_vec(&self) -> Vec<f64>
+    {
+        match self
+        {
+            Property::FloatArray(v) => v.clone(),
+            Property::IntArray(v) => v.iter().map(|i| *i as f64).collect(),
+            other => other.as_f64().into_iter().collect(),
+        }
+    }
+
+    pub fn to_i64_vec(&self) -> Vec<i64>
+    {
+        match self
+        {
+            Property::IntArray(v) => v.clone(),
+            Property::FloatArray(v) => v.iter().map(|f| *f as i64).collect(),
+            other => other.as_i64().into_iter().collect(),
+        }
+    }
+}
+
+impl Node
+{
+    pub fn child(&self, name: &str) -> Option<&Node>
+    {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node> + 'a
+    {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    pub fn property(&self, index: usize) -> Option<&Property>
+    {
+        self.properties.get(index)
+    }
+
+    /// The first property of a child, e.g. `MappingInformationType`
+    pub fn child_str(&self, name: &str) -> Option<&str>
+    {
+        self.child(name).and_then(|c| c.property(0)).and_then(|p| p.as_str())
+    }
+
+    pub fn child_f64_vec(&self, name: &str) -> Vec<f64>
+    {
+        self.child(name).and_then(|c| c.property(0)).map(|p| p.to_f64_vec()).unwrap_or_default()
+    }
+
+    pub fn child_i64_vec(&self, name: &str) -> Vec<i64>
+    {
+        self.child(name).and_then(|c| c.property(0)).map(|p| p.to_i64_vec()).unwrap_or_default()
+    }
+
+    /// The values of an entry in the `Properties70` table,
+    /// e.g. `P: "Lcl Translation", "Lcl Translation", "", "A", 1, 2, 3`
+    pub fn property70(&self, name: &str) -> Option<&[Property]>
+    {
+        self.child("Properties70")?
+            .children.iter()
+            .find(|p| p.property(0).and_then(|p| p.as_str()) == Some(name))
+            .map(|p| &p.properties[4.min(p.properties.len())..])
+    }
+
+    pub fn property70_f64_vec(&self, name: &str) -> Option<Vec<f64>>
+    {
+        self.property70(name).map(|values| values.iter().filter_map(|v| v.as_f64()).collect())
+    }
+}
+
+const BINARY_MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+
+/// Deeper nesting than any real file uses
+/// is rejected, rather than overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+/// Parses an FBX document, in either format,
+/// into its top level nodes
+pub fn parse(contents: &[u8], filename: &str) -> Result<Vec<Node>, ImportError>
+{
+    let error = |msg: String| ImportError(format!("FBX Error: {}: {}", filename, msg));
+
+    if contents.starts_with(BINARY_MAGIC)
+    {
+        parse_binary(contents).map_err(error)
+    }
+    else
+    {
+        parse_ascii(&String::from_utf8_lossy(contents)).map_err(error)
+    }
+}
+
+struct BinaryReader<'a>
+{
+    contents: &'a [u8],
+    pos: usize,
+    /// Versions from 7500 use 64-bit node offsets
+    wide: bool,
+}
+
+impl<'a> BinaryReader<'a>
+{
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String>
+    {
+        if self.pos + len > self.contents.len()
+        {
+            return Err(format!("Unexpected end of file at offset {}", self.pos));
+        }
+
+        let result = &self.contents[self.pos..(self.pos + len)];
+        self.pos += len;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, String>
+    {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String>
+    {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String>
+    {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn offset(&mut self) -> Result<usize, String>
+    {
+        if self.wide { Ok(self.u64()? as usize) } else { Ok(self.u32()? as usize) }
+    }
+
+    /// Reads a node, or `None` at the null
+    /// record that ends a list of nodes
+    fn node(&mut self, depth: usize) -> Result<Option<Node>, String>
+    {
+        let start = self.pos;
+        let end = self.offset()?;
+        let num_properties = self.offset()?;
+        let _properties_len = self.offset()?;
+        let name_len = self.u8()? as usize;
+
+        if end == 0
+        {
+            return Ok(None);
+        }
+
+        // The end must move forwards, or
+        // the reader would never finish
+
+        if end <= start || end > self.contents.len()
+        {
+            return Err(format!("Invalid node end offset {} at offset {}", end, start));
+        }
+
+        if depth >= MAX_DEPTH
+        {
+            return Err(format!("Nodes are nested too deeply at offset {}", start));
+        }
+
+        let name = String::from_utf8_lossy(self.bytes(name_len)?).into_owned();
+
+        let properties = (0..num_properties)
+            .map(|_| self.property())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut children = Vec::new();
+
+        while self.pos < end
+        {
+            match self.node(depth + 1)?
+            {
+                Some(child) => children.push(child),
+                None => break,
+            }
+        }
+
+        self.pos = end;
+
+        Ok(Some(Node { name, properties, children }))
+    }
+
+    fn property(&mut self) -> Result<Property, String>
+    {
+        let type_code = self.u8()?;
+
+        let number = |reader: &mut Self, len: usize| -> Result<[u8; 8], String>
+        {
+            let mut result = [0; 8];
+            result[..len].copy_from_slice(reader.bytes(len)?);
+            Ok(result)
+        };
+
+        Ok(match type_code
+        {
+            b'C' => Property::Int(self.u8()? as i64),
+            b'Y' => Property::Int(i16::from_le_bytes(number(self, 2)?[..2].try_into().unwrap()) as i64),
+            b'I' => Property::Int(i32::from_le_bytes(number(self, 4)?[..4].try_into().unwrap()) as i64),
+            b'L' => Property::Int(i64::from_le_bytes(number(self, 8)?)),
+            b'F' => Property::Float(f32::from_le_bytes(number(self, 4)?[..4].try_into().unwrap()) as f64),
+            b'D' => Property::Float(f64::from_le_bytes(number(self, 8)?)),
+            b'S' =>
+            {
+                let len = self.u32()? as usize;
+
+                // Object names are stored as "Name\0\x01Class" -
+                // convert to the ASCII format's "Class::Name"
+
+                let s = String::from_utf8_lossy(self.bytes(len)?).into_owned();
+
+                match s.split_once("\0\x01")
+                {
+                    Some((name, class)) => Property::Str(format!("{}::{}", class, name)),
+                    None => Property::Str(s),
+                }
+            },
+            b'R' =>
+            {
+                let len = self.u32()? as usize;
+                Property::Raw(self.bytes(len)?.to_vec())
+            },
+            b'b' | b'i' | b'l' | b'f' | b'd' =>
+            {
+                let count = self.u32()? as usize;
+                let encoding = self.u32()?;
+                let len = self.u32()? as usize;
+                let data = self.bytes(len)?;
+
+                let data = match encoding
+                {
+                    0 => data.to_vec(),
+                    1 =>
+                    {
+                        let mut result = Vec::new();
+                        flate2::read::ZlibDecoder::new(data).read_to_end(&mut result)
+                            .map_err(|e| format!("Could not decompress array: {}", e))?;
+                        result
+                    },
+                    other => return Err(format!("Unknown array encoding {}", other)),
+                };
+
+                let element_size = match type_code { b'b' => 1, b'i' | b'f' => 4, _ => 8 };
+
+                if data.len() < count * element_size
+                {
+                    return Err("Truncated array".to_owned());
+                }
+
+                let elements = data.chunks_exact(element_size).take(count);
+
+                match type_code
+                {
+                    b'b' => Property::IntArray(elements.map(|e| e[0] as i64).collect()),
+                    b'i' => Property::IntArray(elements.map(|e| i32::from_le_bytes(e.try_into().unwrap()) as i64).collect()),
+                    b'l' => Property::IntArray(elements.map(|e| i64::from_le_bytes(e.try_into().unwrap())).collect()),
+                    b'f' => Property::FloatArray(elements.map(|e| f32::from_le_bytes(e.try_into().unwrap()) as f64).collect()),
+                    _ => Property::FloatArray(elements.map(|e| f64::from_le_bytes(e.try_into().unwrap())).collect()),
+                }
+            },
+            other => return Err(format!("Unknown property type {:?}", other as char)),
+        })
+    }
+}
+
+fn parse_binary(contents: &[u8]) -> Result<Vec<Node>, String>
+{
+    // The magic is followed by two unknown
+    // bytes and the version number
+
+    let mut reader = BinaryReader { contents, pos: BINARY_MAGIC.len() + 2, wide: false };
+    let version = reader.u32()?;
+    reader.wide = version >= 7500;
+
+    let mut result = Vec::new();
+
+    while let Some(node) = reader.node(0)?
+    {
+        result.push(node);
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token
+{
+    Word(String),
+    Str(String),
+    Num(String),
+    Punct(char),
+}
+
+fn tokenize_ascii(contents: &str) -> Vec<Token>
+{
+    let mut result = Vec::new();
+    let mut chars = contents.chars().peekable();
+
+    while let Some(&c) = chars.peek()
+    {
+        if c.is_whitespace()
+        {
+            chars.next();
+        }
+        else if c == ';'
+        {
+            while chars.next_if(|&c| c != '\n').is_some() {}
+        }
+        else if c == '"'
+        {
+            chars.next();
+
+            let mut s = String::new();
+
+            while let Some(c) = chars.next_if(|&c| c != '"')
+            {
+                s.push(c);
+            }
+
+            chars.next();
+            result.push(Token::Str(s));
+        }
+        else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.'
+        {
+            let mut s = String::new();
+
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+            {
+                s.push(c);
+            }
+
+            result.push(Token::Num(s));
+        }
+        else if c.is_alphanumeric() || c == '_' || c == '|'
+        {
+            let mut s = String::new();
+
+            while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || matches!(c, '_' | '|'))
+            {
+                s.push(c);
+            }
+
+            result.push(Token::Word(s));
+        }
+        else
+        {
+            result.push(Token::Punct(c));
+            chars.next();
+        }
+    }
+
+    result
+}
+
+fn parse_ascii(contents: &str) -> Result<Vec<Node>, String>
+{
+    let tokens = tokenize_ascii(contents);
+    let mut pos = 0;
+    let mut result = Vec::new();
+
+    while pos < tokens.len()
+    {
+        result.push(parse_ascii_node(&tokens, &mut pos, 0)?);
+    }
+
+    Ok(result)
+}
+
+/// Parses `Name: value, value, ... { children }`
+fn parse_ascii_node(tokens: &[Token], pos: &mut usize, depth: usize) -> Result<Node, String>
+{
+    if depth >= MAX_DEPTH
+    {
+        return Err("Nodes are nested too deeply".to_owned());
+    }
+
+    let name = match (tokens.get(*pos), tokens.get(*pos + 1))
+    {
+        (Some(Token::Word(name)), Some(Token::Punct(':'))) => name.clone(),
+        (token, _) => return Err(format!("Expected a node name but got {:?}", token)),
+    };
+
+    *pos += 2;
+
+    let is_next_node = |pos: usize| matches!((tokens.get(pos), tokens.get(pos + 1)), (Some(Token::Word(_)), Some(Token::Punct(':'))));
+
+    let mut properties = Vec::new();
+    let mut is_array = false;
+
+    loop
+    {
+        match tokens.get(*pos)
+        {
+            // Arrays are written as "*count { a: values }"
+            Some(Token::Punct('*')) =>
+            {
+                is_array = true;
+                *pos += 2;
+            },
+            Some(Token::Num(n)) =>
+            {
+                properties.push(match n.parse::<i64>()
+                {
+                    Ok(i) => Property::Int(i),
+                    Err(_) => Property::Float(n.parse::<f64>().map_err(|_| format!("Invalid number {:?}", n))?),
+                });
+                *pos += 1;
+            },
+            Some(Token::Str(s)) =>
+            {
+                properties.push(Property::Str(s.clone()));
+                *pos += 1;
+            },
+            Some(Token::Word(w)) if !is_next_node(*pos) =>
+            {
+                properties.push(Property::Str(w.clone()));
+                *pos += 1;
+            },
+            _ => break,
+        }
+
+        if tokens.get(*pos) == Some(&Token::Punct(','))
+        {
+            *pos += 1;
+        }
+        else
+        {
+            break;
+        }
+    }
+
+    let mut children = Vec::new();
+
+    if tokens.get(*pos) == Some(&Token::Punct('{'))
+    {
+        *pos += 1;
+
+        while tokens.get(*pos) != Some(&Token::Punct('}'))
+        {
+            if *pos >= tokens.len()
+            {
+                return Err(format!("Missing closing brace for {}", name));
+            }
+
+            children.push(parse_ascii_node(tokens, pos, depth + 1)?);
+        }
+
+        *pos += 1;
+    }
+
+    if is_array
+    {
+        // Replace the "a" child with an array property
+
+        let values = children.iter()
+            .find(|c| c.name == "a")
+            .map(|c| c.properties.clone())
+            .unwrap_or_default();
+
+        let array = if values.iter().all(|v| matches!(v, Property::Int(_)))
+        {
+            Property::IntArray(values.iter().filter_map(|v| v.as_i64()).collect())
+        }
+        else
+        {
+            Property::FloatArray(values.iter().filter_map(|v| v.as_f64()).collect())
+        };
+
+        properties = vec![array];
+        children.clear();
+    }
+
+    Ok(Node { name, properties, children })
+}
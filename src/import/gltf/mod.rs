@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod tests;
+
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryInto;
@@ -5,24 +8,31 @@ use std::rc::Rc;
 
 use crate::color::{SRGB, LinearRGB};
 use crate::desc::edit::transform::TransformStage;
-use crate::desc::edit::{Scene, Triangle, TriangleVertex, Geom, Transform, Object, Material, Texture, Color};
+use crate::desc::edit::{CleanupReport, Scene, Triangle, TriangleVertex, Geom, Transform, Object, Opacity, Material, Texture, Color};
 use crate::geom::{Aabb, AabbBuilder};
 use crate::import;
 use crate::import::{FileSystemContext, ImportError};
+use crate::import::image::ColorSpace;
 use crate::indexed::{ImageIndex, MaterialIndex, TextureIndex, TransformIndex};
 use crate::math::Scalar;
 use crate::vec::{Point3, Mat4, Vec3, Quaternion};
 
-pub fn import_gltf_file(path: &str, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
+pub fn import_gltf_file(path: &str, context: &FileSystemContext, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
 {
-    let context = FileSystemContext::new();
     let filename = context.path_to_filename(path);
     let (contents, sub_context) = context.load_binary_file(path)?;
     let file_state = ScopedState::new(scene, sub_context, filename);
 
-    let gltf = gltf::Gltf::from_slice(&contents)
+    let mut gltf = gltf::Gltf::from_slice(&contents)
         .map_err(|e| file_state.error(&format!("Decode Error: {:?}", e)))?;
 
+    // A .glb file's binary chunk is the buffer without a URI
+
+    if let Some(blob) = gltf.blob.take()
+    {
+        file_state.state.borrow_mut().blobs.insert(None, blob);
+    }
+
     match gltf.default_scene()
     {
         None => Err(file_state.error("No default scene")),
@@ -50,6 +60,8 @@ pub fn import_gltf_file(path: &str, destination: &Aabb, scene: &mut Scene) -> Re
                 });
             scene_state.state.borrow_mut().scene.collection.update_value(scene_transform_index, scene_transform);
 
+            import::print_cleanup_report("glTF", &scene_state.state.borrow().cleanup);
+
             Ok(())
         },
     }
@@ -121,9 +133,12 @@ fn import_node(parent_state: &ScopedState, node: &gltf::Node, parent_transform_i
                 gltf::mesh::Mode::Triangles =>
                 {
                     let positions = primitive_state.decode_accessor_required_vector_vec3_f32(primitive.get(&gltf::mesh::Semantic::Positions))?;
-                    let texture_coords = primitive_state.decode_accessor_optional_vector_vec2_f32(primitive.get(&gltf::mesh::Semantic::TexCoords(0)))?
+                    let texture_coords = primitive_state.decode_accessor_optional_texture_coords(primitive.get(&gltf::mesh::Semantic::TexCoords(0)))?
                         .unwrap_or_else(|| positions.clone());
                     let color_coords = primitive_state.decode_accessor_optional_vector_color(primitive.get(&gltf::mesh::Semantic::Colors(0)))?;
+                    let normals = primitive.get(&gltf::mesh::Semantic::Normals)
+                        .map(|accessor| primitive_state.decode_accessor_required_vector_vec3_f32(Some(accessor)))
+                        .transpose()?;
 
                     let max_index = *indexes.iter().max().ok_or_else(|| primitive_state.error("Primitive must have at least one index"))?;
 
@@ -132,6 +147,10 @@ fn import_node(parent_state: &ScopedState, node: &gltf::Node, parent_transform_i
                         println!("{:?}", indexes);
                         return Err(primitive_state.error(&format!("Primitive index {} is larger than provided position count {}", max_index, positions.len())));
                     }
+                    else if normals.as_ref().map(|n| n.len() < positions.len()).unwrap_or(false)
+                    {
+                        return Err(primitive_state.error("Primitive has fewer normals than positions"));
+                    }
                     else if (indexes.len() % 3) != 0
                     {
                         return Err(primitive_state.error(&format!("Primitive triangles expects multiple of 3 indexes, but got {}", indexes.len())));
@@ -163,10 +182,21 @@ fn import_node(parent_state: &ScopedState, node: &gltf::Node, parent_transform_i
                                 e = Some(color_coords[indexes[3 * i + 2]]);
                             }
 
+                            let mut nx = None;
+                            let mut ny = None;
+                            let mut nz = None;
+
+                            if let Some(normals) = &normals
+                            {
+                                nx = Some(normals[indexes[3 * i]]);
+                                ny = Some(normals[indexes[3 * i + 1]]);
+                                nz = Some(normals[indexes[3 * i + 2]]);
+                            }
+
                             triangles.push(Triangle { vertices: [
-                                TriangleVertex{ location: x, texture_coords: u, opt_color: c, },
-                                TriangleVertex{ location: y, texture_coords: v, opt_color: d, },
-                                TriangleVertex{ location: z, texture_coords: w, opt_color: e, },
+                                TriangleVertex{ location: x, texture_coords: u, opt_color: c, opt_normal: nx, },
+                                TriangleVertex{ location: y, texture_coords: v, opt_color: d, opt_normal: ny, },
+                                TriangleVertex{ location: z, texture_coords: w, opt_color: e, opt_normal: nz, },
                             ]});
 
                             let x = node_matrix.mul_point(x);                            
@@ -176,14 +206,15 @@ fn import_node(parent_state: &ScopedState, node: &gltf::Node, parent_transform_i
                             aabb_builder.add_triangle(x, y, z);
                         }
 
-                        let material = import_material(&primitive_state, primitive.material())?;
+                        let (material, opacity) = import_material(&primitive_state, primitive.material())?;
 
                         let mut geom_transform = Transform::new();
                         geom_transform.post = Some(local_transform_index);
 
                         let mut state = primitive_state.state.borrow_mut();
-                        let geom = state.scene.collection.push_named(Geom::Mesh{ triangles, transform: geom_transform }, primitive_name.clone());
-                        let _obj = state.scene.collection.push_named(Object{ geom, material }, primitive_name);
+                        let triangles = import::cleanup_mesh(triangles, &mut state.cleanup);
+                        let geom = state.scene.collection.push_named(Geom::Mesh{ triangles, transform: geom_transform, lod: None }, primitive_name.clone());
+                        let _obj = state.scene.collection.push_named(Object{ geom, material, ray_bias: None, opacity }, primitive_name);
                     }
                 },
                 _ =>
@@ -202,7 +233,9 @@ fn import_node(parent_state: &ScopedState, node: &gltf::Node, parent_transform_i
     Ok(())
 }
 
-fn import_material(parent_state: &ScopedState, material: gltf::Material) -> Result<MaterialIndex, ImportError>
+/// Materials that are masked or blended also return an opacity
+/// from their base color's alpha, for the objects using them
+fn import_material(parent_state: &ScopedState, material: gltf::Material) -> Result<(MaterialIndex, Option<Opacity>), ImportError>
 {
     let index = material.index().unwrap_or(usize::MAX);
     let material_state = parent_state.sub_state("material", material.name(), index);
@@ -212,12 +245,27 @@ fn import_material(parent_state: &ScopedState, material: gltf::Material) -> Resu
     {
         None =>
         {
+            let alpha_mode = material.alpha_mode();
+            let alpha_cutoff = material.alpha_cutoff().unwrap_or(0.5) as Scalar;
+
             let mapped_material = map_material(&material_state, material)?;
 
+            let opacity = match (&mapped_material, alpha_mode)
+            {
+                (_, gltf::material::AlphaMode::Opaque) => None,
+                (Material::Diffuse{ texture, .. } | Material::Pbr{ texture, .. }, alpha_mode) =>
+                {
+                    let cutoff = if alpha_mode == gltf::material::AlphaMode::Mask { Some(alpha_cutoff) } else { None };
+
+                    Some(Opacity { texture: *texture, luminance: false, cutoff })
+                },
+                _ => None,
+            };
+
             let mut state = material_state.state.borrow_mut();
             let added_index = state.scene.collection.push_named(mapped_material, material_state.collection_name());
-            state.materials.insert(index, added_index.clone());
-            Ok(added_index)
+            state.materials.insert(index, (added_index, opacity.clone()));
+            Ok((added_index, opacity))
         },
         Some(existing) =>
         {
@@ -242,6 +290,7 @@ fn map_material(material_state: &ScopedState, material: gltf::Material) -> Resul
         let texture = import_texture(
             material_state,
             "emissive",
+            ColorSpace::Srgb,
             emissive_factor.into(),
             material.emissive_texture())?;
 
@@ -256,10 +305,11 @@ fn map_material(material_state: &ScopedState, material: gltf::Material) -> Resul
         let texture = import_texture(
             material_state,
             "base_color",
+            ColorSpace::Srgb,
             diffuse.into(),
             spec_glossy.diffuse_texture())?;
 
-        return Ok(Material::Diffuse{ texture });
+        return Ok(Material::Diffuse{ texture, normal_texture: import_normal_texture(material_state, material.normal_texture())? });
     }
 
     let mr = material.pbr_metallic_roughness();
@@ -267,25 +317,25 @@ fn map_material(material_state: &ScopedState, material: gltf::Material) -> Resul
     let base_color_factor = mr.base_color_factor();
     let base_color_factor = SRGB::new(base_color_factor[0] as Scalar, base_color_factor[1] as Scalar, base_color_factor[2] as Scalar, base_color_factor[3] as Scalar);
 
-    println!("Material {} => metalic {} roughness {}", material_state.collection_name(), material.pbr_metallic_roughness().metallic_factor(), material.pbr_metallic_roughness().roughness_factor());
-
     let texture = import_texture(
         material_state,
         "base_color",
+        ColorSpace::Srgb,
         base_color_factor.into(),
         mr.base_color_texture())?;
 
-    if mr.metallic_factor() < 0.5
-    {
-        Ok(Material::Diffuse{ texture })
-    }
-    else // TODO - fully metallic
+    let normal_texture = import_normal_texture(material_state, material.normal_texture())?;
+
+    Ok(Material::Pbr
     {
-        Ok(Material::Metal{ texture, fuzz: mr.roughness_factor().powf(2.0) as f64 })
-    }
+        texture,
+        metallic: mr.metallic_factor() as Scalar,
+        roughness: mr.roughness_factor() as Scalar,
+        normal_texture,
+    })
 }
 
-fn import_texture(parent_state: &ScopedState, part: &'static str, base_color: Color, opt_texture_info: Option<gltf::texture::Info>) -> Result<TextureIndex, ImportError>
+fn import_texture(parent_state: &ScopedState, part: &'static str, color_space: ColorSpace, base_color: Color, opt_texture_info: Option<gltf::texture::Info>) -> Result<TextureIndex, ImportError>
 {
     let texture = match opt_texture_info
     {
@@ -295,7 +345,7 @@ fn import_texture(parent_state: &ScopedState, part: &'static str, base_color: Co
         },
         Some(info) =>
         {
-            let image = import_image(parent_state, info.texture().source())?;
+            let image = import_image(parent_state, info.texture().source(), color_space)?;
             let mut scale = Point3::new(1.0, 1.0, 1.0);
             let mut rotate = 0.0;
             let mut translate = Point3::new(0.0, 0.0, 0.0);
@@ -315,7 +365,33 @@ fn import_texture(parent_state: &ScopedState, part: &'static str, base_color: Co
     Ok(state.scene.collection.push_named(texture, format!("{} ({})", parent_state.collection_name(), part)))
 } 
 
-fn import_image(parent_state: &ScopedState, image: gltf::Image) -> Result<ImageIndex, ImportError>
+fn import_normal_texture(parent_state: &ScopedState, opt_normal_texture: Option<gltf::material::NormalTexture>) -> Result<Option<TextureIndex>, ImportError>
+{
+    // The normal scale isn't supported - maps
+    // are always applied at full strength
+
+    let info = match opt_normal_texture
+    {
+        Some(info) => info,
+        None => return Ok(None),
+    };
+
+    let image = import_image(parent_state, info.texture().source(), ColorSpace::Linear)?;
+
+    let texture = Texture::Image
+    {
+        base_color: Color::default(),
+        image,
+        scale: Point3::new(1.0, 1.0, 1.0),
+        rotate: 0.0,
+        translate: Point3::new(0.0, 0.0, 0.0),
+    };
+
+    let mut state = parent_state.state.borrow_mut();
+    Ok(Some(state.scene.collection.push_named(texture, format!("{} (normal)", parent_state.collection_name()))))
+}
+
+fn import_image(parent_state: &ScopedState, image: gltf::Image, color_space: ColorSpace) -> Result<ImageIndex, ImportError>
 {
     // Check for existing import
     {
@@ -339,6 +415,13 @@ fn import_image(parent_state: &ScopedState, image: gltf::Image) -> Result<ImageI
 
             let mut state = image_state.state.borrow_mut();
             let imported_image = import::image::import_image(uri, &mut state.fs_context)?;
+
+            // The glTF spec defines the color space of each texture
+            // by its usage - e.g. base color and emissive textures are
+            // sRGB, while normal, occlusion and metallic-roughness
+            // textures contain linear, non-color data.
+
+            imported_image.set_color_space(color_space);
             let image_index = state.scene.collection.push_named(imported_image, name.clone());
             state.images.insert(image.index(), image_index);
 
@@ -352,8 +435,10 @@ struct ImportState<'a>
     scene: &'a mut Scene,
     fs_context: FileSystemContext,
     blobs: HashMap<Option<String>, Vec<u8>>,
-    materials: HashMap<usize, MaterialIndex>,
+    materials: HashMap<usize, (MaterialIndex, Option<Opacity>)>,
     images: HashMap<usize, ImageIndex>,
+    /// Totals for all of the file's meshes
+    cleanup: CleanupReport,
 }
 
 struct ScopedState<'a>
@@ -370,14 +455,17 @@ impl<'a> ScopedState<'a>
         let blobs = HashMap::new();
         let materials = HashMap::new();
         let images = HashMap::new();
-        let state = Rc::new(RefCell::new(ImportState { scene, fs_context, blobs, materials, images }));
+        let state = Rc::new(RefCell::new(ImportState { scene, fs_context, blobs, materials, images, cleanup: CleanupReport::default() }));
         ScopedState { state, path: filename.clone(), collection_name: filename.clone() }
     }
 
     fn sub_state(&self, kind: &str, name: Option<&str>, index: usize) -> Self
     {
         let path = format!("{}/{}-{}", self.path, kind, name.map(|s| s.to_string()).unwrap_or_else(|| index.to_string()));
-        let collection_name = name.map(|s| s.to_string()).unwrap_or_else(|| format!("{}-{}", kind, index));
+        // Unnamed items take the name of their closest named parent - the
+        // collection adds suffixes to keep the names unique
+
+        let collection_name = name.map(|s| s.to_string()).unwrap_or_else(|| self.collection_name.clone());
         println!("Entering: {}: {} ({})", kind, path, collection_name);
         ScopedState { state: self.state.clone(), path, collection_name }
     }
@@ -408,7 +496,13 @@ impl<'a> ScopedState<'a>
         {
             gltf::buffer::Source::Bin =>
             {
-                Err(buffer_state.error("TODO: GLTF-internal binary blobs not supported yet"))
+                // The chunk is padded to a multiple of four bytes
+
+                match state.blobs.get(&None)
+                {
+                    None => Err(buffer_state.error("No binary chunk for the GLB-internal buffer")),
+                    Some(blob) => Ok(&blob[..blob.len().min(buffer.length())]),
+                }
             },
             gltf::buffer::Source::Uri(uri) =>
             {
@@ -420,11 +514,11 @@ impl<'a> ScopedState<'a>
                     {
                         let (contents, _) = state.fs_context.load_binary_file(uri)?;
                         state.blobs.insert(blob_key.clone(), contents);
-                        Ok(state.blobs.get(&blob_key).unwrap())
+                        Ok(state.blobs.get(&blob_key).unwrap().as_slice())
                     },
                     Some(existing) =>
                     {
-                        Ok(existing)
+                        Ok(existing.as_slice())
                     },
                 }
             },
@@ -483,21 +577,32 @@ impl<'a> ScopedState<'a>
 
     fn decode_accessor_required_vector_u32(&self, accessor: Option<gltf::Accessor>) -> Result<Vec<usize>, ImportError>
     {
-        if let Some(true) = accessor.as_ref().map(|a| a.data_type() == gltf::accessor::DataType::U16)
+        match accessor.as_ref().map(|a| a.data_type())
         {
-            self.decode_accessor_required_vector(
-                accessor,
-                gltf::accessor::Dimensions::Scalar,
-                gltf::accessor::DataType::U16,
-                |v| u16::from_ne_bytes(v) as usize)
-        }
-        else // assume u32
-        {
-            self.decode_accessor_required_vector(
-                accessor,
-                gltf::accessor::Dimensions::Scalar,
-                gltf::accessor::DataType::U32,
-                |v| u32::from_ne_bytes(v) as usize)
+            Some(gltf::accessor::DataType::U8) =>
+            {
+                self.decode_accessor_required_vector(
+                    accessor,
+                    gltf::accessor::Dimensions::Scalar,
+                    gltf::accessor::DataType::U8,
+                    |v: [u8; 1]| v[0] as usize)
+            },
+            Some(gltf::accessor::DataType::U16) =>
+            {
+                self.decode_accessor_required_vector(
+                    accessor,
+                    gltf::accessor::Dimensions::Scalar,
+                    gltf::accessor::DataType::U16,
+                    |v| u16::from_le_bytes(v) as usize)
+            },
+            _ => // assume u32
+            {
+                self.decode_accessor_required_vector(
+                    accessor,
+                    gltf::accessor::Dimensions::Scalar,
+                    gltf::accessor::DataType::U32,
+                    |v| u32::from_le_bytes(v) as usize)
+            },
         }
     }
 
@@ -506,70 +611,104 @@ impl<'a> ScopedState<'a>
         self.decode_accessor_required_vector(accessor, gltf::accessor::Dimensions::Vec3, gltf::accessor::DataType::F32,
             |v: [u8; 12]|
             {
-                let x = f32::from_ne_bytes([v[0], v[1], v[2], v[3]]);
-                let y = f32::from_ne_bytes([v[4], v[5], v[6], v[7]]);
-                let z = f32::from_ne_bytes([v[8], v[9], v[10], v[11]]);
+                let x = f32::from_le_bytes([v[0], v[1], v[2], v[3]]);
+                let y = f32::from_le_bytes([v[4], v[5], v[6], v[7]]);
+                let z = f32::from_le_bytes([v[8], v[9], v[10], v[11]]);
                 Point3::new(x as Scalar, y as Scalar, z as Scalar)
             })
     }
 
-    fn decode_accessor_optional_vector_vec2_f32(&self, accessor: Option<gltf::Accessor>) -> Result<Option<Vec<Point3>>, ImportError>
+    fn decode_accessor_optional_texture_coords(&self, accessor: Option<gltf::Accessor>) -> Result<Option<Vec<Point3>>, ImportError>
     {
-        self.decode_accessor_optional_vector(accessor, gltf::accessor::Dimensions::Vec2, gltf::accessor::DataType::F32,
-            |v: [u8; 8]|
-            {
-                let x = f32::from_ne_bytes([v[0], v[1], v[2], v[3]]);
-                let y = f32::from_ne_bytes([v[4], v[5], v[6], v[7]]);
-                Point3::new(x as Scalar, y as Scalar, 0.0)
-            })
+        if accessor.is_none()
+        {
+            return Ok(None);
+        }
+
+        let coords = self.decode_accessor_normalized::<2>(accessor, gltf::accessor::Dimensions::Vec2)?;
+
+        Ok(Some(coords.into_iter().map(|[u, v]| Point3::new(u, v, 0.0)).collect()))
     }
 
     fn decode_accessor_optional_vector_color(&self, accessor: Option<gltf::Accessor>) -> Result<Option<Vec<Color>>, ImportError>
     {
-        let mut result = None;
-
-        if let Some(dimensions) = accessor.as_ref().map(|a| a.dimensions())
+        match accessor.as_ref().map(|a| a.dimensions())
         {
-            if dimensions == gltf::accessor::Dimensions::Vec4
+            None => Ok(None),
+            Some(gltf::accessor::Dimensions::Vec4) =>
             {
-                result = self.decode_accessor_optional_vector(accessor, gltf::accessor::Dimensions::Vec4, gltf::accessor::DataType::F32,
-                |v: [u8; 16]|
-                        {
-                            let r = f32::from_ne_bytes([v[0], v[1], v[2], v[3]]) as Scalar;
-                            let g = f32::from_ne_bytes([v[4], v[5], v[6], v[7]]) as Scalar;
-                            let b = f32::from_ne_bytes([v[8], v[9], v[10], v[11]]) as Scalar;
-                            let a = f32::from_ne_bytes([v[12], v[13], v[14], v[15]]) as Scalar;
-                            SRGB::new(r, g, b, a).into()
-                        })?;
-                }
-            else // assume RGB with no A
+                let colors = self.decode_accessor_normalized::<4>(accessor, gltf::accessor::Dimensions::Vec4)?;
+
+                Ok(Some(colors.into_iter().map(|[r, g, b, a]| SRGB::new(r, g, b, a).into()).collect()))
+            },
+            Some(_) => // assume RGB with no A
             {
-                result = self.decode_accessor_optional_vector(accessor, gltf::accessor::Dimensions::Vec3, gltf::accessor::DataType::F32,
-            |v: [u8; 12]|
-                    {
-                        let r = f32::from_ne_bytes([v[0], v[1], v[2], v[3]]) as Scalar;
-                        let g = f32::from_ne_bytes([v[4], v[5], v[6], v[7]]) as Scalar;
-                        let b = f32::from_ne_bytes([v[8], v[9], v[10], v[11]]) as Scalar;
-                        SRGB::new(r, g, b, 1.0).into()
-                    })?;
-            }
-        }
+                let colors = self.decode_accessor_normalized::<3>(accessor, gltf::accessor::Dimensions::Vec3)?;
 
-        Ok(result)
+                Ok(Some(colors.into_iter().map(|[r, g, b]| SRGB::new(r, g, b, 1.0).into()).collect()))
+            },
+        }
     }
 
-    fn decode_accessor_optional_vector<const L: usize, T, F>(&self, accessor: Option<gltf::Accessor>, dimensions: gltf::accessor::Dimensions, data_type: gltf::accessor::DataType, convert: F) -> Result<Option<Vec<T>>, ImportError>
-        where F: Fn([u8; L]) -> T + 'static
+    /// Decodes attributes that can be stored either as floats, or
+    /// as normalized unsigned integers - such as texture
+    /// coordinates and colors
+    fn decode_accessor_normalized<const N: usize>(&self, accessor: Option<gltf::Accessor>, dimensions: gltf::accessor::Dimensions) -> Result<Vec<[Scalar; N]>, ImportError>
     {
-        match accessor
+        let (data_type, normalized) = match accessor.as_ref()
         {
-            None => Ok(None),
-            Some(_) => self.decode_accessor_required_vector(accessor, dimensions, data_type, convert).map(|v| Some(v)),
+            Some(accessor) => (accessor.data_type(), accessor.normalized()),
+            None => return Err(self.error("Missing accessor")),
+        };
+
+        if (data_type != gltf::accessor::DataType::F32) && !normalized
+        {
+            return Err(self.error(&format!("Expected normalized {:?} data", data_type)));
         }
+
+        let component_size = data_type.size();
+        let mut result = Vec::new();
+
+        self.visit_accessor_elements(
+            accessor,
+            dimensions,
+            &[gltf::accessor::DataType::F32, gltf::accessor::DataType::U8, gltf::accessor::DataType::U16],
+            |bytes|
+            {
+                let mut item = [0.0; N];
+
+                for (i, component) in item.iter_mut().enumerate()
+                {
+                    let bytes = &bytes[(i * component_size)..];
+
+                    *component = match data_type
+                    {
+                        gltf::accessor::DataType::U8 => (bytes[0] as Scalar) / 255.0,
+                        gltf::accessor::DataType::U16 => (u16::from_le_bytes([bytes[0], bytes[1]]) as Scalar) / 65535.0,
+                        _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as Scalar,
+                    };
+                }
+
+                result.push(item);
+            })?;
+
+        Ok(result)
     }
 
     fn decode_accessor_required_vector<const L: usize, T, F>(&self, accessor: Option<gltf::Accessor>, dimensions: gltf::accessor::Dimensions, data_type: gltf::accessor::DataType, convert: F) -> Result<Vec<T>, ImportError>
         where F: Fn([u8; L]) -> T + 'static
+    {
+        let mut result = Vec::new();
+
+        self.visit_accessor_elements(accessor, dimensions, &[data_type], |bytes| result.push(convert(bytes.try_into().unwrap())))?;
+
+        Ok(result)
+    }
+
+    /// Calls the visitor with the bytes of each element in turn - skipping
+    /// over the rest of each stride, when the view interleaves attributes
+    fn visit_accessor_elements<V>(&self, accessor: Option<gltf::Accessor>, dimensions: gltf::accessor::Dimensions, data_types: &[gltf::accessor::DataType], mut visit: V) -> Result<(), ImportError>
+        where V: FnMut(&[u8])
     {
         match accessor
         {
@@ -578,17 +717,17 @@ impl<'a> ScopedState<'a>
             {
                 let accessor_state = self.sub_state("accessor", accessor.name(), accessor.index());
 
+                let data_type = accessor.data_type();
+
                 if (accessor.dimensions() != dimensions)
-                    || (accessor.data_type() != data_type)
+                    || !data_types.contains(&data_type)
                 {
-                    Err(accessor_state.error(&format!("Expected {:?}/{:?} but got {:?}/{:?}", dimensions, data_type, accessor.dimensions(), accessor.data_type())))
+                    Err(accessor_state.error(&format!("Expected {:?}/{:?} but got {:?}/{:?}", dimensions, data_types, accessor.dimensions(), data_type)))
                 }
                 else
                 {
                     let count = accessor.count();
-
-                    let mut result = Vec::new();
-                    result.reserve(count);
+                    let size = accessor.size();
 
                     println!("Accessor: Trying to decode {} items of type {:?}/{:?}", count, dimensions, data_type);
 
@@ -597,37 +736,39 @@ impl<'a> ScopedState<'a>
                         None => Err(accessor_state.error("No view provided")),
                         Some(view) =>
                         {
-                            let expected = L * count;
+                            let stride = view.stride().unwrap_or(size);
+
+                            if stride < size
+                            {
+                                return Err(accessor_state.error(&format!("Stride of {} is less than the {} bytes of each {:?} x {:?}",
+                                    stride, size, dimensions, data_type)));
+                            }
+
+                            // The last element doesn't need a full stride
+
+                            let expected = if count == 0 { 0 } else { (stride * (count - 1)) + size };
 
                             accessor_state.with_view_data(
                                 &accessor,
                                 &view,
                                 expected,
-                                |slice, stride|
+                                |slice, _|
                                 {
                                     if slice.len() < expected
                                     {
                                         Err(accessor_state.error(&format!("Expected {} bytes of data for {} x {:?} x {:?}, but got {} bytes",
                                             expected, count, dimensions, data_type, slice.len())))
                                     }
-                                    else if stride.unwrap_or(L) != L
-                                    {
-                                        Err(accessor_state.error(&format!("Expected stride of {} for {} x {:?} x {:?}, but got {:?}",
-                                            L, count, dimensions, data_type, stride)))
-                                    }
                                     else
                                     {
                                         for i in 0..count
                                         {
-                                            let offset = i * L;
-                                            let item = convert(slice[offset..(offset+L)].try_into().unwrap());
-                                            result.push(item);
+                                            let offset = i * stride;
+                                            visit(&slice[offset..(offset + size)]);
                                         }
                                         Ok(())
                                     }
-                                })?;
-        
-                            Ok(result)
+                                })
                         },
                     }
                 }
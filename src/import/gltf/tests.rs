@@ -0,0 +1,82 @@
+use crate::desc::edit::{Material, Object, Scene, Texture};
+use crate::geom::Aabb;
+use crate::import::FileSystemContext;
+use crate::import::gltf::import_gltf_file;
+use crate::vec::Point3;
+
+/// A single triangle, with the given material
+fn write_triangle_gltf(dir: &std::path::Path, material: &str) -> String
+{
+    let mut buffer = Vec::new();
+
+    for index in [0u32, 1, 2]
+    {
+        buffer.extend_from_slice(&index.to_le_bytes());
+    }
+
+    for position in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+    {
+        for component in position
+        {
+            buffer.extend_from_slice(&component.to_le_bytes());
+        }
+    }
+
+    let gltf = format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "extensionsUsed": ["KHR_materials_emissive_strength"],
+        "scene": 0,
+        "scenes": [{{ "nodes": [0] }}],
+        "nodes": [{{ "mesh": 0 }}],
+        "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 1 }}, "indices": 0, "material": 0 }}] }}],
+        "materials": [{}],
+        "buffers": [{{ "uri": "triangle.bin", "byteLength": {} }}],
+        "bufferViews": [
+            {{ "buffer": 0, "byteOffset": 0, "byteLength": 12 }},
+            {{ "buffer": 0, "byteOffset": 12, "byteLength": 36 }}
+        ],
+        "accessors": [
+            {{ "bufferView": 0, "componentType": 5125, "count": 3, "type": "SCALAR" }},
+            {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }}
+        ]
+    }}"#, material, buffer.len());
+
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(dir.join("triangle.bin"), buffer).unwrap();
+    std::fs::write(dir.join("triangle.gltf"), gltf).unwrap();
+
+    dir.join("triangle.gltf").to_string_lossy().to_string()
+}
+
+#[test]
+fn test_emissive_material()
+{
+    let dir = std::env::temp_dir().join(format!("beam_test_gltf_{}", std::process::id()));
+
+    let path = write_triangle_gltf(&dir, r#"{
+        "emissiveFactor": [1.0, 0.0, 0.0],
+        "extensions": { "KHR_materials_emissive_strength": { "emissiveStrength": 4.0 } }
+    }"#);
+
+    let mut scene = Scene::new();
+    let destination = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    import_gltf_file(&path, &FileSystemContext::new(), &destination, &mut scene).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // The triangle is emitting, scaled by the strength
+
+    let material = scene.collection.map_all(|object: &Object, _| object.material)[0];
+
+    let texture = match scene.collection.map_item(material, |m, _| m.clone())
+    {
+        Material::Emit{ texture } => texture,
+        other => panic!("Expected an emitting material, got {:?}", other),
+    };
+
+    match scene.collection.map_item(texture, |t, _| t.clone())
+    {
+        Texture::Solid(color) => assert!((color.into_linear().r - 4.0).abs() < 1e-6, "{:?}", color),
+        other => panic!("Expected a solid texture, got {:?}", other),
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureSettings
+{
+    pub budget_bytes: u64,
+    pub lazy_decode: bool,
+    pub srgb_8bit: bool,
+}
+
+impl Default for TextureSettings
+{
+    fn default() -> Self
+    {
+        TextureSettings
+        {
+            budget_bytes: 2 * 1024 * 1024 * 1024,
+            lazy_decode: true,
+            srgb_8bit: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextureMemoryStats
+{
+    pub resident_bytes: u64,
+    pub num_images: u64,
+    pub num_resident: u64,
+    pub num_downsampled: u64,
+}
+
+static SETTINGS: Mutex<Option<TextureSettings>> = Mutex::new(None);
+
+static RESIDENT_BYTES: AtomicU64 = AtomicU64::new(0);
+static NUM_IMAGES: AtomicU64 = AtomicU64::new(0);
+static NUM_RESIDENT: AtomicU64 = AtomicU64::new(0);
+static NUM_DOWNSAMPLED: AtomicU64 = AtomicU64::new(0);
+
+pub fn texture_settings() -> TextureSettings
+{
+    SETTINGS.lock().unwrap().unwrap_or_default()
+}
+
+/// Changes the texture settings. These apply to images as they
+/// are decoded - images that are already resident are not affected.
+pub fn set_texture_settings(settings: TextureSettings)
+{
+    *SETTINGS.lock().unwrap() = Some(settings);
+}
+
+pub fn texture_memory_stats() -> TextureMemoryStats
+{
+    TextureMemoryStats
+    {
+        resident_bytes: RESIDENT_BYTES.load(Ordering::Relaxed),
+        num_images: NUM_IMAGES.load(Ordering::Relaxed),
+        num_resident: NUM_RESIDENT.load(Ordering::Relaxed),
+        num_downsampled: NUM_DOWNSAMPLED.load(Ordering::Relaxed),
+    }
+}
+
+pub(super) fn resident_bytes() -> u64
+{
+    RESIDENT_BYTES.load(Ordering::Relaxed)
+}
+
+pub(super) fn image_created()
+{
+    NUM_IMAGES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(super) fn image_dropped()
+{
+    NUM_IMAGES.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub(super) fn resident_added(bytes: u64, downsampled: bool)
+{
+    RESIDENT_BYTES.fetch_add(bytes, Ordering::Relaxed);
+    NUM_RESIDENT.fetch_add(1, Ordering::Relaxed);
+
+    if downsampled
+    {
+        NUM_DOWNSAMPLED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub(super) fn resident_removed(bytes: u64, downsampled: bool)
+{
+    RESIDENT_BYTES.fetch_sub(bytes, Ordering::Relaxed);
+    NUM_RESIDENT.fetch_sub(1, Ordering::Relaxed);
+
+    if downsampled
+    {
+        NUM_DOWNSAMPLED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -1,16 +1,277 @@
+mod budget;
+
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
-use image::{ImageBuffer, Rgba};
+use half::f16;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
 
-use crate::color::SRGB;
+use crate::color::{LinearRGB, SRGB};
 use crate::import::{FileSystemContext, ImportError};
 use crate::indexed::{IndexedValue, ImageIndex};
 use crate::math::Scalar;
+#[cfg(feature = "ui")]
 use crate::ui::{UiDisplay, UiEdit, UiRenderer};
 
+pub use budget::{set_texture_settings, texture_memory_stats, texture_settings, TextureMemoryStats, TextureSettings};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorSpace
+{
+    /// Color data, encoded with the sRGB transfer function
+    #[default]
+    Srgb,
+    /// Linear or non-color data (HDR images, normal maps, roughness etc.)
+    Linear,
+}
+
+impl ColorSpace
+{
+    pub fn to_linear(self, color: SRGB) -> LinearRGB
+    {
+        match self
+        {
+            ColorSpace::Srgb => color.into(),
+            ColorSpace::Linear => LinearRGB::new(color.r, color.g, color.b, color.a),
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiDisplay for ColorSpace
+{
+    fn ui_display(&self, ui: &UiRenderer, label: &str)
+    {
+        ui.imgui.label_text(label, format!("{:?}", self));
+    }
+}
+
+#[cfg(feature = "ui")]
+impl UiEdit for ColorSpace
+{
+    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    {
+        let mut result = false;
+
+        if let Some(_combo) = ui.imgui.begin_combo(label, format!("{:?}", self))
+        {
+            for entry in [ColorSpace::Srgb, ColorSpace::Linear]
+            {
+                if ui.imgui.selectable_config(format!("{:?}", entry)).selected(entry == *self).build()
+                {
+                    *self = entry;
+                    result = true;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+// Storage is selected by the source bit depth - 8-bit sources
+// are kept as 8-bit, 16-bit sources as half floats, and only
+// HDR sources need full 32-bit floats.
+
+enum ImageStorage
+{
+    F32(ImageBuffer<Rgba<f32>, Vec<f32>>),
+    F16 { width: u32, height: u32, data: Vec<[f16; 4]> },
+    Srgb8(RgbaImage),
+}
+
+impl ImageStorage
+{
+    fn from_dynamic(image: DynamicImage, settings: &TextureSettings) -> Self
+    {
+        match Self::bytes_per_pixel(&image, settings)
+        {
+            4 => ImageStorage::Srgb8(image.into_rgba8()),
+            8 =>
+            {
+                let image = image.into_rgba32f();
+                let (width, height) = image.dimensions();
+                let data = image.pixels().map(|p| p.0.map(f16::from_f32)).collect();
+                ImageStorage::F16 { width, height, data }
+            },
+            _ => ImageStorage::F32(image.into_rgba32f()),
+        }
+    }
+
+    fn bytes_per_pixel(image: &DynamicImage, settings: &TextureSettings) -> u64
+    {
+        match image.color()
+        {
+            image::ColorType::L8 | image::ColorType::La8 | image::ColorType::Rgb8 | image::ColorType::Rgba8
+                if settings.srgb_8bit => 4,
+            image::ColorType::L8 | image::ColorType::La8 | image::ColorType::Rgb8 | image::ColorType::Rgba8
+                | image::ColorType::L16 | image::ColorType::La16 | image::ColorType::Rgb16 | image::ColorType::Rgba16 => 8,
+            _ => 16,
+        }
+    }
+
+    fn dimensions(&self) -> (u32, u32)
+    {
+        match self
+        {
+            ImageStorage::F32(image) => image.dimensions(),
+            ImageStorage::F16 { width, height, .. } => (*width, *height),
+            ImageStorage::Srgb8(image) => image.dimensions(),
+        }
+    }
+
+    fn size_in_bytes(&self) -> u64
+    {
+        let (w, h) = self.dimensions();
+        let bytes_per_pixel = match self
+        {
+            ImageStorage::F32(_) => 16,
+            ImageStorage::F16 { .. } => 8,
+            ImageStorage::Srgb8(_) => 4,
+        };
+
+        (w as u64) * (h as u64) * bytes_per_pixel
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> [Scalar; 4]
+    {
+        match self
+        {
+            ImageStorage::F32(image) =>
+            {
+                let color = image.get_pixel(x, y);
+                [color.0[0] as Scalar, color.0[1] as Scalar, color.0[2] as Scalar, color.0[3] as Scalar]
+            },
+            ImageStorage::F16 { width, data, .. } =>
+            {
+                let color = data[(y as usize) * (*width as usize) + (x as usize)];
+                [color[0].to_f64(), color[1].to_f64(), color[2].to_f64(), color[3].to_f64()]
+            },
+            ImageStorage::Srgb8(image) =>
+            {
+                let color = image.get_pixel(x, y);
+                [color.0[0] as Scalar / 255.0, color.0[1] as Scalar / 255.0, color.0[2] as Scalar / 255.0, color.0[3] as Scalar / 255.0]
+            },
+        }
+    }
+}
+
+// An image is either resident (decoded into memory, possibly
+// downsampled to fit within the texture memory budget) or
+// still encoded, in which case it is decoded on first use.
+
+struct ImageState
+{
+    dimensions: (u32, u32),
+    color_space: ColorSpace,
+    encoded: Option<Vec<u8>>,
+    resident: Option<ImageStorage>,
+    downsampled: bool,
+    /// The file it was loaded from, if it was, so it can be
+    /// loaded again - e.g. when a saved scene is opened
+    source: Option<PathBuf>,
+}
+
+impl ImageState
+{
+    fn new_resident(storage: ImageStorage, color_space: ColorSpace, downsampled: bool) -> Self
+    {
+        budget::image_created();
+        budget::resident_added(storage.size_in_bytes(), downsampled);
+
+        ImageState { dimensions: storage.dimensions(), color_space, encoded: None, resident: Some(storage), downsampled, source: None }
+    }
+
+    fn new_encoded(dimensions: (u32, u32), color_space: ColorSpace, encoded: Vec<u8>) -> Self
+    {
+        budget::image_created();
+
+        ImageState { dimensions, color_space, encoded: Some(encoded), resident: None, downsampled: false, source: None }
+    }
+
+    fn make_resident(&mut self)
+    {
+        if self.resident.is_some()
+        {
+            return;
+        }
+
+        let storage = match self.encoded.take().map(|encoded| image::load_from_memory(&encoded))
+        {
+            Some(Ok(image)) =>
+            {
+                let (storage, downsampled) = decode_within_budget(image);
+                self.downsampled = downsampled;
+                storage
+            },
+            _ =>
+            {
+                // Only the header was checked during import - if
+                // the data can't be decoded, fall back to a single
+                // black pixel rather than panicking on a render thread.
+
+                ImageStorage::F32(ImageBuffer::new(1, 1))
+            },
+        };
+
+        budget::resident_added(storage.size_in_bytes(), self.downsampled);
+        self.resident = Some(storage);
+    }
+}
+
+impl Drop for ImageState
+{
+    fn drop(&mut self)
+    {
+        if let Some(storage) = &self.resident
+        {
+            budget::resident_removed(storage.size_in_bytes(), self.downsampled);
+        }
+
+        budget::image_dropped();
+    }
+}
+
+impl std::fmt::Debug for ImageState
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        f.debug_struct("ImageState")
+            .field("dimensions", &self.dimensions)
+            .field("resident", &self.resident.as_ref().map(|r| r.dimensions()))
+            .finish()
+    }
+}
+
+fn decode_within_budget(image: DynamicImage) -> (ImageStorage, bool)
+{
+    let settings = budget::texture_settings();
+    let bytes_per_pixel = ImageStorage::bytes_per_pixel(&image, &settings);
+    let available = settings.budget_bytes.saturating_sub(budget::resident_bytes());
+
+    let (mut w, mut h) = (image.width(), image.height());
+
+    while ((w as u64) * (h as u64) * bytes_per_pixel > available) && ((w > 1) || (h > 1))
+    {
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+    }
+
+    if (w, h) == (image.width(), image.height())
+    {
+        (ImageStorage::from_dynamic(image, &settings), false)
+    }
+    else
+    {
+        let image = image.resize_exact(w, h, image::imageops::FilterType::Triangle);
+        (ImageStorage::from_dynamic(image, &settings), true)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Image
 {
-    data: Arc<RwLock<ImageBuffer<Rgba<f32>, Vec<f32>>>>
+    data: Arc<RwLock<ImageState>>
 }
 
 impl Image
@@ -18,12 +279,45 @@ impl Image
     pub fn dimensions(&self) -> (u32, u32)
     {
         let image = self.data.read().unwrap();
-        image.dimensions()
+        image.dimensions
+    }
+
+    pub fn is_resident(&self) -> bool
+    {
+        self.data.read().unwrap().resident.is_some()
+    }
+
+    pub fn color_space(&self) -> ColorSpace
+    {
+        self.data.read().unwrap().color_space
+    }
+
+    pub fn set_color_space(&self, color_space: ColorSpace)
+    {
+        self.data.write().unwrap().color_space = color_space;
+    }
+
+    /// None if it wasn't loaded from a file, e.g. it
+    /// was embedded in a model, or is a placeholder
+    pub fn source(&self) -> Option<PathBuf>
+    {
+        self.data.read().unwrap().source.clone()
+    }
+
+    fn set_source(&self, source: &Path)
+    {
+        self.data.write().unwrap().source = Some(source.canonicalize().unwrap_or_else(|_| source.to_owned()));
     }
 
     pub fn sample_at_uv(&self, u: Scalar, v: Scalar) -> SRGB
     {
-        let image = self.data.read().unwrap();
+        if !self.is_resident()
+        {
+            self.data.write().unwrap().make_resident();
+        }
+
+        let state = self.data.read().unwrap();
+        let image = state.resident.as_ref().unwrap();
         let (w, h) = image.dimensions();
 
         let x = (u * ((w - 1) as Scalar)).round() as u32;
@@ -34,12 +328,12 @@ impl Image
 
         let color = image.get_pixel(x, y);
 
-        SRGB::new(color.0[0] as Scalar, color.0[1] as Scalar, color.0[2] as Scalar, color.0[3] as Scalar)
+        SRGB::new(color[0], color[1], color[2], color[3])
     }
 
     pub fn new_empty(w: u32, h: u32) -> Self
     {
-        Image { data: Arc::new(RwLock::new(image::ImageBuffer::new(w, h))) }
+        Image { data: Arc::new(RwLock::new(ImageState::new_resident(ImageStorage::F32(image::ImageBuffer::new(w, h)), ColorSpace::Srgb, false))) }
     }
 }
 
@@ -51,26 +345,66 @@ impl IndexedValue for Image
     {
     }
 
+    fn remap_indexes(&mut self, _remap: &crate::indexed::IndexRemap)
+    {
+    }
+
     fn summary(&self) -> String
     {
-        let dimensions = self.dimensions();
-        format!("{} x {} pixels", dimensions.0, dimensions.1)
+        let state = self.data.read().unwrap();
+        let dimensions = state.dimensions;
+
+        match &state.resident
+        {
+            None => format!("{} x {} pixels (not loaded)", dimensions.0, dimensions.1),
+            Some(resident) if state.downsampled =>
+            {
+                let resident_dimensions = resident.dimensions();
+                format!("{} x {} pixels (downsampled to {} x {})", dimensions.0, dimensions.1, resident_dimensions.0, resident_dimensions.1)
+            },
+            Some(_) => format!("{} x {} pixels", dimensions.0, dimensions.1),
+        }
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for Image
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
     {
+        let _id = ui.imgui.push_id(label);
+
         ui.imgui.label_text(label, self.summary());
+
+        if let Some(source) = self.source()
+        {
+            ui.imgui.label_text("File", source.to_string_lossy());
+        }
+        self.color_space().ui_display(ui, "Color Space");
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for Image
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
+        let _id = ui.imgui.push_id(label);
+
         ui.imgui.label_text(label, self.summary());
+
+        if let Some(source) = self.source()
+        {
+            ui.imgui.label_text("File", source.to_string_lossy());
+        }
+
+        let mut color_space = self.color_space();
+        if color_space.ui_edit(ui, "Color Space")
+        {
+            self.set_color_space(color_space);
+            return true;
+        }
+
         false
     }
 }
@@ -87,9 +421,47 @@ pub fn import_image(path: &str, context: &mut FileSystemContext) -> Result<Image
 {
     let (contents, _sub_context) = context.load_binary_file(path)?;
 
-    match image::load_from_memory(&contents)
+    let image = import_image_from_memory(contents)?;
+    image.set_source(&context.cwd().join(path));
+
+    Ok(image)
+}
+
+/// Imports an image that has already been loaded,
+/// e.g. one stored inside an archive
+pub fn import_image_from_memory(contents: Vec<u8>) -> Result<Image, ImportError>
+{
+    let format = image::guess_format(&contents)
+        .map_err(|err| ImportError(err.to_string()))?;
+
+    // HDR formats store linear values - everything
+    // else is assumed to be sRGB color data
+
+    let color_space = match format
     {
-        Ok(image) => Ok(Image { data: Arc::new(RwLock::new(image.into_rgba32f())) }),
+        image::ImageFormat::Hdr | image::ImageFormat::OpenExr => ColorSpace::Linear,
+        _ => ColorSpace::Srgb,
+    };
+
+    if budget::texture_settings().lazy_decode
+    {
+        // Only read the header now - the pixels are
+        // decoded the first time the image is sampled
+
+        let dimensions = image::io::Reader::with_format(std::io::Cursor::new(&contents), format)
+            .into_dimensions()
+            .map_err(|err| ImportError(err.to_string()))?;
+
+        return Ok(Image { data: Arc::new(RwLock::new(ImageState::new_encoded(dimensions, color_space, contents))) });
+    }
+
+    match image::load_from_memory_with_format(&contents, format)
+    {
+        Ok(image) =>
+        {
+            let (storage, downsampled) = decode_within_budget(image);
+            Ok(Image { data: Arc::new(RwLock::new(ImageState::new_resident(storage, color_space, downsampled))) })
+        },
         Err(err) => Err(ImportError(err.to_string())),
     }
-}
\ No newline at end of file
+}
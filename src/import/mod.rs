@@ -1,12 +1,38 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::desc::edit::{CleanupReport, MeshCleanup, Triangle};
 
 pub mod gltf;
 pub mod image;
+pub mod fbx;
 pub mod obj;
+pub mod pbrt;
+pub mod usd;
+pub mod vox;
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Clone)]
 pub struct ImportError(pub String);
 
+/// Cleans up an imported mesh with the default options,
+/// adding what was removed to the report for the file
+pub fn cleanup_mesh(triangles: Vec<Triangle>, report: &mut CleanupReport) -> Vec<Triangle>
+{
+    let (triangles, mesh_report) = MeshCleanup::default().apply(triangles);
+    report.add(&mesh_report);
+    triangles
+}
+
+pub fn print_cleanup_report(format: &str, report: &CleanupReport)
+{
+    if !report.is_empty()
+    {
+        println!("{}: Mesh cleanup {}", format, report.summary());
+    }
+}
+
 pub struct FileSystemContext
 {
     cwd: PathBuf,
@@ -19,6 +45,16 @@ impl FileSystemContext
         FileSystemContext { cwd: std::env::current_dir().unwrap_or(PathBuf::new()) }
     }
 
+    pub fn new_in_dir(cwd: PathBuf) -> Self
+    {
+        FileSystemContext { cwd }
+    }
+
+    pub fn cwd(&self) -> &Path
+    {
+        &self.cwd
+    }
+
     pub fn path_to_filename(&self, path: &str) -> String
     {
         PathBuf::from(path).file_name().map(|s| s.to_string_lossy()).map(|s| s.to_string()).unwrap_or_default()
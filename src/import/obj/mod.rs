@@ -2,26 +2,26 @@ use std::collections::HashMap;
 
 use crate::color::SRGB;
 use crate::desc::edit::transform::TransformStage;
-use crate::desc::edit::{Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::desc::edit::{CleanupReport, Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
 use crate::geom::Aabb;
-use crate::import::{FileSystemContext, ImportError};
+use crate::import::{FileSystemContext, ImportError, cleanup_mesh, print_cleanup_report};
 use crate::import::image::Image;
 use crate::indexed::MaterialIndex;
-use crate::vec::Point3;
+use crate::vec::{Dir3, Point3};
 
 pub mod obj_file;
 pub mod mtl_file;
 mod parser;
 
-pub fn import_obj_file(path: &str, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
+pub fn import_obj_file(path: &str, context: &FileSystemContext, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
 {
-    let context = FileSystemContext::new();
     let (contents, sub_context) = context.load_text_file(path)?;
     let obj_file = obj_file::parse(&contents, path)?;
 
     let transform = calc_transform(&obj_file.vertices, destination);
 
     let mut resources = ResourceLoader::new(&obj_file.material_library, sub_context)?;
+    let mut cleanup = CleanupReport::default();
 
     for obj in obj_file.objects.iter()
     {
@@ -35,20 +35,22 @@ pub fn import_obj_file(path: &str, destination: &Aabb, scene: &mut Scene) -> Res
 
             push_geom_triangles(&obj_file, &geom, &mut triangles);
 
+            let triangles = cleanup_mesh(triangles, &mut cleanup);
             let name = if single_geom { obj.name.clone() } else { format!("{}.{}", obj.name, geom_index + 1) };
 
-            let geom = scene.collection.push_named(Geom::Mesh { triangles, transform: transform.clone() }, name.clone());
+            let geom = scene.collection.push_named(Geom::Mesh { triangles, transform: transform.clone(), lod: None }, name.clone());
 
-            scene.collection.push_named(Object { geom, material }, name);
+            scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, name);
         }
     }
 
+    print_cleanup_report("OBJ", &cleanup);
+
     Ok(())
 }
 
-pub fn import_obj_file_as_triangle_mesh(path: &str) -> Result<Geom, ImportError>
+pub fn import_obj_file_as_triangle_mesh(path: &str, context: &FileSystemContext) -> Result<Geom, ImportError>
 {
-    let context = FileSystemContext::new();
     let (contents, _sub_context) = context.load_text_file(path)?;
     let obj_file = obj_file::parse(&contents, path)?;
 
@@ -62,7 +64,12 @@ pub fn import_obj_file_as_triangle_mesh(path: &str) -> Result<Geom, ImportError>
         }
     }
 
-    Ok(Geom::Mesh{ triangles, transform: Transform::new() })
+    let mut cleanup = CleanupReport::default();
+    let triangles = cleanup_mesh(triangles, &mut cleanup);
+
+    print_cleanup_report("OBJ", &cleanup);
+
+    Ok(Geom::Mesh{ triangles, transform: Transform::new(), lod: None })
 }
 
 fn push_geom_triangles(obj_file: &obj_file::ObjFile, geom: &obj_file::Geometry, triangles: &mut Vec<Triangle>)
@@ -102,7 +109,11 @@ fn convert_vertex(file: &obj_file::ObjFile, triangle: &obj_file::Vertex) -> Tria
         }
     }
 
-    TriangleVertex { location, texture_coords, opt_color: None }
+    let opt_normal = triangle.normal_index
+        .filter(|ni| *ni < file.normals.len())
+        .map(|ni| Dir3::new(file.normals[ni].0, file.normals[ni].1, file.normals[ni].2));
+
+    TriangleVertex { location, texture_coords, opt_color: None, opt_normal }
 }
 
 fn calc_transform(vertices: &Vec<obj_file::Vector>, destination: &Aabb) -> Transform
@@ -185,7 +196,7 @@ impl ResourceLoader
                     if disolve < 1.0
                     {
                         // Use a dielectric
-                        let result = scene.collection.push_named(Material::Dielectric { ior }, name.clone());
+                        let result = scene.collection.push_named(Material::Dielectric { ior, tint: None }, name.clone());
                         self.imported_materials.insert(Some(name.clone()), result);
                         return Ok(result);
                     }
@@ -208,7 +219,7 @@ impl ResourceLoader
                     scene.collection.push_named(Texture::Solid(mtl.diffuse.into()), name.clone())
                 };
 
-                let result = scene.collection.push_named(Material::Diffuse{ texture }, name.clone());
+                let result = scene.collection.push_named(Material::Diffuse{ texture, normal_texture: None }, name.clone());
                 self.imported_materials.insert(Some(name.clone()), result);
                 return Ok(result);
             }
@@ -219,7 +230,7 @@ impl ResourceLoader
 
         // Return the 'none' material
         let texture = scene.collection.push(Texture::Solid(SRGB::new(1.0, 1.0, 1.0, 1.0).into()));
-        let result = scene.collection.push(Material::Diffuse{ texture });
+        let result = scene.collection.push(Material::Diffuse{ texture, normal_texture: None });
         self.imported_materials.insert(None, result);
         Ok(result)
     }
@@ -0,0 +1,682 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::color::LinearRGB;
+use crate::desc::edit::{Camera, CleanupReport, Color, Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::import;
+use crate::import::{FileSystemContext, ImportError};
+use crate::indexed::{MaterialIndex, TextureIndex};
+use crate::math::Scalar;
+use crate::vec::{Dir3, Mat4, Point3, Vec3};
+
+/// Imports a subset of a PBRT (v3 or v4) scene - the camera,
+/// spheres, triangle meshes, diffuse, metal and glass materials,
+/// image textures and area lights. Everything else is ignored,
+/// and listed once the import is complete. Objects keep their
+/// positions, and the scene's camera is replaced.
+pub fn import_pbrt_file(path: &str, context: &FileSystemContext, scene: &mut Scene) -> Result<(), ImportError>
+{
+    let mut importer = Importer::new(scene);
+
+    importer.import_file(path, context)?;
+
+    if !importer.ignored.is_empty()
+    {
+        println!("PBRT: Ignored unsupported features: {}", importer.ignored.iter().cloned().collect::<Vec<_>>().join(", "));
+    }
+
+    import::print_cleanup_report("PBRT", &importer.cleanup);
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token
+{
+    Word(String),
+    Str(String),
+    Num(Scalar),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String>
+{
+    let mut result = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek()
+    {
+        if c.is_whitespace()
+        {
+            chars.next();
+        }
+        else if c == '#'
+        {
+            while chars.next_if(|&c| c != '\n').is_some() {}
+        }
+        else if c == '['
+        {
+            chars.next();
+            result.push(Token::Open);
+        }
+        else if c == ']'
+        {
+            chars.next();
+            result.push(Token::Close);
+        }
+        else if c == '"'
+        {
+            chars.next();
+
+            let mut s = String::new();
+
+            loop
+            {
+                match chars.next()
+                {
+                    Some('"') => break,
+                    Some(c) => s.push(c),
+                    None => return Err("Unterminated string".to_owned()),
+                }
+            }
+
+            result.push(Token::Str(s));
+        }
+        else
+        {
+            let mut s = String::new();
+
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !matches!(c, '[' | ']' | '"' | '#'))
+            {
+                s.push(c);
+            }
+
+            match s.parse::<Scalar>()
+            {
+                Ok(num) => result.push(Token::Num(num)),
+                Err(_) => result.push(Token::Word(s)),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// A directive's parameters, e.g. `"float radius" [ 2 ]`
+struct Params
+{
+    values: Vec<(String, String, Vec<Token>)>,
+}
+
+impl Params
+{
+    fn parse(tokens: &[Token]) -> Result<Self, String>
+    {
+        let mut values = Vec::new();
+        let mut i = 0;
+
+        while i < tokens.len()
+        {
+            let decl = match &tokens[i]
+            {
+                Token::Str(s) => s,
+                other => return Err(format!("Expected a parameter declaration, but got {:?}", other)),
+            };
+
+            let mut parts = decl.split_whitespace();
+            let (ty, name) = match (parts.next(), parts.next())
+            {
+                (Some(ty), Some(name)) => (ty.to_owned(), name.to_owned()),
+                _ => return Err(format!("Invalid parameter declaration {:?}", decl)),
+            };
+
+            i += 1;
+
+            let mut value = Vec::new();
+
+            match tokens.get(i)
+            {
+                Some(Token::Open) =>
+                {
+                    i += 1;
+
+                    while let Some(token) = tokens.get(i)
+                    {
+                        i += 1;
+
+                        if *token == Token::Close
+                        {
+                            break;
+                        }
+
+                        value.push(token.clone());
+                    }
+                },
+                Some(token) =>
+                {
+                    value.push(token.clone());
+                    i += 1;
+                },
+                None => return Err(format!("Missing value for parameter {:?}", decl)),
+            }
+
+            values.push((ty, name, value));
+        }
+
+        Ok(Params { values })
+    }
+
+    fn find(&self, names: &[&str]) -> Option<(&str, &[Token])>
+    {
+        self.values.iter()
+            .find(|(_, name, _)| names.contains(&name.as_str()))
+            .map(|(ty, _, value)| (ty.as_str(), value.as_slice()))
+    }
+
+    fn floats(&self, names: &[&str]) -> Option<Vec<Scalar>>
+    {
+        self.find(names).map(|(_, value)| numbers(value))
+    }
+
+    fn float(&self, names: &[&str], default: Scalar) -> Scalar
+    {
+        self.floats(names).and_then(|v| v.first().copied()).unwrap_or(default)
+    }
+
+    fn string(&self, names: &[&str]) -> Option<String>
+    {
+        match self.find(names)
+        {
+            Some((_, [Token::Str(s), ..])) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// The name of a texture, if the parameter is one
+    fn texture(&self, names: &[&str]) -> Option<String>
+    {
+        match self.find(names)
+        {
+            Some(("texture", [Token::Str(s), ..])) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// Spectra are approximated as grey, at their
+    /// average value, and blackbodies as white
+    fn color(&self, names: &[&str]) -> Option<LinearRGB>
+    {
+        let (ty, value) = self.find(names)?;
+        let nums = numbers(value);
+
+        match ty
+        {
+            "rgb" | "color" if nums.len() >= 3 => Some(LinearRGB::new(nums[0], nums[1], nums[2], 1.0)),
+            "float" if !nums.is_empty() => Some(LinearRGB::grey(nums[0])),
+            "spectrum" if nums.len() >= 2 =>
+            {
+                let values = nums.iter().skip(1).step_by(2).collect::<Vec<_>>();
+                Some(LinearRGB::grey(values.iter().copied().sum::<Scalar>() / (values.len() as Scalar)))
+            },
+            "blackbody" => Some(LinearRGB::grey(1.0)),
+            _ => None,
+        }
+    }
+}
+
+fn numbers(tokens: &[Token]) -> Vec<Scalar>
+{
+    tokens.iter().filter_map(|t| match t { Token::Num(n) => Some(*n), _ => None }).collect()
+}
+
+/// The graphics state, saved by `AttributeBegin`
+#[derive(Clone)]
+struct GraphicsState
+{
+    matrix: Mat4,
+    material: Option<MaterialIndex>,
+    emission: Option<LinearRGB>,
+}
+
+struct Importer<'a>
+{
+    scene: &'a mut Scene,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    /// Applied to everything in the world, to convert from PBRT's
+    /// left-handed camera to beam's right-handed one
+    world: Mat4,
+    aspect_ratio: Scalar,
+    named_materials: HashMap<String, MaterialIndex>,
+    textures: HashMap<String, TextureIndex>,
+    default_material: Option<MaterialIndex>,
+    ignored: BTreeSet<String>,
+    /// Totals for all of the file's meshes
+    cleanup: CleanupReport,
+    /// The files being imported - the main file,
+    /// then those being included, innermost last
+    files: Vec<PathBuf>,
+}
+
+impl<'a> Importer<'a>
+{
+    fn new(scene: &'a mut Scene) -> Self
+    {
+        Importer
+        {
+            scene,
+            state: GraphicsState { matrix: Mat4::identity(), material: None, emission: None },
+            stack: Vec::new(),
+            world: Mat4::scaling_3d(Vec3::new(-1.0, 1.0, 1.0)),
+            aspect_ratio: 1280.0 / 720.0,
+            named_materials: HashMap::new(),
+            textures: HashMap::new(),
+            default_material: None,
+            ignored: BTreeSet::new(),
+            cleanup: CleanupReport::default(),
+            files: Vec::new(),
+        }
+    }
+
+    fn import_file(&mut self, path: &str, context: &FileSystemContext) -> Result<(), ImportError>
+    {
+        let (text, mut sub_context) = context.load_text_file(path)?;
+
+        // A file that includes itself, directly
+        // or not, would never finish
+
+        let full_path = context.cwd().join(path);
+        let full_path = full_path.canonicalize().unwrap_or(full_path);
+
+        if self.files.contains(&full_path)
+        {
+            return Err(ImportError(format!("PBRT Error: {}: File includes itself", path)));
+        }
+
+        self.files.push(full_path);
+        let result = self.import_text(&text, path, &mut sub_context);
+        self.files.pop();
+
+        result
+    }
+
+    fn import_text(&mut self, text: &str, path: &str, context: &mut FileSystemContext) -> Result<(), ImportError>
+    {
+        let error = |msg: String| ImportError(format!("PBRT Error: {}: {}", path, msg));
+
+        let tokens = tokenize(text).map_err(error)?;
+        let mut i = 0;
+
+        while i < tokens.len()
+        {
+            let directive = match &tokens[i]
+            {
+                Token::Word(w) => w.clone(),
+                other => return Err(error(format!("Expected a directive, but got {:?}", other))),
+            };
+
+            // The arguments run up to the next directive - "true"
+            // and "false" are the only unquoted words in values
+
+            let start = i + 1;
+            i = start;
+
+            while i < tokens.len() && !matches!(&tokens[i], Token::Word(w) if (w != "true") && (w != "false"))
+            {
+                i += 1;
+            }
+
+            self.directive(&directive, &tokens[start..i], context)
+                .map_err(|e| ImportError(format!("PBRT Error: {}: {}: {}", path, directive, e.0)))?;
+        }
+
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, args: &[Token], context: &mut FileSystemContext) -> Result<(), ImportError>
+    {
+        let nums = numbers(args);
+        let strings = args.iter().filter_map(|t| match t { Token::Str(s) => Some(s.as_str()), _ => None }).collect::<Vec<_>>();
+        let first_string = || strings.first().map(|s| s.to_string()).ok_or_else(|| ImportError("Expected a string argument".to_owned()));
+        let need = |n: usize| if nums.len() >= n { Ok(()) } else { Err(ImportError(format!("Expected {} numbers", n))) };
+
+        match directive
+        {
+            "Identity" => self.state.matrix = Mat4::identity(),
+            "Translate" =>
+            {
+                need(3)?;
+                self.state.matrix *= Mat4::translation_3d(Vec3::new(nums[0], nums[1], nums[2]));
+            },
+            "Scale" =>
+            {
+                need(3)?;
+                self.state.matrix *= Mat4::scaling_3d(Vec3::new(nums[0], nums[1], nums[2]));
+            },
+            "Rotate" =>
+            {
+                need(4)?;
+                self.state.matrix *= Mat4::rotation_3d(nums[0].to_radians(), Vec3::new(nums[1], nums[2], nums[3]).normalized());
+            },
+            "Transform" | "ConcatTransform" =>
+            {
+                need(16)?;
+
+                // The values are given column by column
+
+                let mut values = [0.0; 16];
+                values.copy_from_slice(&nums[0..16]);
+                let matrix = Mat4::from_col_array(values);
+
+                self.state.matrix = if directive == "Transform" { matrix } else { self.state.matrix * matrix };
+            },
+            "LookAt" =>
+            {
+                need(9)?;
+
+                let eye = Point3::new(nums[0], nums[1], nums[2]);
+                let dir = (Point3::new(nums[3], nums[4], nums[5]) - eye).normalized();
+                let right = Dir3::new(nums[6], nums[7], nums[8]).normalized().cross(dir).normalized();
+                let up = dir.cross(right);
+
+                let world_from_camera = Mat4::from_col_arrays([
+                    [right.x, right.y, right.z, 0.0],
+                    [up.x, up.y, up.z, 0.0],
+                    [dir.x, dir.y, dir.z, 0.0],
+                    [eye.x, eye.y, eye.z, 1.0],
+                ]);
+
+                self.state.matrix *= world_from_camera.inverted();
+            },
+            "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" | "TransformEnd" =>
+            {
+                let saved = self.stack.pop().ok_or_else(|| ImportError("Unmatched end".to_owned()))?;
+
+                if directive == "TransformEnd"
+                {
+                    self.state.matrix = saved.matrix;
+                }
+                else
+                {
+                    self.state = saved;
+                }
+            },
+            "WorldBegin" =>
+            {
+                self.state.matrix = Mat4::identity();
+            },
+            "Film" =>
+            {
+                let params = Params::parse(&args[1..]).map_err(ImportError)?;
+                let width = params.float(&["xresolution"], 1280.0);
+                let height = params.float(&["yresolution"], 720.0);
+
+                self.aspect_ratio = width / height.max(1.0);
+            },
+            "Camera" =>
+            {
+                let params = Params::parse(&args[1..]).map_err(ImportError)?;
+                self.camera(&params);
+            },
+            "Include" | "Import" =>
+            {
+                self.import_file(&first_string()?, context)?;
+            },
+            "Texture" =>
+            {
+                let params = Params::parse(&args[3.min(args.len())..]).map_err(ImportError)?;
+                self.texture(&strings, &params, context)?;
+            },
+            "Material" =>
+            {
+                let params = Params::parse(&args[1..]).map_err(ImportError)?;
+                self.state.material = Some(self.material(&first_string()?, &params, None));
+            },
+            "MakeNamedMaterial" =>
+            {
+                let name = first_string()?;
+                let params = Params::parse(&args[1..]).map_err(ImportError)?;
+                let ty = params.string(&["type"]).unwrap_or_else(|| "diffuse".to_owned());
+                let material = self.material(&ty, &params, Some(name.clone()));
+
+                self.named_materials.insert(name, material);
+            },
+            "NamedMaterial" =>
+            {
+                let name = first_string()?;
+                let material = *self.named_materials.get(&name).ok_or_else(|| ImportError(format!("Unknown material {:?}", name)))?;
+
+                self.state.material = Some(material);
+            },
+            "AreaLightSource" =>
+            {
+                let params = Params::parse(&args[1..]).map_err(ImportError)?;
+                let radiance = params.color(&["L"]).unwrap_or_else(|| LinearRGB::grey(1.0));
+
+                self.state.emission = Some(radiance.multiplied_by_scalar(params.float(&["scale"], 1.0)));
+            },
+            "Shape" =>
+            {
+                let params = Params::parse(&args[1..]).map_err(ImportError)?;
+                self.shape(&first_string()?, &params)?;
+            },
+            "WorldEnd" | "ReverseOrientation" | "Option" | "ColorSpace" => {},
+            _ =>
+            {
+                self.ignored.insert(directive.to_owned());
+            },
+        }
+
+        Ok(())
+    }
+
+    fn camera(&mut self, params: &Params)
+    {
+        let world_from_camera = self.state.matrix.inverted();
+
+        let location = world_from_camera.mul_point(Point3::zero());
+        let forward = world_from_camera.mul_direction(Dir3::new(0.0, 0.0, 1.0)).normalized();
+        let up = world_from_camera.mul_direction(Dir3::new(0.0, 1.0, 0.0)).normalized();
+        let right = world_from_camera.mul_direction(Dir3::new(1.0, 0.0, 0.0)).normalized();
+
+        // Beam's image right is forward x up - if PBRT's is the
+        // other way, the world is mirrored to keep the image
+        // the same
+
+        self.world = if right.dot(forward.cross(up)) < 0.0
+        {
+            Mat4::scaling_3d(Vec3::new(-1.0, 1.0, 1.0))
+        }
+        else
+        {
+            Mat4::identity()
+        };
+
+        // PBRT's field of view is for the shorter image axis,
+        // while beam's is always horizontal
+
+        let fov = params.float(&["fov"], 90.0);
+        let fov = if self.aspect_ratio > 1.0
+        {
+            2.0 * ((fov.to_radians() / 2.0).tan() * self.aspect_ratio).atan().to_degrees()
+        }
+        else
+        {
+            fov
+        };
+
+        let location = self.world.mul_point(location);
+        let forward = self.world.mul_direction(forward);
+
+        self.scene.camera = Camera
+        {
+            location,
+            look_at: location + forward,
+            up: self.world.mul_direction(up),
+            fov,
+            aperture: 2.0 * params.float(&["lensradius"], 0.0),
+            focus_distance: params.float(&["focaldistance"], 0.0),
+        };
+    }
+
+    fn texture(&mut self, strings: &[&str], params: &Params, context: &mut FileSystemContext) -> Result<(), ImportError>
+    {
+        let (name, class) = match strings
+        {
+            [name, _, class, ..] => (name.to_string(), *class),
+            _ => return Err(ImportError("Expected a name, type and class".to_owned())),
+        };
+
+        match (class, params.string(&["filename"]))
+        {
+            ("imagemap", Some(filename)) =>
+            {
+                let image = import::image::import_image(&filename, context)?;
+                let image = self.scene.collection.push(image);
+
+                let texture = self.scene.collection.push_named(Texture::Image
+                    {
+                        base_color: Color::default(),
+                        image,
+                        scale: Point3::new(1.0, 1.0, 1.0),
+                        rotate: 0.0,
+                        translate: Point3::new(0.0, 0.0, 0.0),
+                    },
+                    name.clone());
+
+                self.textures.insert(name, texture);
+            },
+            _ =>
+            {
+                self.ignored.insert(format!("{} texture", class));
+            },
+        }
+
+        Ok(())
+    }
+
+    fn material(&mut self, ty: &str, params: &Params, name: Option<String>) -> MaterialIndex
+    {
+        let material = match ty
+        {
+            "glass" | "dielectric" | "thindielectric" =>
+            {
+                Material::Dielectric { ior: params.float(&["eta", "index"], 1.5), tint: None }
+            },
+            "metal" | "conductor" =>
+            {
+                let texture = self.color_texture(params, &["reflectance", "Ks"], 0.9);
+                let fuzz = params.float(&["roughness", "uroughness"], 0.0);
+
+                Material::Metal { texture, fuzz, normal_texture: None }
+            },
+            _ =>
+            {
+                if !matches!(ty, "matte" | "diffuse")
+                {
+                    self.ignored.insert(format!("{} material (imported as diffuse)", ty));
+                }
+
+                Material::Diffuse { texture: self.color_texture(params, &["reflectance", "Kd"], 0.5), normal_texture: None }
+            },
+        };
+
+        self.scene.collection.push_opt_name(material, name)
+    }
+
+    fn color_texture(&mut self, params: &Params, names: &[&str], default: Scalar) -> TextureIndex
+    {
+        if let Some(texture) = params.texture(names).and_then(|name| self.textures.get(&name).copied())
+        {
+            return texture;
+        }
+
+        let color = params.color(names).unwrap_or_else(|| LinearRGB::grey(default));
+
+        self.scene.collection.push(Texture::Solid(color.into()))
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params) -> Result<(), ImportError>
+    {
+        let matrix = self.world * self.state.matrix;
+
+        let geom = match ty
+        {
+            "sphere" =>
+            {
+                // Non-uniform scales can't be represented,
+                // so use the average scale
+
+                let radius = params.float(&["radius"], 1.0);
+                let scale = (matrix.mul_direction(Dir3::new(1.0, 0.0, 0.0)).magnitude()
+                    + matrix.mul_direction(Dir3::new(0.0, 1.0, 0.0)).magnitude()
+                    + matrix.mul_direction(Dir3::new(0.0, 0.0, 1.0)).magnitude()) / 3.0;
+
+                Geom::Sphere { center: matrix.mul_point(Point3::zero()), radius: radius * scale }
+            },
+            "trianglemesh" =>
+            {
+                let positions = params.floats(&["P"]).ok_or_else(|| ImportError("Triangle mesh has no positions".to_owned()))?;
+                let uvs = params.floats(&["uv", "st"]).unwrap_or_default();
+                let num_vertices = positions.len() / 3;
+                let indices = params.floats(&["indices"])
+                    .map(|i| i.into_iter().map(|i| i as usize).collect::<Vec<_>>())
+                    .unwrap_or_else(|| (0..num_vertices).collect());
+
+                if !indices.len().is_multiple_of(3) || indices.iter().any(|i| *i >= num_vertices)
+                {
+                    return Err(ImportError("Invalid triangle mesh indices".to_owned()));
+                }
+
+                let vertex = |i: usize| TriangleVertex
+                {
+                    location: matrix.mul_point(Point3::new(positions[3 * i], positions[3 * i + 1], positions[3 * i + 2])),
+                    texture_coords: if uvs.len() >= 2 * num_vertices { Point3::new(uvs[2 * i], uvs[2 * i + 1], 0.0) } else { Point3::zero() },
+                    opt_color: None,
+                    opt_normal: None,
+                };
+
+                let triangles = indices.chunks(3)
+                    .map(|t| Triangle { vertices: [vertex(t[0]), vertex(t[1]), vertex(t[2])] })
+                    .collect();
+
+                let triangles = import::cleanup_mesh(triangles, &mut self.cleanup);
+
+                Geom::Mesh { triangles, transform: Transform::new(), lod: None }
+            },
+            _ =>
+            {
+                self.ignored.insert(format!("{} shape", ty));
+                return Ok(());
+            },
+        };
+
+        let material = match self.state.emission
+        {
+            Some(radiance) =>
+            {
+                let texture = self.scene.collection.push(Texture::Solid(radiance.into()));
+                self.scene.collection.push(Material::Emit { texture })
+            },
+            None => match self.state.material.or(self.default_material)
+            {
+                Some(material) => material,
+                None =>
+                {
+                    let texture = self.scene.collection.push(Texture::Solid(LinearRGB::grey(0.5).into()));
+                    let material = self.scene.collection.push(Material::Diffuse { texture, normal_texture: None });
+
+                    self.default_material = Some(material);
+                    material
+                },
+            },
+        };
+
+        let geom = self.scene.collection.push(geom);
+        self.scene.collection.push(Object { geom, material, ray_bias: None, opacity: None });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::desc::edit::Scene;
+use crate::geom::Aabb;
+use crate::import::{FileSystemContext, ImportError};
+use crate::import::fbx::import_fbx_file;
+use crate::import::pbrt::import_pbrt_file;
+use crate::import::vox::import_vox_file;
+use crate::vec::Point3;
+
+/// Writes the file to a temporary directory, and imports it
+fn import_file<F>(name: &str, contents: &[u8], import: F) -> Result<Scene, String>
+    where F: Fn(&str, &mut Scene) -> Result<(), ImportError>
+{
+    let dir = std::env::temp_dir().join(format!("beam_test_import_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+
+    let mut scene = Scene::new();
+    let result = import(&path.to_string_lossy(), &mut scene);
+
+    let _ = std::fs::remove_file(&path);
+
+    result.map(|_| scene).map_err(|e| e.0)
+}
+
+fn destination() -> Aabb
+{
+    Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+}
+
+/// A binary FBX file, version 7400, with a node header for
+/// each end offset - with no properties, and no name
+fn binary_fbx(nodes: &[u32]) -> Vec<u8>
+{
+    let mut result = b"Kaydara FBX Binary  \0\x1a\0".to_vec();
+    result.extend_from_slice(&7400u32.to_le_bytes());
+
+    for end in nodes.iter()
+    {
+        result.extend_from_slice(&end.to_le_bytes());
+        result.extend_from_slice(&[0; 9]);
+    }
+
+    result
+}
+
+#[test]
+fn test_fbx_invalid_nodes()
+{
+    let import = |path: &str, scene: &mut Scene| import_fbx_file(path, &FileSystemContext::new(), &destination(), scene);
+
+    // A node that ends where it starts, or past the end of the file
+
+    let err = import_file("loop.fbx", &binary_fbx(&[27]), import).err().unwrap();
+    assert!(err.contains("end offset"), "{}", err);
+
+    let err = import_file("past_end.fbx", &binary_fbx(&[1000]), import).err().unwrap();
+    assert!(err.contains("end offset"), "{}", err);
+
+    // Nodes nested more deeply than any real file,
+    // each ending after the null record at the end
+
+    let len = 27 + 13 * 101;
+    let mut nodes = vec![len; 100];
+    nodes.push(0);
+
+    let err = import_file("nested.fbx", &binary_fbx(&nodes), import).err().unwrap();
+    assert!(err.contains("nested too deeply"), "{}", err);
+}
+
+/// An ASCII FBX file with a triangle, using the given
+/// polygon vertex and texture coordinate indexes
+fn ascii_fbx(polygon_vertex_index: &str, uv_index: &str) -> String
+{
+    format!(r#"
+        Objects:  {{
+            Geometry: 1, "Geometry::Triangle", "Mesh" {{
+                Vertices: *9 {{ a: 0,0,0,1,0,0,0,1,0 }}
+                PolygonVertexIndex: *3 {{ a: {} }}
+                LayerElementUV: 0 {{
+                    MappingInformationType: "ByPolygonVertex"
+                    ReferenceInformationType: "IndexToDirect"
+                    UV: *2 {{ a: 0,0 }}
+                    UVIndex: *3 {{ a: {} }}
+                }}
+            }}
+            Model: 2, "Model::Triangle", "Mesh" {{
+            }}
+        }}
+        Connections:  {{
+            C: "OO",2,0
+            C: "OO",1,2
+        }}"#, polygon_vertex_index, uv_index)
+}
+
+#[test]
+fn test_fbx_invalid_indexes()
+{
+    let import = |path: &str, scene: &mut Scene| import_fbx_file(path, &FileSystemContext::new(), &destination(), scene);
+
+    // Invalid texture coordinate indexes are skipped
+
+    let scene = import_file("uvs.fbx", ascii_fbx("0,1,-3", "-1,0,9223372036854775807").as_bytes(), import).unwrap();
+    assert_eq!(scene.collection.map_all(|_: &crate::desc::edit::Object, _| ()).len(), 1);
+
+    // Invalid vertex indexes are errors
+
+    let err = import_file("vertices.fbx", ascii_fbx("0,1,-9223372036854775808", "0,0,0").as_bytes(), import).err().unwrap();
+    assert!(err.contains("Vertex index"), "{}", err);
+}
+
+#[test]
+fn test_pbrt_include_cycle()
+{
+    let err = import_file("self.pbrt", b"Include \"self.pbrt\"", |path, scene| import_pbrt_file(path, &FileSystemContext::new(), scene)).err().unwrap();
+    assert!(err.contains("includes itself"), "{}", err);
+}
+
+fn vox_chunk(id: &[u8], content: &[i32]) -> Vec<u8>
+{
+    let mut result = id.to_vec();
+    result.extend_from_slice(&((content.len() * 4) as i32).to_le_bytes());
+    result.extend_from_slice(&0i32.to_le_bytes());
+    content.iter().for_each(|i| result.extend_from_slice(&i.to_le_bytes()));
+    result
+}
+
+/// A MagicaVoxel file with a single voxel at (255, 0, 0), and the
+/// given scene graph chunks - whose `_t` attributes are translations
+fn vox_file(nodes: &[Vec<u8>]) -> Vec<u8>
+{
+    let mut result = b"VOX ".to_vec();
+    result.extend_from_slice(&150i32.to_le_bytes());
+    result.extend(vox_chunk(b"MAIN", &[]));
+    result.extend(vox_chunk(b"SIZE", &[1, 1, 1]));
+    result.extend(vox_chunk(b"XYZI", &[1, 0xff]));
+    nodes.iter().for_each(|n| result.extend_from_slice(n));
+    result
+}
+
+fn vox_transform(id: i32, child: i32, translation: &str) -> Vec<u8>
+{
+    let mut result = vox_chunk(b"nTRN", &[id, 0, child, -1, -1, 1, 1, 2]);
+    result.extend_from_slice(b"_t");
+    result.extend_from_slice(&(translation.len() as i32).to_le_bytes());
+    result.extend_from_slice(translation.as_bytes());
+
+    // Fix up the content length for the frame's attributes
+
+    let len = (result.len() - 12) as i32;
+    result[4..8].copy_from_slice(&len.to_le_bytes());
+    result
+}
+
+#[test]
+fn test_vox_invalid_scene_graph()
+{
+    let import = |path: &str, scene: &mut Scene| import_vox_file(path, &FileSystemContext::new(), &destination(), scene);
+    let shape = |id: i32| vox_chunk(b"nSHP", &[id, 0, 1, 0, 0]);
+
+    assert!(import_file("valid.vox", &vox_file(&[vox_transform(0, 1, "1 2 3"), shape(1)]), import).is_ok());
+
+    // Groups that contain themselves
+
+    let err = import_file("cycle.vox", &vox_file(&[vox_chunk(b"nGRP", &[0, 0, 1, 0])]), import).err().unwrap();
+    assert!(err.contains("contains itself"), "{}", err);
+
+    // Groups that list the next group twice, so
+    // they expand to 2^40 instances of the shape
+
+    let mut nodes = (0..40).map(|i| vox_chunk(b"nGRP", &[i, 0, 2, i + 1, i + 1])).collect::<Vec<_>>();
+    nodes.push(shape(40));
+
+    let err = import_file("fan_out.vox", &vox_file(&nodes), import).err().unwrap();
+    assert!(err.contains("expands"), "{}", err);
+
+    // Translations that overflow, in the scene
+    // graph or when they're added to the voxels
+
+    let nodes = [vox_transform(0, 1, "2147483647 0 0"), vox_transform(1, 2, "1 0 0"), shape(2)];
+    let err = import_file("overflow.vox", &vox_file(&nodes), import).err().unwrap();
+    assert!(err.contains("out of range"), "{}", err);
+
+    let err = import_file("voxel_overflow.vox", &vox_file(&[vox_transform(0, 1, "2147483647 0 0"), shape(1)]), import).err().unwrap();
+    assert!(err.contains("out of range"), "{}", err);
+}
@@ -0,0 +1,578 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::color::LinearRGB;
+use crate::desc::edit::transform::TransformStage;
+use crate::desc::edit::{CleanupReport, Color, Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::geom::{Aabb, AabbBuilder};
+use crate::import;
+use crate::import::{FileSystemContext, ImportError};
+use crate::import::image::ColorSpace;
+use crate::indexed::{ImageIndex, MaterialIndex, TextureIndex, TransformIndex};
+use crate::math::{Scalar, ScalarConsts};
+use crate::vec::{Mat4, Point3, Quaternion, Vec3};
+
+use usda::{Layer, Prim, Value};
+
+pub mod usda;
+pub mod usdz;
+
+/// Imports the static meshes from a USD text layer (usda), or
+/// a USDZ package containing one, scaled to fit the destination
+/// box. UsdPreviewSurface materials are mapped to the closest
+/// beam material. Binary (usdc) layers, composition arcs and
+/// animation aren't supported - animated attributes use their
+/// first time sample.
+pub fn import_usd_file(path: &str, context: &FileSystemContext, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
+{
+    let filename = context.path_to_filename(path);
+    let (contents, sub_context) = context.load_binary_file(path)?;
+
+    let (layer, assets) = if contents.starts_with(b"PK\x03\x04")
+    {
+        let package = usdz::read(&contents, path)?;
+        let root = String::from_utf8_lossy(&package.files[&package.root]).into_owned();
+        let layer = usda::parse(&root, &format!("{}/{}", path, package.root))?;
+
+        (layer, Assets::Package(package))
+    }
+    else
+    {
+        (usda::parse(&String::from_utf8_lossy(&contents), path)?, Assets::Files(sub_context))
+    };
+
+    let mut scene_transform = Transform::default();
+    let scene_transform_index = scene.collection.push_named(scene_transform.clone(), filename.clone());
+
+    // Beam is Y-up, so Z-up stages are rotated
+
+    let up_axis = match layer.metadata("upAxis")
+    {
+        Some(Value::Str(axis)) if axis == "Z" => Mat4::rotation_3d(-0.5 * ScalarConsts::PI, Vec3::new(1.0, 0.0, 0.0)),
+        _ => Mat4::identity(),
+    };
+
+    let mut importer = Importer
+    {
+        scene,
+        layer: &layer,
+        assets,
+        filename,
+        root: (scene_transform_index, up_axis),
+        materials: HashMap::new(),
+        images: HashMap::new(),
+        default_material: None,
+        aabb_builder: AabbBuilder::new(),
+        num_meshes: 0,
+        ignored: BTreeSet::new(),
+        cleanup: CleanupReport::default(),
+    };
+
+    for prim in layer.prims.iter()
+    {
+        importer.import_prim(prim, scene_transform_index, up_axis, None)?;
+    }
+
+    if importer.num_meshes == 0
+    {
+        return Err(importer.error(path, "No meshes found"));
+    }
+
+    if !importer.ignored.is_empty()
+    {
+        println!("USD: Ignored unsupported features: {}", importer.ignored.iter().cloned().collect::<Vec<_>>().join(", "));
+    }
+
+    import::print_cleanup_report("USD", &importer.cleanup);
+
+    let aabb = importer.aabb_builder.build();
+
+    scene_transform.stages.push(TransformStage::Matrix(up_axis));
+    scene_transform.stages.push(TransformStage::ShiftAndScale
+        {
+            from: crate::desc::edit::geom::Aabb{ min: aabb.min, max: aabb.max },
+            to: crate::desc::edit::geom::Aabb{ min: destination.min, max: destination.max },
+            maintain_aspect: true,
+        });
+    importer.scene.collection.update_value(scene_transform_index, scene_transform);
+
+    Ok(())
+}
+
+/// Where asset paths (e.g. textures) are loaded from
+enum Assets
+{
+    Files(FileSystemContext),
+    Package(usdz::Package),
+}
+
+/// Texture coordinates for a face vertex index and point index
+type TextureCoords<'a> = Box<dyn Fn(usize, usize) -> Point3 + 'a>;
+
+/// A shader input - either a value, or a connected texture
+enum Input<'a>
+{
+    Value(Vec<Scalar>),
+    Texture(&'a Prim),
+}
+
+struct Importer<'a, 'b>
+{
+    scene: &'a mut Scene,
+    layer: &'b Layer,
+    assets: Assets,
+    filename: String,
+    /// The scene transform, and its matrix, for
+    /// prims that reset the transform stack
+    root: (TransformIndex, Mat4),
+    materials: HashMap<String, MaterialIndex>,
+    images: HashMap<String, ImageIndex>,
+    default_material: Option<MaterialIndex>,
+    aabb_builder: AabbBuilder,
+    num_meshes: usize,
+    ignored: BTreeSet<String>,
+    /// Totals for all of the file's meshes
+    cleanup: CleanupReport,
+}
+
+impl<'a, 'b> Importer<'a, 'b>
+{
+    fn error(&self, path: &str, msg: &str) -> ImportError
+    {
+        ImportError(format!("USD Error: {}: {}: {}", self.filename, path, msg))
+    }
+
+    fn import_prim(&mut self, prim: &'b Prim, parent_transform: TransformIndex, parent_matrix: Mat4, inherited_material: Option<&'b str>) -> Result<(), ImportError>
+    {
+        // Classes and overs don't define anything
+        // by themselves, and shading networks are
+        // only imported when bound
+
+        if prim.specifier != "def"
+            || prim.metadata("active") == Some(&Value::Bool(false))
+            || prim.string("visibility") == Some("invisible")
+            || matches!(prim.string("purpose"), Some("proxy") | Some("guide"))
+            || matches!(prim.type_name.as_str(), "Material" | "Shader" | "NodeGraph" | "GeomSubset")
+        {
+            return Ok(());
+        }
+
+        if prim.metadata("references").is_some() || prim.metadata("payload").is_some()
+        {
+            self.ignored.insert("references and payloads".to_owned());
+        }
+
+        let (mut transform, mut matrix) = (parent_transform, parent_matrix);
+
+        if let Some((local, reset)) = self.local_matrix(prim)?
+        {
+            if reset
+            {
+                (transform, matrix) = self.root;
+            }
+
+            transform = self.scene.collection.push_named(
+                Transform { pre: None, stages: vec![TransformStage::Matrix(local)], post: Some(transform) },
+                prim.name.clone());
+            matrix *= local;
+        }
+
+        let material = prim.target("material:binding").or(inherited_material);
+
+        match prim.type_name.as_str()
+        {
+            "Mesh" => self.import_mesh(prim, transform, matrix, material)?,
+            "" | "Xform" | "Scope" => {},
+            other =>
+            {
+                self.ignored.insert(format!("{} prims", other));
+            },
+        }
+
+        for child in prim.children.iter()
+        {
+            self.import_prim(child, transform, matrix, material)?;
+        }
+
+        Ok(())
+    }
+
+    /// The prim's local transform, from its xformOps, and
+    /// whether it ignores its parent's transform
+    fn local_matrix(&self, prim: &Prim) -> Result<Option<(Mat4, bool)>, ImportError>
+    {
+        let order = match prim.value("xformOpOrder")
+        {
+            Some(order) => order.list(),
+            None => return Ok(None),
+        };
+
+        let mut result = Mat4::identity();
+        let mut reset = false;
+
+        for op in order.iter().filter_map(|op| op.as_str())
+        {
+            if op == "!resetXformStack!"
+            {
+                reset = true;
+                continue;
+            }
+
+            let (invert, name) = match op.strip_prefix("!invert!")
+            {
+                Some(name) => (true, name),
+                None => (false, op),
+            };
+
+            let v = prim.scalars(name).ok_or_else(|| self.error(&prim.path, &format!("Missing value for {}", name)))?;
+            let kind = name.split(':').nth(1).unwrap_or_default();
+
+            let needed = match kind
+            {
+                "transform" => 16,
+                "orient" => 4,
+                "rotateX" | "rotateY" | "rotateZ" => 1,
+                _ => 3,
+            };
+
+            if v.len() < needed
+            {
+                return Err(self.error(&prim.path, &format!("Expected {} values for {}", needed, name)));
+            }
+
+            let rotate = |axis: char, degrees: Scalar|
+            {
+                let axis = match axis
+                {
+                    'X' => Vec3::new(1.0, 0.0, 0.0),
+                    'Y' => Vec3::new(0.0, 1.0, 0.0),
+                    _ => Vec3::new(0.0, 0.0, 1.0),
+                };
+
+                Mat4::rotation_3d(degrees.to_radians(), axis)
+            };
+
+            let matrix = match kind
+            {
+                "translate" => Mat4::translation_3d(Vec3::new(v[0], v[1], v[2])),
+                "scale" => Mat4::scaling_3d(Vec3::new(v[0], v[1], v[2])),
+                "rotateX" | "rotateY" | "rotateZ" => rotate(kind.chars().last().unwrap(), v[0]),
+                "orient" => Mat4::from(Quaternion::from_xyzw(v[1], v[2], v[3], v[0]).normalized()),
+                // USD matrices are row-major, for row vectors,
+                // so each row is one of beam's columns
+                "transform" =>
+                {
+                    let mut values = [0.0; 16];
+                    values.copy_from_slice(&v[0..16]);
+                    Mat4::from_col_array(values)
+                },
+                _ if kind.len() == 9 && kind.starts_with("rotate") =>
+                {
+                    // e.g. rotateXYZ rotates around X first
+
+                    kind[6..].chars().zip(v.iter())
+                        .fold(Mat4::identity(), |m, (axis, degrees)| rotate(axis, *degrees) * m)
+                },
+                _ => return Err(self.error(&prim.path, &format!("Unsupported transform {}", name))),
+            };
+
+            result *= if invert { matrix.inverted() } else { matrix };
+        }
+
+        Ok(Some((result, reset)))
+    }
+
+    fn import_mesh(&mut self, prim: &'b Prim, transform: TransformIndex, matrix: Mat4, material: Option<&'b str>) -> Result<(), ImportError>
+    {
+        let points = prim.scalars("points").unwrap_or_default();
+        let counts = prim.scalars("faceVertexCounts").unwrap_or_default();
+        let indices = prim.scalars("faceVertexIndices").unwrap_or_default()
+            .into_iter().map(|i| i as usize).collect::<Vec<_>>();
+
+        let num_points = points.len() / 3;
+
+        if indices.iter().any(|i| *i >= num_points)
+        {
+            return Err(self.error(&prim.path, &format!("Face vertex index is larger than the point count {}", num_points)));
+        }
+        else if counts.iter().map(|c| *c as usize).sum::<usize>() != indices.len()
+        {
+            return Err(self.error(&prim.path, "Face vertex counts don't match the number of face vertex indices"));
+        }
+
+        let point = |i: usize| Point3::new(points[3 * i], points[3 * i + 1], points[3 * i + 2]);
+        let texture_coords = self.texture_coords(prim, num_points, indices.len());
+        let left_handed = prim.string("orientation") == Some("leftHanded");
+
+        let mut triangles = Vec::new();
+        let mut first = 0;
+
+        for count in counts.iter().map(|c| *c as usize)
+        {
+            let vertex = |corner: usize| TriangleVertex
+            {
+                location: point(indices[first + corner]),
+                texture_coords: texture_coords.as_ref().map(|t| t(first + corner, indices[first + corner])).unwrap_or_else(Point3::zero),
+                opt_color: None,
+                opt_normal: None,
+            };
+
+            // Faces are triangulated as fans
+
+            for i in 1..count.saturating_sub(1)
+            {
+                let (b, c) = if left_handed { (i + 1, i) } else { (i, i + 1) };
+                let vertices = [vertex(0), vertex(b), vertex(c)];
+
+                self.aabb_builder.add_triangle(matrix.mul_point(vertices[0].location), matrix.mul_point(vertices[1].location), matrix.mul_point(vertices[2].location));
+                triangles.push(Triangle { vertices });
+            }
+
+            first += count;
+        }
+
+        let material = match material
+        {
+            Some(path) => self.import_material(path)?,
+            None => self.default_material(prim),
+        };
+
+        let mut geom_transform = Transform::new();
+        geom_transform.post = Some(transform);
+
+        let triangles = import::cleanup_mesh(triangles, &mut self.cleanup);
+        let geom = self.scene.collection.push_named(Geom::Mesh { triangles, transform: geom_transform, lod: None }, prim.name.clone());
+        self.scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, prim.name.clone());
+        self.num_meshes += 1;
+
+        Ok(())
+    }
+
+    /// Looks up the texture coordinates of a face vertex, from
+    /// its index within the faces and the point it uses
+    fn texture_coords(&self, prim: &'b Prim, num_points: usize, num_face_vertices: usize) -> Option<TextureCoords<'b>>
+    {
+        let property = prim.property("primvars:st")
+            .or_else(|| prim.properties.iter().find(|p| p.name.starts_with("primvars:") && p.type_name == "texCoord2f[]"))?;
+
+        let values = prim.scalars(&property.name)?;
+        let indices = prim.scalars(&format!("{}:indices", property.name))
+            .map(|i| i.into_iter().map(|i| i as usize).collect::<Vec<_>>());
+
+        let num_values = indices.as_ref().map(|i| i.len()).unwrap_or(values.len() / 2);
+
+        let face_varying = match property.metadata("interpolation").and_then(|i| i.as_str())
+        {
+            Some("faceVarying") => true,
+            Some("vertex") | Some("varying") => false,
+            _ => num_values != num_points,
+        };
+
+        if num_values < (if face_varying { num_face_vertices } else { num_points })
+            || indices.as_ref().map(|i| i.iter().any(|i| (2 * i + 1) >= values.len())).unwrap_or(false)
+        {
+            return None;
+        }
+
+        // USD's texture coordinates start from the bottom left
+
+        Some(Box::new(move |face_vertex, point|
+        {
+            let i = if face_varying { face_vertex } else { point };
+            let i = indices.as_ref().map(|indices| indices[i]).unwrap_or(i);
+
+            Point3::new(values[2 * i], 1.0 - values[2 * i + 1], 0.0)
+        }))
+    }
+
+    /// Unbound meshes use their display color
+    fn default_material(&mut self, prim: &Prim) -> MaterialIndex
+    {
+        if let Some(color) = prim.scalars("primvars:displayColor").filter(|c| c.len() >= 3)
+        {
+            let texture = self.scene.collection.push(Texture::Solid(LinearRGB::new(color[0], color[1], color[2], 1.0).into()));
+            return self.scene.collection.push(Material::Diffuse { texture, normal_texture: None });
+        }
+
+        if let Some(material) = self.default_material
+        {
+            return material;
+        }
+
+        let texture = self.scene.collection.push(Texture::Solid(LinearRGB::grey(0.5).into()));
+        let material = self.scene.collection.push(Material::Diffuse { texture, normal_texture: None });
+
+        self.default_material = Some(material);
+        material
+    }
+
+    fn import_material(&mut self, path: &str) -> Result<MaterialIndex, ImportError>
+    {
+        if let Some(existing) = self.materials.get(path)
+        {
+            return Ok(*existing);
+        }
+
+        let layer = self.layer;
+        let material_prim = layer.find(path).ok_or_else(|| self.error(path, "Bound material not found"))?;
+
+        let surface = material_prim.target("outputs:surface")
+            .and_then(|target| layer.find(target))
+            .or_else(|| material_prim.children.iter().find(|c| c.string("info:id") == Some("UsdPreviewSurface")));
+
+        let material = match surface
+        {
+            Some(surface) if surface.string("info:id") == Some("UsdPreviewSurface") =>
+            {
+                self.map_material(surface)?
+            },
+            _ =>
+            {
+                self.ignored.insert("non-UsdPreviewSurface materials (imported as grey)".to_owned());
+
+                let texture = self.scene.collection.push(Texture::Solid(LinearRGB::grey(0.5).into()));
+                Material::Diffuse { texture, normal_texture: None }
+            },
+        };
+
+        let index = self.scene.collection.push_named(material, material_prim.name.clone());
+        self.materials.insert(path.to_owned(), index);
+
+        Ok(index)
+    }
+
+    fn map_material(&mut self, surface: &'b Prim) -> Result<Material, ImportError>
+    {
+        let scalar = |name: &str, default: Scalar| match self.input(surface, name)
+        {
+            Some(Input::Value(v)) if !v.is_empty() => v[0],
+            _ => default,
+        };
+
+        let metallic = scalar("metallic", 0.0);
+        let roughness = scalar("roughness", 0.5);
+        let opacity = scalar("opacity", 1.0);
+        let ior = scalar("ior", 1.5);
+
+        let emissive = self.input(surface, "emissiveColor");
+
+        let is_emissive = match &emissive
+        {
+            Some(Input::Value(v)) => v.iter().any(|c| *c > 0.0),
+            Some(Input::Texture(_)) => true,
+            None => false,
+        };
+
+        if is_emissive
+        {
+            let texture = self.import_texture(surface, "emissiveColor", emissive, LinearRGB::grey(0.0))?;
+            return Ok(Material::Emit { texture });
+        }
+
+        if opacity < 1.0
+        {
+            return Ok(Material::Dielectric { ior, tint: None });
+        }
+
+        let diffuse = self.input(surface, "diffuseColor");
+        let texture = self.import_texture(surface, "diffuseColor", diffuse, LinearRGB::grey(0.18))?;
+
+        if metallic < 0.5
+        {
+            Ok(Material::Diffuse { texture, normal_texture: None })
+        }
+        else
+        {
+            Ok(Material::Metal { texture, fuzz: roughness.powf(2.0), normal_texture: None })
+        }
+    }
+
+    /// An input's value, or the texture it's connected to - inputs
+    /// connected to the material's interface use its value
+    fn input(&self, shader: &'b Prim, name: &str) -> Option<Input<'b>>
+    {
+        let input = format!("inputs:{}", name);
+
+        if let Some(target) = shader.target(&input)
+        {
+            let prim = self.layer.find(target)?;
+
+            if prim.string("info:id") == Some("UsdUVTexture")
+            {
+                return Some(Input::Texture(prim));
+            }
+
+            let property = target.rsplit_once('.').map(|(_, p)| p)?;
+            return prim.scalars(property).map(Input::Value);
+        }
+
+        shader.scalars(&input).map(Input::Value)
+    }
+
+    fn import_texture(&mut self, shader: &Prim, part: &str, input: Option<Input>, default: LinearRGB) -> Result<TextureIndex, ImportError>
+    {
+        let texture = match input
+        {
+            Some(Input::Value(v)) if v.len() >= 3 =>
+            {
+                Texture::Solid(LinearRGB::new(v[0], v[1], v[2], 1.0).into())
+            },
+            Some(Input::Texture(texture)) =>
+            {
+                // The texture's scale multiplies its color
+
+                let base_color = match texture.scalars("inputs:scale")
+                {
+                    Some(s) if s.len() >= 3 => LinearRGB::new(s[0], s[1], s[2], 1.0).into(),
+                    _ => Color::default(),
+                };
+
+                match self.import_image(texture)?
+                {
+                    Some(image) => Texture::Image { base_color, image, scale: Point3::new(1.0, 1.0, 1.0), rotate: 0.0, translate: Point3::zero() },
+                    None => Texture::Solid(default.into()),
+                }
+            },
+            _ => Texture::Solid(default.into()),
+        };
+
+        Ok(self.scene.collection.push_named(texture, format!("{} ({})", shader.name, part)))
+    }
+
+    fn import_image(&mut self, texture: &Prim) -> Result<Option<ImageIndex>, ImportError>
+    {
+        let file = match texture.string("inputs:file")
+        {
+            Some(file) => file.to_owned(),
+            None => return Ok(None),
+        };
+
+        if let Some(existing) = self.images.get(&file)
+        {
+            return Ok(Some(*existing));
+        }
+
+        let filename = &self.filename;
+
+        let image = match &mut self.assets
+        {
+            Assets::Files(context) => import::image::import_image(&file, context)?,
+            Assets::Package(package) =>
+            {
+                let contents = package.file(&file).ok_or_else(|| ImportError(format!("USD Error: {}: Texture {} not found in the package", filename, file)))?;
+                import::image::import_image_from_memory(contents.clone())?
+            },
+        };
+
+        match texture.string("inputs:sourceColorSpace")
+        {
+            Some("raw") => image.set_color_space(ColorSpace::Linear),
+            Some("sRGB") => image.set_color_space(ColorSpace::Srgb),
+            _ => {},
+        }
+
+        let index = self.scene.collection.push_named(image, file.clone());
+        self.images.insert(file, index);
+
+        Ok(Some(index))
+    }
+}
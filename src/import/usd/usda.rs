@@ -0,0 +1,657 @@
+use crate::import::ImportError;
+use crate::math::Scalar;
+
+/// A USD layer in the text (usda) format. Only the
+/// prim hierarchy and property values are kept - list
+/// editing, variants and composition arcs are ignored.
+pub struct Layer
+{
+    pub metadata: Vec<(String, Value)>,
+    pub prims: Vec<Prim>,
+}
+
+pub struct Prim
+{
+    /// `def`, `over` or `class`
+    pub specifier: String,
+    pub type_name: String,
+    pub name: String,
+    /// The full path, e.g. `/Root/Geom/Mesh`
+    pub path: String,
+    pub metadata: Vec<(String, Value)>,
+    pub properties: Vec<Property>,
+    pub children: Vec<Prim>,
+}
+
+/// An attribute or relationship. Connections and time
+/// samples are stored under their full names, e.g.
+/// `inputs:diffuseColor.connect`.
+pub struct Property
+{
+    pub type_name: String,
+    pub name: String,
+    pub value: Value,
+    pub metadata: Vec<(String, Value)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value
+{
+    None,
+    Bool(bool),
+    Number(Scalar),
+    /// Strings and tokens
+    Str(String),
+    Asset(String),
+    Path(String),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    TimeSamples(Vec<(Scalar, Value)>),
+    /// Dictionaries aren't needed, so their contents are skipped
+    Dictionary,
+}
+
+impl Value
+{
+    pub fn as_scalar(&self) -> Option<Scalar>
+    {
+        match self
+        {
+            Value::Number(n) => Some(*n),
+            Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str>
+    {
+        match self
+        {
+            Value::Str(s) | Value::Asset(s) | Value::Path(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The numbers in a tuple, or in a list of tuples
+    pub fn flatten_scalars(&self) -> Vec<Scalar>
+    {
+        let mut result = Vec::new();
+        self.push_scalars(&mut result);
+        result
+    }
+
+    fn push_scalars(&self, result: &mut Vec<Scalar>)
+    {
+        match self
+        {
+            Value::Tuple(values) | Value::List(values) => values.iter().for_each(|v| v.push_scalars(result)),
+            _ => result.extend(self.as_scalar()),
+        }
+    }
+
+    pub fn list(&self) -> &[Value]
+    {
+        match self
+        {
+            Value::List(values) => values,
+            _ => std::slice::from_ref(self),
+        }
+    }
+}
+
+impl Prim
+{
+    pub fn metadata(&self, name: &str) -> Option<&Value>
+    {
+        self.metadata.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property>
+    {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    /// The value of an attribute - animated attributes
+    /// use their first time sample
+    pub fn value(&self, name: &str) -> Option<&Value>
+    {
+        if let Some(property) = self.property(name)
+        {
+            if property.value != Value::None
+            {
+                return Some(&property.value);
+            }
+        }
+
+        match self.property(&format!("{}.timeSamples", name)).map(|p| &p.value)
+        {
+            Some(Value::TimeSamples(samples)) => samples.first().map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn scalar(&self, name: &str) -> Option<Scalar>
+    {
+        self.value(name).and_then(|v| v.as_scalar())
+    }
+
+    pub fn scalars(&self, name: &str) -> Option<Vec<Scalar>>
+    {
+        self.value(name).map(|v| v.flatten_scalars())
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str>
+    {
+        self.value(name).and_then(|v| v.as_str())
+    }
+
+    /// The target of a connection or relationship, e.g.
+    /// `inputs:diffuseColor` or `material:binding`
+    pub fn target(&self, name: &str) -> Option<&str>
+    {
+        let property = self.property(&format!("{}.connect", name)).or_else(|| self.property(name))?;
+
+        match &property.value
+        {
+            Value::Path(path) => Some(path),
+            Value::List(values) => values.first().and_then(|v| v.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn find(&self, path: &str) -> Option<&Prim>
+    {
+        if self.path == path
+        {
+            return Some(self);
+        }
+
+        self.children.iter()
+            .filter(|c| path.starts_with(&c.path))
+            .find_map(|c| c.find(path))
+    }
+}
+
+impl Property
+{
+    pub fn metadata(&self, name: &str) -> Option<&Value>
+    {
+        self.metadata.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+impl Layer
+{
+    pub fn metadata(&self, name: &str) -> Option<&Value>
+    {
+        self.metadata.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// Finds a prim by its path - properties
+    /// (e.g. `/Mat/Shader.outputs:rgb`) are ignored
+    pub fn find(&self, path: &str) -> Option<&Prim>
+    {
+        let path = path.split('.').next().unwrap_or(path);
+
+        self.prims.iter().find_map(|p| p.find(path))
+    }
+}
+
+pub fn parse(contents: &str, context: &str) -> Result<Layer, ImportError>
+{
+    let error = |msg: String| ImportError(format!("USD Error: {}: {}", context, msg));
+
+    if !contents.starts_with("#usda")
+    {
+        return Err(error("Not a text (usda) layer - binary (usdc) layers aren't supported".to_owned()));
+    }
+
+    let tokens = tokenize(contents).map_err(error)?;
+    let mut parser = Parser { tokens, pos: 0 };
+
+    parser.parse_layer().map_err(|msg| error(format!("Token {}: {}", parser.pos, msg)))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token
+{
+    Ident(String),
+    Str(String),
+    Asset(String),
+    Path(String),
+    Num(Scalar),
+    Punct(char),
+}
+
+fn tokenize(contents: &str) -> Result<Vec<Token>, String>
+{
+    let chars = contents.chars().collect::<Vec<_>>();
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    let read_until = |i: &mut usize, end: char| -> Result<String, String>
+    {
+        let start = *i;
+
+        while *i < chars.len() && chars[*i] != end
+        {
+            *i += 1;
+        }
+
+        if *i >= chars.len()
+        {
+            return Err(format!("Missing closing {:?}", end));
+        }
+
+        *i += 1;
+        Ok(chars[start..(*i - 1)].iter().collect())
+    };
+
+    while i < chars.len()
+    {
+        let c = chars[i];
+
+        if c.is_whitespace()
+        {
+            i += 1;
+        }
+        else if c == '#'
+        {
+            while i < chars.len() && chars[i] != '\n'
+            {
+                i += 1;
+            }
+        }
+        else if c == '"' || c == '\''
+        {
+            // Single or triple quoted, with escapes
+
+            let triple = chars[i..].starts_with(&[c, c, c]);
+            i += if triple { 3 } else { 1 };
+
+            let mut s = String::new();
+
+            loop
+            {
+                if i >= chars.len()
+                {
+                    return Err("Unterminated string".to_owned());
+                }
+                else if chars[i] == '\\' && i + 1 < chars.len()
+                {
+                    s.push(match chars[i + 1] { 'n' => '\n', 't' => '\t', other => other });
+                    i += 2;
+                }
+                else if chars[i] == c && (!triple || chars[i..].starts_with(&[c, c, c]))
+                {
+                    i += if triple { 3 } else { 1 };
+                    break;
+                }
+                else
+                {
+                    s.push(chars[i]);
+                    i += 1;
+                }
+            }
+
+            result.push(Token::Str(s));
+        }
+        else if c == '@'
+        {
+            i += 1;
+            result.push(Token::Asset(read_until(&mut i, '@')?));
+        }
+        else if c == '<'
+        {
+            i += 1;
+            result.push(Token::Path(read_until(&mut i, '>')?));
+        }
+        else if c.is_ascii_digit() || ((c == '-' || c == '.') && chars.get(i + 1).map(|n| n.is_ascii_digit() || *n == '.' || *n == 'i').unwrap_or(false))
+        {
+            let start = i;
+            i += 1;
+
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '.' | '-' | '+'))
+            {
+                // Only allow a sign straight after an exponent
+
+                if matches!(chars[i], '-' | '+') && !matches!(chars[i - 1], 'e' | 'E')
+                {
+                    break;
+                }
+
+                i += 1;
+            }
+
+            let text = chars[start..i].iter().collect::<String>();
+            let num = text.parse::<Scalar>().map_err(|_| format!("Invalid number {:?}", text))?;
+
+            result.push(Token::Num(num));
+        }
+        else if c.is_alphabetic() || c == '_'
+        {
+            let start = i;
+
+            while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | ':' | '.'))
+            {
+                i += 1;
+            }
+
+            let text = chars[start..i].iter().collect::<String>();
+
+            match text.as_str()
+            {
+                "inf" => result.push(Token::Num(Scalar::INFINITY)),
+                "nan" => result.push(Token::Num(Scalar::NAN)),
+                _ => result.push(Token::Ident(text)),
+            }
+        }
+        else
+        {
+            result.push(Token::Punct(c));
+            i += 1;
+        }
+    }
+
+    Ok(result)
+}
+
+struct Parser
+{
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser
+{
+    fn peek(&self) -> Option<&Token>
+    {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String>
+    {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| "Unexpected end of file".to_owned())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is_punct(&self, c: char) -> bool
+    {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn accept_punct(&mut self, c: char) -> bool
+    {
+        let result = self.is_punct(c);
+
+        if result
+        {
+            self.pos += 1;
+        }
+
+        result
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), String>
+    {
+        match self.next()?
+        {
+            Token::Punct(p) if p == c => Ok(()),
+            other => Err(format!("Expected {:?} but got {:?}", c, other)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String>
+    {
+        match self.next()?
+        {
+            Token::Ident(s) => Ok(s),
+            other => Err(format!("Expected an identifier but got {:?}", other)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String>
+    {
+        match self.next()?
+        {
+            Token::Str(s) => Ok(s),
+            other => Err(format!("Expected a string but got {:?}", other)),
+        }
+    }
+
+    /// Skips a bracketed block, starting at the opening bracket
+    fn skip_block(&mut self) -> Result<(), String>
+    {
+        let mut depth = 0;
+
+        loop
+        {
+            match self.next()?
+            {
+                Token::Punct('(') | Token::Punct('[') | Token::Punct('{') => depth += 1,
+                Token::Punct(')') | Token::Punct(']') | Token::Punct('}') => depth -= 1,
+                _ => {},
+            }
+
+            if depth == 0
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_layer(&mut self) -> Result<Layer, String>
+    {
+        let metadata = if self.is_punct('(') { self.parse_metadata()? } else { Vec::new() };
+        let mut prims = Vec::new();
+
+        while self.peek().is_some()
+        {
+            let specifier = self.ident()?;
+
+            match specifier.as_str()
+            {
+                "def" | "over" | "class" => prims.push(self.parse_prim(specifier, "")?),
+                other => return Err(format!("Expected a prim but got {:?}", other)),
+            }
+        }
+
+        Ok(Layer { metadata, prims })
+    }
+
+    /// A parenthesized list of `name = value` entries
+    fn parse_metadata(&mut self) -> Result<Vec<(String, Value)>, String>
+    {
+        let mut result = Vec::new();
+
+        self.expect_punct('(')?;
+
+        while !self.accept_punct(')')
+        {
+            match self.next()?
+            {
+                Token::Ident(mut name) =>
+                {
+                    // List edits, e.g. `prepend apiSchemas = [...]`
+
+                    if matches!(name.as_str(), "prepend" | "append" | "add" | "delete" | "reorder")
+                    {
+                        name = self.ident()?;
+                    }
+
+                    let value = if self.accept_punct('=') { self.parse_value()? } else { Value::None };
+
+                    result.push((name, value));
+                },
+                // Documentation strings and separators
+                Token::Str(_) | Token::Punct(';') | Token::Punct(',') => {},
+                other => return Err(format!("Unexpected {:?} in metadata", other)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String>
+    {
+        match self.peek().cloned()
+        {
+            Some(Token::Punct('(')) =>
+            {
+                self.pos += 1;
+                Ok(Value::Tuple(self.parse_sequence(')')?))
+            },
+            Some(Token::Punct('[')) =>
+            {
+                self.pos += 1;
+                Ok(Value::List(self.parse_sequence(']')?))
+            },
+            Some(Token::Punct('{')) =>
+            {
+                // Time samples are keyed by numbers -
+                // anything else is a dictionary
+
+                if let Some(Token::Num(_)) = self.tokens.get(self.pos + 1)
+                {
+                    self.pos += 1;
+
+                    let mut samples = Vec::new();
+
+                    while !self.accept_punct('}')
+                    {
+                        let time = match self.next()? { Token::Num(n) => n, other => return Err(format!("Expected a time but got {:?}", other)) };
+                        self.expect_punct(':')?;
+                        samples.push((time, self.parse_value()?));
+                        self.accept_punct(',');
+                    }
+
+                    Ok(Value::TimeSamples(samples))
+                }
+                else
+                {
+                    self.skip_block()?;
+                    Ok(Value::Dictionary)
+                }
+            },
+            Some(_) =>
+            {
+                match self.next()?
+                {
+                    Token::Num(n) => Ok(Value::Number(n)),
+                    Token::Str(s) => Ok(Value::Str(s)),
+                    Token::Asset(s) =>
+                    {
+                        // Asset references can target a prim
+
+                        if let Some(Token::Path(_)) = self.peek()
+                        {
+                            self.pos += 1;
+                        }
+
+                        Ok(Value::Asset(s))
+                    },
+                    Token::Path(s) => Ok(Value::Path(s)),
+                    Token::Ident(s) => Ok(match s.as_str()
+                    {
+                        "None" => Value::None,
+                        "true" => Value::Bool(true),
+                        "false" => Value::Bool(false),
+                        _ => Value::Str(s),
+                    }),
+                    other => Err(format!("Expected a value but got {:?}", other)),
+                }
+            },
+            None => Err("Expected a value".to_owned()),
+        }
+    }
+
+    fn parse_sequence(&mut self, end: char) -> Result<Vec<Value>, String>
+    {
+        let mut result = Vec::new();
+
+        while !self.accept_punct(end)
+        {
+            result.push(self.parse_value()?);
+            self.accept_punct(',');
+        }
+
+        Ok(result)
+    }
+
+    /// Parses a prim, after its specifier
+    fn parse_prim(&mut self, specifier: String, parent_path: &str) -> Result<Prim, String>
+    {
+        let type_name = match self.peek()
+        {
+            Some(Token::Ident(_)) => self.ident()?,
+            _ => String::new(),
+        };
+
+        let name = self.string()?;
+        let path = format!("{}/{}", parent_path, name);
+
+        let metadata = if self.is_punct('(') { self.parse_metadata()? } else { Vec::new() };
+
+        self.expect_punct('{')?;
+
+        let mut properties = Vec::new();
+        let mut children = Vec::new();
+
+        while !self.accept_punct('}')
+        {
+            let mut word = self.ident()?;
+
+            match word.as_str()
+            {
+                "def" | "over" | "class" =>
+                {
+                    children.push(self.parse_prim(word, &path)?);
+                    continue;
+                },
+                "variantSet" =>
+                {
+                    // Only the default variants are used,
+                    // so the alternatives can be skipped
+
+                    self.string()?;
+                    self.expect_punct('=')?;
+                    self.skip_block()?;
+                    continue;
+                },
+                "reorder" =>
+                {
+                    self.ident()?;
+                    self.expect_punct('=')?;
+                    self.parse_value()?;
+                    continue;
+                },
+                _ => {},
+            }
+
+            while matches!(word.as_str(), "prepend" | "append" | "add" | "delete" | "custom" | "uniform" | "varying" | "config")
+            {
+                word = self.ident()?;
+            }
+
+            // Relationships have no type, while
+            // attribute types can be arrays
+
+            let mut type_name = word;
+
+            if type_name != "rel" && self.accept_punct('[')
+            {
+                self.expect_punct(']')?;
+                type_name.push_str("[]");
+            }
+
+            let name = self.ident()?;
+            let value = if self.accept_punct('=') { self.parse_value()? } else { Value::None };
+            let metadata = if self.is_punct('(') { self.parse_metadata()? } else { Vec::new() };
+
+            properties.push(Property { type_name, name, value, metadata });
+        }
+
+        Ok(Prim { specifier, type_name, name, path, metadata, properties, children })
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::import::ImportError;
+
+/// The files in a USDZ package. The format is a zip archive
+/// whose entries must be stored uncompressed, so only the
+/// local file headers need to be read.
+pub struct Package
+{
+    /// The first layer in the archive, which is the root layer
+    pub root: String,
+    pub files: HashMap<String, Vec<u8>>,
+}
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const LOCAL_FILE_HEADER_LEN: usize = 30;
+
+pub fn read(contents: &[u8], context: &str) -> Result<Package, ImportError>
+{
+    let error = |msg: &str| ImportError(format!("USDZ Error: {}: {}", context, msg));
+
+    let u16_at = |pos: usize| u16::from_le_bytes([contents[pos], contents[pos + 1]]) as usize;
+    let u32_at = |pos: usize| u32::from_le_bytes([contents[pos], contents[pos + 1], contents[pos + 2], contents[pos + 3]]);
+
+    let mut root = None;
+    let mut files = HashMap::new();
+    let mut pos = 0;
+
+    while (pos + LOCAL_FILE_HEADER_LEN) <= contents.len() && u32_at(pos) == LOCAL_FILE_HEADER
+    {
+        let flags = u16_at(pos + 6);
+        let method = u16_at(pos + 8);
+        let size = u32_at(pos + 18) as usize;
+        let name_len = u16_at(pos + 26);
+        let extra_len = u16_at(pos + 28);
+
+        let name_start = pos + LOCAL_FILE_HEADER_LEN;
+        let data_start = name_start + name_len + extra_len;
+        let data_end = data_start + size;
+
+        if method != 0
+        {
+            return Err(error("Compressed entries aren't allowed in a USDZ package"));
+        }
+        else if (flags & 0x08) != 0
+        {
+            return Err(error("Entries with trailing data descriptors aren't supported"));
+        }
+        else if data_end > contents.len()
+        {
+            return Err(error("Truncated archive"));
+        }
+
+        let name = String::from_utf8_lossy(&contents[name_start..(name_start + name_len)]).into_owned();
+
+        if root.is_none() && (name.ends_with(".usda") || name.ends_with(".usdc") || name.ends_with(".usd"))
+        {
+            root = Some(name.clone());
+        }
+
+        files.insert(name, contents[data_start..data_end].to_vec());
+        pos = data_end;
+    }
+
+    match root
+    {
+        Some(root) => Ok(Package { root, files }),
+        None => Err(error("No USD layer found in the package")),
+    }
+}
+
+impl Package
+{
+    /// Finds a file, relative to the root layer
+    pub fn file(&self, path: &str) -> Option<&Vec<u8>>
+    {
+        let path = path.trim_start_matches("./");
+        let dir = self.root.rsplit_once('/').map(|(dir, _)| dir);
+
+        dir.and_then(|dir| self.files.get(&format!("{}/{}", dir, path)))
+            .or_else(|| self.files.get(path))
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use crate::color::{LinearRGB, SRGB};
+use crate::desc::edit::transform::TransformStage;
+use crate::desc::edit::{Color, Geom, Material, Object, Scene, Texture, Transform, Triangle, TriangleVertex};
+use crate::geom::{Aabb, AabbBuilder};
+use crate::import::{FileSystemContext, ImportError};
+use crate::math::Scalar;
+use crate::vec::Point3;
+
+use vox_file::MaterialKind;
+
+pub mod vox_file;
+
+/// Imports a MagicaVoxel (.vox) file as meshes of its visible
+/// voxel faces, colored by the palette and scaled to fit the
+/// destination box. Voxels are grouped into one object for each
+/// kind of material - diffuse, metal, glass and emissive.
+pub fn import_vox_file(path: &str, context: &FileSystemContext, destination: &Aabb, scene: &mut Scene) -> Result<(), ImportError>
+{
+    let filename = context.path_to_filename(path);
+    let (contents, _sub_context) = context.load_binary_file(path)?;
+    let vox_file = vox_file::parse(&contents, path)?;
+
+    let mut groups: Vec<(MaterialKind, Vec<Triangle>)> = Vec::new();
+    let mut aabb_builder = AabbBuilder::new();
+
+    for instance in vox_file.instances.iter()
+    {
+        let model = &vox_file.models[instance.model];
+        let occupied = model.voxels.iter().cloned().collect::<HashMap<_, _>>();
+        let mirrored = determinant(&instance.rotation) < 0;
+
+        for (voxel, index) in model.voxels.iter()
+        {
+            let kind = vox_file.materials.get(index).copied().unwrap_or(MaterialKind::Diffuse);
+            let [r, g, b, _] = vox_file.palette[*index as usize];
+            let color: Color = SRGB::new((r as Scalar) / 255.0, (g as Scalar) / 255.0, (b as Scalar) / 255.0, 1.0).into();
+
+            let group = match groups.iter().position(|(k, _)| *k == kind)
+            {
+                Some(group) => group,
+                None =>
+                {
+                    groups.push((kind, Vec::new()));
+                    groups.len() - 1
+                },
+            };
+
+            // Only add the faces that aren't hidden by a neighbour
+
+            for axis in 0..3
+            {
+                for sign in [-1, 1]
+                {
+                    let mut neighbour = *voxel;
+                    neighbour[axis] += sign;
+
+                    if occupied.contains_key(&neighbour)
+                    {
+                        continue;
+                    }
+
+                    let mut corners = face_corners(*voxel, axis, sign > 0);
+
+                    if mirrored
+                    {
+                        corners.reverse();
+                    }
+
+                    let mut points = [Point3::zero(); 4];
+
+                    for (point, corner) in points.iter_mut().zip(corners.iter())
+                    {
+                        *point = to_scene(*corner, model.size, instance)
+                            .ok_or_else(|| ImportError(format!("VOX Error: {}: Voxel {:?} is out of range", path, voxel)))?;
+                    }
+
+                    let corners = points;
+                    let vertex = |i: usize| TriangleVertex { location: corners[i], texture_coords: Point3::zero(), opt_color: Some(color), opt_normal: None };
+
+                    aabb_builder.add_triangle(corners[0], corners[1], corners[2]);
+                    aabb_builder.add_point(corners[3]);
+
+                    groups[group].1.push(Triangle { vertices: [vertex(0), vertex(1), vertex(2)] });
+                    groups[group].1.push(Triangle { vertices: [vertex(0), vertex(2), vertex(3)] });
+                }
+            }
+        }
+    }
+
+    if groups.is_empty()
+    {
+        return Err(ImportError(format!("VOX Error: {}: No voxels found", path)));
+    }
+
+    let aabb = aabb_builder.build();
+
+    let mut transform = Transform::new();
+    transform.stages.push(TransformStage::ShiftAndScale
+        {
+            from: crate::desc::edit::geom::Aabb{ min: aabb.min, max: aabb.max },
+            to: crate::desc::edit::geom::Aabb{ min: destination.min, max: destination.max },
+            maintain_aspect: true,
+        });
+
+    let single_group = groups.len() == 1;
+
+    for (kind, triangles) in groups
+    {
+        // Vertex colors multiply the (white) texture
+
+        let name = if single_group { filename.clone() } else { format!("{} ({})", filename, kind_name(&kind)) };
+        let white = |scene: &mut Scene, intensity: Scalar| scene.collection.push(Texture::Solid(LinearRGB::grey(intensity).into()));
+
+        let material = match kind
+        {
+            MaterialKind::Diffuse => Material::Diffuse { texture: white(scene, 1.0), normal_texture: None },
+            MaterialKind::Metal{ roughness } => Material::Metal { texture: white(scene, 1.0), fuzz: roughness, normal_texture: None },
+            MaterialKind::Glass{ ior } => Material::Dielectric { ior, tint: None },
+            MaterialKind::Emit{ intensity } => Material::Emit { texture: white(scene, intensity) },
+        };
+
+        let material = scene.collection.push_named(material, name.clone());
+        let geom = scene.collection.push_named(Geom::Mesh { triangles, transform: transform.clone(), lod: None }, name.clone());
+        scene.collection.push_named(Object { geom, material, ray_bias: None, opacity: None }, name);
+    }
+
+    Ok(())
+}
+
+fn kind_name(kind: &MaterialKind) -> &'static str
+{
+    match kind
+    {
+        MaterialKind::Diffuse => "diffuse",
+        MaterialKind::Metal{..} => "metal",
+        MaterialKind::Glass{..} => "glass",
+        MaterialKind::Emit{..} => "emit",
+    }
+}
+
+/// The corners of a voxel's face, counter-clockwise
+/// when viewed from outside of the voxel
+fn face_corners(voxel: [i32; 3], axis: usize, positive: bool) -> [[i32; 3]; 4]
+{
+    let u = (axis + 1) % 3;
+    let v = (axis + 2) % 3;
+
+    let mut base = voxel;
+    base[axis] += if positive { 1 } else { 0 };
+
+    let offset = |du: i32, dv: i32|
+    {
+        let mut result = base;
+        result[u] += du;
+        result[v] += dv;
+        result
+    };
+
+    if positive
+    {
+        [offset(0, 0), offset(1, 0), offset(1, 1), offset(0, 1)]
+    }
+    else
+    {
+        [offset(0, 0), offset(0, 1), offset(1, 1), offset(1, 0)]
+    }
+}
+
+/// Places a point within a model, which is centered on its
+/// instance's translation, and converts from MagicaVoxel's
+/// Z-up coordinates. Returns `None` if it's out of range.
+fn to_scene(point: [i32; 3], size: [i32; 3], instance: &vox_file::Instance) -> Option<Point3>
+{
+    let centered = [point[0] - size[0] / 2, point[1] - size[1] / 2, point[2] - size[2] / 2];
+    let rotated = vox_file::rotate(&instance.rotation, centered)?;
+    let [x, y, z] = vox_file::add(rotated, instance.translation)?;
+
+    Some(Point3::new(x as Scalar, z as Scalar, -(y as Scalar)))
+}
+
+fn determinant(m: &[[i32; 3]; 3]) -> i32
+{
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
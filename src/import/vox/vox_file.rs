@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::import::ImportError;
+use crate::math::Scalar;
+
+pub type Voxel = [i32; 3];
+
+#[derive(Debug, Clone)]
+pub struct Model
+{
+    pub size: [i32; 3],
+    /// Voxel coordinates and their palette index (1-255)
+    pub voxels: Vec<(Voxel, u8)>,
+}
+
+/// A model, placed by the scene graph
+#[derive(Debug, Clone)]
+pub struct Instance
+{
+    pub model: usize,
+    /// Rows of a signed permutation matrix
+    pub rotation: [[i32; 3]; 3],
+    pub translation: [i32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaterialKind
+{
+    Diffuse,
+    Metal{ roughness: Scalar },
+    Glass{ ior: Scalar },
+    Emit{ intensity: Scalar },
+}
+
+#[derive(Debug, Clone)]
+pub struct VoxFile
+{
+    pub models: Vec<Model>,
+    pub instances: Vec<Instance>,
+    /// RGBA colors, indexed by the palette index
+    pub palette: [[u8; 4]; 256],
+    pub materials: HashMap<u8, MaterialKind>,
+}
+
+const IDENTITY: [[i32; 3]; 3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+/// Limits on the scene graph - groups can list the same
+/// node many times, so a small file can expand to an
+/// enormous number of instances
+const MAX_DEPTH: usize = 64;
+const MAX_NODE_VISITS: usize = 1_000_000;
+
+pub fn parse(contents: &[u8], filename: &str) -> Result<VoxFile, ImportError>
+{
+    let error = |msg: &str| ImportError(format!("VOX Error: {}: {}", filename, msg));
+
+    if !contents.starts_with(b"VOX ") || contents.len() < 8
+    {
+        return Err(error("Not a MagicaVoxel file"));
+    }
+
+    let mut models = Vec::new();
+    let mut palette = default_palette();
+    let mut materials = HashMap::new();
+    let mut nodes = HashMap::new();
+    let mut size = None;
+
+    // The MAIN chunk contains all of the others, which
+    // are read in order - so just skip its header
+
+    let mut reader = Reader { contents, pos: 8 };
+    reader.chunk_header().map_err(|e| error(&e))?;
+
+    while reader.pos < contents.len()
+    {
+        let (id, content) = reader.chunk_header().and_then(|(id, len)| Ok((id, reader.bytes(len)?))).map_err(|e| error(&e))?;
+        let mut chunk = Reader { contents: content, pos: 0 };
+
+        let result: Result<(), String> = (|| match &id
+        {
+            b"SIZE" =>
+            {
+                size = Some([chunk.i32()?, chunk.i32()?, chunk.i32()?]);
+                Ok(())
+            },
+            b"XYZI" =>
+            {
+                let count = chunk.i32()? as usize;
+                let voxels = (0..count)
+                    .map(|_| chunk.bytes(4).map(|v| ([v[0] as i32, v[1] as i32, v[2] as i32], v[3])))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                models.push(Model { size: size.take().ok_or_else(|| "XYZI chunk without a SIZE chunk".to_owned())?, voxels });
+                Ok(())
+            },
+            b"RGBA" =>
+            {
+                // Palette index i is stored in entry i - 1
+
+                for entry in palette.iter_mut().skip(1)
+                {
+                    *entry = chunk.bytes(4)?.try_into().unwrap();
+                }
+
+                Ok(())
+            },
+            b"MATL" =>
+            {
+                let index = chunk.i32()?;
+                let properties = chunk.dict()?;
+
+                if let Some(kind) = material_kind(&properties)
+                {
+                    materials.insert(index.clamp(0, 255) as u8, kind);
+                }
+
+                Ok(())
+            },
+            b"nTRN" | b"nGRP" | b"nSHP" =>
+            {
+                let node_id = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+
+                let node = match &id
+                {
+                    b"nTRN" =>
+                    {
+                        let child = chunk.i32()?;
+                        let _reserved = chunk.i32()?;
+                        let _layer = chunk.i32()?;
+                        let num_frames = chunk.i32()?;
+                        let frame = if num_frames > 0 { chunk.dict()? } else { HashMap::new() };
+
+                        Node::Transform { child, rotation: frame.get("_r").map(|r| rotation(r)).unwrap_or(IDENTITY), translation: translation(frame.get("_t")) }
+                    },
+                    b"nGRP" =>
+                    {
+                        let count = chunk.i32()?;
+                        Node::Group((0..count).map(|_| chunk.i32()).collect::<Result<Vec<_>, _>>()?)
+                    },
+                    _ =>
+                    {
+                        // Only the first frame of animated shapes is used
+
+                        let _count = chunk.i32()?;
+                        Node::Shape(chunk.i32()?)
+                    },
+                };
+
+                nodes.insert(node_id, node);
+                Ok(())
+            },
+            _ => Ok(()),
+        })();
+
+        result.map_err(|e| error(&format!("{} chunk: {}", String::from_utf8_lossy(&id), e)))?;
+    }
+
+    if models.is_empty()
+    {
+        return Err(error("No models found"));
+    }
+
+    // Files without a scene graph have a single model
+
+    let instances = if nodes.is_empty()
+    {
+        vec![Instance { model: 0, rotation: IDENTITY, translation: [0, 0, 0] }]
+    }
+    else
+    {
+        let mut collector = InstanceCollector { nodes: &nodes, path: Vec::new(), visits: 0, instances: Vec::new() };
+        collector.collect(0, IDENTITY, [0, 0, 0]).map_err(|e| error(&e))?;
+
+        let mut instances = collector.instances;
+        instances.retain(|i| i.model < models.len());
+        instances
+    };
+
+    Ok(VoxFile { models, instances, palette, materials })
+}
+
+enum Node
+{
+    Transform{ child: i32, rotation: [[i32; 3]; 3], translation: [i32; 3] },
+    Group(Vec<i32>),
+    Shape(i32),
+}
+
+struct InstanceCollector<'a>
+{
+    nodes: &'a HashMap<i32, Node>,
+    /// The nodes from the root to the current one
+    path: Vec<i32>,
+    visits: usize,
+    instances: Vec<Instance>,
+}
+
+impl<'a> InstanceCollector<'a>
+{
+    fn collect(&mut self, id: i32, rotation: [[i32; 3]; 3], translation: [i32; 3]) -> Result<(), String>
+    {
+        if self.path.contains(&id)
+        {
+            return Err(format!("Node {} contains itself", id));
+        }
+
+        if self.path.len() >= MAX_DEPTH
+        {
+            return Err(format!("Nodes are nested more than {} deep", MAX_DEPTH));
+        }
+
+        self.visits += 1;
+
+        if self.visits > MAX_NODE_VISITS
+        {
+            return Err(format!("The scene graph expands to more than {} nodes", MAX_NODE_VISITS));
+        }
+
+        self.path.push(id);
+        let result = self.collect_node(id, rotation, translation);
+        self.path.pop();
+
+        result
+    }
+
+    fn collect_node(&mut self, id: i32, rotation: [[i32; 3]; 3], translation: [i32; 3]) -> Result<(), String>
+    {
+        let nodes = self.nodes;
+
+        match nodes.get(&id)
+        {
+            Some(Node::Transform{ child, rotation: local_rotation, translation: local_translation }) =>
+            {
+                let translation = rotate(&rotation, *local_translation)
+                    .and_then(|t| add(translation, t))
+                    .ok_or_else(|| format!("Node {} has a translation that's out of range", id))?;
+
+                self.collect(*child, multiply(&rotation, local_rotation), translation)
+            },
+            Some(Node::Group(children)) =>
+            {
+                children.iter().try_for_each(|child| self.collect(*child, rotation, translation))
+            },
+            Some(Node::Shape(model)) =>
+            {
+                self.instances.push(Instance { model: (*model).max(0) as usize, rotation, translation });
+                Ok(())
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns `None` if the result is out of range
+pub fn add(a: [i32; 3], b: [i32; 3]) -> Option<[i32; 3]>
+{
+    Some([a[0].checked_add(b[0])?, a[1].checked_add(b[1])?, a[2].checked_add(b[2])?])
+}
+
+/// Returns `None` if the result is out of range
+pub fn rotate(rotation: &[[i32; 3]; 3], v: [i32; 3]) -> Option<[i32; 3]>
+{
+    let row = |r: &[i32; 3]| r[0].checked_mul(v[0])?
+        .checked_add(r[1].checked_mul(v[1])?)?
+        .checked_add(r[2].checked_mul(v[2])?);
+
+    Some([row(&rotation[0])?, row(&rotation[1])?, row(&rotation[2])?])
+}
+
+fn multiply(a: &[[i32; 3]; 3], b: &[[i32; 3]; 3]) -> [[i32; 3]; 3]
+{
+    let mut result = [[0; 3]; 3];
+
+    for (r, row) in result.iter_mut().enumerate()
+    {
+        for (c, value) in row.iter_mut().enumerate()
+        {
+            *value = (0..3).map(|k| a[r][k] * b[k][c]).sum();
+        }
+    }
+
+    result
+}
+
+/// Rotations are packed into a byte - bits 0-1 and 2-3 are
+/// the columns of the non-zero entries in the first two rows,
+/// and bits 4-6 are the signs of the three rows
+fn rotation(packed: &str) -> [[i32; 3]; 3]
+{
+    let packed = packed.trim().parse::<u8>().unwrap_or(4);
+    let first = (packed & 3) as usize;
+    let second = ((packed >> 2) & 3) as usize;
+
+    if first > 2 || second > 2 || first == second
+    {
+        return IDENTITY;
+    }
+
+    let third = 3 - first - second;
+    let mut result = [[0; 3]; 3];
+
+    for (row, column) in [first, second, third].iter().enumerate()
+    {
+        result[row][*column] = if (packed >> (4 + row)) & 1 != 0 { -1 } else { 1 };
+    }
+
+    result
+}
+
+fn translation(value: Option<&String>) -> [i32; 3]
+{
+    let values = value.map(|v| v.split_whitespace().filter_map(|n| n.parse::<i32>().ok()).collect::<Vec<_>>()).unwrap_or_default();
+
+    match values.as_slice()
+    {
+        [x, y, z, ..] => [*x, *y, *z],
+        _ => [0, 0, 0],
+    }
+}
+
+fn material_kind(properties: &HashMap<String, String>) -> Option<MaterialKind>
+{
+    let value = |name: &str, default: Scalar| properties.get(name).and_then(|v| v.parse::<Scalar>().ok()).unwrap_or(default);
+
+    match properties.get("_type").map(|t| t.as_str())
+    {
+        Some("_metal") => Some(MaterialKind::Metal{ roughness: value("_rough", 0.1) }),
+        // The index of refraction is stored without the leading 1
+        Some("_glass") => Some(MaterialKind::Glass{ ior: 1.0 + value("_ior", 0.5) }),
+        Some("_emit") => Some(MaterialKind::Emit{ intensity: value("_emit", 1.0) * (1.0 + value("_flux", 0.0)) }),
+        _ => None,
+    }
+}
+
+/// The palette used by files without an RGBA chunk - a 6x6x6
+/// color cube, then red, green, blue and grey ramps
+fn default_palette() -> [[u8; 4]; 256]
+{
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut result = [[0; 4]; 256];
+    let mut index = 1;
+
+    for r in CUBE.iter()
+    {
+        for g in CUBE.iter()
+        {
+            for b in CUBE.iter()
+            {
+                if index < 216
+                {
+                    result[index] = [*r, *g, *b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    for channel in 0..4
+    {
+        for value in RAMP.iter()
+        {
+            result[index] = match channel
+            {
+                3 => [*value, *value, *value, 0xff],
+                _ =>
+                {
+                    let mut color = [0, 0, 0, 0xff];
+                    color[channel] = *value;
+                    color
+                },
+            };
+
+            index += 1;
+        }
+    }
+
+    result
+}
+
+struct Reader<'a>
+{
+    contents: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a>
+{
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String>
+    {
+        if self.pos + len > self.contents.len()
+        {
+            return Err("Unexpected end of data".to_owned());
+        }
+
+        let result = &self.contents[self.pos..(self.pos + len)];
+        self.pos += len;
+        Ok(result)
+    }
+
+    fn i32(&mut self) -> Result<i32, String>
+    {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String>
+    {
+        let len = self.i32()?.max(0) as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, String>
+    {
+        let count = self.i32()?.max(0);
+
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+
+    /// The chunk's ID and content size - child chunks
+    /// follow the content, so aren't skipped
+    fn chunk_header(&mut self) -> Result<([u8; 4], usize), String>
+    {
+        let id = self.bytes(4)?.try_into().unwrap();
+        let content_len = self.i32()?.max(0) as usize;
+        let _children_len = self.i32()?;
+
+        Ok((id, content_len))
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod tests;
+
 use std::any::{TypeId, Any};
 use std::collections::{HashSet, HashMap};
 use std::hash::Hash;
@@ -6,30 +9,39 @@ use std::cell::RefCell;
 use std::marker::PhantomData;
 use std::rc::Rc;
 
+#[cfg(feature = "ui")]
 use imgui::TreeNodeFlags;
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
-use crate::ui::{UiDisplay, UiEdit, UiRenderer};
+#[cfg(feature = "ui")]
+use crate::ui::{IndexPick, UiDisplay, UiEdit, UiRenderer};
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ImageIndex(usize);
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct TextureIndex(usize);
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct TransformIndex(usize);
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct MaterialIndex(usize);
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct GeomIndex(usize);
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ObjectIndex(usize);
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct GroupIndex(usize);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProbeIndex(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum AnyIndex
 {
     Image(ImageIndex),
@@ -38,6 +50,26 @@ pub enum AnyIndex
     Material(MaterialIndex),
     Geom(GeomIndex),
     Object(ObjectIndex),
+    Group(GroupIndex),
+    Probe(ProbeIndex),
+}
+
+impl AnyIndex
+{
+    fn type_and_position(&self) -> (TypeId, usize)
+    {
+        match self
+        {
+            AnyIndex::Image(i) => (TypeId::of::<ImageIndex>(), i.to_usize()),
+            AnyIndex::Texture(i) => (TypeId::of::<TextureIndex>(), i.to_usize()),
+            AnyIndex::Transform(i) => (TypeId::of::<TransformIndex>(), i.to_usize()),
+            AnyIndex::Material(i) => (TypeId::of::<MaterialIndex>(), i.to_usize()),
+            AnyIndex::Geom(i) => (TypeId::of::<GeomIndex>(), i.to_usize()),
+            AnyIndex::Object(i) => (TypeId::of::<ObjectIndex>(), i.to_usize()),
+            AnyIndex::Group(i) => (TypeId::of::<GroupIndex>(), i.to_usize()),
+            AnyIndex::Probe(i) => (TypeId::of::<ProbeIndex>(), i.to_usize()),
+        }
+    }
 }
 
 pub trait Index: Debug + Default + Clone + Copy + PartialEq + Eq + PartialOrd + Ord + Hash + Send + 'static
@@ -46,16 +78,76 @@ pub trait Index: Debug + Default + Clone + Copy + PartialEq + Eq + PartialOrd +
 
     fn from_usize(index: usize) -> Self;
     fn to_usize(&self) -> usize;
+    fn to_any(&self) -> AnyIndex;
 }
 
-pub trait IndexedValue: Debug + Default + Clone + UiDisplay + UiEdit + Send + 'static
+/// Indexed values can be shown and edited in the UI - but
+/// only when it's built, so headless builds don't need it
+#[cfg(feature = "ui")]
+pub trait IndexedValueUi: UiDisplay + UiEdit {}
+
+#[cfg(feature = "ui")]
+impl<T: UiDisplay + UiEdit> IndexedValueUi for T {}
+
+#[cfg(not(feature = "ui"))]
+pub trait IndexedValueUi {}
+
+#[cfg(not(feature = "ui"))]
+impl<T> IndexedValueUi for T {}
+
+pub trait IndexedValue: Debug + Default + Clone + IndexedValueUi + Send + 'static
 {
     type Index: Index;
 
     fn collect_indexes(&self, indexes: &mut HashSet<AnyIndex>);
+    /// Updates the same indexes as `collect_indexes`,
+    /// once the items they refer to have moved
+    fn remap_indexes(&mut self, remap: &IndexRemap);
     fn summary(&self) -> String;
 }
 
+/// Where items have moved to, after items
+/// of a collection are removed or reordered
+#[derive(Clone, Debug, Default)]
+pub struct IndexRemap
+{
+    moved: HashMap<AnyIndex, usize>,
+}
+
+impl IndexRemap
+{
+    /// Records that the item has moved
+    pub fn insert<I: Index>(&mut self, from: I, to: I)
+    {
+        if from != to
+        {
+            self.moved.insert(from.to_any(), to.to_usize());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.moved.is_empty()
+    }
+
+    /// Updates the index, if the item it refers to has moved
+    pub fn apply<I: Index>(&self, index: &mut I)
+    {
+        if let Some(to) = self.moved.get(&index.to_any())
+        {
+            *index = I::from_usize(*to);
+        }
+    }
+
+    pub fn apply_opt<I: Index>(&self, index: &mut Option<I>)
+    {
+        if let Some(index) = index
+        {
+            self.apply(index);
+        }
+    }
+}
+
 impl Index for ImageIndex
 {
     type Value = crate::import::image::Image;
@@ -69,6 +161,11 @@ impl Index for ImageIndex
     {
         self.0
     }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Image(*self)
+    }
 }
 
 impl Index for TextureIndex
@@ -84,6 +181,11 @@ impl Index for TextureIndex
     {
         self.0
     }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Texture(*self)
+    }
 }
 
 impl Index for TransformIndex
@@ -99,6 +201,11 @@ impl Index for TransformIndex
     {
         self.0
     }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Transform(*self)
+    }
 }
 
 impl Index for MaterialIndex
@@ -114,6 +221,11 @@ impl Index for MaterialIndex
     {
         self.0
     }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Material(*self)
+    }
 }
 
 impl Index for GeomIndex
@@ -129,6 +241,11 @@ impl Index for GeomIndex
     {
         self.0
     }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Geom(*self)
+    }
 }
 
 impl Index for ObjectIndex
@@ -144,14 +261,66 @@ impl Index for ObjectIndex
     {
         self.0
     }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Object(*self)
+    }
+}
+
+impl Index for GroupIndex
+{
+    type Value = crate::desc::edit::Group;
+    
+    fn from_usize(index: usize) -> Self
+    {
+        GroupIndex(index)
+    }
+
+    fn to_usize(&self) -> usize
+    {
+        self.0
+    }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Group(*self)
+    }
+}
+
+impl Index for ProbeIndex
+{
+    type Value = crate::desc::edit::Probe;
+    
+    fn from_usize(index: usize) -> Self
+    {
+        ProbeIndex(index)
+    }
+
+    fn to_usize(&self) -> usize
+    {
+        self.0
+    }
+
+    fn to_any(&self) -> AnyIndex
+    {
+        AnyIndex::Probe(*self)
+    }
 }
 
 pub trait IndexedCollectionVTable
 {
     fn clone_vtable(&self) -> Box<dyn IndexedCollectionVTable>;
     fn clone_vec(&self, vec: &Box<dyn Any + Send>) -> Box<dyn Any + Send>;
+    #[cfg(feature = "ui")]
     fn ui_display(&self, ui: &UiRenderer, label: &str, vec: &Box<dyn Any + Send>);
-    fn ui_edit(&self, ui: &UiRenderer, label: &str, vec: &mut Box<dyn Any + Send>) -> bool;
+    #[cfg(feature = "ui")]
+    fn ui_edit(&self, ui: &UiRenderer, label: &str, vec: &mut Box<dyn Any + Send>, referenced: &HashSet<AnyIndex>) -> (bool, IndexRemap);
+    fn collect_references(&self, vec: &Box<dyn Any + Send>, reverse: &mut ReverseIndex);
+    #[cfg(feature = "ui")]
+    fn ui_labels(&self, vec: &Box<dyn Any + Send>) -> Vec<String>;
+    fn remap_indexes(&self, vec: &mut Box<dyn Any + Send>, remap: &IndexRemap);
+    fn len(&self, vec: &Box<dyn Any + Send>) -> usize;
 }
 
 pub struct IndexedCollectionVTableImpl<V: IndexedValue>
@@ -189,14 +358,98 @@ impl<V: IndexedValue> IndexedCollectionVTable for IndexedCollectionVTableImpl<V>
         Box::new(self.downcast_ref(vec).clone())
     }
 
+    #[cfg(feature = "ui")]
     fn ui_display(&self, ui: &UiRenderer, label: &str, vec: &Box<dyn Any + Send>)
     {
         self.downcast_ref(vec).ui_display(ui, label);
     }
 
-    fn ui_edit(&self, ui: &UiRenderer, label: &str, vec: &mut Box<dyn Any + Send>) -> bool
+    #[cfg(feature = "ui")]
+    fn ui_edit(&self, ui: &UiRenderer, label: &str, vec: &mut Box<dyn Any + Send>, referenced: &HashSet<AnyIndex>) -> (bool, IndexRemap)
+    {
+        self.downcast_mut(vec).ui_edit_items(ui, label, referenced)
+    }
+
+    fn collect_references(&self, vec: &Box<dyn Any + Send>, reverse: &mut ReverseIndex)
+    {
+        for (i, entry) in self.downcast_ref(vec).items.iter().enumerate()
+        {
+            let mut indexes = HashSet::new();
+            entry.value.borrow().collect_indexes(&mut indexes);
+
+            for index in indexes
+            {
+                reverse.referrers.entry(index).or_default().push(V::Index::from_usize(i).to_any());
+            }
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    fn ui_labels(&self, vec: &Box<dyn Any + Send>) -> Vec<String>
     {
-        self.downcast_mut(vec).ui_edit(ui, label)
+        self.downcast_ref(vec).items.iter().enumerate()
+            .map(|(i, e)| format!("{}: {}", i, e.name.clone().unwrap_or_else(|| e.value.borrow().summary())))
+            .collect()
+    }
+
+    fn remap_indexes(&self, vec: &mut Box<dyn Any + Send>, remap: &IndexRemap)
+    {
+        for entry in self.downcast_mut(vec).items.iter_mut()
+        {
+            entry.value.get_mut().remap_indexes(remap);
+        }
+    }
+
+    fn len(&self, vec: &Box<dyn Any + Send>) -> usize
+    {
+        self.downcast_ref(vec).items.len()
+    }
+}
+
+/// For each item, the items that refer to it
+#[derive(Clone, Debug, Default)]
+pub struct ReverseIndex
+{
+    referrers: HashMap<AnyIndex, Vec<AnyIndex>>,
+}
+
+impl ReverseIndex
+{
+    /// The items that refer directly to the item
+    pub fn referrers(&self, index: AnyIndex) -> &[AnyIndex]
+    {
+        self.referrers.get(&index).map(|r| r.as_slice()).unwrap_or(&[])
+    }
+
+    /// The items that refer to the item, either directly or
+    /// through other items - e.g. the objects using a material
+    /// that uses a texture
+    pub fn all_referrers(&self, index: AnyIndex) -> Vec<AnyIndex>
+    {
+        let mut result = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![index];
+
+        while let Some(cur) = pending.pop()
+        {
+            for referrer in self.referrers(cur)
+            {
+                if seen.insert(*referrer)
+                {
+                    result.push(*referrer);
+                    pending.push(*referrer);
+                }
+            }
+        }
+
+        result.sort();
+        result
+    }
+
+    /// If any other item refers to the item
+    pub fn is_referenced(&self, index: AnyIndex) -> bool
+    {
+        self.referrers(index).iter().any(|r| *r != index)
     }
 }
 
@@ -298,6 +551,233 @@ impl IndexedCollection
         let entry = self.by_value.get(&key_value).unwrap();
         entry.borrow().vec.downcast_ref::<IndexedVec<V>>().unwrap().items.iter().map(move |e| func(&e.value.borrow(), self)).collect()
     }
+
+    pub fn name_of<I: Index>(&self, index: I) -> Option<String>
+    {
+        let key_index = TypeId::of::<I>();
+        let entry = self.by_index.get(&key_index).unwrap();
+        let result = entry.borrow().vec.downcast_ref::<IndexedVec<I::Value>>().unwrap().items[index.to_usize()].name.clone();
+        result
+    }
+
+    /// True if the index refers to an item - it's not past
+    /// the end, or the placeholder item of an empty list
+    pub fn contains<I: Index>(&self, index: I) -> bool
+    {
+        let key_index = TypeId::of::<I>();
+        let entry = self.by_index.get(&key_index).unwrap();
+        let result = entry.borrow().vec.downcast_ref::<IndexedVec<I::Value>>().unwrap().items.get(index.to_usize()).map(|e| !e.is_default).unwrap_or(false);
+        result
+    }
+
+    /// True for the placeholder item that an empty list holds,
+    /// until the first item is pushed in its place
+    pub fn is_default<I: Index>(&self, index: I) -> bool
+    {
+        let key_index = TypeId::of::<I>();
+        let entry = self.by_index.get(&key_index).unwrap();
+        let result = entry.borrow().vec.downcast_ref::<IndexedVec<I::Value>>().unwrap().items[index.to_usize()].is_default;
+        result
+    }
+
+    /// Returns the name, or if it's already used by an item
+    /// of the same type, the name with a numbered suffix
+    pub fn unique_name<V: IndexedValue>(&self, name: &str) -> String
+    {
+        let key_value = TypeId::of::<V>();
+        let entry = self.by_value.get(&key_value).unwrap();
+        let result = entry.borrow().vec.downcast_ref::<IndexedVec<V>>().unwrap().unique_name(name);
+        result
+    }
+
+    /// Renames an item, returning the name actually used - which
+    /// has a numbered suffix if another item already has the name
+    pub fn rename<I: Index>(&mut self, index: I, name: Option<String>) -> Option<String>
+    {
+        let key_index = TypeId::of::<I>();
+        let entry = self.by_index.get(&key_index).unwrap();
+        let result = entry.borrow_mut().vec.downcast_mut::<IndexedVec<I::Value>>().unwrap().rename(<I::Value as IndexedValue>::Index::from_usize(index.to_usize()), name);
+        result
+    }
+
+    /// Finds the item of a type with the given name
+    pub fn find_by_name<I: Index>(&self, name: &str) -> Option<I>
+    {
+        let key_index = TypeId::of::<I>();
+        let entry = self.by_index.get(&key_index).unwrap();
+        let result = entry.borrow().vec.downcast_ref::<IndexedVec<I::Value>>().unwrap().by_name.get(name).map(|i| I::from_usize(*i));
+        result
+    }
+
+    /// Removes an item, moving the later items of the same type down,
+    /// and updating all indexes in the collection that refer to them.
+    /// The returned remap must also be applied to any indexes kept
+    /// outside the collection. Items still referred to by other
+    /// items can't be removed.
+    pub fn remove<I: Index>(&mut self, index: I) -> Result<IndexRemap, String>
+    {
+        let referrers = self.reverse_index().referrers(index.to_any()).iter()
+            .filter(|r| **r != index.to_any())
+            .map(|r| format!("{:?}", r))
+            .collect_vec();
+
+        if !referrers.is_empty()
+        {
+            return Err(format!("{:?} is used by {}", index.to_any(), referrers.join(", ")));
+        }
+
+        let key_index = TypeId::of::<I>();
+        let remap = self.by_index.get(&key_index).unwrap()
+            .borrow_mut().vec.downcast_mut::<IndexedVec<I::Value>>().unwrap()
+            .remove(<I::Value as IndexedValue>::Index::from_usize(index.to_usize()));
+
+        self.remap_indexes(&remap);
+        Ok(remap)
+    }
+
+    /// Swaps two items of the same type, updating all indexes in the
+    /// collection that refer to them. The returned remap must also be
+    /// applied to any indexes kept outside the collection.
+    pub fn swap<I: Index>(&mut self, a: I, b: I) -> IndexRemap
+    {
+        let key_index = TypeId::of::<I>();
+        let remap = self.by_index.get(&key_index).unwrap()
+            .borrow_mut().vec.downcast_mut::<IndexedVec<I::Value>>().unwrap()
+            .swap(<I::Value as IndexedValue>::Index::from_usize(a.to_usize()), <I::Value as IndexedValue>::Index::from_usize(b.to_usize()));
+
+        self.remap_indexes(&remap);
+        remap
+    }
+
+    /// Updates the indexes of every item in the collection
+    pub fn remap_indexes(&mut self, remap: &IndexRemap)
+    {
+        if remap.is_empty()
+        {
+            return;
+        }
+
+        for entry in self.in_order.iter()
+        {
+            let entry = &mut *entry.borrow_mut();
+            entry.vtable.remap_indexes(&mut entry.vec, remap);
+        }
+    }
+
+    /// Lets indexes edited in the UI be picked from
+    /// the items, by name or summary
+    #[cfg(feature = "ui")]
+    pub fn set_ui_index_labels(&self, ui: &UiRenderer)
+    {
+        for entry in self.in_order.iter()
+        {
+            let entry = entry.borrow();
+            ui.set_index_labels(entry.key_index, entry.vtable.ui_labels(&entry.vec));
+        }
+    }
+
+    /// Edits the collection, where items can also be removed and
+    /// reordered. Items in the referenced set (e.g. from outside the
+    /// collection), or referred to by other items, can't be removed.
+    /// Returns if anything changed, and the remap to apply to any
+    /// indexes outside the collection.
+    #[cfg(feature = "ui")]
+    pub fn ui_edit_remapped(&mut self, ui: &UiRenderer, label: &str, referenced: &HashSet<AnyIndex>) -> (bool, IndexRemap)
+    {
+        let mut result = false;
+        let mut remap = IndexRemap::default();
+
+        let _id = ui.imgui.push_id(label);
+        if ui.imgui.collapsing_header(label, TreeNodeFlags::empty())
+        {
+            self.set_ui_index_labels(ui);
+
+            let reverse = self.reverse_index();
+            let mut referenced = referenced.clone();
+            referenced.extend(reverse.referrers.keys().filter(|i| reverse.is_referenced(**i)));
+
+            ui.imgui.indent();
+            for i in self.in_order.iter()
+            {
+                let i = &mut *i.borrow_mut();
+                let _i_id = ui.imgui.push_id_usize(i.index);
+                if ui.imgui.collapsing_header(&i.name, TreeNodeFlags::empty())
+                {
+                    ui.imgui.indent();
+
+                    let (changed, moved) = i.vtable.ui_edit(ui, &i.name, &mut i.vec, &referenced);
+                    result |= changed;
+
+                    if !moved.is_empty()
+                    {
+                        remap = moved;
+                    }
+
+                    ui.imgui.unindent();
+                }
+            }
+            ui.imgui.unindent();
+        }
+
+        // Only one item can be moved at a time, and the
+        // items are all released, so they can be updated
+
+        self.remap_indexes(&remap);
+        (result, remap)
+    }
+
+    /// Builds an index from each item to the items that refer to it
+    pub fn reverse_index(&self) -> ReverseIndex
+    {
+        let mut result = ReverseIndex::default();
+
+        for entry in self.in_order.iter()
+        {
+            let entry = entry.borrow();
+            entry.vtable.collect_references(&entry.vec, &mut result);
+        }
+
+        result
+    }
+
+    /// Checks that the indexes held by every item, and those
+    /// given (e.g. from outside the collection), refer to items
+    /// that exist - e.g. once they've been loaded from a file
+    pub fn check_indexes(&self, others: &HashSet<AnyIndex>) -> Result<(), String>
+    {
+        let reverse = self.reverse_index();
+
+        let missing = reverse.referrers.keys().chain(others.iter())
+            .filter(|index|
+            {
+                let (key_index, position) = index.type_and_position();
+
+                match self.by_index.get(&key_index)
+                {
+                    Some(entry) => { let entry = entry.borrow(); position >= entry.vtable.len(&entry.vec) },
+                    None => true,
+                }
+            })
+            .sorted()
+            .dedup()
+            .map(|index| format!("{:?}", index))
+            .collect_vec();
+
+        if missing.is_empty()
+        {
+            return Ok(());
+        }
+
+        Err(format!("Refers to items that don't exist: {}", missing.join(", ")))
+    }
+
+    pub fn map_all_with_index<V: IndexedValue, F, R>(&self, func: F) -> Vec<R>
+        where F: Fn(V::Index, &V, &IndexedCollection) -> R
+    {
+        let key_value = TypeId::of::<V>();
+        let entry = self.by_value.get(&key_value).unwrap();
+        entry.borrow().vec.downcast_ref::<IndexedVec<V>>().unwrap().items.iter().enumerate().map(move |(i, e)| func(V::Index::from_usize(i), &e.value.borrow(), self)).collect()
+    }
 }
 
 impl Clone for IndexedCollection
@@ -334,6 +814,7 @@ impl Clone for IndexedCollection
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiDisplay for IndexedCollection
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -360,32 +841,13 @@ impl UiDisplay for IndexedCollection
     }
 }
 
+#[cfg(feature = "ui")]
 impl UiEdit for IndexedCollection
 {
+    /// Only for collections with no indexes kept outside - see `ui_edit_remapped`
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
-        let mut result = false;
-
-        let _id = ui.imgui.push_id(label);
-        if ui.imgui.collapsing_header(label, TreeNodeFlags::empty())
-        {
-            ui.imgui.indent();
-            for i in self.in_order.iter()
-            {
-                let i = &mut *i.borrow_mut();
-                let _i_id = ui.imgui.push_id_usize(i.index);
-                if ui.imgui.collapsing_header(&i.name, TreeNodeFlags::empty())
-                {
-                    ui.imgui.indent();
-
-                    result |= i.vtable.ui_edit(ui, &i.name, &mut i.vec);
-
-                    ui.imgui.unindent();
-                }
-            }
-            ui.imgui.unindent();
-        }
-        result
+        self.ui_edit_remapped(ui, label, &HashSet::new()).0
     }
 }
 
@@ -397,10 +859,12 @@ struct IndexedVecEntry<V: IndexedValue>
     is_default: bool,
 }
 
+/// Names are kept unique, so that items can be found by name
 #[derive(Clone, Debug)]
 pub struct IndexedVec<V: IndexedValue>
 {
     items: Vec<IndexedVecEntry<V>>,
+    by_name: HashMap<String, usize>,
 }
 
 impl<V: IndexedValue> IndexedVec<V>
@@ -409,7 +873,7 @@ impl<V: IndexedValue> IndexedVec<V>
     {
         let mut items = Vec::new();
         items.push(IndexedVecEntry { value: RefCell::new(V::default()), name: None, is_default: true });
-        IndexedVec{ items }
+        IndexedVec{ items, by_name: HashMap::new() }
     }
 
     pub fn push(&mut self, item: V) -> V::Index
@@ -434,20 +898,109 @@ impl<V: IndexedValue> IndexedVec<V>
         *entry.value.borrow_mut() = v;
     }
 
-    fn push_internal(&mut self, item: V, opt_name: Option<String>) -> V::Index
+    pub fn rename(&mut self, i: V::Index, opt_name: Option<String>) -> Option<String>
+    {
+        let i = i.to_usize();
+
+        if let Some(old_name) = self.items[i].name.take()
+        {
+            self.by_name.remove(&old_name);
+        }
+
+        let opt_name = opt_name.map(|name| self.unique_name(&name));
+
+        if let Some(name) = &opt_name
+        {
+            self.by_name.insert(name.clone(), i);
+        }
+
+        self.items[i].name = opt_name.clone();
+        opt_name
+    }
+
+    /// Removes an item, moving the later items down, and returns where
+    /// they moved to. Removing the only item leaves a default item.
+    pub fn remove(&mut self, i: V::Index) -> IndexRemap
     {
+        let mut remap = IndexRemap::default();
+        let i = i.to_usize();
+
         if self.items.len() == 1
+        {
+            self.items[0] = IndexedVecEntry { value: RefCell::new(V::default()), name: None, is_default: true };
+        }
+        else
+        {
+            self.items.remove(i);
+
+            for moved in i..self.items.len()
+            {
+                remap.insert(V::Index::from_usize(moved + 1), V::Index::from_usize(moved));
+            }
+        }
+
+        self.update_names();
+        remap
+    }
+
+    /// Swaps two items, and returns where they moved to
+    pub fn swap(&mut self, a: V::Index, b: V::Index) -> IndexRemap
+    {
+        let mut remap = IndexRemap::default();
+
+        self.items.swap(a.to_usize(), b.to_usize());
+        remap.insert(a, b);
+        remap.insert(b, a);
+
+        self.update_names();
+        remap
+    }
+
+    fn update_names(&mut self)
+    {
+        self.by_name = self.items.iter().enumerate()
+            .filter_map(|(i, e)| e.name.clone().map(|name| (name, i)))
+            .collect();
+    }
+
+    fn unique_name(&self, name: &str) -> String
+    {
+        if !self.by_name.contains_key(name)
+        {
+            return name.to_owned();
+        }
+
+        (2..)
+            .map(|n| format!("{} ({})", name, n))
+            .find(|candidate| !self.by_name.contains_key(candidate))
+            .unwrap()
+    }
+
+    fn push_internal(&mut self, item: V, opt_name: Option<String>) -> V::Index
+    {
+        let index = if self.items.len() == 1
             && self.items[0].is_default
         {
             self.items[0].is_default = false;
-            self.items[0].name = opt_name;
             self.items[0].value.replace(item);
             V::Index::from_usize(0)
         }
         else
         {
-            self.items.push(IndexedVecEntry { value: RefCell::new(item), name: opt_name, is_default: false });
+            self.items.push(IndexedVecEntry { value: RefCell::new(item), name: None, is_default: false });
             V::Index::from_usize(self.items.len() - 1)
+        };
+
+        self.rename(index, opt_name);
+        index
+    }
+
+    #[cfg(feature = "ui")]
+    fn pop(&mut self)
+    {
+        if let Some(name) = self.items.pop().and_then(|e| e.name)
+        {
+            self.by_name.remove(&name);
         }
     }
 }
@@ -460,6 +1013,7 @@ impl<V: IndexedValue> Default for IndexedVec<V>
     }
 }
 
+#[cfg(feature = "ui")]
 impl<V: IndexedValue> UiDisplay for IndexedVec<V>
 {
     fn ui_display(&self, ui: &UiRenderer, label: &str)
@@ -479,11 +1033,16 @@ impl<V: IndexedValue> UiDisplay for IndexedVec<V>
 }
 
 
-impl<V: IndexedValue> UiEdit for IndexedVec<V>
+#[cfg(feature = "ui")]
+impl<V: IndexedValue> IndexedVec<V>
 {
-    fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
+    /// Edits the items, which can also be removed, unless they're
+    /// in the referenced set, or moved up and down. Returns if anything
+    /// changed, and where any items moved to.
+    fn ui_edit_items(&mut self, ui: &UiRenderer, label: &str, referenced: &HashSet<AnyIndex>) -> (bool, IndexRemap)
     {
         let mut result = false;
+        let mut remap = IndexRemap::default();
         let mut len = self.items.len();
 
         if let Some(_header) = ui.imgui.tree_node_config(&format!("{} ({} item{})###{}", label, len, if len == 1 { "" } else { "s" }, label))
@@ -499,7 +1058,7 @@ impl<V: IndexedValue> UiEdit for IndexedVec<V>
                 while len < self.items.len()
                     && self.items.len() >= 2
                 {
-                    self.items.pop();
+                    self.pop();
                 }
                 while len > self.items.len()
                 {
@@ -507,6 +1066,11 @@ impl<V: IndexedValue> UiEdit for IndexedVec<V>
                 }
             }
 
+            let mut renamed = None;
+            let mut removed = None;
+            let mut swapped = None;
+            let count = self.items.len();
+
             for (i, e) in self.items.iter_mut().enumerate()
             {
                 let mut v = e.value.borrow_mut();
@@ -519,6 +1083,40 @@ impl<V: IndexedValue> UiEdit for IndexedVec<V>
                     .frame_padding(true)
                     .push()
                 {
+                    // Renames are applied once editing is finished,
+                    // so the name isn't made unique while typing
+
+                    let mut name = e.name.clone().unwrap_or_default();
+
+                    if ui.imgui.input_text(format!("Name###name{}", i), &mut name).enter_returns_true(true).build()
+                    {
+                        renamed = Some((i, name));
+                    }
+
+                    if (i > 0) && ui.imgui.small_button(format!("Move Up###up{}", i))
+                    {
+                        swapped = Some((i - 1, i));
+                    }
+
+                    if i + 1 < count
+                    {
+                        ui.imgui.same_line();
+                        if ui.imgui.small_button(format!("Move Down###down{}", i))
+                        {
+                            swapped = Some((i, i + 1));
+                        }
+                    }
+
+                    ui.imgui.same_line();
+                    if referenced.contains(&V::Index::from_usize(i).to_any())
+                    {
+                        ui.imgui.text_disabled("In use");
+                    }
+                    else if ui.imgui.small_button(format!("Delete###delete{}", i))
+                    {
+                        removed = Some(i);
+                    }
+
                     let changed = v.ui_edit(ui, &i.to_string());
                     result |= changed;
 
@@ -528,12 +1126,30 @@ impl<V: IndexedValue> UiEdit for IndexedVec<V>
                     }
                 }
             }
+
+            if let Some((i, name)) = renamed
+            {
+                self.rename(V::Index::from_usize(i), if name.is_empty() { None } else { Some(name) });
+                result = true;
+            }
+
+            if let Some((a, b)) = swapped
+            {
+                remap = self.swap(V::Index::from_usize(a), V::Index::from_usize(b));
+                result = true;
+            }
+            else if let Some(i) = removed
+            {
+                remap = self.remove(V::Index::from_usize(i));
+                result = true;
+            }
         }
 
-        result
+        (result, remap)
     }
 }
 
+#[cfg(feature = "ui")]
 impl<T> UiDisplay for T
     where T: Index
 {
@@ -543,22 +1159,36 @@ impl<T> UiDisplay for T
     }
 }
 
+#[cfg(feature = "ui")]
 impl<T> UiEdit for T
     where T: Index
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
-        let mut as_usize = self.to_usize();
-
-        if ui.imgui.input_scalar(label, &mut as_usize).build()
+        match ui.pick_index(label, TypeId::of::<T>(), Some(self.to_usize()), false)
         {
-            *self = T::from_usize(as_usize);
-            return true;
+            IndexPick::Picked(Some(picked)) =>
+            {
+                *self = T::from_usize(picked);
+                true
+            },
+            IndexPick::Picked(None) | IndexPick::Unchanged => false,
+            IndexPick::NoLabels =>
+            {
+                let mut as_usize = self.to_usize();
+
+                if ui.imgui.input_scalar(label, &mut as_usize).build()
+                {
+                    *self = T::from_usize(as_usize);
+                    return true;
+                }
+                false
+            },
         }
-        false
     }
 }
 
+#[cfg(feature = "ui")]
 impl<T> UiDisplay for Option<T>
     where T: Index
 {
@@ -568,18 +1198,31 @@ impl<T> UiDisplay for Option<T>
     }
 }
 
+#[cfg(feature = "ui")]
 impl<T> UiEdit for Option<T>
     where T: Index
 {
     fn ui_edit(&mut self, ui: &UiRenderer, label: &str) -> bool
     {
-        let mut as_usize = self.map(|s| s.to_usize()).unwrap_or(usize::MAX);
-
-        if ui.imgui.input_scalar(label, &mut as_usize).build()
+        match ui.pick_index(label, TypeId::of::<T>(), self.map(|s| s.to_usize()), true)
         {
-            *self = Some(T::from_usize(as_usize));
-            return true;
+            IndexPick::Picked(picked) =>
+            {
+                *self = picked.map(T::from_usize);
+                true
+            },
+            IndexPick::Unchanged => false,
+            IndexPick::NoLabels =>
+            {
+                let mut as_usize = self.map(|s| s.to_usize()).unwrap_or(usize::MAX);
+
+                if ui.imgui.input_scalar(label, &mut as_usize).build()
+                {
+                    *self = Some(T::from_usize(as_usize));
+                    return true;
+                }
+                false
+            },
         }
-        false
     }
 }
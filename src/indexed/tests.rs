@@ -0,0 +1,116 @@
+use crate::desc::edit::{Clipboard, ClipboardItem, Geom, Group, Material, MaterialVariant, Object, Scene, Texture, Transform};
+use crate::indexed::{GeomIndex, Index, MaterialIndex, ObjectIndex, TextureIndex, TransformIndex};
+
+fn diffuse(texture: usize) -> Material
+{
+    Material::Diffuse{ texture: TextureIndex::from_usize(texture), normal_texture: None }
+}
+
+fn object_material(scene: &Scene, object: usize) -> usize
+{
+    scene.collection.map_item(ObjectIndex::from_usize(object), |o: &Object, _| o.material).to_usize()
+}
+
+#[test]
+fn test_remove_and_swap()
+{
+    let mut scene = Scene::new();
+
+    let unused = scene.collection.push_named(diffuse(0), "Unused".to_owned());
+    let red = scene.collection.push_named(diffuse(1), "Red".to_owned());
+    let blue = scene.collection.push_named(diffuse(2), "Blue".to_owned());
+
+    scene.collection.push(Object { geom: GeomIndex::default(), material: red, ray_bias: None, opacity: None });
+    scene.collection.push(Object { geom: GeomIndex::default(), material: blue, ray_bias: None, opacity: None });
+    scene.material_variants.push(MaterialVariant { name: "Blue".to_owned(), object: ObjectIndex::from_usize(0), material: blue });
+
+    // Used items can't be removed
+
+    assert!(scene.remove(red).is_err());
+    assert!(scene.remove(blue).is_err());
+
+    // Later items move down, and everything referring to them follows
+
+    scene.remove(unused).unwrap();
+
+    assert_eq!(object_material(&scene, 0), 0);
+    assert_eq!(object_material(&scene, 1), 1);
+    assert_eq!(scene.material_variants[0].material, MaterialIndex::from_usize(1));
+    assert_eq!(scene.collection.find_by_name::<MaterialIndex>("Red"), Some(MaterialIndex::from_usize(0)));
+    assert_eq!(scene.collection.find_by_name::<MaterialIndex>("Unused"), None);
+
+    scene.swap(MaterialIndex::from_usize(0), MaterialIndex::from_usize(1));
+
+    assert_eq!(object_material(&scene, 0), 1);
+    assert_eq!(object_material(&scene, 1), 0);
+    assert_eq!(scene.material_variants[0].material, MaterialIndex::from_usize(0));
+    assert_eq!(scene.collection.find_by_name::<MaterialIndex>("Blue"), Some(MaterialIndex::from_usize(0)));
+    assert!(matches!(scene.collection.map_item(MaterialIndex::from_usize(1), |m, _| m.clone()), Material::Diffuse{ texture, .. } if texture.to_usize() == 1));
+}
+
+#[test]
+fn test_furnace_object()
+{
+    let mut scene = Scene::new();
+
+    let first = scene.collection.push(Object { geom: GeomIndex::default(), material: MaterialIndex::default(), ray_bias: None, opacity: None });
+    let second = scene.collection.push(Object { geom: GeomIndex::default(), material: MaterialIndex::default(), ray_bias: None, opacity: None });
+    scene.furnace.object = second;
+
+    // The furnace test's object follows it when earlier objects are removed
+
+    scene.remove(first).unwrap();
+    assert_eq!(scene.furnace.object, ObjectIndex::from_usize(0));
+    assert!(crate::desc::SceneDescription::new_furnace(&scene).is_ok());
+
+    // And it can't be tested once it's gone
+
+    scene.remove(scene.furnace.object).unwrap();
+    assert!(crate::desc::SceneDescription::new_furnace(&scene).is_err());
+
+    scene.furnace.object = ObjectIndex::from_usize(5);
+    assert!(crate::desc::SceneDescription::new_furnace(&scene).is_err());
+}
+
+#[test]
+fn test_paste()
+{
+    let mut source = Scene::new();
+
+    let texture = source.collection.push_named(Texture::default(), "Texture".to_owned());
+    let material = source.collection.push_named(diffuse(texture.to_usize()), "Material".to_owned());
+    let outer = source.collection.push_named(Transform::new(), "Outer".to_owned());
+    let inner = source.collection.push_named(Transform { pre: Some(outer), ..Transform::new() }, "Inner".to_owned());
+    let geom = source.collection.push_named(Geom::Mesh{ triangles: Vec::new(), transform: Transform { pre: Some(inner), ..Transform::new() }, lod: None }, "Mesh".to_owned());
+    let first = source.collection.push(Object { geom, material, ray_bias: None, opacity: None });
+    let second = source.collection.push(Object { geom, material, ray_bias: None, opacity: None });
+    let group = source.collection.push_named(Group { objects: vec![first, second], groups: Vec::new(), transform: Transform::new() }, "Group".to_owned());
+
+    let mut dest = Scene::new();
+
+    dest.collection.push_named(Texture::default(), "Texture".to_owned());
+    dest.collection.push(Transform::new());
+    dest.collection.push(diffuse(0));
+    dest.collection.push(Geom::default());
+
+    Clipboard::new(&source, ClipboardItem::Group(group)).paste_into(&mut dest);
+
+    // Everything the group refers to is copied once, with
+    // its indexes following the copies
+
+    let objects = dest.collection.map_all(|o: &Object, _| o.clone());
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[0].material, objects[1].material);
+    assert_eq!(objects[0].geom, objects[1].geom);
+    assert_eq!(dest.collection.map_all(|_: &Material, _| ()).len(), 2);
+
+    let texture = dest.collection.map_item(objects[0].material, |m, _| match m { Material::Diffuse{ texture, .. } => *texture, _ => panic!() });
+    assert_eq!(dest.collection.name_of(texture), Some("Texture (2)".to_owned()));
+
+    let pre = dest.collection.map_item(objects[0].geom, |g, _| match g { Geom::Mesh{ transform, .. } => transform.pre, _ => None }).unwrap();
+    assert_eq!(dest.collection.name_of(pre), Some("Inner".to_owned()));
+
+    let pre = dest.collection.map_item(pre, |t: &Transform, _| t.pre).unwrap();
+    assert_eq!(dest.collection.name_of(pre), Some("Outer".to_owned()));
+    assert_eq!(dest.collection.map_item(pre, |t: &Transform, _| t.pre), None::<TransformIndex>);
+}
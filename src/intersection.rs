@@ -1,6 +1,7 @@
 use crate::color::LinearRGB;
 use crate::material::Material;
 use crate::math::Scalar;
+use crate::object::Object;
 use crate::ray::Ray;
 use crate::vec::{Dir3, Point3};
 
@@ -20,10 +21,41 @@ pub struct SurfaceIntersection<'r>
     pub normal: Dir3,
     pub texture_coords: Option<Point3>,
     pub opt_color: Option<LinearRGB>,
+    /// Directions of increasing u and v texture
+    /// coordinates - used for normal mapping
+    pub tangents: Option<[Dir3; 2]>,
 }
 
 impl<'r> SurfaceIntersection<'r>
 {
+    pub fn with_tangents(mut self, tangent: Dir3, bitangent: Dir3) -> Self
+    {
+        // Keep the same handedness as the
+        // normal, which is flipped for back faces
+
+        self.tangents = match self.face
+        {
+            Face::Front => Some([tangent, bitangent]),
+            Face::Back => Some([-tangent, -bitangent]),
+        };
+        self
+    }
+
+    /// Replaces the geometric normal with one for shading, e.g.
+    /// interpolated from a mesh's vertices - turned to the same
+    /// side of the surface as the geometric normal, which has
+    /// already been turned towards the ray for back faces
+    pub fn with_shading_normal(mut self, normal: Dir3) -> Self
+    {
+        if normal.magnitude_squared() > 0.0
+        {
+            let normal = normal.normalized();
+
+            self.normal = if normal.dot(self.normal) < 0.0 { -normal } else { normal };
+        }
+        self
+    }
+
     pub fn location(&self) -> Point3
     {
         match self.location
@@ -43,6 +75,7 @@ pub struct ObjectIntersection<'r, 'm>
 {
     pub surface: SurfaceIntersection<'r>,
     pub material: &'m Material,
+    pub object: &'m Object,
 }
 
 pub struct ShadingIntersection
@@ -53,11 +86,38 @@ pub struct ShadingIntersection
     pub texture_coords: Point3,
     pub opt_color: Option<LinearRGB>,
     pub face: Face,
+    pub tangents: Option<[Dir3; 2]>,
+    pub ray_bias: Scalar,
+}
+
+impl ShadingIntersection
+{
+    /// A ray leaving the surface, with its source offset by the
+    /// ray bias to the side of the surface that it's leaving from
+    pub fn spawn_ray(&self, dir: Dir3) -> Ray
+    {
+        if self.ray_bias == 0.0
+        {
+            return Ray::new(self.location, dir);
+        }
+
+        let side = if self.normal.dot(dir) < 0.0 { -1.0 } else { 1.0 };
+
+        Ray::new(self.location + self.normal * (side * self.ray_bias), dir)
+    }
 }
 
 impl<'r> From<SurfaceIntersection<'r>> for ShadingIntersection
 {
     fn from(val: SurfaceIntersection<'r>) -> Self
+    {
+        (&val).into()
+    }
+}
+
+impl<'r> From<&SurfaceIntersection<'r>> for ShadingIntersection
+{
+    fn from(val: &SurfaceIntersection<'r>) -> Self
     {
         ShadingIntersection
         {
@@ -67,6 +127,8 @@ impl<'r> From<SurfaceIntersection<'r>> for ShadingIntersection
             texture_coords: val.texture_coords(),
             opt_color: val.opt_color,
             face: val.face,
+            tangents: val.tangents,
+            ray_bias: 0.0,
         }
     }
 }
@@ -1,9 +1,16 @@
+pub mod api;
+pub mod atmosphere;
+pub mod autosave;
+pub mod bake;
 pub mod bsdf;
 pub mod camera;
+pub mod capture;
 pub mod color;
 pub mod desc;
 pub mod exec;
+pub mod export;
 pub mod geom;
+pub mod guiding;
 pub mod import;
 pub mod indexed;
 pub mod intersection;
@@ -11,10 +18,16 @@ pub mod lighting;
 pub mod material;
 pub mod math;
 pub mod object;
+pub mod occlusion;
+pub mod probe;
+pub mod radiance;
 pub mod ray;
 pub mod render;
+pub mod reservoir;
 pub mod sample;
 pub mod scene;
+pub mod settings;
 pub mod texture;
+#[cfg(feature = "ui")]
 pub mod ui;
 pub mod vec;
\ No newline at end of file
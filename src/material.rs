@@ -2,13 +2,32 @@ use crate::color::LinearRGB;
 use crate::intersection::{Face, ShadingIntersection};
 use crate::math::Scalar;
 use crate::texture::Texture;
+use crate::vec::Dir3;
 
 pub enum MaterialInteraction
 {
     Diffuse{ diffuse_color: LinearRGB},
     Reflection{ attenuate_color: LinearRGB, fuzz: Scalar },
-    Refraction{ ior: Scalar },
+    Pbr{ base_color: LinearRGB, metallic: Scalar, roughness: Scalar },
+    Refraction{ ior: Scalar, tint: LinearRGB },
     Emit{ emitted_color: LinearRGB},
+    Medium{ albedo: LinearRGB, emitted_color: LinearRGB },
+}
+
+impl MaterialInteraction
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            MaterialInteraction::Diffuse{..} => "Diffuse",
+            MaterialInteraction::Reflection{..} => "Reflection",
+            MaterialInteraction::Pbr{..} => "PBR",
+            MaterialInteraction::Refraction{..} => "Refraction",
+            MaterialInteraction::Emit{..} => "Emit",
+            MaterialInteraction::Medium{..} => "Medium",
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -16,9 +35,13 @@ pub enum Material
 {
     Diffuse(Texture),
     Metal(Texture, Scalar),
-    Dielectric(Scalar),
+    Pbr(Texture, Scalar, Scalar),
+    Dielectric(Scalar, Option<Texture>),
     Emit(Texture),
+    Medium(Texture, Texture),
     FrontBack(Box<Material>, Box<Material>),
+    NormalMapped(Box<Material>, Texture),
+    Opaque(Box<Material>),
 }
 
 impl Material
@@ -33,9 +56,15 @@ impl Material
         Material::Metal(texture, fuzz)
     }
 
+    /// The metallic-roughness model used by glTF
+    pub fn pbr(texture: Texture, metallic: Scalar, roughness: Scalar) -> Material
+    {
+        Material::Pbr(texture, metallic, roughness)
+    }
+
     pub fn dielectric(ior: Scalar) -> Material
     {
-        Material::Dielectric(ior)
+        Material::Dielectric(ior, None)
     }
 
     pub fn emit(texture: Texture) -> Material
@@ -43,11 +72,31 @@ impl Material
         Material::Emit(texture)
     }
 
+    pub fn medium(albedo: Texture, emission: Texture) -> Material
+    {
+        Material::Medium(albedo, emission)
+    }
+
     pub fn front_back(front: Material, back: Material) -> Material
     {
         Material::FrontBack(Box::new(front), Box::new(back))
     }
 
+    /// Perturbs the surface normal by a tangent-space normal map,
+    /// encoded with the usual +X right, +Y up, +Z out convention
+    pub fn normal_mapped(material: Material, normal_texture: Texture) -> Material
+    {
+        Material::NormalMapped(Box::new(material), normal_texture)
+    }
+
+    /// Ignores the alpha of the material's colors - for objects
+    /// with an opacity texture, which has already decided
+    /// that the ray hits the surface
+    pub fn opaque(material: Material) -> Material
+    {
+        Material::Opaque(Box::new(material))
+    }
+
     pub fn front_only(front: Material) -> Material
     {
         Self::front_back(
@@ -55,13 +104,15 @@ impl Material
             Material::Emit(Texture::solid(LinearRGB::black())))
     }
 
-    pub fn get_surface_interaction(&self, intersection: &ShadingIntersection) -> MaterialInteraction
+    /// Normal mapped materials also update the normal
+    /// of the intersection, to be used for shading
+    pub fn get_surface_interaction(&self, intersection: &mut ShadingIntersection) -> MaterialInteraction
     {
         match self
         {
             Material::Diffuse(texture) =>
             {
-                let mut diffuse_color = texture.get_color_at(intersection.texture_coords);
+                let mut diffuse_color = texture.get_color_at(intersection);
 
                 if let Some(color_coords) = intersection.opt_color
                 {
@@ -72,7 +123,7 @@ impl Material
             },
             Material::Metal(texture, fuzz) =>
             {
-                let mut attenuate_color = texture.get_color_at(intersection.texture_coords);
+                let mut attenuate_color = texture.get_color_at(intersection);
 
                 if let Some(color_coords) = intersection.opt_color
                 {
@@ -85,16 +136,33 @@ impl Material
                     fuzz: *fuzz,
                 }
             },
-            Material::Dielectric(ior) =>
+            Material::Pbr(texture, metallic, roughness) =>
+            {
+                let mut base_color = texture.get_color_at(intersection);
+
+                if let Some(color_coords) = intersection.opt_color
+                {
+                    base_color = base_color.combined_with(&color_coords);
+                }
+
+                MaterialInteraction::Pbr
+                {
+                    base_color,
+                    metallic: metallic.clamp(0.0, 1.0),
+                    roughness: roughness.clamp(0.0, 1.0),
+                }
+            },
+            Material::Dielectric(ior, tint) =>
             {
                 MaterialInteraction::Refraction
                 {
                     ior: *ior,
+                    tint: tint.as_ref().map(|tint| tint.get_color_at(intersection)).unwrap_or_else(LinearRGB::white),
                 }
             },
             Material::Emit(texture) =>
             {
-                let mut emitted_color = texture.get_color_at(intersection.texture_coords);
+                let mut emitted_color = texture.get_color_at(intersection);
 
                 if let Some(color_coords) = intersection.opt_color
                 {
@@ -103,6 +171,14 @@ impl Material
 
                 MaterialInteraction::Emit { emitted_color }
             },
+            Material::Medium(albedo, emission) =>
+            {
+                MaterialInteraction::Medium
+                {
+                    albedo: albedo.get_color_at(intersection),
+                    emitted_color: emission.get_color_at(intersection),
+                }
+            },
             Material::FrontBack(front, back) =>
             {
                 match intersection.face
@@ -111,6 +187,64 @@ impl Material
                     Face::Back => back.get_surface_interaction(intersection),
                 }
             },
+            Material::NormalMapped(material, normal_texture) =>
+            {
+                if let Some(normal) = mapped_normal(intersection, normal_texture)
+                {
+                    intersection.normal = normal;
+                }
+
+                material.get_surface_interaction(intersection)
+            },
+            Material::Opaque(material) =>
+            {
+                match material.get_surface_interaction(intersection)
+                {
+                    MaterialInteraction::Diffuse{ diffuse_color } =>
+                    {
+                        MaterialInteraction::Diffuse{ diffuse_color: LinearRGB::new(diffuse_color.r, diffuse_color.g, diffuse_color.b, 1.0) }
+                    },
+                    MaterialInteraction::Pbr{ base_color, metallic, roughness } =>
+                    {
+                        MaterialInteraction::Pbr{ base_color: LinearRGB::new(base_color.r, base_color.g, base_color.b, 1.0), metallic, roughness }
+                    },
+                    interaction => interaction,
+                }
+            },
         }
     }
 }
+
+fn mapped_normal(intersection: &ShadingIntersection, normal_texture: &Texture) -> Option<Dir3>
+{
+    let [tangent, bitangent] = intersection.tangents?;
+    let normal = intersection.normal;
+
+    // Build an orthonormal basis around the normal. Texture
+    // v coordinates increase down the image, so the map's
+    // "up" is the opposite direction to the bitangent.
+
+    let tangent = tangent - normal * normal.dot(tangent);
+    let bitangent = bitangent - normal * normal.dot(bitangent);
+    let bitangent = bitangent - tangent * (tangent.dot(bitangent) / tangent.magnitude_squared().max(Scalar::MIN_POSITIVE));
+
+    if (tangent.magnitude_squared() == 0.0) || (bitangent.magnitude_squared() == 0.0)
+    {
+        return None;
+    }
+
+    let color = normal_texture.get_color_at(intersection);
+    let mapped = tangent.normalized() * (2.0 * color.r - 1.0)
+        - bitangent.normalized() * (2.0 * color.g - 1.0)
+        + normal * (2.0 * color.b - 1.0);
+
+    // Normals that face away from the viewer
+    // can't be shaded, so are ignored
+
+    if (mapped.magnitude_squared() == 0.0) || (mapped.dot(intersection.incoming) <= 0.0)
+    {
+        return None;
+    }
+
+    Some(mapped.normalized())
+}
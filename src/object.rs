@@ -1,13 +1,85 @@
-use crate::geom::Surface;
-use crate::intersection::ObjectIntersection;
+use crate::geom::{Aabb, Surface};
+use crate::intersection::{ObjectIntersection, ShadingIntersection, SurfaceIntersection};
 use crate::material::Material;
+use crate::math::Scalar;
 use crate::ray::{Ray, RayRange};
+use crate::texture::Texture;
+
+/// A texture that decides whether rays hit the surface, or pass
+/// straight through it, e.g. for leaves on alpha cards
+#[derive(Clone)]
+pub struct Opacity
+{
+    pub texture: Texture,
+    /// Use the texture's luminance, rather than its alpha
+    pub luminance: bool,
+    /// Surfaces are fully opaque at or above the cutoff, and
+    /// fully transparent below. Without it, the opacity is
+    /// the probability of a ray hitting the surface.
+    pub cutoff: Option<Scalar>,
+}
+
+impl Opacity
+{
+    fn is_hit(&self, surface: &SurfaceIntersection) -> bool
+    {
+        let intersection: ShadingIntersection = surface.into();
+        let mut color = self.texture.get_color_at(&intersection);
+
+        let opacity = if self.luminance
+        {
+            (0.2126 * color.r) + (0.7152 * color.g) + (0.0722 * color.b)
+        }
+        else
+        {
+            if let Some(vertex_color) = intersection.opt_color
+            {
+                color = color.combined_with(&vertex_color);
+            }
+
+            color.a
+        };
+
+        match self.cutoff
+        {
+            Some(cutoff) => opacity >= cutoff,
+            None => hash_unit(surface.ray, surface.distance) < opacity,
+        }
+    }
+}
+
+/// A random-looking number from zero to one, that's always the same
+/// for the same hit. Rays are traced without a sampler, but they
+/// start at, and go in, random directions, so this varies as
+/// much as a real random number would.
+fn hash_unit(ray: &Ray, distance: Scalar) -> Scalar
+{
+    let values = [ray.source.x, ray.source.y, ray.source.z, ray.dir.x, ray.dir.y, ray.dir.z, distance];
+
+    let mut h: u64 = 0xCBF2_9CE4_8422_2325;
+
+    for value in values.iter()
+    {
+        h ^= value.to_bits();
+        h = h.wrapping_mul(0x0000_0100_0000_01B3);
+        h ^= h >> 29;
+    }
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+
+    ((h >> 11) as Scalar) / ((1u64 << 53) as Scalar)
+}
 
 #[derive(Clone)]
 pub struct Object
 {
     surface: Box<dyn Surface>,
     material: Material,
+    label: Option<String>,
+    ray_bias: Option<Scalar>,
+    opacity: Option<Opacity>,
 }
 
 impl Object
@@ -18,6 +90,9 @@ impl Object
         {
             surface,
             material,
+            label: None,
+            ray_bias: None,
+            opacity: None,
         }
     }
 
@@ -28,25 +103,82 @@ impl Object
         {
             surface: Box::new(surface),
             material,
+            label: None,
+            ray_bias: None,
+            opacity: None,
         }
     }
 
+    pub fn with_label(mut self, label: String) -> Self
+    {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn with_ray_bias(mut self, ray_bias: Option<Scalar>) -> Self
+    {
+        self.ray_bias = ray_bias;
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: Option<Opacity>) -> Self
+    {
+        self.opacity = opacity;
+        self
+    }
+
+    /// How far rays leaving the surface are offset
+    pub fn ray_bias(&self) -> Scalar
+    {
+        self.ray_bias.unwrap_or(0.0)
+    }
+
+        pub fn label(&self) -> Option<&str>
+    {
+        self.label.as_deref()
+    }
+
+    pub fn is_medium(&self) -> bool
+    {
+        self.surface.is_medium()
+    }
+
+    pub fn transmittance(&self, ray: &Ray, range: &RayRange) -> Scalar
+    {
+        self.surface.transmittance(ray, range)
+    }
+
+    pub fn bounding_aabb(&self) -> Option<Aabb>
+    {
+        self.surface.bounding_aabb()
+    }
+
+    /// Hits that the opacity rejects are skipped, and the
+    /// search continues behind them - this applies to shadow
+    /// rays too, so light shines through the gaps
     pub fn closest_intersection_in_range<'r, 'm>(&'m self, ray: &'r Ray, range: &RayRange) -> Option<ObjectIntersection<'r, 'm>>
     {
-        match self.surface.closest_intersection_in_range(ray, range)
+        let mut range = range.clone();
+
+        loop
         {
-            Some(si) =>
+            let si = self.surface.closest_intersection_in_range(ray, &range)?;
+
+            if let Some(opacity) = &self.opacity
             {
-                Some(ObjectIntersection
+                if !opacity.is_hit(&si)
                 {
-                    surface: si,
-                    material: &self.material,
-                })
-            },
-            None =>
+                    range.set_min(si.distance);
+                    continue;
+                }
+            }
+
+            return Some(ObjectIntersection
             {
-                None
-            },
+                surface: si,
+                material: &self.material,
+                object: self,
+            });
         }
     }
 }
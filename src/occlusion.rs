@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::math::Scalar;
+use crate::vec::{Dir3, Point3};
+
+/// Cells along the diagonal of a typical object
+const CELLS_PER_OBJECT: Scalar = 64.0;
+
+/// Occluders further than this many cells away are ignored
+const RADIUS_CELLS: Scalar = 8.0;
+
+/// Samples each cell collects before it's considered converged
+pub const TARGET_SAMPLES: u32 = 64;
+
+/// Rays traced each time a cell that's not
+/// converged is looked up
+pub const RAYS_PER_LOOKUP: u32 = 2;
+
+pub type CellKey = (i64, i64, i64, u8);
+
+#[derive(Clone, Copy, Default)]
+struct Cell
+{
+    visible: u32,
+    samples: u32,
+}
+
+/// Ambient occlusion for the local lighting preview, stored in a
+/// world-space hash grid and filled in lazily as points are shaded.
+/// Like the mesh cache, it's owned by the application rather than
+/// the scene, so the occlusion is kept while the camera moves, and
+/// the preview gets smoother each frame. It's cleared when the
+/// scene's geometry, as given by its key, changes.
+#[derive(Clone, Default)]
+pub struct AoCache
+{
+    inner: Arc<RwLock<AoCacheInner>>,
+}
+
+#[derive(Default)]
+struct AoCacheInner
+{
+    geometry_key: u64,
+    cell_size: Scalar,
+    cells: HashMap<CellKey, Cell>,
+}
+
+impl AoCache
+{
+    pub fn new() -> Self
+    {
+        AoCache::default()
+    }
+
+    /// Prepares the cache for a scene, clearing it unless the
+    /// scene's geometry is the same as the last one's. Cells are
+    /// sized from the diagonal of a typical object in the scene.
+    pub fn prepare(&self, geometry_key: u64, object_diagonal: Scalar)
+    {
+        let mut inner = self.inner.write().unwrap();
+
+        let cell_size = if object_diagonal.is_finite() && (object_diagonal > 0.0) { object_diagonal / CELLS_PER_OBJECT } else { 0.01 };
+
+        if (inner.geometry_key != geometry_key) || (inner.cell_size != cell_size)
+        {
+            inner.geometry_key = geometry_key;
+            inner.cell_size = cell_size;
+            inner.cells.clear();
+        }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.inner.read().unwrap().cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    pub fn radius(&self) -> Scalar
+    {
+        self.inner.read().unwrap().cell_size * RADIUS_CELLS
+    }
+
+    /// The fraction of the hemisphere that's not occluded,
+    /// interpolated between the cells around the location.
+    /// Also returns the nearest cell, and if it needs more
+    /// samples, for the caller to trace and then add.
+    pub fn lookup(&self, location: Point3, normal: Dir3) -> (Option<Scalar>, Option<CellKey>)
+    {
+        let inner = self.inner.read().unwrap();
+
+        let scaled = location / inner.cell_size;
+        let base = scaled.map(|c| c.floor());
+        let fraction = scaled - base;
+        let face = normal_face(normal);
+
+        let mut total = 0.0;
+        let mut total_weight = 0.0;
+
+        for corner in 0..8
+        {
+            let offset = Point3::new((corner & 1) as Scalar, ((corner >> 1) & 1) as Scalar, ((corner >> 2) & 1) as Scalar);
+
+            if let Some(cell) = inner.cells.get(&cell_key(base + offset, face))
+            {
+                if cell.samples > 0
+                {
+                    let weight = (0..3).map(|axis| if offset[axis] > 0.5 { fraction[axis] } else { 1.0 - fraction[axis] }).product::<Scalar>();
+
+                    total += weight * (cell.visible as Scalar) / (cell.samples as Scalar);
+                    total_weight += weight;
+                }
+            }
+        }
+
+        let nearest = cell_key(scaled.map(|c| c.round()), face);
+        let needs_samples = inner.cells.get(&nearest).map(|c| c.samples < TARGET_SAMPLES).unwrap_or(true);
+
+        let visibility = if total_weight > 0.0 { Some(total / total_weight) } else { None };
+
+        (visibility, if needs_samples { Some(nearest) } else { None })
+    }
+
+    pub fn add_samples(&self, key: CellKey, visible: u32, samples: u32)
+    {
+        let mut inner = self.inner.write().unwrap();
+        let cell = inner.cells.entry(key).or_default();
+
+        cell.visible += visible;
+        cell.samples += samples;
+    }
+}
+
+fn cell_key(p: Point3, face: u8) -> CellKey
+{
+    (p.x as i64, p.y as i64, p.z as i64, face)
+}
+
+/// Points on opposite sides of thin walls share cells,
+/// so they're kept apart by the normal's main direction
+pub fn normal_face(normal: Dir3) -> u8
+{
+    let abs = normal.map(|c| c.abs());
+    let axis = if (abs.x >= abs.y) && (abs.x >= abs.z) { 0 } else if abs.y >= abs.z { 1 } else { 2 };
+
+    ((2 * axis) + if normal[axis] < 0.0 { 1 } else { 0 }) as u8
+}
@@ -0,0 +1,146 @@
+use crate::color::LinearRGB;
+use crate::math::{Scalar, ScalarConsts};
+use crate::vec::{Dir3, Point3};
+
+/// Incident radiance at a point, projected onto the first
+/// nine (order 2) real spherical harmonics.
+#[derive(Clone, Debug)]
+pub struct Sh9
+{
+    sum: [[Scalar; 3]; 9],
+    samples: u64,
+}
+
+impl Sh9
+{
+    pub fn new() -> Self
+    {
+        Sh9 { sum: [[0.0; 3]; 9], samples: 0 }
+    }
+
+    pub fn num_samples(&self) -> u64
+    {
+        self.samples
+    }
+
+    /// Adds a radiance estimate for a direction chosen
+    /// uniformly over the unit sphere.
+    pub fn add_uniform_sample(&mut self, dir: Dir3, radiance: LinearRGB)
+    {
+        let basis = sh_basis(dir);
+
+        for (sum, y) in self.sum.iter_mut().zip(basis.iter())
+        {
+            sum[0] += radiance.r * y;
+            sum[1] += radiance.g * y;
+            sum[2] += radiance.b * y;
+        }
+
+        self.samples += 1;
+    }
+
+    pub fn add(&mut self, other: &Sh9)
+    {
+        for (sum, other) in self.sum.iter_mut().zip(other.sum.iter())
+        {
+            for c in 0..3
+            {
+                sum[c] += other[c];
+            }
+        }
+
+        self.samples += other.samples;
+    }
+
+    /// The projected radiance coefficients.
+    pub fn coefficients(&self) -> [[Scalar; 3]; 9]
+    {
+        if self.samples == 0
+        {
+            return [[0.0; 3]; 9];
+        }
+
+        // Monte-Carlo estimate with a uniform sphere pdf of 1/(4 PI)
+
+        let scale = 4.0 * ScalarConsts::PI / (self.samples as Scalar);
+        self.sum.map(|c| c.map(|v| v * scale))
+    }
+
+    /// Irradiance arriving at a surface with the given normal,
+    /// by convolving the radiance with the clamped cosine lobe
+    /// (Ramamoorthi and Hanrahan 2001).
+    pub fn irradiance(&self, normal: Dir3) -> LinearRGB
+    {
+        const BAND_SCALE: [Scalar; 9] = [
+            ScalarConsts::PI,
+            2.0 * ScalarConsts::PI / 3.0, 2.0 * ScalarConsts::PI / 3.0, 2.0 * ScalarConsts::PI / 3.0,
+            ScalarConsts::FRAC_PI_4, ScalarConsts::FRAC_PI_4, ScalarConsts::FRAC_PI_4, ScalarConsts::FRAC_PI_4, ScalarConsts::FRAC_PI_4,
+        ];
+
+        let coefficients = self.coefficients();
+        let basis = sh_basis(normal);
+
+        let mut result = [0.0; 3];
+
+        for i in 0..9
+        {
+            for c in 0..3
+            {
+                result[c] += BAND_SCALE[i] * coefficients[i][c] * basis[i];
+            }
+        }
+
+        LinearRGB::new(result[0].max(0.0), result[1].max(0.0), result[2].max(0.0), 1.0)
+    }
+}
+
+impl Default for Sh9
+{
+    fn default() -> Self
+    {
+        Sh9::new()
+    }
+}
+
+fn sh_basis(dir: Dir3) -> [Scalar; 9]
+{
+    let (x, y, z) = (dir.x, dir.y, dir.z);
+
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+/// Formats probe results as JSON, with the coefficients
+/// for each probe listed in the standard (l, m) order.
+pub fn probes_to_json(probes: &[(Point3, Sh9)]) -> String
+{
+    let mut result = String::new();
+    result.push_str("{\n    \"probes\": [\n");
+
+    for (i, (location, sh)) in probes.iter().enumerate()
+    {
+        let coefficients = sh.coefficients()
+            .iter()
+            .map(|c| format!("[{}, {}, {}]", c[0], c[1], c[2]))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        result.push_str("        {\n");
+        result.push_str(&format!("            \"location\": [{}, {}, {}],\n", location.x, location.y, location.z));
+        result.push_str(&format!("            \"samples\": {},\n", sh.num_samples()));
+        result.push_str(&format!("            \"sh9\": [{}]\n", coefficients));
+        result.push_str(if (i + 1) < probes.len() { "        },\n" } else { "        }\n" });
+    }
+
+    result.push_str("    ]\n}\n");
+    result
+}
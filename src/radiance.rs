@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use crate::camera::Camera;
+use crate::color::LinearRGB;
+use crate::math::Scalar;
+use crate::occlusion::normal_face;
+use crate::vec::{Dir3, Point3};
+
+/// Cells are sized so about this many span the
+/// camera's view, at each distance from it
+const CELLS_ACROSS_VIEW: Scalar = 32.0;
+
+/// Samples each cell collects before its radiance
+/// is used in place of tracing further
+pub const TARGET_SAMPLES: u32 = 64;
+
+/// Cells are split between separately locked
+/// maps, so render threads rarely wait
+const NUM_SHARDS: usize = 64;
+
+/// Position, normal direction and size level
+pub type CellKey = (i64, i64, i64, u8, i32);
+
+/// A world-space grid whose cells grow with distance from the
+/// camera, so they cover about the same area of the image
+#[derive(Clone, Copy, Debug)]
+pub struct ViewGrid
+{
+    camera_location: Point3,
+    /// Width of each cell at unit distance
+    cell_width: Scalar,
+}
+
+impl ViewGrid
+{
+    pub fn new(camera: &Camera, cells_across_view: Scalar) -> Self
+    {
+        ViewGrid { camera_location: camera.location(), cell_width: camera.view_width() / cells_across_view }
+    }
+
+    /// The cell containing the location, and the
+    /// offset from its center, in cells
+    pub fn cell(&self, location: Point3, normal: Dir3) -> (CellKey, Point3)
+    {
+        let distance = (location - self.camera_location).magnitude();
+        let level = ((distance * self.cell_width).max(1.0e-6).log2().ceil() as i32).clamp(-40, 40);
+        let size = (2.0 as Scalar).powi(level);
+
+        let scaled = location / size;
+        let cell = scaled.map(|c| c.floor());
+        let offset = scaled - cell - Point3::broadcast(0.5);
+
+        ((cell.x as i64, cell.y as i64, cell.z as i64, normal_face(normal), level), offset)
+    }
+}
+
+/// Where a point falls in the cache
+#[derive(Clone, Copy, Debug)]
+pub struct CachePoint
+{
+    key: CellKey,
+    /// From the cell's center, in cells
+    offset: Point3,
+}
+
+/// Sums over a cell's samples, which give their mean and,
+/// by least squares, how the radiance changes across the cell
+#[derive(Clone, Copy, Default)]
+struct Cell
+{
+    samples: u32,
+    radiance: [Scalar; 3],
+    offset: [Scalar; 3],
+    offset_squared: [Scalar; 3],
+    /// Per channel, then per axis
+    radiance_offset: [[Scalar; 3]; 3],
+}
+
+impl Cell
+{
+    fn add(&mut self, offset: Point3, radiance: [Scalar; 3])
+    {
+        self.samples += 1;
+
+        for axis in 0..3
+        {
+            self.offset[axis] += offset[axis];
+            self.offset_squared[axis] += offset[axis] * offset[axis];
+        }
+
+        for ((sum, sum_offset), value) in self.radiance.iter_mut().zip(self.radiance_offset.iter_mut()).zip(radiance.iter())
+        {
+            *sum += value;
+
+            for (axis, s) in sum_offset.iter_mut().enumerate()
+            {
+                *s += value * offset[axis];
+            }
+        }
+    }
+
+    /// The mean radiance, moved along the gradient
+    /// to the offset - limited to between zero and
+    /// twice the mean, as gradients are noisy
+    fn estimate(&self, offset: Point3) -> LinearRGB
+    {
+        let n = self.samples.max(1) as Scalar;
+
+        let channel = |sum: Scalar, sum_offset: &[Scalar; 3]|
+        {
+            let mean = sum / n;
+            let mut value = mean;
+
+            for axis in 0..3
+            {
+                let mean_offset = self.offset[axis] / n;
+                let variance = (self.offset_squared[axis] / n) - (mean_offset * mean_offset);
+
+                if variance > 1.0e-4
+                {
+                    let covariance = (sum_offset[axis] / n) - (mean * mean_offset);
+                    value += (covariance / variance) * (offset[axis] - mean_offset);
+                }
+            }
+
+            value.clamp(0.0, 2.0 * mean)
+        };
+
+        LinearRGB::new(
+            channel(self.radiance[0], &self.radiance_offset[0]),
+            channel(self.radiance[1], &self.radiance_offset[1]),
+            channel(self.radiance[2], &self.radiance_offset[2]),
+            1.0)
+    }
+}
+
+/// Caches the radiance leaving diffuse surfaces, in a hash grid
+/// whose cells grow with distance from the camera. Paths that
+/// reach a diffuse surface after a diffuse bounce use the cache
+/// in place of tracing further, once the cell has enough samples,
+/// and until then, add the radiance they find to it. It's built
+/// for each render, as it depends on the whole scene.
+#[derive(Clone)]
+pub struct RadianceCache
+{
+    grid: ViewGrid,
+    shards: Arc<Vec<Mutex<HashMap<CellKey, Cell>>>>,
+}
+
+impl RadianceCache
+{
+    pub fn new(camera: &Camera) -> Self
+    {
+        let shards = Arc::new((0..NUM_SHARDS).map(|_| Mutex::new(HashMap::new())).collect());
+
+        RadianceCache { grid: ViewGrid::new(camera, CELLS_ACROSS_VIEW), shards }
+    }
+
+    pub fn point(&self, location: Point3, normal: Dir3) -> CachePoint
+    {
+        let (key, offset) = self.grid.cell(location, normal);
+
+        CachePoint { key, offset }
+    }
+
+    /// The cached radiance, if the point's cell has collected enough samples
+    pub fn radiance(&self, point: &CachePoint) -> Option<LinearRGB>
+    {
+        let shard = self.shard(&point.key).lock().unwrap();
+
+        shard.get(&point.key)
+            .filter(|cell| cell.samples >= TARGET_SAMPLES)
+            .map(|cell| cell.estimate(point.offset))
+    }
+
+    /// Samples added to cells that have enough are ignored
+    pub fn add_sample(&self, point: &CachePoint, radiance: LinearRGB)
+    {
+        let values = [radiance.r, radiance.g, radiance.b];
+
+        if !values.iter().all(|v| v.is_finite())
+        {
+            return;
+        }
+
+        let mut shard = self.shard(&point.key).lock().unwrap();
+        let cell = shard.entry(point.key).or_default();
+
+        if cell.samples < TARGET_SAMPLES
+        {
+            cell.add(point.offset, values);
+        }
+    }
+
+    /// Shows the cells - their radiance so far, tinted red
+    /// until they have enough samples, or magenta if empty
+    pub fn visualize(&self, point: &CachePoint) -> LinearRGB
+    {
+        let shard = self.shard(&point.key).lock().unwrap();
+
+        match shard.get(&point.key)
+        {
+            Some(cell) if cell.samples >= TARGET_SAMPLES => cell.estimate(point.offset),
+            Some(cell) => cell.estimate(point.offset).combined_with(&LinearRGB::new(1.0, 0.5, 0.5, 1.0)),
+            None => LinearRGB::new(1.0, 0.0, 1.0, 1.0),
+        }
+    }
+
+    fn shard(&self, key: &CellKey) -> &Mutex<HashMap<CellKey, Cell>>
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        &self.shards[(hasher.finish() as usize) % NUM_SHARDS]
+    }
+}
@@ -29,6 +29,7 @@ impl Ray
                 normal: normal,
                 texture_coords: None,
                 opt_color: None,
+                tangents: None,
             }
         }
         else
@@ -42,6 +43,7 @@ impl Ray
                 normal: -normal,
                 texture_coords: None,
                 opt_color: None,
+                tangents: None,
             }
         }
     }
@@ -59,6 +61,7 @@ impl Ray
                 normal: normal,
                 texture_coords: Some(texture_coords),
                 opt_color,
+                tangents: None,
             }
         }
         else
@@ -72,6 +75,7 @@ impl Ray
                 normal: -normal,
                 texture_coords: Some(texture_coords),
                 opt_color,
+                tangents: None,
             }
         }
     }
@@ -89,6 +93,7 @@ impl Ray
                 normal: normal,
                 texture_coords: None,
                 opt_color: None,
+                tangents: None,
             }
         }
         else
@@ -102,6 +107,7 @@ impl Ray
                 normal: -normal,
                 texture_coords: None,
                 opt_color: None,
+                tangents: None,
             }
         }
     }
@@ -1,12 +1,21 @@
+use crate::camera::Camera;
 use crate::color;
-use crate::desc::SceneDescription;
+use crate::desc::{BuildMonitor, BuildProgress, SceneDescription};
+use crate::export::{RenderStatus, write_status_file};
+use crate::geom::{MeshCache, OctreeQuality};
 use crate::math::Scalar;
-use crate::scene::{SamplingMode, Scene, SceneSampleStats};
+use crate::occlusion::AoCache;
+use crate::probe::Sh9;
+use crate::ray::Ray;
+use crate::scene::{SamplingMode, Scene, SceneSampleStats, TraceOptions};
 use crate::sample::Sampler;
+use crate::vec::Point3;
 
 use std::time::{Instant, Duration};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
-use crossbeam::channel::Sender;
+use crossbeam::channel::{Sender, TryRecvError};
 use itertools::Itertools;
 use rand::{thread_rng, seq::SliceRandom};
 
@@ -17,14 +26,95 @@ pub enum RenderIlluminationMode
     Global,
 }
 
+/// The order that pixels are rendered in each pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder
+{
+    Random,
+    Scanline,
+    Hilbert,
+    /// Outwards from the center of the image, so
+    /// the subject usually converges first
+    Spiral,
+}
+
+impl TileOrder
+{
+    pub const ALL: [TileOrder; 4] = [TileOrder::Random, TileOrder::Scanline, TileOrder::Hilbert, TileOrder::Spiral];
+}
+
 #[derive(Clone)]
 pub struct RenderOptions
 {
     pub width: u32,
     pub height: u32,
     pub illumination_mode: RenderIlluminationMode,
+    /// Ambient occlusion in local illumination, from the
+    /// AO cache the renderer is given
+    pub ambient_occlusion: bool,
+    /// Samples per pixel in local illumination - more than
+    /// one are jittered within the pixel, to antialias edges
+    pub local_samples: usize,
     pub sampling_mode: SamplingMode,
     pub max_blockiness: u32,
+    pub tile_order: TileOrder,
+    /// Global illumination stops once each pixel has this many samples
+    pub max_samples: usize,
+    /// Maximum number of times a path can scatter, in total
+    /// and off each type of material - see `TraceOptions`
+    pub max_bounces: usize,
+    pub max_diffuse_bounces: usize,
+    pub max_glossy_bounces: usize,
+    pub max_transmission_bounces: usize,
+    /// Dielectrics that shadow rays pass through - see `TraceOptions`
+    pub max_shadow_transparent_hits: usize,
+    /// Filter the image once sampling completes
+    pub denoise: bool,
+    /// Radiance clamps for light reached after one, or more than
+    /// one, bounce - see `TraceOptions`. Zero disables them.
+    pub direct_clamp: Scalar,
+    pub indirect_clamp: Scalar,
+    /// Global illumination stops early once the estimated noise
+    /// is below this, or after this many seconds of sampling.
+    /// Zero disables them.
+    pub target_noise: Scalar,
+    pub time_limit: Scalar,
+    /// While the user is interacting, only the blocky preview
+    /// passes are rendered - the full schedule starts once there's
+    /// been no interaction for this many milliseconds.
+    pub interaction_delay_ms: u64,
+    /// Pixels that are sampled ahead of the rest of the image,
+    /// so they converge first
+    pub priority_region: Option<PixelRect>,
+    /// Progress is periodically written to this file as JSON
+    pub status_file: Option<String>,
+    /// Tint surfaces at the camera's focus distance
+    pub show_focal_plane: bool,
+    /// Global illumination uses a radiance cache for light
+    /// reached through diffuse bounces - faster to converge,
+    /// especially indoors, but slightly blurred and biased
+    pub radiance_cache: bool,
+    /// Show the radiance cache's cells in place of the render
+    pub show_radiance_cache: bool,
+    /// Global illumination learns where light comes from as it
+    /// renders, and steers diffuse bounces towards it - slower
+    /// per sample, but converges faster with difficult lighting
+    pub path_guiding: bool,
+    /// Bake transforms into mesh triangles, rather than keeping
+    /// them in object space - slower to rebuild after an edit,
+    /// but faster to trace
+    pub bake_transforms: bool,
+    /// Trades mesh octree build time against trace speed
+    pub octree_quality: OctreeQuality,
+    /// How the accumulated pixels are converted for display and
+    /// 8-bit export - exposure and tone mapping, then the display
+    /// transform, followed by grading LUTs in order. These don't
+    /// affect the samples, so don't need the render to restart.
+    pub tone_mapping: color::ToneMapping,
+    pub display_transform: color::DisplayTransform,
+    pub grades: Vec<color::ColorGrade>,
+    /// Film response and grain, applied last
+    pub film: Option<color::FilmEmulation>,
 }
 
 impl RenderOptions
@@ -32,10 +122,65 @@ impl RenderOptions
     pub fn new(width: u32, height: u32) -> Self
     {
         let illumination_mode = RenderIlluminationMode::Global;
+        let ambient_occlusion = false;
+        let local_samples = 1;
         let sampling_mode = SamplingMode::BsdfAndLights;
         let max_blockiness = 1024;
+        let tile_order = TileOrder::Random;
+        let max_samples = 8096;
+        let max_bounces = 50;
+        let max_diffuse_bounces = 50;
+        let max_glossy_bounces = 50;
+        let max_transmission_bounces = 50;
+        let max_shadow_transparent_hits = 8;
+        let denoise = false;
+        let direct_clamp = 0.0;
+        let indirect_clamp = 0.0;
+        let target_noise = 0.0;
+        let time_limit = 0.0;
+        let interaction_delay_ms = 300;
+        let priority_region = None;
+        let status_file = None;
+        let show_focal_plane = false;
+        let radiance_cache = false;
+        let show_radiance_cache = false;
+        let path_guiding = false;
+        let bake_transforms = false;
+        let octree_quality = OctreeQuality::default();
+        let tone_mapping = color::ToneMapping::default();
+        let display_transform = color::DisplayTransform::default();
+        let grades = Vec::new();
+        let film = None;
+
+        RenderOptions { width, height, illumination_mode, ambient_occlusion, local_samples, sampling_mode, max_blockiness, tile_order, max_samples, max_bounces, max_diffuse_bounces, max_glossy_bounces, max_transmission_bounces, max_shadow_transparent_hits, denoise, direct_clamp, indirect_clamp, target_noise, time_limit, interaction_delay_ms, priority_region, status_file, show_focal_plane, radiance_cache, show_radiance_cache, path_guiding, bake_transforms, octree_quality, tone_mapping, display_transform, grades, film }
+    }
+
+    /// The pixel position is used for film grain
+    pub fn display_color(&self, color: color::LinearRGB, x: u32, y: u32) -> color::SRGB
+    {
+        let color = self.grades.iter().fold(self.display_transform.apply(self.tone_mapping.apply(color)), |color, grade| grade.apply(color));
+
+        match &self.film
+        {
+            Some(film) => film.apply(color, x, y),
+            None => color,
+        }
+    }
 
-        RenderOptions { width, height, illumination_mode, sampling_mode, max_blockiness }
+    pub fn trace_options(&self) -> TraceOptions
+    {
+        TraceOptions
+        {
+            max_bounces: self.max_bounces,
+            max_diffuse_bounces: self.max_diffuse_bounces,
+            max_glossy_bounces: self.max_glossy_bounces,
+            max_transmission_bounces: self.max_transmission_bounces,
+            max_shadow_transparent_hits: self.max_shadow_transparent_hits,
+            direct_clamp: self.direct_clamp,
+            indirect_clamp: self.indirect_clamp,
+            show_focal_plane: self.show_focal_plane,
+            show_radiance_cache: self.show_radiance_cache,
+        }
     }
 }
 
@@ -48,6 +193,38 @@ pub struct PixelRect
     pub height: u32,
 }
 
+impl PixelRect
+{
+    pub fn contains(&self, x: u32, y: u32) -> bool
+    {
+        (x >= self.x) && (y >= self.y) && (x < self.x + self.width) && (y < self.y + self.height)
+    }
+}
+
+/// The pixels rendered in a pass
+enum PassPixels
+{
+    All,
+    Inside(PixelRect),
+    Outside(PixelRect),
+    /// Those without samples, e.g. that couldn't be reprojected
+    Unsampled,
+}
+
+impl PassPixels
+{
+    fn includes(&self, x: u32, y: u32, sampled: bool) -> bool
+    {
+        match self
+        {
+            PassPixels::All => true,
+            PassPixels::Inside(rect) => rect.contains(x, y),
+            PassPixels::Outside(rect) => !rect.contains(x, y),
+            PassPixels::Unsampled => !sampled,
+        }
+    }
+}
+
 pub struct PixelUpdate
 {
     pub rect: PixelRect,
@@ -60,6 +237,30 @@ pub struct RenderProgress
     pub total_duration: Duration,
     pub avg_duration_per_sample: Duration,
     pub stats: SceneSampleStats,
+    /// Set while the scene is being built
+    pub build: Option<BuildProgress>,
+    /// Set once global illumination has two or more samples per pixel
+    pub convergence: Option<Convergence>,
+    /// Rays traced per second of (wall clock) render time
+    pub rays_per_second: f64,
+    /// The passes completed so far
+    pub passes: Vec<PassTiming>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PassTiming
+{
+    pub name: String,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Convergence
+{
+    /// The average relative standard error of the pixels
+    pub noise: Scalar,
+    /// Estimated sampling time until the target noise is reached
+    pub time_to_target: Option<Duration>,
 }
 
 pub struct RenderUpdate
@@ -67,29 +268,177 @@ pub struct RenderUpdate
     pub progress: RenderProgress,
     pub complete: bool,
     pub pixels: Vec<PixelUpdate>,
+    pub probes: Vec<(Point3, Sh9)>,
+}
+
+/// Records when the user last interacted (e.g. moved the camera),
+/// so the renderer can hold back expensive passes while they
+/// continue. It's owned by the application, and shared with
+/// each renderer that's started.
+#[derive(Clone, Default)]
+pub struct InteractionTracker
+{
+    last: Arc<Mutex<Option<Instant>>>,
+}
+
+impl InteractionTracker
+{
+    pub fn new() -> Self
+    {
+        InteractionTracker::default()
+    }
+
+    pub fn notify(&self)
+    {
+        *self.last.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Time since the last interaction - if there's been
+    /// none then there's nothing to wait for
+    pub fn idle_duration(&self) -> Duration
+    {
+        match *self.last.lock().unwrap()
+        {
+            Some(last) => last.elapsed(),
+            None => Duration::MAX,
+        }
+    }
+
+    /// Waits until there's been no interaction for the delay.
+    /// Returns false if cancelled while waiting.
+    fn wait_until_idle(&self, delay: Duration, cancelled: &AtomicBool) -> bool
+    {
+        loop
+        {
+            if cancelled.load(Ordering::Relaxed)
+            {
+                return false;
+            }
+
+            let idle = self.idle_duration();
+
+            if idle >= delay
+            {
+                return true;
+            }
+
+            std::thread::sleep((delay - idle).min(Duration::from_millis(20)));
+        }
+    }
+}
+
+/// The pixels of a render, as seen from its camera
+struct Frame
+{
+    camera: Camera,
+    illumination_mode: RenderIlluminationMode,
+    width: u32,
+    height: u32,
+    pixels: Vec<SampleCollector>,
+}
+
+/// Keeps the accumulated pixels of the last render, so that
+/// after the camera moves a little the next one can start from
+/// them, reprojected to the new view, rather than from black.
+/// Like the interaction tracker, it's owned by the application
+/// and shared with each renderer that's started. The pixels are
+/// only re-used when the application allows it, as only it
+/// knows that nothing but the camera has changed.
+#[derive(Clone, Default)]
+pub struct FrameHistory
+{
+    inner: Arc<Mutex<FrameHistoryInner>>,
+}
+
+#[derive(Default)]
+struct FrameHistoryInner
+{
+    frame: Option<Frame>,
+    reuse: bool,
+}
+
+impl FrameHistory
+{
+    pub fn new() -> Self
+    {
+        FrameHistory::default()
+    }
+
+    /// Lets the next renderer start from the last one's pixels
+    pub fn allow_reuse(&self)
+    {
+        self.inner.lock().unwrap().reuse = true;
+    }
+
+    /// Each renderer takes the last frame, even if it can't
+    /// use it, so it's never re-used after other changes
+    fn take(&self) -> Option<Frame>
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let frame = inner.frame.take();
+
+        if std::mem::replace(&mut inner.reuse, false) { frame } else { None }
+    }
+
+    fn store(&self, frame: Frame)
+    {
+        self.inner.lock().unwrap().frame = Some(frame);
+    }
 }
 
 pub struct Renderer
 {
     thread: Option<JoinHandle<()>>,
     receiver: Option<crossbeam::channel::Receiver<RenderUpdate>>,
+    cancelled: Arc<AtomicBool>,
 }
 
 impl Renderer
 {
-    pub fn new(options: RenderOptions, desc: SceneDescription) -> Self
+    /// Meshes are re-used from, and added to, the mesh cache,
+    /// and likewise for ambient occlusion and the AO cache.
+    /// The pixels are kept in the history when it's stopped.
+    pub fn new(options: RenderOptions, desc: SceneDescription, cache: MeshCache, ao_cache: AoCache, interaction: InteractionTracker, history: FrameHistory) -> Self
     {
         let (sender, receiver) = crossbeam::channel::bounded(2 * num_cpus::get());
 
-        let thread = Some(std::thread::spawn(move || render_thread(options, desc, sender)));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = cancelled.clone();
+
+        let shared = RenderShared { cache, ao_cache, interaction, history };
+        let thread = Some(std::thread::spawn(move || render_thread(options, desc, shared, sender, thread_cancelled)));
         let receiver = Some(receiver);
 
-        Renderer { thread, receiver }
+        Renderer { thread, receiver, cancelled }
     }
 
     pub fn get_update(&self) -> Option<RenderUpdate>
     {
-        self.receiver.as_ref().unwrap().try_recv().ok()
+        self.receiver.as_ref()?.try_recv().ok()
+    }
+
+    /// Blocks until the next update - returns None once the
+    /// render has finished and all updates have been taken
+    pub fn wait_for_update(&self) -> Option<RenderUpdate>
+    {
+        self.receiver.as_ref()?.recv().ok()
+    }
+
+    /// Stops rendering, and waits until the pixels are in the
+    /// history - so stopping before starting the next renderer
+    /// lets it start from them. Dropping also stops it.
+    pub fn stop(&mut self)
+    {
+        // Stop any scene build in progress - rendering
+        // stops when it finds the receiver has gone
+
+        self.cancelled.store(true, Ordering::Relaxed);
+        drop(self.receiver.take());
+
+        if let Some(thread) = self.thread.take()
+        {
+            thread.join().unwrap();
+        }
     }
 }
 
@@ -97,11 +446,19 @@ impl Drop for Renderer
 {
     fn drop(&mut self)
     {
-        drop(self.receiver.take());
-        self.thread.take().unwrap().join().unwrap();
+        self.stop();
     }
 }
 
+/// What each render thread shares with the application
+struct RenderShared
+{
+    cache: MeshCache,
+    ao_cache: AoCache,
+    interaction: InteractionTracker,
+    history: FrameHistory,
+}
+
 struct SampleUpdate
 {
     pub rect: PixelRect,
@@ -119,6 +476,8 @@ struct SampleResult
 struct SampleCollector
 {
     sum: color::LinearRGB,
+    /// Sum of the squared luminance of each sample
+    sum_sq: Scalar,
     samples: u64,
 }
 
@@ -129,26 +488,72 @@ impl SampleCollector
         SampleCollector
         {
             sum: color::LinearRGB::black(),
+            sum_sq: 0.0,
             samples: 0,
         }
     }
 
     pub fn add_sample(&mut self, color: color::LinearRGB, probability: Scalar)
     {
-        self.sum = self.sum + color.divided_by_scalar(probability);
+        let color = color.divided_by_scalar(probability);
+        let lum = luminance(&color);
+
+        self.sum = self.sum + color;
+        self.sum_sq += lum * lum;
         self.samples += 1;
     }
 
     pub fn add_collection(&mut self, collector: &SampleCollector)
     {
         self.sum = self.sum + collector.sum;
+        self.sum_sq += collector.sum_sq;
         self.samples += collector.samples;
     }
 
+    /// The standard error of the mean luminance, relative to
+    /// the mean. The mean has a floor, so that the noise in
+    /// very dark pixels isn't exaggerated.
+    pub fn relative_error(&self) -> Option<Scalar>
+    {
+        if self.samples < 2
+        {
+            return None;
+        }
+
+        let n = self.samples as Scalar;
+        let mean = luminance(&self.sum) / n;
+        let variance = ((self.sum_sq / n) - (mean * mean)).max(0.0) / (n - 1.0);
+
+        Some(variance.sqrt() / mean.max(0.01))
+    }
+
     pub fn result(&self) -> color::LinearRGB
     {
         self.sum.divided_by_scalar(self.samples as Scalar)
     }
+
+    /// The same mean, from at most the given number of samples
+    pub fn limited(&self, max_samples: u64) -> SampleCollector
+    {
+        if self.samples <= max_samples
+        {
+            return self.clone();
+        }
+
+        let scale = (max_samples as Scalar) / (self.samples as Scalar);
+
+        SampleCollector
+        {
+            sum: self.sum.multiplied_by_scalar(scale),
+            sum_sq: self.sum_sq * scale,
+            samples: max_samples,
+        }
+    }
+}
+
+fn luminance(color: &color::LinearRGB) -> Scalar
+{
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
 }
 
 struct RenderState
@@ -158,28 +563,249 @@ struct RenderState
     stats: SceneSampleStats,
     total_duration: Duration,
     pixels: Vec<SampleCollector>,
+    probes: Vec<(Point3, Sh9)>,
+    convergence: Option<Convergence>,
+    start: Instant,
+    last_status: Option<Instant>,
+    passes: Vec<PassTiming>,
+    history: FrameHistory,
+    /// When passes stop, part-way through if needed
+    deadline: Option<Instant>,
 }
 
 impl RenderState
 {
-    fn new(options: RenderOptions, desc: SceneDescription) -> Self
+    fn new(options: RenderOptions, desc: SceneDescription, shared: &RenderShared, sender: &Sender<RenderUpdate>, cancelled: &Arc<AtomicBool>) -> Option<Self>
     {
         let num_pixels = (options.width as usize) * (options.height as usize);
-        let scene = desc.build_scene(&options);
 
-        RenderState
+        let build_start = Instant::now();
+        let scene = desc.build_scene_with_monitor(&options, &mut RenderBuildMonitor { sender, cancelled, last_percent: None }, &shared.cache)?;
+        let build_pass = PassTiming { name: format!("Scene Build ({} Octrees)", options.octree_quality.name()), duration: build_start.elapsed() };
+
+        // Only keep the meshes this (complete) scene uses
+
+        shared.cache.retain_used();
+
+        let ao = (options.illumination_mode == RenderIlluminationMode::Local) && options.ambient_occlusion;
+        let scene = scene.with_ao_cache(if ao { Some(shared.ao_cache.clone()) } else { None });
+        let scene = scene.with_radiance_cache((options.illumination_mode == RenderIlluminationMode::Global) && options.radiance_cache);
+        let scene = scene.with_path_guide((options.illumination_mode == RenderIlluminationMode::Global) && options.path_guiding);
+        let probes = desc.probe_locations().into_iter().map(|l| (l, Sh9::new())).collect();
+
+        Some(RenderState
         {
             options,
             scene,
             stats: SceneSampleStats::new(),
             total_duration: Duration::default(),
             pixels: vec![SampleCollector::new(); num_pixels],
+            probes,
+            convergence: None,
+            start: Instant::now(),
+            last_status: None,
+            passes: vec![build_pass],
+            history: shared.history.clone(),
+            deadline: None,
+        })
+    }
+
+    fn progress(&self, actions: String) -> RenderProgress
+    {
+        let elapsed = self.start.elapsed().as_secs_f64();
+
+        RenderProgress
+        {
+            actions,
+            total_duration: self.total_duration,
+            avg_duration_per_sample: time_per_sample(&self.total_duration, &self.stats.num_samples),
+            stats: self.stats,
+            build: None,
+            convergence: self.convergence,
+            rays_per_second: if elapsed > 0.0 { (self.stats.num_rays as f64) / elapsed } else { 0.0 },
+            passes: self.passes.clone(),
+        }
+    }
+
+    fn add_pass(&mut self, name: String, start: Instant)
+    {
+        self.passes.push(PassTiming { name, duration: start.elapsed() });
+    }
+
+    /// Writes the status file (if enabled), at most once per
+    /// second unless the render is complete
+    fn write_status(&mut self, actions: &str, complete: bool)
+    {
+        const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+        let path = match &self.options.status_file
+        {
+            Some(path) => std::path::PathBuf::from(path),
+            None => return,
+        };
+
+        if !complete && self.last_status.map(|t| t.elapsed() < STATUS_INTERVAL).unwrap_or(false)
+        {
+            return;
+        }
+
+        self.last_status = Some(Instant::now());
+
+        let samples_per_pixel = (self.stats.num_samples as f64) / (self.pixels.len().max(1) as f64);
+
+        let planned_samples = match self.options.illumination_mode
+        {
+            RenderIlluminationMode::Local => self.options.local_samples.max(1),
+            RenderIlluminationMode::Global => self.options.max_samples.min(*SAMPLE_SCHEDULE.last().unwrap()),
+        };
+
+        let fraction = if complete { 1.0 } else { (samples_per_pixel / (planned_samples as f64)).min(1.0) };
+        let elapsed = self.start.elapsed();
+
+        let eta = if complete
+        {
+            Some(Duration::ZERO)
+        }
+        else if fraction > 0.0
+        {
+            Some(elapsed.mul_f64((1.0 - fraction) / fraction))
+        }
+        else
+        {
+            None
+        };
+
+        let status = RenderStatus
+        {
+            actions: actions.to_owned(),
+            complete,
+            percent: 100.0 * fraction,
+            samples_per_pixel,
+            num_samples: self.stats.num_samples,
+            num_rays: self.stats.num_rays,
+            elapsed,
+            eta,
+        };
+
+        if let Err(err) = write_status_file(&path, &status)
+        {
+            println!("Error: {}", err);
+        }
+    }
+
+    /// The average relative error of all pixels
+    fn noise_estimate(&self) -> Option<Scalar>
+    {
+        let (sum, count) = self.pixels.iter()
+            .filter_map(|p| p.relative_error())
+            .fold((0.0, 0), |(sum, count), e| (sum + e, count + 1));
+
+        if count == 0
+        {
+            None
+        }
+        else
+        {
+            Some(sum / (count as Scalar))
+        }
+    }
+}
+
+impl Drop for RenderState
+{
+    /// However the render ends, its pixels are kept
+    /// for the next one to start from
+    fn drop(&mut self)
+    {
+        self.history.store(Frame
+        {
+            camera: self.scene.camera().clone(),
+            illumination_mode: self.options.illumination_mode.clone(),
+            width: self.options.width,
+            height: self.options.height,
+            pixels: std::mem::take(&mut self.pixels),
+        });
+    }
+}
+
+/// Sends scene build progress to the UI, and
+/// cancels the build once the renderer is dropped
+struct RenderBuildMonitor<'a>
+{
+    sender: &'a Sender<RenderUpdate>,
+    cancelled: &'a Arc<AtomicBool>,
+    last_percent: Option<u32>,
+}
+
+impl<'a> BuildMonitor for RenderBuildMonitor<'a>
+{
+    fn progress(&mut self, progress: BuildProgress)
+    {
+        // Only send an update when the displayed percentage
+        // changes, and drop it if the UI is behind
+
+        let percent = (progress.fraction * 100.0) as u32;
+
+        if self.last_percent == Some(percent)
+        {
+            return;
         }
+
+        self.last_percent = Some(percent);
+
+        let update = RenderUpdate
+        {
+            progress: RenderProgress
+                {
+                    actions: format!("Building scene: {} {}%", progress.stage, percent),
+                    total_duration: Duration::default(),
+                    avg_duration_per_sample: Duration::default(),
+                    stats: SceneSampleStats::new(),
+                    build: Some(progress),
+                    convergence: None,
+                    rays_per_second: 0.0,
+                    passes: Vec::new(),
+                },
+            complete: false,
+            pixels: Vec::new(),
+            probes: Vec::new(),
+        };
+
+        let _ = self.sender.try_send(update);
+    }
+
+    fn is_cancelled(&self) -> bool
+    {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn cancelled_flag(&self) -> Option<Arc<AtomicBool>>
+    {
+        Some(self.cancelled.clone())
     }
 }
 
-fn render_thread(options: RenderOptions, desc: SceneDescription, sender: Sender<RenderUpdate>)
+/// Total samples per pixel after each global illumination pass
+const SAMPLE_SCHEDULE: [usize; 6] = [8, 32, 128, 512, 2048, 8096];
+
+/// Pixels re-used from the last render count as at most this
+/// many samples, so they're soon outweighed by new ones
+const MAX_REPROJECTED_SAMPLES: u64 = 16;
+
+/// The blocky preview is skipped if at least
+/// this fraction of pixels are reprojected
+const MIN_REPROJECTED_FRACTION: Scalar = 0.5;
+
+/// Surfaces seen through a pixel in both frames must be at
+/// the same depth, within this fraction, and face the same
+/// way, with at least this cosine between their normals
+const REPROJECT_DEPTH_TOLERANCE: Scalar = 0.02;
+const REPROJECT_MIN_NORMAL_COSINE: Scalar = 0.9;
+
+fn render_thread(options: RenderOptions, desc: SceneDescription, shared: RenderShared, sender: Sender<RenderUpdate>, cancelled: Arc<AtomicBool>)
 {
+    let previous = shared.history.take();
+
     // Notify that we're building the scene
 
     {
@@ -191,92 +817,360 @@ fn render_thread(options: RenderOptions, desc: SceneDescription, sender: Sender<
                     total_duration: Duration::default(),
                     avg_duration_per_sample: Duration::default(),
                     stats: SceneSampleStats::new(),
+                    build: None,
+                    convergence: None,
+                    rays_per_second: 0.0,
+                    passes: Vec::new(),
                 },
             complete: false,
             pixels: Vec::new(),
+            probes: Vec::new(),
         };
 
         let _ = sender.send(final_update);
     }
 
-    let mut state = RenderState::new(options, desc);
+    let mut state = match RenderState::new(options, desc, &shared, &sender, &cancelled)
+    {
+        Some(state) => state,
+        None => return,
+    };
+
+    // After the camera moves, start from the last render's
+    // pixels, where they can be reprojected
+
+    let reprojected_fraction = match previous
+    {
+        Some(frame) => match reproject(&mut state, frame, &sender)
+        {
+            Some(fraction) => fraction,
+            None => return,
+        },
+        None => 0.0,
+    };
+
+    let mut first_local_pass = true;
+
+    if reprojected_fraction >= MIN_REPROJECTED_FRACTION
+    {
+        // They're better than the blocky preview, so
+        // only the pixels they don't cover are sampled
+
+        if !render_pass(&mut state, 1, true, &PassPixels::Unsampled, 1, 1, &sender)
+        {
+            return;
+        }
+    }
+    else
+    {
+        // First, do a quick pass with local lighting
+        // down to half the resolution
+
+        const MAX_STEP_SIZE: u32 = 1024;
+
+        let mut step = MAX_STEP_SIZE;
+
+        while (step > 1) && (step > state.options.width) && (step > state.options.height)
+        {
+            step /= 2;
+        }
+
+        while step >= 2
+        {
+            if step <= state.options.max_blockiness
+            {
+                if !render_pass(&mut state, step, first_local_pass, &PassPixels::All, 1, 1, &sender)
+                {
+                    return;
+                }
+                first_local_pass = false;
+            }
+
+            step /= 2;
+        }
+    }
+
+    // Hold back the full resolution passes while the user is
+    // still interacting. The delay acts as hysteresis - a run of
+    // interactions restarts the renderer each time, but the
+    // expensive passes only start once they've settled.
+
+    if !shared.interaction.wait_until_idle(Duration::from_millis(state.options.interaction_delay_ms), &cancelled)
+    {
+        return;
+    }
+
+    // Ensure all pixels have at least one sample taken
+
+    if !render_pass(&mut state, 1, first_local_pass, &PassPixels::All, 1, 1, &sender)
+    {
+        return;
+    }
+
+    let mut stop_reason = None;
+
+    if (state.options.illumination_mode == RenderIlluminationMode::Local) && (state.options.local_samples > 1)
+    {
+        // Antialias with the rest of the jittered samples
+
+        let local_samples = state.options.local_samples;
+
+        if !render_pass(&mut state, 1, true, &PassPixels::All, local_samples - 1, local_samples, &sender)
+        {
+            return;
+        }
+    }
+
+    if state.options.illumination_mode == RenderIlluminationMode::Global
+    {
+        // Sample all pixels with additional samples
+
+        let mut completed_samples = 1;
+        let mut region_samples = 1;
+        let sampling_start = Instant::now();
+
+        // Passes stop part-way through at the time limit,
+        // rather than overshooting it by a whole pass
+
+        if state.options.time_limit > 0.0
+        {
+            state.deadline = Some(sampling_start + Duration::from_secs_f64(state.options.time_limit));
+        }
+
+        for (index, pass_samples) in SAMPLE_SCHEDULE.iter().enumerate()
+        {
+            if let Some(reason) = auto_stop_reason(&state, sampling_start.elapsed())
+            {
+                stop_reason = Some(reason);
+                break;
+            }
+
+            let requested_samples = (*pass_samples).min(state.options.max_samples);
+
+            if requested_samples <= completed_samples
+            {
+                break;
+            }
+
+            let new_samples = requested_samples - completed_samples;
+
+            // The priority region is kept a step
+            // ahead of the rest of the schedule
+
+            let pass_pixels = match state.options.priority_region.clone()
+            {
+                Some(region) =>
+                {
+                    let ahead_samples = SAMPLE_SCHEDULE.get(index + 1).copied().unwrap_or(*pass_samples).min(state.options.max_samples);
+
+                    if ahead_samples > region_samples
+                    {
+                        if !render_pass(&mut state, 1, true, &PassPixels::Inside(region.clone()), ahead_samples - region_samples, ahead_samples, &sender)
+                        {
+                            return;
+                        }
+
+                        region_samples = ahead_samples;
+                    }
+
+                    PassPixels::Outside(region)
+                },
+                None => PassPixels::All,
+            };
+
+            if !render_pass(&mut state, 1, true, &pass_pixels, new_samples, requested_samples, &sender)
+            {
+                return;
+            }
+
+            if state.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                stop_reason = Some("reached time limit");
+                break;
+            }
+
+            if !sample_probes(&mut state, new_samples * PROBE_DIRECTIONS_PER_SAMPLE, &sender)
+            {
+                return;
+            }
+
+            completed_samples = requested_samples;
+
+            state.convergence = state.noise_estimate().map(|noise|
+            {
+                // Noise reduces with the square root of the number of samples
+
+                let target = state.options.target_noise;
+
+                let time_to_target = if (target > 0.0) && (noise > target)
+                {
+                    let required_samples = (completed_samples as f64) * (noise / target).powi(2);
+                    let per_sample = sampling_start.elapsed().as_secs_f64() / (completed_samples as f64);
+
+                    Some(Duration::from_secs_f64(per_sample * (required_samples - (completed_samples as f64))))
+                }
+                else
+                {
+                    None
+                };
+
+                Convergence { noise, time_to_target }
+            });
+        }
+    }
+
+    if state.options.denoise
+    {
+        let denoise_start = Instant::now();
+        let pixels = denoise(&state);
+        state.add_pass("Denoise".to_owned(), denoise_start);
+
+        let update = RenderUpdate
+        {
+            progress: state.progress("Denoising".to_owned()),
+            complete: false,
+            pixels,
+            probes: Vec::new(),
+        };
+
+        if sender.send(update).is_err()
+        {
+            return;
+        }
+    }
+
+    // Mark that we're completed
+
+    let actions = match stop_reason
+    {
+        Some(reason) => format!("Complete - {}", reason),
+        None => "Complete".to_owned(),
+    };
+
+    state.write_status(&actions, true);
+
+    let final_update = RenderUpdate
+    {
+        progress: state.progress(actions),
+        complete: true,
+        pixels: Vec::new(),
+        probes: state.probes.clone(),
+    };
+
+    let _ = sender.send(final_update);
+}
+
+/// Checked between passes - passes also stop
+/// part-way through once the time limit is reached
+fn auto_stop_reason(state: &RenderState, elapsed: Duration) -> Option<&'static str>
+{
+    let options = &state.options;
+
+    if let Some(convergence) = &state.convergence
+    {
+        if (options.target_noise > 0.0) && (convergence.noise <= options.target_noise)
+        {
+            return Some("reached target noise");
+        }
+    }
+
+    if (options.time_limit > 0.0) && (elapsed.as_secs_f64() >= options.time_limit)
+    {
+        return Some("reached time limit");
+    }
+
+    None
+}
 
-    // First, do a quick pass with local lighting
-    // down to half the resolution
+/// Sorts the updates into the order they should be rendered.
+/// Threads take updates in turn, so this is (approximately)
+/// the order they complete in.
+fn order_updates(updates: &mut [PixelRect], order: TileOrder, step: u32, width: u32, height: u32)
+{
+    match order
+    {
+        TileOrder::Random =>
+        {
+            updates.shuffle(&mut thread_rng());
+        },
+        TileOrder::Scanline =>
+        {
+            updates.sort_by_key(|u| (u.y, u.x));
+        },
+        TileOrder::Hilbert =>
+        {
+            let cells = width.max(height).div_ceil(step).next_power_of_two();
 
-    let mut first_local_pass = true;
+            updates.sort_by_key(|u| hilbert_index(cells, u.x / step, u.y / step));
+        },
+        TileOrder::Spiral =>
+        {
+            // By square rings around the center, and
+            // then around each ring by angle
 
-    {
-        const MAX_STEP_SIZE: u32 = 1024;
+            let cx = (width as Scalar) / 2.0;
+            let cy = (height as Scalar) / 2.0;
 
-        let mut step = MAX_STEP_SIZE;
+            let key = |u: &PixelRect|
+            {
+                let dx = (u.x as Scalar) + 0.5 * (u.width as Scalar) - cx;
+                let dy = (u.y as Scalar) + 0.5 * (u.height as Scalar) - cy;
+                let ring = (dx.abs().max(dy.abs()) / (step as Scalar)) as u32;
 
-        while (step > 1) && (step > state.options.width) && (step > state.options.height)
-        {
-            step /= 2;
-        }
+                (ring, dy.atan2(dx))
+            };
 
-        while step >= 2
-        {
-            if step <= state.options.max_blockiness
+            updates.sort_by(|a, b|
             {
-                if !render_pass(&mut state, step, first_local_pass, 1, 1, &sender)
-                {
-                    return;
-                }
-                first_local_pass = false;
-            }
+                let (ring_a, angle_a) = key(a);
+                let (ring_b, angle_b) = key(b);
 
-            step /= 2;
-        }
+                ring_a.cmp(&ring_b).then(angle_a.total_cmp(&angle_b))
+            });
+        },
     }
+}
 
-    // Ensure all pixels have at least one sample taken
+/// Distance along a Hilbert curve filling a
+/// square grid, with a power-of-two size
+fn hilbert_index(size: u32, mut x: u32, mut y: u32) -> u64
+{
+    let mut result = 0;
+    let mut s = size / 2;
 
-    if !render_pass(&mut state, 1, first_local_pass, 1, 1, &sender)
+    while s > 0
     {
-        return;
-    }
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
 
-    if state.options.illumination_mode == RenderIlluminationMode::Global
-    {
-        // Sample all pixels with additional samples
+        result += (s as u64) * (s as u64) * match (rx, ry) { (false, false) => 0, (false, true) => 1, (true, true) => 2, (true, false) => 3 };
 
-        let mut completed_samples = 1;
+        // Rotate the quadrant
 
-        for requested_samples in [8, 32, 128, 512, 2048, 8096].iter()
+        if !ry
         {
-            let new_samples = requested_samples - completed_samples;
-
-            if !render_pass(&mut state, 1, true, new_samples, *requested_samples, &sender)
+            if rx
             {
-                return;
+                x = size - 1 - x;
+                y = size - 1 - y;
             }
 
-            completed_samples = *requested_samples;
+            std::mem::swap(&mut x, &mut y);
         }
-    }
 
-    // Mark that we're completed
-
-    let final_update = RenderUpdate
-    {
-        progress: RenderProgress
-            {
-                actions: "Complete".to_owned(),
-                total_duration: state.total_duration,
-                avg_duration_per_sample: time_per_sample(&state.total_duration, &state.stats.num_samples),
-                stats: state.stats.clone(),
-            },
-        complete: true,
-        pixels: Vec::new(),
-    };
+        s /= 2;
+    }
 
-    let _ = sender.send(final_update);
+    result
 }
 
-fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples_per_pixel: usize, total_samples_per_pixel: usize, sender: &Sender<RenderUpdate>) -> bool
+/// Renders a pass over the pixels. Once the state's deadline
+/// has passed, the threads stop taking new tiles, leaving the
+/// rest of the pass's pixels with the samples they have.
+fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, pass_pixels: &PassPixels, new_samples_per_pixel: usize, total_samples_per_pixel: usize, sender: &Sender<RenderUpdate>) -> bool
 {
+    let pass_start = Instant::now();
+
     // Work out which pixels we need to update, and the size
     // that they are drawn at
 
@@ -292,7 +1186,9 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
             let x_mod = x % (step * 2);
             let y_mod = y % (step * 2);
 
-            if all_pixels || (x_mod != 0) || (y_mod != 0)
+            let sampled = state.pixels[(y * width + x) as usize].samples > 0;
+
+            if (all_pixels || (x_mod != 0) || (y_mod != 0)) && pass_pixels.includes(x, y, sampled)
             {
                 let mut w_step = step;
                 let mut h_step = step;
@@ -319,9 +1215,7 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
         }
     }
 
-    // Shuffle the updates so they occur in a more random order.
-
-    updates.shuffle(&mut thread_rng());
+    order_updates(&mut updates, state.options.tile_order, step, width, height);
 
     // Break the updates into chunks of updates
 
@@ -341,9 +1235,16 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
 
     let num_chunks = chunks.len();
 
-    // Break this into threads, and start threads to run them
+    // Deal the chunks out to threads in turn, so they're
+    // rendered in (roughly) the order chosen above
+
+    let mut thread_chunks = vec![Vec::new(); num_threads.min(num_chunks)];
+    let num_thread_chunks = thread_chunks.len();
 
-    let chunks_per_thread = (chunks.len() + num_threads - 1) / num_threads;
+    for (index, chunk) in chunks.into_iter().enumerate()
+    {
+        thread_chunks[index % num_thread_chunks].push(chunk);
+    }
 
     let (sub_sender, sub_receiver) = crossbeam::channel::unbounded();
 
@@ -352,29 +1253,45 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
         let thread_sender = sub_sender.clone();
         let thread_options = state.options.clone();
         let thread_scene = state.scene.clone();
+        let deadline = state.deadline;
 
-        std::thread::spawn(move || render_pixel_thread(thread_options, thread_scene, new_samples_per_pixel, chunks, thread_sender))
+        std::thread::spawn(move || render_pixel_thread(thread_options, thread_scene, new_samples_per_pixel, chunks, deadline, thread_sender))
     };
 
-    let join_handles: Vec<JoinHandle<()>> = chunks
+    let join_handles: Vec<JoinHandle<()>> = thread_chunks
         .into_iter()
-        .chunks(chunks_per_thread)
-        .into_iter()
-        .map(|i| i.collect::<Vec<_>>())
         .map(|chunks| spawn_thread(chunks))
         .collect::<Vec<_>>();
 
+    // Only the threads hold senders now, so the channel
+    // disconnects once they've all finished - which is
+    // early, if they've stopped at the deadline
+
+    drop(sub_sender);
+
     // Receive updates from the threads and aggregate these
     // into the completed results
 
     let mut collected_chunks = 0;
+    let mut stopped = false;
 
-    while collected_chunks < num_chunks
+    while (collected_chunks < num_chunks) && !stopped
     {
         let mut pixels = Vec::new();
 
-        while let Ok(chunk) = sub_receiver.try_recv()
+        loop
         {
+            let chunk = match sub_receiver.try_recv()
+            {
+                Ok(chunk) => chunk,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) =>
+                {
+                    stopped = collected_chunks < num_chunks;
+                    break;
+                },
+            };
+
             state.stats = state.stats + chunk.stats;
             state.total_duration = state.total_duration + chunk.duration;
 
@@ -402,19 +1319,16 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
         }
         else
         {
-            format!("Rendering {} sample{}/pixel, {:.1}%",
+            format!("Rendering {}{} sample{}/pixel, {:.1}%",
+                match pass_pixels { PassPixels::Inside(_) => "priority region, ", PassPixels::Unsampled => "unsampled pixels, ", _ => "" },
                 total_samples_per_pixel,
                 if total_samples_per_pixel == 1 { "" } else { "s" },
                 100.0 * (collected_chunks as f64) / (num_chunks as f64))
         };
 
-        let progress = RenderProgress
-        {
-            actions,
-            total_duration: state.total_duration,
-            avg_duration_per_sample: time_per_sample(&state.total_duration, &state.stats.num_samples),
-            stats: state.stats.clone(),
-        };
+        state.write_status(&actions, false);
+
+        let progress = state.progress(actions);
 
         let complete = false;
 
@@ -423,6 +1337,7 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
             progress,
             complete,
             pixels,
+            probes: Vec::new(),
         };
 
         if !sender.send(render_update).is_ok()
@@ -443,9 +1358,284 @@ fn render_pass(state: &mut RenderState, step: u32, all_pixels: bool, new_samples
         .map(|jh| jh.join().unwrap())
         .last();
 
+    let name = if step > 1
+    {
+        format!("Preview {}x{}", step, step)
+    }
+    else
+    {
+        format!("{}{} sample{}/pixel{}",
+            match pass_pixels { PassPixels::Inside(_) => "Priority region, ", PassPixels::Unsampled => "Unsampled pixels, ", _ => "" },
+            total_samples_per_pixel,
+            if total_samples_per_pixel == 1 { "" } else { "s" },
+            if stopped { ", stopped at time limit" } else { "" })
+    };
+
+    state.add_pass(name, pass_start);
+
     true
 }
 
+/// An edge-preserving (bilateral) filter - neighbouring pixels
+/// are averaged, weighted by both their distance and how
+/// similar their colors are, so edges stay sharp while
+/// noise in flat areas is smoothed out.
+fn denoise(state: &RenderState) -> Vec<PixelUpdate>
+{
+    const RADIUS: i64 = 2;
+    const SPATIAL_SIGMA: Scalar = 1.5;
+    const COLOR_SIGMA: Scalar = 0.2;
+
+    let width = state.options.width as i64;
+    let height = state.options.height as i64;
+
+    let colors = state.pixels.iter().map(|p| p.result()).collect::<Vec<_>>();
+    let mut updates = Vec::with_capacity(colors.len());
+
+    for y in 0..height
+    {
+        for x in 0..width
+        {
+            let center = colors[(y * width + x) as usize];
+
+            // Compare colors relative to the center's brightness,
+            // so bright and dark areas are treated the same
+
+            let scale = 1.0 + center.max_color_component();
+
+            let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+            let mut total_weight = 0.0;
+
+            for dy in -RADIUS..=RADIUS
+            {
+                for dx in -RADIUS..=RADIUS
+                {
+                    let (nx, ny) = (x + dx, y + dy);
+
+                    if (nx < 0) || (ny < 0) || (nx >= width) || (ny >= height)
+                    {
+                        continue;
+                    }
+
+                    let neighbour = colors[(ny * width + nx) as usize];
+
+                    let spatial = ((dx * dx + dy * dy) as Scalar) / (2.0 * SPATIAL_SIGMA * SPATIAL_SIGMA);
+
+                    let (dr, dg, db) = ((neighbour.r - center.r) / scale, (neighbour.g - center.g) / scale, (neighbour.b - center.b) / scale);
+                    let range = (dr * dr + dg * dg + db * db) / (2.0 * COLOR_SIGMA * COLOR_SIGMA);
+
+                    let weight = (-(spatial + range)).exp();
+
+                    r += weight * neighbour.r;
+                    g += weight * neighbour.g;
+                    b += weight * neighbour.b;
+                    total_weight += weight;
+                }
+            }
+
+            updates.push(PixelUpdate
+            {
+                rect: PixelRect { x: x as u32, y: y as u32, width: 1, height: 1 },
+                color: color::LinearRGB::new(r / total_weight, g / total_weight, b / total_weight, center.a),
+            });
+        }
+    }
+
+    updates
+}
+
+const PROBE_DIRECTIONS_PER_SAMPLE: usize = 16;
+
+fn sample_probes(state: &mut RenderState, directions: usize, sender: &Sender<RenderUpdate>) -> bool
+{
+    if state.probes.is_empty()
+    {
+        return true;
+    }
+
+    let pass_start = Instant::now();
+
+    // Split the directions for each probe between threads
+
+    let num_threads = num_cpus::get().max(1);
+    let directions_per_thread = directions.div_ceil(num_threads);
+
+    let join_handles = (0..num_threads)
+        .map(|_|
+        {
+            let scene = state.scene.clone();
+            let locations = state.probes.iter().map(|(l, _)| *l).collect::<Vec<_>>();
+
+            std::thread::spawn(move ||
+            {
+                let mut sampler = Sampler::new();
+                let mut stats = SceneSampleStats::new();
+
+                locations.iter()
+                    .map(|location|
+                    {
+                        let mut sh = Sh9::new();
+
+                        for _ in 0..directions_per_thread
+                        {
+                            let dir = sampler.uniform_dir_on_unit_sphere();
+                            let (color, probability) = scene.path_trace_global_lighting_ray(Ray::new(*location, dir), &mut sampler, &mut stats);
+
+                            if probability > 0.0
+                            {
+                                sh.add_uniform_sample(dir, color.divided_by_scalar(probability));
+                            }
+                        }
+
+                        sh
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in join_handles
+    {
+        for (probe, sh) in state.probes.iter_mut().zip(handle.join().unwrap())
+        {
+            probe.1.add(&sh);
+        }
+    }
+
+    state.add_pass(format!("Probes, {} directions", directions), pass_start);
+
+    let update = RenderUpdate
+    {
+        progress: state.progress("Sampling probes".to_owned()),
+        complete: false,
+        pixels: Vec::new(),
+        probes: state.probes.clone(),
+    };
+
+    sender.send(update).is_ok()
+}
+
+/// Primes the pixels from the last render's, where the same
+/// surface is seen through both - the surface seen through the
+/// center of each pixel is projected into the last camera, and
+/// the surface seen through that pixel must be at the same depth,
+/// and face the same way. Returns the fraction of pixels primed,
+/// or None if the renderer has been closed.
+fn reproject(state: &mut RenderState, frame: Frame, sender: &Sender<RenderUpdate>) -> Option<Scalar>
+{
+    let width = state.options.width;
+    let height = state.options.height;
+
+    // Only global illumination is slow enough to need it
+
+    if (frame.width != width)
+        || (frame.height != height)
+        || (frame.illumination_mode != RenderIlluminationMode::Global)
+        || (state.options.illumination_mode != RenderIlluminationMode::Global)
+    {
+        return Some(0.0);
+    }
+
+    let pass_start = Instant::now();
+
+    // Split the rows between threads
+
+    let num_threads = num_cpus::get().max(1);
+    let rows_per_thread = height.div_ceil(num_threads as u32).max(1);
+
+    let join_handles = (0..height)
+        .step_by(rows_per_thread as usize)
+        .map(|first_row|
+        {
+            let scene = state.scene.clone();
+            let camera = frame.camera.clone();
+            let rows = first_row..(first_row + rows_per_thread).min(height);
+
+            std::thread::spawn(move ||
+            {
+                rows.flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .filter_map(|(x, y)| reprojected_pixel(&scene, &camera, width, height, x, y).map(|previous| (x, y, previous)))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut pixels = Vec::new();
+
+    for handle in join_handles
+    {
+        for (x, y, previous) in handle.join().unwrap()
+        {
+            let previous = &frame.pixels[previous];
+
+            if previous.samples > 0
+            {
+                let index = (y * width + x) as usize;
+                state.pixels[index] = previous.limited(MAX_REPROJECTED_SAMPLES);
+
+                pixels.push(PixelUpdate
+                {
+                    rect: PixelRect { x, y, width: 1, height: 1 },
+                    color: state.pixels[index].result(),
+                });
+            }
+        }
+    }
+
+    let fraction = (pixels.len() as Scalar) / (state.pixels.len().max(1) as Scalar);
+
+    state.add_pass("Reprojection".to_owned(), pass_start);
+
+    let update = RenderUpdate
+    {
+        progress: state.progress(format!("Reprojected {:.0}% of pixels", 100.0 * fraction)),
+        complete: false,
+        pixels,
+        probes: Vec::new(),
+    };
+
+    if sender.send(update).is_err()
+    {
+        return None;
+    }
+
+    Some(fraction)
+}
+
+/// The index of the last frame's pixel that sees the
+/// same surface as the new frame's pixel, if there is one
+fn reprojected_pixel(scene: &Scene, previous_camera: &Camera, width: u32, height: u32, x: u32, y: u32) -> Option<usize>
+{
+    let (w, h) = (width as Scalar, height as Scalar);
+
+    let ray = scene.camera().get_center_ray(((x as Scalar) + 0.5) / w, ((y as Scalar) + 0.5) / h);
+    let hit = scene.trace_intersection(&ray)?;
+    let location = ray.point_at(hit.surface.distance);
+
+    let (u, v) = previous_camera.project(location)?;
+
+    if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v)
+    {
+        return None;
+    }
+
+    let (px, py) = (((u * w) as u32).min(width - 1), ((v * h) as u32).min(height - 1));
+
+    let previous_ray = previous_camera.get_center_ray(((px as Scalar) + 0.5) / w, ((py as Scalar) + 0.5) / h);
+    let previous_hit = scene.trace_intersection(&previous_ray)?;
+
+    let depth = previous_camera.depth_of(location);
+    let previous_depth = previous_camera.depth_of(previous_ray.point_at(previous_hit.surface.distance));
+
+    if ((previous_depth - depth).abs() > REPROJECT_DEPTH_TOLERANCE * depth)
+        || (hit.surface.normal.dot(previous_hit.surface.normal) < REPROJECT_MIN_NORMAL_COSINE)
+    {
+        return None;
+    }
+
+    Some((py * width + px) as usize)
+}
+
 fn time_per_sample(duration: &Duration, samples: &u64) -> Duration
 {
     if *samples == 0
@@ -458,12 +1648,17 @@ fn time_per_sample(duration: &Duration, samples: &u64) -> Duration
     }
 }
 
-fn render_pixel_thread(options: RenderOptions, scene: Scene, new_samples_per_pixel: usize, updates: Vec<Vec<PixelRect>>, sender: Sender<SampleResult>)
+fn render_pixel_thread(options: RenderOptions, scene: Scene, new_samples_per_pixel: usize, updates: Vec<Vec<PixelRect>>, deadline: Option<Instant>, sender: Sender<SampleResult>)
 {
     let mut sampler = Sampler::new();
 
     for updates in updates.into_iter()
     {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return;
+        }
+
         let mut stats = SceneSampleStats::new();
         let now = Instant::now();
 
@@ -497,10 +1692,20 @@ fn calculate_update(options: &RenderOptions, scene: &Scene, sampler: &mut Sample
     {
         RenderIlluminationMode::Local =>
         {
-            let u = (update.x as Scalar) / (options.width as Scalar);
-            let v = (update.y as Scalar) / (options.height as Scalar);
+            // A single sample is taken through the pixel's corner,
+            // so the preview is stable - otherwise they're jittered
+
+            let jitter = options.local_samples > 1;
+
+            for _ in 0..new_samples_per_pixel
+            {
+                let (du, dv) = if jitter { (sampler.uniform_scalar_unit(), sampler.uniform_scalar_unit()) } else { (0.0, 0.0) };
+
+                let u = ((update.x as Scalar) + du) / (options.width as Scalar);
+                let v = ((update.y as Scalar) + dv) / (options.height as Scalar);
 
-            collector.add_sample(scene.path_trace_local_lighting(u, v, sampler, stats).0, 1.0);
+                collector.add_sample(scene.path_trace_local_lighting(u, v, sampler, stats).0, 1.0);
+            }
         },
         RenderIlluminationMode::Global =>
         {
@@ -521,3 +1726,126 @@ fn calculate_update(options: &RenderOptions, scene: &Scene, sampler: &mut Sample
         collector: collector,
     }
 }
+
+/// Pixels along each side of the tiles that a progressive
+/// render samples at a time, so progress is shown often
+const PROGRESSIVE_TILE_SIZE: u32 = 32;
+
+/// Renders on the calling thread, a tile at a time, for where
+/// threads aren't available, such as WASM in a browser. Each pass
+/// adds a sample to every pixel, visiting the tiles in the options'
+/// order, and the image can be read back between tiles.
+pub struct ProgressiveRender
+{
+    options: RenderOptions,
+    scene: Scene,
+    sampler: Sampler,
+    stats: SceneSampleStats,
+    pixels: Vec<SampleCollector>,
+    tiles: Vec<PixelRect>,
+    next_tile: usize,
+    passes: usize,
+}
+
+impl ProgressiveRender
+{
+    pub fn new(options: RenderOptions, desc: &SceneDescription) -> Self
+    {
+        let scene = desc.build_scene(&options);
+        let (width, height) = (options.width, options.height);
+
+        let mut tiles = Vec::new();
+
+        for y in (0..height).step_by(PROGRESSIVE_TILE_SIZE as usize)
+        {
+            for x in (0..width).step_by(PROGRESSIVE_TILE_SIZE as usize)
+            {
+                tiles.push(PixelRect { x, y, width: PROGRESSIVE_TILE_SIZE.min(width - x), height: PROGRESSIVE_TILE_SIZE.min(height - y) });
+            }
+        }
+
+        order_updates(&mut tiles, options.tile_order, PROGRESSIVE_TILE_SIZE, width, height);
+
+        ProgressiveRender
+        {
+            pixels: vec![SampleCollector::new(); (width as usize) * (height as usize)],
+            options,
+            scene,
+            sampler: Sampler::new(),
+            stats: SceneSampleStats::new(),
+            tiles,
+            next_tile: 0,
+            passes: 0,
+        }
+    }
+
+    /// Samples the next tile, and returns it - or none once every
+    /// pixel has the options' maximum (or local) samples
+    pub fn render_tile(&mut self) -> Option<PixelRect>
+    {
+        let max_samples = match self.options.illumination_mode
+        {
+            RenderIlluminationMode::Local => self.options.local_samples.max(1),
+            RenderIlluminationMode::Global => self.options.max_samples,
+        };
+
+        if self.tiles.is_empty() || (self.passes >= max_samples)
+        {
+            return None;
+        }
+
+        let tile = self.tiles[self.next_tile].clone();
+
+        for y in tile.y..(tile.y + tile.height)
+        {
+            for x in tile.x..(tile.x + tile.width)
+            {
+                let update = calculate_update(&self.options, &self.scene, &mut self.sampler, 1, &mut self.stats, PixelRect { x, y, width: 1, height: 1 });
+
+                self.pixels[(y * self.options.width + x) as usize].add_collection(&update.collector);
+            }
+        }
+
+        self.next_tile += 1;
+
+        if self.next_tile == self.tiles.len()
+        {
+            self.next_tile = 0;
+            self.passes += 1;
+        }
+
+        Some(tile)
+    }
+
+    /// Complete passes, so the samples in every pixel
+    pub fn passes(&self) -> usize
+    {
+        self.passes
+    }
+
+    pub fn stats(&self) -> &SceneSampleStats
+    {
+        &self.stats
+    }
+
+    /// 8-bit RGBA, in rows from the top, converted through the
+    /// options' display transform. Pixels without samples are black.
+    pub fn to_rgba8(&self) -> Vec<u8>
+    {
+        let width = self.options.width;
+
+        self.pixels.iter()
+            .enumerate()
+            .flat_map(|(i, pixel)|
+            {
+                if pixel.samples == 0
+                {
+                    return [0, 0, 0, 255];
+                }
+
+                let (r, g, b, _) = self.options.display_color(pixel.result(), (i as u32) % width, (i as u32) / width).to_u8_rgba_tuple();
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use crate::bsdf::Bsdf;
+use crate::camera::Camera;
+use crate::lighting::LightingRegion;
+use crate::math::{EPSILON, Scalar};
+use crate::radiance::{CellKey, ViewGrid};
+use crate::ray::{Ray, RayRange};
+use crate::sample::Sampler;
+use crate::vec::{Dir3, Point3};
+
+/// Reservoirs are shared by points in cells of about
+/// this many across the camera's view
+const CELLS_ACROSS_VIEW: Scalar = 64.0;
+
+/// Light samples drawn at each point
+const CANDIDATES: u32 = 8;
+
+/// A cell's reservoir counts for at most this many of
+/// the current point's candidates, so that it keeps
+/// adapting, and one lucky sample doesn't dominate
+const MAX_HISTORY: u32 = 20 * CANDIDATES;
+
+const NUM_SHARDS: usize = 64;
+
+/// A point on a light, chosen from a stream of candidates
+/// in proportion to how much it could contribute
+#[derive(Clone, Copy)]
+struct Reservoir
+{
+    point: Point3,
+    normal: Dir3,
+    /// The sample's contribution weight - an
+    /// estimate of one over its pdf, by area
+    weight: Scalar,
+    /// Candidates seen
+    count: u32,
+}
+
+/// Reservoir-based resampling of direct lighting (as in ReSTIR).
+/// Each point draws several candidate samples from the lights,
+/// and keeps one in proportion to the BSDF's response to it,
+/// combined with the reservoir kept for the point's cell in a
+/// world-space grid - so samples are reused between nearby
+/// pixels, and from pass to pass. Reservoirs hold points on the
+/// lights, rather than directions, so they can be re-weighted
+/// from anywhere. Visibility and the lights' brightness aren't
+/// included, so it helps most for many similar lights.
+/// It's built for each render.
+#[derive(Clone)]
+pub struct ReservoirGrid
+{
+    grid: ViewGrid,
+    shards: Arc<Vec<Mutex<HashMap<CellKey, Reservoir>>>>,
+}
+
+impl ReservoirGrid
+{
+    pub fn new(camera: &Camera) -> Self
+    {
+        let shards = Arc::new((0..NUM_SHARDS).map(|_| Mutex::new(HashMap::new())).collect());
+
+        ReservoirGrid { grid: ViewGrid::new(camera, CELLS_ACROSS_VIEW), shards }
+    }
+
+    /// Chooses a direction towards the region's lights, and returns
+    /// it with its probability density, by solid angle. Returns `None`
+    /// if none of the candidates could contribute.
+    pub fn sample(&self, lighting_region: &LightingRegion, location: Point3, normal: Dir3, bsdf: &dyn Bsdf, sampler: &mut Sampler) -> Option<(Dir3, Scalar)>
+    {
+        let num_lights = lighting_region.global_surfaces.len();
+
+        if num_lights == 0
+        {
+            return None;
+        }
+
+        // The target density - how much the point on the
+        // light could contribute, other than its brightness
+
+        let target = |point: Point3, light_normal: Dir3| -> Scalar
+        {
+            let offset = point - location;
+            let distance_squared = offset.magnitude_squared();
+
+            if distance_squared <= EPSILON * EPSILON
+            {
+                return 0.0;
+            }
+
+            let dir = offset / distance_squared.sqrt();
+
+            bsdf.reflectance(dir).max(0.0) * dir.dot(light_normal).abs() / distance_squared
+        };
+
+        let mut chosen: Option<Reservoir> = None;
+        let mut weight_sum = 0.0;
+        let mut count = 0;
+
+        let mut add = |candidate: Reservoir, weight: Scalar, count_added: u32, sampler: &mut Sampler|
+        {
+            count += count_added;
+
+            if weight.is_finite() && (weight > 0.0)
+            {
+                weight_sum += weight;
+
+                if sampler.uniform_scalar_unit() * weight_sum < weight
+                {
+                    chosen = Some(candidate);
+                }
+            }
+        };
+
+        for _ in 0..CANDIDATES
+        {
+            // The same mixture of the lights as light sampling
+
+            let light_index = sampler.uniform_index(num_lights);
+            let light = &lighting_region.global_surfaces[light_index];
+
+            let (dir, _) = light.generate_random_sample_direction_from_and_calc_pdf(location, sampler);
+            let ray = Ray::new(location, dir);
+
+            let pdf = lighting_region.global_surfaces.iter().map(|s| s.calculate_pdf_for_ray(&ray)).sum::<Scalar>() / (num_lights as Scalar);
+
+            let hit = light.closest_intersection_in_range(&ray, &RayRange::new(EPSILON, Scalar::MAX))
+                .map(|i| (ray.point_at(i.distance), i.normal));
+
+            let (point, light_normal, weight) = match hit
+            {
+                // Target and source densities are both converted from solid
+                // angle to area by the same factor, so the weight doesn't need it
+                Some((point, light_normal)) if pdf > 0.0 => (point, light_normal, bsdf.reflectance(dir).max(0.0) / pdf),
+                _ => (location, normal, 0.0),
+            };
+
+            add(Reservoir { point, normal: light_normal, weight: 0.0, count: 1 }, weight, 1, sampler);
+        }
+
+        // Merge in the cell's reservoir, re-weighted for this point
+
+        let (key, _) = self.grid.cell(location, normal);
+        let mut shard = self.shard(&key).lock().unwrap();
+
+        if let Some(previous) = shard.get(&key).copied()
+        {
+            let previous_count = previous.count.min(MAX_HISTORY);
+
+            add(previous, target(previous.point, previous.normal) * previous.weight * (previous_count as Scalar), previous_count, sampler);
+        }
+
+        let mut chosen = chosen?;
+        let chosen_target = target(chosen.point, chosen.normal);
+
+        if chosen_target <= 0.0
+        {
+            return None;
+        }
+
+        chosen.weight = weight_sum / ((count as Scalar) * chosen_target);
+        chosen.count = count;
+
+        shard.insert(key, chosen);
+        drop(shard);
+
+        // Convert the weight back to a density by solid angle
+
+        let offset = chosen.point - location;
+        let distance_squared = offset.magnitude_squared();
+        let dir = offset / distance_squared.sqrt();
+        let geometry = dir.dot(chosen.normal).abs() / distance_squared;
+
+        Some((dir, 1.0 / (chosen.weight * geometry)))
+    }
+
+    fn shard(&self, key: &CellKey) -> &Mutex<HashMap<CellKey, Reservoir>>
+    {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        &self.shards[(hasher.finish() as usize) % NUM_SHARDS]
+    }
+}
@@ -58,6 +58,21 @@ impl Sampler
         }
     }
 
+    /// Returns the (x, y) coordinates
+    pub fn uniform_point_in_unit_disc(&mut self) -> (Scalar, Scalar)
+    {
+        loop
+        {
+            let x = -1.0 + 2.0 * self.uniform_scalar_unit();
+            let y = -1.0 + 2.0 * self.uniform_scalar_unit();
+
+            if (x * x) + (y * y) <= 1.0
+            {
+                return (x, y);
+            }
+        }
+    }
+
     pub fn uniform_dir_on_unit_sphere(&mut self) -> Dir3
     {
         self.uniform_point_in_unit_sphere().normalized()
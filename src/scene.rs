@@ -1,15 +1,26 @@
-use crate::bsdf::{Bsdf, Lambertian, Phong};
-use crate::camera::Camera;
+use crate::atmosphere::Atmosphere;
+use crate::bsdf::{Bsdf, Ggx, Isotropic, Lambertian, Phong};
+use crate::camera::{Backplate, Camera};
 use crate::color::LinearRGB;
+use crate::geom::Bvh;
+use crate::guiding::{GuideCell, PathGuide};
 use crate::intersection::{Face, ObjectIntersection, ShadingIntersection};
 use crate::lighting::LightingRegion;
 use crate::material::MaterialInteraction;
 use crate::math::{EPSILON, Scalar, ScalarConsts};
 use crate::object::Object;
+use crate::occlusion::{AoCache, RAYS_PER_LOOKUP};
+use crate::radiance::{CachePoint, RadianceCache};
 use crate::ray::{Ray, RayRange};
+use crate::reservoir::ReservoirGrid;
 use crate::sample::Sampler;
 use crate::vec::{Dir3, Point3, RefractResult, bsdf_reflect, bsdf_refract_or_reflect};
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 #[derive(Debug, Copy, Clone)]
 pub enum SamplingMode
 {
@@ -17,6 +28,9 @@ pub enum SamplingMode
     BsdfOnly,
     LightsOnly,
     BsdfAndLights,
+    /// Light samples are chosen by reservoir resampling, shared
+    /// between nearby points, and mixed with BSDF sampling
+    Reservoir,
 }
 
 pub enum ScatteringResult
@@ -51,6 +65,88 @@ pub trait ScatteringFunction
     fn termination_contdition(attenuation: LinearRGB) -> LinearRGB;
 }
 
+/// How the direction leaving a path vertex was chosen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SampleTechnique
+{
+    Uniform,
+    Bsdf,
+    Light,
+    Specular,
+    Emit,
+    /// The path ended at the radiance cache
+    Cache,
+}
+
+impl SampleTechnique
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            SampleTechnique::Uniform => "Uniform",
+            SampleTechnique::Bsdf => "BSDF",
+            SampleTechnique::Light => "Light",
+            SampleTechnique::Specular => "Specular",
+            SampleTechnique::Emit => "Emit",
+            SampleTechnique::Cache => "Radiance Cache",
+        }
+    }
+}
+
+/// A vertex along a traced path, as recorded for inspection.
+/// The attenuation and probability are cumulative, including
+/// the scattering at this vertex.
+#[derive(Clone, Debug)]
+pub struct PathVertex
+{
+    pub location: Point3,
+    pub normal: Dir3,
+    pub front_face: bool,
+    pub distance: Scalar,
+    pub object: Option<String>,
+    pub interaction: &'static str,
+    pub technique: SampleTechnique,
+    pub next_dir: Option<Dir3>,
+    pub attenuation: LinearRGB,
+    pub probability: Scalar,
+}
+
+pub trait PathRecorder
+{
+    const ENABLED: bool;
+
+    fn record(&mut self, vertex: PathVertex);
+}
+
+impl PathRecorder for ()
+{
+    const ENABLED: bool = false;
+
+    fn record(&mut self, _vertex: PathVertex)
+    {
+    }
+}
+
+impl PathRecorder for Vec<PathVertex>
+{
+    const ENABLED: bool = true;
+
+    fn record(&mut self, vertex: PathVertex)
+    {
+        self.push(vertex);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PixelInspection
+{
+    pub origin: Point3,
+    pub path: Vec<PathVertex>,
+    pub color: LinearRGB,
+    pub probability: Scalar,
+}
+
 #[derive(Clone, Copy)]
 pub struct SceneSampleStats
 {
@@ -60,6 +156,33 @@ pub struct SceneSampleStats
     pub stopped_due_to_max_rays: u64,
     pub stopped_due_to_min_atten: u64,
     pub stopped_due_to_min_prob: u64,
+    /// Paths that used the radiance cache, in place of tracing further
+    pub radiance_cache_hits: u64,
+    /// Total radiance removed by clamping
+    pub clamped_energy: Scalar,
+    /// Time spent in each stage of tracing, measured for only
+    /// one in every `TIMED_SAMPLE_INTERVAL` samples, so that
+    /// the timing doesn't slow down rendering. Light sampling
+    /// for local illumination is included in shading.
+    pub timed_samples: u64,
+    pub intersection_time: Duration,
+    pub shading_time: Duration,
+    pub sampling_time: Duration,
+}
+
+pub const TIMED_SAMPLE_INTERVAL: u64 = 64;
+
+fn start_timer(timed: bool) -> Option<Instant>
+{
+    if timed { Some(Instant::now()) } else { None }
+}
+
+fn add_elapsed(start: Option<Instant>, total: &mut Duration)
+{
+    if let Some(start) = start
+    {
+        *total += start.elapsed();
+    }
 }
 
 impl SceneSampleStats
@@ -74,6 +197,12 @@ impl SceneSampleStats
             stopped_due_to_max_rays: 0,
             stopped_due_to_min_atten: 0,
             stopped_due_to_min_prob: 0,
+            radiance_cache_hits: 0,
+            clamped_energy: 0.0,
+            timed_samples: 0,
+            intersection_time: Duration::ZERO,
+            shading_time: Duration::ZERO,
+            sampling_time: Duration::ZERO,
         }
     }
 
@@ -102,6 +231,122 @@ impl std::ops::Add for SceneSampleStats
             stopped_due_to_max_rays: self.stopped_due_to_max_rays + rhs.stopped_due_to_max_rays,
             stopped_due_to_min_atten: self.stopped_due_to_min_atten + rhs.stopped_due_to_min_atten,
             stopped_due_to_min_prob: self.stopped_due_to_min_prob + rhs.stopped_due_to_min_prob,
+            radiance_cache_hits: self.radiance_cache_hits + rhs.radiance_cache_hits,
+            clamped_energy: self.clamped_energy + rhs.clamped_energy,
+            timed_samples: self.timed_samples + rhs.timed_samples,
+            intersection_time: self.intersection_time + rhs.intersection_time,
+            shading_time: self.shading_time + rhs.shading_time,
+            sampling_time: self.sampling_time + rhs.sampling_time,
+        }
+    }
+}
+
+/// Limits on the paths traced through the scene
+#[derive(Clone, Copy, Debug)]
+pub struct TraceOptions
+{
+    /// Maximum number of times a path can scatter, on
+    /// top of the scattering function's own limit
+    pub max_bounces: usize,
+    /// Maximum number of times a path can scatter
+    /// off each type of material
+    pub max_diffuse_bounces: usize,
+    pub max_glossy_bounces: usize,
+    pub max_transmission_bounces: usize,
+    /// Maximum number of dielectrics a shadow ray passes
+    /// through - any more are treated as opaque
+    pub max_shadow_transparent_hits: usize,
+    /// Maximum radiance from light reached after one bounce,
+    /// or zero for no limit
+    pub direct_clamp: Scalar,
+    /// Maximum radiance from light reached after more
+    /// than one bounce, or zero for no limit
+    pub indirect_clamp: Scalar,
+    /// Tint surfaces seen from the camera that are
+    /// at (or near) its focus distance
+    pub show_focal_plane: bool,
+    /// Show the radiance cache's cells on surfaces seen from the camera
+    pub show_radiance_cache: bool,
+}
+
+impl Default for TraceOptions
+{
+    fn default() -> Self
+    {
+        TraceOptions
+        {
+            max_bounces: usize::MAX,
+            max_diffuse_bounces: usize::MAX,
+            max_glossy_bounces: usize::MAX,
+            max_transmission_bounces: usize::MAX,
+            max_shadow_transparent_hits: 8,
+            direct_clamp: 0.0,
+            indirect_clamp: 0.0,
+            show_focal_plane: false,
+            show_radiance_cache: false,
+        }
+    }
+}
+
+impl TraceOptions
+{
+    fn max_bounces_of(&self, bounce_type: BounceType) -> usize
+    {
+        match bounce_type
+        {
+            BounceType::Diffuse => self.max_diffuse_bounces,
+            BounceType::Glossy => self.max_glossy_bounces,
+            BounceType::Transmission => self.max_transmission_bounces,
+        }
+    }
+}
+
+/// Vertices along a path that path guiding learns from
+const MAX_GUIDE_SAMPLES: usize = 8;
+
+/// Where a path meets the radiance cache and the path guide
+#[derive(Default)]
+struct CachePoints
+{
+    /// The first vertex whose radiance should be added to the
+    /// cache, with the attenuation and probability before it
+    sample: Option<(CachePoint, LinearRGB, Scalar)>,
+    /// Where the camera sees the cache, when it's being shown
+    shown: Option<CachePoint>,
+    guide_samples: [Option<GuideSample>; MAX_GUIDE_SAMPLES],
+}
+
+/// A diffuse bounce, with the direction it sampled and its
+/// probability, and the attenuation and probability after it
+struct GuideSample
+{
+    cell: GuideCell,
+    dir: Dir3,
+    dir_probability: Scalar,
+    attenuation: LinearRGB,
+    probability: Scalar,
+}
+
+/// The types of scattering that have separate limits
+#[derive(Clone, Copy)]
+enum BounceType
+{
+    Diffuse,
+    Glossy,
+    Transmission,
+}
+
+impl BounceType
+{
+    fn of(interaction: &MaterialInteraction) -> Option<BounceType>
+    {
+        match interaction
+        {
+            MaterialInteraction::Diffuse{..} | MaterialInteraction::Medium{..} => Some(BounceType::Diffuse),
+            MaterialInteraction::Reflection{..} => Some(BounceType::Glossy),
+            MaterialInteraction::Pbr{ metallic, .. } => Some(if *metallic < 0.5 { BounceType::Diffuse } else { BounceType::Glossy }),
+            MaterialInteraction::Refraction{..} => Some(BounceType::Transmission),
+            MaterialInteraction::Emit{..} => None,
         }
     }
 }
@@ -113,38 +358,323 @@ pub struct Scene
     camera: Camera,
     lighting_regions: Vec<LightingRegion>,
     objects: Vec<Object>,
+    /// Over the objects with finite bounds
+    bvh: Bvh,
+    /// Objects that are traced without the BVH, e.g. planes
+    unbounded: Vec<usize>,
+    backplate: Option<Backplate>,
+    /// Lights rays that escape the scene
+    atmosphere: Option<Arc<Atmosphere>>,
+    /// Darkens the ambient term of local lighting
+    ao_cache: Option<AoCache>,
+    /// Shortens global lighting paths after diffuse bounces
+    radiance_cache: Option<RadianceCache>,
+    /// Learns where light comes from, to guide diffuse bounces
+    path_guide: Option<PathGuide>,
+    /// For the reservoir sampling mode
+    reservoirs: Option<ReservoirGrid>,
+    trace_options: TraceOptions,
 }
 
 impl Scene
 {
     pub fn new(sampling_mode: SamplingMode, camera: Camera, lighting_regions: Vec<LightingRegion>, objects: Vec<Object>) -> Self
     {
-        Scene { sampling_mode, camera, lighting_regions, objects }
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+
+        for (index, object) in objects.iter().enumerate()
+        {
+            match object.bounding_aabb()
+            {
+                Some(bounds) => bounded.push((index, bounds)),
+                None => unbounded.push(index),
+            }
+        }
+
+        let bvh = Bvh::new(bounded);
+
+        let reservoirs = if let SamplingMode::Reservoir = sampling_mode { Some(ReservoirGrid::new(&camera)) } else { None };
+
+        Scene { sampling_mode, camera, lighting_regions, objects, bvh, unbounded, backplate: None, atmosphere: None, ao_cache: None, radiance_cache: None, path_guide: None, reservoirs, trace_options: TraceOptions::default() }
+    }
+
+    pub fn with_trace_options(mut self, trace_options: TraceOptions) -> Self
+    {
+        self.trace_options = trace_options;
+        self
+    }
+
+    pub fn with_backplate(mut self, backplate: Option<Backplate>) -> Self
+    {
+        self.backplate = backplate;
+        self
+    }
+
+    pub fn with_atmosphere(mut self, atmosphere: Option<Atmosphere>) -> Self
+    {
+        self.atmosphere = atmosphere.map(Arc::new);
+        self
+    }
+
+    /// The cache is prepared for this scene, so it's
+    /// cleared if the geometry has changed
+    pub fn with_ao_cache(mut self, ao_cache: Option<AoCache>) -> Self
+    {
+        if let Some(ao_cache) = &ao_cache
+        {
+            // The median is used, as scenes often
+            // have a few very large objects, e.g. domes
+
+            let mut diagonals = self.objects.iter()
+                .filter_map(|o| o.bounding_aabb())
+                .map(|b| (b.max - b.min).magnitude())
+                .collect::<Vec<_>>();
+
+            diagonals.sort_by(|a, b| a.total_cmp(b));
+
+            ao_cache.prepare(self.geometry_key(), diagonals.get(diagonals.len() / 2).copied().unwrap_or(0.0));
+        }
+
+        self.ao_cache = ao_cache;
+        self
+    }
+
+    /// A new, empty, cache for this scene's camera
+    pub fn with_radiance_cache(mut self, enabled: bool) -> Self
+    {
+        self.radiance_cache = if enabled { Some(RadianceCache::new(&self.camera)) } else { None };
+        self
+    }
+
+    /// A new guide, which learns as this scene is rendered
+    pub fn with_path_guide(mut self, enabled: bool) -> Self
+    {
+        self.path_guide = if enabled { Some(PathGuide::new(&self.camera)) } else { None };
+        self
+    }
+
+    pub fn camera(&self) -> &Camera
+    {
+        &self.camera
+    }
+
+    /// Identifies the shape of the scene, from the bounds of each
+    /// object - changes to the camera or materials keep the same key
+    pub fn geometry_key(&self) -> u64
+    {
+        let mut hasher = DefaultHasher::new();
+
+        hasher.write_usize(self.objects.len());
+
+        for object in self.objects.iter()
+        {
+            if let Some(bounds) = object.bounding_aabb()
+            {
+                for c in bounds.min.iter().chain(bounds.max.iter())
+                {
+                    hasher.write_u64(c.to_bits());
+                }
+            }
+        }
+
+        hasher.finish()
+    }
+
+    /// The fraction of the hemisphere above the intersection that's
+    /// not blocked nearby, from the AO cache, if the scene has one.
+    /// Lookups of cells that haven't converged trace a few more rays.
+    pub fn ambient_visibility(&self, intersection: &ShadingIntersection, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> Option<Scalar>
+    {
+        let ao_cache = self.ao_cache.as_ref()?;
+
+        let (visibility, needs_samples) = ao_cache.lookup(intersection.location, intersection.normal);
+
+        if let Some(key) = needs_samples
+        {
+            let lambertian = Lambertian::new(intersection);
+            let range = RayRange::new(EPSILON, ao_cache.radius());
+
+            let visible = (0..RAYS_PER_LOOKUP)
+                .filter(|_|
+                {
+                    let (dir, _) = lambertian.generate_random_sample_dir_and_calc_pdf(sampler);
+                    stats.num_rays += 1;
+
+                    self.closest_intersection(&intersection.spawn_ray(dir), &range, |_| true).is_none()
+                })
+                .count() as u32;
+
+            ao_cache.add_samples(key, visible, RAYS_PER_LOOKUP);
+
+            if visibility.is_none()
+            {
+                return Some((visible as Scalar) / (RAYS_PER_LOOKUP as Scalar));
+            }
+        }
+
+        visibility
     }
 
     pub fn path_trace_global_lighting(&self, u: Scalar, v: Scalar, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> (LinearRGB, Scalar)
     {
-        let ray = self.camera.get_ray(u, v);
+        let ray = self.camera.get_ray(u, v, sampler);
+        let result = self.trace_path::<GlobalLighting, _>(ray, self.backplate_color(u, v), sampler, stats, &mut ());
 
-        self.path_trace::<GlobalLighting>(ray, sampler, stats)
+        self.tint_focal_plane(u, v, result)
     }
 
     pub fn path_trace_local_lighting(&self, u: Scalar, v: Scalar, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> (LinearRGB, Scalar)
     {
-        let ray = self.camera.get_ray(u, v);
+        let ray = self.camera.get_ray(u, v, sampler);
+        let result = self.trace_path::<LocalLighting, _>(ray, self.backplate_color(u, v), sampler, stats, &mut ());
+
+        self.tint_focal_plane(u, v, result)
+    }
+
+    pub fn path_trace_global_lighting_ray(&self, ray: Ray, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> (LinearRGB, Scalar)
+    {
+        self.path_trace::<GlobalLighting>(ray, sampler, stats)
+    }
+
+    /// Traces a single global lighting path through the given
+    /// pixel coordinates, recording each vertex along the path.
+    pub fn inspect_pixel(&self, u: Scalar, v: Scalar, sampler: &mut Sampler) -> PixelInspection
+    {
+        let ray = self.camera.get_ray(u, v, sampler);
+        let origin = ray.source;
+        let mut stats = SceneSampleStats::new();
+        let mut path = Vec::new();
+
+        let (color, probability) = self.trace_path::<GlobalLighting, _>(ray, self.backplate_color(u, v), sampler, &mut stats, &mut path);
+
+        PixelInspection { origin, path, color, probability }
+    }
 
-        self.path_trace::<LocalLighting>(ray, sampler, stats)
+    /// Traces a number of independent paths through the
+    /// same pixel coordinates.
+    pub fn inspect_paths(&self, u: Scalar, v: Scalar, count: usize, sampler: &mut Sampler) -> Vec<PixelInspection>
+    {
+        (0..count).map(|_| self.inspect_pixel(u, v, sampler)).collect()
     }
 
     pub fn path_trace<S: ScatteringFunction>(&self, ray: Ray, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> (LinearRGB, Scalar)
+    {
+        self.path_trace_recorded::<S, _>(ray, sampler, stats, &mut ())
+    }
+
+    pub fn path_trace_recorded<S: ScatteringFunction, R: PathRecorder>(&self, ray: Ray, sampler: &mut Sampler, stats: &mut SceneSampleStats, recorder: &mut R) -> (LinearRGB, Scalar)
+    {
+        self.trace_path::<S, R>(ray, None, sampler, stats, recorder)
+    }
+
+    /// Distance (along the view direction) to the surface seen
+    /// through the center of the lens at the given pixel coordinates
+    pub fn depth_at(&self, u: Scalar, v: Scalar) -> Option<Scalar>
+    {
+        let ray = self.camera.get_center_ray(u, v);
+
+        self.trace_intersection(&ray)
+            .map(|i| self.camera.depth_of(ray.point_at(i.surface.distance)))
+    }
+
+    fn tint_focal_plane(&self, u: Scalar, v: Scalar, result: (LinearRGB, Scalar)) -> (LinearRGB, Scalar)
+    {
+        const TOLERANCE: Scalar = 0.02;
+
+        let (color, probability) = result;
+
+        if !self.trace_options.show_focal_plane || (probability <= 0.0)
+        {
+            return result;
+        }
+
+        match (self.camera.focus_distance(), self.depth_at(u, v))
+        {
+            (Some(focus), Some(depth)) if (depth - focus).abs() <= TOLERANCE * focus =>
+            {
+                // The color is divided by the probability later
+
+                let tint = LinearRGB::new(0.0, 1.0, 0.0, 1.0).multiplied_by_scalar(probability);
+
+                ((color + tint).multiplied_by_scalar(0.5), probability)
+            },
+            _ => result,
+        }
+    }
+
+    fn backplate_color(&self, u: Scalar, v: Scalar) -> Option<LinearRGB>
+    {
+        self.backplate.as_ref().map(|b| b.color_at(u, v))
+    }
+
+    /// The backplate color is returned if the ray misses
+    /// the scene, possibly after passing straight through
+    /// transparent surfaces
+    fn trace_path<S: ScatteringFunction, R: PathRecorder>(&self, ray: Ray, backplate: Option<LinearRGB>, sampler: &mut Sampler, stats: &mut SceneSampleStats, recorder: &mut R) -> (LinearRGB, Scalar)
+    {
+        let mut cache_points = CachePoints::default();
+
+        let (color, probability) = self.trace_path_vertices::<S, R>(ray, backplate, sampler, stats, recorder, &mut cache_points);
+
+        // The radiance leaving the vertex is the path's
+        // result, without the attenuation before it
+
+        if let (Some(radiance_cache), Some((point, attenuation, vertex_probability))) = (&self.radiance_cache, cache_points.sample)
+        {
+            let channel = |c: Scalar, a: Scalar| if (a > 0.0) && (probability > 0.0) { (c / a) * (vertex_probability / probability) } else { 0.0 };
+
+            radiance_cache.add_sample(&point, LinearRGB::new(
+                channel(color.r, attenuation.r),
+                channel(color.g, attenuation.g),
+                channel(color.b, attenuation.b),
+                1.0));
+        }
+
+        // The light arriving along each guided bounce is the path's
+        // result, without the attenuation up to and including it
+
+        for sample in cache_points.guide_samples.iter().flatten()
+        {
+            let channel = |c: Scalar, a: Scalar| if (a > 0.0) && (probability > 0.0) { (c / a) * (sample.probability / probability) } else { 0.0 };
+
+            let arriving = (channel(color.r, sample.attenuation.r) + channel(color.g, sample.attenuation.g) + channel(color.b, sample.attenuation.b)) / 3.0;
+
+            sample.cell.add_sample(sample.dir, arriving / sample.dir_probability);
+        }
+
+        // The path is still traced when showing the cache,
+        // so that it fills in as it would otherwise
+
+        if let (Some(radiance_cache), Some(point)) = (&self.radiance_cache, cache_points.shown)
+        {
+            return (radiance_cache.visualize(&point), 1.0);
+        }
+
+        (color, probability)
+    }
+
+    fn trace_path_vertices<S: ScatteringFunction, R: PathRecorder>(&self, ray: Ray, backplate: Option<LinearRGB>, sampler: &mut Sampler, stats: &mut SceneSampleStats, recorder: &mut R, cache_points: &mut CachePoints) -> (LinearRGB, Scalar)
     {
         stats.num_samples += 1;
 
+        let timed = stats.num_samples.is_multiple_of(TIMED_SAMPLE_INTERVAL);
+
+        if timed
+        {
+            stats.timed_samples += 1;
+        }
+
+        let mut backplate = backplate;
+        let max_rays = S::max_rays().min(self.trace_options.max_bounces.saturating_add(1));
+
         let mut cur_ray = ray;
         let mut cur_attenuation = LinearRGB::white();
         let mut cur_probability = 1.0;
+        let mut bounces = [0; 3];
+        let mut diffuse_bounce = false;
 
-        for ray_num in 0..S::max_rays()
+        for ray_num in 0..max_rays
         {
             stats.num_rays += 1;
 
@@ -153,28 +683,130 @@ impl Scene
                 stats.max_rays = ray_num + 1;
             }
 
-            match self.trace_intersection(&cur_ray)
+            let intersection_start = start_timer(timed);
+            let opt_intersection = self.trace_intersection(&cur_ray);
+            add_elapsed(intersection_start, &mut stats.intersection_time);
+
+            match opt_intersection
             {
                 Some(intersection) =>
                 {
-                    let shading_intersection = intersection.surface.into();
-                    let material_interaction = intersection.material.get_surface_interaction(&shading_intersection);
+                    let shading_start = start_timer(timed);
+                    let distance = intersection.surface.distance;
+                    let object = intersection.object;
+                    let mut shading_intersection: ShadingIntersection = intersection.surface.into();
+                    shading_intersection.ray_bias = object.ray_bias();
+                    let material_interaction = intersection.material.get_surface_interaction(&mut shading_intersection);
+                    let interaction = material_interaction.name();
+                    let bounce_type = BounceType::of(&material_interaction);
+                    let technique;
+                    let mut bounced = None;
+
+                    if let (Some(radiance_cache), MaterialInteraction::Diffuse{ diffuse_color }) = (&self.radiance_cache, &material_interaction)
+                    {
+                        if diffuse_color.a >= 0.999999
+                        {
+                            let point = radiance_cache.point(shading_intersection.location, shading_intersection.normal);
+
+                            if (ray_num == 0) && self.trace_options.show_radiance_cache
+                            {
+                                cache_points.shown = Some(point);
+                            }
+
+                            // Only light reaching the camera through a diffuse
+                            // bounce is cached - the cells would be visible
+                            // directly, or in reflections
+
+                            if diffuse_bounce
+                            {
+                                if let Some(radiance) = radiance_cache.radiance(&point)
+                                {
+                                    stats.radiance_cache_hits += 1;
+
+                                    let final_color = self.clamp_radiance(radiance.combined_with(&cur_attenuation), cur_probability, ray_num, stats);
+
+                                    if R::ENABLED
+                                    {
+                                        recorder.record(PathVertex
+                                        {
+                                            location: shading_intersection.location,
+                                            normal: shading_intersection.normal,
+                                            front_face: shading_intersection.face == Face::Front,
+                                            distance,
+                                            object: object.label().map(|l| l.to_owned()),
+                                            interaction,
+                                            technique: SampleTechnique::Cache,
+                                            next_dir: None,
+                                            attenuation: final_color,
+                                            probability: cur_probability,
+                                        });
+                                    }
+
+                                    return (final_color, cur_probability);
+                                }
+
+                                if cache_points.sample.is_none()
+                                {
+                                    cache_points.sample = Some((point, cur_attenuation, cur_probability));
+                                }
+                            }
+                        }
+                    }
+
+                    let scattering = S::scatter_ray(&self, &shading_intersection, material_interaction, sampler, stats);
+                    add_elapsed(shading_start, &mut stats.shading_time);
 
-                    match S::scatter_ray(&self, &shading_intersection, material_interaction, sampler, stats)
+                    match scattering
                     {
                         ScatteringResult::Scatter{ attenuation_color, bsdf, probability } =>
                         {
-                            let (scatter_dir, reflectance, scatter_probability) = self.scatter(&shading_intersection, bsdf, sampler);
+                            let sampling_start = start_timer(timed);
 
-                            cur_ray = Ray::new(shading_intersection.location, scatter_dir);
+                            let guide_cell = match (&self.path_guide, bounce_type)
+                            {
+                                (Some(path_guide), Some(BounceType::Diffuse)) => Some(path_guide.cell(shading_intersection.location, shading_intersection.normal)),
+                                _ => None,
+                            };
+
+                            let bsdf = match &guide_cell
+                            {
+                                Some(cell) => cell.guided_bsdf(bsdf),
+                                None => bsdf,
+                            };
+
+                            let (scatter_dir, reflectance, scatter_probability, scatter_technique) = self.scatter(&shading_intersection, bsdf, sampler);
+                            add_elapsed(sampling_start, &mut stats.sampling_time);
+
+                            cur_ray = shading_intersection.spawn_ray(scatter_dir);
                             cur_attenuation = cur_attenuation.combined_with(&attenuation_color.multiplied_by_scalar(reflectance));
                             cur_probability *= probability * scatter_probability;
+                            technique = scatter_technique;
+                            backplate = None;
+                            bounced = bounce_type;
+
+                            if let (Some(cell), Some(slot)) = (guide_cell, cache_points.guide_samples.iter_mut().find(|s| s.is_none()))
+                            {
+                                if scatter_probability > 0.0
+                                {
+                                    *slot = Some(GuideSample { cell, dir: scatter_dir, dir_probability: scatter_probability, attenuation: cur_attenuation, probability: cur_probability });
+                                }
+                            }
                         },
                         ScatteringResult::Trace{ attenuation_color, next_dir, probability } =>
                         {
-                            cur_ray = Ray::new(shading_intersection.location, next_dir);
+                            // Passing straight through transparent
+                            // surfaces doesn't count as a bounce
+
+                            if next_dir.normalized().dot(cur_ray.dir.normalized()) < (1.0 - EPSILON)
+                            {
+                                backplate = None;
+                                bounced = bounce_type;
+                            }
+
+                            cur_ray = shading_intersection.spawn_ray(next_dir);
                             cur_attenuation = cur_attenuation.combined_with(&attenuation_color);
                             cur_probability *= probability;
+                            technique = SampleTechnique::Specular;
                         },
                         ScatteringResult::Emit{ emitted_color, probability } =>
                         {
@@ -182,17 +814,79 @@ impl Scene
                             // the total contribution
 
                             let final_probability = cur_probability * probability;
+                            let final_color = self.clamp_radiance(emitted_color.combined_with(&cur_attenuation), final_probability, ray_num, stats);
+
+                            if R::ENABLED
+                            {
+                                recorder.record(PathVertex
+                                {
+                                    location: shading_intersection.location,
+                                    normal: shading_intersection.normal,
+                                    front_face: shading_intersection.face == Face::Front,
+                                    distance,
+                                    object: object.label().map(|l| l.to_owned()),
+                                    interaction,
+                                    technique: SampleTechnique::Emit,
+                                    next_dir: None,
+                                    attenuation: final_color,
+                                    probability: final_probability,
+                                });
+                            }
 
-                            return (emitted_color.combined_with(&cur_attenuation), final_probability);
+                            return (final_color, final_probability);
                         },
                     }
+
+                    if R::ENABLED
+                    {
+                        recorder.record(PathVertex
+                        {
+                            location: shading_intersection.location,
+                            normal: shading_intersection.normal,
+                            front_face: shading_intersection.face == Face::Front,
+                            distance,
+                            object: object.label().map(|l| l.to_owned()),
+                            interaction,
+                            technique,
+                            next_dir: Some(cur_ray.dir),
+                            attenuation: cur_attenuation,
+                            probability: cur_probability,
+                        });
+                    }
+
+                    if let Some(bounce_type) = bounced
+                    {
+                        diffuse_bounce = matches!(bounce_type, BounceType::Diffuse);
+                        bounces[bounce_type as usize] += 1;
+
+                        if bounces[bounce_type as usize] > self.trace_options.max_bounces_of(bounce_type)
+                        {
+                            // The same as running out of rays, but
+                            // for just this type of material
+
+                            stats.stopped_due_to_max_rays += 1;
+
+                            return (S::termination_contdition(cur_attenuation), cur_probability);
+                        }
+                    }
                 },
                 None =>
                 {
                     // This ray doens't hit any objects -
-                    // there's nothing to see
+                    // there's nothing to see, other than
+                    // the backplate from the camera, or
+                    // the atmosphere
 
-                    return (LinearRGB::black(), cur_probability);
+                    return match (backplate, &self.atmosphere)
+                    {
+                        (Some(color), _) => (color.combined_with(&cur_attenuation), cur_probability),
+                        (None, Some(atmosphere)) =>
+                        {
+                            let color = atmosphere.radiance(cur_ray.dir).combined_with(&cur_attenuation);
+                            (self.clamp_radiance(color, cur_probability, ray_num, stats), cur_probability)
+                        },
+                        (None, None) => (LinearRGB::black(), cur_probability),
+                    };
                 },
             }
 
@@ -236,12 +930,95 @@ impl Scene
         (S::termination_contdition(cur_attenuation), cur_probability)
     }
 
+    /// Clamps the radiance (color / probability) of light reached
+    /// after bouncing, trading a little bias (darkening) for less
+    /// of the noise caused by rare, very bright, paths
+    fn clamp_radiance(&self, color: LinearRGB, probability: Scalar, ray_num: usize, stats: &mut SceneSampleStats) -> LinearRGB
+    {
+        let clamp = match ray_num
+        {
+            0 => return color,
+            1 => self.trace_options.direct_clamp,
+            _ => self.trace_options.indirect_clamp,
+        };
+
+        if (clamp <= 0.0) || (probability <= 0.0)
+        {
+            return color;
+        }
+
+        let radiance = color.max_color_component() / probability;
+
+        if radiance <= clamp
+        {
+            return color;
+        }
+
+        stats.clamped_energy += radiance - clamp;
+
+        color.multiplied_by_scalar(clamp / radiance)
+    }
+
     pub fn trace_intersection<'r, 'm>(&'m self, ray: &'r Ray) -> Option<ObjectIntersection<'r, 'm>>
+    {
+        self.closest_intersection(ray, &RayRange::new(EPSILON, Scalar::MAX), |_| true)
+    }
+
+    /// Traces a shadow ray, returning the closest intersection
+    /// with a solid surface, and the color of light that makes
+    /// it through any participating media and dielectrics before
+    /// it. Dielectrics are passed straight through, without
+    /// refracting, and tint the light each time - up to the
+    /// maximum number of transparent hits, after which they're opaque.
+    pub fn trace_shadow<'r, 'm>(&'m self, ray: &'r Ray) -> (Option<ObjectIntersection<'r, 'm>>, LinearRGB)
     {
         let mut range = RayRange::new(EPSILON, Scalar::MAX);
+        let mut transmittance = LinearRGB::white();
+        let mut transparent_hits = 0;
+
+        let closest = loop
+        {
+            let closest = self.closest_intersection(ray, &range, |o| !o.is_medium());
+
+            if let Some(intersection) = &closest
+            {
+                if transparent_hits < self.trace_options.max_shadow_transparent_hits
+                {
+                    let mut shading: ShadingIntersection = (&intersection.surface).into();
+
+                    if let MaterialInteraction::Refraction{ tint, .. } = intersection.material.get_surface_interaction(&mut shading)
+                    {
+                        transmittance = transmittance.combined_with(&tint);
+                        transparent_hits += 1;
+                        range.set_min(intersection.surface.distance);
+                        continue;
+                    }
+                }
+            }
+
+            break closest;
+        };
+
+        let range = RayRange::new(EPSILON, closest.as_ref().map(|i| i.surface.distance).unwrap_or(Scalar::MAX));
+
+        let media_transmittance: Scalar = self.objects.iter()
+            .filter(|o| o.is_medium())
+            .map(|o| o.transmittance(ray, &range))
+            .product();
+
+        (closest, transmittance.multiplied_by_scalar(media_transmittance))
+    }
+
+    /// The closest intersection with the objects that match the filter -
+    /// the unbounded objects are tested first, as they're usually large
+    /// (e.g. the ground), so they shorten the range searched in the BVH
+    fn closest_intersection<'r, 'm, F>(&'m self, ray: &'r Ray, range: &RayRange, filter: F) -> Option<ObjectIntersection<'r, 'm>>
+        where F: Fn(&Object) -> bool
+    {
+        let mut range = range.clone();
         let mut closest = None;
 
-        for obj in self.objects.iter()
+        for obj in self.unbounded.iter().map(|i| &self.objects[*i]).filter(|o| filter(o))
         {
             if let Some(intersection) = obj.closest_intersection_in_range(ray, &range)
             {
@@ -250,6 +1027,21 @@ impl Scene
             }
         }
 
+        self.bvh.closest_in_range(ray, &range, |index, range|
+        {
+            let obj = &self.objects[index];
+
+            if !filter(obj)
+            {
+                return None;
+            }
+
+            let intersection = obj.closest_intersection_in_range(ray, range)?;
+            let distance = intersection.surface.distance;
+            closest = Some(intersection);
+            Some(distance)
+        });
+
         closest
     }
 
@@ -258,17 +1050,21 @@ impl Scene
         self.lighting_regions.iter().filter(|lr| lr.covered_volume.is_point_inside(location)).nth(0)
     }
 
-    fn scatter(&self, intersection: &ShadingIntersection, bsdf: Box<dyn Bsdf>, sampler: &mut Sampler) -> (Dir3, Scalar, Scalar)
+    fn scatter(&self, intersection: &ShadingIntersection, bsdf: Box<dyn Bsdf>, sampler: &mut Sampler) -> (Dir3, Scalar, Scalar, SampleTechnique)
     {
-        let (scatter_dir, probability) = match self.sampling_mode
+        // Set where directions are covered by another technique
+        let mut reflectance_scale = 1.0;
+
+        let (scatter_dir, probability, technique) = match self.sampling_mode
         {
             SamplingMode::Uniform =>
             {
-                (sampler.uniform_dir_on_unit_sphere(), 0.25 * ScalarConsts::FRAC_1_PI)
+                (sampler.uniform_dir_on_unit_sphere(), 0.25 * ScalarConsts::FRAC_1_PI, SampleTechnique::Uniform)
             },
             SamplingMode::BsdfOnly =>
             {
-                bsdf.generate_random_sample_dir_and_calc_pdf(sampler)
+                let (dir, prob) = bsdf.generate_random_sample_dir_and_calc_pdf(sampler);
+                (dir, prob, SampleTechnique::Bsdf)
             },
             SamplingMode::LightsOnly =>
             {
@@ -292,13 +1088,13 @@ impl Scene
                             }
                         }
         
-                        (dir, prob / (num_lights as Scalar))
+                        (dir, prob / (num_lights as Scalar), SampleTechnique::Light)
                     },
                     None =>
                     {
                         // No light sampling information - revert to uniform sampling
             
-                        (sampler.uniform_dir_on_unit_sphere(), 0.25 * ScalarConsts::FRAC_1_PI)
+                        (sampler.uniform_dir_on_unit_sphere(), 0.25 * ScalarConsts::FRAC_1_PI, SampleTechnique::Uniform)
                     },
                 }
             },
@@ -334,7 +1130,7 @@ impl Scene
                             let prob = (light_prob * prob / (num_lights as Scalar))
                                 + (bsdf_prob * bsdf.calculate_pdf_for_dir(dir));
             
-                            (dir, prob)
+                            (dir, prob, SampleTechnique::Light)
                         }
                         else
                         {
@@ -347,22 +1143,99 @@ impl Scene
                             let prob = (bsdf_prob * prob)
                                 + (light_prob * lighting_region.global_surfaces.iter().map(|s| s.calculate_pdf_for_ray(&sampled_ray)).sum::<Scalar>());
             
-                            (dir, prob)
+                            (dir, prob, SampleTechnique::Bsdf)
                         }
                     },
                     None =>
                     {
-                        // No light sampling information - revert to BSDF sampling
-            
-                        bsdf.generate_random_sample_dir_and_calc_pdf(sampler)
+                        match &self.atmosphere
+                        {
+                            Some(atmosphere) =>
+                            {
+                                // Sample towards the sun half of the time,
+                                // as it's too small for the BSDF to find
+
+                                let sun_prob = 0.5;
+
+                                if sampler.uniform_scalar_unit() < sun_prob
+                                {
+                                    let (dir, prob) = atmosphere.sample_sun(sampler);
+                                    let prob = (sun_prob * prob) + ((1.0 - sun_prob) * bsdf.calculate_pdf_for_dir(dir));
+
+                                    (dir, prob, SampleTechnique::Light)
+                                }
+                                else
+                                {
+                                    let (dir, prob) = bsdf.generate_random_sample_dir_and_calc_pdf(sampler);
+                                    let prob = ((1.0 - sun_prob) * prob) + (sun_prob * atmosphere.sun_pdf(dir));
+
+                                    (dir, prob, SampleTechnique::Bsdf)
+                                }
+                            },
+                            None =>
+                            {
+                                // No light sampling information - revert to BSDF sampling
+
+                                let (dir, prob) = bsdf.generate_random_sample_dir_and_calc_pdf(sampler);
+                                (dir, prob, SampleTechnique::Bsdf)
+                            },
+                        }
                     }
                 }
             },
+            SamplingMode::Reservoir =>
+            {
+                match (&self.reservoirs, self.lighting_regions.iter().find(|lr| lr.covered_volume.is_point_inside(intersection.location)))
+                {
+                    (Some(reservoirs), Some(lighting_region)) =>
+                    {
+                        // Directions towards the lights are only found by
+                        // resampling, and the rest only by the BSDF, so
+                        // each technique has half the probability
+
+                        let light_prob = 0.5;
+                        let bsdf_prob = 1.0 - light_prob;
+
+                        if sampler.uniform_scalar_unit() < light_prob
+                        {
+                            match reservoirs.sample(lighting_region, intersection.location, intersection.normal, bsdf.as_ref(), sampler)
+                            {
+                                Some((dir, prob)) => (dir, light_prob * prob, SampleTechnique::Light),
+                                None =>
+                                {
+                                    reflectance_scale = 0.0;
+                                    (intersection.normal, light_prob, SampleTechnique::Light)
+                                },
+                            }
+                        }
+                        else
+                        {
+                            let (dir, prob) = bsdf.generate_random_sample_dir_and_calc_pdf(sampler);
+
+                            let sampled_ray = Ray::new(intersection.location, dir);
+
+                            if lighting_region.global_surfaces.iter().any(|s| s.calculate_pdf_for_ray(&sampled_ray) > 0.0)
+                            {
+                                reflectance_scale = 0.0;
+                            }
+
+                            (dir, bsdf_prob * prob, SampleTechnique::Bsdf)
+                        }
+                    },
+                    _ =>
+                    {
+                        // No light sampling information - revert to BSDF sampling
+
+                        let (dir, prob) = bsdf.generate_random_sample_dir_and_calc_pdf(sampler);
+                        (dir, prob, SampleTechnique::Bsdf)
+                    },
+                }
+            },
         };
     
-        let reflectance = bsdf.reflectance(scatter_dir);
+        let reflectance = reflectance_scale * bsdf.reflectance(scatter_dir);
 
-        (scatter_dir, reflectance, probability)
+        (scatter_dir, reflectance, probability, technique)
     }
 }
 
@@ -403,7 +1276,44 @@ impl ScatteringFunction for GlobalLighting
                     Box::new(Phong::new(intersection, 0.2, 0.8, 5.0 / fuzz)),
                     1.0)
             },
-            MaterialInteraction::Refraction{ ior } =>
+            MaterialInteraction::Pbr{ base_color, metallic, roughness } =>
+            {
+                if sampler.uniform_scalar_unit() > base_color.a
+                {
+                    return ScatteringResult::trace(LinearRGB::white(), -intersection.incoming, 1.0 - base_color.a);
+                }
+
+                // Choose between the specular and diffuse
+                // lobes, in proportion to their weights
+
+                let (specular_color, diffuse_color) = pbr_lobe_colors(intersection, base_color, metallic);
+
+                let specular_weight = specular_color.max_color_component();
+                let diffuse_weight = diffuse_color.max_color_component();
+
+                if (specular_weight + diffuse_weight) <= 0.0
+                {
+                    return ScatteringResult::emit(LinearRGB::black(), base_color.a);
+                }
+
+                let specular_probability = specular_weight / (specular_weight + diffuse_weight);
+
+                if sampler.uniform_scalar_unit() < specular_probability
+                {
+                    ScatteringResult::scatter(
+                        specular_color,
+                        Box::new(Ggx::new(intersection, roughness)),
+                        base_color.a * specular_probability)
+                }
+                else
+                {
+                    ScatteringResult::scatter(
+                        diffuse_color,
+                        Box::new(Lambertian::new(intersection)),
+                        base_color.a * (1.0 - specular_probability))
+                }
+            },
+            MaterialInteraction::Refraction{ ior, tint } =>
             {
                 let refraction_ratio = if intersection.face == Face::Front
                 {
@@ -422,16 +1332,18 @@ impl ScatteringFunction for GlobalLighting
                     },
                     RefractResult::ReflectOrRefract{ refract_dir, reflect_dir, reflect_probability } =>
                     {
-                        let (dir, probability) = if sampler.uniform_scalar_unit() < reflect_probability
+                        // Only the transmitted light is tinted
+
+                        let (dir, color, probability) = if sampler.uniform_scalar_unit() < reflect_probability
                         {
-                            (reflect_dir, reflect_probability)
+                            (reflect_dir, LinearRGB::white(), reflect_probability)
                         }
                         else
                         {
-                            (refract_dir, 1.0 - reflect_probability)
+                            (refract_dir, tint, 1.0 - reflect_probability)
                         };
 
-                        ScatteringResult::trace(LinearRGB::grey(probability), dir, probability)
+                        ScatteringResult::trace(color.multiplied_by_scalar(probability), dir, probability)
                     },
                 }
             },
@@ -442,6 +1354,23 @@ impl ScatteringFunction for GlobalLighting
 
                 ScatteringResult::emit(emitted_color, 1.0)
             },
+            MaterialInteraction::Medium{ albedo, emitted_color } =>
+            {
+                // Scatter with a probability of the albedo - otherwise
+                // the light is absorbed, and the medium's own emission
+                // is all that is returned
+
+                let scatter_probability = albedo.max_color_component().clamp(0.0, 1.0);
+
+                if sampler.uniform_scalar_unit() < scatter_probability
+                {
+                    ScatteringResult::scatter(albedo, Box::new(Isotropic), scatter_probability)
+                }
+                else
+                {
+                    ScatteringResult::emit(emitted_color, 1.0)
+                }
+            },
         }
     }
 
@@ -465,7 +1394,7 @@ impl ScatteringFunction for LocalLighting
         5
     }
 
-    fn scatter_ray(scene: &Scene, intersection: &ShadingIntersection, material_interaction: MaterialInteraction, _sampler: &mut Sampler, stats: &mut SceneSampleStats) -> ScatteringResult
+    fn scatter_ray(scene: &Scene, intersection: &ShadingIntersection, material_interaction: MaterialInteraction, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> ScatteringResult
     {
         match material_interaction
         {
@@ -479,7 +1408,7 @@ impl ScatteringFunction for LocalLighting
                     // we apply the local Phong model,
                     // and then Emit that light back towards the camera.
 
-                    ScatteringResult::emit(Phong::local_shading(scene, intersection, diffuse_color, 0.1, 0.6, diffuse_color, 0.3, 20.0, stats), 1.0)
+                    ScatteringResult::emit(Phong::local_shading(scene, intersection, diffuse_color, local_ambient(scene, intersection, sampler, stats), 0.6, diffuse_color, 0.3, 20.0, stats), 1.0)
                 }
                 else if diffuse_color.a <= 0.000001
                 {
@@ -491,7 +1420,7 @@ impl ScatteringFunction for LocalLighting
                 {
                     // Mix
 
-                    let local = Phong::local_shading(scene, intersection, diffuse_color, 0.1, 0.6, diffuse_color, 0.3, 20.0, stats);
+                    let local = Phong::local_shading(scene, intersection, diffuse_color, local_ambient(scene, intersection, sampler, stats), 0.6, diffuse_color, 0.3, 20.0, stats);
 
                     ScatteringResult::trace(local.multiplied_by_scalar(1.0 - diffuse_color.a), -intersection.incoming, 1.0)
                 }
@@ -500,7 +1429,20 @@ impl ScatteringFunction for LocalLighting
             {
                 ScatteringResult::trace(attenuate_color, bsdf_reflect(intersection.incoming, intersection.normal), 1.0)
             },
-            MaterialInteraction::Refraction{ ior } =>
+            MaterialInteraction::Pbr{ base_color, metallic, .. } =>
+            {
+                if metallic >= 0.5
+                {
+                    ScatteringResult::trace(base_color, bsdf_reflect(intersection.incoming, intersection.normal), 1.0)
+                }
+                else
+                {
+                    let (specular_color, diffuse_color) = pbr_lobe_colors(intersection, base_color, metallic);
+
+                    ScatteringResult::emit(Phong::local_shading(scene, intersection, diffuse_color, local_ambient(scene, intersection, sampler, stats), 0.6, specular_color, 0.3, 20.0, stats), 1.0)
+                }
+            },
+            MaterialInteraction::Refraction{ ior, tint } =>
             {
                 let refraction_ratio = if intersection.face == Face::Front
                 {
@@ -511,18 +1453,27 @@ impl ScatteringFunction for LocalLighting
                     ior
                 };
 
-                let new_dir = match bsdf_refract_or_reflect(intersection.incoming, intersection.normal, refraction_ratio)
+                let (new_dir, color) = match bsdf_refract_or_reflect(intersection.incoming, intersection.normal, refraction_ratio)
                 {
-                    RefractResult::TotalInternalReflection{ reflect_dir } => reflect_dir,
-                    RefractResult::ReflectOrRefract{ refract_dir, .. } => refract_dir,
+                    RefractResult::TotalInternalReflection{ reflect_dir } => (reflect_dir, LinearRGB::white()),
+                    RefractResult::ReflectOrRefract{ refract_dir, .. } => (refract_dir, tint),
                 };
 
-                ScatteringResult::trace(LinearRGB::white(), new_dir, 1.0)
+                ScatteringResult::trace(color, new_dir, 1.0)
             },
             MaterialInteraction::Emit{ emitted_color } =>
             {
                 ScatteringResult::emit(emitted_color, 1.0)
             },
+            MaterialInteraction::Medium{ albedo, emitted_color } =>
+            {
+                // The normal faces back along the ray, so
+                // just lights on this side are included
+
+                let local = Phong::local_shading(scene, intersection, albedo, 0.1, 0.6, albedo, 0.0, 1.0, stats);
+
+                ScatteringResult::emit(local + emitted_color, 1.0)
+            },
         }
     }
 
@@ -536,3 +1487,37 @@ impl ScatteringFunction for LocalLighting
         attenuation
     }
 }
+
+/// The ambient factor for local lighting - with ambient occlusion,
+/// it can be brighter without flattening out the shading
+fn local_ambient(scene: &Scene, intersection: &ShadingIntersection, sampler: &mut Sampler, stats: &mut SceneSampleStats) -> Scalar
+{
+    match scene.ambient_visibility(intersection, sampler, stats)
+    {
+        Some(visibility) => 0.3 * visibility,
+        None => 0.1,
+    }
+}
+
+/// The colors of the specular and diffuse lobes of the metallic-roughness
+/// model. Dielectrics reflect 4% at normal incidence, and metals reflect their
+/// base color. The Fresnel term uses Schlick's approximation for the viewing
+/// angle, as the lobe's color has to be chosen before the direction.
+fn pbr_lobe_colors(intersection: &ShadingIntersection, base_color: LinearRGB, metallic: Scalar) -> (LinearRGB, LinearRGB)
+{
+    let f0 = LinearRGB::grey(0.04).multiplied_by_scalar(1.0 - metallic) + base_color.multiplied_by_scalar(metallic);
+
+    let cos_theta = intersection.normal.dot(intersection.incoming).clamp(0.0, 1.0);
+    let weight = (1.0 - cos_theta).powi(5);
+
+    let fresnel = |f0: Scalar| f0 + ((1.0 - f0) * weight);
+
+    let specular = LinearRGB::new(fresnel(f0.r), fresnel(f0.g), fresnel(f0.b), 1.0);
+    let diffuse = LinearRGB::new(
+        base_color.r * (1.0 - specular.r),
+        base_color.g * (1.0 - specular.g),
+        base_color.b * (1.0 - specular.b),
+        1.0).multiplied_by_scalar(1.0 - metallic);
+
+    (specular, diffuse)
+}
@@ -0,0 +1,196 @@
+use crate::geom::OctreeQuality;
+use crate::render::RenderOptions;
+
+/// Render options that are commonly used together,
+/// so they can be selected by name
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderPreset
+{
+    pub name: String,
+    /// The image is rendered at the window size divided by this
+    pub downscale: u32,
+    pub max_samples: usize,
+    pub max_bounces: usize,
+    pub denoise: bool,
+    pub bake_transforms: bool,
+    pub octree_quality: OctreeQuality,
+}
+
+impl RenderPreset
+{
+    pub fn standard() -> Vec<RenderPreset>
+    {
+        vec![
+            RenderPreset { name: "draft".to_owned(), downscale: 4, max_samples: 8, max_bounces: 4, denoise: true, bake_transforms: false, octree_quality: OctreeQuality::Fast },
+            RenderPreset { name: "preview".to_owned(), downscale: 2, max_samples: 128, max_bounces: 8, denoise: true, bake_transforms: false, octree_quality: OctreeQuality::Balanced },
+            RenderPreset { name: "final".to_owned(), downscale: 1, max_samples: 2048, max_bounces: 50, denoise: false, bake_transforms: true, octree_quality: OctreeQuality::HighQuality },
+        ]
+    }
+
+    /// Creates a preset from the current settings
+    pub fn capture(name: String, downscale: u32, options: &RenderOptions) -> Self
+    {
+        RenderPreset
+        {
+            name,
+            downscale,
+            max_samples: options.max_samples,
+            max_bounces: options.max_bounces,
+            denoise: options.denoise,
+            bake_transforms: options.bake_transforms,
+            octree_quality: options.octree_quality,
+        }
+    }
+
+    pub fn apply(&self, downscale: &mut u32, options: &mut RenderOptions)
+    {
+        *downscale = self.downscale.max(1);
+        options.max_samples = self.max_samples.max(1);
+        options.max_bounces = self.max_bounces;
+        options.denoise = self.denoise;
+        options.bake_transforms = self.bake_transforms;
+        options.octree_quality = self.octree_quality;
+    }
+}
+
+/// Settings that are kept between sessions
+#[derive(Clone, Debug)]
+pub struct AppSettings
+{
+    pub presets: Vec<RenderPreset>,
+    /// Seconds between autosaves of the open documents - zero disables it
+    pub autosave_interval: u64,
+}
+
+impl Default for AppSettings
+{
+    fn default() -> Self
+    {
+        AppSettings { presets: RenderPreset::standard(), autosave_interval: AppSettings::DEFAULT_AUTOSAVE_INTERVAL }
+    }
+}
+
+impl AppSettings
+{
+    pub const DEFAULT_FILENAME: &'static str = "beam_settings.ini";
+    pub const DEFAULT_AUTOSAVE_INTERVAL: u64 = 60;
+
+    /// Loads the settings, or returns the defaults
+    /// if they've not been saved yet
+    pub fn load(path: &str) -> Result<Self, String>
+    {
+        match std::fs::read_to_string(path)
+        {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("Could not load settings {:?}: {}", path, e)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(AppSettings::default()),
+            Err(err) => Err(format!("Could not load settings {:?}: {}", path, err)),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String>
+    {
+        std::fs::write(path, self.to_text())
+            .map_err(|e| format!("Could not save settings {:?}: {}", path, e))
+    }
+
+    pub fn find_preset(&self, name: &str) -> Option<&RenderPreset>
+    {
+        self.presets.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Adds the preset, replacing any existing preset with the same name
+    pub fn set_preset(&mut self, preset: RenderPreset)
+    {
+        match self.presets.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&preset.name))
+        {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    fn to_text(&self) -> String
+    {
+        let mut result = String::new();
+
+        result.push_str("[autosave]\n");
+        result.push_str(&format!("interval = {}\n", self.autosave_interval));
+        result.push('\n');
+
+        for preset in self.presets.iter()
+        {
+            result.push_str(&format!("[preset {}]\n", preset.name));
+            result.push_str(&format!("downscale = {}\n", preset.downscale));
+            result.push_str(&format!("max_samples = {}\n", preset.max_samples));
+            result.push_str(&format!("max_bounces = {}\n", preset.max_bounces));
+            result.push_str(&format!("denoise = {}\n", preset.denoise));
+            result.push_str(&format!("bake_transforms = {}\n", preset.bake_transforms));
+            result.push_str(&format!("octree_quality = {}\n", preset.octree_quality.arg_name()));
+            result.push('\n');
+        }
+
+        result
+    }
+
+    fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut presets = Vec::new();
+        let mut autosave_interval = AppSettings::DEFAULT_AUTOSAVE_INTERVAL;
+        let mut in_autosave = false;
+
+        for (line_num, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            let error = |msg: &str| format!("line {}: {}", line_num + 1, msg);
+
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+
+            if line == "[autosave]"
+            {
+                in_autosave = true;
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("[preset ").and_then(|l| l.strip_suffix(']'))
+            {
+                in_autosave = false;
+
+                // Start from the defaults, so older files
+                // can be missing newer values
+
+                presets.push(RenderPreset::capture(name.trim().to_owned(), 1, &RenderOptions::new(1, 1)));
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| error("Expected \"key = value\""))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if in_autosave
+            {
+                match key
+                {
+                    "interval" => autosave_interval = value.parse().map_err(|_| error("Invalid autosave interval"))?,
+                    _ => return Err(error(&format!("Unknown setting {:?}", key))),
+                }
+                continue;
+            }
+
+            let preset = presets.last_mut().ok_or_else(|| error("Value outside of a [preset] section"))?;
+
+            match key
+            {
+                "downscale" => preset.downscale = value.parse().map_err(|_| error("Invalid downscale"))?,
+                "max_samples" => preset.max_samples = value.parse().map_err(|_| error("Invalid max_samples"))?,
+                "max_bounces" => preset.max_bounces = value.parse().map_err(|_| error("Invalid max_bounces"))?,
+                "denoise" => preset.denoise = value.parse().map_err(|_| error("Invalid denoise"))?,
+                "bake_transforms" => preset.bake_transforms = value.parse().map_err(|_| error("Invalid bake_transforms"))?,
+                "octree_quality" => preset.octree_quality = OctreeQuality::from_name(value).ok_or_else(|| error("Invalid octree_quality"))?,
+                _ => return Err(error(&format!("Unknown setting {:?}", key))),
+            }
+        }
+
+        Ok(AppSettings { presets, autosave_interval })
+    }
+}
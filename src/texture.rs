@@ -1,15 +1,20 @@
+use std::sync::Arc;
+
 use crate::color::LinearRGB;
+use crate::exec::{CompiledExpression, CompiledType, CompiledValue, ExecResult};
 use crate::geom::Sdf;
-use crate::import::image::Image;
-use crate::vec::{Point3, Mat4};
+use crate::import::image::{ColorSpace, Image};
+use crate::intersection::ShadingIntersection;
+use crate::vec::Mat4;
 
 #[derive(Clone)]
 pub enum Texture
 {
     Solid(LinearRGB),
     Checkerboard(LinearRGB, LinearRGB),
-    Image{ base_color: LinearRGB, image: Image, transform: Mat4},
+    Image{ base_color: LinearRGB, image: Image, color_space: ColorSpace, transform: Mat4},
     Sdf(Sdf),
+    Expression(Arc<CompiledExpression>),
 }
 
 impl Texture
@@ -26,7 +31,11 @@ impl Texture
 
     pub fn image<C: Into<LinearRGB>>(base_color: C, image: Image, transform: Mat4) -> Texture
     {
-        Texture::Image{ base_color: base_color.into(), image, transform }
+        // Capture the color space now, as the image is
+        // shared with the editor, where it can be changed
+
+        let color_space = image.color_space();
+        Texture::Image{ base_color: base_color.into(), image, color_space, transform }
     }
 
     pub fn sdf(sdf: Sdf) -> Texture
@@ -34,8 +43,27 @@ impl Texture
         Texture::Sdf(sdf)
     }
 
-    pub fn get_color_at(&self, point: Point3) -> LinearRGB
+    pub fn expression(script: &str) -> ExecResult<Texture>
+    {
+        let compiled = CompiledExpression::compile(script, &[
+            ("u", CompiledType::Scalar),
+            ("v", CompiledType::Scalar),
+            ("position", CompiledType::Vec3),
+            ("normal", CompiledType::Vec3),
+        ])?;
+
+        if compiled.result_type() == CompiledType::Bool
+        {
+            return Err(crate::exec::ExecError::new_no_loc("Texture expressions must evaluate to a scalar or color vector"));
+        }
+
+        Ok(Texture::Expression(Arc::new(compiled)))
+    }
+
+    pub fn get_color_at(&self, intersection: &ShadingIntersection) -> LinearRGB
     {
+        let point = intersection.texture_coords;
+
         match self
         {
             Texture::Solid(c1) =>
@@ -55,13 +83,13 @@ impl Texture
                     *c2
                 }
             }
-            Texture::Image{ base_color, image, transform } =>
+            Texture::Image{ base_color, image, color_space, transform } =>
             {
                 let point = transform.mul_point(point);
                 let u = point[0].fract();
                 let v = point[1].fract();
 
-                base_color.combined_with(&image.sample_at_uv(u, v).into())
+                base_color.combined_with(&color_space.to_linear(image.sample_at_uv(u, v)))
             },
             Texture::Sdf(sdf) =>
             {
@@ -84,7 +112,23 @@ impl Texture
                         LinearRGB::new(0.1, 0.6, 0.8, 1.0).multiplied_by_scalar(prod)
                     }
                 }
-            }
+            },
+            Texture::Expression(compiled) =>
+            {
+                let result = compiled.evaluate(&[
+                    CompiledValue::Scalar(point[0]),
+                    CompiledValue::Scalar(point[1]),
+                    CompiledValue::Vec3(intersection.location),
+                    CompiledValue::Vec3(intersection.normal),
+                ]);
+
+                match result
+                {
+                    CompiledValue::Scalar(s) => LinearRGB::new(s, s, s, 1.0),
+                    CompiledValue::Vec3(v) => LinearRGB::new(v.x, v.y, v.z, 1.0),
+                    CompiledValue::Bool(_) => LinearRGB::black(),
+                }
+            },
         }
     }
 }
\ No newline at end of file
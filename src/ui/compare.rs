@@ -0,0 +1,126 @@
+use image::{Rgba, RgbaImage};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareMode
+{
+    Wipe,
+    Flicker,
+    Difference,
+    HeatMap,
+}
+
+impl CompareMode
+{
+    pub fn all() -> &'static [CompareMode]
+    {
+        &[CompareMode::Wipe, CompareMode::Flicker, CompareMode::Difference, CompareMode::HeatMap]
+    }
+}
+
+pub struct CompareSettings
+{
+    pub mode: CompareMode,
+    pub wipe: f32,
+    pub show_b: bool,
+    pub difference_scale: f32,
+}
+
+impl Default for CompareSettings
+{
+    fn default() -> Self
+    {
+        CompareSettings
+        {
+            mode: CompareMode::Wipe,
+            wipe: 0.5,
+            show_b: false,
+            difference_scale: 1.0,
+        }
+    }
+}
+
+/// Combines two images into a single comparison image.
+/// The images are stretched (nearest neighbour) to the
+/// requested output dimensions, so renders at different
+/// resolutions and loaded reference images can be compared.
+pub fn compare_images(a: &RgbaImage, b: &RgbaImage, settings: &CompareSettings, width: u32, height: u32) -> RgbaImage
+{
+    let wipe_x = (settings.wipe.clamp(0.0, 1.0) * (width as f32)) as u32;
+
+    RgbaImage::from_fn(width, height, |x, y|
+    {
+        let pa = sample(a, x, y, width, height);
+        let pb = sample(b, x, y, width, height);
+
+        match settings.mode
+        {
+            CompareMode::Wipe =>
+            {
+                if x < wipe_x { pa } else { pb }
+            },
+            CompareMode::Flicker =>
+            {
+                if settings.show_b { pb } else { pa }
+            },
+            CompareMode::Difference =>
+            {
+                let diff = |c: usize| -> u8
+                {
+                    let d = ((pa[c] as f32) - (pb[c] as f32)).abs() * settings.difference_scale;
+                    d.clamp(0.0, 255.0) as u8
+                };
+                Rgba([diff(0), diff(1), diff(2), 255])
+            },
+            CompareMode::HeatMap =>
+            {
+                let diff = (0..3)
+                    .map(|c| ((pa[c] as f32) - (pb[c] as f32)).abs())
+                    .fold(0.0, f32::max);
+
+                heat_map_color(diff * settings.difference_scale / 255.0)
+            },
+        }
+    })
+}
+
+fn sample(image: &RgbaImage, x: u32, y: u32, width: u32, height: u32) -> Rgba<u8>
+{
+    let (iw, ih) = image.dimensions();
+
+    if (iw == 0) || (ih == 0)
+    {
+        return Rgba([0, 0, 0, 255]);
+    }
+
+    let sx = ((x as u64) * (iw as u64) / (width as u64)) as u32;
+    let sy = ((y as u64) * (ih as u64) / (height as u64)) as u32;
+
+    *image.get_pixel(sx.min(iw - 1), sy.min(ih - 1))
+}
+
+// Maps 0..1 onto black -> blue -> green -> yellow -> red
+
+fn heat_map_color(t: f32) -> Rgba<u8>
+{
+    let t = t.clamp(0.0, 1.0);
+
+    let stops = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0],
+    ];
+
+    let scaled = t * ((stops.len() - 1) as f32);
+    let index = (scaled as usize).min(stops.len() - 2);
+    let frac = scaled - (index as f32);
+
+    let lerp = |c: usize| -> u8
+    {
+        let v = stops[index][c] + (stops[index + 1][c] - stops[index][c]) * frac;
+        (v * 255.0) as u8
+    };
+
+    Rgba([lerp(0), lerp(1), lerp(2), 255])
+}
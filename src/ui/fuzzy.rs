@@ -0,0 +1,35 @@
+/// Matches the pattern's characters, in order but not necessarily
+/// together, against the text, ignoring case and spaces in the
+/// pattern. Returns None if it doesn't match, otherwise a score
+/// (higher is better) that prefers runs of matching characters
+/// and matches at the start of words.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<i64>
+{
+    let text = text.chars().map(|c| c.to_ascii_lowercase()).collect::<Vec<_>>();
+
+    let mut score = 0;
+    let mut next = 0;
+    let mut prev_match = None;
+
+    for pc in pattern.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_lowercase())
+    {
+        let found = next + text[next..].iter().position(|tc| *tc == pc)?;
+
+        score += 1;
+
+        if prev_match.map(|p| p + 1) == Some(found)
+        {
+            score += 5;
+        }
+
+        if (found == 0) || !text[found - 1].is_alphanumeric()
+        {
+            score += 3;
+        }
+
+        prev_match = Some(found);
+        next = found + 1;
+    }
+
+    Some(score)
+}
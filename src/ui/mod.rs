@@ -1,14 +1,25 @@
+mod compare;
+mod fuzzy;
 mod pixel;
 mod system;
 
+pub use compare::{compare_images, CompareMode, CompareSettings};
+pub use fuzzy::fuzzy_match;
 pub use system::System;
 pub use pixel::PixelDisplay;
 
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
 use crate::vec::{Vec3, Quaternion};
 
 pub trait UiApplication<T: 'static>
 {
     fn handle_event(&mut self, event: winit::event::Event<T>) -> Option<winit::event_loop::ControlFlow>;
+    /// Returns false to keep running when the window is closed, e.g. to
+    /// ask about unsaved changes - and later exit from `handle_event`
+    fn close_requested(&mut self) -> bool;
     fn render_background(&mut self, display: &glium::Display, frame: &mut glium::Frame);
     fn render_ui(&mut self, ui: &UiRenderer);
     fn idle(&mut self);
@@ -34,16 +45,73 @@ pub trait UiTaggedEnum
     fn get_tag(&self) -> Self::TagEnum;
 }
 
+/// The result of picking an index from a list
+pub enum IndexPick
+{
+    Unchanged,
+    /// None if the none entry was picked
+    Picked(Option<usize>),
+    /// There's no list for the type of index, so it must be typed in
+    NoLabels,
+}
+
 pub struct UiRenderer<'a>
 {
-    pub imgui: &'a imgui::Ui
+    pub imgui: &'a imgui::Ui,
+    /// For each type of index, a label for each item it can refer to,
+    /// so indexes can be picked from a list rather than typed in
+    index_labels: RefCell<HashMap<TypeId, Vec<String>>>,
 }
 
 impl<'a> UiRenderer<'a>
 {
     pub fn new(imgui: &'a imgui::Ui) -> Self
     {
-        Self { imgui }
+        Self { imgui, index_labels: RefCell::new(HashMap::new()) }
+    }
+
+    pub fn set_index_labels(&self, index_type: TypeId, labels: Vec<String>)
+    {
+        self.index_labels.borrow_mut().insert(index_type, labels);
+    }
+
+    /// Shows a combo to pick an index of the given type,
+    /// with a none entry at the top, if it's allowed
+    pub fn pick_index(&self, label: &str, index_type: TypeId, current: Option<usize>, allow_none: bool) -> IndexPick
+    {
+        let labels = self.index_labels.borrow();
+
+        let labels = match labels.get(&index_type)
+        {
+            Some(labels) => labels,
+            None => return IndexPick::NoLabels,
+        };
+
+        let preview = match current
+        {
+            Some(i) => labels.get(i).cloned().unwrap_or_else(|| format!("{}: <Missing>", i)),
+            None => "<None>".to_owned(),
+        };
+
+        let mut result = IndexPick::Unchanged;
+
+        if let Some(_combo) = self.imgui.begin_combo(label, preview)
+        {
+            if allow_none && self.imgui.selectable_config("<None>").selected(current.is_none()).build()
+            {
+                result = IndexPick::Picked(None);
+            }
+
+            for (i, item) in labels.iter().enumerate()
+            {
+                if self.imgui.selectable_config(item).selected(current == Some(i)).build()
+                {
+                    result = IndexPick::Picked(Some(i));
+                }
+            }
+        }
+
+        result
     }
 
     pub fn display_float(&self, label: &str, val: &f64)
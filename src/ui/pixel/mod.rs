@@ -133,6 +133,17 @@ impl PixelDisplay
             .unwrap();
     }
 
+    pub fn image(&self) -> &RgbaImage
+    {
+        &self.image
+    }
+
+    pub fn set_image(&mut self, image: RgbaImage)
+    {
+        self.image = image;
+        self.image_changed = true;
+    }
+
     pub fn dimensions(&self) -> (u32, u32)
     {
         self.image.dimensions()
@@ -132,7 +132,10 @@ impl<T: 'static> System<T>
                 }
                 Event::WindowEvent { event: WindowEvent::CloseRequested, .. } =>
                 {
-                    *control_flow = ControlFlow::Exit
+                    if app.close_requested()
+                    {
+                        *control_flow = ControlFlow::Exit
+                    }
                 }
                 event =>
                 {
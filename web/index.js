@@ -0,0 +1,77 @@
+import init, { WebRender } from "./pkg/beam_web.js";
+
+// Time to spend rendering in each animation
+// frame, leaving the rest for the page
+const FRAME_BUDGET_MS = 30;
+
+const canvas = document.getElementById("canvas");
+const context = canvas.getContext("2d");
+const status = document.getElementById("status");
+const script = document.getElementById("script");
+
+let render = null;
+let tilesPerFrame = 1;
+
+function frame()
+{
+    if (render === null)
+    {
+        return;
+    }
+
+    const start = performance.now();
+    const running = render.render_tiles(tilesPerFrame);
+    const elapsed = performance.now() - start;
+
+    // Adapt the number of tiles to the budget
+
+    if (elapsed < FRAME_BUDGET_MS / 2)
+    {
+        tilesPerFrame *= 2;
+    }
+    else if ((elapsed > FRAME_BUDGET_MS) && (tilesPerFrame > 1))
+    {
+        tilesPerFrame = Math.floor(tilesPerFrame / 2);
+    }
+
+    const image = new ImageData(new Uint8ClampedArray(render.pixels()), canvas.width, canvas.height);
+    context.putImageData(image, 0, 0);
+
+    status.textContent = `${render.passes()} samples per pixel`;
+
+    if (running)
+    {
+        requestAnimationFrame(frame);
+    }
+    else
+    {
+        status.textContent += " - complete";
+    }
+}
+
+function start()
+{
+    if (render !== null)
+    {
+        render.free();
+        render = null;
+    }
+
+    try
+    {
+        render = new WebRender(script.value, canvas.width, canvas.height);
+    }
+    catch (error)
+    {
+        status.textContent = `Script error: ${error}`;
+        return;
+    }
+
+    tilesPerFrame = 1;
+    requestAnimationFrame(frame);
+}
+
+await init();
+
+document.getElementById("render").addEventListener("click", start);
+start();
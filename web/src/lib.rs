@@ -0,0 +1,45 @@
+use beam::desc::{run_script, SceneDescription};
+use beam::render::{ProgressiveRender, RenderOptions};
+use wasm_bindgen::prelude::*;
+
+/// A progressive render of a scene script, for drawing to a
+/// canvas. Browsers run it on the page's thread, so it renders
+/// a few tiles at a time, between animation frames.
+#[wasm_bindgen]
+pub struct WebRender
+{
+    render: ProgressiveRender,
+}
+
+#[wasm_bindgen]
+impl WebRender
+{
+    /// Runs the script, and builds its scene - any
+    /// script error is thrown, as a string
+    #[wasm_bindgen(constructor)]
+    pub fn new(script: &str, width: u32, height: u32) -> Result<WebRender, JsValue>
+    {
+        let scene = run_script(script).map_err(|e| JsValue::from_str(&e.message()))?;
+        let render = ProgressiveRender::new(RenderOptions::new(width, height), &SceneDescription::new_edit(&scene));
+
+        Ok(WebRender { render })
+    }
+
+    /// Returns false once the render is complete
+    pub fn render_tiles(&mut self, count: u32) -> bool
+    {
+        (0..count).all(|_| self.render.render_tile().is_some())
+    }
+
+    /// Samples in every pixel
+    pub fn passes(&self) -> u32
+    {
+        self.render.passes() as u32
+    }
+
+    /// RGBA bytes, to copy into the canvas's `ImageData`
+    pub fn pixels(&self) -> Vec<u8>
+    {
+        self.render.to_rgba8()
+    }
+}